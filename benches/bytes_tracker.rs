@@ -0,0 +1,92 @@
+use rama::net::stream::layer::BytesRWTracker;
+use std::io::IoSlice;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const CHUNK: &[u8] = &[0x42; 1024];
+const WRITES: usize = 1_000;
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+fn main() {
+    // Run registered benchmarks.
+    divan::main();
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// Write [`WRITES`] times three chunks using vectored writes,
+/// while draining the other end of the pipe.
+async fn write_vectored<W, R>(mut writer: W, mut reader: R)
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let drain = tokio::spawn(async move {
+        let mut buf = vec![0; PIPE_CAPACITY];
+        let mut total = 0;
+        loop {
+            match reader.read(&mut buf).await.unwrap() {
+                0 => return total,
+                n => total += n,
+            }
+        }
+    });
+
+    for _ in 0..WRITES {
+        let mut bufs = [
+            IoSlice::new(CHUNK),
+            IoSlice::new(CHUNK),
+            IoSlice::new(CHUNK),
+        ];
+        let mut bufs = &mut bufs[..];
+        while !bufs.is_empty() {
+            let n = writer.write_vectored(bufs).await.unwrap();
+            IoSlice::advance_slices(&mut bufs, n);
+        }
+    }
+    writer.shutdown().await.unwrap();
+    drop(writer);
+
+    assert_eq!(drain.await.unwrap(), WRITES * CHUNK.len() * 3);
+}
+
+#[divan::bench]
+fn untracked_write_vectored(bencher: divan::Bencher) {
+    let rt = runtime();
+    bencher.bench_local(|| {
+        rt.block_on(async {
+            let (writer, reader) = tokio::io::duplex(PIPE_CAPACITY);
+            write_vectored(writer, reader).await;
+        })
+    });
+}
+
+#[divan::bench]
+fn tracked_write_vectored(bencher: divan::Bencher) {
+    let rt = runtime();
+    bencher.bench_local(|| {
+        rt.block_on(async {
+            let (writer, reader) = tokio::io::duplex(PIPE_CAPACITY);
+            let writer = BytesRWTracker::new(writer);
+            let handle = writer.handle();
+            write_vectored(writer, reader).await;
+            divan::black_box(handle.written());
+        })
+    });
+}
+
+#[divan::bench]
+fn tracked_write_vectored_with_limit(bencher: divan::Bencher) {
+    let rt = runtime();
+    bencher.bench_local(|| {
+        rt.block_on(async {
+            let (writer, reader) = tokio::io::duplex(PIPE_CAPACITY);
+            let writer = BytesRWTracker::with_write_limit(writer, usize::MAX);
+            write_vectored(writer, reader).await;
+        })
+    });
+}
@@ -0,0 +1,107 @@
+use divan::AllocProfiler;
+use rama::Context;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+#[global_allocator]
+static ALLOC: AllocProfiler = AllocProfiler::system();
+
+const LOOKUPS: usize = 10_000;
+
+fn main() {
+    // Run registered benchmarks.
+    divan::main();
+}
+
+#[derive(Debug, Clone)]
+struct Ext<const N: usize>(usize);
+
+/// The amount of extensions present in the context prior to the lookup.
+const SIZES: &[usize] = &[1, 4, 8, 16];
+
+fn context(size: usize) -> Context<()> {
+    let mut ctx = Context::default();
+    macro_rules! fill {
+        ($($n:literal)+) => {
+            $(if $n < size { ctx.insert(Ext::<$n>($n)); })+
+        };
+    }
+    fill!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15);
+    ctx
+}
+
+/// The map used by extensions prior to the inline small-map storage,
+/// kept as a baseline to compare against.
+fn hash_map(size: usize) -> HashMap<TypeId, Box<dyn Any + Send + Sync>> {
+    let mut map: HashMap<TypeId, Box<dyn Any + Send + Sync>> = HashMap::new();
+    macro_rules! fill {
+        ($($n:literal)+) => {
+            $(if $n < size { map.insert(TypeId::of::<Ext<$n>>(), Box::new(Ext::<$n>($n))); })+
+        };
+    }
+    fill!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15);
+    map
+}
+
+#[divan::bench(args = SIZES)]
+fn context_get(bencher: divan::Bencher, size: usize) {
+    let ctx = context(size);
+    bencher.bench_local(|| {
+        for _ in 0..LOOKUPS {
+            divan::black_box(ctx.get::<Ext<0>>());
+        }
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn context_get_missing(bencher: divan::Bencher, size: usize) {
+    let ctx = context(size);
+    bencher.bench_local(|| {
+        for _ in 0..LOOKUPS {
+            divan::black_box(ctx.get::<u8>());
+        }
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn context_insert(bencher: divan::Bencher, size: usize) {
+    bencher
+        .with_inputs(|| context(size))
+        .bench_local_values(|mut ctx| {
+            ctx.insert(42u8);
+            ctx
+        });
+}
+
+#[divan::bench(args = SIZES)]
+fn context_get_or_insert_with_ctx(bencher: divan::Bencher, size: usize) {
+    let mut ctx = context(size);
+    bencher.bench_local(|| {
+        for _ in 0..LOOKUPS {
+            divan::black_box(ctx.get_or_insert_with_ctx(|_| 42u8));
+        }
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn baseline_hash_map_get(bencher: divan::Bencher, size: usize) {
+    let map = hash_map(size);
+    bencher.bench_local(|| {
+        for _ in 0..LOOKUPS {
+            divan::black_box(
+                map.get(&TypeId::of::<Ext<0>>())
+                    .and_then(|boxed| boxed.downcast_ref::<Ext<0>>()),
+            );
+        }
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn baseline_hash_map_insert(bencher: divan::Bencher, size: usize) {
+    bencher
+        .with_inputs(|| hash_map(size))
+        .bench_local_values(|mut map| {
+            map.insert(TypeId::of::<u8>(), Box::new(42u8));
+            map
+        });
+}
@@ -0,0 +1,70 @@
+use divan::AllocProfiler;
+use rama::http::{Body, Request};
+use rama::net::http::{RequestContext, RequestContextExt};
+use rama::Context;
+
+#[global_allocator]
+static ALLOC: AllocProfiler = AllocProfiler::system();
+
+const DERIVATIONS: usize = 10_000;
+
+fn main() {
+    // Run registered benchmarks.
+    divan::main();
+}
+
+fn request() -> Request {
+    Request::builder()
+        .uri("/foo?bar=baz")
+        .header("host", "www.example.com:8080")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[divan::bench]
+fn request_context_derive(bencher: divan::Bencher) {
+    let req = request();
+    bencher.bench_local(|| {
+        for _ in 0..DERIVATIONS {
+            let ctx = Context::default();
+            let _ = divan::black_box(RequestContext::try_from((&ctx, &req)).unwrap());
+        }
+    });
+}
+
+#[divan::bench]
+fn request_context_memoized(bencher: divan::Bencher) {
+    let req = request();
+    bencher.bench_local(|| {
+        let mut ctx = Context::default();
+        for _ in 0..DERIVATIONS {
+            let _ = divan::black_box(
+                ctx.get_or_try_insert_with_ctx::<RequestContext, _>(|ctx| (ctx, &req).try_into())
+                    .unwrap(),
+            );
+        }
+    });
+}
+
+#[divan::bench]
+fn request_context_ext_memoized(bencher: divan::Bencher) {
+    let req = request();
+    bencher.bench_local(|| {
+        let mut ctx = Context::default();
+        for _ in 0..DERIVATIONS {
+            let _ = divan::black_box(ctx.get_or_try_insert_request_context(&req).unwrap());
+        }
+    });
+}
+
+#[divan::bench]
+fn request_context_ext_get(bencher: divan::Bencher) {
+    let req = request();
+    let mut ctx = Context::default();
+    ctx.get_or_try_insert_request_context(&req).unwrap();
+    bencher.bench_local(|| {
+        for _ in 0..DERIVATIONS {
+            let _ = divan::black_box(ctx.request_context());
+        }
+    });
+}
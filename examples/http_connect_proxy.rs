@@ -75,7 +75,10 @@ use rama::{
     layer::HijackLayer,
     net::http::RequestContext,
     net::stream::layer::http::BodyLimitLayer,
-    net::{address::Domain, user::Basic},
+    net::{
+        address::{Authority, Domain},
+        user::Basic,
+    },
     rt::Executor,
     service::service_fn,
     tcp::{client::default_tcp_connect, server::TcpListener, utils::is_connection_error},
@@ -193,6 +196,13 @@ where
         .authority
         .clone();
     tracing::info!("CONNECT to {authority}");
+    let authority = match Authority::try_from(authority) {
+        Ok(authority) => authority,
+        Err(err) => {
+            tracing::error!(error = %err, "no port known to connect to");
+            return Ok(());
+        }
+    };
     let (mut stream, _) = match default_tcp_connect(&ctx, authority).await {
         Ok(stream) => stream,
         Err(err) => {
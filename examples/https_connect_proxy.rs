@@ -36,6 +36,7 @@ use rama::{
         server::HttpServer,
         Body, IntoResponse, Request, Response, StatusCode,
     },
+    net::address::Authority,
     net::http::RequestContext,
     net::stream::layer::http::BodyLimitLayer,
     net::tls::{
@@ -157,6 +158,13 @@ where
         .authority
         .clone();
     tracing::info!("CONNECT to {authority}");
+    let authority = match Authority::try_from(authority) {
+        Ok(authority) => authority,
+        Err(err) => {
+            tracing::error!(error = %err, "no port known to connect to");
+            return Ok(());
+        }
+    };
     let (mut stream, _) = match default_tcp_connect(&ctx, authority).await {
         Ok(stream) => stream,
         Err(err) => {
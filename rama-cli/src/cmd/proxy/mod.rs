@@ -15,6 +15,7 @@ use rama::{
         Body, IntoResponse, Request, Response, StatusCode,
     },
     layer::{limit::policy::ConcurrentPolicy, LimitLayer, TimeoutLayer},
+    net::address::Authority,
     net::http::RequestContext,
     net::stream::layer::http::BodyLimitLayer,
     rt::Executor,
@@ -130,6 +131,13 @@ where
         .authority
         .clone();
     tracing::info!("CONNECT to {authority}");
+    let authority = match Authority::try_from(authority) {
+        Ok(authority) => authority,
+        Err(err) => {
+            tracing::error!(error = %err, "no port known to connect to");
+            return Ok(());
+        }
+    };
     let (mut stream, _) = match default_tcp_connect(&ctx, authority).await {
         Ok(stream) => stream,
         Err(err) => {
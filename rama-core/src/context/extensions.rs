@@ -1,9 +1,16 @@
 use std::any::{Any, TypeId};
-use std::collections::HashMap;
+use std::collections::{hash_map, HashMap};
 use std::fmt;
 use std::hash::{BuildHasherDefault, Hasher};
 
-type AnyMap = HashMap<TypeId, Box<dyn AnyClone + Send + Sync>, BuildHasherDefault<IdHasher>>;
+type BoxedAny = Box<dyn AnyClone + Send + Sync>;
+
+/// Amount of extensions stored inline, prior to spilling into a [`HashMap`].
+///
+/// Most contexts and requests carry only a handful of extensions,
+/// for which a linear scan over their [`TypeId`]s is cheaper than hashing,
+/// and which can be stored without any allocation beyond the map itself.
+const INLINE_CAPACITY: usize = 8;
 
 // With TypeIds as keys, there's no need to hash them. They are already hashes
 // themselves, coming from the compiler. The IdHasher just holds the u64 of
@@ -27,14 +34,131 @@ impl Hasher for IdHasher {
     }
 }
 
+/// Small type map, storing the first [`INLINE_CAPACITY`] entries inline
+/// and only spilling into a (hashed) [`HashMap`] once those are exhausted.
+#[derive(Clone, Default)]
+struct AnyMap {
+    inline: [Option<(TypeId, BoxedAny)>; INLINE_CAPACITY],
+    inline_len: usize,
+    spilled: HashMap<TypeId, BoxedAny, BuildHasherDefault<IdHasher>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Location of an entry within an [`Extensions`] map,
+/// as returned by [`Extensions::slot`].
+pub(crate) enum Slot {
+    Inline(usize),
+    Spilled,
+}
+
+impl AnyMap {
+    #[inline]
+    fn slot(&self, id: TypeId) -> Option<Slot> {
+        self.inline[..self.inline_len]
+            .iter()
+            .position(|entry| matches!(entry, Some((entry_id, _)) if *entry_id == id))
+            .map(Slot::Inline)
+            .or_else(|| {
+                (!self.spilled.is_empty() && self.spilled.contains_key(&id))
+                    .then_some(Slot::Spilled)
+            })
+    }
+
+    #[inline]
+    fn get(&self, id: TypeId) -> Option<&BoxedAny> {
+        match self.slot(id)? {
+            Slot::Inline(index) => self.inline[index].as_ref().map(|(_, boxed)| boxed),
+            Slot::Spilled => self.spilled.get(&id),
+        }
+    }
+
+    #[inline]
+    fn get_mut(&mut self, id: TypeId) -> Option<&mut BoxedAny> {
+        let slot = self.slot(id)?;
+        Some(self.get_mut_at(id, slot))
+    }
+
+    #[inline]
+    fn get_mut_at(&mut self, id: TypeId, slot: Slot) -> &mut BoxedAny {
+        match slot {
+            Slot::Inline(index) => self.inline[index]
+                .as_mut()
+                .map(|(_, boxed)| boxed)
+                .expect("inline slot to be occupied"),
+            Slot::Spilled => self
+                .spilled
+                .get_mut(&id)
+                .expect("spilled slot to be occupied"),
+        }
+    }
+
+    fn insert(&mut self, id: TypeId, val: BoxedAny) -> Option<BoxedAny> {
+        match self.slot(id) {
+            Some(slot) => Some(std::mem::replace(self.get_mut_at(id, slot), val)),
+            None => {
+                self.insert_new(id, val);
+                None
+            }
+        }
+    }
+
+    /// Insert a value for a [`TypeId`] which is known not to be present yet,
+    /// returning an exclusive reference to the inserted value.
+    fn insert_new(&mut self, id: TypeId, val: BoxedAny) -> &mut BoxedAny {
+        debug_assert!(self.slot(id).is_none());
+        if self.inline_len < INLINE_CAPACITY {
+            let index = self.inline_len;
+            self.inline_len += 1;
+            &mut self.inline[index].insert((id, val)).1
+        } else {
+            match self.spilled.entry(id) {
+                hash_map::Entry::Occupied(mut entry) => {
+                    entry.insert(val);
+                    entry.into_mut()
+                }
+                hash_map::Entry::Vacant(entry) => entry.insert(val),
+            }
+        }
+    }
+
+    fn get_or_insert_with(&mut self, id: TypeId, f: impl FnOnce() -> BoxedAny) -> &mut BoxedAny {
+        match self.slot(id) {
+            Some(slot) => self.get_mut_at(id, slot),
+            None => self.insert_new(id, f()),
+        }
+    }
+
+    fn remove(&mut self, id: TypeId) -> Option<BoxedAny> {
+        match self.slot(id)? {
+            Slot::Inline(index) => {
+                // keep the inline entries contiguous by moving the last one into the gap
+                self.inline_len -= 1;
+                self.inline.swap(index, self.inline_len);
+                self.inline[self.inline_len].take().map(|(_, boxed)| boxed)
+            }
+            Slot::Spilled => self.spilled.remove(&id),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.inline[..self.inline_len].fill_with(|| None);
+        self.inline_len = 0;
+        self.spilled.clear();
+    }
+
+    fn into_iter(self) -> impl Iterator<Item = (TypeId, BoxedAny)> {
+        self.inline.into_iter().flatten().chain(self.spilled)
+    }
+}
+
 /// A type map of protocol extensions.
 ///
 /// `Extensions` can be used by `Request` and `Response` to store
 /// extra data derived from the underlying protocol.
 #[derive(Clone, Default)]
 pub struct Extensions {
-    // If extensions are never used, no need to carry around an empty HashMap.
-    // That's 3 words. Instead, this is only 1 word.
+    // If extensions are never used, no need to carry around an empty map.
+    // Instead, this is only 1 word.
     map: Option<Box<AnyMap>>,
 }
 
@@ -70,8 +194,9 @@ impl Extensions {
     pub fn extend(&mut self, other: Extensions) {
         if let Some(other_map) = other.map {
             let map = self.map.get_or_insert_with(Box::default);
-            #[allow(clippy::useless_conversion)]
-            map.extend(other_map.into_iter());
+            for (id, boxed) in other_map.into_iter() {
+                map.insert(id, boxed);
+            }
         }
     }
 
@@ -84,17 +209,14 @@ impl Extensions {
 
     /// Returns true if the `Extensions` contains the given type.
     pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
-        self.map
-            .as_ref()
-            .map(|map| map.contains_key(&TypeId::of::<T>()))
-            .unwrap_or_default()
+        self.slot::<T>().is_some()
     }
 
     /// Get a shared reference to a type previously inserted on this `Extensions`.
     pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
         self.map
             .as_ref()
-            .and_then(|map| map.get(&TypeId::of::<T>()))
+            .and_then(|map| map.get(TypeId::of::<T>()))
             .and_then(|boxed| (**boxed).as_any().downcast_ref())
     }
 
@@ -102,10 +224,47 @@ impl Extensions {
     pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
         self.map
             .as_mut()
-            .and_then(|map| map.get_mut(&TypeId::of::<T>()))
+            .and_then(|map| map.get_mut(TypeId::of::<T>()))
             .and_then(|boxed| (**boxed).as_any_mut().downcast_mut())
     }
 
+    /// Find the [`Slot`] of a type previously inserted on this `Extensions`,
+    /// such that it can later be accessed using [`Self::get_mut_at`]
+    /// without having to look it up again.
+    #[inline]
+    pub(crate) fn slot<T: Send + Sync + 'static>(&self) -> Option<Slot> {
+        self.map
+            .as_ref()
+            .and_then(|map| map.slot(TypeId::of::<T>()))
+    }
+
+    /// Get an exclusive reference to the type found at the given [`Slot`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slot was not returned by [`Self::slot`] for the same type `T`,
+    /// or if this `Extensions` was modified since.
+    #[inline]
+    pub(crate) fn get_mut_at<T: Send + Sync + 'static>(&mut self, slot: Slot) -> &mut T {
+        let map = self.map.as_mut().expect("slot to point into existing map");
+        (**map.get_mut_at(TypeId::of::<T>(), slot))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("type mismatch")
+    }
+
+    /// Insert a type which is known not to be present yet in this `Extensions`,
+    /// returning an exclusive reference to the inserted value.
+    pub(crate) fn insert_new<T: Clone + Send + Sync + 'static>(&mut self, val: T) -> &mut T {
+        (**self
+            .map
+            .get_or_insert_with(Box::default)
+            .insert_new(TypeId::of::<T>(), Box::new(val)))
+        .as_any_mut()
+        .downcast_mut()
+        .expect("type mismatch")
+    }
+
     /// Inserts a value into the map computed from `f` into if it is [`None`],
     /// then returns an exclusive reference to the contained value.
     ///
@@ -116,15 +275,14 @@ impl Extensions {
         &mut self,
         f: impl FnOnce(&Self) -> T,
     ) -> &mut T {
-        if self.contains::<T>() {
+        if let Some(slot) = self.slot::<T>() {
             // NOTE: once <https://github.com/rust-lang/polonius>
-            // is merged into rust we can use directly `if let Some(v) = self.extensions.get_mut()`,
-            // until then we need this work around.
-            return self.get_mut().unwrap();
+            // is merged into rust we can use directly `if let Some(v) = self.get_mut()`,
+            // until then we go via the slot, which does not require a second lookup.
+            return self.get_mut_at(slot);
         }
         let v = f(self);
-        self.insert(v);
-        self.get_mut().unwrap()
+        self.insert_new(v)
     }
 
     /// Inserts a value into the map computed from `f` into if it is [`None`],
@@ -134,8 +292,7 @@ impl Extensions {
         f: impl FnOnce() -> T,
     ) -> &mut T {
         let map = self.map.get_or_insert_with(Box::default);
-        let entry = map.entry(TypeId::of::<T>());
-        let boxed = entry.or_insert_with(|| Box::new(f()));
+        let boxed = map.get_or_insert_with(TypeId::of::<T>(), || Box::new(f()));
         (**boxed)
             .as_any_mut()
             .downcast_mut()
@@ -149,13 +306,7 @@ impl Extensions {
         T: Send + Sync + Clone + 'static,
         U: Into<T>,
     {
-        let map = self.map.get_or_insert_with(Box::default);
-        let entry = map.entry(TypeId::of::<T>());
-        let boxed = entry.or_insert_with(|| Box::new(src.into()));
-        (**boxed)
-            .as_any_mut()
-            .downcast_mut()
-            .expect("type mismatch")
+        self.get_or_insert_with(|| src.into())
     }
 
     /// Retrieves a value of type `T` from the context.
@@ -176,7 +327,7 @@ impl Extensions {
     pub fn remove<T: Clone + Send + Sync + 'static>(&mut self) -> Option<T> {
         self.map
             .as_mut()
-            .and_then(|map| map.remove(&TypeId::of::<T>()))
+            .and_then(|map| map.remove(TypeId::of::<T>()))
             .and_then(|boxed| boxed.into_any().downcast().ok().map(|boxed| *boxed))
     }
 }
@@ -253,3 +404,72 @@ fn test_extensions() {
     assert_eq!(extensions2.get::<i32>(), None);
     assert_eq!(extensions2.get::<MyType>(), None);
 }
+
+#[test]
+fn test_extensions_spilled() {
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Nr<const N: usize>(usize);
+
+    let mut extensions = Extensions::new();
+    extensions.insert(Nr::<0>(0));
+    extensions.insert(Nr::<1>(1));
+    extensions.insert(Nr::<2>(2));
+    extensions.insert(Nr::<3>(3));
+    extensions.insert(Nr::<4>(4));
+    extensions.insert(Nr::<5>(5));
+    extensions.insert(Nr::<6>(6));
+    extensions.insert(Nr::<7>(7));
+    // these no longer fit inline
+    extensions.insert(Nr::<8>(8));
+    extensions.insert(Nr::<9>(9));
+
+    assert_eq!(extensions.get(), Some(&Nr::<0>(0)));
+    assert_eq!(extensions.get(), Some(&Nr::<7>(7)));
+    assert_eq!(extensions.get(), Some(&Nr::<9>(9)));
+
+    // replace both an inline and spilled value
+    assert_eq!(extensions.insert(Nr::<3>(30)), Some(Nr::<3>(3)));
+    assert_eq!(extensions.insert(Nr::<8>(80)), Some(Nr::<8>(8)));
+    assert_eq!(extensions.get(), Some(&Nr::<3>(30)));
+    assert_eq!(extensions.get(), Some(&Nr::<8>(80)));
+
+    // removing an inline value keeps all others reachable
+    assert_eq!(extensions.remove::<Nr<2>>(), Some(Nr::<2>(2)));
+    assert!(!extensions.contains::<Nr<2>>());
+    for contained in [
+        extensions.contains::<Nr<0>>(),
+        extensions.contains::<Nr<1>>(),
+        extensions.contains::<Nr<3>>(),
+        extensions.contains::<Nr<4>>(),
+        extensions.contains::<Nr<5>>(),
+        extensions.contains::<Nr<6>>(),
+        extensions.contains::<Nr<7>>(),
+        extensions.contains::<Nr<8>>(),
+        extensions.contains::<Nr<9>>(),
+    ] {
+        assert!(contained);
+    }
+
+    // the freed inline slot is reused, without duplicating spilled values
+    assert_eq!(*extensions.get_or_insert_with(|| Nr::<9>(0)), Nr::<9>(9));
+    assert_eq!(
+        *extensions.get_or_insert_with(|| Nr::<10>(10)),
+        Nr::<10>(10)
+    );
+    assert_eq!(extensions.remove::<Nr<9>>(), Some(Nr::<9>(9)));
+    assert_eq!(extensions.remove::<Nr<9>>(), None);
+
+    let ext2 = extensions.clone();
+    let mut ext3 = Extensions::new();
+    ext3.insert(Nr::<0>(100));
+    ext3.extend(ext2);
+    assert_eq!(ext3.get(), Some(&Nr::<0>(0)));
+    assert_eq!(ext3.get(), Some(&Nr::<8>(80)));
+    assert_eq!(ext3.get(), Some(&Nr::<10>(10)));
+
+    extensions.clear();
+    assert!(!extensions.contains::<Nr<0>>());
+    assert!(!extensions.contains::<Nr<8>>());
+    extensions.insert(Nr::<8>(8));
+    assert_eq!(extensions.get(), Some(&Nr::<8>(8)));
+}
@@ -331,34 +331,34 @@ impl<S> Context<S> {
         &mut self,
         f: impl FnOnce(&Self) -> T,
     ) -> &mut T {
-        if self.extensions.contains::<T>() {
+        if let Some(slot) = self.extensions.slot::<T>() {
             // NOTE: once <https://github.com/rust-lang/polonius>
             // is merged into rust we can use directly `if let Some(v) = self.extensions.get_mut()`,
-            // until then we need this work around.
-            return self.extensions.get_mut().unwrap();
+            // until then we go via the slot, which does not require a second lookup.
+            return self.extensions.get_mut_at(slot);
         }
         let v = f(self);
-        self.extensions.insert(v);
-        self.extensions.get_mut().unwrap()
+        self.extensions.insert_new(v)
     }
 
     /// Try to insert a value into the map computed from `f` into if it is [`None`],
     /// then returns an exclusive reference to the contained value.
     ///
     /// Similar to [`Self::get_or_insert_with_ctx`] but fallible.
+    ///
+    /// `f` is only called in case no value of type `T` is present yet,
+    /// such that a (costly) derived value is never computed twice for the same context.
+    /// Either way the returned reference is obtained using a single lookup.
     pub fn get_or_try_insert_with_ctx<T: Clone + Send + Sync + 'static, E>(
         &mut self,
         f: impl FnOnce(&Self) -> Result<T, E>,
     ) -> Result<&mut T, E> {
-        if self.extensions.contains::<T>() {
-            // NOTE: once <https://github.com/rust-lang/polonius>
-            // is merged into rust we can use directly `if let Some(v) = self.extensions.get_mut()`,
-            // until then we need this work around.
-            return Ok(self.extensions.get_mut().unwrap());
+        if let Some(slot) = self.extensions.slot::<T>() {
+            // NOTE: see [`Self::get_or_insert_with_ctx`] for why we go via the slot
+            return Ok(self.extensions.get_mut_at(slot));
         }
         let v = f(self)?;
-        self.extensions.insert(v);
-        Ok(self.extensions.get_mut().unwrap())
+        Ok(self.extensions.insert_new(v))
     }
 
     /// Inserts a value into the map computed from converting `U` into `T if no value was already inserted is [`None`],
@@ -30,7 +30,8 @@ where
     F: FnOnce() -> E + Clone + Send + Sync + 'static,
     E: Send + 'static,
 {
-    pub(crate) const fn new(f: F) -> Self {
+    /// Create a new [`LayerErrorFn`], calling the given function to create each error value.
+    pub const fn new(f: F) -> Self {
         Self(f)
     }
 }
@@ -91,7 +92,8 @@ impl<E> LayerErrorStatic<E>
 where
     E: Clone + Send + Sync + 'static,
 {
-    pub(crate) const fn new(e: E) -> Self {
+    /// Create a new [`LayerErrorStatic`], returning a clone of the given error value.
+    pub const fn new(e: E) -> Self {
         Self(e)
     }
 }
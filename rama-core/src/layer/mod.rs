@@ -714,7 +714,7 @@ mod map_result;
 pub use map_result::{MapResult, MapResultLayer};
 
 pub mod timeout;
-pub use timeout::{Timeout, TimeoutLayer};
+pub use timeout::{Deadline, Timeout, TimeoutLayer};
 
 pub mod limit;
 pub use limit::{Limit, LimitLayer};
@@ -725,6 +725,9 @@ pub use add_extension::{AddExtension, AddExtensionLayer};
 pub mod get_extension;
 pub use get_extension::{GetExtension, GetExtensionLayer};
 
+pub mod scoped;
+pub use scoped::{ScopedService, ScopedServiceLayer};
+
 macro_rules! impl_layer_either {
     ($id:ident, $($param:ident),+ $(,)?) => {
         impl<$($param),+, S> Layer<S> for crate::combinators::$id<$($param),+>
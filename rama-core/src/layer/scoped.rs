@@ -0,0 +1,563 @@
+//! Middleware to dispatch requests to services built per scope (e.g. per tenant).
+//!
+//! The [`ScopedServiceLayer`] resolves the scope of each request into a config,
+//! using a [`ScopeResolver`] (e.g. loading the settings of the authenticated tenant
+//! from a database), and dispatches the request to the service built for that config,
+//! wrapping the inner service. This allows per-scope stacks to be composed from
+//! regular layers, rather than scattering per-scope lookups over a shared stack.
+//!
+//! Built services are cached, keyed by their config, such that a service
+//! is only built again in case the config of its scope changed,
+//! its time-to-live expired or it was evicted as the least recently used.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::{
+//!     layer::{scoped::ScopedServiceLayer, MapResponseLayer},
+//!     service::service_fn,
+//!     Context, Layer, Service,
+//! };
+//! use std::convert::Infallible;
+//!
+//! #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+//! struct TenantConfig {
+//!     greeting: String,
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let layer = ScopedServiceLayer::new(
+//!     |ctx: &Context<()>| {
+//!         let tenant = ctx.get::<&'static str>().copied().unwrap_or("anonymous");
+//!         // e.g. load the config of the tenant from a database
+//!         async move {
+//!             Ok::<_, Infallible>(TenantConfig {
+//!                 greeting: format!("hello {tenant}"),
+//!             })
+//!         }
+//!     },
+//!     |config: &TenantConfig, inner| {
+//!         let greeting = config.greeting.clone();
+//!         MapResponseLayer::new(move |name: String| format!("{greeting}, {name}")).layer(inner)
+//!     },
+//! )
+//! .with_capacity(1024);
+//! let stats = layer.stats();
+//!
+//! let service = layer.layer(service_fn(|name: String| async move { Ok::<_, Infallible>(name) }));
+//!
+//! let mut ctx = Context::default();
+//! ctx.insert("acme");
+//! let res = service.serve(ctx, "john".to_owned()).await.unwrap();
+//! assert_eq!(res, "hello acme, john");
+//! assert_eq!(stats.misses(), 1);
+//! # }
+//! ```
+
+use crate::error::BoxError;
+use crate::{Context, Layer, Service};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    hash::Hash,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Resolves the scope of a request into the config used to build the service for that scope.
+///
+/// Implemented for any `Fn(&Context<State>) -> Future<Output = Result<Config, Error>>`,
+/// of which the future can not borrow from the [`Context`].
+pub trait ScopeResolver<State>: Send + Sync + 'static {
+    /// The config of a scope, also used as the key of the built services cache.
+    type Config: Clone + Eq + Hash + Send + Sync + 'static;
+    /// The error returned in case the scope could not be resolved.
+    type Error: Into<BoxError> + Send + 'static;
+
+    /// Resolve the config of the scope of the current request.
+    fn resolve(
+        &self,
+        ctx: &Context<State>,
+    ) -> impl Future<Output = Result<Self::Config, Self::Error>> + Send;
+}
+
+impl<State, F, Fut, Config, Error> ScopeResolver<State> for F
+where
+    F: Fn(&Context<State>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Config, Error>> + Send,
+    Config: Clone + Eq + Hash + Send + Sync + 'static,
+    Error: Into<BoxError> + Send + 'static,
+{
+    type Config = Config;
+    type Error = Error;
+
+    fn resolve(
+        &self,
+        ctx: &Context<State>,
+    ) -> impl Future<Output = Result<Self::Config, Self::Error>> + Send {
+        (self)(ctx)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Statistics of the built services cache of a [`ScopedServiceLayer`],
+/// shared by all services created by that layer.
+pub struct ScopedCacheStats(Arc<StatsInner>);
+
+#[derive(Debug, Default)]
+struct StatsInner {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl ScopedCacheStats {
+    /// The number of requests served by a cached service.
+    pub fn hits(&self) -> u64 {
+        self.0.hits.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests for which a service had to be built.
+    pub fn misses(&self) -> u64 {
+        self.0.misses.load(Ordering::Relaxed)
+    }
+
+    /// The number of cached services evicted to respect the capacity of the cache.
+    pub fn evictions(&self) -> u64 {
+        self.0.evictions.load(Ordering::Relaxed)
+    }
+
+    /// The ratio of requests served by a cached service, `0.0` if no requests were served yet.
+    pub fn hit_rate(&self) -> f64 {
+        let hits = self.hits();
+        let total = hits + self.misses();
+        if total == 0 {
+            0.0
+        } else {
+            hits as f64 / total as f64
+        }
+    }
+}
+
+/// The default amount of services cached by a [`ScopedServiceLayer`].
+const DEFAULT_CAPACITY: usize = 512;
+
+/// Cache of the services built per config, evicting the least recently used.
+struct ServiceCache<C, T> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    stats: ScopedCacheStats,
+    state: Mutex<CacheState<C, T>>,
+}
+
+struct CacheState<C, T> {
+    tick: u64,
+    entries: HashMap<C, CacheEntry<T>>,
+}
+
+struct CacheEntry<T> {
+    service: Arc<T>,
+    built_at: Instant,
+    last_used: u64,
+}
+
+impl<C, T> fmt::Debug for ServiceCache<C, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceCache")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .field("stats", &self.stats)
+            .field("len", &self.state.lock().entries.len())
+            .finish()
+    }
+}
+
+impl<C: Clone + Eq + Hash, T> ServiceCache<C, T> {
+    fn new(capacity: usize, ttl: Option<Duration>, stats: ScopedCacheStats) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ttl,
+            stats,
+            state: Mutex::new(CacheState {
+                tick: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    fn get_or_build(&self, config: C, build: impl FnOnce(&C) -> T) -> Arc<T> {
+        {
+            let mut state = self.state.lock();
+            state.tick += 1;
+            let tick = state.tick;
+            if let Some(entry) = state.entries.get_mut(&config) {
+                if self.ttl.is_none_or(|ttl| entry.built_at.elapsed() < ttl) {
+                    entry.last_used = tick;
+                    self.stats.0.hits.fetch_add(1, Ordering::Relaxed);
+                    return entry.service.clone();
+                }
+            }
+        }
+
+        // build outside of the lock, as building is user code
+        self.stats.0.misses.fetch_add(1, Ordering::Relaxed);
+        let service = Arc::new(build(&config));
+
+        let mut state = self.state.lock();
+        if state.entries.len() >= self.capacity && !state.entries.contains_key(&config) {
+            if let Some(lru) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(config, _)| config.clone())
+            {
+                state.entries.remove(&lru);
+                self.stats.0.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let tick = state.tick;
+        state.entries.insert(
+            config,
+            CacheEntry {
+                service: service.clone(),
+                built_at: Instant::now(),
+                last_used: tick,
+            },
+        );
+        service
+    }
+}
+
+/// Layer which dispatches requests to services built per scope,
+/// wrapping the inner service.
+///
+/// See the [module docs](self) for more details.
+pub struct ScopedServiceLayer<R, B, C> {
+    resolver: Arc<R>,
+    builder: Arc<B>,
+    capacity: usize,
+    ttl: Option<Duration>,
+    stats: ScopedCacheStats,
+    _config: PhantomData<fn() -> C>,
+}
+
+impl<R: fmt::Debug, B, C> fmt::Debug for ScopedServiceLayer<R, B, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScopedServiceLayer")
+            .field("resolver", &self.resolver)
+            .field("builder", &format_args!("{}", std::any::type_name::<B>()))
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl<R, B, C> Clone for ScopedServiceLayer<R, B, C> {
+    fn clone(&self) -> Self {
+        Self {
+            resolver: self.resolver.clone(),
+            builder: self.builder.clone(),
+            capacity: self.capacity,
+            ttl: self.ttl,
+            stats: self.stats.clone(),
+            _config: PhantomData,
+        }
+    }
+}
+
+impl<R, B, C> ScopedServiceLayer<R, B, C> {
+    /// Create a new [`ScopedServiceLayer`] using the given [`ScopeResolver`]
+    /// to resolve the config of the scope of a request, and the given builder
+    /// to build the service of a scope from its config and the inner service.
+    pub fn new(resolver: R, builder: B) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            builder: Arc::new(builder),
+            capacity: DEFAULT_CAPACITY,
+            ttl: None,
+            stats: ScopedCacheStats::default(),
+            _config: PhantomData,
+        }
+    }
+
+    /// Set the maximum amount of built services cached, 512 by default.
+    ///
+    /// The least recently used service is evicted once this capacity is reached.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set the maximum amount of built services cached, 512 by default.
+    ///
+    /// The least recently used service is evicted once this capacity is reached.
+    pub fn set_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set the time-to-live of built services, after which they are built again.
+    ///
+    /// By default built services live until evicted.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Set the time-to-live of built services, after which they are built again.
+    ///
+    /// By default built services live until evicted.
+    pub fn set_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Returns the [`ScopedCacheStats`] of the services created by this layer.
+    pub fn stats(&self) -> ScopedCacheStats {
+        self.stats.clone()
+    }
+}
+
+impl<S, R, B, C, T> Layer<S> for ScopedServiceLayer<R, B, C>
+where
+    B: Fn(&C, S) -> T,
+    C: Clone + Eq + Hash,
+{
+    type Service = ScopedService<S, R, B, C, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ScopedService {
+            inner,
+            resolver: self.resolver.clone(),
+            builder: self.builder.clone(),
+            cache: Arc::new(ServiceCache::new(
+                self.capacity,
+                self.ttl,
+                self.stats.clone(),
+            )),
+        }
+    }
+}
+
+/// Service which dispatches requests to services built per scope,
+/// wrapping the inner service.
+///
+/// Created using the [`ScopedServiceLayer`], see the [module docs](self) for more details.
+pub struct ScopedService<S, R, B, C, T> {
+    inner: S,
+    resolver: Arc<R>,
+    builder: Arc<B>,
+    cache: Arc<ServiceCache<C, T>>,
+}
+
+impl<S: fmt::Debug, R: fmt::Debug, B, C, T> fmt::Debug for ScopedService<S, R, B, C, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScopedService")
+            .field("inner", &self.inner)
+            .field("resolver", &self.resolver)
+            .field("builder", &format_args!("{}", std::any::type_name::<B>()))
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+impl<S: Clone, R, B, C, T> Clone for ScopedService<S, R, B, C, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            resolver: self.resolver.clone(),
+            builder: self.builder.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<S, R, B, C, T> ScopedService<S, R, B, C, T> {
+    /// Gets a reference to the underlying service, wrapped by each built service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the [`ScopedCacheStats`] of this service.
+    pub fn stats(&self) -> ScopedCacheStats {
+        self.cache.stats.clone()
+    }
+}
+
+impl<S, R, B, C, T, State, Request> Service<State, Request> for ScopedService<S, R, B, C, T>
+where
+    S: Clone + Send + Sync + 'static,
+    R: ScopeResolver<State, Config = C>,
+    B: Fn(&C, S) -> T + Send + Sync + 'static,
+    C: Clone + Eq + Hash + Send + Sync + 'static,
+    T: Service<State, Request, Error: Into<BoxError>>,
+    State: Clone + Send + Sync + 'static,
+    Request: Send + 'static,
+{
+    type Response = T::Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let config = self.resolver.resolve(&ctx).await.map_err(Into::into)?;
+        let service = self
+            .cache
+            .get_or_build(config, |config| (self.builder)(config, self.inner.clone()));
+        service.serve(ctx, req).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::OpaqueError,
+        layer::{MapErrLayer, MapResponseLayer},
+        service::{service_fn, BoxService},
+    };
+    use std::convert::Infallible;
+
+    #[derive(Debug, Clone)]
+    struct Tenant(&'static str);
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum Policy {
+        Uppercase,
+        Deny,
+    }
+
+    #[derive(Debug, Clone)]
+    struct Echo;
+
+    impl Service<(), String> for Echo {
+        type Response = String;
+        type Error = Infallible;
+
+        async fn serve(&self, _ctx: Context<()>, req: String) -> Result<String, Infallible> {
+            Ok(req)
+        }
+    }
+
+    fn tenant_layer() -> ScopedServiceLayer<
+        impl ScopeResolver<(), Config = Policy>,
+        impl Fn(&Policy, Echo) -> BoxService<(), String, String, BoxError>,
+        Policy,
+    > {
+        ScopedServiceLayer::new(
+            |ctx: &Context<()>| {
+                let tenant = ctx.get::<Tenant>().cloned();
+                async move {
+                    // simulate a database lookup
+                    tokio::task::yield_now().await;
+                    match tenant {
+                        Some(Tenant("acme")) => Ok(Policy::Uppercase),
+                        Some(Tenant("globex")) => Ok(Policy::Deny),
+                        _ => Err(OpaqueError::from_display("unknown tenant")),
+                    }
+                }
+            },
+            |policy: &Policy, inner: Echo| match policy {
+                Policy::Uppercase => (
+                    MapErrLayer::new(BoxError::from),
+                    MapResponseLayer::new(|res: String| res.to_uppercase()),
+                )
+                    .layer(inner)
+                    .boxed(),
+                Policy::Deny => service_fn(|_: String| async {
+                    Err::<String, BoxError>(OpaqueError::from_display("denied").into())
+                })
+                .boxed(),
+            },
+        )
+    }
+
+    fn ctx(tenant: &'static str) -> Context<()> {
+        let mut ctx = Context::default();
+        ctx.insert(Tenant(tenant));
+        ctx
+    }
+
+    #[tokio::test]
+    async fn test_scoped_service_per_tenant_concurrently() {
+        let layer = tenant_layer();
+        let stats = layer.stats();
+        let service = Arc::new(layer.layer(Echo));
+
+        let handles: Vec<_> = (0..10)
+            .map(|i| {
+                let service = service.clone();
+                let tenant = if i % 2 == 0 { "acme" } else { "globex" };
+                tokio::spawn(async move {
+                    (tenant, service.serve(ctx(tenant), format!("req{i}")).await)
+                })
+            })
+            .collect();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            match handle.await.unwrap() {
+                ("acme", result) => assert_eq!(result.unwrap(), format!("REQ{i}")),
+                (_, result) => assert_eq!(result.unwrap_err().to_string(), "denied"),
+            }
+        }
+
+        // services are built at least once per tenant,
+        // and at most once per request in case of concurrent misses
+        assert!(stats.misses() >= 2);
+        assert_eq!(stats.hits() + stats.misses(), 10);
+        assert_eq!(stats.evictions(), 0);
+
+        // once built, services are reused
+        let misses = stats.misses();
+        assert_eq!(
+            service.serve(ctx("acme"), "a".to_owned()).await.unwrap(),
+            "A"
+        );
+        assert!(service.serve(ctx("globex"), "b".to_owned()).await.is_err());
+        assert_eq!(stats.misses(), misses);
+
+        // unresolved scopes fail without building a service
+        let err = service
+            .serve(ctx("initech"), "c".to_owned())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown tenant");
+        assert_eq!(stats.misses(), misses);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_service_cache_bounds() {
+        let layer = tenant_layer().with_capacity(1);
+        let stats = layer.stats();
+        let service = layer.layer(Echo);
+
+        for tenant in ["acme", "acme", "globex", "acme"] {
+            let _ = service.serve(ctx(tenant), "req".to_owned()).await;
+        }
+        assert_eq!(stats.hits(), 1);
+        assert_eq!(stats.misses(), 3);
+        assert_eq!(stats.evictions(), 2);
+        assert_eq!(stats.hit_rate(), 0.25);
+
+        let layer = tenant_layer().with_ttl(Duration::ZERO);
+        let stats = layer.stats();
+        let service = layer.layer(Echo);
+
+        for _ in 0..3 {
+            let _ = service.serve(ctx("acme"), "req".to_owned()).await;
+        }
+        assert_eq!(stats.hits(), 0);
+        assert_eq!(stats.misses(), 3);
+        assert_eq!(stats.evictions(), 0);
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// An [`Extensions`] value that can be added to the [`Context`]
+/// to communicate the point in time by which a request has to be served.
+///
+/// The [`Timeout`] middleware enforces the [`Deadline`] found in the [`Context`],
+/// in case it expires prior to its own timeout.
+///
+/// [`Extensions`]: crate::context::Extensions
+/// [`Context`]: crate::Context
+/// [`Timeout`]: super::Timeout
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// Create a new [`Deadline`] expiring at the given [`Instant`].
+    pub const fn new(instant: Instant) -> Self {
+        Self(instant)
+    }
+
+    /// Create a new [`Deadline`] expiring after the given [`Duration`], starting now.
+    pub fn after(duration: Duration) -> Self {
+        let now = Instant::now();
+        Self(
+            now.checked_add(duration)
+                .unwrap_or(now + Duration::from_secs(u32::MAX as u64)),
+        )
+    }
+
+    /// Get the [`Instant`] at which this [`Deadline`] expires.
+    pub const fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Get the [`Duration`] remaining until this [`Deadline`] expires,
+    /// which is zero in case it already expired.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns `true` if this [`Deadline`] has expired.
+    pub fn is_expired(&self) -> bool {
+        self.0 <= Instant::now()
+    }
+}
+
+impl From<Instant> for Deadline {
+    fn from(instant: Instant) -> Self {
+        Self(instant)
+    }
+}
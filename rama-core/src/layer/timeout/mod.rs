@@ -1,7 +1,8 @@
 //! Middleware that applies a timeout to requests.
 //!
 //! If the response does not complete within the specified timeout, the response
-//! will be aborted.
+//! will be aborted. The timeout is shortened in case a [`Deadline`] found in the
+//! [`Context`] expires earlier.
 
 use super::{LayerErrorFn, LayerErrorStatic, MakeLayerError};
 use crate::{Context, Service};
@@ -16,6 +17,10 @@ mod layer;
 #[doc(inline)]
 pub use layer::TimeoutLayer;
 
+mod deadline;
+#[doc(inline)]
+pub use deadline::Deadline;
+
 /// Applies a timeout to requests.
 pub struct Timeout<S, F> {
     inner: S,
@@ -122,9 +127,49 @@ where
         ctx: Context<S>,
         request: Request,
     ) -> Result<Self::Response, Self::Error> {
+        let timeout = match ctx.get::<Deadline>() {
+            Some(deadline) => deadline.remaining().min(self.timeout),
+            None => self.timeout,
+        };
         tokio::select! {
             res = self.inner.serve(ctx, request) => res,
-            _ = tokio::time::sleep(self.timeout) => Err(self.into_error.make_layer_error().into()),
+            _ = tokio::time::sleep(timeout) => Err(self.into_error.make_layer_error().into()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::BoxError, service::service_fn};
+
+    #[tokio::test]
+    async fn test_timeout_enforces_deadline() {
+        let svc = Timeout::new(
+            service_fn(|_ctx, ()| async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok::<_, BoxError>(())
+            }),
+            Duration::from_secs(30),
+        );
+
+        let mut ctx = Context::default();
+        ctx.insert(Deadline::after(Duration::from_millis(10)));
+
+        let err = svc.serve(ctx, ()).await.unwrap_err();
+        assert!(err.is::<Elapsed>());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_without_deadline() {
+        let svc = Timeout::new(
+            service_fn(|_ctx, ()| async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Ok::<_, BoxError>(())
+            }),
+            Duration::from_secs(30),
+        );
+
+        svc.serve(Context::default(), ()).await.unwrap();
+    }
+}
@@ -43,5 +43,7 @@ pub mod matcher;
 
 pub mod username;
 
+pub mod store;
+
 #[cfg(feature = "telemetry")]
 pub mod telemetry;
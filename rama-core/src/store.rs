@@ -0,0 +1,627 @@
+//! Bounded stores for long-lived per-key state.
+//!
+//! Layers such as caches and limiters often need to keep state per key
+//! (e.g. per client ip or per target authority). Without bounds these maps grow
+//! forever under key churn. The [`KeyedStateStore`] provided here is a sharded
+//! concurrent map which evicts entries once they expire (ttl), once they were not
+//! accessed for a while (idle timeout) or, in case the store is full,
+//! the least recently used entry of a shard.
+//!
+//! Expired entries are evicted lazily on access, and in bulk by [`KeyedStateStore::sweep`],
+//! which can be run periodically in the background using [`KeyedStateStore::spawn_sweeper`].
+
+use crate::graceful::ShutdownGuard;
+use parking_lot::Mutex;
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasher, Hash, RandomState},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+
+const SHARDS: usize = 16;
+
+/// A sharded concurrent map to store per-key state,
+/// bounded in both size and (optionally) time.
+///
+/// Clones of the store share the same entries, ttl and idle timeout.
+pub struct KeyedStateStore<K, V> {
+    shared: Arc<Shared<K, V>>,
+}
+
+struct Shared<K, V> {
+    shards: Box<[Mutex<Shard<K, V>>]>,
+    hasher: RandomState,
+    max_entries_per_shard: usize,
+    ttl: AtomicTimeout,
+    idle_timeout: AtomicTimeout,
+    stats: Stats,
+}
+
+/// An optional [`Duration`] stored as nanoseconds, `u64::MAX` meaning none.
+struct AtomicTimeout(AtomicU64);
+
+struct Shard<K, V> {
+    entries: HashMap<K, Entry<V>>,
+}
+
+struct Entry<V> {
+    value: V,
+    inserted_at: Instant,
+    last_access: Instant,
+}
+
+#[derive(Default)]
+struct Stats {
+    entries: AtomicUsize,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    sweep_duration_nanos: AtomicU64,
+}
+
+/// Statistics of a [`KeyedStateStore`], see [`KeyedStateStore::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyedStateStoreStats {
+    /// The number of entries currently stored.
+    pub entries: usize,
+    /// The number of entries evicted to make room for new entries (so far).
+    pub evictions: u64,
+    /// The number of entries removed because they expired or were idle (so far).
+    pub expirations: u64,
+    /// The duration of the last sweep.
+    pub last_sweep_duration: Duration,
+}
+
+impl<K, V> fmt::Debug for KeyedStateStore<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedStateStore")
+            .field("ttl", &self.shared.ttl.get())
+            .field("idle_timeout", &self.shared.idle_timeout.get())
+            .field("max_entries", &self.max_entries())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl<K, V> Clone for KeyedStateStore<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K, V> KeyedStateStore<K, V> {
+    /// The default maximum number of entries of a [`KeyedStateStore`].
+    pub const DEFAULT_MAX_ENTRIES: usize = 4096;
+
+    /// Create a new [`KeyedStateStore`] bounded to [`Self::DEFAULT_MAX_ENTRIES`] entries,
+    /// without a ttl or idle timeout.
+    pub fn new() -> Self {
+        Self::with_max_entries(Self::DEFAULT_MAX_ENTRIES)
+    }
+
+    /// Create a new [`KeyedStateStore`] bounded to the given number of entries,
+    /// without a ttl or idle timeout.
+    ///
+    /// The bound is divided over the shards of the store,
+    /// with each shard evicting its least recently used entry when full.
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                shards: (0..SHARDS)
+                    .map(|_| {
+                        Mutex::new(Shard {
+                            entries: HashMap::new(),
+                        })
+                    })
+                    .collect(),
+                hasher: RandomState::new(),
+                max_entries_per_shard: max_entries.div_ceil(SHARDS).max(1),
+                ttl: AtomicTimeout::none(),
+                idle_timeout: AtomicTimeout::none(),
+                stats: Stats::default(),
+            }),
+        }
+    }
+
+    /// Expire entries once they were inserted for longer than the given duration.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.set_ttl(ttl);
+        self
+    }
+
+    /// Expire entries once they were inserted for longer than the given duration.
+    ///
+    /// Applies to all clones of the store.
+    pub fn set_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.shared.ttl.set(ttl);
+        self
+    }
+
+    /// Expire entries once they were not accessed for longer than the given duration.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.set_idle_timeout(timeout);
+        self
+    }
+
+    /// Expire entries once they were not accessed for longer than the given duration.
+    ///
+    /// Applies to all clones of the store.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.shared.idle_timeout.set(timeout);
+        self
+    }
+
+    /// Get the ttl of the entries, if any.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.shared.ttl.get()
+    }
+
+    /// Get the idle timeout of the entries, if any.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.shared.idle_timeout.get()
+    }
+
+    /// Get the maximum number of entries of this store.
+    pub fn max_entries(&self) -> usize {
+        self.shared.max_entries_per_shard * SHARDS
+    }
+
+    /// Get the number of entries currently stored,
+    /// including expired entries which were not yet evicted.
+    pub fn len(&self) -> usize {
+        self.shared.stats.entries.load(Ordering::Acquire)
+    }
+
+    /// Returns true if no entries are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the [`KeyedStateStoreStats`] of this store.
+    pub fn stats(&self) -> KeyedStateStoreStats {
+        self.shared.stats.snapshot()
+    }
+
+    /// Remove all expired and idle entries, returning the number of entries removed.
+    pub fn sweep(&self) -> usize {
+        sweep(&self.shared)
+    }
+
+    /// Spawn a task which [sweeps](Self::sweep) the store at the given interval,
+    /// until shutdown is triggered or all clones of the store are dropped.
+    pub fn spawn_sweeper(&self, guard: ShutdownGuard, interval: Duration)
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        let shared = Arc::downgrade(&self.shared);
+        guard.spawn_task_fn(move |guard| run_sweeper(guard, shared, interval));
+    }
+
+    #[cfg(feature = "telemetry")]
+    /// Register observable gauges for the [`KeyedStateStoreStats`] of this store
+    /// with the given [`Meter`], using the given name as metric prefix.
+    ///
+    /// The gauges stop reporting once all clones of the store are dropped.
+    ///
+    /// [`Meter`]: crate::telemetry::opentelemetry::metrics::Meter
+    pub fn register_metrics(
+        &self,
+        meter: &crate::telemetry::opentelemetry::metrics::Meter,
+        name: &str,
+    ) where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let shared = Arc::downgrade(&self.shared);
+        meter
+            .u64_observable_gauge(format!("{name}.entries"))
+            .with_description("number of entries stored")
+            .with_callback(move |observer| {
+                if let Some(shared) = shared.upgrade() {
+                    observer.observe(shared.stats.snapshot().entries as u64, &[]);
+                }
+            })
+            .build();
+
+        let shared = Arc::downgrade(&self.shared);
+        meter
+            .u64_observable_gauge(format!("{name}.evictions"))
+            .with_description("number of entries evicted to make room for new entries")
+            .with_callback(move |observer| {
+                if let Some(shared) = shared.upgrade() {
+                    observer.observe(shared.stats.snapshot().evictions, &[]);
+                }
+            })
+            .build();
+
+        let shared = Arc::downgrade(&self.shared);
+        meter
+            .f64_observable_gauge(format!("{name}.sweep_duration"))
+            .with_description("duration of the last sweep")
+            .with_unit("s")
+            .with_callback(move |observer| {
+                if let Some(shared) = shared.upgrade() {
+                    observer.observe(
+                        shared.stats.snapshot().last_sweep_duration.as_secs_f64(),
+                        &[],
+                    );
+                }
+            })
+            .build();
+    }
+}
+
+impl<K, V> Default for KeyedStateStore<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V> KeyedStateStore<K, V> {
+    /// Get a clone of the value stored for the given key,
+    /// `None` if no value is stored or if it expired.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.with_value(key, |value| value.clone())
+    }
+
+    /// Call the given function with a mutable reference to the value stored for the given key,
+    /// returning its result, or `None` if no value is stored or if it expired.
+    pub fn with_value<Q, T>(&self, key: &Q, f: impl FnOnce(&mut V) -> T) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        let mut shard = self.shard(key).lock();
+        let entry = shard.entries.get_mut(key)?;
+        if self.is_expired(entry, now) {
+            shard.entries.remove(key);
+            self.shared.stats.record_expired(1);
+            return None;
+        }
+        entry.last_access = now;
+        Some(f(&mut entry.value))
+    }
+
+    /// Call the given function with a mutable reference to the value stored for the given key,
+    /// inserting the value created by `default` first in case no (unexpired) value is stored.
+    pub fn with_value_or_insert_with<T>(
+        &self,
+        key: K,
+        default: impl FnOnce() -> V,
+        f: impl FnOnce(&mut V) -> T,
+    ) -> T {
+        let now = Instant::now();
+        let mut shard = self.shard(&key).lock();
+        if let Some(entry) = shard.entries.get_mut(&key) {
+            if !self.is_expired(entry, now) {
+                entry.last_access = now;
+                return f(&mut entry.value);
+            }
+            shard.entries.remove(&key);
+            self.shared.stats.record_expired(1);
+        }
+        self.make_room(&mut shard, now);
+        let entry = shard.entries.entry(key).or_insert(Entry {
+            value: default(),
+            inserted_at: now,
+            last_access: now,
+        });
+        self.shared.stats.entries.fetch_add(1, Ordering::AcqRel);
+        f(&mut entry.value)
+    }
+
+    /// Insert the value for the given key, returning the previous (unexpired) value if any.
+    ///
+    /// In case the shard of the key is full, its least recently used entry is evicted.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let now = Instant::now();
+        let mut shard = self.shard(&key).lock();
+        let entry = Entry {
+            value,
+            inserted_at: now,
+            last_access: now,
+        };
+        if let Some(previous) = shard.entries.get_mut(&key) {
+            let previous = std::mem::replace(previous, entry);
+            if self.is_expired(&previous, now) {
+                self.shared.stats.record_expired(1);
+                return None;
+            }
+            return Some(previous.value);
+        }
+        self.make_room(&mut shard, now);
+        shard.entries.insert(key, entry);
+        self.shared.stats.entries.fetch_add(1, Ordering::AcqRel);
+        None
+    }
+
+    /// Remove the value stored for the given key, returning it if it did not expire yet.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.shard(key).lock().entries.remove(key)?;
+        self.shared.stats.entries.fetch_sub(1, Ordering::AcqRel);
+        (!self.is_expired(&entry, Instant::now())).then_some(entry.value)
+    }
+
+    fn shard<Q>(&self, key: &Q) -> &Mutex<Shard<K, V>>
+    where
+        Q: Hash + ?Sized,
+    {
+        let index = self.shared.hasher.hash_one(key) as usize % SHARDS;
+        &self.shared.shards[index]
+    }
+
+    fn is_expired(&self, entry: &Entry<V>, now: Instant) -> bool {
+        self.shared.is_expired(entry, now)
+    }
+
+    fn make_room(&self, shard: &mut Shard<K, V>, now: Instant) {
+        if shard.entries.len() < self.shared.max_entries_per_shard {
+            return;
+        }
+
+        let before = shard.entries.len();
+        shard
+            .entries
+            .retain(|_, entry| !self.shared.is_expired(entry, now));
+        let expired = before - shard.entries.len();
+        if expired > 0 {
+            self.shared.stats.record_expired(expired);
+            return;
+        }
+
+        if let Some(lru_access) = shard.entries.values().map(|entry| entry.last_access).min() {
+            let mut evicted = false;
+            shard.entries.retain(|_, entry| {
+                if evicted || entry.last_access != lru_access {
+                    return true;
+                }
+                evicted = true;
+                false
+            });
+            self.shared.stats.entries.fetch_sub(1, Ordering::AcqRel);
+            self.shared.stats.evictions.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+}
+
+impl<K, V> Shared<K, V> {
+    fn is_expired(&self, entry: &Entry<V>, now: Instant) -> bool {
+        is_expired(entry, now, self.ttl.get(), self.idle_timeout.get())
+    }
+}
+
+fn is_expired<V>(
+    entry: &Entry<V>,
+    now: Instant,
+    ttl: Option<Duration>,
+    idle_timeout: Option<Duration>,
+) -> bool {
+    ttl.is_some_and(|ttl| now.saturating_duration_since(entry.inserted_at) >= ttl)
+        || idle_timeout
+            .is_some_and(|timeout| now.saturating_duration_since(entry.last_access) >= timeout)
+}
+
+fn sweep<K, V>(shared: &Shared<K, V>) -> usize {
+    let start = std::time::Instant::now();
+    let mut removed = 0;
+    let (ttl, idle_timeout) = (shared.ttl.get(), shared.idle_timeout.get());
+    if ttl.is_some() || idle_timeout.is_some() {
+        let now = Instant::now();
+        for shard in shared.shards.iter() {
+            let mut shard = shard.lock();
+            let before = shard.entries.len();
+            shard
+                .entries
+                .retain(|_, entry| !is_expired(entry, now, ttl, idle_timeout));
+            removed += before - shard.entries.len();
+        }
+    }
+    shared.stats.record_expired(removed);
+    shared
+        .stats
+        .sweep_duration_nanos
+        .store(start.elapsed().as_nanos() as u64, Ordering::Release);
+    removed
+}
+
+async fn run_sweeper<K, V>(guard: ShutdownGuard, shared: Weak<Shared<K, V>>, interval: Duration) {
+    let mut interval = tokio::time::interval_at(Instant::now() + interval, interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = guard.cancelled() => return,
+            _ = interval.tick() => (),
+        }
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let removed = sweep(&shared);
+        tracing::trace!("keyed state store: swept {removed} expired entries");
+    }
+}
+
+impl AtomicTimeout {
+    const NONE: u64 = u64::MAX;
+
+    fn none() -> Self {
+        Self(AtomicU64::new(Self::NONE))
+    }
+
+    fn get(&self) -> Option<Duration> {
+        match self.0.load(Ordering::Acquire) {
+            Self::NONE => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+
+    fn set(&self, timeout: Duration) {
+        let nanos = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        self.0.store(nanos.min(Self::NONE - 1), Ordering::Release);
+    }
+}
+
+impl Stats {
+    fn record_expired(&self, n: usize) {
+        if n > 0 {
+            self.entries.fetch_sub(n, Ordering::AcqRel);
+            self.expirations.fetch_add(n as u64, Ordering::AcqRel);
+        }
+    }
+
+    fn snapshot(&self) -> KeyedStateStoreStats {
+        KeyedStateStoreStats {
+            entries: self.entries.load(Ordering::Acquire),
+            evictions: self.evictions.load(Ordering::Acquire),
+            expirations: self.expirations.load(Ordering::Acquire),
+            last_sweep_duration: Duration::from_nanos(
+                self.sweep_duration_nanos.load(Ordering::Acquire),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keyed_state_store_ttl() {
+        let store = KeyedStateStore::new().with_ttl(Duration::from_secs(10));
+        store.insert("a", 1);
+        assert_eq!(store.get("a"), Some(1));
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        store.insert("b", 2);
+        assert_eq!(store.get("a"), Some(1));
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(store.get("a"), None);
+        assert_eq!(store.get("b"), Some(2));
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.stats().expirations, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keyed_state_store_idle_timeout() {
+        let store = KeyedStateStore::new().with_idle_timeout(Duration::from_secs(10));
+        store.insert("a", 1);
+        store.insert("b", 2);
+
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_secs(6)).await;
+            assert_eq!(store.get("a"), Some(1));
+        }
+
+        assert_eq!(store.sweep(), 1);
+        assert_eq!(store.get("b"), None);
+        assert_eq!(store.get("a"), Some(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keyed_state_store_clones_share_timeouts() {
+        let mut store = KeyedStateStore::new();
+        let clone = store.clone();
+        assert_eq!(clone.ttl(), None);
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = crate::graceful::Shutdown::new(rx);
+        clone.spawn_sweeper(shutdown.guard(), Duration::from_secs(30));
+
+        store.set_ttl(Duration::from_secs(10));
+        assert_eq!(clone.ttl(), Some(Duration::from_secs(10)));
+        clone.insert("a", 1);
+
+        // the sweeper, spawned before the ttl was set, expires the entry
+        tokio::time::sleep(Duration::from_secs(31)).await;
+        assert!(store.is_empty());
+        assert_eq!(store.stats().expirations, 1);
+
+        tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(60), shutdown.shutdown())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_keyed_state_store_lru_eviction() {
+        let store = KeyedStateStore::with_max_entries(SHARDS);
+        assert_eq!(store.max_entries(), SHARDS);
+
+        // one entry per shard: find two keys sharing a shard
+        let first = 0usize;
+        let second = (1..)
+            .find(|key| std::ptr::eq(store.shard(key), store.shard(&first)))
+            .unwrap();
+
+        store.insert(first, "first");
+        store.insert(second, "second");
+        assert_eq!(store.get(&first), None);
+        assert_eq!(store.get(&second), Some("second"));
+        assert_eq!(store.stats().evictions, 1);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_keyed_state_store_with_value() {
+        let store = KeyedStateStore::new();
+        for _ in 0..3 {
+            store.with_value_or_insert_with("counter", || 0, |n| *n += 1);
+        }
+        assert_eq!(store.with_value("counter", |n| *n), Some(3));
+        assert_eq!(store.remove("counter"), Some(3));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_keyed_state_store_bounded_under_key_churn() {
+        let store = KeyedStateStore::with_max_entries(1024);
+        for key in 0..100_000u64 {
+            store.with_value_or_insert_with(key, || [0u8; 64], |_| ());
+            assert!(store.len() <= store.max_entries());
+        }
+        let stats = store.stats();
+        assert_eq!(stats.entries, store.len());
+        assert_eq!(stats.evictions, 100_000 - stats.entries as u64);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keyed_state_store_sweeper() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = crate::graceful::Shutdown::new(rx);
+
+        let store = KeyedStateStore::new().with_ttl(Duration::from_secs(10));
+        store.spawn_sweeper(shutdown.guard(), Duration::from_secs(30));
+        for key in 0..100 {
+            store.insert(key, ());
+        }
+
+        tokio::time::sleep(Duration::from_secs(31)).await;
+        assert!(store.is_empty());
+        assert_eq!(store.stats().expirations, 100);
+
+        // the sweeper stops on shutdown
+        tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(60), shutdown.shutdown())
+            .await
+            .unwrap();
+    }
+}
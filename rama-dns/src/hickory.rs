@@ -1,6 +1,9 @@
 //! dns using the [`hickory_resolver`] crate
 
-use crate::DnsResolver;
+use crate::{
+    srv::{SrvName, SrvRecord, SrvResolver},
+    DnsResolver,
+};
 use hickory_resolver::{
     proto::rr::rdata::{A, AAAA},
     Name, TokioAsyncResolver,
@@ -128,6 +131,34 @@ impl DnsResolver for HickoryDns {
     }
 }
 
+impl SrvResolver for HickoryDns {
+    type Error = OpaqueError;
+
+    async fn srv_lookup(&self, name: SrvName) -> Result<Vec<SrvRecord>, Self::Error> {
+        let mut fqdn =
+            Name::from_utf8(name.to_string()).context("try to use SrvName as a Dns Name")?;
+        fqdn.set_fqdn(true);
+        Ok(self
+            .0
+            .srv_lookup(fqdn)
+            .await
+            .context("lookup SRV record(s)")?
+            .iter()
+            .filter_map(|srv| {
+                // a target of "." signals that the service is decidedly not available
+                let target = srv.target().to_utf8();
+                let target = Domain::try_from(target.trim_end_matches('.').to_owned()).ok()?;
+                Some(SrvRecord {
+                    priority: srv.priority(),
+                    weight: srv.weight(),
+                    port: srv.port(),
+                    target,
+                })
+            })
+            .collect())
+    }
+}
+
 fn fqdn_from_domain(domain: Domain) -> Result<Name, OpaqueError> {
     let mut name = Name::from_utf8(domain).context("try to consume a Domain as a Dns Name")?;
     name.set_fqdn(true);
@@ -93,4 +93,8 @@ pub use deny_all::{DenyAllDns, DnsDeniedError};
 
 pub mod chain;
 
+pub mod srv;
+#[doc(inline)]
+pub use srv::{SrvName, SrvRecord, SrvResolver};
+
 mod variant;
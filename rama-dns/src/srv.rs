@@ -0,0 +1,326 @@
+//! SRV record lookup support, used for service discovery.
+//!
+//! See [RFC 2782] for more information about SRV records.
+//!
+//! [RFC 2782]: https://datatracker.ietf.org/doc/html/rfc2782
+
+use rama_core::error::{ErrorContext, OpaqueError};
+use rama_net::address::{Authority, Domain};
+use rama_utils::rng::{HasherRng, Rng};
+use std::{borrow::Cow, fmt, future::Future, str::FromStr, sync::Arc};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// The name of a service to be discovered using SRV records,
+/// formatted as `_service._proto.name`, e.g. `_xmpp-server._tcp.example.com`.
+pub struct SrvName {
+    service: Cow<'static, str>,
+    protocol: Cow<'static, str>,
+    domain: Domain,
+}
+
+impl SrvName {
+    /// Create a new [`SrvName`] for the given service and protocol
+    /// (e.g. `http` and `tcp`) offered by the given [`Domain`].
+    ///
+    /// The service and protocol can be given with or without their leading underscore.
+    pub fn new(
+        service: impl Into<Cow<'static, str>>,
+        protocol: impl Into<Cow<'static, str>>,
+        domain: Domain,
+    ) -> Result<Self, OpaqueError> {
+        let service = strip_label_underscore(service.into());
+        let protocol = strip_label_underscore(protocol.into());
+        if !is_valid_label(&service) {
+            return Err(OpaqueError::from_display(format!(
+                "invalid srv service label: '{service}'"
+            )));
+        }
+        if !is_valid_label(&protocol) {
+            return Err(OpaqueError::from_display(format!(
+                "invalid srv protocol label: '{protocol}'"
+            )));
+        }
+        Ok(Self {
+            service,
+            protocol,
+            domain,
+        })
+    }
+
+    /// The service label, without its leading underscore.
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    /// The protocol label, without its leading underscore.
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// The [`Domain`] offering the service.
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+}
+
+fn strip_label_underscore(label: Cow<'static, str>) -> Cow<'static, str> {
+    match label {
+        Cow::Borrowed(s) => Cow::Borrowed(s.strip_prefix('_').unwrap_or(s)),
+        Cow::Owned(s) => match s.strip_prefix('_') {
+            Some(stripped) => Cow::Owned(stripped.to_owned()),
+            None => Cow::Owned(s),
+        },
+    }
+}
+
+fn is_valid_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() < 63
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-')
+}
+
+impl fmt::Display for SrvName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "_{}._{}.{}", self.service, self.protocol, self.domain)
+    }
+}
+
+impl FromStr for SrvName {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (service, rest) = s
+            .split_once('.')
+            .filter(|(service, _)| service.starts_with('_'))
+            .context("missing '_service' label in srv name")?;
+        let (protocol, domain) = rest
+            .split_once('.')
+            .filter(|(protocol, _)| protocol.starts_with('_'))
+            .context("missing '_proto' label in srv name")?;
+        let domain = Domain::try_from(domain.to_owned()).context("parse srv name domain")?;
+        Self::new(service.to_owned(), protocol.to_owned(), domain)
+    }
+}
+
+impl TryFrom<&str> for SrvName {
+    type Error = OpaqueError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A single SRV record, pointing to a target offering the looked up service.
+pub struct SrvRecord {
+    /// The priority of the target, targets with a lower priority are to be tried first.
+    pub priority: u16,
+    /// The relative weight of the target among the targets with the same priority.
+    pub weight: u16,
+    /// The port on the target at which the service is offered.
+    pub port: u16,
+    /// The domain of the target.
+    pub target: Domain,
+}
+
+impl SrvRecord {
+    /// The [`Authority`] (target and port) at which the service is offered.
+    pub fn authority(&self) -> Authority {
+        (self.target.clone(), self.port).into()
+    }
+}
+
+/// A resolver of [`SrvName`]s into the [`SrvRecord`]s of its targets.
+pub trait SrvResolver: Send + Sync + 'static {
+    /// Error returned by the [`SrvResolver`]
+    type Error;
+
+    /// Lookup the SRV records of the given [`SrvName`].
+    ///
+    /// The records are returned in the order received,
+    /// use [`order_srv_records`] to get them in the order to try them in.
+    fn srv_lookup(
+        &self,
+        name: SrvName,
+    ) -> impl Future<Output = Result<Vec<SrvRecord>, Self::Error>> + Send + '_;
+}
+
+impl<R: SrvResolver> SrvResolver for Arc<R> {
+    type Error = R::Error;
+
+    fn srv_lookup(
+        &self,
+        name: SrvName,
+    ) -> impl Future<Output = Result<Vec<SrvRecord>, Self::Error>> + Send + '_ {
+        (**self).srv_lookup(name)
+    }
+}
+
+/// Order the given [`SrvRecord`]s in the order in which its targets
+/// are to be tried, as defined by [RFC 2782].
+///
+/// Records are ordered by ascending priority, and within
+/// the same priority in a random order weighted by their weight.
+///
+/// [RFC 2782]: https://datatracker.ietf.org/doc/html/rfc2782
+pub fn order_srv_records<R: Rng>(mut records: Vec<SrvRecord>, rng: &mut R) -> Vec<SrvRecord> {
+    records.sort_by_key(|record| record.priority);
+
+    let mut ordered = Vec::with_capacity(records.len());
+    let mut records = records.into_iter().peekable();
+    while let Some(first) = records.next() {
+        let priority = first.priority;
+        let mut group = vec![first];
+        while let Some(record) = records.next_if(|record| record.priority == priority) {
+            group.push(record);
+        }
+
+        // zero-weight records are placed first, giving them
+        // only a very small chance of being selected first
+        group.sort_by_key(|record| record.weight != 0);
+        let mut total_weight: u64 = group.iter().map(|record| record.weight as u64).sum();
+        while !group.is_empty() {
+            let pick = rng.next_range(0..total_weight + 1);
+            let mut running_weight = 0;
+            let index = group
+                .iter()
+                .position(|record| {
+                    running_weight += record.weight as u64;
+                    running_weight >= pick
+                })
+                .unwrap_or(group.len() - 1);
+            let record = group.remove(index);
+            total_weight -= record.weight as u64;
+            ordered.push(record);
+        }
+    }
+    ordered
+}
+
+/// Lookup the SRV records of the given [`SrvName`] using the given [`SrvResolver`],
+/// returning the [`Authority`] of its targets in the order in which they are to be tried.
+///
+/// Use the returned authorities as the targets of a connector or load balancer.
+pub async fn lookup_srv_authorities<R: SrvResolver>(
+    resolver: &R,
+    name: SrvName,
+) -> Result<Vec<Authority>, R::Error> {
+    let records = resolver.srv_lookup(name).await?;
+    Ok(order_srv_records(records, &mut HasherRng::new())
+        .iter()
+        .map(SrvRecord::authority)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    struct MockSrvResolver(Vec<SrvRecord>);
+
+    impl SrvResolver for MockSrvResolver {
+        type Error = Infallible;
+
+        async fn srv_lookup(&self, name: SrvName) -> Result<Vec<SrvRecord>, Self::Error> {
+            assert_eq!(name.to_string(), "_http._tcp.example.com");
+            Ok(self.0.clone())
+        }
+    }
+
+    fn record(priority: u16, weight: u16, target: &'static str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight,
+            port: 8080,
+            target: Domain::from_static(target),
+        }
+    }
+
+    #[test]
+    fn test_srv_name() {
+        for (input, expected) in [
+            ("_http._tcp.example.com", Some("_http._tcp.example.com")),
+            (
+                "_xmpp-server._tcp.example.com",
+                Some("_xmpp-server._tcp.example.com"),
+            ),
+            ("_sip._udp.a.example.com", Some("_sip._udp.a.example.com")),
+            ("http._tcp.example.com", None),
+            ("_http.tcp.example.com", None),
+            ("_http._tcp", None),
+            ("__http._tcp.example.com", None),
+            ("_ht tp._tcp.example.com", None),
+            ("_http._tcp.", None),
+        ] {
+            let name: Result<SrvName, _> = input.parse();
+            assert_eq!(
+                name.ok().map(|name| name.to_string()).as_deref(),
+                expected,
+                "input: {input}"
+            );
+        }
+
+        let name = SrvName::new("_http", "tcp", Domain::from_static("example.com")).unwrap();
+        assert_eq!(name.service(), "http");
+        assert_eq!(name.protocol(), "tcp");
+        assert_eq!(name.domain(), "example.com");
+    }
+
+    #[tokio::test]
+    async fn test_lookup_srv_authorities_priority() {
+        let resolver = MockSrvResolver(vec![
+            record(20, 10, "c.example.com"),
+            record(10, 5, "a.example.com"),
+            record(30, 0, "d.example.com"),
+            record(10, 5, "b.example.com"),
+        ]);
+        let name: SrvName = "_http._tcp.example.com".parse().unwrap();
+        for _ in 0..100 {
+            let authorities = lookup_srv_authorities(&resolver, name.clone())
+                .await
+                .unwrap();
+            let hosts: Vec<_> = authorities.iter().map(|a| a.to_string()).collect();
+            assert_eq!(hosts.len(), 4);
+            assert!(hosts[..2].contains(&"a.example.com:8080".to_owned()));
+            assert!(hosts[..2].contains(&"b.example.com:8080".to_owned()));
+            assert_eq!(hosts[2], "c.example.com:8080");
+            assert_eq!(hosts[3], "d.example.com:8080");
+        }
+    }
+
+    #[test]
+    fn test_order_srv_records_weighted() {
+        let records = vec![
+            record(10, 90, "a.example.com"),
+            record(10, 10, "b.example.com"),
+            record(20, 0, "c.example.com"),
+        ];
+        let mut rng = HasherRng::new();
+        let mut a_first = 0;
+        for _ in 0..10_000 {
+            let ordered = order_srv_records(records.clone(), &mut rng);
+            assert_eq!(ordered.len(), 3);
+            assert_eq!(ordered[2].target, "c.example.com");
+            if ordered[0].target == "a.example.com" {
+                a_first += 1;
+            }
+        }
+        assert!((8_500..9_500).contains(&a_first), "a_first = {a_first}");
+    }
+
+    #[test]
+    fn test_order_srv_records_zero_weight() {
+        let records = vec![
+            record(10, 0, "a.example.com"),
+            record(10, 0, "b.example.com"),
+        ];
+        let ordered = order_srv_records(records, &mut HasherRng::new());
+        assert_eq!(ordered.len(), 2);
+        assert!(order_srv_records(vec![], &mut HasherRng::new()).is_empty());
+    }
+}
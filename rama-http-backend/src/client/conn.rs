@@ -1,4 +1,4 @@
-use super::{svc::SendRequest, HttpClientService};
+use super::{svc::SendRequest, upstream::PrefaceRecorder, HttpClientService};
 use rama_core::{
     error::{BoxError, OpaqueError},
     Context, Layer, Service,
@@ -99,55 +99,67 @@ where
             *req.version_mut() = new_version;
         }
 
-        let io = Box::pin(conn);
-
-        match req.version() {
-            Version::HTTP_2 => {
-                trace!(uri = %req.uri(), "create h2 client executor");
-                let executor = ctx.executor().clone();
-                let (sender, conn) =
-                    rama_http_core::client::conn::http2::handshake(executor, io).await?;
-
-                ctx.spawn(async move {
-                    if let Err(err) = conn.await {
-                        tracing::debug!("connection failed: {:?}", err);
-                    }
-                });
-
-                let svc = HttpClientService(SendRequest::Http2(sender));
-
-                Ok(EstablishedClientConnection {
-                    ctx,
-                    req,
-                    conn: svc,
-                    addr,
-                })
-            }
-            Version::HTTP_11 | Version::HTTP_10 | Version::HTTP_09 => {
-                trace!(uri = %req.uri(), "create ~h1 client executor");
-                let (sender, conn) = rama_http_core::client::conn::http1::handshake(io).await?;
-
-                ctx.spawn(async move {
-                    if let Err(err) = conn.await {
-                        tracing::debug!("connection failed: {:?}", err);
-                    }
-                });
-
-                let svc = HttpClientService(SendRequest::Http1(sender));
-
-                Ok(EstablishedClientConnection {
-                    ctx,
-                    req,
-                    conn: svc,
-                    addr,
-                })
-            }
-            version => Err(OpaqueError::from_display(format!(
-                "unsupported Http version: {:?}",
-                version
-            ))
-            .into()),
+        trace!(uri = %req.uri(), "create http client executor");
+        let svc = http_handshake(&ctx, conn, req.version()).await?;
+
+        Ok(EstablishedClientConnection {
+            ctx,
+            req,
+            conn: svc,
+            addr,
+        })
+    }
+}
+
+/// Perform the http client handshake for the given [`Version`]
+/// over an established connection, driving the connection
+/// in the background using the executor of the [`Context`].
+pub(crate) async fn http_handshake<State, Body, IO>(
+    ctx: &Context<State>,
+    conn: IO,
+    version: Version,
+) -> Result<HttpClientService<Body>, BoxError>
+where
+    State: Clone + Send + Sync + 'static,
+    Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Unpin + Send + 'static,
+    IO: Stream + Unpin,
+{
+    let (conn, preface) = PrefaceRecorder::new(conn);
+    let io = Box::pin(conn);
+
+    match version {
+        Version::HTTP_2 => {
+            trace!("create h2 client executor");
+            let executor = ctx.executor().clone();
+            let (sender, conn) =
+                rama_http_core::client::conn::http2::handshake(executor, io).await?;
+
+            ctx.spawn(async move {
+                if let Err(err) = conn.await {
+                    tracing::debug!("connection failed: {:?}", err);
+                }
+            });
+
+            Ok(HttpClientService(SendRequest::Http2(sender), preface))
+        }
+        Version::HTTP_11 | Version::HTTP_10 | Version::HTTP_09 => {
+            trace!("create ~h1 client executor");
+            let (sender, conn) = rama_http_core::client::conn::http1::handshake(io).await?;
+
+            ctx.spawn(async move {
+                // with upgrades, such that upgrade requests can take over the connection
+                if let Err(err) = conn.with_upgrades().await {
+                    tracing::debug!("connection failed: {:?}", err);
+                }
+            });
+
+            Ok(HttpClientService(SendRequest::Http1(sender), preface))
         }
+        version => Err(OpaqueError::from_display(format!(
+            "unsupported Http version: {:?}",
+            version
+        ))
+        .into()),
     }
 }
 
@@ -0,0 +1,389 @@
+//! Downgrade HTTP/2 (and HTTP/3) requests to HTTP/1.1,
+//! such that they can be forwarded to upstreams which only speak HTTP/1.1.
+//!
+//! The pseudo headers of such requests are already part of the [`Request`]:
+//! the method, the uri (`:scheme`, `:authority` and `:path`) and the version.
+//! The [`Http1Downgrade`] middleware maps these to what an HTTP/1.1 request expects:
+//!
+//! - the version is set to HTTP/1.1, also in the [`RequestContext`],
+//!   such that a connection is established for HTTP/1.1;
+//! - the `:authority` is used as the `Host` header (taking precedence over any `Host` header);
+//! - the uri is kept in absolute-form, which the [`HttpClient`] turns into
+//!   the origin-form (e.g. `/foo?bar=baz`) of the request line for direct connections,
+//!   while keeping it for requests sent via an HTTP proxy;
+//! - cookies split over multiple headers (as allowed in h2) are joined into a single header;
+//! - connection-specific headers (e.g. `TE`) are removed;
+//! - trailers are dropped, unless configured to forward them
+//!   (see [`Http1DowngradeLayer::with_forward_trailers`]).
+//!
+//! Requests of other versions are passed through untouched.
+//!
+//! [`HttpClient`]: super::HttpClient
+
+use rama_core::{
+    error::{BoxError, ErrorContext},
+    Context, Layer, Service,
+};
+use rama_http_types::{
+    dep::{http_body, http_body_util::BodyExt},
+    header::{
+        CONNECTION, COOKIE, HOST, KEEP_ALIVE, PROXY_CONNECTION, TE, TRAILER, TRANSFER_ENCODING,
+        UPGRADE,
+    },
+    headers::HeaderMapExt,
+    Body, HeaderMap, HeaderValue, Request, Version,
+};
+use rama_net::http::{RequestContext, RequestContextExt};
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+
+/// Layer that applies [`Http1Downgrade`], which downgrades
+/// HTTP/2 and HTTP/3 requests to HTTP/1.1.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct Http1DowngradeLayer {
+    forward_trailers: bool,
+}
+
+impl Http1DowngradeLayer {
+    /// Create a new [`Http1DowngradeLayer`].
+    pub const fn new() -> Self {
+        Self {
+            forward_trailers: false,
+        }
+    }
+
+    /// Define whether or not the trailers of the request body are forwarded,
+    /// by default they are dropped.
+    ///
+    /// Note that HTTP/1.1 can only send trailers for a chunked body
+    /// and only those declared in the `Trailer` header of the request.
+    pub const fn with_forward_trailers(mut self, forward: bool) -> Self {
+        self.forward_trailers = forward;
+        self
+    }
+
+    /// Define whether or not the trailers of the request body are forwarded,
+    /// by default they are dropped.
+    ///
+    /// Note that HTTP/1.1 can only send trailers for a chunked body
+    /// and only those declared in the `Trailer` header of the request.
+    pub fn set_forward_trailers(&mut self, forward: bool) -> &mut Self {
+        self.forward_trailers = forward;
+        self
+    }
+}
+
+impl<S> Layer<S> for Http1DowngradeLayer {
+    type Service = Http1Downgrade<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Http1Downgrade {
+            inner,
+            forward_trailers: self.forward_trailers,
+        }
+    }
+}
+
+/// Middleware that downgrades HTTP/2 and HTTP/3 requests to HTTP/1.1.
+///
+/// See the [module docs](self) for more details.
+pub struct Http1Downgrade<S> {
+    inner: S,
+    forward_trailers: bool,
+}
+
+impl<S: fmt::Debug> fmt::Debug for Http1Downgrade<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Http1Downgrade")
+            .field("inner", &self.inner)
+            .field("forward_trailers", &self.forward_trailers)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for Http1Downgrade<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            forward_trailers: self.forward_trailers,
+        }
+    }
+}
+
+impl<S> Http1Downgrade<S> {
+    /// Create a new [`Http1Downgrade`], dropping the trailers of the request body.
+    pub const fn new(inner: S) -> Self {
+        Self {
+            inner,
+            forward_trailers: false,
+        }
+    }
+
+    /// Define whether or not the trailers of the request body are forwarded,
+    /// by default they are dropped.
+    ///
+    /// See [`Http1DowngradeLayer::with_forward_trailers`] for more details.
+    pub const fn with_forward_trailers(mut self, forward: bool) -> Self {
+        self.forward_trailers = forward;
+        self
+    }
+
+    /// Define whether or not the trailers of the request body are forwarded,
+    /// by default they are dropped.
+    ///
+    /// See [`Http1DowngradeLayer::with_forward_trailers`] for more details.
+    pub fn set_forward_trailers(&mut self, forward: bool) -> &mut Self {
+        self.forward_trailers = forward;
+        self
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<State, S, ReqBody> Service<State, Request<ReqBody>> for Http1Downgrade<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<Body>, Error: Into<BoxError>>,
+    ReqBody: http_body::Body<Data = bytes::Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let req = match req.version() {
+            Version::HTTP_2 | Version::HTTP_3 => {
+                // the request context is derived while the request is still
+                // the original one, as it defines (among others) the scheme
+                // to be used for the upstream connection
+                let request_ctx = ctx
+                    .get_or_try_insert_request_context(&req)
+                    .context("derive request context of request to downgrade")?;
+                request_ctx.http_version = Version::HTTP_11;
+                let request_ctx = request_ctx.clone();
+                downgrade_request(req, &request_ctx, self.forward_trailers)?
+            }
+            _ => req.map(Body::new),
+        };
+        self.inner.serve(ctx, req).await.map_err(Into::into)
+    }
+}
+
+fn downgrade_request<B>(
+    req: Request<B>,
+    request_ctx: &RequestContext,
+    forward_trailers: bool,
+) -> Result<Request<Body>, BoxError>
+where
+    B: http_body::Body<Data = bytes::Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    let (mut parts, body) = req.into_parts();
+    tracing::trace!(
+        uri = %parts.uri,
+        version = ?parts.version,
+        "downgrade request to http/1.1",
+    );
+    parts.version = Version::HTTP_11;
+
+    // :authority takes precedence over the Host header,
+    // cfr: <https://datatracker.ietf.org/doc/html/rfc9113#section-8.3.1>
+    match parts.uri.authority() {
+        Some(authority) => {
+            parts
+                .headers
+                .typed_insert(rama_http_types::headers::Host::from(authority.clone()));
+        }
+        None if !parts.headers.contains_key(HOST) => {
+            let value = HeaderValue::try_from(request_ctx.authority.to_string())
+                .context("use authority as host")?;
+            parts.headers.insert(HOST, value);
+        }
+        None => (),
+    }
+
+    for header in [
+        &CONNECTION,
+        &KEEP_ALIVE,
+        &PROXY_CONNECTION,
+        &TRANSFER_ENCODING,
+        &UPGRADE,
+        &TE,
+    ] {
+        if let Some(value) = parts.headers.remove(header) {
+            tracing::trace!(
+                ?header,
+                ?value,
+                "removed connection-specific header from downgraded request"
+            );
+        }
+    }
+
+    join_cookies(&mut parts.headers)?;
+
+    let body = if forward_trailers {
+        Body::new(body)
+    } else {
+        parts.headers.remove(TRAILER);
+        // empty data frames are skipped by the http/1.1 encoder
+        Body::new(body.map_frame(|frame| match frame.into_trailers() {
+            Ok(trailers) => {
+                tracing::trace!(?trailers, "drop trailers of downgraded request");
+                http_body::Frame::data(bytes::Bytes::new())
+            }
+            Err(frame) => frame,
+        }))
+    };
+
+    Ok(Request::from_parts(parts, body))
+}
+
+/// Join cookies split over multiple headers into a single header,
+/// cfr: <https://datatracker.ietf.org/doc/html/rfc9113#section-8.2.3>
+fn join_cookies(headers: &mut HeaderMap) -> Result<(), BoxError> {
+    let mut cookies = headers.get_all(COOKIE).iter();
+    if cookies.next().is_none() || cookies.next().is_none() {
+        return Ok(());
+    }
+    let mut joined = Vec::new();
+    for value in headers.get_all(COOKIE) {
+        if !joined.is_empty() {
+            joined.extend_from_slice(b"; ");
+        }
+        joined.extend_from_slice(value.as_bytes());
+    }
+    let value = HeaderValue::from_bytes(&joined).context("join cookie headers")?;
+    headers.insert(COOKIE, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::HttpClient;
+    use rama_core::service::service_fn;
+    use rama_http_types::{BodyExtractExt, Response};
+    use std::convert::Infallible;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Spawn a mock http/1.1 upstream which replies to a single request,
+    /// returning its address and the raw request head it received.
+    async fn spawn_h1_upstream() -> (std::net::SocketAddr, tokio::sync::oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 1024];
+            while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut chunk).await.unwrap();
+                assert_ne!(n, 0, "connection closed before end of request head");
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                .await
+                .unwrap();
+            let _ = tx.send(String::from_utf8(buf).unwrap());
+        });
+        (addr, rx)
+    }
+
+    #[tokio::test]
+    async fn test_downgrade_h2_request_to_h1_upstream() {
+        let (addr, head) = spawn_h1_upstream().await;
+
+        let svc = Http1DowngradeLayer::new().layer(HttpClient::default());
+        let req = Request::builder()
+            .version(Version::HTTP_2)
+            .uri(format!("http://{addr}/foo?bar=baz"))
+            .header(HOST, "ignored.example.com")
+            .header(TE, "trailers")
+            .header(COOKIE, "a=1")
+            .header(COOKIE, "b=2")
+            .body(Body::empty())
+            .unwrap();
+
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.try_into_string().await.unwrap(), "ok");
+
+        let head = head.await.unwrap().to_lowercase();
+        let mut lines = head.split("\r\n");
+        assert_eq!(lines.next(), Some("get /foo?bar=baz http/1.1"));
+        let headers: Vec<_> = lines.filter(|line| !line.is_empty()).collect();
+        assert!(
+            headers.contains(&format!("host: {addr}").as_str()),
+            "{headers:?}"
+        );
+        assert!(headers.contains(&"cookie: a=1; b=2"), "{headers:?}");
+        assert!(!headers.iter().any(|h| h.starts_with("te:")), "{headers:?}");
+        assert_eq!(
+            headers.iter().filter(|h| h.starts_with("host:")).count(),
+            1,
+            "{headers:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_downgrade_trailers() {
+        async fn trailers(req: Request) -> Result<Response<Option<HeaderMap>>, Infallible> {
+            assert_eq!(req.version(), Version::HTTP_11);
+            let collected = req.into_body().collect().await.unwrap();
+            let trailers = collected.trailers().cloned();
+            assert_eq!(collected.to_bytes(), "foo");
+            Ok(Response::new(trailers))
+        }
+
+        fn request() -> Request<Body> {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("x-checksum", HeaderValue::from_static("42"));
+            let body =
+                rama_http_types::dep::http_body_util::Full::new(bytes::Bytes::from_static(b"foo"))
+                    .with_trailers(std::future::ready(Some(Ok::<_, Infallible>(trailers))));
+            Request::builder()
+                .version(Version::HTTP_2)
+                .uri("https://example.com/upload")
+                .header(TRAILER, "x-checksum")
+                .body(Body::new(body))
+                .unwrap()
+        }
+
+        let svc = Http1DowngradeLayer::new().layer(service_fn(trailers));
+        let resp = svc.serve(Context::default(), request()).await.unwrap();
+        assert!(resp.into_body().is_none());
+
+        let svc = Http1DowngradeLayer::new()
+            .with_forward_trailers(true)
+            .layer(service_fn(|ctx: Context<()>, req: Request| async move {
+                let request_ctx = ctx.request_context().unwrap();
+                assert_eq!(request_ctx.http_version, Version::HTTP_11);
+                assert_eq!(request_ctx.authority.to_string(), "example.com:443");
+                assert!(req.headers().contains_key(TRAILER));
+                trailers(req).await
+            }));
+        let resp = svc.serve(Context::default(), request()).await.unwrap();
+        assert_eq!(resp.into_body().unwrap()["x-checksum"], "42");
+
+        // h1 requests are passed through
+        let svc = Http1DowngradeLayer::new().layer(service_fn(|req: Request| async move {
+            assert_eq!(req.headers()[TE], "trailers");
+            Ok::<_, Infallible>(Response::new(()))
+        }));
+        let req = Request::builder()
+            .uri("http://example.com")
+            .header(TE, "trailers")
+            .body(Body::empty())
+            .unwrap();
+        let ctx = Context::default();
+        svc.serve(ctx.clone(), req).await.unwrap();
+        assert!(ctx.request_context().is_none());
+    }
+}
@@ -0,0 +1,180 @@
+//! Load-shedding of requests for which no upstream connection
+//! could be dialed due to file descriptor (fd) pressure.
+//!
+//! Without it such failures surface as generic errors, typically turned into
+//! a `502 Bad Gateway` by a proxy. The [`FdPressureLayer`] turns them into a
+//! `503 Service Unavailable` with a `Retry-After` header instead, signalling
+//! clients that the proxy itself is (temporarily) overloaded.
+//!
+//! See [`FdBudget`] for more information on how dials are shed.
+//!
+//! [`FdBudget`]: rama_tcp::fd_budget::FdBudget
+
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_http_types::{
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    Body, HeaderValue, Request, Response, StatusCode,
+};
+use rama_tcp::fd_budget::FdPressureError;
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+/// A [`Layer`] that produces [`FdPressure`] services.
+pub struct FdPressureLayer;
+
+impl FdPressureLayer {
+    /// Create a new [`FdPressureLayer`].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for FdPressureLayer {
+    type Service = FdPressure<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FdPressure { inner }
+    }
+}
+
+/// Service which turns [`FdPressureError`]s into `503 Service Unavailable`
+/// responses with a `Retry-After` header.
+///
+/// The body of such a response is a json object, e.g.:
+///
+/// ```json
+/// {"error":"fd_pressure","exhausted":false}
+/// ```
+///
+/// See the [module docs](self) for more details.
+pub struct FdPressure<S> {
+    inner: S,
+}
+
+impl<S> FdPressure<S> {
+    /// Create a new [`FdPressure`] service.
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for FdPressure<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FdPressure")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for FdPressure<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S, State, ReqBody> Service<State, Request<ReqBody>> for FdPressure<S>
+where
+    S: Service<State, Request<ReqBody>, Response = Response, Error: Into<BoxError>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        match self.inner.serve(ctx, req).await {
+            Ok(resp) => Ok(resp),
+            Err(err) => {
+                let err = err.into();
+                match FdPressureError::find(err.as_ref()) {
+                    Some(pressure) => {
+                        tracing::debug!(error = %err, "fd pressure: shed request");
+                        Ok(service_unavailable(pressure))
+                    }
+                    None => Err(err),
+                }
+            }
+        }
+    }
+}
+
+fn service_unavailable(err: &FdPressureError) -> Response {
+    let body = format!(
+        r#"{{"error":"fd_pressure","exhausted":{}}}"#,
+        err.is_exhausted()
+    );
+    let retry_after = err.retry_after();
+    // round up, as a retry after 0 seconds would defeat the purpose
+    let retry_after_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+    let mut resp = Response::new(Body::from(body));
+    *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    resp.headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(retry_after_secs.max(1)));
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::HttpClient;
+    use rama_http_types::BodyExtractExt;
+    use rama_tcp::fd_budget::FdBudget;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_fd_pressure_sheds_with_service_unavailable() {
+        let budget = FdBudget::new(1).with_retry_after(Duration::from_millis(2500));
+        let client = FdPressureLayer::new().layer(HttpClient::new().with_fd_budget(budget.clone()));
+
+        // the only socket of the budget is in use
+        let _accepted = budget.track();
+
+        let resp = client
+            .serve(
+                Context::default(),
+                Request::builder()
+                    .uri("http://127.0.0.1:1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers().get(RETRY_AFTER).unwrap(), "3");
+        assert_eq!(
+            resp.into_body().try_into_string().await.unwrap(),
+            r#"{"error":"fd_pressure","exhausted":false}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fd_pressure_passes_other_errors() {
+        let client =
+            FdPressureLayer::new().layer(HttpClient::new().with_fd_budget(FdBudget::new(8)));
+
+        // nothing listens on port 1, so connecting is refused
+        let result = client
+            .serve(
+                Context::default(),
+                Request::builder()
+                    .uri("http://127.0.0.1:1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -1,14 +1,16 @@
 //! Rama HTTP client module,
 //! which provides the [`HttpClient`] type to serve HTTP requests.
 
-use proxy::layer::HttpProxyConnector;
+use proxy::layer::{HttpProxyConnector, HttpProxyTunnel};
 use rama_core::{
-    error::{BoxError, ErrorExt, OpaqueError},
+    error::{BoxError, ErrorContext, ErrorExt, OpaqueError},
     Context, Service,
 };
-use rama_http_types::{dep::http_body, Request, Response};
+use rama_http_types::{
+    dep::http_body, PhaseMark, PhaseTimings, PhaseTimingsBody, Request, Response, StatusCode,
+};
 use rama_net::client::{ConnectorService, EstablishedClientConnection};
-use rama_tcp::client::service::TcpConnector;
+use rama_tcp::{client::service::TcpConnector, fd_budget::FdBudget};
 
 #[cfg(any(feature = "rustls", feature = "boring"))]
 use rama_tls::std::client::{TlsConnector, TlsConnectorData};
@@ -16,24 +18,46 @@ use rama_tls::std::client::{TlsConnector, TlsConnectorData};
 #[cfg(any(feature = "rustls", feature = "boring"))]
 use rama_net::tls::client::ClientConfig;
 
-#[cfg(any(feature = "rustls", feature = "boring"))]
-use rama_core::error::ErrorContext;
-
 mod svc;
 #[doc(inline)]
 pub use svc::HttpClientService;
 
 mod conn;
+#[cfg(any(test, feature = "test-utils"))]
+pub(crate) use conn::http_handshake;
 #[doc(inline)]
 pub use conn::{HttpConnector, HttpConnectorLayer};
+
+pub mod downgrade;
+
+pub mod upstream;
+
+pub mod fd_pressure;
 use tracing::trace;
 
 pub mod proxy;
 
+#[doc(inline)]
+pub use rama_http_core::upgrade::Upgraded;
+
+#[doc(inline)]
+pub use rama_http_core::ext::{on_informational, OnInformational};
+
 #[derive(Debug, Clone, Default)]
 #[non_exhaustive]
 /// An opiniated http client that can be used to serve HTTP requests.
 ///
+/// In case [`PhaseTimings`] are found in the [`Context`], the upstream phases
+/// (connect, first byte and body transfer) are recorded in them.
+///
+/// The (http/1) framing headers of a request are normalized (or the request is rejected)
+/// using [`normalize_request_framing`] prior to sending it upstream, in [`FramingMode::Strict`]
+/// mode by default. Insert [`FramingMode::Lenient`] in the [`Context`] to relax it.
+///
+/// [`FramingMode::Strict`]: rama_http_types::proto::h1::FramingMode::Strict
+/// [`FramingMode::Lenient`]: rama_http_types::proto::h1::FramingMode::Lenient
+/// [`normalize_request_framing`]: rama_http_types::proto::h1::normalize_request_framing
+///
 /// You can fork this http client in case you have use cases not possible with this service example.
 /// E.g. perhaps you wish to have middleware in into outbound requests, after they
 /// passed through your "connector" setup. All this and more is possible by defining your own
@@ -44,6 +68,7 @@ pub struct HttpClient {
     tls_config: Option<ClientConfig>,
     #[cfg(any(feature = "rustls", feature = "boring"))]
     proxy_tls_config: Option<ClientConfig>,
+    fd_budget: Option<FdBudget>,
 }
 
 impl HttpClient {
@@ -93,6 +118,61 @@ impl HttpClient {
         self.proxy_tls_config = cfg;
         self
     }
+
+    /// Set the [`FdBudget`] used to track the connections dialed by this [`HttpClient`],
+    /// shedding new dials once its soft limit is reached.
+    ///
+    /// Use the [`FdPressureLayer`] to turn the resulting errors into `503` responses.
+    ///
+    /// [`FdPressureLayer`]: fd_pressure::FdPressureLayer
+    pub fn set_fd_budget(&mut self, budget: FdBudget) -> &mut Self {
+        self.fd_budget = Some(budget);
+        self
+    }
+
+    /// Replace this [`HttpClient`] with the [`FdBudget`] set,
+    /// used to track the connections it dials and shed new dials once its soft limit is reached.
+    ///
+    /// Use the [`FdPressureLayer`] to turn the resulting errors into `503` responses.
+    ///
+    /// [`FdPressureLayer`]: fd_pressure::FdPressureLayer
+    pub fn with_fd_budget(mut self, budget: FdBudget) -> Self {
+        self.fd_budget = Some(budget);
+        self
+    }
+
+    /// Send an http upgrade request (e.g. with an `Upgrade: custom-proto` header),
+    /// returning the [`Response`] together with the [`Upgraded`] connection
+    /// in case the server agreed to switch protocols (`101 Switching Protocols`).
+    ///
+    /// Any other response is returned as-is, without an upgraded connection.
+    ///
+    /// Upgrades are only possible for http/1.1 connections, so make sure
+    /// to not negotiate h2 (ALPN) in case you use a custom tls config.
+    /// When connecting via an http proxy the upgrade request is always
+    /// sent over a `CONNECT` tunnel, see [`HttpProxyTunnel`].
+    pub async fn upgrade<State, Body>(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<Body>,
+    ) -> Result<(Response, Option<Upgraded>), OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+        Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Unpin + Send + 'static,
+    {
+        ctx.insert(HttpProxyTunnel::new());
+
+        let mut resp = self.serve(ctx, req).await?;
+        if resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+            trace!(status = %resp.status(), "HttpClient: upgrade request not accepted");
+            return Ok((resp, None));
+        }
+
+        let upgraded = rama_http_core::upgrade::on(&mut resp)
+            .await
+            .context("HttpClient: upgrade connection")?;
+        Ok((resp, Some(upgraded)))
+    }
 }
 
 impl<State, Body> Service<State, Request<Body>> for HttpClient
@@ -109,12 +189,16 @@ where
         req: Request<Body>,
     ) -> Result<Self::Response, Self::Error> {
         let uri = req.uri().clone();
+        let timings = ctx.get::<PhaseTimings>().cloned();
 
         // record original req version,
         // so we can put the response back
         let original_req_version = req.version();
 
-        let tcp_connector = TcpConnector::new();
+        let mut tcp_connector = TcpConnector::new();
+        if let Some(fd_budget) = self.fd_budget.as_ref() {
+            tcp_connector.set_fd_budget(fd_budget.clone());
+        }
 
         #[cfg(any(feature = "rustls", feature = "boring"))]
         let connector = {
@@ -164,10 +248,16 @@ where
         // so that the other end can read it... This might however give issues in
         // case switching http versions requires more work than version. If so,
         // your first place will be to check here and/or in the [`HttpConnector`].
+        if let Some(timings) = timings.as_ref() {
+            timings.record(PhaseMark::UpstreamConnectStart);
+        }
         let EstablishedClientConnection { ctx, req, conn, .. } = connector
             .connect(ctx, req)
             .await
             .map_err(|err| OpaqueError::from_boxed(err).with_context(|| uri.to_string()))?;
+        if let Some(timings) = timings.as_ref() {
+            timings.record(PhaseMark::UpstreamConnected);
+        }
 
         trace!(uri = %uri, "send http req to connector stack");
         let mut resp = conn.serve(ctx, req).await.map_err(|err| {
@@ -176,6 +266,17 @@ where
         })?;
         trace!(uri = %uri, "response received from connector stack");
 
+        if let Some(timings) = timings {
+            timings.record(PhaseMark::UpstreamFirstByte);
+            resp = resp.map(|body| {
+                rama_http_types::Body::new(PhaseTimingsBody::new(
+                    body,
+                    timings,
+                    PhaseMark::UpstreamBodyEnd,
+                ))
+            });
+        }
+
         trace!(
             "incoming response version {:?}, normalizing to {:?}",
             resp.version(),
@@ -188,3 +289,94 @@ where
         Ok(resp)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::HttpServer;
+    use rama_core::service::service_fn;
+    use rama_http_types::{
+        header::{CONNECTION, UPGRADE},
+        Body, BodyExtractExt,
+    };
+    use std::convert::Infallible;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn custom_proto_server(mut req: Request) -> Result<Response, Infallible> {
+        if req
+            .headers()
+            .get(UPGRADE)
+            .is_none_or(|v| v != "custom-proto")
+        {
+            return Ok(Response::new(Body::from("no upgrade")));
+        }
+
+        let on_upgrade = rama_http_core::upgrade::on(&mut req);
+        tokio::spawn(async move {
+            let mut upgraded = on_upgrade.await.unwrap();
+            let mut buf = [0u8; 4];
+            upgraded.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ping");
+            upgraded.write_all(b"pong").await.unwrap();
+        });
+
+        Ok(Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "custom-proto")
+            .body(Body::empty())
+            .unwrap())
+    }
+
+    async fn spawn_custom_proto_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            HttpServer::http1()
+                .service(service_fn(custom_proto_server))
+                .serve(Context::default(), stream)
+                .await
+                .unwrap();
+        });
+        format!("http://{addr}/")
+    }
+
+    #[tokio::test]
+    async fn test_http_client_upgrade() {
+        let uri = spawn_custom_proto_server().await;
+
+        let req = Request::builder()
+            .uri(uri)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "custom-proto")
+            .body(Body::empty())
+            .unwrap();
+        let (resp, upgraded) = HttpClient::new()
+            .upgrade(Context::default(), req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(resp.headers()[UPGRADE], "custom-proto");
+
+        let mut upgraded = upgraded.unwrap();
+        upgraded.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        upgraded.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[tokio::test]
+    async fn test_http_client_upgrade_not_accepted() {
+        let uri = spawn_custom_proto_server().await;
+
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let (resp, upgraded) = HttpClient::new()
+            .upgrade(Context::default(), req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(upgraded.is_none());
+        assert_eq!(resp.try_into_string().await.unwrap(), "no upgrade");
+    }
+}
@@ -3,7 +3,7 @@ use rama_http_types::{
     headers::{HeaderMapExt, ProxyAuthorization},
     Request,
 };
-use rama_net::{address::ProxyAddress, http::RequestContext, user::ProxyCredential};
+use rama_net::{address::ProxyAddress, http::RequestContextExt, user::ProxyCredential};
 use std::{fmt, future::Future};
 
 #[derive(Debug, Clone, Default)]
@@ -70,9 +70,7 @@ where
         mut ctx: Context<State>,
         mut req: Request<Body>,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
-        let maybe_request_ctx = ctx
-            .get_or_try_insert_with_ctx::<RequestContext, _>(|ctx| (ctx, &req).try_into())
-            .ok();
+        let maybe_request_ctx = ctx.get_or_try_insert_request_context(&req).ok();
         if !maybe_request_ctx
             .map(|ctx| ctx.protocol.is_secure())
             .unwrap_or_default()
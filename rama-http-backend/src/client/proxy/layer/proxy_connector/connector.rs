@@ -5,9 +5,12 @@
 use rama_core::error::{ErrorContext, OpaqueError};
 use rama_http_core::{client::conn::http1, upgrade};
 use rama_http_types::{
-    header::{HOST, USER_AGENT},
+    header::{
+        CONNECTION, CONTENT_LENGTH, HOST, KEEP_ALIVE, PROXY_CONNECTION, TE, TRAILER,
+        TRANSFER_ENCODING, UPGRADE, USER_AGENT,
+    },
     headers::{Header, HeaderMapExt},
-    Body, HeaderName, HeaderValue, Method, Request, StatusCode, Version,
+    Body, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode, Version,
 };
 use rama_net::{address::Authority, stream::Stream};
 
@@ -53,6 +56,27 @@ impl InnerHttpProxyConnector {
         self
     }
 
+    /// Add the given headers to the request, replacing the default headers of the same name.
+    ///
+    /// Hop-by-hop headers and headers managed by the connector itself
+    /// (e.g. `Host`) are not forwarded.
+    pub(super) fn with_headers(&mut self, headers: &HeaderMap) -> &mut Self {
+        for name in headers.keys() {
+            if is_managed_header(name) {
+                tracing::debug!(
+                    header = %name,
+                    "http proxy connector: skip hop-by-hop or managed CONNECT header",
+                );
+                continue;
+            }
+            self.req.headers_mut().remove(name);
+            for value in headers.get_all(name) {
+                self.req.headers_mut().append(name, value.clone());
+            }
+        }
+        self
+    }
+
     /// Add a typed header to the request.
     pub(super) fn with_typed_header(&mut self, header: impl Header) -> &mut Self {
         self.req.headers_mut().typed_insert(header);
@@ -86,10 +110,32 @@ impl InnerHttpProxyConnector {
                 .await
                 .map_err(|err| HttpProxyError::Transport(OpaqueError::from_std(err).into_boxed())),
             StatusCode::PROXY_AUTHENTICATION_REQUIRED => Err(HttpProxyError::AuthRequired),
+            StatusCode::FORBIDDEN => Err(HttpProxyError::Forbidden),
+            StatusCode::BAD_GATEWAY => Err(HttpProxyError::BadGateway),
             StatusCode::SERVICE_UNAVAILABLE => Err(HttpProxyError::Unavailable),
-            status => Err(HttpProxyError::Other(format!(
-                "invalid http proxy conn handshake: status={status}",
-            ))),
+            StatusCode::GATEWAY_TIMEOUT => Err(HttpProxyError::GatewayTimeout),
+            status => Err(HttpProxyError::Other {
+                status,
+                raw: format!("{:?} {status}", response.version()),
+                headers: response.into_parts().0.headers,
+            }),
         }
     }
 }
+
+/// Returns `true` for hop-by-hop headers, which only concern a single connection,
+/// and headers managed by the [`InnerHttpProxyConnector`] itself.
+fn is_managed_header(name: &HeaderName) -> bool {
+    [
+        &CONNECTION,
+        &KEEP_ALIVE,
+        &PROXY_CONNECTION,
+        &TE,
+        &TRAILER,
+        &TRANSFER_ENCODING,
+        &UPGRADE,
+        &HOST,
+        &CONTENT_LENGTH,
+    ]
+    .contains(&name)
+}
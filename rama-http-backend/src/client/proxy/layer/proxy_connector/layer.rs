@@ -1,5 +1,8 @@
 use super::HttpProxyConnector;
 use rama_core::Layer;
+use rama_http_types::{HeaderMap, HeaderName, HeaderValue};
+use rama_net::address::NoProxy;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Default)]
 /// A [`Layer`] which wraps the given service with a [`HttpProxyConnector`].
@@ -7,6 +10,8 @@ use rama_core::Layer;
 /// See [`HttpProxyConnector`] for more information.
 pub struct HttpProxyConnectorLayer {
     required: bool,
+    no_proxy: Option<Arc<NoProxy>>,
+    connect_headers: Option<Arc<HeaderMap>>,
 }
 
 impl HttpProxyConnectorLayer {
@@ -17,7 +22,11 @@ impl HttpProxyConnectorLayer {
     /// [`Context`]: rama_core::Context
     /// [`ProxyAddress`]: rama_net::address::ProxyAddress
     pub fn optional() -> Self {
-        Self { required: false }
+        Self {
+            required: false,
+            no_proxy: None,
+            connect_headers: None,
+        }
     }
 
     /// Create a new [`HttpProxyConnectorLayer`] which creates a [`HttpProxyConnector`]
@@ -27,7 +36,67 @@ impl HttpProxyConnectorLayer {
     /// [`Context`]: rama_core::Context
     /// [`ProxyAddress`]: rama_net::address::ProxyAddress
     pub fn required() -> Self {
-        Self { required: true }
+        Self {
+            required: true,
+            no_proxy: None,
+            connect_headers: None,
+        }
+    }
+
+    /// Connect directly to the destinations matched by the given [`NoProxy`],
+    /// even if a [`ProxyAddress`] is found in the [`Context`] or a proxy is required.
+    ///
+    /// [`Context`]: rama_core::Context
+    /// [`ProxyAddress`]: rama_net::address::ProxyAddress
+    pub fn with_no_proxy(mut self, no_proxy: NoProxy) -> Self {
+        self.no_proxy = Some(Arc::new(no_proxy));
+        self
+    }
+
+    /// Connect directly to the destinations matched by the given [`NoProxy`],
+    /// even if a [`ProxyAddress`] is found in the [`Context`] or a proxy is required.
+    ///
+    /// [`Context`]: rama_core::Context
+    /// [`ProxyAddress`]: rama_net::address::ProxyAddress
+    pub fn set_no_proxy(&mut self, no_proxy: NoProxy) -> &mut Self {
+        self.no_proxy = Some(Arc::new(no_proxy));
+        self
+    }
+
+    /// Add a header to send as part of the `CONNECT` request,
+    /// replacing a default header of the same name (e.g. `User-Agent`).
+    ///
+    /// Hop-by-hop headers (e.g. `Connection`) and the `Host` header are never sent.
+    pub fn with_connect_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.set_connect_header(name, value);
+        self
+    }
+
+    /// Add a header to send as part of the `CONNECT` request,
+    /// replacing a default header of the same name (e.g. `User-Agent`).
+    ///
+    /// Hop-by-hop headers (e.g. `Connection`) and the `Host` header are never sent.
+    pub fn set_connect_header(&mut self, name: HeaderName, value: HeaderValue) -> &mut Self {
+        Arc::make_mut(self.connect_headers.get_or_insert_default()).append(name, value);
+        self
+    }
+
+    /// Set the headers to send as part of the `CONNECT` request,
+    /// replacing any headers added before.
+    ///
+    /// Hop-by-hop headers (e.g. `Connection`) and the `Host` header are never sent.
+    pub fn with_connect_headers(mut self, headers: HeaderMap) -> Self {
+        self.connect_headers = Some(Arc::new(headers));
+        self
+    }
+
+    /// Set the headers to send as part of the `CONNECT` request,
+    /// replacing any headers added before.
+    ///
+    /// Hop-by-hop headers (e.g. `Connection`) and the `Host` header are never sent.
+    pub fn set_connect_headers(&mut self, headers: HeaderMap) -> &mut Self {
+        self.connect_headers = Some(Arc::new(headers));
+        self
     }
 }
 
@@ -35,6 +104,11 @@ impl<S> Layer<S> for HttpProxyConnectorLayer {
     type Service = HttpProxyConnector<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        HttpProxyConnector::new(inner, self.required)
+        HttpProxyConnector::new(
+            inner,
+            self.required,
+            self.no_proxy.clone(),
+            self.connect_headers.clone(),
+        )
     }
 }
@@ -12,4 +12,4 @@ pub use layer::HttpProxyConnectorLayer;
 
 mod service;
 #[doc(inline)]
-pub use service::HttpProxyConnector;
+pub use service::{HttpProxyConnector, HttpProxyTunnel};
@@ -1,6 +1,8 @@
 use std::fmt;
 
 use rama_core::error::BoxError;
+use rama_core::layer::timeout::Elapsed;
+use rama_http_types::{HeaderMap, StatusCode};
 
 #[derive(Debug)]
 /// error that can be returned in case a http proxy
@@ -10,18 +12,38 @@ pub enum HttpProxyError {
     ///
     /// (Proxy returned HTTP 407)
     AuthRequired,
+    /// Proxy refused to establish the connection
+    ///
+    /// (Proxy returned HTTP 403)
+    Forbidden,
+    /// Proxy received an invalid response from the upstream server
+    ///
+    /// (Proxy returned HTTP 502)
+    BadGateway,
     /// Proxy is Unavailable
     ///
     /// (Proxy returned HTTP 503)
     Unavailable,
+    /// Proxy did not receive a timely response from the upstream server
+    ///
+    /// (Proxy returned HTTP 504)
+    GatewayTimeout,
     /// I/O error happened as part of HTTP Proxy Connection Establishment
     ///
     /// (e.g. some kind of TCP error)
     Transport(BoxError),
-    /// Something went wrong, but classification did not happen.
+    /// Proxy responded with a status which is not classified.
     ///
-    /// (First header line of http response is included in error)
-    Other(String),
+    /// The status and headers of the http response are included,
+    /// e.g. to react to a `Proxy-Authenticate` challenge or `Retry-After` delay.
+    Other {
+        /// The status code of the proxy response.
+        status: StatusCode,
+        /// The headers of the proxy response.
+        headers: HeaderMap,
+        /// The status line of the proxy response, for debugging purposes.
+        raw: String,
+    },
 }
 
 impl fmt::Display for HttpProxyError {
@@ -30,17 +52,101 @@ impl fmt::Display for HttpProxyError {
             HttpProxyError::AuthRequired => {
                 write!(f, "http proxy error: proxy auth required (http 407)")
             }
+            HttpProxyError::Forbidden => {
+                write!(f, "http proxy error: proxy refused connection (http 403)")
+            }
+            HttpProxyError::BadGateway => {
+                write!(
+                    f,
+                    "http proxy error: bad gateway, invalid response from upstream (http 502)"
+                )
+            }
             HttpProxyError::Unavailable => {
                 write!(f, "http proxy error: proxy unavailable (http 503)")
             }
+            HttpProxyError::GatewayTimeout => {
+                write!(
+                    f,
+                    "http proxy error: gateway timeout, no timely response from upstream (http 504)"
+                )
+            }
             HttpProxyError::Transport(error) => {
                 write!(f, "http proxy error: transport error: I/O [{}]", error)
             }
-            HttpProxyError::Other(header) => {
-                write!(f, "http proxy error: first line of header = [{}]", header)
+            HttpProxyError::Other { raw, .. } => {
+                write!(f, "http proxy error: unexpected response = [{}]", raw)
+            }
+        }
+    }
+}
+
+impl HttpProxyError {
+    /// Returns the status code of the proxy response which caused this error,
+    /// `None` in case of a transport error.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            HttpProxyError::AuthRequired => Some(StatusCode::PROXY_AUTHENTICATION_REQUIRED),
+            HttpProxyError::Forbidden => Some(StatusCode::FORBIDDEN),
+            HttpProxyError::BadGateway => Some(StatusCode::BAD_GATEWAY),
+            HttpProxyError::Unavailable => Some(StatusCode::SERVICE_UNAVAILABLE),
+            HttpProxyError::GatewayTimeout => Some(StatusCode::GATEWAY_TIMEOUT),
+            HttpProxyError::Transport(_) => None,
+            HttpProxyError::Other { status, .. } => Some(*status),
+        }
+    }
+
+    /// Returns `true` if the connection establishment is worth retrying.
+    ///
+    /// This is the case for a proxy which is unavailable or whose upstream failed
+    /// (http 502, 503 and 504), for an unclassified request timeout or rate limit
+    /// (http 408 and 429), and for transport errors caused by a timeout
+    /// or the connection being reset or aborted.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            HttpProxyError::AuthRequired | HttpProxyError::Forbidden => false,
+            HttpProxyError::BadGateway
+            | HttpProxyError::Unavailable
+            | HttpProxyError::GatewayTimeout => true,
+            HttpProxyError::Transport(err) => is_retryable_transport_error(err.as_ref()),
+            HttpProxyError::Other { status, .. } => matches!(
+                *status,
+                StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS
+            ),
+        }
+    }
+
+    /// Returns `true` if the proxy requires (other) authentication
+    /// to establish the connection (http 407).
+    pub fn is_auth_error(&self) -> bool {
+        matches!(self, HttpProxyError::AuthRequired)
+            || matches!(
+                self,
+                HttpProxyError::Other { status, .. } if *status == StatusCode::PROXY_AUTHENTICATION_REQUIRED
+            )
+    }
+}
+
+/// Walk the source chain of a transport error for a timeout,
+/// or an I/O error caused by the connection being reset or aborted.
+fn is_retryable_transport_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if err.is::<Elapsed>() || err.is::<tokio::time::error::Elapsed>() {
+            return true;
+        }
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+            if matches!(
+                err.kind(),
+                std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+            ) {
+                return true;
             }
         }
+        next = err.source();
     }
+    false
 }
 
 impl From<std::io::Error> for HttpProxyError {
@@ -53,7 +159,10 @@ impl std::error::Error for HttpProxyError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             HttpProxyError::AuthRequired => None,
+            HttpProxyError::Forbidden => None,
+            HttpProxyError::BadGateway => None,
             HttpProxyError::Unavailable => None,
+            HttpProxyError::GatewayTimeout => None,
             HttpProxyError::Transport(err) => {
                 // filter out generic io errors,
                 // but do allow custom errors (e.g. because IP is blocked)
@@ -64,7 +173,67 @@ impl std::error::Error for HttpProxyError {
                     Some(err_ref)
                 }
             }
-            HttpProxyError::Other(_) => None,
+            HttpProxyError::Other { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::error::{ErrorContext, OpaqueError};
+    use rama_http_types::HeaderMap;
+    use std::io;
+
+    fn other(status: StatusCode) -> HttpProxyError {
+        HttpProxyError::Other {
+            status,
+            headers: HeaderMap::new(),
+            raw: format!("HTTP/1.1 {status}"),
+        }
+    }
+
+    fn transport(kind: io::ErrorKind) -> HttpProxyError {
+        HttpProxyError::Transport(
+            Err::<(), _>(io::Error::from(kind))
+                .context("connect to proxy")
+                .unwrap_err()
+                .into(),
+        )
+    }
+
+    #[test]
+    fn test_http_proxy_error_classification() {
+        for (err, retryable, auth) in [
+            (HttpProxyError::AuthRequired, false, true),
+            (HttpProxyError::Forbidden, false, false),
+            (HttpProxyError::BadGateway, true, false),
+            (HttpProxyError::Unavailable, true, false),
+            (HttpProxyError::GatewayTimeout, true, false),
+            (transport(io::ErrorKind::TimedOut), true, false),
+            (transport(io::ErrorKind::ConnectionReset), true, false),
+            (transport(io::ErrorKind::ConnectionAborted), true, false),
+            (transport(io::ErrorKind::ConnectionRefused), false, false),
+            (
+                HttpProxyError::Transport(Elapsed::default().into()),
+                true,
+                false,
+            ),
+            (
+                HttpProxyError::Transport(OpaqueError::from_display("blocked").into()),
+                false,
+                false,
+            ),
+            (other(StatusCode::TOO_MANY_REQUESTS), true, false),
+            (
+                other(StatusCode::PROXY_AUTHENTICATION_REQUIRED),
+                false,
+                true,
+            ),
+            (other(StatusCode::IM_A_TEAPOT), false, false),
+        ] {
+            assert_eq!(err.is_retryable(), retryable, "{err}");
+            assert_eq!(err.is_auth_error(), auth, "{err}");
         }
     }
 }
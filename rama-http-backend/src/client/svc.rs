@@ -2,14 +2,18 @@ use rama_core::{
     error::{BoxError, ErrorContext, OpaqueError},
     Context, Service,
 };
+use rama_http_core::ext::OnInformational;
 use rama_http_types::{
     dep::{http::uri::PathAndQuery, http_body},
-    header::{CONNECTION, HOST, KEEP_ALIVE, PROXY_CONNECTION, TRANSFER_ENCODING, UPGRADE},
+    header::{CONNECTION, HOST, KEEP_ALIVE, PROXY_CONNECTION, TE, TRANSFER_ENCODING, UPGRADE},
     headers::HeaderMapExt,
-    Method, Request, Response, Version,
+    proto::h1::{normalize_request_framing, FramingMode},
+    HeaderMap, HeaderValue, Method, Request, Response, Version,
 };
 use rama_net::{address::ProxyAddress, http::RequestContext};
 
+use super::upstream::{ResponsePreface, UpstreamProtocolError};
+
 #[derive(Debug)]
 pub(super) enum SendRequest<Body> {
     Http1(rama_http_core::client::conn::http1::SendRequest<Body>),
@@ -18,7 +22,10 @@ pub(super) enum SendRequest<Body> {
 
 #[derive(Debug)]
 /// Internal http sender used to send the actual requests.
-pub struct HttpClientService<Body>(pub(super) SendRequest<Body>);
+///
+/// Informational (`1xx`) responses received over HTTP/1 are passed to the
+/// [`OnInformational`] callback found in the request extensions or [`Context`], if any.
+pub struct HttpClientService<Body>(pub(super) SendRequest<Body>, pub(super) ResponsePreface);
 
 impl<State, Body> Service<State, Request<Body>> for HttpClientService<Body>
 where
@@ -41,12 +48,28 @@ where
         //
         // TODO: fix this in hyper fork (embedded in rama http core)
         // directly instead of here...
-        let req = sanitize_client_req_header(&mut ctx, req)?;
+        let mut req = sanitize_client_req_header(&mut ctx, req)?;
 
+        // informational responses can be processed using
+        // a callback found in the request extensions or the context
+        if let Some(on_informational) = ctx.get::<OnInformational>() {
+            if req.extensions().get::<OnInformational>().is_none() {
+                req.extensions_mut().insert(on_informational.clone());
+            }
+        }
+
+        let version = req.version();
         let resp = match &self.0 {
             SendRequest::Http1(sender) => sender.send_request(req).await,
             SendRequest::Http2(sender) => sender.send_request(req).await,
-        }?;
+        }
+        .map_err(|err| {
+            let err: BoxError = err.into();
+            match UpstreamProtocolError::classify(&self.1, version, &err) {
+                Some(err) => err.into(),
+                None => err,
+            }
+        })?;
 
         Ok(resp.map(rama_http_types::Body::new))
     }
@@ -54,13 +77,19 @@ where
 
 fn sanitize_client_req_header<S, B>(
     ctx: &mut Context<S>,
-    req: Request<B>,
+    mut req: Request<B>,
 ) -> Result<Request<B>, BoxError> {
     // logic specific to this method
     if req.method() == Method::CONNECT && req.uri().host().is_none() {
         return Err(OpaqueError::from_display("missing host in CONNECT request").into());
     }
 
+    // never forward ambiguous (h1) framing headers,
+    // as these are the root cause of request smuggling
+    let framing_mode = ctx.get::<FramingMode>().copied().unwrap_or_default();
+    normalize_request_framing(&mut req, framing_mode)
+        .context("normalize http/1 request framing")?;
+
     // logic specific to http versions
     Ok(match req.version() {
         Version::HTTP_09 | Version::HTTP_10 | Version::HTTP_11 => {
@@ -176,6 +205,10 @@ fn sanitize_client_req_header<S, B>(
                 }
             }
 
+            // TE is only allowed to contain "trailers" in h2
+            // cfr: <https://datatracker.ietf.org/doc/html/rfc7540#section-8.1.2.2>
+            sanitize_h2_te_header(req.headers_mut());
+
             req
         }
         Version::HTTP_3 => {
@@ -195,3 +228,147 @@ fn sanitize_client_req_header<S, B>(
         }
     })
 }
+
+fn sanitize_h2_te_header(headers: &mut HeaderMap) {
+    if !headers.contains_key(TE) {
+        return;
+    }
+
+    let trailers = headers.get_all(TE).iter().any(|value| {
+        value.to_str().is_ok_and(|value| {
+            value.split(',').any(|coding| {
+                coding
+                    .split(';')
+                    .next()
+                    .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("trailers"))
+            })
+        })
+    });
+
+    let header = headers.remove(TE);
+    if trailers {
+        headers.insert(TE, HeaderValue::from_static("trailers"));
+    } else {
+        tracing::trace!(?header, "removed disallowed TE header from h2 request");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_http_types::header::CONTENT_LENGTH;
+
+    fn sanitize_h2_request(te: &[&'static str]) -> Request<()> {
+        let mut builder = Request::builder()
+            .version(Version::HTTP_2)
+            .uri("https://example.com/");
+        for value in te {
+            builder = builder.header(TE, *value);
+        }
+        let mut ctx = Context::default();
+        sanitize_client_req_header(&mut ctx, builder.body(()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_h2_te_gzip_stripped() {
+        let req = sanitize_h2_request(&["gzip"]);
+        assert!(!req.headers().contains_key(TE));
+    }
+
+    #[test]
+    fn test_h2_te_trailers_preserved() {
+        let req = sanitize_h2_request(&["trailers"]);
+        assert_eq!(req.headers()[TE], "trailers");
+
+        let req = sanitize_h2_request(&["gzip, Trailers"]);
+        let values: Vec<_> = req.headers().get_all(TE).iter().collect();
+        assert_eq!(values, ["trailers"]);
+
+        let req = sanitize_h2_request(&["gzip;q=0.5", "trailers"]);
+        let values: Vec<_> = req.headers().get_all(TE).iter().collect();
+        assert_eq!(values, ["trailers"]);
+    }
+
+    #[test]
+    fn test_h1_te_untouched() {
+        let req = Request::builder()
+            .version(Version::HTTP_11)
+            .uri("/")
+            .header(HOST, "example.com")
+            .header(TE, "gzip")
+            .body(())
+            .unwrap();
+        let req = sanitize_client_req_header(&mut Context::<()>::default(), req).unwrap();
+        assert_eq!(req.headers()[TE], "gzip");
+    }
+
+    #[test]
+    fn test_h1_framing_normalized_by_default() {
+        fn request() -> Request<()> {
+            Request::builder()
+                .version(Version::HTTP_11)
+                .uri("/")
+                .header(HOST, "example.com")
+                .header(TRANSFER_ENCODING, "gzip")
+                .header(TRANSFER_ENCODING, "chunked")
+                .header(CONTENT_LENGTH, "3")
+                .body(())
+                .unwrap()
+        }
+
+        assert!(sanitize_client_req_header(&mut Context::<()>::default(), request()).is_err());
+
+        let mut ctx = Context::<()>::default();
+        ctx.insert(FramingMode::Lenient);
+        let req = sanitize_client_req_header(&mut ctx, request()).unwrap();
+        let values: Vec<_> = req.headers().get_all(TRANSFER_ENCODING).iter().collect();
+        assert_eq!(values, ["gzip, chunked"]);
+        assert!(!req.headers().contains_key(CONTENT_LENGTH));
+    }
+
+    #[tokio::test]
+    async fn test_h1_early_hints_surfaced() {
+        use crate::client::HttpClient;
+        use rama_http_types::{Body, BodyExtractExt};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 1024];
+            while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut chunk).await.unwrap();
+                assert_ne!(n, 0, "connection closed before end of request head");
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload; as=style\r\n\r\n\
+                    HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok",
+                )
+                .await
+                .unwrap();
+        });
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut ctx = Context::default();
+        ctx.insert(OnInformational::new(move |res| {
+            let link = res.headers()["link"].to_str().unwrap();
+            tx.send(format!("{} {link}", res.status().as_u16()))
+                .unwrap();
+        }));
+
+        let req = Request::builder()
+            .uri(format!("http://{addr}/"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = HttpClient::default().serve(ctx, req).await.unwrap();
+
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.try_into_string().await.unwrap(), "ok");
+        let hints: Vec<_> = rx.try_iter().collect();
+        assert_eq!(hints, ["103 </style.css>; rel=preload; as=style"]);
+    }
+}
@@ -0,0 +1,640 @@
+//! Validation of the responses received from an upstream,
+//! such that misconfigured upstreams (e.g. a plaintext http client pointed
+//! at an https port) are detected early and reported clearly.
+//!
+//! The [`HttpClient`] classifies immediate protocol failures,
+//! which occur before any response headers were received,
+//! as an [`UpstreamProtocolError`]. The [`UpstreamValidationLayer`] turns these
+//! into `502 Bad Gateway` responses with a stable machine-readable body and
+//! opens a circuit per upstream once the same failure keeps repeating.
+//!
+//! [`HttpClient`]: super::HttpClient
+
+use parking_lot::Mutex;
+use pin_project_lite::pin_project;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_http_types::{
+    header::CONTENT_TYPE, Body, HeaderValue, Request, Response, StatusCode, Version,
+};
+use rama_net::{address::Authority, http::RequestContext};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    collections::HashMap,
+    fmt, io,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Instant,
+};
+
+/// Amount of bytes recorded from the start of an upstream response.
+const PREFACE_LEN: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The kind of [`UpstreamProtocolError`].
+pub enum UpstreamProtocolErrorKind {
+    /// The upstream replied with a TLS record to a plaintext request.
+    TlsOnPlaintext,
+    /// The upstream replied with bytes which are not a valid
+    /// http response preface for the http version used.
+    InvalidPreface,
+    /// The upstream closed or reset the connection
+    /// before any response bytes were received.
+    ResetBeforeHeaders,
+}
+
+impl UpstreamProtocolErrorKind {
+    /// Stable machine-readable code of this kind,
+    /// used in the bodies of the responses created for it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::TlsOnPlaintext => "upstream_tls_on_plaintext",
+            Self::InvalidPreface => "upstream_invalid_preface",
+            Self::ResetBeforeHeaders => "upstream_reset_before_headers",
+        }
+    }
+
+    /// Remediation hint for the operator of the proxy.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::TlsOnPlaintext => {
+                "upstream speaks TLS: use the https scheme (and a tls enabled client) for this upstream"
+            }
+            Self::InvalidPreface => {
+                "upstream does not speak the expected http version: verify the upstream port and protocol"
+            }
+            Self::ResetBeforeHeaders => {
+                "upstream closed the connection before responding: verify it expects this protocol (e.g. tls or plaintext) and is healthy"
+            }
+        }
+    }
+}
+
+impl fmt::Display for UpstreamProtocolErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+#[derive(Debug)]
+/// An immediate protocol failure of an upstream,
+/// which occurred before any response headers were received.
+///
+/// It can be found in the error chain of the [`HttpClient`] using
+/// [`UpstreamProtocolError::find`].
+///
+/// [`HttpClient`]: super::HttpClient
+pub struct UpstreamProtocolError {
+    kind: UpstreamProtocolErrorKind,
+    detail: String,
+}
+
+impl UpstreamProtocolError {
+    /// The [`UpstreamProtocolErrorKind`] of this error.
+    pub fn kind(&self) -> UpstreamProtocolErrorKind {
+        self.kind
+    }
+
+    /// Find the first [`UpstreamProtocolError`] in the chain of the given error.
+    pub fn find<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a Self> {
+        let mut next = Some(err);
+        while let Some(err) = next {
+            if let Some(err) = err.downcast_ref::<Self>() {
+                return Some(err);
+            }
+            next = err.source();
+        }
+        None
+    }
+
+    /// Classify the error which occurred while waiting for the response headers,
+    /// using the preface of the response received so far (if any).
+    pub(super) fn classify(
+        preface: &ResponsePreface,
+        version: Version,
+        err: &BoxError,
+    ) -> Option<Self> {
+        let kind = match preface.get() {
+            Some(preface) if is_tls_record(preface) => UpstreamProtocolErrorKind::TlsOnPlaintext,
+            Some(preface) if !is_http_preface(version, preface) => {
+                UpstreamProtocolErrorKind::InvalidPreface
+            }
+            None if is_reset_or_closed(err.as_ref()) => {
+                UpstreamProtocolErrorKind::ResetBeforeHeaders
+            }
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            detail: err.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for UpstreamProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "upstream protocol error ({}): {} ({})",
+            self.kind,
+            self.detail,
+            self.kind.hint()
+        )
+    }
+}
+
+impl std::error::Error for UpstreamProtocolError {}
+
+fn is_tls_record(preface: &[u8]) -> bool {
+    // content type (change_cipher_spec, alert, handshake or application_data),
+    // followed by the major version of the record layer
+    matches!(preface, [0x14..=0x17] | [0x14..=0x17, 0x03, ..])
+}
+
+fn is_http_preface(version: Version, preface: &[u8]) -> bool {
+    match version {
+        // a server connection preface starts with a SETTINGS frame
+        Version::HTTP_2 => preface.get(3).is_none_or(|frame_type| *frame_type == 0x04),
+        _ => {
+            let n = preface.len().min(PREFACE_LEN);
+            b"HTTP/"[..n].eq_ignore_ascii_case(&preface[..n])
+        }
+    }
+}
+
+fn is_reset_or_closed(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if err
+            .downcast_ref::<rama_http_core::Error>()
+            .is_some_and(|err| err.is_incomplete_message())
+        {
+            return true;
+        }
+        if err.downcast_ref::<io::Error>().is_some_and(|err| {
+            matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+            )
+        }) {
+            return true;
+        }
+        next = err.source();
+    }
+    false
+}
+
+#[derive(Debug, Clone, Default)]
+/// The first bytes received from an upstream connection.
+pub(super) struct ResponsePreface(Arc<OnceLock<Box<[u8]>>>);
+
+impl ResponsePreface {
+    fn get(&self) -> Option<&[u8]> {
+        self.0.get().map(AsRef::as_ref)
+    }
+}
+
+pin_project! {
+    /// Stream which records the [`ResponsePreface`] of the inner stream.
+    pub(super) struct PrefaceRecorder<S> {
+        #[pin]
+        inner: S,
+        preface: ResponsePreface,
+    }
+}
+
+impl<S> PrefaceRecorder<S> {
+    pub(super) fn new(inner: S) -> (Self, ResponsePreface) {
+        let preface = ResponsePreface::default();
+        (
+            Self {
+                inner,
+                preface: preface.clone(),
+            },
+            preface,
+        )
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for PrefaceRecorder<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let offset = buf.filled().len();
+        let result = this.inner.poll_read(cx, buf);
+        if this.preface.0.get().is_none() {
+            let bytes = &buf.filled()[offset..];
+            if !bytes.is_empty() {
+                let _ = this
+                    .preface
+                    .0
+                    .set(bytes[..bytes.len().min(PREFACE_LEN)].into());
+            }
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for PrefaceRecorder<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+/// Layer that applies [`UpstreamValidation`], which turns [`UpstreamProtocolError`]s
+/// into `502 Bad Gateway` responses and short-circuits upstreams which keep failing
+/// with the same [`UpstreamProtocolErrorKind`].
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct UpstreamValidationLayer {
+    failure_threshold: usize,
+    cooldown: Duration,
+}
+
+impl Default for UpstreamValidationLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UpstreamValidationLayer {
+    /// Create a new [`UpstreamValidationLayer`], which by default opens the circuit
+    /// of an upstream for 30 seconds after 3 consecutive identical failures.
+    pub fn new() -> Self {
+        Self {
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the amount of consecutive identical failures
+    /// after which the circuit of an upstream is opened.
+    pub fn with_failure_threshold(mut self, threshold: usize) -> Self {
+        self.failure_threshold = threshold.max(1);
+        self
+    }
+
+    /// Set the amount of consecutive identical failures
+    /// after which the circuit of an upstream is opened.
+    pub fn set_failure_threshold(&mut self, threshold: usize) -> &mut Self {
+        self.failure_threshold = threshold.max(1);
+        self
+    }
+
+    /// Set the duration for which the circuit of an upstream remains open,
+    /// after which a single request is let through again to probe the upstream.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Set the duration for which the circuit of an upstream remains open,
+    /// after which a single request is let through again to probe the upstream.
+    pub fn set_cooldown(&mut self, cooldown: Duration) -> &mut Self {
+        self.cooldown = cooldown;
+        self
+    }
+}
+
+impl<S> Layer<S> for UpstreamValidationLayer {
+    type Service = UpstreamValidation<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        UpstreamValidation {
+            inner,
+            failure_threshold: self.failure_threshold,
+            cooldown: self.cooldown,
+            circuits: Default::default(),
+        }
+    }
+}
+
+/// Service which turns [`UpstreamProtocolError`]s into `502 Bad Gateway` responses
+/// and short-circuits upstreams which keep failing with the same [`UpstreamProtocolErrorKind`].
+///
+/// The body of such a response is a json object, e.g.:
+///
+/// ```json
+/// {"error":"upstream_tls_on_plaintext","short_circuit":false}
+/// ```
+///
+/// See the [module docs](self) for more details.
+pub struct UpstreamValidation<S> {
+    inner: S,
+    failure_threshold: usize,
+    cooldown: Duration,
+    circuits: Arc<Mutex<HashMap<Authority, Circuit>>>,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    kind: UpstreamProtocolErrorKind,
+    failures: usize,
+    open_until: Option<Instant>,
+}
+
+impl<S> UpstreamValidation<S> {
+    define_inner_service_accessors!();
+
+    fn check_circuit(&self, upstream: &Authority) -> Option<UpstreamProtocolErrorKind> {
+        let circuits = self.circuits.lock();
+        let circuit = circuits.get(upstream)?;
+        circuit
+            .open_until
+            .is_some_and(|open_until| Instant::now() < open_until)
+            .then_some(circuit.kind)
+    }
+
+    fn record_failure(&self, upstream: Authority, kind: UpstreamProtocolErrorKind) {
+        let mut circuits = self.circuits.lock();
+        let circuit = circuits.entry(upstream).or_insert(Circuit {
+            kind,
+            failures: 0,
+            open_until: None,
+        });
+        if circuit.kind != kind {
+            circuit.kind = kind;
+            circuit.failures = 0;
+        }
+        circuit.failures += 1;
+        if circuit.failures >= self.failure_threshold {
+            circuit.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    fn record_success(&self, upstream: &Authority) {
+        self.circuits.lock().remove(upstream);
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for UpstreamValidation<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpstreamValidation")
+            .field("inner", &self.inner)
+            .field("failure_threshold", &self.failure_threshold)
+            .field("cooldown", &self.cooldown)
+            .field("circuits", &self.circuits)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for UpstreamValidation<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            failure_threshold: self.failure_threshold,
+            cooldown: self.cooldown,
+            circuits: self.circuits.clone(),
+        }
+    }
+}
+
+impl<S, State, ReqBody> Service<State, Request<ReqBody>> for UpstreamValidation<S>
+where
+    S: Service<State, Request<ReqBody>, Response = Response, Error: Into<BoxError>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let upstream = RequestContext::try_from((&ctx, &req))
+            .ok()
+            .and_then(|req_ctx| Authority::try_from(req_ctx.authority).ok());
+
+        if let Some(kind) = upstream
+            .as_ref()
+            .and_then(|upstream| self.check_circuit(upstream))
+        {
+            tracing::debug!(
+                upstream = ?upstream,
+                error = %kind,
+                "upstream circuit open: short-circuit request",
+            );
+            return Ok(bad_gateway(kind, true));
+        }
+
+        match self.inner.serve(ctx, req).await {
+            Ok(resp) => {
+                if let Some(upstream) = upstream.as_ref() {
+                    self.record_success(upstream);
+                }
+                Ok(resp)
+            }
+            Err(err) => {
+                let err = err.into();
+                let Some(kind) = UpstreamProtocolError::find(err.as_ref()).map(|err| err.kind())
+                else {
+                    return Err(err);
+                };
+                tracing::warn!(
+                    upstream = ?upstream,
+                    error = %err,
+                    hint = kind.hint(),
+                    "upstream protocol error",
+                );
+                if let Some(upstream) = upstream {
+                    self.record_failure(upstream, kind);
+                }
+                Ok(bad_gateway(kind, false))
+            }
+        }
+    }
+}
+
+fn bad_gateway(kind: UpstreamProtocolErrorKind, short_circuit: bool) -> Response {
+    let body = format!(
+        r#"{{"error":"{}","short_circuit":{}}}"#,
+        kind.code(),
+        short_circuit
+    );
+    let mut resp = Response::new(Body::from(body));
+    *resp.status_mut() = StatusCode::BAD_GATEWAY;
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::HttpClient;
+    use rama_http_types::BodyExtractExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Spawn a listener which replies with the given bytes to any request,
+    /// returning its uri and the amount of accepted connections.
+    async fn spawn_mismatched_upstream(reply: &'static [u8]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream.write_all(reply).await;
+                });
+            }
+        });
+        (format!("http://{addr}/"), accepted)
+    }
+
+    async fn validate(svc: &UpstreamValidation<HttpClient>, uri: &str) -> (StatusCode, String) {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        let status = resp.status();
+        (status, resp.try_into_string().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_tls_on_plaintext() {
+        // TLS alert record: protocol_version (fatal)
+        let (uri, _) = spawn_mismatched_upstream(&[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x46]).await;
+
+        let req = Request::builder().uri(&uri).body(Body::empty()).unwrap();
+        let err = HttpClient::new()
+            .serve(Context::default(), req)
+            .await
+            .unwrap_err();
+        let err = UpstreamProtocolError::find(&err).unwrap();
+        assert_eq!(err.kind(), UpstreamProtocolErrorKind::TlsOnPlaintext);
+
+        let svc = UpstreamValidationLayer::new().layer(HttpClient::new());
+        let (status, body) = validate(&svc, &uri).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            body,
+            r#"{"error":"upstream_tls_on_plaintext","short_circuit":false}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_preface() {
+        let (uri, _) = spawn_mismatched_upstream(b"SSH-2.0-OpenSSH_9.6\r\n").await;
+
+        let svc = UpstreamValidationLayer::new().layer(HttpClient::new());
+        let (status, body) = validate(&svc, &uri).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            body,
+            r#"{"error":"upstream_invalid_preface","short_circuit":false}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reset_before_headers() {
+        let (uri, _) = spawn_mismatched_upstream(b"").await;
+
+        let svc = UpstreamValidationLayer::new().layer(HttpClient::new());
+        let (status, body) = validate(&svc, &uri).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            body,
+            r#"{"error":"upstream_reset_before_headers","short_circuit":false}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_valid_upstream_untouched() {
+        let (uri, _) =
+            spawn_mismatched_upstream(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok").await;
+
+        let svc = UpstreamValidationLayer::new().layer(HttpClient::new());
+        let (status, body) = validate(&svc, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_repeated_failures_short_circuit() {
+        let (uri, accepted) =
+            spawn_mismatched_upstream(&[0x15, 0x03, 0x01, 0x00, 0x02, 0x02, 0x46]).await;
+
+        let svc = UpstreamValidationLayer::new()
+            .with_failure_threshold(2)
+            .layer(HttpClient::new());
+
+        for _ in 0..2 {
+            let (status, body) = validate(&svc, &uri).await;
+            assert_eq!(status, StatusCode::BAD_GATEWAY);
+            assert_eq!(
+                body,
+                r#"{"error":"upstream_tls_on_plaintext","short_circuit":false}"#
+            );
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+        let (status, body) = validate(&svc, &uri).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            body,
+            r#"{"error":"upstream_tls_on_plaintext","short_circuit":true}"#
+        );
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_classify_preface() {
+        assert!(is_tls_record(&[0x16, 0x03, 0x01, 0x00, 0x2a]));
+        assert!(is_tls_record(&[0x15]));
+        assert!(!is_tls_record(b"HTTP/"));
+        assert!(!is_tls_record(&[0x16, 0x00]));
+
+        assert!(is_http_preface(Version::HTTP_11, b"HTTP/"));
+        assert!(is_http_preface(Version::HTTP_11, b"HT"));
+        assert!(!is_http_preface(Version::HTTP_11, b"SSH-2"));
+        assert!(is_http_preface(
+            Version::HTTP_2,
+            &[0x00, 0x00, 0x12, 0x04, 0x00]
+        ));
+        assert!(!is_http_preface(Version::HTTP_2, b"HTTP/"));
+    }
+}
@@ -17,3 +17,6 @@
 
 pub mod client;
 pub mod server;
+
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
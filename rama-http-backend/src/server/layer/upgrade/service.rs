@@ -102,6 +102,12 @@ where
                     exec.spawn_task(async move {
                         match rama_http_core::upgrade::on(&mut req).await {
                             Ok(upgraded) => {
+                                #[cfg(feature = "telemetry")]
+                                let upgraded = Upgraded::new(
+                                    rama_net::stream::layer::TunnelRegistry::from_ctx(&ctx)
+                                        .track(&ctx, "upgrade", upgraded),
+                                    bytes::Bytes::new(),
+                                );
                                 let _ = handler.serve(ctx, upgraded).await;
                             }
                             Err(e) => {
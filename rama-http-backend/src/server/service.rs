@@ -264,3 +264,249 @@ where
             .http_core_serve_connection(ctx, stream, service)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::{service::service_fn, Layer};
+    use rama_http_types::{Body, Response};
+    use rama_net::stream::layer::{BytesRWTrackerHandle, IncomingBytesTrackerLayer};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_bytes_tracker_handle_available_in_http_service() {
+        let (mut client, server) = tokio::io::duplex(1024);
+
+        let http_service = service_fn(|ctx: Context<()>, _req: Request| async move {
+            let handle = ctx.get::<BytesRWTrackerHandle>().unwrap();
+            Ok::<_, Infallible>(Response::new(Body::from(format!("read={}", handle.read()))))
+        });
+        let svc = IncomingBytesTrackerLayer::new().layer(HttpServer::http1().service(http_service));
+
+        let server_task = tokio::spawn(async move { svc.serve(Context::default(), server).await });
+
+        let request = b"GET / HTTP/1.1\r\nhost: example.com\r\nconnection: close\r\n\r\n";
+        client.write_all(request).await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(
+            response.ends_with(&format!("read={}", request.len())),
+            "{response}"
+        );
+
+        server_task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http_service_serves_rewind_stream() {
+        use rama_net::stream::RewindStream;
+
+        let (mut client, server) = tokio::io::duplex(1024);
+
+        let server_task = tokio::spawn(async move {
+            // a prefix reader which reads more than the (line) prefix it consumes
+            let mut stream = RewindStream::new(server);
+            let mut buf = [0u8; 16];
+            stream.read_exact(&mut buf).await.unwrap();
+            let prefix_len = buf.iter().position(|b| *b == b'\n').unwrap() + 1;
+            assert_eq!(&buf[..prefix_len], b"PREFIX\r\n");
+            stream.rewind(buf[prefix_len..].to_vec().into());
+
+            HttpServer::http1()
+                .service(service_fn(|req: Request| async move {
+                    Ok::<_, Infallible>(Response::new(Body::from(req.uri().path().to_owned())))
+                }))
+                .serve(Context::default(), stream)
+                .await
+        });
+
+        client
+            .write_all(b"PREFIX\r\nGET /rewound HTTP/1.1\r\nhost: example.com\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("/rewound"), "{response}");
+
+        server_task.await.unwrap().unwrap();
+    }
+
+    #[derive(Debug, Clone)]
+    struct RequestMarker(String);
+
+    async fn serve_pipelined(ctx: Context<()>, req: Request) -> Result<Response, Infallible> {
+        let leaked = ctx.get::<RequestMarker>().map(|m| m.0.clone());
+        let mut ctx = ctx;
+        let id = req.headers()["x-request-id"].to_str().unwrap().to_owned();
+        ctx.insert(RequestMarker(id.clone()));
+        // serve the first requests the slowest, as to detect reordering
+        let delay = match req.uri().path() {
+            "/1" => 30,
+            "/2" => 15,
+            _ => 0,
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+        Ok(Response::builder()
+            .header("x-request-id", id)
+            .body(Body::from(format!(
+                "{};leaked={}",
+                req.uri().path(),
+                leaked.as_deref().unwrap_or("none")
+            )))
+            .unwrap())
+    }
+
+    const PIPELINED_REQUESTS: &[u8] =
+        b"GET /1 HTTP/1.1\r\nhost: example.com\r\nx-request-id: a\r\n\r\n\
+        GET /2 HTTP/1.1\r\nhost: example.com\r\nx-request-id: b\r\n\r\n\
+        GET /3 HTTP/1.1\r\nhost: example.com\r\nx-request-id: c\r\nconnection: close\r\n\r\n";
+
+    #[tokio::test]
+    async fn test_h1_pipelined_requests_ordered_and_isolated() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_task = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            HttpServer::http1()
+                .service(service_fn(serve_pipelined))
+                .serve(Context::default(), stream)
+                .await
+        });
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client.write_all(PIPELINED_REQUESTS).await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+
+        let responses: Vec<_> = response
+            .split("HTTP/1.1 200 OK")
+            .skip(1)
+            .map(|r| r.to_lowercase())
+            .collect();
+        assert_eq!(responses.len(), 3, "{response}");
+        for (response, (path, id)) in responses
+            .iter()
+            .zip([("/1", "a"), ("/2", "b"), ("/3", "c")])
+        {
+            assert!(
+                response.contains(&format!("x-request-id: {id}\r\n")),
+                "{response}"
+            );
+            assert!(
+                response.ends_with(&format!("{path};leaked=none")),
+                "{response}"
+            );
+        }
+
+        server_task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_h1_pipelining_disabled() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (seen_tx, mut seen_rx) = tokio::sync::mpsc::unbounded_channel();
+        let server_task = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut server = HttpServer::http1();
+            server.http1_mut().pipelining(false);
+            server
+                .service(service_fn(move |ctx, req: Request| {
+                    seen_tx.send(req.uri().path().to_owned()).unwrap();
+                    serve_pipelined(ctx, req)
+                }))
+                .serve(Context::default(), stream)
+                .await
+        });
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client.write_all(PIPELINED_REQUESTS).await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 1, "{response}");
+        assert!(response.contains("connection: close\r\n"), "{response}");
+        assert!(response.ends_with("/1;leaked=none"), "{response}");
+
+        server_task.await.unwrap().unwrap();
+        assert_eq!(seen_rx.recv().await.as_deref(), Some("/1"));
+        assert_eq!(seen_rx.recv().await, None);
+    }
+
+    async fn report_trailers(mut req: Request) -> Result<Response, Infallible> {
+        let trailers = req.body_mut().trailers().await.unwrap().unwrap_or_default();
+        let mut report: Vec<_> = trailers
+            .iter()
+            .map(|(name, value)| format!("{name}={}", value.to_str().unwrap()))
+            .collect();
+        report.sort();
+        Ok(Response::new(Body::from(report.join(";"))))
+    }
+
+    #[tokio::test]
+    async fn test_h1_chunked_request_trailers() {
+        let (mut client, server) = tokio::io::duplex(1024);
+
+        let svc = HttpServer::http1().service(service_fn(report_trailers));
+        let server_task = tokio::spawn(async move { svc.serve(Context::default(), server).await });
+
+        client
+            .write_all(
+                b"POST / HTTP/1.1\r\nhost: example.com\r\nconnection: close\r\n\
+                transfer-encoding: chunked\r\ntrailer: x-checksum\r\n\r\n\
+                4\r\ntest\r\n0\r\nx-checksum: abc\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("x-checksum=abc"), "{response}");
+
+        server_task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_h2_request_trailers() {
+        use rama_http_types::dep::http_body_util::BodyExt;
+        use rama_http_types::{HeaderMap, HeaderValue};
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+
+        let svc = HttpServer::h2(Executor::default()).service(service_fn(report_trailers));
+        let server_task = tokio::spawn(async move { svc.serve(Context::default(), server).await });
+
+        let (sender, conn) =
+            rama_http_core::client::conn::http2::handshake(Executor::default(), client)
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        let body =
+            Body::new(Body::from("test").with_trailers(std::future::ready(Some(Ok(trailers)))));
+        let req = Request::builder()
+            .method("POST")
+            .uri("http://example.com/")
+            .body(body)
+            .unwrap();
+
+        let resp = sender.send_request(req).await.unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "grpc-status=0;x-checksum=abc");
+
+        drop(sender);
+        server_task.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,482 @@
+//! Utilities to test http services and middleware.
+//!
+//! Available with the `test-utils` feature.
+
+use crate::{
+    client::{http_handshake, HttpClientService, Upgraded},
+    server::HttpServer,
+};
+use rama_core::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    Context, Service,
+};
+use rama_http_types::{Body, IntoResponse, Request, Response, StatusCode, Version};
+use rama_net::stream::SocketInfo;
+use std::{convert::Infallible, fmt, future::Future, net::SocketAddr};
+use tokio::task::JoinHandle;
+
+#[cfg(feature = "tls")]
+use rama_net::tls::{client::NegotiatedTlsParameters, SecureTransport};
+
+/// An in-process http connection to a [`Service`],
+/// used to test (composed) http services end-to-end without sockets.
+///
+/// Requests are sent using a real http client over an in-memory duplex stream,
+/// which is served by a real [`HttpServer`], such that wire-level behaviour
+/// such as body streaming, keep-alive and upgrades is exercised.
+/// All requests sent using the same [`InProcessConnection`] share that connection.
+///
+/// The server [`Context`] contains the connection-scoped extensions
+/// a server would insert for a real connection, such as the [`SocketInfo`]
+/// and (if configured) the tls information, see [`InProcessConnectionBuilder`].
+///
+/// # Example
+///
+/// ```
+/// use rama_core::{service::service_fn, Context, Service};
+/// use rama_http_backend::test_utils::InProcessConnection;
+/// use rama_http_types::{Body, BodyExtractExt, Request, Response};
+/// use rama_net::stream::SocketInfo;
+/// use std::convert::Infallible;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let conn = InProcessConnection::http1(
+///     Context::default(),
+///     service_fn(|ctx: Context<()>, _req: Request| async move {
+///         let peer_addr = ctx.get::<SocketInfo>().unwrap().peer_addr();
+///         Ok::<_, Infallible>(Response::new(Body::from(peer_addr.to_string())))
+///     }),
+/// )
+/// .await
+/// .unwrap();
+///
+/// let req = Request::builder()
+///     .uri("http://example.com/")
+///     .body(Body::empty())
+///     .unwrap();
+/// let resp = conn.serve(Context::default(), req).await.unwrap();
+/// assert_eq!(resp.try_into_string().await.unwrap(), "127.0.0.1:54321");
+/// # }
+/// ```
+pub struct InProcessConnection {
+    client: HttpClientService<Body>,
+    server: JoinHandle<Result<(), BoxError>>,
+}
+
+impl fmt::Debug for InProcessConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InProcessConnection")
+            .field("client", &self.client)
+            .field("server", &self.server)
+            .finish()
+    }
+}
+
+impl InProcessConnection {
+    /// Create a new [`InProcessConnectionBuilder`] to configure the connection.
+    pub fn builder() -> InProcessConnectionBuilder {
+        InProcessConnectionBuilder::new()
+    }
+
+    /// Connect to the given [`Service`] using http/1.1
+    /// and the default [`InProcessConnectionBuilder`] settings.
+    pub async fn http1<State, S>(ctx: Context<State>, service: S) -> Result<Self, OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+        S: Service<State, Request, Response: IntoResponse + Send + 'static, Error = Infallible>,
+    {
+        Self::builder().connect(ctx, service).await
+    }
+
+    /// Connect to the given [`Service`] using h2
+    /// and the default [`InProcessConnectionBuilder`] settings.
+    pub async fn h2<State, S>(ctx: Context<State>, service: S) -> Result<Self, OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+        S: Service<State, Request, Response: IntoResponse + Send + 'static, Error = Infallible>,
+    {
+        Self::builder()
+            .with_version(Version::HTTP_2)
+            .connect(ctx, service)
+            .await
+    }
+
+    /// Send an http upgrade request (e.g. with an `Upgrade: custom-proto` header),
+    /// returning the [`Response`] together with the [`Upgraded`] connection
+    /// in case the server agreed to switch protocols (`101 Switching Protocols`).
+    ///
+    /// Any other response is returned as-is, without an upgraded connection.
+    pub async fn upgrade<State>(
+        &self,
+        ctx: Context<State>,
+        req: Request,
+    ) -> Result<(Response, Option<Upgraded>), OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+    {
+        let mut resp = self
+            .serve(ctx, req)
+            .await
+            .map_err(OpaqueError::from_boxed)?;
+        if resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return Ok((resp, None));
+        }
+
+        let upgraded = rama_http_core::upgrade::on(&mut resp)
+            .await
+            .context("InProcessConnection: upgrade connection")?;
+        Ok((resp, Some(upgraded)))
+    }
+
+    /// Close the client side of the connection and
+    /// wait until the server finished serving it,
+    /// returning the result of the server.
+    pub async fn close(self) -> Result<(), BoxError> {
+        drop(self.client);
+        self.server.await?
+    }
+}
+
+impl<State> Service<State, Request> for InProcessConnection
+where
+    State: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = BoxError;
+
+    fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
+        self.client.serve(ctx, req)
+    }
+}
+
+/// Builder to configure and establish an [`InProcessConnection`].
+pub struct InProcessConnectionBuilder {
+    version: Version,
+    socket_info: SocketInfo,
+    max_buf_size: usize,
+    #[cfg(feature = "tls")]
+    tls: Option<NegotiatedTlsParameters>,
+}
+
+impl fmt::Debug for InProcessConnectionBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("InProcessConnectionBuilder");
+        d.field("version", &self.version)
+            .field("socket_info", &self.socket_info)
+            .field("max_buf_size", &self.max_buf_size);
+        #[cfg(feature = "tls")]
+        d.field("tls", &self.tls);
+        d.finish()
+    }
+}
+
+impl Clone for InProcessConnectionBuilder {
+    fn clone(&self) -> Self {
+        Self {
+            version: self.version,
+            socket_info: self.socket_info.clone(),
+            max_buf_size: self.max_buf_size,
+            #[cfg(feature = "tls")]
+            tls: self.tls.clone(),
+        }
+    }
+}
+
+impl Default for InProcessConnectionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InProcessConnectionBuilder {
+    const DEFAULT_MAX_BUF_SIZE: usize = 64 * 1024;
+
+    /// Create a new [`InProcessConnectionBuilder`] for an http/1.1 connection
+    /// from `127.0.0.1:54321` to `127.0.0.1:8080`.
+    pub fn new() -> Self {
+        Self {
+            version: Version::HTTP_11,
+            socket_info: SocketInfo::new(
+                Some(SocketAddr::from(([127, 0, 0, 1], 8080))),
+                SocketAddr::from(([127, 0, 0, 1], 54321)),
+            ),
+            max_buf_size: Self::DEFAULT_MAX_BUF_SIZE,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Set the http [`Version`] used for the connection (http/1.1 by default).
+    ///
+    /// Only http/1.x and h2 are supported.
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Set the http [`Version`] used for the connection (http/1.1 by default).
+    ///
+    /// Only http/1.x and h2 are supported.
+    pub fn set_version(&mut self, version: Version) -> &mut Self {
+        self.version = version;
+        self
+    }
+
+    /// Set the [`SocketInfo`] inserted in the server [`Context`].
+    pub fn with_socket_info(mut self, socket_info: SocketInfo) -> Self {
+        self.socket_info = socket_info;
+        self
+    }
+
+    /// Set the [`SocketInfo`] inserted in the server [`Context`].
+    pub fn set_socket_info(&mut self, socket_info: SocketInfo) -> &mut Self {
+        self.socket_info = socket_info;
+        self
+    }
+
+    /// Set the maximum amount of bytes buffered in each
+    /// direction of the in-memory duplex stream (64 KiB by default).
+    ///
+    /// Use a small size to exercise backpressure when streaming bodies.
+    pub fn with_max_buf_size(mut self, max_buf_size: usize) -> Self {
+        self.max_buf_size = max_buf_size;
+        self
+    }
+
+    /// Set the maximum amount of bytes buffered in each
+    /// direction of the in-memory duplex stream (64 KiB by default).
+    ///
+    /// Use a small size to exercise backpressure when streaming bodies.
+    pub fn set_max_buf_size(&mut self, max_buf_size: usize) -> &mut Self {
+        self.max_buf_size = max_buf_size;
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    /// Emulate a tls connection, inserting a [`SecureTransport`] and
+    /// the given [`NegotiatedTlsParameters`] in the server [`Context`],
+    /// as a tls acceptor would do.
+    ///
+    /// The connection itself remains in plain text.
+    pub fn with_tls(mut self, params: NegotiatedTlsParameters) -> Self {
+        self.tls = Some(params);
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    /// Emulate a tls connection, inserting a [`SecureTransport`] and
+    /// the given [`NegotiatedTlsParameters`] in the server [`Context`],
+    /// as a tls acceptor would do.
+    ///
+    /// The connection itself remains in plain text.
+    pub fn set_tls(&mut self, params: NegotiatedTlsParameters) -> &mut Self {
+        self.tls = Some(params);
+        self
+    }
+
+    /// Establish the [`InProcessConnection`] to the given [`Service`].
+    ///
+    /// The given [`Context`] is used as the server [`Context`] of the connection,
+    /// and its executor is used to drive both ends of the connection.
+    pub async fn connect<State, S>(
+        self,
+        ctx: Context<State>,
+        service: S,
+    ) -> Result<InProcessConnection, OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+        S: Service<State, Request, Response: IntoResponse + Send + 'static, Error = Infallible>,
+    {
+        let (client_stream, server_stream) = tokio::io::duplex(self.max_buf_size);
+
+        let mut server_ctx = ctx.clone();
+        server_ctx.insert(self.socket_info);
+        #[cfg(feature = "tls")]
+        if let Some(params) = self.tls {
+            server_ctx.insert(SecureTransport::default());
+            server_ctx.insert(params);
+        }
+
+        let server = match self.version {
+            Version::HTTP_2 => {
+                let server = HttpServer::h2(ctx.executor().clone()).service(service);
+                ctx.spawn(async move { server.serve(server_ctx, server_stream).await })
+            }
+            Version::HTTP_11 | Version::HTTP_10 | Version::HTTP_09 => {
+                let server = HttpServer::http1().service(service);
+                ctx.spawn(async move { server.serve(server_ctx, server_stream).await })
+            }
+            version => {
+                return Err(OpaqueError::from_display(format!(
+                    "InProcessConnection: unsupported http version: {version:?}"
+                )));
+            }
+        };
+
+        let client = http_handshake(&ctx, client_stream, self.version)
+            .await
+            .map_err(OpaqueError::from_boxed)
+            .context("InProcessConnection: http client handshake")?;
+
+        Ok(InProcessConnection { client, server })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::service::service_fn;
+    use rama_http_types::{
+        header::{CONNECTION, TRANSFER_ENCODING, UPGRADE},
+        BodyExtractExt,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn request(uri: &str) -> Request {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_in_process_connection_keep_alive() {
+        let conn = InProcessConnection::http1(
+            Context::default(),
+            service_fn(|ctx: Context<()>, req: Request| async move {
+                let peer_addr = ctx.get::<SocketInfo>().unwrap().peer_addr();
+                Ok::<_, Infallible>(Response::new(Body::from(format!(
+                    "{peer_addr}{}",
+                    req.uri().path()
+                ))))
+            }),
+        )
+        .await
+        .unwrap();
+
+        for path in ["/a", "/b", "/c"] {
+            let resp = conn
+                .serve(
+                    Context::default(),
+                    request(&format!("http://example.com{path}")),
+                )
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(
+                resp.try_into_string().await.unwrap(),
+                format!("127.0.0.1:54321{path}")
+            );
+        }
+
+        conn.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_in_process_connection_h2() {
+        let conn = InProcessConnection::h2(
+            Context::default(),
+            service_fn(|req: Request| async move {
+                Ok::<_, Infallible>(Response::new(Body::from(format!("{:?}", req.version()))))
+            }),
+        )
+        .await
+        .unwrap();
+
+        let mut req = request("http://example.com/");
+        *req.version_mut() = Version::HTTP_2;
+        let resp = conn.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.try_into_string().await.unwrap(), "HTTP/2.0");
+    }
+
+    #[tokio::test]
+    async fn test_in_process_connection_streaming_body() {
+        let conn = InProcessConnection::builder()
+            .with_max_buf_size(16)
+            .connect(
+                Context::default(),
+                service_fn(|req: Request| async move {
+                    assert_eq!(req.headers()[TRANSFER_ENCODING], "chunked");
+                    let body = req.try_into_string().await.unwrap();
+                    Ok::<_, Infallible>(Response::new(Body::from(body.len().to_string())))
+                }),
+            )
+            .await
+            .unwrap();
+
+        let chunks = futures_lite::stream::iter((0..64).map(|_| Ok::<_, Infallible>("0123456789")));
+        let req = Request::builder()
+            .uri("http://example.com/upload")
+            .method("POST")
+            .body(Body::from_stream(chunks))
+            .unwrap();
+        let resp = conn.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.try_into_string().await.unwrap(), "640");
+    }
+
+    async fn custom_proto_server(mut req: Request) -> Result<Response, Infallible> {
+        if req
+            .headers()
+            .get(UPGRADE)
+            .is_none_or(|v| v != "custom-proto")
+        {
+            return Ok(Response::new(Body::from("no upgrade")));
+        }
+
+        let on_upgrade = rama_http_core::upgrade::on(&mut req);
+        tokio::spawn(async move {
+            let mut upgraded = on_upgrade.await.unwrap();
+            let mut buf = [0u8; 4];
+            upgraded.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ping");
+            upgraded.write_all(b"pong").await.unwrap();
+        });
+
+        Ok(Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "custom-proto")
+            .body(Body::empty())
+            .unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_in_process_connection_upgrade() {
+        let conn = InProcessConnection::http1(Context::default(), service_fn(custom_proto_server))
+            .await
+            .unwrap();
+
+        let req = Request::builder()
+            .uri("http://example.com/")
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "custom-proto")
+            .body(Body::empty())
+            .unwrap();
+        let (resp, upgraded) = conn.upgrade(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(resp.headers()[UPGRADE], "custom-proto");
+
+        let mut upgraded = upgraded.unwrap();
+        upgraded.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        upgraded.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[tokio::test]
+    async fn test_in_process_connection_upgrade_not_accepted() {
+        let conn = InProcessConnection::http1(Context::default(), service_fn(custom_proto_server))
+            .await
+            .unwrap();
+
+        let (resp, upgraded) = conn
+            .upgrade(Context::default(), request("http://example.com/"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(upgraded.is_none());
+        assert_eq!(resp.try_into_string().await.unwrap(), "no upgrade");
+    }
+}
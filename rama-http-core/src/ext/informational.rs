@@ -0,0 +1,40 @@
+use rama_http_types::{Request, Response};
+use std::{fmt, sync::Arc};
+
+/// A callback called for each informational (`1xx`) response
+/// received by an HTTP/1 client, prior to the final response.
+///
+/// Informational responses, such as `100 Continue` and `103 Early Hints`,
+/// are never returned as the response of a request. Attach an [`OnInformational`]
+/// to the extensions of a request (see [`on_informational`]) in order to process them,
+/// e.g. to preload the resources advertised in the `Link` headers of an early hint.
+///
+/// `101 Switching Protocols` is a final response and as such not passed to this callback.
+#[derive(Clone)]
+pub struct OnInformational(Arc<dyn Fn(Response<()>) + Send + Sync + 'static>);
+
+impl OnInformational {
+    /// Create a new [`OnInformational`] calling the given function
+    /// for each informational response received.
+    pub fn new(f: impl Fn(Response<()>) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, res: Response<()>) {
+        (self.0)(res)
+    }
+}
+
+impl fmt::Debug for OnInformational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnInformational").finish()
+    }
+}
+
+/// Call the given function for each informational (`1xx`) response
+/// received for this request, prior to its final response.
+///
+/// See [`OnInformational`] for more information.
+pub fn on_informational<B>(req: &mut Request<B>, f: impl Fn(Response<()>) + Send + Sync + 'static) {
+    req.extensions_mut().insert(OnInformational::new(f));
+}
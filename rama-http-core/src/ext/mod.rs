@@ -5,6 +5,9 @@ use std::fmt;
 mod h1_reason_phrase;
 pub use h1_reason_phrase::ReasonPhrase;
 
+mod informational;
+pub use informational::{on_informational, OnInformational};
+
 /// Represents the `:protocol` pseudo-header used by
 /// the [Extended CONNECT Protocol].
 ///
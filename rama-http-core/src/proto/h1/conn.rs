@@ -48,6 +48,7 @@ where
             io: Buffered::new(io),
             state: State {
                 allow_half_close: false,
+                disable_pipelining: false,
                 error: None,
                 keep_alive: KA::Busy,
                 method: None,
@@ -60,6 +61,7 @@ where
                 title_case_headers: false,
                 h09_responses: false,
                 notify_read: false,
+                on_informational: None,
                 reading: Reading::Init,
                 writing: Writing::Init,
                 upgrade: None,
@@ -116,6 +118,10 @@ where
         self.state.allow_half_close = true;
     }
 
+    pub(crate) fn set_disable_pipelining(&mut self) {
+        self.state.disable_pipelining = true;
+    }
+
     pub(crate) fn disable_date_header(&mut self) {
         self.state.date_header = false;
     }
@@ -203,6 +209,7 @@ where
                 h1_parser_config: self.state.h1_parser_config.clone(),
                 h1_max_headers: self.state.h1_max_headers,
                 h09_responses: self.state.h09_responses,
+                on_informational: &mut self.state.on_informational,
             },
         ) {
             Poll::Ready(Ok(msg)) => msg,
@@ -511,6 +518,7 @@ where
     }
 
     fn try_keep_alive(&mut self, cx: &mut Context<'_>) {
+        self.detect_pipelined_message();
         self.state.try_keep_alive::<T>();
         self.maybe_notify(cx);
     }
@@ -558,8 +566,10 @@ where
 
         if !T::should_read_first() {
             self.state.busy();
+            self.state.on_informational = head.extensions.remove::<crate::ext::OnInformational>();
         }
 
+        self.detect_pipelined_message();
         self.enforce_version(&mut head);
 
         let buf = self.io.headers_buf();
@@ -588,6 +598,27 @@ where
         }
     }
 
+    // If pipelining is disabled, a server does not serve requests
+    // which were sent before the response to the current request completed.
+    //
+    // Keep-alive is disabled instead, such that the connection is closed
+    // once the current response is written, which signals the client
+    // to retry its unanswered requests on a new connection (RFC 9112, section 9.3.2).
+    fn detect_pipelined_message(&mut self) {
+        if self.state.disable_pipelining
+            && T::should_read_first()
+            && matches!(self.state.reading, Reading::KeepAlive)
+            && !self.io.read_buf().is_empty()
+            && self.state.wants_keep_alive()
+        {
+            debug!(
+                "received {} pipelined bytes while pipelining is disabled, closing after current response",
+                self.io.read_buf().len()
+            );
+            self.state.disable_keep_alive();
+        }
+    }
+
     // Fix keep-alive when Connection: keep-alive header is not present
     fn fix_keep_alive(&mut self, head: &mut MessageHead<T::Outgoing>) {
         let outgoing_is_keep_alive = head
@@ -843,6 +874,8 @@ impl<I: Unpin, B, T> Unpin for Conn<I, B, T> {}
 
 struct State {
     allow_half_close: bool,
+    /// Set to true when a server should not serve pipelined requests.
+    disable_pipelining: bool,
     /// If an error occurs when there wasn't a direct way to return it
     /// back to the user, this is set.
     error: Option<crate::Error>,
@@ -864,6 +897,8 @@ struct State {
     /// Set to true when the Dispatcher should poll read operations
     /// again. See the `maybe_notify` method for more.
     notify_read: bool,
+    /// Callback of the in-flight client request for informational responses.
+    on_informational: Option<crate::ext::OnInformational>,
     /// State of allowed reads
     reading: Reading,
     /// State of allowed writes
@@ -909,6 +944,10 @@ impl fmt::Debug for State {
             builder.field("allow_half_close", &true);
         }
 
+        if self.disable_pipelining {
+            builder.field("disable_pipelining", &true);
+        }
+
         // Purposefully leaving off other fields..
 
         builder.finish()
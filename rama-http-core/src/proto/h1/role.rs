@@ -945,6 +945,7 @@ impl Http1Transaction for Client {
                 extensions,
             };
             if let Some((decode, is_upgrade)) = Client::decoder(&head, ctx.req_method)? {
+                *ctx.on_informational = None;
                 return Ok(Some(ParsedMessage {
                     head,
                     decode,
@@ -956,6 +957,10 @@ impl Http1Transaction for Client {
                 }));
             }
 
+            if let Some(callback) = ctx.on_informational.as_ref() {
+                callback.call(head.into_response(()));
+            }
+
             // Parsing a 1xx response could have consumed the buffer, check if
             // it is empty now...
             if buf.is_empty() {
@@ -1430,6 +1435,7 @@ mod tests {
                 h1_parser_config: Default::default(),
                 h1_max_headers: None,
                 h09_responses: false,
+                on_informational: &mut None,
             },
         )
         .unwrap()
@@ -1451,6 +1457,7 @@ mod tests {
             h1_parser_config: Default::default(),
             h1_max_headers: None,
             h09_responses: false,
+            on_informational: &mut None,
         };
         let msg = Client::parse(&mut raw, ctx).unwrap().unwrap();
         assert_eq!(raw.len(), 0);
@@ -1468,6 +1475,7 @@ mod tests {
             h1_parser_config: Default::default(),
             h1_max_headers: None,
             h09_responses: false,
+            on_informational: &mut None,
         };
         Server::parse(&mut raw, ctx).unwrap_err();
     }
@@ -1482,6 +1490,7 @@ mod tests {
             h1_parser_config: Default::default(),
             h1_max_headers: None,
             h09_responses: true,
+            on_informational: &mut None,
         };
         let msg = Client::parse(&mut raw, ctx).unwrap().unwrap();
         assert_eq!(raw, H09_RESPONSE);
@@ -1498,6 +1507,7 @@ mod tests {
             h1_parser_config: Default::default(),
             h1_max_headers: None,
             h09_responses: false,
+            on_informational: &mut None,
         };
         Client::parse(&mut raw, ctx).unwrap_err();
         assert_eq!(raw, H09_RESPONSE);
@@ -1518,6 +1528,7 @@ mod tests {
             h1_parser_config,
             h1_max_headers: None,
             h09_responses: false,
+            on_informational: &mut None,
         };
         let msg = Client::parse(&mut raw, ctx).unwrap().unwrap();
         assert_eq!(raw.len(), 0);
@@ -1535,6 +1546,7 @@ mod tests {
             h1_parser_config: Default::default(),
             h1_max_headers: None,
             h09_responses: false,
+            on_informational: &mut None,
         };
         Client::parse(&mut raw, ctx).unwrap_err();
     }
@@ -1548,6 +1560,7 @@ mod tests {
             h1_parser_config: Default::default(),
             h1_max_headers: None,
             h09_responses: false,
+            on_informational: &mut None,
         };
         let parsed_message = Server::parse(&mut raw, ctx).unwrap().unwrap();
         let mut orig_headers = parsed_message
@@ -1572,6 +1585,7 @@ mod tests {
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
                     h09_responses: false,
+                    on_informational: &mut None,
                 },
             )
             .expect("parse ok")
@@ -1587,6 +1601,7 @@ mod tests {
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
                     h09_responses: false,
+                    on_informational: &mut None,
                 },
             )
             .expect_err(comment)
@@ -1811,6 +1826,7 @@ mod tests {
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
                     h09_responses: false,
+                    on_informational: &mut None,
                 }
             )
             .expect("parse ok")
@@ -1826,6 +1842,7 @@ mod tests {
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
                     h09_responses: false,
+                    on_informational: &mut None,
                 },
             )
             .expect("parse ok")
@@ -1841,6 +1858,7 @@ mod tests {
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
                     h09_responses: false,
+                    on_informational: &mut None,
                 },
             )
             .expect_err("parse should err")
@@ -2433,6 +2451,7 @@ mod tests {
                 h1_parser_config: Default::default(),
                 h1_max_headers: None,
                 h09_responses: false,
+                on_informational: &mut None,
             },
         )
         .expect("parse ok")
@@ -2470,6 +2489,7 @@ mod tests {
                         h1_parser_config: Default::default(),
                         h1_max_headers: max_headers,
                         h09_responses: false,
+                        on_informational: &mut None,
                     },
                 );
                 if should_success {
@@ -2488,6 +2508,7 @@ mod tests {
                         h1_parser_config: Default::default(),
                         h1_max_headers: max_headers,
                         h09_responses: false,
+                        on_informational: &mut None,
                     },
                 );
                 if should_success {
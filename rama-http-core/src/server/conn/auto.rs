@@ -575,6 +575,18 @@ impl Http1Builder<'_> {
         self
     }
 
+    /// Set whether pipelined HTTP/1 requests are served.
+    ///
+    /// See [`Http1Builder::pipelining`] for more information.
+    ///
+    /// Default is true.
+    ///
+    /// [`Http1Builder::pipelining`]: crate::server::conn::http1::Builder::pipelining
+    pub fn pipelining(&mut self, enabled: bool) -> &mut Self {
+        self.inner.http1.pipelining(enabled);
+        self
+    }
+
     /// Bind a connection together with a [`Service`].
     pub async fn serve_connection<I, S>(&self, io: I, service: S) -> Result<()>
     where
@@ -73,6 +73,7 @@ pub struct Builder {
     h1_writev: Option<bool>,
     max_buf_size: Option<usize>,
     pipeline_flush: bool,
+    pipelining: bool,
     date_header: bool,
 }
 
@@ -233,6 +234,7 @@ impl Builder {
             h1_writev: None,
             max_buf_size: None,
             pipeline_flush: false,
+            pipelining: true,
             date_header: true,
         }
     }
@@ -364,6 +366,21 @@ impl Builder {
         self
     }
 
+    /// Set whether pipelined HTTP/1 requests are served.
+    ///
+    /// Requests are always served one at a time, and their responses
+    /// are written in the order the requests were received.
+    /// When disabled, a request received before the response to the previous
+    /// request has been written is not served. Instead the connection is closed
+    /// after that response, such that the client retries the unanswered
+    /// requests on a new connection.
+    ///
+    /// Default is true.
+    pub fn pipelining(&mut self, enabled: bool) -> &mut Self {
+        self.pipelining = enabled;
+        self
+    }
+
     /// Bind a connection together with a [`Service`](crate::service::Service).
     ///
     /// This returns a Future that must be polled in order for HTTP to be
@@ -401,6 +418,9 @@ impl Builder {
             }
         }
         conn.set_flush_pipeline(self.pipeline_flush);
+        if !self.pipelining {
+            conn.set_disable_pipelining();
+        }
         if let Some(max) = self.max_buf_size {
             conn.set_max_buf_size(max);
         }
@@ -11,6 +11,12 @@ pub trait HttpService<ReqBody>: sealed::Sealed<ReqBody> {
     ) -> impl Future<Output = Result<Response, Infallible>> + Send + 'static;
 }
 
+/// Adapter serving the requests of a connection with a rama [`Service`].
+///
+/// Each request is served with its own clone of the connection [`Context`],
+/// such that data inserted while serving one request is never visible
+/// to other requests on the same connection. For HTTP/1 connections,
+/// requests are served one at a time, in the order they were received.
 pub struct RamaHttpService<S, State> {
     svc: S,
     ctx: Context<State>,
@@ -124,7 +124,11 @@ pub(super) fn pending() -> (Pending, OnUpgrade) {
 // ===== impl Upgraded =====
 
 impl Upgraded {
-    pub(super) fn new<T>(io: T, read_buf: Bytes) -> Self
+    /// Create a new [`Upgraded`] for the given IO,
+    /// of which the bytes in the given read buffer are read first.
+    ///
+    /// Useful to wrap the IO of an [`Upgraded`], e.g. to track its bytes.
+    pub fn new<T>(io: T, read_buf: Bytes) -> Self
     where
         T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     {
@@ -4,11 +4,13 @@ use crate::dep::{
     http_body::{self, Body as _, Frame},
     http_body_util::{self, BodyExt},
 };
+use crate::HeaderMap;
 use bytes::Bytes;
 use futures_core::TryStream;
 use futures_lite::stream::Stream;
 use pin_project_lite::pin_project;
 use rama_error::{BoxError, OpaqueError};
+use rama_utils::any::try_downcast;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use sync_wrapper::SyncWrapper;
@@ -22,19 +24,6 @@ where
     try_downcast(body).unwrap_or_else(|body| body.map_err(Into::into).boxed())
 }
 
-pub(crate) fn try_downcast<T, K>(k: K) -> Result<T, K>
-where
-    T: 'static,
-    K: Send + 'static,
-{
-    let mut k = Some(k);
-    if let Some(k) = <dyn std::any::Any>::downcast_mut::<Option<T>>(&mut k) {
-        Ok(k.take().unwrap())
-    } else {
-        Err(k.unwrap())
-    }
-}
-
 /// The body type used in rama requests and responses.
 #[derive(Debug)]
 pub struct Body(BoxBody);
@@ -87,6 +76,25 @@ impl Body {
     pub fn into_data_stream(self) -> BodyDataStream {
         BodyDataStream { inner: self }
     }
+
+    /// Consume the body until its end, returning the trailers sent after the data (if any).
+    ///
+    /// Data frames are discarded. Use [`BodyExt::collect`] in case you need
+    /// both the data and the trailers of the body.
+    ///
+    /// [`BodyExt::collect`]: https://docs.rs/http-body-util/latest/http_body_util/trait.BodyExt.html#method.collect
+    pub async fn trailers(&mut self) -> Result<Option<HeaderMap>, BoxError> {
+        let mut trailers: Option<HeaderMap> = None;
+        while let Some(frame) = self.0.frame().await {
+            if let Ok(frame_trailers) = frame?.into_trailers() {
+                match trailers.as_mut() {
+                    Some(trailers) => trailers.extend(frame_trailers),
+                    None => trailers = Some(frame_trailers),
+                }
+            }
+        }
+        Ok(trailers)
+    }
 }
 
 impl Default for Body {
@@ -220,9 +228,3 @@ where
         }
     }
 }
-
-#[test]
-fn test_try_downcast() {
-    assert_eq!(try_downcast::<i32, _>(5_u32), Err(5_u32));
-    assert_eq!(try_downcast::<i32, _>(5_i32), Ok(5_i32));
-}
@@ -26,6 +26,12 @@ pub use body_limit::BodyLimit;
 mod body_ext;
 pub use body_ext::BodyExtractExt;
 
+mod phase_timings;
+pub use phase_timings::{PhaseMark, PhaseTimings, PhaseTimingsBody};
+
+mod provenance;
+pub use provenance::ResponseProvenance;
+
 /// Type alias for [`http::Request`] whose body type
 /// defaults to [`Body`], the most common body type used with rama.
 pub type Request<T = Body> = http::Request<T>;
@@ -117,11 +123,23 @@ pub mod header {
     }
 
     // non-std conventional
-    static_header!["x-forwarded-host", "x-forwarded-for", "x-forwarded-proto",];
+    static_header![
+        "x-forwarded-host",
+        "x-forwarded-for",
+        "x-forwarded-proto",
+        "x-forwarded-port",
+    ];
 
     // standard
     static_header!["keep-alive", "proxy-connection"];
 
+    // standard security headers
+    static_header![
+        "permissions-policy",
+        "cross-origin-opener-policy",
+        "cross-origin-embedder-policy",
+    ];
+
     // non-std client ip forward headers
     static_header![
         "cf-connecting-ip",
@@ -0,0 +1,314 @@
+use crate::dep::http_body::{self, Frame};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Per-request phase durations, recorded as a request travels
+/// through a proxy (e.g. a reverse proxy) towards its upstream and back.
+///
+/// It is a cheap to clone handle, meaning the same timings can be shared
+/// between the [`Context`] of a request and the extensions of its response.
+/// Each [`PhaseMark`] can only be recorded once, the first recording wins.
+///
+/// The phases are defined by the marks surrounding them:
+///
+/// | phase | from | until |
+/// | --- | --- | --- |
+/// | [`PhaseTimings::middleware`] | creation | [`PhaseMark::UpstreamConnectStart`] |
+/// | [`PhaseTimings::upstream_connect`] | [`PhaseMark::UpstreamConnectStart`] | [`PhaseMark::UpstreamConnected`] |
+/// | [`PhaseTimings::upstream_first_byte`] | [`PhaseMark::UpstreamConnected`] | [`PhaseMark::UpstreamFirstByte`] |
+/// | [`PhaseTimings::upstream_body`] | [`PhaseMark::UpstreamFirstByte`] | [`PhaseMark::UpstreamBodyEnd`] |
+/// | [`PhaseTimings::downstream_write`] | [`PhaseMark::UpstreamBodyEnd`] | [`PhaseMark::DownstreamWriteEnd`] |
+///
+/// [`Context`]: rama_core::Context
+#[derive(Clone)]
+pub struct PhaseTimings {
+    inner: Arc<PhaseTimingsInner>,
+}
+
+struct PhaseTimingsInner {
+    start: Instant,
+    marks: [OnceLock<Instant>; PhaseMark::COUNT],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A point in time recorded by [`PhaseTimings`].
+pub enum PhaseMark {
+    /// Start of the upstream connection establishment (or pool checkout).
+    UpstreamConnectStart,
+    /// Upstream connection is established and ready to be used.
+    UpstreamConnected,
+    /// Response head of the upstream has been received.
+    UpstreamFirstByte,
+    /// Response body of the upstream has been fully received.
+    UpstreamBodyEnd,
+    /// Response has been fully written downstream.
+    DownstreamWriteEnd,
+}
+
+impl PhaseMark {
+    const COUNT: usize = 5;
+
+    const fn index(self) -> usize {
+        match self {
+            PhaseMark::UpstreamConnectStart => 0,
+            PhaseMark::UpstreamConnected => 1,
+            PhaseMark::UpstreamFirstByte => 2,
+            PhaseMark::UpstreamBodyEnd => 3,
+            PhaseMark::DownstreamWriteEnd => 4,
+        }
+    }
+}
+
+impl Default for PhaseTimings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PhaseTimings {
+    /// Create a new [`PhaseTimings`], starting now.
+    pub fn new() -> Self {
+        Self::starting_at(Instant::now())
+    }
+
+    /// Create a new [`PhaseTimings`], starting at the given [`Instant`].
+    pub fn starting_at(start: Instant) -> Self {
+        Self {
+            inner: Arc::new(PhaseTimingsInner {
+                start,
+                marks: Default::default(),
+            }),
+        }
+    }
+
+    /// Record the given [`PhaseMark`] as happening now.
+    ///
+    /// This is a no-op in case the mark was already recorded.
+    pub fn record(&self, mark: PhaseMark) {
+        self.record_at(mark, Instant::now())
+    }
+
+    /// Record the given [`PhaseMark`] as happening at the given [`Instant`].
+    ///
+    /// This is a no-op in case the mark was already recorded.
+    pub fn record_at(&self, mark: PhaseMark, instant: Instant) {
+        let _ = self.inner.marks[mark.index()].set(instant);
+    }
+
+    /// Get the [`Instant`] at which these timings started.
+    pub fn start(&self) -> Instant {
+        self.inner.start
+    }
+
+    /// Get the [`Instant`] at which the given [`PhaseMark`] was recorded, if recorded.
+    pub fn get(&self, mark: PhaseMark) -> Option<Instant> {
+        self.inner.marks[mark.index()].get().copied()
+    }
+
+    /// Time spent in middleware prior to the upstream being contacted.
+    pub fn middleware(&self) -> Option<Duration> {
+        self.get(PhaseMark::UpstreamConnectStart)
+            .map(|end| end.saturating_duration_since(self.inner.start))
+    }
+
+    /// Time spent establishing the upstream connection (or checking it out of a pool).
+    pub fn upstream_connect(&self) -> Option<Duration> {
+        self.between(
+            PhaseMark::UpstreamConnectStart,
+            PhaseMark::UpstreamConnected,
+        )
+    }
+
+    /// Time spent waiting on the response head of the upstream,
+    /// once the connection was established.
+    pub fn upstream_first_byte(&self) -> Option<Duration> {
+        self.between(PhaseMark::UpstreamConnected, PhaseMark::UpstreamFirstByte)
+    }
+
+    /// Time spent receiving the response body of the upstream.
+    pub fn upstream_body(&self) -> Option<Duration> {
+        self.between(PhaseMark::UpstreamFirstByte, PhaseMark::UpstreamBodyEnd)
+    }
+
+    /// Time spent writing the response downstream,
+    /// once the upstream response body was fully received.
+    pub fn downstream_write(&self) -> Option<Duration> {
+        self.between(PhaseMark::UpstreamBodyEnd, PhaseMark::DownstreamWriteEnd)
+    }
+
+    /// Time elapsed between the start and the last recorded [`PhaseMark`].
+    pub fn total(&self) -> Duration {
+        self.inner
+            .marks
+            .iter()
+            .filter_map(|mark| mark.get())
+            .max()
+            .map(|end| end.saturating_duration_since(self.inner.start))
+            .unwrap_or_default()
+    }
+
+    fn between(&self, from: PhaseMark, to: PhaseMark) -> Option<Duration> {
+        let from = self.get(from)?;
+        let to = self.get(to)?;
+        Some(to.saturating_duration_since(from))
+    }
+}
+
+impl fmt::Debug for PhaseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhaseTimings")
+            .field("middleware", &self.middleware())
+            .field("upstream_connect", &self.upstream_connect())
+            .field("upstream_first_byte", &self.upstream_first_byte())
+            .field("upstream_body", &self.upstream_body())
+            .field("downstream_write", &self.downstream_write())
+            .field("total", &self.total())
+            .finish()
+    }
+}
+
+pin_project! {
+    /// A [`http_body::Body`] wrapper which records a [`PhaseMark`]
+    /// in the [`PhaseTimings`] once the inner body reached its end,
+    /// or once the body is dropped (see [`PhaseTimingsBody::on_drop`]).
+    pub struct PhaseTimingsBody<B> {
+        #[pin]
+        inner: B,
+        timings: PhaseTimings,
+        mark: PhaseMark,
+        on_drop: bool,
+    }
+
+    impl<B> PinnedDrop for PhaseTimingsBody<B> {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            if *this.on_drop {
+                this.timings.record(*this.mark);
+            }
+        }
+    }
+}
+
+impl<B> PhaseTimingsBody<B> {
+    /// Create a new [`PhaseTimingsBody`], recording the given [`PhaseMark`]
+    /// once the given body reached its end.
+    pub fn new(inner: B, timings: PhaseTimings, mark: PhaseMark) -> Self {
+        Self {
+            inner,
+            timings,
+            mark,
+            on_drop: false,
+        }
+    }
+
+    /// Create a new [`PhaseTimingsBody`], recording the given [`PhaseMark`]
+    /// once the body is dropped.
+    ///
+    /// A server drops the response body only once it has been written
+    /// to the client, making this the right choice to mark the end of
+    /// a downstream write, which its end-of-stream does not guarantee.
+    pub fn on_drop(inner: B, timings: PhaseTimings, mark: PhaseMark) -> Self {
+        Self {
+            inner,
+            timings,
+            mark,
+            on_drop: true,
+        }
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for PhaseTimingsBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhaseTimingsBody")
+            .field("inner", &self.inner)
+            .field("timings", &self.timings)
+            .field("mark", &self.mark)
+            .field("on_drop", &self.on_drop)
+            .finish()
+    }
+}
+
+impl<B> http_body::Body for PhaseTimingsBody<B>
+where
+    B: http_body::Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let result = this.inner.poll_frame(cx);
+        if !*this.on_drop && matches!(result, Poll::Ready(None)) {
+            this.timings.record(*this.mark);
+        }
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_timings() {
+        let start = Instant::now();
+        let timings = PhaseTimings::starting_at(start);
+
+        assert_eq!(timings.middleware(), None);
+        assert_eq!(timings.total(), Duration::ZERO);
+
+        timings.record_at(
+            PhaseMark::UpstreamConnectStart,
+            start + Duration::from_millis(1),
+        );
+        timings.record_at(
+            PhaseMark::UpstreamConnected,
+            start + Duration::from_millis(3),
+        );
+        timings.record_at(
+            PhaseMark::UpstreamFirstByte,
+            start + Duration::from_millis(6),
+        );
+        timings.record_at(
+            PhaseMark::UpstreamBodyEnd,
+            start + Duration::from_millis(10),
+        );
+        timings.record_at(
+            PhaseMark::DownstreamWriteEnd,
+            start + Duration::from_millis(15),
+        );
+
+        // first recording wins
+        timings.record_at(
+            PhaseMark::UpstreamConnectStart,
+            start + Duration::from_millis(2),
+        );
+
+        assert_eq!(timings.middleware(), Some(Duration::from_millis(1)));
+        assert_eq!(timings.upstream_connect(), Some(Duration::from_millis(2)));
+        assert_eq!(
+            timings.upstream_first_byte(),
+            Some(Duration::from_millis(3))
+        );
+        assert_eq!(timings.upstream_body(), Some(Duration::from_millis(4)));
+        assert_eq!(timings.downstream_write(), Some(Duration::from_millis(5)));
+        assert_eq!(timings.total(), Duration::from_millis(15));
+    }
+}
@@ -0,0 +1,440 @@
+//! Normalization of the message framing headers of http/1 requests,
+//! in order to protect upstreams against request smuggling.
+//!
+//! The framing of an http/1 request body is determined by its `Transfer-Encoding`
+//! and `Content-Length` headers. A proxy which forwards a request whose framing
+//! can be interpreted in more than one way (e.g. both headers present, duplicate
+//! headers or obfuscated codings) risks that the upstream interprets it differently,
+//! which is the root cause of the classic `CL.TE` and `TE.CL` request smuggling attacks.
+//!
+//! Header values containing an obs-fold (a line folded using CRLF followed by whitespace)
+//! are handled as well, as these would otherwise reach the upstream writer as-is
+//! and allow to inject headers.
+//!
+//! See [`normalize_request_framing`] for the rules applied.
+
+use crate::{
+    header::{CONTENT_LENGTH, TRANSFER_ENCODING},
+    HeaderMap, HeaderValue, Request, Version,
+};
+
+rama_utils::macros::error::static_str_error! {
+    #[doc = "http/1 request framing is ambiguous"]
+    pub struct AmbiguousRequestFraming;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// The mode used by [`normalize_request_framing`].
+///
+/// The http client normalizes the request framing prior to sending the request
+/// upstream in [`FramingMode::Strict`] mode, unless another mode
+/// is found in the [`Context`] of the request.
+///
+/// [`Context`]: rama_core::Context
+pub enum FramingMode {
+    #[default]
+    /// Reject any request whose framing headers are not in their canonical form.
+    Strict,
+    /// Normalize framing headers which can be interpreted in only one way
+    /// into their canonical form, logging a warning when doing so.
+    ///
+    /// Requests whose framing is truly ambiguous are still rejected.
+    Lenient,
+}
+
+/// Normalize the framing headers of an http/1 request,
+/// such that its framing can only be interpreted in one way.
+///
+/// The following rules are applied in both modes:
+///
+/// - header values containing a CR or LF which is not part of an obs-fold are rejected;
+/// - a `Transfer-Encoding` header in an http/1.0 request is rejected;
+/// - the `Transfer-Encoding` codings have to end with a single final `chunked` coding;
+/// - the `Content-Length` header is removed when `Transfer-Encoding` is used;
+/// - the `Content-Length` values have to agree and be a valid non-negative integer.
+///
+/// In [`FramingMode::Strict`] mode any request with an obs-fold, more than one
+/// `Transfer-Encoding` or `Content-Length` header, a `Content-Length` list
+/// or any coding other than `chunked` is rejected. In [`FramingMode::Lenient`] mode
+/// obs-folds are replaced by a single space and the framing headers
+/// are merged into a single canonical header instead.
+///
+/// Requests of other versions than http/1 are left untouched.
+pub fn normalize_request_framing<B>(
+    req: &mut Request<B>,
+    mode: FramingMode,
+) -> Result<(), AmbiguousRequestFraming> {
+    let version = req.version();
+    if !matches!(
+        version,
+        Version::HTTP_09 | Version::HTTP_10 | Version::HTTP_11
+    ) {
+        return Ok(());
+    }
+
+    let headers = req.headers_mut();
+    unfold_header_values(headers, mode)?;
+
+    if headers.contains_key(TRANSFER_ENCODING) {
+        if version != Version::HTTP_11 {
+            tracing::debug!(?version, "reject transfer-encoding in pre-http/1.1 request");
+            return Err(AmbiguousRequestFraming);
+        }
+        normalize_transfer_encoding(headers, mode)?;
+        if headers.remove(CONTENT_LENGTH).is_some() {
+            tracing::debug!("removed content-length from request with transfer-encoding");
+        }
+        return Ok(());
+    }
+
+    if headers.contains_key(CONTENT_LENGTH) {
+        normalize_content_length(headers, mode)?;
+    }
+    Ok(())
+}
+
+fn normalize_transfer_encoding(
+    headers: &mut HeaderMap,
+    mode: FramingMode,
+) -> Result<(), AmbiguousRequestFraming> {
+    let mut lines = 0;
+    let mut codings = Vec::new();
+    for value in headers.get_all(TRANSFER_ENCODING) {
+        lines += 1;
+        let value = value.to_str().map_err(|_| AmbiguousRequestFraming)?;
+        for coding in value.split(',') {
+            let coding = coding.trim();
+            if !is_token(coding) {
+                tracing::debug!(%coding, "reject invalid transfer-encoding coding");
+                return Err(AmbiguousRequestFraming);
+            }
+            codings.push(coding.to_ascii_lowercase());
+        }
+    }
+
+    let chunked_count = codings.iter().filter(|c| *c == "chunked").count();
+    if chunked_count != 1 || codings.last().map(String::as_str) != Some("chunked") {
+        tracing::debug!(
+            ?codings,
+            "reject transfer-encoding without single final chunked"
+        );
+        return Err(AmbiguousRequestFraming);
+    }
+
+    if lines == 1 && codings.len() == 1 {
+        if headers[TRANSFER_ENCODING] != "chunked" {
+            headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        }
+        return Ok(());
+    }
+
+    match mode {
+        FramingMode::Strict => {
+            tracing::debug!(
+                lines,
+                ?codings,
+                "reject non-canonical transfer-encoding (strict mode)"
+            );
+            Err(AmbiguousRequestFraming)
+        }
+        FramingMode::Lenient => {
+            tracing::warn!(
+                lines,
+                ?codings,
+                "normalize non-canonical transfer-encoding (lenient mode)"
+            );
+            let value =
+                HeaderValue::try_from(codings.join(", ")).map_err(|_| AmbiguousRequestFraming)?;
+            headers.insert(TRANSFER_ENCODING, value);
+            Ok(())
+        }
+    }
+}
+
+fn normalize_content_length(
+    headers: &mut HeaderMap,
+    mode: FramingMode,
+) -> Result<(), AmbiguousRequestFraming> {
+    let mut lines = 0;
+    let mut values = 0;
+    let mut length = None;
+    for value in headers.get_all(CONTENT_LENGTH) {
+        lines += 1;
+        let value = value.to_str().map_err(|_| AmbiguousRequestFraming)?;
+        for value in value.split(',') {
+            values += 1;
+            let value = value.trim();
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                tracing::debug!(%value, "reject invalid content-length");
+                return Err(AmbiguousRequestFraming);
+            }
+            let value: u64 = value.parse().map_err(|_| AmbiguousRequestFraming)?;
+            if *length.get_or_insert(value) != value {
+                tracing::debug!("reject conflicting content-length values");
+                return Err(AmbiguousRequestFraming);
+            }
+        }
+    }
+
+    if lines == 1 && values == 1 {
+        return Ok(());
+    }
+
+    match mode {
+        FramingMode::Strict => {
+            tracing::debug!(
+                lines,
+                values,
+                "reject non-canonical content-length (strict mode)"
+            );
+            Err(AmbiguousRequestFraming)
+        }
+        FramingMode::Lenient => {
+            tracing::warn!(
+                lines,
+                values,
+                "normalize non-canonical content-length (lenient mode)"
+            );
+            let length = length.ok_or(AmbiguousRequestFraming)?;
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+            Ok(())
+        }
+    }
+}
+
+/// Replace the obs-folds in the header values, rejecting them in [`FramingMode::Strict`] mode.
+///
+/// Valid header values never contain a CR or LF, but these can still
+/// end up in a [`HeaderMap`] using the unchecked [`HeaderValue`] constructors.
+fn unfold_header_values(
+    headers: &mut HeaderMap,
+    mode: FramingMode,
+) -> Result<(), AmbiguousRequestFraming> {
+    for (name, value) in headers.iter_mut() {
+        if !value.as_bytes().iter().any(|b| matches!(b, b'\r' | b'\n')) {
+            continue;
+        }
+        let Some(unfolded) = unfold(value.as_bytes()) else {
+            tracing::debug!(%name, "reject header value with bare CR or LF");
+            return Err(AmbiguousRequestFraming);
+        };
+        match mode {
+            FramingMode::Strict => {
+                tracing::debug!(%name, "reject obs-fold header value (strict mode)");
+                return Err(AmbiguousRequestFraming);
+            }
+            FramingMode::Lenient => {
+                tracing::warn!(%name, "replace obs-fold in header value (lenient mode)");
+                *value = HeaderValue::from_bytes(&unfolded).map_err(|_| AmbiguousRequestFraming)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replace each [obs-fold] in the given header value by a single space,
+/// returning `None` in case it contains a CR or LF which is not part of an obs-fold.
+///
+/// [obs-fold]: https://datatracker.ietf.org/doc/html/rfc9112#section-5.2
+fn unfold(value: &[u8]) -> Option<Vec<u8>> {
+    let mut unfolded = Vec::with_capacity(value.len());
+    let mut bytes = value.iter().copied().peekable();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\r' if bytes.next_if_eq(&b'\n').is_none() => return None,
+            b'\r' | b'\n' => {
+                bytes.next_if(|b| matches!(b, b' ' | b'\t'))?;
+                while bytes.next_if(|b| matches!(b, b' ' | b'\t')).is_some() {}
+                unfolded.push(b' ');
+            }
+            b => unfolded.push(b),
+        }
+    }
+    Some(unfolded)
+}
+
+/// Returns `true` if the given string is a valid [token].
+///
+/// [token]: https://datatracker.ietf.org/doc/html/rfc9110#section-5.6.2
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(version: Version, headers: &[(&'static str, &'static str)]) -> Request<()> {
+        let mut builder = Request::builder().version(version).uri("/");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    fn normalize(
+        mode: FramingMode,
+        headers: &[(&'static str, &'static str)],
+    ) -> Result<HeaderMap, AmbiguousRequestFraming> {
+        let mut req = request(Version::HTTP_11, headers);
+        normalize_request_framing(&mut req, mode)?;
+        Ok(req.headers().clone())
+    }
+
+    fn assert_rejected(headers: &[(&'static str, &'static str)]) {
+        for mode in [FramingMode::Strict, FramingMode::Lenient] {
+            assert!(
+                normalize(mode, headers).is_err(),
+                "{mode:?}: {headers:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_canonical_framing_untouched() {
+        for mode in [FramingMode::Strict, FramingMode::Lenient] {
+            let headers = normalize(mode, &[("content-length", "5")]).unwrap();
+            assert_eq!(headers[CONTENT_LENGTH], "5");
+
+            let headers = normalize(mode, &[("transfer-encoding", "chunked")]).unwrap();
+            assert_eq!(headers[TRANSFER_ENCODING], "chunked");
+
+            let headers = normalize(mode, &[("transfer-encoding", "Chunked ")]).unwrap();
+            assert_eq!(headers[TRANSFER_ENCODING], "chunked");
+
+            let headers = normalize(mode, &[]).unwrap();
+            assert!(headers.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_cl_te_smuggling() {
+        // front-end uses content-length, back-end uses transfer-encoding
+        for mode in [FramingMode::Strict, FramingMode::Lenient] {
+            let headers = normalize(
+                mode,
+                &[("content-length", "13"), ("transfer-encoding", "chunked")],
+            )
+            .unwrap();
+            assert!(!headers.contains_key(CONTENT_LENGTH));
+            assert_eq!(headers[TRANSFER_ENCODING], "chunked");
+        }
+    }
+
+    #[test]
+    fn test_te_cl_smuggling() {
+        // front-end uses transfer-encoding, back-end uses content-length
+        for mode in [FramingMode::Strict, FramingMode::Lenient] {
+            let headers = normalize(
+                mode,
+                &[("transfer-encoding", "chunked"), ("content-length", "3")],
+            )
+            .unwrap();
+            assert!(!headers.contains_key(CONTENT_LENGTH));
+            assert_eq!(headers.get_all(TRANSFER_ENCODING).iter().count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_obfuscated_transfer_encoding_rejected() {
+        assert_rejected(&[("transfer-encoding", "xchunked")]);
+        assert_rejected(&[("transfer-encoding", "chunked, identity")]);
+        assert_rejected(&[("transfer-encoding", "chunked, chunked")]);
+        assert_rejected(&[("transfer-encoding", "chunked,")]);
+        assert_rejected(&[("transfer-encoding", "\tchunked;x=1")]);
+        assert_rejected(&[("transfer-encoding", "chunked"), ("transfer-encoding", "x")]);
+        assert_rejected(&[
+            ("transfer-encoding", "chunked"),
+            ("transfer-encoding", "chunked"),
+        ]);
+        assert_rejected(&[("transfer-encoding", "gzip"), ("content-length", "3")]);
+    }
+
+    #[test]
+    fn test_duplicate_transfer_encoding() {
+        let headers = &[
+            ("transfer-encoding", "gzip"),
+            ("transfer-encoding", "chunked"),
+        ];
+        assert!(normalize(FramingMode::Strict, headers).is_err());
+
+        let headers = normalize(FramingMode::Lenient, headers).unwrap();
+        let values: Vec<_> = headers.get_all(TRANSFER_ENCODING).iter().collect();
+        assert_eq!(values, ["gzip, chunked"]);
+
+        assert!(normalize(
+            FramingMode::Strict,
+            &[("transfer-encoding", "gzip, chunked")]
+        )
+        .is_err());
+        let headers = normalize(
+            FramingMode::Lenient,
+            &[("transfer-encoding", "GZIP,chunked")],
+        )
+        .unwrap();
+        assert_eq!(headers[TRANSFER_ENCODING], "gzip, chunked");
+    }
+
+    #[test]
+    fn test_content_length() {
+        assert_rejected(&[("content-length", "5"), ("content-length", "6")]);
+        assert_rejected(&[("content-length", "5, 6")]);
+        assert_rejected(&[("content-length", "+5")]);
+        assert_rejected(&[("content-length", "-1")]);
+        assert_rejected(&[("content-length", "0x5")]);
+        assert_rejected(&[("content-length", "")]);
+
+        for headers in [
+            &[("content-length", "5"), ("content-length", "5")][..],
+            &[("content-length", "5, 5")][..],
+        ] {
+            assert!(normalize(FramingMode::Strict, headers).is_err());
+            let headers = normalize(FramingMode::Lenient, headers).unwrap();
+            let values: Vec<_> = headers.get_all(CONTENT_LENGTH).iter().collect();
+            assert_eq!(values, ["5"]);
+        }
+    }
+
+    #[test]
+    fn test_unfold() {
+        for (value, expected) in [
+            (&b"chunked"[..], Some(&b"chunked"[..])),
+            (b"gzip,\r\n chunked", Some(b"gzip, chunked")),
+            (b"gzip,\r\n\t \tchunked", Some(b"gzip, chunked")),
+            (b"gzip,\n chunked", Some(b"gzip, chunked")),
+            (b"a\r\n b\r\n\tc", Some(b"a b c")),
+            (b"chunked\r\n", None),
+            (b"chunked\r\nx-injected: 1", None),
+            (b"chunked\rx", None),
+            (b"chunked\r \n", None),
+            (b"chunked\n", None),
+        ] {
+            assert_eq!(
+                unfold(value).as_deref(),
+                expected,
+                "{:?}",
+                String::from_utf8_lossy(value)
+            );
+        }
+    }
+
+    #[test]
+    fn test_transfer_encoding_pre_http11_rejected() {
+        for mode in [FramingMode::Strict, FramingMode::Lenient] {
+            let mut req = request(Version::HTTP_10, &[("transfer-encoding", "chunked")]);
+            assert!(normalize_request_framing(&mut req, mode).is_err());
+        }
+    }
+
+    #[test]
+    fn test_h2_untouched() {
+        let mut req = request(
+            Version::HTTP_2,
+            &[("content-length", "5"), ("content-length", "6")],
+        );
+        normalize_request_framing(&mut req, FramingMode::Strict).unwrap();
+        assert_eq!(req.headers().get_all(CONTENT_LENGTH).iter().count(), 2);
+    }
+}
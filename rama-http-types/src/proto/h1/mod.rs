@@ -2,3 +2,6 @@
 
 pub mod headers;
 pub use headers::{Http1HeaderMap, Http1HeaderName, IntoHttp1HeaderName, TryIntoHttp1HeaderName};
+
+pub mod framing;
+pub use framing::{normalize_request_framing, AmbiguousRequestFraming, FramingMode};
@@ -0,0 +1,98 @@
+use crate::Response;
+use std::{
+    borrow::Cow,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(cfg!(debug_assertions));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Identifies the layer which short-circuited a request,
+/// producing the response instead of the inner service,
+/// and the reason why it did so.
+///
+/// Rama layers which reject requests (e.g. authorization, CORS preflight
+/// and request validation) record it in the response extensions using
+/// [`ResponseProvenance::record`], as long as recording is enabled.
+/// Recording is enabled by default for debug builds only,
+/// use [`ResponseProvenance::set_enabled`] to toggle it at runtime.
+///
+/// It is included in the event of the `DefaultOnResponse` of the `TraceLayer`,
+/// and can be emitted as the `x-rama-denied-by` response header using
+/// the `ProvenanceHeaderLayer`, found in the `rama-http` crate.
+pub struct ResponseProvenance {
+    layer: &'static str,
+    reason: Cow<'static, str>,
+}
+
+impl ResponseProvenance {
+    /// Create a new [`ResponseProvenance`] for the given layer and reason.
+    pub fn new(layer: &'static str, reason: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            layer,
+            reason: reason.into(),
+        }
+    }
+
+    /// The name of the layer which produced the response.
+    pub fn layer(&self) -> &'static str {
+        self.layer
+    }
+
+    /// The reason why the layer produced the response.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Returns `true` if [`ResponseProvenance`]s are recorded.
+    pub fn is_enabled() -> bool {
+        ENABLED.load(Ordering::Relaxed)
+    }
+
+    /// Enable or disable the recording of [`ResponseProvenance`]s, process-wide.
+    pub fn set_enabled(enabled: bool) {
+        ENABLED.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Record the given layer and reason as the [`ResponseProvenance`]
+    /// of the given short-circuited response, if recording is enabled.
+    ///
+    /// A [`ResponseProvenance`] already recorded by a layer
+    /// further down the stack is kept.
+    pub fn record<B>(
+        response: &mut Response<B>,
+        layer: &'static str,
+        reason: impl Into<Cow<'static, str>>,
+    ) {
+        if !Self::is_enabled() || response.extensions().get::<Self>().is_some() {
+            return;
+        }
+        response.extensions_mut().insert(Self::new(layer, reason));
+    }
+}
+
+impl fmt::Display for ResponseProvenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.layer, self.reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_keeps_innermost_provenance() {
+        ResponseProvenance::set_enabled(true);
+
+        let mut response = Response::new(());
+        ResponseProvenance::record(&mut response, "inner", "denied");
+        ResponseProvenance::record(&mut response, "outer", "also denied");
+
+        let provenance = response.extensions().get::<ResponseProvenance>().unwrap();
+        assert_eq!(provenance.layer(), "inner");
+        assert_eq!(provenance.reason(), "denied");
+        assert_eq!(provenance.to_string(), "inner (denied)");
+    }
+}
@@ -2,6 +2,7 @@ use crate::headers::{self, Header};
 use crate::{HeaderName, HeaderValue};
 use rama_core::error::{ErrorContext, OpaqueError};
 use rama_net::forwarded::{ForwardedElement, ForwardedProtocol, ForwardedVersion, NodeId};
+use std::fmt;
 
 /// The Via general header is added by proxies, both forward and reverse.
 ///
@@ -11,13 +12,14 @@ use rama_net::forwarded::{ForwardedElement, ForwardedProtocol, ForwardedVersion,
 ///
 /// It is recommended to use the [`Forwarded`](super::Forwarded) header instead if you can.
 ///
-/// More info can be found at <https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Via>.
+/// More info can be found at <https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Via>
+/// and in [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-7.6.3).
 ///
 /// # Syntax
 ///
 /// ```text
-/// Via: [ <protocol-name> "/" ] <protocol-version> <host> [ ":" <port> ]
-/// Via: [ <protocol-name> "/" ] <protocol-version> <pseudonym>
+/// Via: [ <protocol-name> "/" ] <protocol-version> <host> [ ":" <port> ] [ <comment> ]
+/// Via: [ <protocol-name> "/" ] <protocol-version> <pseudonym> [ <comment> ]
 /// ```
 ///
 /// # Example values
@@ -30,11 +32,106 @@ use rama_net::forwarded::{ForwardedElement, ForwardedProtocol, ForwardedVersion,
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Via(Vec<ViaElement>);
 
+impl Via {
+    /// Create a new [`Via`] header with the given [`ViaElement`] as its only element.
+    pub fn new(element: ViaElement) -> Self {
+        Self(vec![element])
+    }
+
+    /// Append a [`ViaElement`] to this [`Via`] header.
+    pub fn append(&mut self, element: ViaElement) -> &mut Self {
+        self.0.push(element);
+        self
+    }
+
+    /// Returns the last [`ViaElement`] of this [`Via`] header,
+    /// added by the proxy closest to the recipient.
+    pub fn last(&self) -> Option<&ViaElement> {
+        self.0.last()
+    }
+
+    /// Iterate over the [`ViaElement`]s in this [`Via`] header,
+    /// starting with the one added by the proxy closest to the sender.
+    pub fn iter(&self) -> impl Iterator<Item = &ViaElement> {
+        self.0.iter()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct ViaElement {
+/// A single element of the [`Via`] header, added by one proxy.
+pub struct ViaElement {
     protocol: Option<ForwardedProtocol>,
     version: ForwardedVersion,
     node_id: NodeId,
+    comment: Option<String>,
+}
+
+impl ViaElement {
+    /// Create a new [`ViaElement`] for the given received protocol version,
+    /// received by the given host or pseudonym.
+    pub fn new(version: ForwardedVersion, received_by: impl Into<NodeId>) -> Self {
+        Self {
+            protocol: None,
+            version,
+            node_id: received_by.into(),
+            comment: None,
+        }
+    }
+
+    /// Set the name of the received protocol, omitted by default (implying http).
+    pub fn with_protocol(mut self, protocol: ForwardedProtocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Set the name of the received protocol, omitted by default (implying http).
+    pub fn set_protocol(&mut self, protocol: ForwardedProtocol) -> &mut Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Set the comment of this element, e.g. to identify the software of the proxy.
+    ///
+    /// Control characters are replaced by a space.
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.set_comment(comment);
+        self
+    }
+
+    /// Set the comment of this element, e.g. to identify the software of the proxy.
+    ///
+    /// Control characters are replaced by a space.
+    pub fn set_comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        let comment = comment
+            .into()
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        self.comment = Some(comment);
+        self
+    }
+
+    /// The name of the received protocol, if defined.
+    pub fn protocol(&self) -> Option<&ForwardedProtocol> {
+        self.protocol.as_ref()
+    }
+
+    /// The version of the received protocol.
+    pub fn version(&self) -> ForwardedVersion {
+        self.version
+    }
+
+    /// The host or pseudonym of the proxy which received the message.
+    pub fn received_by(&self) -> &NodeId {
+        &self.node_id
+    }
+
+    /// The comment of this element, without its surrounding parentheses, if any.
+    ///
+    /// Comments which are not valid UTF-8 are decoded as ISO-8859-1.
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
 }
 
 impl From<ViaElement> for ForwardedElement {
@@ -56,11 +153,22 @@ impl Header for Via {
     fn decode<'i, I: Iterator<Item = &'i HeaderValue>>(
         values: &mut I,
     ) -> Result<Self, headers::Error> {
-        crate::headers::util::csv::from_comma_delimited(values).map(Via)
+        let mut elements = Vec::new();
+        for value in values {
+            for element in split_elements(value.as_bytes())? {
+                elements.push(ViaElement::try_from(element).map_err(|err| {
+                    tracing::trace!(err = %err, "failed to parse via element");
+                    headers::Error::invalid()
+                })?);
+            }
+        }
+        if elements.is_empty() {
+            return Err(headers::Error::invalid());
+        }
+        Ok(Via(elements))
     }
 
     fn encode<E: Extend<HeaderValue>>(&self, values: &mut E) {
-        use std::fmt;
         struct Format<F>(F);
         impl<F> fmt::Display for Format<F>
         where
@@ -104,6 +212,7 @@ impl super::ForwardHeader for Via {
                     protocol,
                     version,
                     node_id,
+                    comment: None,
                 })
             })
             .collect();
@@ -136,81 +245,175 @@ impl Iterator for ViaIterator {
     }
 }
 
-impl std::str::FromStr for ViaElement {
-    type Err = OpaqueError;
+/// Split a header value into its (non-empty) elements,
+/// separated by commas which are not part of a comment.
+fn split_elements(bytes: &[u8]) -> Result<Vec<&[u8]>, headers::Error> {
+    let mut elements = Vec::new();
+    let mut depth = 0usize;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, b) in bytes.iter().enumerate() {
+        if depth > 0 {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'(' => depth += 1,
+                b')' => depth -= 1,
+                _ => (),
+            }
+            continue;
+        }
+        match b {
+            b'(' => depth += 1,
+            b',' => {
+                elements.push(&bytes[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    if depth > 0 {
+        return Err(headers::Error::invalid());
+    }
+    elements.push(&bytes[start..]);
+    elements.retain(|element| !trim(element).is_empty());
+    Ok(elements)
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut bytes = s.as_bytes();
-
-        bytes = trim_left(bytes);
-
-        let (protocol, version) = match bytes.iter().position(|b| *b == b'/' || *b == b' ') {
-            Some(index) => match bytes[index] {
-                b'/' => {
-                    let protocol: ForwardedProtocol = std::str::from_utf8(&bytes[..index])
-                        .context("parse via protocol as utf-8")?
-                        .try_into()
-                        .context("parse via utf-8 protocol as protocol")?;
-                    bytes = &bytes[index + 1..];
-                    let index = bytes.iter().position(|b| *b == b' ').ok_or_else(|| {
-                        OpaqueError::from_display("via str: missing space after protocol separator")
-                    })?;
-                    let version =
-                        ForwardedVersion::try_from(&bytes[..index]).context("parse via version")?;
-                    bytes = &bytes[index + 1..];
-                    (Some(protocol), version)
-                }
-                b' ' => {
-                    let version =
-                        ForwardedVersion::try_from(&bytes[..index]).context("parse via version")?;
-                    bytes = &bytes[index + 1..];
-                    (None, version)
-                }
-                _ => unreachable!(),
-            },
-            None => {
-                return Err(OpaqueError::from_display("via str: missing version"));
+impl TryFrom<&[u8]> for ViaElement {
+    type Error = OpaqueError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let bytes = trim(bytes);
+
+        let index = bytes
+            .iter()
+            .position(|b| is_whitespace(*b))
+            .ok_or_else(|| OpaqueError::from_display("via str: missing received-by"))?;
+        let (protocol, version) = match bytes[..index].iter().position(|b| *b == b'/') {
+            Some(slash) => {
+                let protocol: ForwardedProtocol = std::str::from_utf8(&bytes[..slash])
+                    .context("parse via protocol as utf-8")?
+                    .try_into()
+                    .context("parse via utf-8 protocol as protocol")?;
+                let version = ForwardedVersion::try_from(&bytes[slash + 1..index])
+                    .context("parse via version")?;
+                (Some(protocol), version)
             }
+            None => (
+                None,
+                ForwardedVersion::try_from(&bytes[..index]).context("parse via version")?,
+            ),
         };
 
-        bytes = trim_right(trim_left(bytes));
-        let node_id = NodeId::from_bytes_lossy(bytes);
+        let bytes = trim(&bytes[index..]);
+        let index = bytes
+            .iter()
+            .position(|b| is_whitespace(*b) || *b == b'(')
+            .unwrap_or(bytes.len());
+        if index == 0 {
+            return Err(OpaqueError::from_display("via str: missing received-by"));
+        }
+        let node_id = NodeId::from_bytes_lossy(&bytes[..index]);
+
+        let bytes = trim(&bytes[index..]);
+        let comment = if bytes.is_empty() {
+            None
+        } else {
+            Some(parse_comment(bytes)?)
+        };
 
         Ok(Self {
             protocol,
             version,
             node_id,
+            comment,
         })
     }
 }
 
-impl std::fmt::Display for ViaElement {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(ref proto) = self.protocol {
-            write!(f, "{proto}/")?;
+/// Parse a comment, including its surrounding parentheses, into its unescaped text.
+///
+/// Nested comments are kept as-is, and comments which are not valid UTF-8
+/// (e.g. containing obs-text) are decoded as ISO-8859-1.
+fn parse_comment(bytes: &[u8]) -> Result<String, OpaqueError> {
+    let inner = bytes
+        .strip_prefix(b"(")
+        .and_then(|bytes| bytes.strip_suffix(b")"))
+        .ok_or_else(|| OpaqueError::from_display("via str: invalid comment"))?;
+
+    let mut text = Vec::with_capacity(inner.len());
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for b in inner.iter().copied() {
+        if escaped {
+            escaped = false;
+        } else {
+            match b {
+                b'\\' => {
+                    escaped = true;
+                    continue;
+                }
+                b'(' => depth += 1,
+                b')' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| OpaqueError::from_display("via str: invalid comment"))?;
+                }
+                _ => (),
+            }
         }
-        write!(f, "{} {}", self.version, self.node_id)
+        text.push(b);
     }
+    if depth > 0 || escaped {
+        return Err(OpaqueError::from_display("via str: invalid comment"));
+    }
+
+    Ok(match String::from_utf8(text) {
+        Ok(text) => text,
+        Err(err) => err.into_bytes().into_iter().map(char::from).collect(),
+    })
 }
 
-fn trim_left(b: &[u8]) -> &[u8] {
-    let mut offset = 0;
-    while offset < b.len() && b[offset] == b' ' {
-        offset += 1;
+impl std::str::FromStr for ViaElement {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.as_bytes().try_into()
     }
-    &b[offset..]
 }
 
-fn trim_right(b: &[u8]) -> &[u8] {
-    if b.is_empty() {
-        return b;
+impl fmt::Display for ViaElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref proto) = self.protocol {
+            write!(f, "{proto}/")?;
+        }
+        write!(f, "{} {}", self.version, self.node_id)?;
+        if let Some(ref comment) = self.comment {
+            f.write_str(" (")?;
+            for c in comment.chars() {
+                if matches!(c, '(' | ')' | '\\') {
+                    f.write_str("\\")?;
+                }
+                write!(f, "{c}")?;
+            }
+            f.write_str(")")?;
+        }
+        Ok(())
     }
+}
 
-    let mut offset = b.len();
-    while offset > 0 && b[offset - 1] == b' ' {
-        offset -= 1;
-    }
-    &b[..offset]
+fn is_whitespace(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+fn trim(b: &[u8]) -> &[u8] {
+    let start = b.iter().position(|b| !is_whitespace(*b)).unwrap_or(b.len());
+    let end = b
+        .iter()
+        .rposition(|b| !is_whitespace(*b))
+        .map_or(start, |i| i + 1);
+    &b[start..end]
 }
 
 #[cfg(test)]
@@ -246,6 +449,7 @@ mod tests {
             protocol: None,
             version: ForwardedVersion::HTTP_11,
             node_id: NodeId::try_from_str("vegur").unwrap(),
+            comment: None,
         }]))
     );
     test_header!(
@@ -255,6 +459,7 @@ mod tests {
             protocol: None,
             version: ForwardedVersion::HTTP_11,
             node_id: NodeId::try_from_str("vegur").unwrap(),
+            comment: None,
         }]))
     );
     test_header!(
@@ -265,11 +470,13 @@ mod tests {
                 protocol: None,
                 version: ForwardedVersion::HTTP_10,
                 node_id: NodeId::try_from_str("fred").unwrap(),
+                comment: None,
             },
             ViaElement {
                 protocol: None,
                 version: ForwardedVersion::HTTP_11,
                 node_id: NodeId::try_from_str("p.example.net").unwrap(),
+                comment: None,
             }
         ]))
     );
@@ -281,11 +488,13 @@ mod tests {
                 protocol: None,
                 version: ForwardedVersion::HTTP_10,
                 node_id: NodeId::try_from_str("fred").unwrap(),
+                comment: None,
             },
             ViaElement {
                 protocol: None,
                 version: ForwardedVersion::HTTP_11,
                 node_id: NodeId::try_from_str("p.example.net").unwrap(),
+                comment: None,
             }
         ]))
     );
@@ -297,11 +506,13 @@ mod tests {
                 protocol: None,
                 version: ForwardedVersion::HTTP_10,
                 node_id: NodeId::try_from_str("fred").unwrap(),
+                comment: None,
             },
             ViaElement {
                 protocol: None,
                 version: ForwardedVersion::HTTP_11,
                 node_id: NodeId::try_from_str("p.example.net").unwrap(),
+                comment: None,
             }
         ]))
     );
@@ -313,11 +524,13 @@ mod tests {
                 protocol: Some(ForwardedProtocol::HTTP),
                 version: ForwardedVersion::HTTP_11,
                 node_id: NodeId::try_from_str("proxy.example.re").unwrap(),
+                comment: None,
             },
             ViaElement {
                 protocol: None,
                 version: ForwardedVersion::HTTP_11,
                 node_id: NodeId::try_from_str("edge_1").unwrap(),
+                comment: None,
             }
         ]))
     );
@@ -327,13 +540,57 @@ mod tests {
         Some(Via(vec![ViaElement {
             protocol: None,
             version: ForwardedVersion::HTTP_11,
-            node_id: NodeId::try_from_str(
-                "2e9b3ee4d534903f433e1ed8ea30e57a.cloudfront.net__CloudFront_"
-            )
-            .unwrap(),
+            node_id: NodeId::try_from_str("2e9b3ee4d534903f433e1ed8ea30e57a.cloudfront.net")
+                .unwrap(),
+            comment: Some("CloudFront".to_owned()),
         }]))
     );
 
+    test_header!(
+        test_multiple_with_comments,
+        vec!["1.0 fred (Fred, the proxy), HTTP/1.1 p.example.net:8080 (nested (comment) \\) ok), 2 edge"],
+        Some(Via(vec![
+            ViaElement {
+                protocol: None,
+                version: ForwardedVersion::HTTP_10,
+                node_id: NodeId::try_from_str("fred").unwrap(),
+                comment: Some("Fred, the proxy".to_owned()),
+            },
+            ViaElement {
+                protocol: Some(ForwardedProtocol::HTTP),
+                version: ForwardedVersion::HTTP_11,
+                node_id: NodeId::from_str_lossy("p.example.net:8080"),
+                comment: Some("nested (comment) ) ok".to_owned()),
+            },
+            ViaElement {
+                protocol: None,
+                version: ForwardedVersion::HTTP_2,
+                node_id: NodeId::try_from_str("edge").unwrap(),
+                comment: None,
+            }
+        ]))
+    );
+    test_header!(
+        test_invalid_unbalanced_comment,
+        vec!["1.1 fred (unbalanced, 1.1 edge"],
+        None::<Via>
+    );
+    test_header!(test_invalid_missing_received_by, vec!["1.1"], None::<Via>);
+    test_header!(test_invalid_empty, vec![" , "], None::<Via>);
+
+    #[test]
+    fn test_via_obs_text_comment() {
+        let value =
+            HeaderValue::from_bytes(b"1.1 vegur (caf\xe9), 1.1 edge (\xc3\xa9t\xc3\xa9)").unwrap();
+        let via = Via::decode(&mut std::iter::once(&value)).unwrap();
+        let comments: Vec<_> = via.iter().map(|el| el.comment()).collect();
+        assert_eq!(comments, [Some("caf\u{e9}"), Some("\u{e9}t\u{e9}")]);
+        assert_eq!(
+            via.last().unwrap().received_by(),
+            &NodeId::try_from_str("edge").unwrap()
+        );
+    }
+
     #[test]
     fn test_via_symmetric_encoder() {
         for via_input in [
@@ -342,11 +599,13 @@ mod tests {
                     protocol: None,
                     version: ForwardedVersion::HTTP_10,
                     node_id: NodeId::try_from_str("fred").unwrap(),
+                    comment: None,
                 },
                 ViaElement {
                     protocol: None,
                     version: ForwardedVersion::HTTP_11,
                     node_id: NodeId::try_from_str("p.example.net").unwrap(),
+                    comment: None,
                 },
             ]),
             Via(vec![
@@ -354,20 +613,27 @@ mod tests {
                     protocol: Some(ForwardedProtocol::HTTP),
                     version: ForwardedVersion::HTTP_11,
                     node_id: NodeId::try_from_str("proxy.example.re").unwrap(),
+                    comment: None,
                 },
                 ViaElement {
                     protocol: None,
                     version: ForwardedVersion::HTTP_11,
                     node_id: NodeId::try_from_str("edge_1").unwrap(),
+                    comment: None,
                 },
             ]),
             Via(vec![ViaElement {
                 protocol: None,
                 version: ForwardedVersion::HTTP_11,
-                node_id: NodeId::try_from_str(
-                    "2e9b3ee4d534903f433e1ed8ea30e57a.cloudfront.net__CloudFront_",
-                )
-                .unwrap(),
+                node_id: NodeId::try_from_str("2e9b3ee4d534903f433e1ed8ea30e57a.cloudfront.net")
+                    .unwrap(),
+                comment: Some("CloudFront".to_owned()),
+            }]),
+            Via(vec![ViaElement {
+                protocol: None,
+                version: ForwardedVersion::HTTP_2,
+                node_id: NodeId::try_from_str("edge").unwrap(),
+                comment: Some("rama (v1, \\beta\\) caf\u{e9}".to_owned()),
             }]),
         ] {
             let mut values = Vec::new();
@@ -38,7 +38,10 @@ impl Header for XForwardedHost {
         Ok(XForwardedHost(
             values
                 .next()
-                .and_then(|value| value.to_str().ok().and_then(|s| s.parse().ok()))
+                .and_then(|value| value.to_str().ok())
+                // only the left-most (client) value of a comma-separated list is used
+                .and_then(|s| s.split(',').next())
+                .and_then(|s| s.trim().parse().ok())
                 .ok_or_else(crate::headers::Error::invalid)?,
         ))
     }
@@ -174,6 +177,12 @@ mod tests {
         ))
     );
 
+    test_header!(
+        test_comma_separated,
+        vec!["id42.example-cdn.com, proxy.internal"],
+        Some(XForwardedHost("id42.example-cdn.com".parse().unwrap()))
+    );
+
     #[test]
     fn test_x_forwarded_host_symmetry_encode() {
         for input in [
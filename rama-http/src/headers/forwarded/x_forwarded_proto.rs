@@ -37,7 +37,10 @@ impl Header for XForwardedProto {
         Ok(XForwardedProto(
             values
                 .next()
-                .and_then(|value| value.to_str().ok().and_then(|s| s.parse().ok()))
+                .and_then(|value| value.to_str().ok())
+                // only the left-most (client) value of a comma-separated list is used
+                .and_then(|s| s.split(',').next())
+                .and_then(|s| s.trim().parse().ok())
                 .ok_or_else(crate::headers::Error::invalid)?,
         ))
     }
@@ -134,6 +137,12 @@ mod tests {
         Some(XForwardedProto(ForwardedProtocol::HTTP))
     );
 
+    test_header!(
+        test_comma_separated,
+        vec!["https,http"],
+        Some(XForwardedProto(ForwardedProtocol::HTTPS))
+    );
+
     #[test]
     fn test_x_forwarded_proto_symmetric_encoder() {
         for input in [ForwardedProtocol::HTTP, ForwardedProtocol::HTTPS] {
@@ -85,7 +85,7 @@ pub use common::Accept;
 mod forwarded;
 #[doc(inline)]
 pub use forwarded::{
-    CFConnectingIp, ClientIp, ForwardHeader, Forwarded, TrueClientIp, Via, XClientIp,
+    CFConnectingIp, ClientIp, ForwardHeader, Forwarded, TrueClientIp, Via, ViaElement, XClientIp,
     XForwardedFor, XForwardedHost, XForwardedProto, XRealIp,
 };
 
@@ -116,7 +116,7 @@
 //! # }
 //! ```
 
-use crate::{Request, Response};
+use crate::{Request, Response, ResponseProvenance};
 use rama_core::{Context, Layer, Service};
 use rama_utils::macros::define_inner_service_accessors;
 use std::future::Future;
@@ -191,7 +191,10 @@ where
     ) -> Result<Self::Response, Self::Error> {
         let (ctx, req) = match self.auth.authorize(ctx, req).await {
             Ok(req) => req,
-            Err(res) => return Ok(res),
+            Err(mut res) => {
+                ResponseProvenance::record(&mut res, "async_require_authorization", "unauthorized");
+                return Ok(res);
+            }
         };
         self.inner.serve(ctx, req).await
     }
@@ -0,0 +1,831 @@
+//! Authorize requests using a pluggable [`AuthorizationPolicy`],
+//! e.g. backed by an external policy engine such as OPA or Cedar.
+//!
+//! The [`AuthorizationPolicyLayer`] asks its policy for a [`Decision`] for each request:
+//!
+//! - [`Decision::Allow`]: the request is forwarded to the inner service;
+//! - [`Decision::AllowWithObligations`]: the request is forwarded with the given
+//!   headers added to it (replacing any existing header with the same name);
+//! - [`Decision::Deny`]: a response with the given status is returned instead.
+//!
+//! A policy which fails to decide (e.g. because the policy engine is unreachable or
+//! did not answer within the configured timeout) results in a `503 Service Unavailable`
+//! response, unless the layer is configured to fail open, in which case the request is allowed.
+//!
+//! Decisions can be cached using a [`DecisionCache`], keyed by a user-supplied
+//! cache key extractor, see [`AuthorizationPolicyLayer::with_decision_cache`].
+//!
+//! Policies are provided for in-process closures and for an [`HttpAuthorizationPolicy`]
+//! which posts the request attributes as a JSON document to an http endpoint.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use rama_core::error::BoxError;
+//! use rama_http::layer::auth::{AuthorizationPolicyLayer, Decision};
+//! use rama_http::{dep::http::request::Parts, Body, Request, Response, StatusCode};
+//!
+//! async fn handle(_req: Request) -> Result<Response, BoxError> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = AuthorizationPolicyLayer::new(|_ctx: &Context<()>, parts: &Parts| {
+//!     let admin = parts.uri.path().starts_with("/admin");
+//!     async move {
+//!         Ok(if admin {
+//!             Decision::deny(StatusCode::FORBIDDEN)
+//!         } else {
+//!             Decision::Allow
+//!         })
+//!     }
+//! })
+//! .with_timeout(Duration::from_millis(100))
+//! .with_decision_cache(
+//!     |_ctx: &Context<()>, parts: &Parts| Some(parts.uri.path().to_owned()),
+//!     Duration::from_secs(30),
+//! )
+//! .layer(service_fn(handle));
+//!
+//! let request = Request::get("/admin").body(Body::empty())?;
+//! let response = service.serve(Context::default(), request).await?;
+//! assert_eq!(response.status(), StatusCode::FORBIDDEN);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    dep::http::request::Parts, header::CONTENT_TYPE, BodyExtractExt, HeaderMap, HeaderName,
+    HeaderValue, Method, Request, Response, ResponseProvenance, StatusCode, Uri,
+};
+use rama_core::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    store::KeyedStateStore,
+    Context, Layer, Service,
+};
+use rama_utils::macros::define_inner_service_accessors;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::BTreeMap, fmt, future::Future, hash::Hash, time::Duration};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The decision made by an [`AuthorizationPolicy`] for a request.
+pub enum Decision {
+    /// Allow the request.
+    Allow,
+    /// Deny the request, responding with the given status.
+    Deny {
+        /// Status of the response returned for the denied request.
+        status: StatusCode,
+        /// Optional reason for denying the request, logged but not exposed.
+        reason: Option<Cow<'static, str>>,
+    },
+    /// Allow the request, adding the given headers to it
+    /// (replacing any existing header with the same name).
+    AllowWithObligations(HeaderMap),
+}
+
+impl Decision {
+    /// Create a [`Decision::Deny`] with the given status and no reason.
+    pub const fn deny(status: StatusCode) -> Self {
+        Self::Deny {
+            status,
+            reason: None,
+        }
+    }
+}
+
+/// A policy deciding whether or not a request is authorized.
+///
+/// Implemented for closures taking the [`Context`] and request [`Parts`]
+/// and returning a (`'static`) future resolving to a `Result<Decision, BoxError>`.
+///
+/// An error means no decision could be made (e.g. the policy engine is down),
+/// which is handled by the [`AuthorizationPolicyLayer`] depending on its fail-open setting.
+pub trait AuthorizationPolicy<State>: Send + Sync + 'static {
+    /// Decide whether or not the request is authorized.
+    fn decide(
+        &self,
+        ctx: &Context<State>,
+        parts: &Parts,
+    ) -> impl Future<Output = Result<Decision, BoxError>> + Send;
+}
+
+impl<State, F, Fut> AuthorizationPolicy<State> for F
+where
+    F: Fn(&Context<State>, &Parts) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Decision, BoxError>> + Send + 'static,
+{
+    fn decide(
+        &self,
+        ctx: &Context<State>,
+        parts: &Parts,
+    ) -> impl Future<Output = Result<Decision, BoxError>> + Send {
+        self(ctx, parts)
+    }
+}
+
+/// Layer that applies [`AuthorizationPolicyService`] which authorizes
+/// requests using an [`AuthorizationPolicy`].
+///
+/// See the [module docs](self) for more details.
+pub struct AuthorizationPolicyLayer<P> {
+    policy: P,
+    fail_open: bool,
+    timeout: Option<Duration>,
+}
+
+impl<P: fmt::Debug> fmt::Debug for AuthorizationPolicyLayer<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthorizationPolicyLayer")
+            .field("policy", &self.policy)
+            .field("fail_open", &self.fail_open)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<P: Clone> Clone for AuthorizationPolicyLayer<P> {
+    fn clone(&self) -> Self {
+        Self {
+            policy: self.policy.clone(),
+            fail_open: self.fail_open,
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<P> AuthorizationPolicyLayer<P> {
+    /// Create a new [`AuthorizationPolicyLayer`] using the given [`AuthorizationPolicy`].
+    ///
+    /// By default it fails closed and no timeout is applied to the policy.
+    pub const fn new(policy: P) -> Self {
+        Self {
+            policy,
+            fail_open: false,
+            timeout: None,
+        }
+    }
+
+    /// Allow requests for which the policy failed to decide,
+    /// instead of responding with a `503 Service Unavailable`.
+    pub fn fail_open(mut self, fail_open: bool) -> Self {
+        self.fail_open = fail_open;
+        self
+    }
+
+    /// Allow requests for which the policy failed to decide,
+    /// instead of responding with a `503 Service Unavailable`.
+    pub fn set_fail_open(&mut self, fail_open: bool) -> &mut Self {
+        self.fail_open = fail_open;
+        self
+    }
+
+    /// Consider the policy as failed in case it did not decide within the given duration.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Consider the policy as failed in case it did not decide within the given duration.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Cache the decisions of the policy for the given time to live,
+    /// keyed by the key returned by the given extractor.
+    ///
+    /// Requests for which the extractor returns `None` are not cached.
+    /// See [`DecisionCache`] for more details.
+    pub fn with_decision_cache<F, K>(
+        self,
+        key_fn: F,
+        ttl: Duration,
+    ) -> AuthorizationPolicyLayer<DecisionCache<P, F, K>> {
+        AuthorizationPolicyLayer {
+            policy: DecisionCache::new(self.policy, key_fn, ttl),
+            fail_open: self.fail_open,
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<S, P: Clone> Layer<S> for AuthorizationPolicyLayer<P> {
+    type Service = AuthorizationPolicyService<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthorizationPolicyService {
+            inner,
+            policy: self.policy.clone(),
+            fail_open: self.fail_open,
+            timeout: self.timeout,
+        }
+    }
+}
+
+/// Middleware which authorizes requests using an [`AuthorizationPolicy`].
+///
+/// See the [module docs](self) for more details.
+pub struct AuthorizationPolicyService<S, P> {
+    inner: S,
+    policy: P,
+    fail_open: bool,
+    timeout: Option<Duration>,
+}
+
+impl<S, P> AuthorizationPolicyService<S, P> {
+    /// Create a new [`AuthorizationPolicyService`] using the given [`AuthorizationPolicy`].
+    ///
+    /// By default it fails closed and no timeout is applied to the policy.
+    pub const fn new(inner: S, policy: P) -> Self {
+        Self {
+            inner,
+            policy,
+            fail_open: false,
+            timeout: None,
+        }
+    }
+
+    /// Allow requests for which the policy failed to decide,
+    /// instead of responding with a `503 Service Unavailable`.
+    pub fn fail_open(mut self, fail_open: bool) -> Self {
+        self.fail_open = fail_open;
+        self
+    }
+
+    /// Allow requests for which the policy failed to decide,
+    /// instead of responding with a `503 Service Unavailable`.
+    pub fn set_fail_open(&mut self, fail_open: bool) -> &mut Self {
+        self.fail_open = fail_open;
+        self
+    }
+
+    /// Consider the policy as failed in case it did not decide within the given duration.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Consider the policy as failed in case it did not decide within the given duration.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug, P: fmt::Debug> fmt::Debug for AuthorizationPolicyService<S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthorizationPolicyService")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .field("fail_open", &self.fail_open)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<S: Clone, P: Clone> Clone for AuthorizationPolicyService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            policy: self.policy.clone(),
+            fail_open: self.fail_open,
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<State, S, P, ReqBody, ResBody> Service<State, Request<ReqBody>>
+    for AuthorizationPolicyService<S, P>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    P: AuthorizationPolicy<State>,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let (mut parts, body) = req.into_parts();
+
+        let result = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.policy.decide(&ctx, &parts))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(OpaqueError::from_display("authorization policy timed out").into())
+                }),
+            None => self.policy.decide(&ctx, &parts).await,
+        };
+
+        let decision = match result {
+            Ok(decision) => decision,
+            Err(err) if self.fail_open => {
+                tracing::warn!(error = %err, "authorization policy failed: fail open");
+                Decision::Allow
+            }
+            Err(err) => {
+                tracing::warn!(error = %err, "authorization policy failed: fail closed");
+                let mut res = status_response(StatusCode::SERVICE_UNAVAILABLE);
+                ResponseProvenance::record(&mut res, "authorization_policy", "policy failed");
+                return Ok(res);
+            }
+        };
+
+        match decision {
+            Decision::Allow => (),
+            Decision::AllowWithObligations(headers) => parts.headers.extend(headers),
+            Decision::Deny { status, reason } => {
+                tracing::debug!(%status, ?reason, "request denied by authorization policy");
+                let mut res = status_response(status);
+                ResponseProvenance::record(
+                    &mut res,
+                    "authorization_policy",
+                    reason.unwrap_or(Cow::Borrowed("denied")),
+                );
+                return Ok(res);
+            }
+        }
+
+        self.inner
+            .serve(ctx, Request::from_parts(parts, body))
+            .await
+    }
+}
+
+fn status_response<B: Default>(status: StatusCode) -> Response<B> {
+    let mut res = Response::new(B::default());
+    *res.status_mut() = status;
+    res
+}
+
+/// An [`AuthorizationPolicy`] which caches the decisions of the wrapped policy,
+/// keyed by a user-supplied cache key extractor.
+///
+/// Only decisions are cached, failures to decide are not.
+/// Clones of the cache share the same cached decisions.
+pub struct DecisionCache<P, F, K> {
+    policy: P,
+    key_fn: F,
+    ttl: Duration,
+    entries: KeyedStateStore<K, Decision>,
+}
+
+impl<P: fmt::Debug, F, K> fmt::Debug for DecisionCache<P, F, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecisionCache")
+            .field("policy", &self.policy)
+            .field("key_fn", &std::any::type_name::<F>())
+            .field("ttl", &self.ttl)
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl<P: Clone, F: Clone, K> Clone for DecisionCache<P, F, K> {
+    fn clone(&self) -> Self {
+        Self {
+            policy: self.policy.clone(),
+            key_fn: self.key_fn.clone(),
+            ttl: self.ttl,
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<P, F, K> DecisionCache<P, F, K> {
+    /// Create a new [`DecisionCache`] caching the decisions of the given policy
+    /// for the given time to live, keyed by the key returned by the given extractor.
+    ///
+    /// Requests for which the extractor returns `None` are not cached.
+    pub fn new(policy: P, key_fn: F, ttl: Duration) -> Self {
+        Self {
+            policy,
+            key_fn,
+            ttl,
+            entries: KeyedStateStore::new().with_ttl(ttl),
+        }
+    }
+
+    /// Set the maximum number of cached decisions, `4096` by default.
+    ///
+    /// The least recently used decisions are evicted when the cache is full.
+    /// Previously cached decisions are dropped.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.entries = KeyedStateStore::with_max_entries(max_entries).with_ttl(self.ttl);
+        self
+    }
+
+    /// Set the maximum number of cached decisions, `4096` by default.
+    ///
+    /// The least recently used decisions are evicted when the cache is full.
+    /// Previously cached decisions are dropped.
+    pub fn set_max_entries(&mut self, max_entries: usize) -> &mut Self {
+        self.entries = KeyedStateStore::with_max_entries(max_entries).with_ttl(self.ttl);
+        self
+    }
+
+    /// Get the [`KeyedStateStore`] used to cache the decisions,
+    /// e.g. to spawn a sweeper or register its metrics.
+    pub fn store(&self) -> &KeyedStateStore<K, Decision> {
+        &self.entries
+    }
+}
+
+impl<State, P, F, K> AuthorizationPolicy<State> for DecisionCache<P, F, K>
+where
+    State: Send + Sync + 'static,
+    P: AuthorizationPolicy<State>,
+    F: Fn(&Context<State>, &Parts) -> Option<K> + Send + Sync + 'static,
+    K: Hash + Eq + Send + Sync + 'static,
+{
+    async fn decide(&self, ctx: &Context<State>, parts: &Parts) -> Result<Decision, BoxError> {
+        let Some(key) = (self.key_fn)(ctx, parts) else {
+            return self.policy.decide(ctx, parts).await;
+        };
+
+        if let Some(decision) = self.entries.get(&key) {
+            return Ok(decision);
+        }
+
+        let decision = self.policy.decide(ctx, parts).await?;
+        self.entries.insert(key, decision.clone());
+        Ok(decision)
+    }
+}
+
+/// An [`AuthorizationPolicy`] which asks an external policy engine for a decision,
+/// by posting a JSON document with the request attributes to an http endpoint.
+///
+/// The document posted has the following shape:
+///
+/// ```json
+/// {
+///     "method": "GET",
+///     "uri": "http://example.com/foo?bar=baz",
+///     "path": "/foo",
+///     "query": "bar=baz",
+///     "version": "HTTP/1.1",
+///     "headers": { "authorization": ["Bearer ..."] }
+/// }
+/// ```
+///
+/// The endpoint is expected to respond with a `2xx` status and a JSON document
+/// of the following shape, where all fields but `allow` are optional:
+///
+/// ```json
+/// {
+///     "allow": false,
+///     "status": 403,
+///     "reason": "not an admin",
+///     "headers": { "x-user-id": "42" }
+/// }
+/// ```
+///
+/// Any other response, as well as an error returned by the client,
+/// is considered a failure to decide.
+///
+/// The request is sent using the given client service (e.g. the `HttpClient` of `rama-http-backend`),
+/// within a new [`Context`] sharing only the state and executor with the authorized request.
+pub struct HttpAuthorizationPolicy<S> {
+    client: S,
+    endpoint: Uri,
+}
+
+impl<S: fmt::Debug> fmt::Debug for HttpAuthorizationPolicy<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpAuthorizationPolicy")
+            .field("client", &self.client)
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for HttpAuthorizationPolicy<S> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            endpoint: self.endpoint.clone(),
+        }
+    }
+}
+
+impl<S> HttpAuthorizationPolicy<S> {
+    /// Create a new [`HttpAuthorizationPolicy`] posting the request attributes
+    /// to the given endpoint using the given client service.
+    pub const fn new(client: S, endpoint: Uri) -> Self {
+        Self { client, endpoint }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PolicyInput<'a> {
+    method: &'a str,
+    uri: String,
+    path: &'a str,
+    query: Option<&'a str>,
+    version: String,
+    headers: BTreeMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> From<&'a Parts> for PolicyInput<'a> {
+    fn from(parts: &'a Parts) -> Self {
+        let mut headers: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (name, value) in parts.headers.iter() {
+            if let Ok(value) = value.to_str() {
+                headers.entry(name.as_str()).or_default().push(value);
+            }
+        }
+        Self {
+            method: parts.method.as_str(),
+            uri: parts.uri.to_string(),
+            path: parts.uri.path(),
+            query: parts.uri.query(),
+            version: format!("{:?}", parts.version),
+            headers,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PolicyOutput {
+    allow: bool,
+    #[serde(default)]
+    status: Option<u16>,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+impl TryFrom<PolicyOutput> for Decision {
+    type Error = OpaqueError;
+
+    fn try_from(output: PolicyOutput) -> Result<Self, Self::Error> {
+        if !output.allow {
+            let status = match output.status {
+                Some(status) => StatusCode::from_u16(status).context("policy deny status")?,
+                None => StatusCode::FORBIDDEN,
+            };
+            return Ok(Decision::Deny {
+                status,
+                reason: output.reason.map(Into::into),
+            });
+        }
+
+        if output.headers.is_empty() {
+            return Ok(Decision::Allow);
+        }
+
+        let mut headers = HeaderMap::with_capacity(output.headers.len());
+        for (name, value) in output.headers {
+            headers.insert(
+                HeaderName::try_from(name).context("policy obligation header name")?,
+                HeaderValue::try_from(value).context("policy obligation header value")?,
+            );
+        }
+        Ok(Decision::AllowWithObligations(headers))
+    }
+}
+
+impl<State, S> AuthorizationPolicy<State> for HttpAuthorizationPolicy<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request, Response = Response, Error: Into<BoxError>>,
+{
+    async fn decide(&self, ctx: &Context<State>, parts: &Parts) -> Result<Decision, BoxError> {
+        let input = serde_json::to_vec(&PolicyInput::from(parts)).context("encode policy input")?;
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.endpoint.clone())
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(input.into())
+            .context("build policy request")?;
+
+        let ctx = Context::new(ctx.state_clone(), ctx.executor().clone());
+        let res = self.client.serve(ctx, req).await.map_err(Into::into)?;
+        if !res.status().is_success() {
+            return Err(OpaqueError::from_display(format!(
+                "authorization policy endpoint responded with status {}",
+                res.status()
+            ))
+            .into());
+        }
+
+        let output: PolicyOutput = res.try_into_json().await.context("decode policy output")?;
+        Ok(Decision::try_from(output)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    async fn echo_header(req: Request) -> Result<Response, Infallible> {
+        let value = req
+            .headers()
+            .get("x-user-id")
+            .map(|value| value.to_str().unwrap().to_owned())
+            .unwrap_or_default();
+        Ok(Response::new(Body::from(value)))
+    }
+
+    fn request(path: &str) -> Request {
+        Request::get(path)
+            .header("x-user-id", "spoofed")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_policy_deny() {
+        let service = AuthorizationPolicyLayer::new(|_: &Context<()>, _: &Parts| async {
+            Ok(Decision::Deny {
+                status: StatusCode::FORBIDDEN,
+                reason: Some("nope".into()),
+            })
+        })
+        .layer(service_fn(echo_header));
+
+        let res = service
+            .serve(Context::default(), request("/"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_policy_obligations() {
+        let service = AuthorizationPolicyLayer::new(|_: &Context<()>, _: &Parts| async {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-user-id", HeaderValue::from_static("42"));
+            Ok(Decision::AllowWithObligations(headers))
+        })
+        .layer(service_fn(echo_header));
+
+        let res = service
+            .serve(Context::default(), request("/"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.into_body().try_into_string().await.unwrap(), "42");
+    }
+
+    #[tokio::test]
+    async fn test_policy_cache_hits() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let policy = {
+            let calls = calls.clone();
+            move |_: &Context<()>, parts: &Parts| {
+                calls.fetch_add(1, Ordering::AcqRel);
+                let allow = parts.uri.path() != "/admin";
+                async move {
+                    Ok(if allow {
+                        Decision::Allow
+                    } else {
+                        Decision::deny(StatusCode::FORBIDDEN)
+                    })
+                }
+            }
+        };
+        let service = AuthorizationPolicyLayer::new(policy)
+            .with_decision_cache(
+                |_: &Context<()>, parts: &Parts| {
+                    (parts.uri.path() != "/uncached").then(|| parts.uri.path().to_owned())
+                },
+                Duration::from_secs(60),
+            )
+            .layer(service_fn(echo_header));
+
+        for (path, status, total_calls) in [
+            ("/", StatusCode::OK, 1),
+            ("/", StatusCode::OK, 1),
+            ("/admin", StatusCode::FORBIDDEN, 2),
+            ("/admin", StatusCode::FORBIDDEN, 2),
+            ("/uncached", StatusCode::OK, 3),
+            ("/uncached", StatusCode::OK, 4),
+        ] {
+            let res = service
+                .serve(Context::default(), request(path))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status, "{path}");
+            assert_eq!(calls.load(Ordering::Acquire), total_calls, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_policy_cache_expires() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let policy = {
+            let calls = calls.clone();
+            move |_: &Context<()>, _: &Parts| {
+                calls.fetch_add(1, Ordering::AcqRel);
+                async { Ok(Decision::Allow) }
+            }
+        };
+        let cache = DecisionCache::new(
+            policy,
+            |_: &Context<()>, _: &Parts| Some(()),
+            Duration::from_millis(10),
+        );
+
+        let (parts, _) = request("/").into_parts();
+        let ctx = Context::default();
+        cache.decide(&ctx, &parts).await.unwrap();
+        cache.decide(&ctx, &parts).await.unwrap();
+        assert_eq!(calls.load(Ordering::Acquire), 1);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        cache.decide(&ctx, &parts).await.unwrap();
+        assert_eq!(calls.load(Ordering::Acquire), 2);
+    }
+
+    #[tokio::test]
+    async fn test_policy_timeout_fail_closed() {
+        let slow_policy = |_: &Context<()>, _: &Parts| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(Decision::Allow)
+        };
+
+        let service = AuthorizationPolicyLayer::new(slow_policy)
+            .with_timeout(Duration::from_millis(10))
+            .layer(service_fn(echo_header));
+        let res = service
+            .serve(Context::default(), request("/"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let service = AuthorizationPolicyLayer::new(slow_policy)
+            .with_timeout(Duration::from_millis(10))
+            .fail_open(true)
+            .layer(service_fn(echo_header));
+        let res = service
+            .serve(Context::default(), request("/"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_http_policy() {
+        let engine = service_fn(|req: Request| async move {
+            assert_eq!(req.method(), Method::POST);
+            assert_eq!(req.uri(), "http://policy.local/v1/decide");
+            let input: serde_json::Value = req.into_body().try_into_json().await.unwrap();
+            assert_eq!(input["headers"]["x-user-id"][0], "spoofed");
+            let output = match input["path"].as_str().unwrap() {
+                "/admin" => r##"{"allow": false, "status": 401, "reason": "not an admin"}"##,
+                "/down" => return Ok(Response::builder().status(500).body(Body::empty()).unwrap()),
+                _ => r##"{"allow": true, "headers": {"x-user-id": "42"}}"##,
+            };
+            Ok::<_, Infallible>(Response::new(Body::from(output)))
+        });
+
+        let service = AuthorizationPolicyLayer::new(HttpAuthorizationPolicy::new(
+            engine,
+            Uri::from_static("http://policy.local/v1/decide"),
+        ))
+        .layer(service_fn(echo_header));
+
+        let res = service
+            .serve(Context::default(), request("/"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.into_body().try_into_string().await.unwrap(), "42");
+
+        let res = service
+            .serve(Context::default(), request("/admin"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = service
+            .serve(Context::default(), request("/down"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
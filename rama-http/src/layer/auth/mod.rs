@@ -2,6 +2,7 @@
 
 pub mod add_authorization;
 pub mod async_require_authorization;
+pub mod authorization_policy;
 pub mod require_authorization;
 
 #[doc(inline)]
@@ -10,4 +11,8 @@ pub use self::{
     async_require_authorization::{
         AsyncAuthorizeRequest, AsyncRequireAuthorization, AsyncRequireAuthorizationLayer,
     },
+    authorization_policy::{
+        AuthorizationPolicy, AuthorizationPolicyLayer, AuthorizationPolicyService, Decision,
+        DecisionCache, HttpAuthorizationPolicy,
+    },
 };
@@ -60,7 +60,7 @@ use crate::layer::validate_request::{
 };
 use crate::{
     header::{self, HeaderValue},
-    Request, Response, StatusCode,
+    Request, Response, ResponseProvenance, StatusCode,
 };
 use rama_core::Context;
 
@@ -222,6 +222,11 @@ where
             _ => {
                 let mut res = Response::new(ResBody::default());
                 *res.status_mut() = StatusCode::UNAUTHORIZED;
+                ResponseProvenance::record(
+                    &mut res,
+                    "require_authorization",
+                    "bearer: unauthorized",
+                );
                 Err(res)
             }
         }
@@ -292,6 +297,11 @@ where
                 *res.status_mut() = StatusCode::UNAUTHORIZED;
                 res.headers_mut()
                     .insert(header::WWW_AUTHENTICATE, "Basic".parse().unwrap());
+                ResponseProvenance::record(
+                    &mut res,
+                    "require_authorization",
+                    "basic: unauthorized",
+                );
                 Err(res)
             }
         }
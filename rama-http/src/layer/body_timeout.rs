@@ -0,0 +1,609 @@
+//! Apply idle and/or total timeouts to the transfer of request and response bodies.
+//!
+//! Contrary to the [`timeout`] middleware, which only covers the time until a response
+//! is produced, these timeouts apply while the body itself is being transferred.
+//! A body that times out yields a [`BodyTimeout`] error, which reports how many
+//! bytes were transferred prior to the timeout, e.g. for diagnostics
+//! or to decide whether a partial transfer can be resumed.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//!
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use rama_http::layer::body_timeout::RequestBodyTimeoutLayer;
+//! use rama_http::{Body, Request, Response};
+//!
+//! async fn handle(_: Request) -> Result<Response, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = (
+//!     // fail request bodies which stall for more than 10 seconds,
+//!     // or which take longer than a minute to be received
+//!     RequestBodyTimeoutLayer::idle(Duration::from_secs(10))
+//!         .with_total_timeout(Duration::from_secs(60)),
+//! ).layer(service_fn(handle));
+//!
+//! svc.serve(Context::default(), Request::new(Body::empty())).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`timeout`]: crate::layer::timeout
+
+use crate::dep::http_body::{Frame, SizeHint};
+use crate::{Body, Request, Response};
+use bytes::Bytes;
+use futures_lite::ready;
+use pin_project_lite::pin_project;
+use rama_core::error::BoxError;
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The kind of timeout that caused a [`BodyTimeout`].
+pub enum BodyTimeoutKind {
+    /// No bytes were transferred for the configured idle timeout.
+    Idle,
+    /// The body was not transferred completely within the configured total timeout.
+    Total,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Error returned by a [`TimeoutBody`] in case its transfer timed out.
+pub struct BodyTimeout {
+    transferred: usize,
+    kind: BodyTimeoutKind,
+}
+
+impl BodyTimeout {
+    /// Get the number of body (data) bytes transferred prior to the timeout.
+    pub fn transferred(&self) -> usize {
+        self.transferred
+    }
+
+    /// Get the [`BodyTimeoutKind`] of this timeout.
+    pub fn kind(&self) -> BodyTimeoutKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for BodyTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            BodyTimeoutKind::Idle => write!(
+                f,
+                "body idle timeout elapsed after {} bytes transferred",
+                self.transferred
+            ),
+            BodyTimeoutKind::Total => write!(
+                f,
+                "body total timeout elapsed after {} bytes transferred",
+                self.transferred
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BodyTimeout {}
+
+pin_project! {
+    /// A body wrapper which fails with a [`BodyTimeout`] error once no data
+    /// was transferred for the configured idle timeout, or once the body
+    /// was not transferred completely within the configured total timeout.
+    ///
+    /// The total timeout starts when the [`TimeoutBody`] is created.
+    pub struct TimeoutBody<B> {
+        #[pin]
+        inner: B,
+        idle_timeout: Option<Duration>,
+        idle_sleep: Option<Pin<Box<Sleep>>>,
+        total_sleep: Option<Pin<Box<Sleep>>>,
+        transferred: usize,
+        timed_out: bool,
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for TimeoutBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeoutBody")
+            .field("inner", &self.inner)
+            .field("idle_timeout", &self.idle_timeout)
+            .field(
+                "total_deadline",
+                &self.total_sleep.as_ref().map(|sleep| sleep.deadline()),
+            )
+            .field("transferred", &self.transferred)
+            .field("timed_out", &self.timed_out)
+            .finish()
+    }
+}
+
+impl<B> TimeoutBody<B> {
+    /// Create a new [`TimeoutBody`] wrapping the given body,
+    /// with the given (optional) idle and total timeouts.
+    pub fn new(inner: B, idle_timeout: Option<Duration>, total_timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            idle_timeout,
+            idle_sleep: None,
+            total_sleep: total_timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+            transferred: 0,
+            timed_out: false,
+        }
+    }
+
+    /// Get the number of body (data) bytes transferred so far.
+    pub fn transferred(&self) -> usize {
+        self.transferred
+    }
+
+    /// Consume the [`TimeoutBody`] and return the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> http_body::Body for TimeoutBody<B>
+where
+    B: http_body::Body<Error: Into<BoxError>>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if *this.timed_out {
+            return Poll::Ready(None);
+        }
+
+        if let Poll::Ready(result) = this.inner.poll_frame(cx) {
+            if let Some(Ok(frame)) = &result {
+                if let Some(data) = frame.data_ref() {
+                    *this.transferred += bytes::Buf::remaining(data);
+                }
+                // reset lazily, on the next pending poll
+                *this.idle_sleep = None;
+            }
+            return Poll::Ready(result.map(|result| result.map_err(Into::into)));
+        }
+
+        let kind = if this
+            .total_sleep
+            .as_mut()
+            .is_some_and(|sleep| sleep.as_mut().poll(cx).is_ready())
+        {
+            BodyTimeoutKind::Total
+        } else if let Some(timeout) = *this.idle_timeout {
+            let sleep = this.idle_sleep.get_or_insert_with(|| {
+                Box::pin(tokio::time::sleep_until(Instant::now() + timeout))
+            });
+            ready!(sleep.as_mut().poll(cx));
+            BodyTimeoutKind::Idle
+        } else {
+            return Poll::Pending;
+        };
+
+        *this.timed_out = true;
+        Poll::Ready(Some(Err(BodyTimeout {
+            transferred: *this.transferred,
+            kind,
+        }
+        .into())))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.timed_out || self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Layer that applies the [`RequestBodyTimeout`] middleware,
+/// wrapping request bodies in a [`TimeoutBody`].
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone, Default)]
+pub struct RequestBodyTimeoutLayer {
+    idle_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+}
+
+impl RequestBodyTimeoutLayer {
+    /// Create a new [`RequestBodyTimeoutLayer`] without any timeouts.
+    pub const fn new() -> Self {
+        Self {
+            idle_timeout: None,
+            total_timeout: None,
+        }
+    }
+
+    /// Create a new [`RequestBodyTimeoutLayer`] with the given idle timeout.
+    pub const fn idle(timeout: Duration) -> Self {
+        Self {
+            idle_timeout: Some(timeout),
+            total_timeout: None,
+        }
+    }
+
+    /// Create a new [`RequestBodyTimeoutLayer`] with the given total timeout.
+    pub const fn total(timeout: Duration) -> Self {
+        Self {
+            idle_timeout: None,
+            total_timeout: Some(timeout),
+        }
+    }
+
+    /// Set the duration after which a stalled request body times out.
+    pub const fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the duration after which a stalled request body times out.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the duration within which the request body has to be transferred completely.
+    pub const fn with_total_timeout(mut self, timeout: Duration) -> Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the duration within which the request body has to be transferred completely.
+    pub fn set_total_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+}
+
+impl<S> Layer<S> for RequestBodyTimeoutLayer {
+    type Service = RequestBodyTimeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestBodyTimeout::new(inner, self.idle_timeout, self.total_timeout)
+    }
+}
+
+/// Middleware which wraps request bodies in a [`TimeoutBody`].
+///
+/// See the [module docs](self) for an example.
+pub struct RequestBodyTimeout<S> {
+    inner: S,
+    idle_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+}
+
+impl<S> RequestBodyTimeout<S> {
+    /// Create a new [`RequestBodyTimeout`] with the given (optional) idle and total timeouts.
+    pub const fn new(
+        inner: S,
+        idle_timeout: Option<Duration>,
+        total_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            inner,
+            idle_timeout,
+            total_timeout,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for RequestBodyTimeout<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestBodyTimeout")
+            .field("inner", &self.inner)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("total_timeout", &self.total_timeout)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RequestBodyTimeout<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            idle_timeout: self.idle_timeout,
+            total_timeout: self.total_timeout,
+        }
+    }
+}
+
+/// Layer that applies the [`ResponseBodyTimeout`] middleware,
+/// wrapping response bodies in a [`TimeoutBody`].
+#[derive(Debug, Clone, Default)]
+pub struct ResponseBodyTimeoutLayer {
+    idle_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+}
+
+impl ResponseBodyTimeoutLayer {
+    /// Create a new [`ResponseBodyTimeoutLayer`] without any timeouts.
+    pub const fn new() -> Self {
+        Self {
+            idle_timeout: None,
+            total_timeout: None,
+        }
+    }
+
+    /// Create a new [`ResponseBodyTimeoutLayer`] with the given idle timeout.
+    pub const fn idle(timeout: Duration) -> Self {
+        Self {
+            idle_timeout: Some(timeout),
+            total_timeout: None,
+        }
+    }
+
+    /// Create a new [`ResponseBodyTimeoutLayer`] with the given total timeout.
+    pub const fn total(timeout: Duration) -> Self {
+        Self {
+            idle_timeout: None,
+            total_timeout: Some(timeout),
+        }
+    }
+
+    /// Set the duration after which a stalled response body times out.
+    pub const fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the duration after which a stalled response body times out.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the duration within which the response body has to be transferred completely.
+    pub const fn with_total_timeout(mut self, timeout: Duration) -> Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the duration within which the response body has to be transferred completely.
+    pub fn set_total_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+}
+
+impl<S> Layer<S> for ResponseBodyTimeoutLayer {
+    type Service = ResponseBodyTimeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseBodyTimeout::new(inner, self.idle_timeout, self.total_timeout)
+    }
+}
+
+/// Middleware which wraps response bodies in a [`TimeoutBody`].
+///
+/// The total timeout starts once the response is returned by the inner service.
+pub struct ResponseBodyTimeout<S> {
+    inner: S,
+    idle_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+}
+
+impl<S> ResponseBodyTimeout<S> {
+    /// Create a new [`ResponseBodyTimeout`] with the given (optional) idle and total timeouts.
+    pub const fn new(
+        inner: S,
+        idle_timeout: Option<Duration>,
+        total_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            inner,
+            idle_timeout,
+            total_timeout,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for ResponseBodyTimeout<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseBodyTimeout")
+            .field("inner", &self.inner)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("total_timeout", &self.total_timeout)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for ResponseBodyTimeout<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            idle_timeout: self.idle_timeout,
+            total_timeout: self.total_timeout,
+        }
+    }
+}
+
+impl<S, State, ReqBody> Service<State, Request<ReqBody>> for RequestBodyTimeout<S>
+where
+    S: Service<State, Request<Body>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let req = req.map(|body| {
+            Body::new(TimeoutBody::new(
+                body,
+                self.idle_timeout,
+                self.total_timeout,
+            ))
+        });
+        self.inner.serve(ctx, req).await
+    }
+}
+
+impl<S, State, Req, ResBody> Service<State, Req> for ResponseBodyTimeout<S>
+where
+    S: Service<State, Req, Response = Response<ResBody>>,
+    State: Clone + Send + Sync + 'static,
+    Req: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn serve(&self, ctx: Context<State>, req: Req) -> Result<Self::Response, Self::Error> {
+        let res = self.inner.serve(ctx, req).await?;
+        Ok(res.map(|body| {
+            Body::new(TimeoutBody::new(
+                body,
+                self.idle_timeout,
+                self.total_timeout,
+            ))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::{BodyExt, StreamBody};
+    use futures_lite::StreamExt;
+    use rama_core::error::OpaqueError;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    /// Body which yields the given chunks, each after the given delay,
+    /// and then stalls forever.
+    fn stalling_body(chunks: &'static [&'static str], delay: Duration) -> Body {
+        let stream = futures_lite::stream::iter(chunks.iter())
+            .then(move |chunk| async move {
+                tokio::time::sleep(delay).await;
+                Ok::<_, Infallible>(Frame::data(Bytes::from_static(chunk.as_bytes())))
+            })
+            .chain(futures_lite::stream::pending());
+        Body::new(StreamBody::new(stream))
+    }
+
+    async fn collect_until_error(mut body: Body) -> (usize, OpaqueError) {
+        let mut received = 0;
+        loop {
+            match body.frame().await {
+                Some(Ok(frame)) => received += frame.into_data().unwrap().len(),
+                Some(Err(err)) => return (received, err),
+                None => panic!("unexpected end of body"),
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_reports_transferred() {
+        let body = TimeoutBody::new(
+            stalling_body(&["foo", "barbaz"], Duration::from_secs(3)),
+            Some(Duration::from_secs(5)),
+            None,
+        );
+
+        let start = Instant::now();
+        let (received, err) = collect_until_error(Body::new(body)).await;
+        let err = err.downcast_ref::<BodyTimeout>().unwrap();
+        assert_eq!(err.kind(), BodyTimeoutKind::Idle);
+        assert_eq!(err.transferred(), 9);
+        assert_eq!(err.transferred(), received);
+        // 2 chunks of 3s each, followed by 5s of inactivity
+        assert_eq!(start.elapsed(), Duration::from_secs(11));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_total_timeout_reports_transferred() {
+        let body = TimeoutBody::new(
+            stalling_body(&["foo", "bar", "baz", "qux"], Duration::from_secs(3)),
+            Some(Duration::from_secs(5)),
+            Some(Duration::from_secs(10)),
+        );
+
+        let start = Instant::now();
+        let (received, err) = collect_until_error(Body::new(body)).await;
+        let err = err.downcast_ref::<BodyTimeout>().unwrap();
+        assert_eq!(err.kind(), BodyTimeoutKind::Total);
+        assert_eq!(err.transferred(), 9);
+        assert_eq!(err.transferred(), received);
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_body_within_timeouts() {
+        let body = TimeoutBody::new(
+            Body::from("Hello, World!"),
+            Some(Duration::from_secs(5)),
+            Some(Duration::from_secs(10)),
+        );
+        let bytes = body.collect().await.unwrap().to_bytes();
+        assert_eq!(bytes, "Hello, World!");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_request_body_timeout_layer() {
+        let svc = RequestBodyTimeoutLayer::idle(Duration::from_secs(5)).layer(service_fn(
+            |req: Request| async move {
+                let (received, err) = collect_until_error(req.into_body()).await;
+                Ok::<_, Infallible>((received, err.downcast::<BodyTimeout>().unwrap()))
+            },
+        ));
+
+        let req = Request::new(stalling_body(&["foo"], Duration::from_secs(1)));
+        let (received, err) = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(received, 3);
+        assert_eq!(err.kind(), BodyTimeoutKind::Idle);
+        assert_eq!(err.transferred(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_response_body_timeout_layer() {
+        let svc = ResponseBodyTimeoutLayer::total(Duration::from_secs(5)).layer(service_fn(
+            |_req: Request| async move {
+                Ok::<_, Infallible>(Response::new(stalling_body(
+                    &["foo", "bar"],
+                    Duration::from_secs(2),
+                )))
+            },
+        ));
+
+        let res = svc
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        let (received, err) = collect_until_error(res.into_body()).await;
+        let err = err.downcast_ref::<BodyTimeout>().unwrap();
+        assert_eq!(err.kind(), BodyTimeoutKind::Total);
+        assert_eq!(err.transferred(), 6);
+        assert_eq!(received, 6);
+    }
+}
@@ -0,0 +1,577 @@
+//! Middleware to support byte-range requests when proxying to an upstream server,
+//! as is typically done by a reverse proxy.
+//!
+//! The `Range` header of requests is passed through as-is, such that upstream
+//! servers supporting range requests reply with `206 Partial Content` themselves.
+//!
+//! Upstream servers that do not support range requests reply with the full (`200 OK`)
+//! response instead. In case synthesizing ranges is enabled, such a response is converted
+//! into the requested `206 Partial Content` response, by skipping and limiting the body
+//! while it is streamed. The upstream body is no longer read (and dropped) once the range
+//! is satisfied. Synthesizing a range requires the upstream response to have a `Content-Length`.
+//!
+//! Multi-range requests are never synthesized. Depending on the configured
+//! [`MultiRangeMode`], such requests are either declined with
+//! `416 Range Not Satisfiable` or answered with the full response.
+//!
+//! # Example
+//!
+//! ```
+//! use std::convert::Infallible;
+//! use rama_core::error::BoxError;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use rama_http::dep::http_body_util::BodyExt;
+//! use rama_http::layer::byte_range::ByteRangeLayer;
+//! use rama_http::{header, Body, Request, Response, StatusCode};
+//!
+//! async fn upstream(_req: Request) -> Result<Response, Infallible> {
+//!     Ok(Response::builder()
+//!         .header(header::CONTENT_LENGTH, 13)
+//!         .body(Body::from("Hello, World!"))
+//!         .unwrap())
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let svc = ByteRangeLayer::new()
+//!     .with_synthesize_ranges(true)
+//!     .layer(service_fn(upstream));
+//!
+//! let req = Request::builder()
+//!     .header(header::RANGE, "bytes=7-11")
+//!     .body(Body::empty())?;
+//! let res = svc.serve(Context::default(), req).await?;
+//!
+//! assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+//! assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 7-11/13");
+//! assert_eq!(res.into_body().collect().await?.to_bytes(), "World");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dep::http_body::{Frame, SizeHint};
+use crate::{header, Body, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use bytes::Bytes;
+use rama_core::error::{BoxError, OpaqueError};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    ops::RangeInclusive,
+    pin::Pin,
+    task::{ready, Context as TaskContext, Poll},
+};
+
+/// Defines how multi-range requests are handled by [`ByteRange`],
+/// in case the upstream server replied with the full response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MultiRangeMode {
+    /// Reply with the full (`200 OK`) response.
+    #[default]
+    Full,
+    /// Decline the request with `416 Range Not Satisfiable`.
+    Decline,
+}
+
+/// Layer that applies [`ByteRange`] which adds byte-range request
+/// support for proxied upstream responses.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct ByteRangeLayer {
+    synthesize_ranges: bool,
+    multi_range_mode: MultiRangeMode,
+}
+
+impl ByteRangeLayer {
+    /// Create a new [`ByteRangeLayer`], passing through ranges without synthesizing them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether or not a full (`200 OK`) upstream response to a range request
+    /// is converted into the requested `206 Partial Content` response.
+    pub fn with_synthesize_ranges(mut self, synthesize: bool) -> Self {
+        self.synthesize_ranges = synthesize;
+        self
+    }
+
+    /// Set whether or not a full (`200 OK`) upstream response to a range request
+    /// is converted into the requested `206 Partial Content` response.
+    pub fn set_synthesize_ranges(&mut self, synthesize: bool) -> &mut Self {
+        self.synthesize_ranges = synthesize;
+        self
+    }
+
+    /// Set the [`MultiRangeMode`] used for multi-range requests
+    /// answered by the upstream server with the full response.
+    pub fn with_multi_range_mode(mut self, mode: MultiRangeMode) -> Self {
+        self.multi_range_mode = mode;
+        self
+    }
+
+    /// Set the [`MultiRangeMode`] used for multi-range requests
+    /// answered by the upstream server with the full response.
+    pub fn set_multi_range_mode(&mut self, mode: MultiRangeMode) -> &mut Self {
+        self.multi_range_mode = mode;
+        self
+    }
+}
+
+impl<S> Layer<S> for ByteRangeLayer {
+    type Service = ByteRange<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ByteRange {
+            inner,
+            synthesize_ranges: self.synthesize_ranges,
+            multi_range_mode: self.multi_range_mode,
+        }
+    }
+}
+
+/// Middleware adding byte-range request support for proxied upstream responses.
+///
+/// See the [module docs](self) for more details.
+pub struct ByteRange<S> {
+    inner: S,
+    synthesize_ranges: bool,
+    multi_range_mode: MultiRangeMode,
+}
+
+impl<S> ByteRange<S> {
+    /// Create a new [`ByteRange`], passing through ranges without synthesizing them.
+    ///
+    /// Use a [`ByteRangeLayer`] in order to configure the middleware.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            synthesize_ranges: false,
+            multi_range_mode: MultiRangeMode::default(),
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for ByteRange<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ByteRange")
+            .field("inner", &self.inner)
+            .field("synthesize_ranges", &self.synthesize_ranges)
+            .field("multi_range_mode", &self.multi_range_mode)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for ByteRange<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            synthesize_ranges: self.synthesize_ranges,
+            multi_range_mode: self.multi_range_mode,
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for ByteRange<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let range_request = if self.synthesize_ranges && req.method() == Method::GET {
+            req.headers()
+                .get(header::RANGE)
+                .and_then(|v| v.to_str().ok())
+                .map(|range| RangeRequest {
+                    range: range.to_owned(),
+                    if_range: req.headers().get(header::IF_RANGE).cloned(),
+                })
+        } else {
+            None
+        };
+
+        let res = self.inner.serve(ctx, req).await?;
+
+        match range_request {
+            Some(range_request) if res.status() == StatusCode::OK => {
+                Ok(self.synthesize_range(range_request, res))
+            }
+            _ => Ok(res.map(Body::new)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RangeRequest {
+    range: String,
+    if_range: Option<HeaderValue>,
+}
+
+impl<S> ByteRange<S> {
+    fn synthesize_range<B>(&self, range_request: RangeRequest, res: Response<B>) -> Response
+    where
+        B: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+    {
+        if let Some(if_range) = range_request.if_range.as_ref() {
+            if !if_range_matches(if_range, res.headers()) {
+                tracing::trace!("byte range: if-range does not match: serve full response");
+                return res.map(Body::new);
+            }
+        }
+
+        let Some(size) = res
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+        else {
+            tracing::trace!("byte range: unknown content length: serve full response");
+            return res.map(Body::new);
+        };
+
+        let ranges = match http_range_header::parse_range_header(&range_request.range)
+            .and_then(|ranges| ranges.validate(size))
+        {
+            Ok(ranges) => ranges,
+            Err(err) => {
+                tracing::trace!(?err, "byte range: unsatisfiable range");
+                return range_not_satisfiable(size);
+            }
+        };
+
+        let range = match ranges.as_slice() {
+            [range] => range.clone(),
+            _ => {
+                return match self.multi_range_mode {
+                    MultiRangeMode::Full => {
+                        tracing::trace!("byte range: multi-range request: serve full response");
+                        res.map(Body::new)
+                    }
+                    MultiRangeMode::Decline => {
+                        tracing::trace!("byte range: multi-range request: decline");
+                        range_not_satisfiable(size)
+                    }
+                };
+            }
+        };
+
+        tracing::trace!(?range, size, "byte range: synthesize partial content");
+
+        let (mut parts, body) = res.into_parts();
+        parts.status = StatusCode::PARTIAL_CONTENT;
+        parts.headers.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::try_from(format!("bytes {}-{}/{}", range.start(), range.end(), size))
+                .expect("content range to be a valid header value"),
+        );
+        parts
+            .headers
+            .insert(header::CONTENT_LENGTH, range_len(&range).into());
+        Response::from_parts(parts, Body::new(RangeBody::new(Body::new(body), &range)))
+    }
+}
+
+fn if_range_matches(if_range: &HeaderValue, headers: &HeaderMap) -> bool {
+    let Ok(if_range) = if_range.to_str() else {
+        return false;
+    };
+    if if_range.starts_with('"') {
+        // only strong entity tags are allowed to match
+        headers
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|etag| etag == if_range)
+    } else {
+        headers
+            .get(header::LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|last_modified| last_modified == if_range)
+    }
+}
+
+fn range_not_satisfiable(size: u64) -> Response {
+    Response::builder()
+        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+        .body(Body::empty())
+        .expect("416 response to be valid")
+}
+
+fn range_len(range: &RangeInclusive<u64>) -> u64 {
+    range.end() - range.start() + 1
+}
+
+/// Body which only yields the bytes of the inner body within the given range,
+/// dropping the inner body as soon as the range is satisfied.
+struct RangeBody {
+    inner: Option<Body>,
+    skip: u64,
+    remaining: u64,
+}
+
+impl RangeBody {
+    fn new(inner: Body, range: &RangeInclusive<u64>) -> Self {
+        Self {
+            inner: Some(inner),
+            skip: *range.start(),
+            remaining: range_len(range),
+        }
+    }
+}
+
+impl http_body::Body for RangeBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            if this.remaining == 0 {
+                // range satisfied: stop reading the inner body
+                this.inner = None;
+                return Poll::Ready(None);
+            }
+            let Some(inner) = this.inner.as_mut() else {
+                return Poll::Ready(None);
+            };
+
+            let frame = match ready!(Pin::new(inner).poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => {
+                    this.inner = None;
+                    return Poll::Ready(Some(Err(OpaqueError::from_display(
+                        "upstream body ended before the requested range was satisfied",
+                    )
+                    .into())));
+                }
+            };
+            let Ok(mut data) = frame.into_data() else {
+                // trailers are not part of the requested range
+                continue;
+            };
+
+            let len = data.len() as u64;
+            if this.skip >= len {
+                this.skip -= len;
+                continue;
+            }
+            let data = data.split_off(this.skip as usize);
+            this.skip = 0;
+
+            let data = if data.len() as u64 > this.remaining {
+                data.slice(..this.remaining as usize)
+            } else {
+                data
+            };
+            this.remaining -= data.len() as u64;
+            if !data.is_empty() {
+                return Poll::Ready(Some(Ok(Frame::data(data))));
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0 || self.inner.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::{BodyExt, StreamBody};
+    use futures_lite::StreamExt;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    const CONTENT: &str = "Hello, World!";
+
+    fn upstream_response() -> Response {
+        Response::builder()
+            .header(header::CONTENT_LENGTH, CONTENT.len())
+            .header(header::ETAG, "\"foo\"")
+            .body(Body::from(CONTENT))
+            .unwrap()
+    }
+
+    async fn full_upstream(_req: Request) -> Result<Response, Infallible> {
+        Ok(upstream_response())
+    }
+
+    async fn serve<S>(svc: S, range: &'static str) -> (StatusCode, HeaderMap, Bytes)
+    where
+        S: Service<(), Request, Response = Response, Error = Infallible>,
+    {
+        let req = Request::builder()
+            .header(header::RANGE, range)
+            .body(Body::empty())
+            .unwrap();
+        let (parts, body) = svc
+            .serve(Context::default(), req)
+            .await
+            .unwrap()
+            .into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        (parts.status, parts.headers, body)
+    }
+
+    #[tokio::test]
+    async fn test_byte_range_upstream_supported() {
+        let svc = ByteRangeLayer::new()
+            .with_synthesize_ranges(true)
+            .layer(service_fn(|req: Request| async move {
+                assert_eq!(req.headers()[header::RANGE], "bytes=0-4");
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .header(header::CONTENT_RANGE, "bytes 0-4/13")
+                        .body(Body::from("Hello"))
+                        .unwrap(),
+                )
+            }));
+
+        let (status, headers, body) = serve(svc, "bytes=0-4").await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes 0-4/13");
+        assert_eq!(body, "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_byte_range_passthrough_by_default() {
+        let svc = ByteRangeLayer::new().layer(service_fn(full_upstream));
+        let (status, headers, body) = serve(svc, "bytes=0-4").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key(header::CONTENT_RANGE));
+        assert_eq!(body, CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_byte_range_synthesized() {
+        let svc = ByteRangeLayer::new()
+            .with_synthesize_ranges(true)
+            .layer(service_fn(full_upstream));
+
+        for (range, expected_range, expected_body) in [
+            ("bytes=0-4", "bytes 0-4/13", "Hello"),
+            ("bytes=7-", "bytes 7-12/13", "World!"),
+            ("bytes=-6", "bytes 7-12/13", "World!"),
+            ("bytes=7-100", "bytes 7-12/13", "World!"),
+        ] {
+            let (status, headers, body) = serve(svc.clone(), range).await;
+            assert_eq!(status, StatusCode::PARTIAL_CONTENT, "range: {range}");
+            assert_eq!(headers[header::CONTENT_RANGE], expected_range);
+            assert_eq!(
+                headers[header::CONTENT_LENGTH],
+                expected_body.len().to_string()
+            );
+            assert_eq!(body, expected_body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_byte_range_synthesized_stops_reading_upstream() {
+        let polled_too_far = Arc::new(AtomicBool::new(false));
+        let svc = ByteRangeLayer::new()
+            .with_synthesize_ranges(true)
+            .layer(service_fn({
+                let polled_too_far = polled_too_far.clone();
+                move |_req: Request| {
+                    let polled_too_far = polled_too_far.clone();
+                    async move {
+                        let chunks = ["Hel", "lo, ", "World!"].map(|chunk| {
+                            Ok::<_, Infallible>(Frame::data(Bytes::from_static(chunk.as_bytes())))
+                        });
+                        let stream = futures_lite::stream::iter(chunks).chain(
+                            futures_lite::stream::poll_fn(move |_| {
+                                polled_too_far.store(true, Ordering::SeqCst);
+                                Poll::Ready(None)
+                            }),
+                        );
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .header(header::CONTENT_LENGTH, 100)
+                                .body(Body::new(StreamBody::new(stream)))
+                                .unwrap(),
+                        )
+                    }
+                }
+            }));
+
+        let (status, headers, body) = serve(svc, "bytes=2-8").await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes 2-8/100");
+        assert_eq!(body, "llo, Wo");
+        assert!(!polled_too_far.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_byte_range_synthesized_unsatisfiable() {
+        let svc = ByteRangeLayer::new()
+            .with_synthesize_ranges(true)
+            .layer(service_fn(full_upstream));
+
+        let (status, headers, _) = serve(svc, "bytes=20-30").await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes */13");
+    }
+
+    #[tokio::test]
+    async fn test_byte_range_synthesized_multi_range() {
+        let svc = ByteRangeLayer::new()
+            .with_synthesize_ranges(true)
+            .layer(service_fn(full_upstream));
+        let (status, _, body) = serve(svc, "bytes=0-1,4-5").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, CONTENT);
+
+        let svc = ByteRangeLayer::new()
+            .with_synthesize_ranges(true)
+            .with_multi_range_mode(MultiRangeMode::Decline)
+            .layer(service_fn(full_upstream));
+        let (status, headers, _) = serve(svc, "bytes=0-1,4-5").await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes */13");
+    }
+
+    #[tokio::test]
+    async fn test_byte_range_synthesized_if_range() {
+        let svc = ByteRangeLayer::new()
+            .with_synthesize_ranges(true)
+            .layer(service_fn(full_upstream));
+
+        for (if_range, expected_status) in [
+            ("\"foo\"", StatusCode::PARTIAL_CONTENT),
+            ("\"bar\"", StatusCode::OK),
+            ("Wed, 21 Oct 2015 07:28:00 GMT", StatusCode::OK),
+        ] {
+            let req = Request::builder()
+                .header(header::RANGE, "bytes=0-4")
+                .header(header::IF_RANGE, if_range)
+                .body(Body::empty())
+                .unwrap();
+            let res = svc.serve(Context::default(), req).await.unwrap();
+            assert_eq!(res.status(), expected_status, "if-range: {if_range}");
+        }
+    }
+}
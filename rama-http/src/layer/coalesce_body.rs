@@ -0,0 +1,465 @@
+//! Coalesce small data frames of response bodies into larger ones.
+//!
+//! Handlers which produce a body as many tiny chunks cause the server
+//! to write each of them separately, resulting in many small tcp segments
+//! and tls records. The [`CoalesceResponseBodyLayer`] buffers such chunks
+//! until a flush threshold is reached or a maximum delay elapsed,
+//! trading a bit of latency for fewer and larger writes.
+//!
+//! Chunks which are at least as large as the flush threshold
+//! are passed through as-is, when nothing is buffered.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{convert::Infallible, time::Duration};
+//!
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use rama_http::layer::coalesce_body::CoalesceResponseBodyLayer;
+//! use rama_http::{Body, Request, Response};
+//!
+//! async fn handle(_: Request) -> Result<Response, Infallible> {
+//!     // ...
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let svc = (
+//!     // buffer up to 16 KiB of response body data,
+//!     // flushing whatever is buffered at least every 10 milliseconds
+//!     CoalesceResponseBodyLayer::new()
+//!         .with_flush_threshold(16 * 1024)
+//!         .with_max_delay(Duration::from_millis(10)),
+//! ).layer(service_fn(handle));
+//!
+//! svc.serve(Context::default(), Request::new(Body::empty())).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dep::http_body::{Frame, SizeHint};
+use crate::{Body, Response};
+use bytes::{Buf, Bytes, BytesMut};
+use pin_project_lite::pin_project;
+use rama_core::error::BoxError;
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+use tokio::time::Sleep;
+
+const DEFAULT_FLUSH_THRESHOLD: usize = 8 * 1024;
+const DEFAULT_MAX_DELAY: Duration = Duration::from_millis(5);
+
+pin_project! {
+    /// A body wrapper which coalesces the data frames of the inner body,
+    /// yielding the buffered data once at least the flush threshold
+    /// amount of bytes is buffered, or once the max delay elapsed
+    /// since the first byte was buffered.
+    ///
+    /// Buffered data is always yielded prior to trailers,
+    /// errors and the end of the inner body.
+    pub struct CoalesceBody<B> {
+        #[pin]
+        inner: B,
+        flush_threshold: usize,
+        max_delay: Duration,
+        buffer: BytesMut,
+        delay: Option<Pin<Box<Sleep>>>,
+        pending: Option<Option<Result<Frame<Bytes>, BoxError>>>,
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for CoalesceBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CoalesceBody")
+            .field("inner", &self.inner)
+            .field("flush_threshold", &self.flush_threshold)
+            .field("max_delay", &self.max_delay)
+            .field("buffered", &self.buffer.len())
+            .finish()
+    }
+}
+
+impl<B> CoalesceBody<B> {
+    /// Create a new [`CoalesceBody`] wrapping the given body,
+    /// with the given flush threshold (in bytes) and max delay.
+    pub fn new(inner: B, flush_threshold: usize, max_delay: Duration) -> Self {
+        Self {
+            inner,
+            flush_threshold,
+            max_delay,
+            buffer: BytesMut::new(),
+            delay: None,
+            pending: None,
+        }
+    }
+
+    /// Consume the [`CoalesceBody`] and return the inner body.
+    ///
+    /// Any buffered data is lost.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> http_body::Body for CoalesceBody<B>
+where
+    B: http_body::Body<Error: Into<BoxError>>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        if let Some(pending) = this.pending.take() {
+            return Poll::Ready(pending);
+        }
+
+        loop {
+            if !this.buffer.is_empty() && this.buffer.len() >= *this.flush_threshold {
+                *this.delay = None;
+                return Poll::Ready(Some(Ok(Frame::data(this.buffer.split().freeze()))));
+            }
+
+            let pending = match this.inner.as_mut().poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => {
+                    match frame
+                        .map_data(|mut data| data.copy_to_bytes(data.remaining()))
+                        .into_data()
+                    {
+                        Ok(data) => {
+                            if this.buffer.is_empty() && data.len() >= *this.flush_threshold {
+                                return Poll::Ready(Some(Ok(Frame::data(data))));
+                            }
+                            if this.buffer.is_empty() && !data.is_empty() {
+                                *this.delay = Some(Box::pin(tokio::time::sleep(*this.max_delay)));
+                            }
+                            this.buffer.extend_from_slice(&data);
+                            continue;
+                        }
+                        Err(frame) => Some(Ok(frame)),
+                    }
+                }
+                Poll::Ready(Some(Err(err))) => Some(Err(err.into())),
+                Poll::Ready(None) => None,
+                Poll::Pending => {
+                    if this.buffer.is_empty() {
+                        return Poll::Pending;
+                    }
+                    if this
+                        .delay
+                        .as_mut()
+                        .is_some_and(|delay| delay.as_mut().poll(cx).is_pending())
+                    {
+                        return Poll::Pending;
+                    }
+                    *this.delay = None;
+                    return Poll::Ready(Some(Ok(Frame::data(this.buffer.split().freeze()))));
+                }
+            };
+
+            // flush buffered data prior to trailers, errors or the end of the body
+            if this.buffer.is_empty() {
+                return Poll::Ready(pending);
+            }
+            *this.delay = None;
+            *this.pending = Some(pending);
+            return Poll::Ready(Some(Ok(Frame::data(this.buffer.split().freeze()))));
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.buffer.is_empty()
+            && matches!(self.pending, None | Some(None))
+            && (self.pending.is_some() || self.inner.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = self.buffer.len() as u64;
+        let inner = self.inner.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + buffered);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + buffered);
+        }
+        hint
+    }
+}
+
+/// Layer that applies the [`CoalesceResponseBody`] middleware,
+/// wrapping response bodies in a [`CoalesceBody`].
+///
+/// See the [module docs](self) for an example.
+#[derive(Debug, Clone)]
+pub struct CoalesceResponseBodyLayer {
+    flush_threshold: usize,
+    max_delay: Duration,
+}
+
+impl Default for CoalesceResponseBodyLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoalesceResponseBodyLayer {
+    /// Create a new [`CoalesceResponseBodyLayer`],
+    /// with a flush threshold of 8 KiB and a max delay of 5 milliseconds.
+    pub const fn new() -> Self {
+        Self {
+            flush_threshold: DEFAULT_FLUSH_THRESHOLD,
+            max_delay: DEFAULT_MAX_DELAY,
+        }
+    }
+
+    /// Set the amount of buffered bytes at which the buffer is flushed.
+    pub const fn with_flush_threshold(mut self, threshold: usize) -> Self {
+        self.flush_threshold = threshold;
+        self
+    }
+
+    /// Set the amount of buffered bytes at which the buffer is flushed.
+    pub fn set_flush_threshold(&mut self, threshold: usize) -> &mut Self {
+        self.flush_threshold = threshold;
+        self
+    }
+
+    /// Set the maximum duration data is buffered while waiting for more data.
+    pub const fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Set the maximum duration data is buffered while waiting for more data.
+    pub fn set_max_delay(&mut self, delay: Duration) -> &mut Self {
+        self.max_delay = delay;
+        self
+    }
+}
+
+impl<S> Layer<S> for CoalesceResponseBodyLayer {
+    type Service = CoalesceResponseBody<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CoalesceResponseBody::new(inner, self.flush_threshold, self.max_delay)
+    }
+}
+
+/// Middleware which wraps response bodies in a [`CoalesceBody`].
+pub struct CoalesceResponseBody<S> {
+    inner: S,
+    flush_threshold: usize,
+    max_delay: Duration,
+}
+
+impl<S> CoalesceResponseBody<S> {
+    /// Create a new [`CoalesceResponseBody`] with the given flush threshold (in bytes) and max delay.
+    pub const fn new(inner: S, flush_threshold: usize, max_delay: Duration) -> Self {
+        Self {
+            inner,
+            flush_threshold,
+            max_delay,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for CoalesceResponseBody<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CoalesceResponseBody")
+            .field("inner", &self.inner)
+            .field("flush_threshold", &self.flush_threshold)
+            .field("max_delay", &self.max_delay)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for CoalesceResponseBody<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            flush_threshold: self.flush_threshold,
+            max_delay: self.max_delay,
+        }
+    }
+}
+
+impl<S, State, Req, ResBody> Service<State, Req> for CoalesceResponseBody<S>
+where
+    S: Service<State, Req, Response = Response<ResBody>>,
+    State: Clone + Send + Sync + 'static,
+    Req: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn serve(&self, ctx: Context<State>, req: Req) -> Result<Self::Response, Self::Error> {
+        let res = self.inner.serve(ctx, req).await?;
+        Ok(res.map(|body| {
+            Body::new(CoalesceBody::new(
+                body,
+                self.flush_threshold,
+                self.max_delay,
+            ))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::{BodyExt, StreamBody};
+    use crate::{HeaderMap, Request};
+    use futures_lite::StreamExt;
+    use rama_core::service::service_fn;
+    use rama_http_backend::server::HttpServer;
+    use rama_net::stream::layer::BytesRWTracker;
+    use std::convert::Infallible;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Body which yields `n` chunks of the given data, each after the given delay.
+    fn chatty_body(n: usize, data: &'static str, delay: Duration) -> Body {
+        let stream = futures_lite::stream::iter(0..n).then(move |_| async move {
+            tokio::time::sleep(delay).await;
+            Ok::<_, Infallible>(Frame::data(Bytes::from_static(data.as_bytes())))
+        });
+        Body::new(StreamBody::new(stream))
+    }
+
+    async fn collect_frames<B>(mut body: B) -> Vec<Frame<Bytes>>
+    where
+        B: http_body::Body<Data = Bytes, Error: fmt::Debug> + Unpin,
+    {
+        let mut frames = Vec::new();
+        while let Some(frame) = body.frame().await {
+            frames.push(frame.unwrap());
+        }
+        frames
+    }
+
+    fn data_lens(frames: &[Frame<Bytes>]) -> Vec<usize> {
+        frames
+            .iter()
+            .filter_map(|frame| frame.data_ref().map(|data| data.len()))
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_body_flush_threshold() {
+        let body = CoalesceBody::new(
+            chatty_body(10, "0123456789", Duration::from_millis(1)),
+            25,
+            Duration::from_secs(60),
+        );
+        let frames = collect_frames(body).await;
+        assert_eq!(data_lens(&frames), vec![30, 30, 30, 10]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_body_max_delay() {
+        let body = CoalesceBody::new(
+            chatty_body(6, "0123456789", Duration::from_millis(4)),
+            1024,
+            Duration::from_millis(10),
+        );
+        let frames = collect_frames(body).await;
+        // first byte buffered at 4ms, flushed at 14ms (chunks of 4ms, 8ms and 12ms),
+        // then 16ms until 26ms (16ms, 20ms, 24ms)
+        assert_eq!(data_lens(&frames), vec![30, 30]);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_body_large_chunks_pass_through() {
+        let body = CoalesceBody::new(
+            Body::from(Bytes::from(vec![b'a'; 100])),
+            10,
+            Duration::from_secs(60),
+        );
+        let frames = collect_frames(body).await;
+        assert_eq!(data_lens(&frames), vec![100]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_body_flushes_before_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", "42".parse().unwrap());
+        let stream = futures_lite::stream::iter([
+            Ok::<_, Infallible>(Frame::data(Bytes::from_static(b"foo"))),
+            Ok(Frame::data(Bytes::from_static(b"bar"))),
+            Ok(Frame::trailers(trailers.clone())),
+        ]);
+
+        let body = CoalesceBody::new(StreamBody::new(stream), 1024, Duration::from_secs(60));
+        let frames = collect_frames(body).await;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data_ref().unwrap().as_ref(), b"foobar");
+        assert_eq!(frames[1].trailers_ref().unwrap(), &trailers);
+    }
+
+    /// Serve a chatty response over http/1.1 and return the number of
+    /// write operations done by the server, as observed by a [`BytesRWTracker`].
+    async fn server_write_ops(coalesce: bool) -> u64 {
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+
+        let tracker = BytesRWTracker::new(server).with_histogram(&[64, 1024]);
+        let handle = tracker.handle();
+
+        let handler = service_fn(|_req: Request| async move {
+            Ok::<_, Infallible>(Response::new(chatty_body(
+                100,
+                "0123456789",
+                Duration::from_millis(1),
+            )))
+        });
+        let server = if coalesce {
+            let server = HttpServer::http1().service(
+                CoalesceResponseBodyLayer::new()
+                    .with_flush_threshold(512)
+                    .with_max_delay(Duration::from_secs(1))
+                    .layer(handler),
+            );
+            tokio::spawn(async move { server.serve(Context::default(), tracker).await })
+        } else {
+            let server = HttpServer::http1().service(handler);
+            tokio::spawn(async move { server.serve(Context::default(), tracker).await })
+        };
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nhost: example.com\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert_eq!(response.matches("0123456789").count(), 100, "{response}");
+        server.await.unwrap().unwrap();
+
+        handle
+            .written_histogram()
+            .iter()
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_response_body_reduces_write_ops() {
+        let uncoalesced = server_write_ops(false).await;
+        let coalesced = server_write_ops(true).await;
+        assert!(uncoalesced >= 100, "uncoalesced = {uncoalesced}");
+        assert!(coalesced <= 5, "coalesced = {coalesced}");
+    }
+}
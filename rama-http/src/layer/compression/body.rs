@@ -23,9 +23,10 @@ use tokio_util::io::StreamReader;
 use super::pin_project_cfg::pin_project_cfg;
 
 pin_project! {
-    /// Response body of [`Compression`].
+    /// Response body of [`Compression`] and request body of [`RequestCompression`].
     ///
     /// [`Compression`]: super::Compression
+    /// [`RequestCompression`]: super::RequestCompression
     pub struct CompressionBody<B>
     where
         B: Body,
@@ -1,5 +1,8 @@
 //! Middleware that compresses response bodies.
 //!
+//! Request bodies can be compressed as well, for clients talking to servers
+//! known to accept compressed request bodies, using the [`RequestCompressionLayer`].
+//!
 //! # Example
 //!
 //! Example showing how to respond with the compressed contents of a file.
@@ -78,8 +81,11 @@ pub mod predicate;
 mod body;
 mod layer;
 mod pin_project_cfg;
+mod request;
 mod service;
 
+#[doc(inline)]
+pub use self::request::{layer::RequestCompressionLayer, service::RequestCompression};
 #[doc(inline)]
 pub use self::{
     body::CompressionBody,
@@ -0,0 +1,78 @@
+use super::service::RequestCompression;
+use crate::layer::util::{compression::CompressionLevel, content_encoding::Encoding};
+use rama_core::Layer;
+
+/// Compresses request bodies of the underlying service.
+///
+/// This adds the [`RequestCompression`] middleware to a service.
+///
+/// See the [module docs](crate::layer::compression) for more details.
+#[derive(Debug, Clone)]
+pub struct RequestCompressionLayer {
+    encoding: Encoding,
+    quality: CompressionLevel,
+}
+
+impl Default for RequestCompressionLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for RequestCompressionLayer {
+    type Service = RequestCompression<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RequestCompression {
+            inner: service,
+            encoding: self.encoding,
+            quality: self.quality,
+        }
+    }
+}
+
+impl RequestCompressionLayer {
+    /// Creates a new [`RequestCompressionLayer`], compressing request bodies using gzip.
+    pub const fn new() -> Self {
+        Self {
+            encoding: Encoding::Gzip,
+            quality: CompressionLevel::Default,
+        }
+    }
+
+    /// Compresses request bodies using gzip.
+    pub fn gzip(mut self) -> Self {
+        self.encoding = Encoding::Gzip;
+        self
+    }
+
+    /// Compresses request bodies using Deflate.
+    pub fn deflate(mut self) -> Self {
+        self.encoding = Encoding::Deflate;
+        self
+    }
+
+    /// Compresses request bodies using Brotli.
+    pub fn br(mut self) -> Self {
+        self.encoding = Encoding::Brotli;
+        self
+    }
+
+    /// Compresses request bodies using Zstd.
+    pub fn zstd(mut self) -> Self {
+        self.encoding = Encoding::Zstd;
+        self
+    }
+
+    /// Sets the compression quality.
+    pub fn quality(mut self, quality: CompressionLevel) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Sets the compression quality.
+    pub fn set_quality(&mut self, quality: CompressionLevel) -> &mut Self {
+        self.quality = quality;
+        self
+    }
+}
@@ -0,0 +1,98 @@
+pub(super) mod layer;
+pub(super) mod service;
+
+#[cfg(test)]
+mod tests {
+    use super::layer::RequestCompressionLayer;
+    use super::service::RequestCompression;
+
+    use crate::dep::http_body_util::BodyExt;
+    use crate::layer::compression::CompressionBody;
+    use crate::{header, Body, Request, Response};
+    use rama_core::service::service_fn;
+    use rama_core::{Context, Layer, Service};
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::{convert::Infallible, io::Read};
+
+    const PAYLOAD: &str = "Hello, World! Hello, World! Hello, World!";
+
+    #[tokio::test]
+    async fn gzip_request_body() {
+        let svc = RequestCompression::new(service_fn(|req| async move {
+            let (parts, body) = read_request(req).await;
+            assert_eq!(parts.headers[header::CONTENT_ENCODING], "gzip");
+            assert!(!parts.headers.contains_key(header::CONTENT_LENGTH));
+
+            let mut decompressed = String::new();
+            GzDecoder::new(&body[..])
+                .read_to_string(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, PAYLOAD);
+
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+        svc.serve(Context::default(), request()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn deflate_request_body() {
+        let svc = RequestCompressionLayer::new()
+            .deflate()
+            .layer(service_fn(|req| async move {
+                let (parts, body) = read_request(req).await;
+                assert_eq!(parts.headers[header::CONTENT_ENCODING], "deflate");
+
+                let mut decompressed = String::new();
+                ZlibDecoder::new(&body[..])
+                    .read_to_string(&mut decompressed)
+                    .unwrap();
+                assert_eq!(decompressed, PAYLOAD);
+
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }));
+        svc.serve(Context::default(), request()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn skip_already_encoded_request_body() {
+        let svc = RequestCompression::new(service_fn(|req| async move {
+            let (parts, body) = read_request(req).await;
+            assert_eq!(parts.headers[header::CONTENT_ENCODING], "br");
+            assert_eq!(body, PAYLOAD.as_bytes());
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+        let mut req = request();
+        req.headers_mut()
+            .insert(header::CONTENT_ENCODING, "br".parse().unwrap());
+        svc.serve(Context::default(), req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn skip_empty_request_body() {
+        let svc = RequestCompression::new(service_fn(|req| async move {
+            let (parts, body) = read_request(req).await;
+            assert!(!parts.headers.contains_key(header::CONTENT_ENCODING));
+            assert!(body.is_empty());
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+        svc.serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+    }
+
+    fn request() -> Request<Body> {
+        Request::builder()
+            .header(header::CONTENT_LENGTH, PAYLOAD.len())
+            .body(Body::from(PAYLOAD))
+            .unwrap()
+    }
+
+    async fn read_request(
+        req: Request<CompressionBody<Body>>,
+    ) -> (crate::dep::http::request::Parts, Vec<u8>) {
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await.unwrap().to_bytes().to_vec();
+        (parts, body)
+    }
+}
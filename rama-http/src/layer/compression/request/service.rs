@@ -0,0 +1,157 @@
+use std::fmt;
+
+use crate::dep::http_body::Body;
+use crate::layer::{
+    compression::body::BodyInner,
+    compression::CompressionBody,
+    util::compression::{CompressionLevel, WrapBody},
+    util::content_encoding::Encoding,
+};
+use crate::{header, Request};
+use rama_core::{Context, Service};
+use rama_utils::macros::define_inner_service_accessors;
+
+/// Compresses request bodies and calls its underlying service.
+///
+/// The request body is compressed while it is streamed to the underlying service,
+/// using the configured encoding (gzip by default), and the `Content-Encoding`
+/// header is set accordingly. As the compressed size is not known up front,
+/// the `Content-Length` header is removed.
+///
+/// Requests that already have a `Content-Encoding` header or an empty body
+/// are passed through unmodified.
+///
+/// Only use this middleware for servers known (or configured)
+/// to support the chosen encoding for request bodies.
+///
+/// See the [module docs](crate::layer::compression) for more details.
+pub struct RequestCompression<S> {
+    pub(super) inner: S,
+    pub(super) encoding: Encoding,
+    pub(super) quality: CompressionLevel,
+}
+
+impl<S: fmt::Debug> fmt::Debug for RequestCompression<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestCompression")
+            .field("inner", &self.inner)
+            .field("encoding", &self.encoding)
+            .field("quality", &self.quality)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RequestCompression<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            encoding: self.encoding,
+            quality: self.quality,
+        }
+    }
+}
+
+impl<State, S, ReqBody> Service<State, Request<ReqBody>> for RequestCompression<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<CompressionBody<ReqBody>>>,
+    ReqBody: Body + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let (mut parts, body) = req.into_parts();
+
+        // never recompress request bodies that are already compressed,
+        // and do not bother compressing empty ones
+        if parts.headers.contains_key(header::CONTENT_ENCODING)
+            || body.size_hint().exact() == Some(0)
+        {
+            let req = Request::from_parts(parts, CompressionBody::new(BodyInner::identity(body)));
+            return self.inner.serve(ctx, req).await;
+        }
+
+        let body = match self.encoding {
+            Encoding::Gzip => {
+                CompressionBody::new(BodyInner::gzip(WrapBody::new(body, self.quality)))
+            }
+            Encoding::Deflate => {
+                CompressionBody::new(BodyInner::deflate(WrapBody::new(body, self.quality)))
+            }
+            Encoding::Brotli => {
+                CompressionBody::new(BodyInner::brotli(WrapBody::new(body, self.quality)))
+            }
+            Encoding::Zstd => {
+                CompressionBody::new(BodyInner::zstd(WrapBody::new(body, self.quality)))
+            }
+            Encoding::Identity => {
+                let req =
+                    Request::from_parts(parts, CompressionBody::new(BodyInner::identity(body)));
+                return self.inner.serve(ctx, req).await;
+            }
+        };
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts
+            .headers
+            .insert(header::CONTENT_ENCODING, self.encoding.into_header_value());
+
+        let req = Request::from_parts(parts, body);
+        self.inner.serve(ctx, req).await
+    }
+}
+
+impl<S> RequestCompression<S> {
+    /// Creates a new [`RequestCompression`] wrapping the `service`,
+    /// compressing request bodies using gzip.
+    pub const fn new(service: S) -> Self {
+        Self {
+            inner: service,
+            encoding: Encoding::Gzip,
+            quality: CompressionLevel::Default,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Compresses request bodies using gzip.
+    pub fn gzip(mut self) -> Self {
+        self.encoding = Encoding::Gzip;
+        self
+    }
+
+    /// Compresses request bodies using Deflate.
+    pub fn deflate(mut self) -> Self {
+        self.encoding = Encoding::Deflate;
+        self
+    }
+
+    /// Compresses request bodies using Brotli.
+    pub fn br(mut self) -> Self {
+        self.encoding = Encoding::Brotli;
+        self
+    }
+
+    /// Compresses request bodies using Zstd.
+    pub fn zstd(mut self) -> Self {
+        self.encoding = Encoding::Zstd;
+        self
+    }
+
+    /// Sets the compression quality.
+    pub fn quality(mut self, quality: CompressionLevel) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Sets the compression quality.
+    pub fn set_quality(&mut self, quality: CompressionLevel) -> &mut Self {
+        self.quality = quality;
+        self
+    }
+}
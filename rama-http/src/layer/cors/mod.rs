@@ -50,6 +50,7 @@ use crate::dep::http::{
     header::{self, HeaderName},
     HeaderMap, HeaderValue, Method, Request, Response,
 };
+use crate::ResponseProvenance;
 use bytes::{BufMut, BytesMut};
 use rama_core::{Context, Layer, Service};
 use rama_utils::macros::define_inner_service_accessors;
@@ -683,6 +684,7 @@ where
 
             let mut response = Response::new(ResBody::default());
             mem::swap(response.headers_mut(), &mut headers);
+            ResponseProvenance::record(&mut response, "cors", "preflight request");
 
             Ok(response)
         } else {
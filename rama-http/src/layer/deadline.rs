@@ -0,0 +1,441 @@
+//! Honor and propagate request deadlines.
+//!
+//! The [`SetDeadlineLayer`] parses an incoming deadline header, such as the
+//! [`grpc-timeout`] header, into a [`Deadline`] inserted in the [`Context`].
+//! This [`Deadline`] is enforced by the [`Timeout`] middleware (and its http variant)
+//! and can be propagated to upstream requests using the [`PropagateDeadlineLayer`],
+//! which sets the header to the time remaining until the [`Deadline`] expires.
+//!
+//! Header values are expected to be encoded as defined for the [`grpc-timeout`] header:
+//! at most 8 ASCII digits followed by a unit, one of `H` (hours), `M` (minutes),
+//! `S` (seconds), `m` (milliseconds), `u` (microseconds) or `n` (nanoseconds).
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::deadline::{PropagateDeadlineLayer, SetDeadlineLayer};
+//! use rama_http::layer::timeout::TimeoutLayer;
+//! use rama_http::{Body, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::time::Duration;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let svc = (
+//!     // honor incoming `grpc-timeout` headers
+//!     SetDeadlineLayer::grpc_timeout(),
+//!     // enforce the deadline (or 30 seconds at most)
+//!     TimeoutLayer::new(Duration::from_secs(30)),
+//!     // propagate the remaining time of the deadline to the upstream
+//!     PropagateDeadlineLayer::grpc_timeout(),
+//! ).layer(service_fn(|req: Request| async move {
+//!     assert!(req.headers().contains_key("grpc-timeout"));
+//!     Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+//! }));
+//!
+//! let request = Request::builder()
+//!     .header("grpc-timeout", "500m")
+//!     .body(Body::empty())?;
+//! svc.serve(Context::default(), request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`grpc-timeout`]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#requests
+//! [`Context`]: rama_core::Context
+//! [`Timeout`]: rama_core::layer::timeout::Timeout
+
+use crate::{
+    header::{HeaderName, HeaderValue},
+    Request,
+};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, time::Duration};
+
+#[doc(inline)]
+pub use rama_core::layer::timeout::Deadline;
+
+pub(crate) const GRPC_TIMEOUT: &str = "grpc-timeout";
+
+/// Maximum amount of digits allowed in a deadline header value.
+const MAX_DIGITS: usize = 8;
+
+/// Parse a [`grpc-timeout`] encoded header value into a [`Duration`].
+///
+/// [`grpc-timeout`]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#requests
+fn parse_timeout(value: &HeaderValue) -> Option<Duration> {
+    let value = value.as_bytes();
+    let (unit, digits) = value.split_last()?;
+    if digits.is_empty() || digits.len() > MAX_DIGITS || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let amount: u64 = std::str::from_utf8(digits).ok()?.parse().ok()?;
+    Some(match unit {
+        b'H' => Duration::from_secs(amount * 60 * 60),
+        b'M' => Duration::from_secs(amount * 60),
+        b'S' => Duration::from_secs(amount),
+        b'm' => Duration::from_millis(amount),
+        b'u' => Duration::from_micros(amount),
+        b'n' => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
+
+/// Encode a [`Duration`] as a [`grpc-timeout`] header value,
+/// using the most precise unit which fits within the allowed digits.
+///
+/// [`grpc-timeout`]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#requests
+fn encode_timeout(timeout: Duration) -> HeaderValue {
+    const MAX_AMOUNT: u128 = 99_999_999;
+
+    let nanos = timeout.as_nanos();
+    let (amount, unit) = [
+        (1, 'n'),
+        (1_000, 'u'),
+        (1_000_000, 'm'),
+        (1_000_000_000, 'S'),
+        (60 * 1_000_000_000, 'M'),
+    ]
+    .into_iter()
+    .map(|(divisor, unit)| (nanos / divisor, unit))
+    .find(|(amount, _)| *amount <= MAX_AMOUNT)
+    .unwrap_or_else(|| ((nanos / (60 * 60 * 1_000_000_000)).min(MAX_AMOUNT), 'H'));
+
+    HeaderValue::try_from(format!("{amount}{unit}")).expect("valid header value")
+}
+
+/// Honor a deadline header by inserting a [`Deadline`] in the [`Context`].
+///
+/// This layer applies the [`SetDeadline`] middleware.
+///
+/// See the [module docs](self) and [`SetDeadline`] for more details.
+#[derive(Debug, Clone)]
+pub struct SetDeadlineLayer {
+    header_name: HeaderName,
+}
+
+impl SetDeadlineLayer {
+    /// Create a new [`SetDeadlineLayer`].
+    pub const fn new(header_name: HeaderName) -> Self {
+        Self { header_name }
+    }
+
+    /// Create a new [`SetDeadlineLayer`] that uses `grpc-timeout` as the header name.
+    pub const fn grpc_timeout() -> Self {
+        Self::new(HeaderName::from_static(GRPC_TIMEOUT))
+    }
+}
+
+impl<S> Layer<S> for SetDeadlineLayer {
+    type Service = SetDeadline<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetDeadline::new(inner, self.header_name.clone())
+    }
+}
+
+/// Honor a deadline header by inserting a [`Deadline`] in the [`Context`].
+///
+/// See the [module docs](self) for an example.
+///
+/// In case a [`Deadline`] is already present in the [`Context`],
+/// the earliest of both is kept. Invalid header values are ignored.
+pub struct SetDeadline<S> {
+    inner: S,
+    header_name: HeaderName,
+}
+
+impl<S> SetDeadline<S> {
+    /// Create a new [`SetDeadline`].
+    pub const fn new(inner: S, header_name: HeaderName) -> Self {
+        Self { inner, header_name }
+    }
+
+    /// Create a new [`SetDeadline`] that uses `grpc-timeout` as the header name.
+    pub const fn grpc_timeout(inner: S) -> Self {
+        Self::new(inner, HeaderName::from_static(GRPC_TIMEOUT))
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for SetDeadline<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetDeadline")
+            .field("inner", &self.inner)
+            .field("header_name", &self.header_name)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for SetDeadline<S> {
+    fn clone(&self) -> Self {
+        SetDeadline {
+            inner: self.inner.clone(),
+            header_name: self.header_name.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody> Service<State, Request<ReqBody>> for SetDeadline<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>>,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(value) = req.headers().get(&self.header_name) {
+            match parse_timeout(value) {
+                Some(timeout) => {
+                    let deadline = Deadline::after(timeout);
+                    match ctx.get_mut::<Deadline>() {
+                        Some(existing) => *existing = deadline.min(*existing),
+                        None => {
+                            ctx.insert(deadline);
+                        }
+                    }
+                }
+                None => {
+                    tracing::debug!(
+                        header_name = %self.header_name,
+                        "SetDeadline: ignore invalid deadline header value: {value:?}",
+                    );
+                }
+            }
+        }
+
+        self.inner.serve(ctx, req).await
+    }
+}
+
+/// Propagate the remaining time of the [`Deadline`] found in the [`Context`]
+/// as a deadline header of the request.
+///
+/// This layer applies the [`PropagateDeadline`] middleware.
+///
+/// See the [module docs](self) and [`PropagateDeadline`] for more details.
+#[derive(Debug, Clone)]
+pub struct PropagateDeadlineLayer {
+    header_name: HeaderName,
+}
+
+impl PropagateDeadlineLayer {
+    /// Create a new [`PropagateDeadlineLayer`].
+    pub const fn new(header_name: HeaderName) -> Self {
+        Self { header_name }
+    }
+
+    /// Create a new [`PropagateDeadlineLayer`] that uses `grpc-timeout` as the header name.
+    pub const fn grpc_timeout() -> Self {
+        Self::new(HeaderName::from_static(GRPC_TIMEOUT))
+    }
+}
+
+impl<S> Layer<S> for PropagateDeadlineLayer {
+    type Service = PropagateDeadline<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PropagateDeadline::new(inner, self.header_name.clone())
+    }
+}
+
+/// Propagate the remaining time of the [`Deadline`] found in the [`Context`]
+/// as a deadline header of the request.
+///
+/// See the [module docs](self) for an example.
+///
+/// Any existing header with the same name is overwritten,
+/// such that an upstream never receives more time than remains.
+pub struct PropagateDeadline<S> {
+    inner: S,
+    header_name: HeaderName,
+}
+
+impl<S> PropagateDeadline<S> {
+    /// Create a new [`PropagateDeadline`].
+    pub const fn new(inner: S, header_name: HeaderName) -> Self {
+        Self { inner, header_name }
+    }
+
+    /// Create a new [`PropagateDeadline`] that uses `grpc-timeout` as the header name.
+    pub const fn grpc_timeout(inner: S) -> Self {
+        Self::new(inner, HeaderName::from_static(GRPC_TIMEOUT))
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for PropagateDeadline<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PropagateDeadline")
+            .field("inner", &self.inner)
+            .field("header_name", &self.header_name)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for PropagateDeadline<S> {
+    fn clone(&self) -> Self {
+        PropagateDeadline {
+            inner: self.inner.clone(),
+            header_name: self.header_name.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody> Service<State, Request<ReqBody>> for PropagateDeadline<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>>,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(deadline) = ctx.get::<Deadline>() {
+            req.headers_mut().insert(
+                self.header_name.clone(),
+                encode_timeout(deadline.remaining()),
+            );
+        }
+
+        self.inner.serve(ctx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layer::timeout::TimeoutLayer, Body, Response, StatusCode};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_parse_timeout() {
+        for (input, expected) in [
+            ("500m", Some(Duration::from_millis(500))),
+            ("1H", Some(Duration::from_secs(3600))),
+            ("2M", Some(Duration::from_secs(120))),
+            ("3S", Some(Duration::from_secs(3))),
+            ("4u", Some(Duration::from_micros(4))),
+            ("5n", Some(Duration::from_nanos(5))),
+            ("99999999S", Some(Duration::from_secs(99_999_999))),
+            ("100000000S", None),
+            ("m", None),
+            ("500", None),
+            ("500x", None),
+            ("-5m", None),
+            ("", None),
+        ] {
+            assert_eq!(
+                parse_timeout(&HeaderValue::from_static(input)),
+                expected,
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_timeout() {
+        for (input, expected) in [
+            (Duration::from_nanos(5), "5n"),
+            (Duration::from_millis(50), "50000000n"),
+            (Duration::from_millis(500), "500000u"),
+            (Duration::from_secs(5), "5000000u"),
+            (Duration::from_secs(500_000), "500000S"),
+        ] {
+            assert_eq!(encode_timeout(input), expected, "input: {input:?}");
+            assert_eq!(
+                parse_timeout(&encode_timeout(input)),
+                Some(input),
+                "input: {input:?}"
+            );
+        }
+
+        // precision is lost for durations which do not fit in minutes
+        assert_eq!(
+            encode_timeout(Duration::from_secs(60 * 100_000_000)),
+            "1666666H"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_grpc_timeout_propagates_remaining_deadline() {
+        let upstream =
+            PropagateDeadlineLayer::grpc_timeout().layer(service_fn(|req: Request| async move {
+                Ok::<_, Infallible>(
+                    parse_timeout(req.headers().get(GRPC_TIMEOUT).unwrap()).unwrap(),
+                )
+            }));
+        let svc = SetDeadlineLayer::grpc_timeout().layer(service_fn(
+            move |ctx: Context<()>, req: Request| {
+                let upstream = upstream.clone();
+                async move {
+                    assert!(ctx.contains::<Deadline>());
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    upstream.serve(ctx, req).await
+                }
+            },
+        ));
+
+        let request = Request::builder()
+            .header(GRPC_TIMEOUT, "500m")
+            .body(Body::empty())
+            .unwrap();
+        let remaining = svc.serve(Context::default(), request).await.unwrap();
+        assert!(remaining > Duration::ZERO);
+        assert!(remaining <= Duration::from_millis(450), "{remaining:?}");
+    }
+
+    #[tokio::test]
+    async fn test_set_deadline_keeps_earliest() {
+        let svc = SetDeadlineLayer::grpc_timeout().layer(service_fn(
+            |ctx: Context<()>, _req: Request| async move {
+                Ok::<_, Infallible>(ctx.get::<Deadline>().unwrap().remaining())
+            },
+        ));
+
+        let mut ctx = Context::default();
+        ctx.insert(Deadline::after(Duration::from_millis(100)));
+        let request = Request::builder()
+            .header(GRPC_TIMEOUT, "10S")
+            .body(Body::empty())
+            .unwrap();
+        let remaining = svc.serve(ctx, request).await.unwrap();
+        assert!(remaining <= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_enforces_deadline() {
+        let svc = (
+            SetDeadlineLayer::grpc_timeout(),
+            TimeoutLayer::new(Duration::from_secs(30)),
+        )
+            .layer(service_fn(|_req: Request| async move {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }));
+
+        let request = Request::builder()
+            .header(GRPC_TIMEOUT, "10m")
+            .body(Body::empty())
+            .unwrap();
+        let response = svc.serve(Context::default(), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+}
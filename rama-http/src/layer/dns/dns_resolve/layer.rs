@@ -4,16 +4,36 @@ use rama_core::Layer;
 
 /// Layer which can extend `Dns` (see `rama_core`) overwrites with mappings.
 ///
+/// Requests with an invalid header value are rejected, unless
+/// [`DnsResolveModeLayer::with_ignore_invalid`] is used.
+/// See [`DnsResolveModeService`] for more information.
+///
 /// See [the module level documentation](crate::layer::dns) for more information.
 #[derive(Debug, Clone)]
 pub struct DnsResolveModeLayer {
     header_name: HeaderName,
+    ignore_invalid: bool,
 }
 
 impl DnsResolveModeLayer {
     /// Creates a new [`DnsResolveModeLayer`].
     pub const fn new(name: HeaderName) -> Self {
-        Self { header_name: name }
+        Self {
+            header_name: name,
+            ignore_invalid: false,
+        }
+    }
+
+    /// Ignore invalid header values, instead of rejecting the request.
+    pub const fn with_ignore_invalid(mut self, ignore: bool) -> Self {
+        self.ignore_invalid = ignore;
+        self
+    }
+
+    /// Ignore invalid header values, instead of rejecting the request.
+    pub fn set_ignore_invalid(&mut self, ignore: bool) -> &mut Self {
+        self.ignore_invalid = ignore;
+        self
     }
 }
 
@@ -22,35 +42,70 @@ impl<S> Layer<S> for DnsResolveModeLayer {
 
     fn layer(&self, inner: S) -> Self::Service {
         DnsResolveModeService::new(inner, self.header_name.clone())
+            .with_ignore_invalid(self.ignore_invalid)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{layer::dns::DnsResolveMode, Request};
+    use crate::{layer::dns::DnsResolveMode, BodyExtractExt, Request, StatusCode};
     use rama_core::{service::service_fn, Context, Service};
     use std::convert::Infallible;
 
-    #[tokio::test]
-    async fn test_dns_resolve_mode_layer() {
-        let svc = DnsResolveModeLayer::new(HeaderName::from_static("x-dns-resolve")).layer(
-            service_fn(|ctx: Context<()>, _req: Request<()>| async move {
-                assert_eq!(
-                    ctx.get::<DnsResolveMode>().unwrap(),
-                    &DnsResolveMode::eager()
-                );
-                Ok::<_, Infallible>(())
-            }),
-        );
+    async fn serve_dns_resolve_mode(
+        ignore_invalid: bool,
+        header_value: &'static str,
+    ) -> (StatusCode, Option<DnsResolveMode>) {
+        let header_name = HeaderName::from_static("x-dns-resolve");
+        let inner = service_fn(|ctx: Context<()>, _req: Request<()>| async move {
+            Ok::<_, Infallible>(match ctx.get::<DnsResolveMode>() {
+                Some(mode) => mode.to_string(),
+                None => "none".to_owned(),
+            })
+        });
 
-        let ctx = Context::default();
         let req = Request::builder()
-            .header("x-dns-resolve", "eager")
+            .header("x-dns-resolve", header_value)
             .uri("http://example.com")
             .body(())
             .unwrap();
 
-        svc.serve(ctx, req).await.unwrap();
+        let svc = DnsResolveModeLayer::new(header_name)
+            .with_ignore_invalid(ignore_invalid)
+            .layer(inner);
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        let status = res.status();
+        let body = res.try_into_string().await.unwrap();
+        (status, body.parse().ok())
+    }
+
+    #[tokio::test]
+    async fn test_dns_resolve_mode_layer() {
+        for ignore_invalid in [false, true] {
+            assert_eq!(
+                serve_dns_resolve_mode(ignore_invalid, "eager").await,
+                (StatusCode::OK, Some(DnsResolveMode::eager()))
+            );
+            assert_eq!(
+                serve_dns_resolve_mode(ignore_invalid, "Lazy").await,
+                (StatusCode::OK, Some(DnsResolveMode::lazy()))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_resolve_mode_layer_invalid_value() {
+        // default: the request is rejected
+        assert_eq!(
+            serve_dns_resolve_mode(false, "sometimes").await.0,
+            StatusCode::BAD_REQUEST
+        );
+
+        // ignore invalid: the header value is ignored
+        assert_eq!(
+            serve_dns_resolve_mode(true, "sometimes").await,
+            (StatusCode::OK, None)
+        );
     }
 }
@@ -1,5 +1,5 @@
 use super::DnsResolveMode;
-use crate::{HeaderName, Request};
+use crate::{HeaderName, IntoResponse, Request, Response, StatusCode};
 use rama_core::{error::OpaqueError, Context, Service};
 use rama_utils::macros::define_inner_service_accessors;
 use std::fmt;
@@ -12,16 +12,38 @@ use std::fmt;
 /// a way to have requested the intent
 /// to reoslve DNS even if it is not needed.
 ///
+/// Any mode supported by [`DnsResolveMode`] is accepted as header value.
+/// Requests with an invalid header value are answered with a `400 Bad Request` response,
+/// unless [`DnsResolveModeService::with_ignore_invalid`] is used to ignore (and log)
+/// such header values instead.
+///
 /// See `Dns` (`rama_core`) and [`DnsResolveMode`] for more information.
 pub struct DnsResolveModeService<S> {
     inner: S,
     header_name: HeaderName,
+    ignore_invalid: bool,
 }
 
 impl<S> DnsResolveModeService<S> {
     /// Create a new instance of the [`DnsResolveModeService`].
     pub const fn new(inner: S, header_name: HeaderName) -> Self {
-        Self { inner, header_name }
+        Self {
+            inner,
+            header_name,
+            ignore_invalid: false,
+        }
+    }
+
+    /// Ignore invalid header values, instead of rejecting the request.
+    pub const fn with_ignore_invalid(mut self, ignore: bool) -> Self {
+        self.ignore_invalid = ignore;
+        self
+    }
+
+    /// Ignore invalid header values, instead of rejecting the request.
+    pub fn set_ignore_invalid(&mut self, ignore: bool) -> &mut Self {
+        self.ignore_invalid = ignore;
+        self
     }
 
     define_inner_service_accessors!();
@@ -31,6 +53,8 @@ impl<S: fmt::Debug> fmt::Debug for DnsResolveModeService<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("DnsResolveModeService")
             .field("inner", &self.inner)
+            .field("header_name", &self.header_name)
+            .field("ignore_invalid", &self.ignore_invalid)
             .finish()
     }
 }
@@ -40,6 +64,7 @@ impl<S: Clone> Clone for DnsResolveModeService<S> {
         DnsResolveModeService {
             inner: self.inner.clone(),
             header_name: self.header_name.clone(),
+            ignore_invalid: self.ignore_invalid,
         }
     }
 }
@@ -51,10 +76,11 @@ where
     S: Service<
         State,
         Request<Body>,
+        Response: IntoResponse,
         Error: Into<rama_core::error::BoxError> + Send + Sync + 'static,
     >,
 {
-    type Response = S::Response;
+    type Response = Response;
     type Error = OpaqueError;
 
     async fn serve(
@@ -62,14 +88,43 @@ where
         mut ctx: Context<State>,
         request: Request<Body>,
     ) -> Result<Self::Response, Self::Error> {
-        if let Some(header_value) = request.headers().get(&self.header_name) {
-            let dns_resolve_mode: DnsResolveMode = header_value.try_into()?;
-            ctx.insert(dns_resolve_mode);
+        match dns_resolve_mode_from_request(&self.header_name, &request) {
+            Ok(Some(dns_resolve_mode)) => {
+                ctx.insert(dns_resolve_mode);
+            }
+            Ok(None) => (),
+            Err(err) if self.ignore_invalid => {
+                tracing::debug!(
+                    error = %err,
+                    header_name = %self.header_name,
+                    "ignore invalid dns resolve mode header value",
+                );
+            }
+            Err(err) => {
+                tracing::debug!(
+                    error = %err,
+                    header_name = %self.header_name,
+                    "reject request with invalid dns resolve mode header value",
+                );
+                return Ok(StatusCode::BAD_REQUEST.into_response());
+            }
         }
 
         self.inner
             .serve(ctx, request)
             .await
+            .map(IntoResponse::into_response)
             .map_err(|err| OpaqueError::from_boxed(err.into()))
     }
 }
+
+fn dns_resolve_mode_from_request<Body>(
+    header_name: &HeaderName,
+    request: &Request<Body>,
+) -> Result<Option<DnsResolveMode>, OpaqueError> {
+    request
+        .headers()
+        .get(header_name)
+        .map(DnsResolveMode::try_from)
+        .transpose()
+}
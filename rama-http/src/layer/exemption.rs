@@ -0,0 +1,571 @@
+//! Middleware to exempt CORS preflight, health check and internal traffic
+//! from other middleware, such as auth, rate limiting and access logging.
+//!
+//! An [`ExemptionMatcher`] is defined once, and shared by all [`ExemptionLayer`]s
+//! wrapping the layers that such traffic should bypass. Which kinds of
+//! exemptions apply can be configured per [`ExemptionLayer`], and each layer
+//! counts the requests it exempted in its own [`ExemptionStats`].
+//!
+//! # Example
+//!
+//! ```
+//! use std::sync::Arc;
+//! use rama_http::layer::exemption::{ExemptionLayer, ExemptionMatcher, ExemptionReason};
+//! use rama_http::layer::validate_request::ValidateRequestHeaderLayer;
+//! use rama_http::layer::trace::TraceLayer;
+//! use rama_http::{header, Body, Method, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use rama_core::error::BoxError;
+//!
+//! async fn handle(request: Request) -> Result<Response, BoxError> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let exemptions = Arc::new(ExemptionMatcher::new().with_health_path("/healthz"));
+//!
+//! let auth = ExemptionLayer::new(
+//!     exemptions.clone(),
+//!     ValidateRequestHeaderLayer::bearer("secret"),
+//! );
+//! let auth_stats = auth.stats();
+//!
+//! let service = (
+//!     // health checks are not logged, but preflights are
+//!     ExemptionLayer::new(exemptions, TraceLayer::new_for_http())
+//!         .with_exemption(ExemptionReason::Preflight, false),
+//!     auth,
+//! )
+//!     .layer(service_fn(handle));
+//!
+//! let request = Request::builder()
+//!     .method(Method::OPTIONS)
+//!     .header(header::ORIGIN, "https://example.com")
+//!     .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+//!     .body(Body::empty())?;
+//! let response = service.serve(Context::default(), request).await?;
+//! assert_eq!(StatusCode::OK, response.status());
+//! assert_eq!(1, auth_stats.count(ExemptionReason::Preflight));
+//!
+//! let request = Request::builder().uri("/healthz").body(Body::empty())?;
+//! let response = service.serve(Context::default(), request).await?;
+//! assert_eq!(StatusCode::OK, response.status());
+//! assert_eq!(1, auth_stats.count(ExemptionReason::HealthCheck));
+//!
+//! let request = Request::builder().uri("/").body(Body::empty())?;
+//! let response = service.serve(Context::default(), request).await?;
+//! assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+//! assert_eq!(2, auth_stats.total());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{header, Body, Method, Request, Response};
+use bytes::Bytes;
+use rama_core::{error::BoxError, matcher::Matcher, Context, Layer, Service};
+use rama_net::stream::matcher::{ip::IntoIpNet, IpNetMatcher};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The reason a request is exempted by an [`ExemptionMatcher`].
+pub enum ExemptionReason {
+    /// A CORS preflight request: an `OPTIONS` request
+    /// with both an `Origin` and `Access-Control-Request-Method` header.
+    Preflight,
+    /// A request for one of the configured health check paths.
+    HealthCheck,
+    /// A request from a peer within one of the configured internal networks.
+    InternalNetwork,
+}
+
+impl ExemptionReason {
+    const ALL: [ExemptionReason; 3] = [
+        ExemptionReason::Preflight,
+        ExemptionReason::HealthCheck,
+        ExemptionReason::InternalNetwork,
+    ];
+
+    const fn index(self) -> usize {
+        match self {
+            ExemptionReason::Preflight => 0,
+            ExemptionReason::HealthCheck => 1,
+            ExemptionReason::InternalNetwork => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Declarative definition of the requests which can be exempted
+/// from the layers wrapped in an [`ExemptionLayer`].
+///
+/// By default only CORS preflight requests are matched.
+pub struct ExemptionMatcher {
+    preflight: bool,
+    health_paths: Vec<String>,
+    internal_networks: Vec<IpNetMatcher>,
+}
+
+impl Default for ExemptionMatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExemptionMatcher {
+    /// Create a new [`ExemptionMatcher`], matching only CORS preflight requests.
+    pub fn new() -> Self {
+        Self {
+            preflight: true,
+            health_paths: Vec::new(),
+            internal_networks: Vec::new(),
+        }
+    }
+
+    /// Define whether or not CORS preflight requests are matched, `true` by default.
+    pub fn with_preflight(mut self, preflight: bool) -> Self {
+        self.preflight = preflight;
+        self
+    }
+
+    /// Define whether or not CORS preflight requests are matched, `true` by default.
+    pub fn set_preflight(&mut self, preflight: bool) -> &mut Self {
+        self.preflight = preflight;
+        self
+    }
+
+    /// Add a path which is matched as a health check, e.g. `/healthz`.
+    ///
+    /// The path of the request has to be equal to it, the query is ignored.
+    pub fn with_health_path(mut self, path: impl Into<String>) -> Self {
+        self.health_paths.push(path.into());
+        self
+    }
+
+    /// Add a path which is matched as a health check, e.g. `/healthz`.
+    ///
+    /// The path of the request has to be equal to it, the query is ignored.
+    pub fn set_health_path(&mut self, path: impl Into<String>) -> &mut Self {
+        self.health_paths.push(path.into());
+        self
+    }
+
+    /// Add an internal network, matching requests from peers within it.
+    ///
+    /// Requests for which no peer address is known are never matched as internal.
+    pub fn with_internal_network(mut self, net: impl IntoIpNet) -> Self {
+        self.internal_networks.push(IpNetMatcher::new(net));
+        self
+    }
+
+    /// Add an internal network, matching requests from peers within it.
+    ///
+    /// Requests for which no peer address is known are never matched as internal.
+    pub fn set_internal_network(&mut self, net: impl IntoIpNet) -> &mut Self {
+        self.internal_networks.push(IpNetMatcher::new(net));
+        self
+    }
+
+    /// Returns `true` if the request is matched for the given [`ExemptionReason`].
+    pub fn matches<State, Body>(
+        &self,
+        reason: ExemptionReason,
+        ctx: &Context<State>,
+        req: &Request<Body>,
+    ) -> bool {
+        match reason {
+            ExemptionReason::Preflight => {
+                self.preflight
+                    && req.method() == Method::OPTIONS
+                    && req.headers().contains_key(header::ORIGIN)
+                    && req
+                        .headers()
+                        .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+            }
+            ExemptionReason::HealthCheck => {
+                let path = req.uri().path();
+                self.health_paths
+                    .iter()
+                    .any(|health_path| health_path == path)
+            }
+            ExemptionReason::InternalNetwork => self
+                .internal_networks
+                .iter()
+                .any(|net| net.matches(None, ctx, req)),
+        }
+    }
+
+    /// Get the first [`ExemptionReason`] for which the request is matched, if any.
+    pub fn exemption<State, Body>(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<Body>,
+    ) -> Option<ExemptionReason> {
+        ExemptionReason::ALL
+            .into_iter()
+            .find(|reason| self.matches(*reason, ctx, req))
+    }
+}
+
+#[derive(Debug, Default)]
+/// Amount of requests exempted by an [`ExemptionLayer`], per [`ExemptionReason`].
+pub struct ExemptionStats {
+    counts: [AtomicU64; 3],
+}
+
+impl ExemptionStats {
+    /// Get the amount of requests exempted for the given [`ExemptionReason`].
+    pub fn count(&self, reason: ExemptionReason) -> u64 {
+        self.counts[reason.index()].load(Ordering::Acquire)
+    }
+
+    /// Get the total amount of exempted requests.
+    pub fn total(&self) -> u64 {
+        self.counts
+            .iter()
+            .map(|count| count.load(Ordering::Acquire))
+            .sum()
+    }
+
+    fn record(&self, reason: ExemptionReason) {
+        self.counts[reason.index()].fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// Layer which wraps another [`Layer`], such that requests matched
+/// by the [`ExemptionMatcher`] bypass the wrapped layer.
+///
+/// See the [module docs](crate::layer::exemption) for an example.
+pub struct ExemptionLayer<L> {
+    matcher: Arc<ExemptionMatcher>,
+    layer: L,
+    enabled: [bool; 3],
+    stats: Arc<ExemptionStats>,
+}
+
+impl<L: fmt::Debug> fmt::Debug for ExemptionLayer<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExemptionLayer")
+            .field("matcher", &self.matcher)
+            .field("layer", &self.layer)
+            .field("enabled", &self.enabled)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl<L: Clone> Clone for ExemptionLayer<L> {
+    fn clone(&self) -> Self {
+        Self {
+            matcher: self.matcher.clone(),
+            layer: self.layer.clone(),
+            enabled: self.enabled,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<L> ExemptionLayer<L> {
+    /// Create a new [`ExemptionLayer`], exempting the requests matched
+    /// by the shared [`ExemptionMatcher`] from the given layer,
+    /// for all [`ExemptionReason`]s.
+    pub fn new(matcher: Arc<ExemptionMatcher>, layer: L) -> Self {
+        Self {
+            matcher,
+            layer,
+            enabled: [true; 3],
+            stats: Arc::new(ExemptionStats::default()),
+        }
+    }
+
+    /// Define whether or not requests are exempted for the given [`ExemptionReason`],
+    /// all reasons are enabled by default.
+    pub fn with_exemption(mut self, reason: ExemptionReason, enabled: bool) -> Self {
+        self.enabled[reason.index()] = enabled;
+        self
+    }
+
+    /// Define whether or not requests are exempted for the given [`ExemptionReason`],
+    /// all reasons are enabled by default.
+    pub fn set_exemption(&mut self, reason: ExemptionReason, enabled: bool) -> &mut Self {
+        self.enabled[reason.index()] = enabled;
+        self
+    }
+
+    /// Get the [`ExemptionStats`] of this layer,
+    /// shared by all services created by it (or its clones).
+    pub fn stats(&self) -> Arc<ExemptionStats> {
+        self.stats.clone()
+    }
+}
+
+impl<S, L> Layer<S> for ExemptionLayer<L>
+where
+    S: Clone,
+    L: Layer<S>,
+{
+    type Service = ExemptionService<S, L::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExemptionService {
+            exempt: inner.clone(),
+            inner: self.layer.layer(inner),
+            matcher: self.matcher.clone(),
+            enabled: self.enabled,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+/// Middleware serving requests matched by an [`ExemptionMatcher`]
+/// using the service as it was prior to being wrapped by the exempted layer.
+///
+/// The response bodies of both services are unified as a [`Body`],
+/// such that layers which wrap the response body (e.g. for access logging)
+/// can be exempted as well.
+///
+/// See [`ExemptionLayer`] for more details.
+pub struct ExemptionService<S, T> {
+    exempt: S,
+    inner: T,
+    matcher: Arc<ExemptionMatcher>,
+    enabled: [bool; 3],
+    stats: Arc<ExemptionStats>,
+}
+
+impl<S: fmt::Debug, T: fmt::Debug> fmt::Debug for ExemptionService<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExemptionService")
+            .field("exempt", &self.exempt)
+            .field("inner", &self.inner)
+            .field("matcher", &self.matcher)
+            .field("enabled", &self.enabled)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl<S: Clone, T: Clone> Clone for ExemptionService<S, T> {
+    fn clone(&self) -> Self {
+        Self {
+            exempt: self.exempt.clone(),
+            inner: self.inner.clone(),
+            matcher: self.matcher.clone(),
+            enabled: self.enabled,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<S, T> ExemptionService<S, T> {
+    /// Get the [`ExemptionStats`] of this service.
+    pub fn stats(&self) -> &ExemptionStats {
+        &self.stats
+    }
+}
+
+impl<State, ReqBody, ResBody, InnerResBody, S, T> Service<State, Request<ReqBody>>
+    for ExemptionService<S, T>
+where
+    State: Clone + Send + Sync + 'static,
+    ReqBody: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+    InnerResBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>, Error: Into<T::Error>>,
+    T: Service<State, Request<ReqBody>, Response = Response<InnerResBody>>,
+{
+    type Response = Response;
+    type Error = T::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let exemption = ExemptionReason::ALL
+            .into_iter()
+            .filter(|reason| self.enabled[reason.index()])
+            .find(|reason| self.matcher.matches(*reason, &ctx, &req));
+        match exemption {
+            Some(reason) => {
+                tracing::trace!(?reason, "exempt request from layer");
+                self.stats.record(reason);
+                let res = self.exempt.serve(ctx, req).await.map_err(Into::into)?;
+                Ok(res.map(Body::new))
+            }
+            None => {
+                let res = self.inner.serve(ctx, req).await?;
+                Ok(res.map(Body::new))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::validate_request::ValidateRequestHeaderLayer;
+    use crate::StatusCode;
+    use rama_core::layer::limit::{policy::ConcurrentPolicy, LimitLayer};
+    use rama_core::service::service_fn;
+    use rama_net::stream::{matcher::ip::Ipv4Net, SocketInfo};
+    use std::convert::Infallible;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    async fn ok(_req: Request) -> Result<Response, Infallible> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    fn preflight() -> Request {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api")
+            .header(header::ORIGIN, "https://example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_preflight_passes_auth() {
+        let matcher = Arc::new(ExemptionMatcher::new());
+        let layer = ExemptionLayer::new(matcher, ValidateRequestHeaderLayer::bearer("secret"));
+        let stats = layer.stats();
+        let service = layer.layer(service_fn(ok));
+
+        let res = service
+            .serve(Context::default(), preflight())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(stats.count(ExemptionReason::Preflight), 1);
+
+        // forged preflights are not exempted
+        for req in [
+            // missing Access-Control-Request-Method
+            Request::builder()
+                .method(Method::OPTIONS)
+                .header(header::ORIGIN, "https://example.com")
+                .body(Body::empty())
+                .unwrap(),
+            // missing Origin
+            Request::builder()
+                .method(Method::OPTIONS)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .body(Body::empty())
+                .unwrap(),
+            // not an OPTIONS request
+            Request::builder()
+                .method(Method::POST)
+                .header(header::ORIGIN, "https://example.com")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .body(Body::empty())
+                .unwrap(),
+        ] {
+            let res = service.serve(Context::default(), req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert_eq!(stats.total(), 1);
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        let res = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(stats.total(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_exemption_configurable_per_layer() {
+        let matcher = Arc::new(
+            ExemptionMatcher::new()
+                .with_health_path("/healthz")
+                .with_internal_network(Ipv4Net::new(Ipv4Addr::new(10, 0, 0, 0), 8).unwrap()),
+        );
+
+        // rate limit layer rejecting all requests, except for health checks
+        let limit = ExemptionLayer::new(matcher.clone(), LimitLayer::new(ConcurrentPolicy::max(0)))
+            .with_exemption(ExemptionReason::Preflight, false)
+            .with_exemption(ExemptionReason::InternalNetwork, false);
+        let limit_stats = limit.stats();
+        let auth = ExemptionLayer::new(matcher, ValidateRequestHeaderLayer::bearer("secret"));
+        let auth_stats = auth.stats();
+
+        let service = (limit, auth).layer(service_fn(ok));
+
+        let res = service
+            .serve(
+                Context::default(),
+                Request::builder()
+                    .uri("/healthz?probe=lb")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let result: Result<_, BoxError> = service.serve(Context::default(), preflight()).await;
+        assert!(result.is_err());
+
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(
+            None,
+            SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 4242)),
+        ));
+        let result = service
+            .serve(
+                ctx,
+                Request::builder().uri("/").body(Body::empty()).unwrap(),
+            )
+            .await;
+        assert!(result.is_err());
+
+        assert_eq!(limit_stats.count(ExemptionReason::HealthCheck), 1);
+        assert_eq!(limit_stats.total(), 1);
+        assert_eq!(auth_stats.count(ExemptionReason::HealthCheck), 1);
+        assert_eq!(auth_stats.total(), 1);
+    }
+
+    #[test]
+    fn test_exemption_matcher() {
+        let matcher = ExemptionMatcher::new()
+            .with_preflight(false)
+            .with_health_path("/healthz")
+            .with_internal_network(Ipv4Net::new(Ipv4Addr::new(10, 0, 0, 0), 8).unwrap());
+
+        assert_eq!(matcher.exemption(&Context::default(), &preflight()), None);
+
+        let req = Request::builder()
+            .uri("http://example.com/healthz")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            matcher.exemption(&Context::default(), &req),
+            Some(ExemptionReason::HealthCheck)
+        );
+
+        let req = Request::builder().uri("/healthz/deep").body(()).unwrap();
+        assert_eq!(matcher.exemption(&Context::default(), &req), None);
+
+        let mut ctx = Context::default();
+        assert_eq!(matcher.exemption(&ctx, &req), None);
+        ctx.insert(SocketInfo::new(
+            None,
+            SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 4242)),
+        ));
+        assert_eq!(
+            matcher.exemption(&ctx, &req),
+            Some(ExemptionReason::InternalNetwork)
+        );
+    }
+}
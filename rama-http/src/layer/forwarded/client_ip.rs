@@ -0,0 +1,366 @@
+use super::TrustedProxies;
+use crate::header::{X_FORWARDED_FOR, X_REAL_IP};
+use crate::{HeaderMap, Request};
+use rama_core::{Context, Layer, Service};
+use rama_net::forwarded::{ClientIp, Forwarded};
+use rama_net::stream::SocketInfo;
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A source from which the [`ClientIpLayer`] can resolve the [`ClientIp`].
+pub enum ClientIpSource {
+    /// The client IP of the [`Forwarded`] extension found in the [`Context`],
+    /// e.g. as inserted by the [`GetForwardedHeadersLayer`].
+    ///
+    /// [`GetForwardedHeadersLayer`]: super::GetForwardedHeadersLayer
+    Forwarded,
+    /// The client IP of the `X-Forwarded-For` header,
+    /// being the leftmost address not reported by a trusted proxy.
+    XForwardedFor,
+    /// The IP of the `X-Real-IP` header.
+    XRealIp,
+    /// The IP of the peer of the connection, found in the [`SocketInfo`].
+    Peer,
+}
+
+const DEFAULT_SOURCES: [ClientIpSource; 4] = [
+    ClientIpSource::Forwarded,
+    ClientIpSource::XForwardedFor,
+    ClientIpSource::XRealIp,
+    ClientIpSource::Peer,
+];
+
+/// Layer which resolves the effective [`ClientIp`] of a request
+/// and inserts it in the [`Context`].
+///
+/// The [`ClientIpSource`]s are tried in order, by default:
+///
+/// 1. the [`Forwarded`] extension;
+/// 2. the `X-Forwarded-For` header;
+/// 3. the `X-Real-IP` header;
+/// 4. the peer address of the connection.
+///
+/// Use [`ClientIpLayer::with_sources`] to change which sources are considered, and in what order.
+///
+/// Header values which are not a valid IP address (e.g. with a port attached) are skipped.
+/// The headers are only honored in case the peer of the connection is one of the
+/// [`TrustedProxies`], which are also used to find the client in the `X-Forwarded-For` chain.
+/// By default all proxies are trusted, which is only safe in case this server
+/// is not reachable other than via trusted proxies.
+///
+/// ## Example
+///
+/// ```rust
+/// use rama_core::{service::service_fn, Context, Layer, Service};
+/// use rama_http::{layer::forwarded::ClientIpLayer, Request};
+/// use rama_net::forwarded::ClientIp;
+/// use std::{convert::Infallible, net::IpAddr};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let service = ClientIpLayer::new().layer(service_fn(|ctx: Context<()>, _| async move {
+///     let client_ip = ctx.get::<ClientIp>().unwrap();
+///     assert_eq!(client_ip.ip(), IpAddr::from([12, 23, 34, 45]));
+///     Ok::<_, Infallible>(())
+/// }));
+///
+/// let req = Request::builder()
+///     .header("X-Real-IP", "12.23.34.45")
+///     .body(())
+///     .unwrap();
+/// service.serve(Context::default(), req).await.unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientIpLayer {
+    sources: Arc<[ClientIpSource]>,
+    trusted_proxies: TrustedProxies,
+}
+
+impl ClientIpLayer {
+    /// Create a new [`ClientIpLayer`] using the default sources and trusting all proxies.
+    pub fn new() -> Self {
+        Self {
+            sources: Arc::new(DEFAULT_SOURCES),
+            trusted_proxies: TrustedProxies::all(),
+        }
+    }
+
+    /// Set the [`ClientIpSource`]s to resolve the [`ClientIp`] from, in order of priority.
+    pub fn with_sources(mut self, sources: impl IntoIterator<Item = ClientIpSource>) -> Self {
+        self.sources = sources.into_iter().collect();
+        self
+    }
+
+    /// Set the [`ClientIpSource`]s to resolve the [`ClientIp`] from, in order of priority.
+    pub fn set_sources(&mut self, sources: impl IntoIterator<Item = ClientIpSource>) -> &mut Self {
+        self.sources = sources.into_iter().collect();
+        self
+    }
+
+    /// Only honor the headers reported by the given [`TrustedProxies`].
+    ///
+    /// See the [`ClientIpLayer`] documentation for more details.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Only honor the headers reported by the given [`TrustedProxies`].
+    ///
+    /// See the [`ClientIpLayer`] documentation for more details.
+    pub fn set_trusted_proxies(&mut self, trusted_proxies: TrustedProxies) -> &mut Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+}
+
+impl Default for ClientIpLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for ClientIpLayer {
+    type Service = ClientIpService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientIpService {
+            inner,
+            sources: self.sources.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+        }
+    }
+}
+
+/// Middleware [`Service`] which resolves the effective [`ClientIp`] of a request
+/// and inserts it in the [`Context`].
+///
+/// See [`ClientIpLayer`] for more information.
+pub struct ClientIpService<S> {
+    inner: S,
+    sources: Arc<[ClientIpSource]>,
+    trusted_proxies: TrustedProxies,
+}
+
+impl<S: fmt::Debug> fmt::Debug for ClientIpService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientIpService")
+            .field("inner", &self.inner)
+            .field("sources", &self.sources)
+            .field("trusted_proxies", &self.trusted_proxies)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for ClientIpService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            sources: self.sources.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
+        }
+    }
+}
+
+impl<S> ClientIpService<S> {
+    /// Create a new [`ClientIpService`] using the default sources and trusting all proxies.
+    pub fn new(inner: S) -> Self {
+        ClientIpLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+
+    /// Resolve the client IP from the configured sources, in order.
+    fn client_ip<State>(&self, ctx: &Context<State>, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer_ip = ctx
+            .get::<SocketInfo>()
+            .map(|socket_info| socket_info.peer_addr().ip());
+        let trusted_peer = self
+            .trusted_proxies
+            .client_ip_index(peer_ip, std::iter::empty())
+            .is_some();
+
+        self.sources.iter().find_map(|source| match source {
+            ClientIpSource::Forwarded => ctx.get::<Forwarded>()?.client_ip(),
+            ClientIpSource::XForwardedFor if trusted_peer => {
+                let ips: Vec<_> = headers
+                    .get_all(&X_FORWARDED_FOR)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .flat_map(|value| value.split(','))
+                    .map(parse_ip)
+                    .collect();
+                let index = self
+                    .trusted_proxies
+                    .client_ip_index(peer_ip, ips.iter().copied())?;
+                // in case the client is not known, take the leftmost known ip
+                ips.get(index..)?.iter().flatten().next().copied()
+            }
+            ClientIpSource::XRealIp if trusted_peer => {
+                parse_ip(headers.get(&X_REAL_IP)?.to_str().ok()?)
+            }
+            ClientIpSource::XForwardedFor | ClientIpSource::XRealIp => None,
+            ClientIpSource::Peer => peer_ip,
+        })
+    }
+}
+
+/// Parse a header value as IP address, skipping values with a port attached or otherwise invalid.
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    value.trim().parse().ok()
+}
+
+impl<S, State, Body> Service<State, Request<Body>> for ClientIpService<S>
+where
+    S: Service<State, Request<Body>>,
+    State: Clone + Send + Sync + 'static,
+    Body: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<Body>,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(ip) = self.client_ip(&ctx, req.headers()) {
+            ctx.insert(ClientIp::new(ip));
+        }
+        self.inner.serve(ctx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::service::service_fn;
+    use rama_net::forwarded::{ForwardedElement, NodeId};
+    use rama_net::stream::dep::ipnet::IpNet;
+    use std::convert::Infallible;
+
+    async fn resolve(
+        layer: ClientIpLayer,
+        peer: Option<&str>,
+        forwarded: Option<&str>,
+        headers: &[(&'static str, &'static str)],
+    ) -> Option<IpAddr> {
+        let mut ctx = Context::default();
+        if let Some(peer) = peer {
+            ctx.insert(SocketInfo::new(None, peer.parse().unwrap()));
+        }
+        if let Some(forwarded) = forwarded {
+            ctx.insert(Forwarded::new(ForwardedElement::forwarded_for(
+                NodeId::try_from_str(forwarded).unwrap(),
+            )));
+        }
+        let mut req = Request::builder();
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        layer
+            .layer(service_fn(|ctx: Context<()>, _: Request<()>| async move {
+                Ok::<_, Infallible>(ctx.get::<ClientIp>().map(ClientIp::ip))
+            }))
+            .serve(ctx, req.body(()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_priority() {
+        let peer = Some("10.0.0.1:4000");
+        let headers = &[
+            ("x-forwarded-for", "1.1.1.1, 10.0.0.2"),
+            ("x-real-ip", "2.2.2.2"),
+        ];
+
+        for (layer, forwarded, headers, expected) in [
+            (
+                ClientIpLayer::new(),
+                Some("3.3.3.3"),
+                &headers[..],
+                ip("3.3.3.3"),
+            ),
+            (ClientIpLayer::new(), None, headers, ip("1.1.1.1")),
+            (ClientIpLayer::new(), None, &headers[1..], ip("2.2.2.2")),
+            (ClientIpLayer::new(), None, &[], ip("10.0.0.1")),
+            (
+                ClientIpLayer::new()
+                    .with_sources([ClientIpSource::XRealIp, ClientIpSource::XForwardedFor]),
+                Some("3.3.3.3"),
+                headers,
+                ip("2.2.2.2"),
+            ),
+            (
+                ClientIpLayer::new().with_sources([ClientIpSource::Forwarded]),
+                None,
+                headers,
+                None,
+            ),
+        ] {
+            assert_eq!(
+                resolve(layer.clone(), peer, forwarded, headers).await,
+                expected,
+                "{layer:?} {forwarded:?} {headers:?}"
+            );
+        }
+        assert_eq!(resolve(ClientIpLayer::new(), None, None, &[]).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_skips_malformed_values() {
+        let peer = Some("10.0.0.1:4000");
+
+        for (headers, expected) in [
+            (&[("x-real-ip", "2.2.2.2:8080")][..], ip("10.0.0.1")),
+            (&[("x-real-ip", "[2001:db8::1]:8080")], ip("10.0.0.1")),
+            (&[("x-real-ip", "garbage")], ip("10.0.0.1")),
+            (&[("x-real-ip", " 2001:db8::1 ")], ip("2001:db8::1")),
+            (
+                &[("x-forwarded-for", "1.1.1.1:80"), ("x-real-ip", "2.2.2.2")],
+                ip("2.2.2.2"),
+            ),
+            (&[("x-forwarded-for", "garbage, 1.1.1.1")], ip("1.1.1.1")),
+            (&[("x-forwarded-for", "unknown,,foo")], ip("10.0.0.1")),
+            (&[("x-forwarded-for", "1.1.1.1, caf\u{e9}")], ip("10.0.0.1")),
+        ] {
+            assert_eq!(
+                resolve(ClientIpLayer::new(), peer, None, headers).await,
+                expected,
+                "{headers:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_trusted_proxies() {
+        let layer = ClientIpLayer::new().with_trusted_proxies(TrustedProxies::nets(["10.0.0.0/8"
+            .parse::<IpNet>()
+            .unwrap()]));
+        let headers = &[
+            ("x-forwarded-for", "6.6.6.6, 1.1.1.1, 10.0.0.2"),
+            ("x-real-ip", "2.2.2.2"),
+        ];
+
+        // spoofed elements of the client are ignored
+        assert_eq!(
+            resolve(layer.clone(), Some("10.0.0.1:4000"), None, headers).await,
+            ip("1.1.1.1")
+        );
+        // headers of untrusted peers are ignored
+        assert_eq!(
+            resolve(layer.clone(), Some("1.2.3.4:4000"), None, headers).await,
+            ip("1.2.3.4")
+        );
+        assert_eq!(resolve(layer, None, None, headers).await, None);
+    }
+}
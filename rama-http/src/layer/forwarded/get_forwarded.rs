@@ -1,10 +1,13 @@
+use super::TrustedProxies;
+use crate::header::{X_FORWARDED_HOST, X_FORWARDED_PORT};
 use crate::headers::{
     ForwardHeader, HeaderMapExt, Via, XForwardedFor, XForwardedHost, XForwardedProto,
 };
-use crate::Request;
+use crate::{HeaderMap, Request};
 use rama_core::{Context, Layer, Service};
 use rama_net::forwarded::Forwarded;
-use rama_net::forwarded::ForwardedElement;
+use rama_net::forwarded::{ForwardedAuthority, ForwardedElement};
+use rama_net::stream::SocketInfo;
 use rama_utils::macros::all_the_tuples_no_last_special_case;
 use std::fmt;
 use std::future::Future;
@@ -33,6 +36,11 @@ use std::marker::PhantomData;
 /// - [`GetForwardedHeadersLayer::x_forwarded_host`]: The canonical [`X-Forwarded-Host`] header [`RFC 7239`](https://tools.ietf.org/html/rfc7239#section-5.4).
 /// - [`GetForwardedHeadersLayer::x_forwarded_proto`]: The canonical [`X-Forwarded-Proto`] header [`RFC 7239`](https://tools.ietf.org/html/rfc7239#section-5.3).
 ///
+/// The legacy `X-Forwarded-Port` header is taken into account together with
+/// the [`X-Forwarded-Host`] header, as the port of the forwarded host,
+/// in case that host has no port of its own. Only the left-most value of
+/// comma-separated `X-Forwarded-*` headers is used.
+///
 /// Rama also has the following headers already implemented for you to use:
 ///
 /// > [`X-Real-Ip`], [`X-Client-Ip`], [`Client-Ip`], [`Cf-Connecting-Ip`] and [`True-Client-Ip`].
@@ -47,6 +55,15 @@ use std::marker::PhantomData;
 /// [`CF-Connecting-Ip`]: crate::headers::CFConnectingIp
 /// [`True-Client-Ip`]: crate::headers::TrueClientIp
 ///
+/// ## Trusted proxies
+///
+/// By default the forwarded information is trusted, regardless of who reported it.
+/// In case the server can be reached by clients directly, this allows them to spoof it.
+/// Use [`GetForwardedHeadersLayer::with_trusted_proxies`] to only honor the
+/// forwarded information reported by [`TrustedProxies`], in which case
+/// the specified headers are stripped from requests of untrusted peers,
+/// and the peer of the connection is recorded as the client instead.
+///
 /// ## Example
 ///
 /// This example shows you can extract the client IP from the `X-Forwarded-For`
@@ -82,12 +99,14 @@ use std::marker::PhantomData;
 /// }
 /// ```
 pub struct GetForwardedHeadersLayer<T = Forwarded> {
+    trusted_proxies: TrustedProxies,
     _headers: PhantomData<fn() -> T>,
 }
 
 impl<T: fmt::Debug> fmt::Debug for GetForwardedHeadersLayer<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("GetForwardedHeadersLayer")
+            .field("trusted_proxies", &self.trusted_proxies)
             .field(
                 "_headers",
                 &format_args!("{}", std::any::type_name::<fn() -> T>()),
@@ -99,6 +118,7 @@ impl<T: fmt::Debug> fmt::Debug for GetForwardedHeadersLayer<T> {
 impl<T: Clone> Clone for GetForwardedHeadersLayer<T> {
     fn clone(&self) -> Self {
         Self {
+            trusted_proxies: self.trusted_proxies.clone(),
             _headers: PhantomData,
         }
     }
@@ -114,9 +134,26 @@ impl<T> GetForwardedHeadersLayer<T> {
     /// Create a new `GetForwardedHeadersLayer` for the specified headers `T`.
     pub const fn new() -> Self {
         Self {
+            trusted_proxies: TrustedProxies::all(),
             _headers: PhantomData,
         }
     }
+
+    /// Only honor the forwarded information reported by the given [`TrustedProxies`].
+    ///
+    /// See the [`GetForwardedHeadersLayer`] documentation for more details.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Only honor the forwarded information reported by the given [`TrustedProxies`].
+    ///
+    /// See the [`GetForwardedHeadersLayer`] documentation for more details.
+    pub fn set_trusted_proxies(&mut self, trusted_proxies: TrustedProxies) -> &mut Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
 }
 
 impl GetForwardedHeadersLayer {
@@ -165,6 +202,7 @@ impl<H, S> Layer<S> for GetForwardedHeadersLayer<H> {
     fn layer(&self, inner: S) -> Self::Service {
         Self::Service {
             inner,
+            trusted_proxies: self.trusted_proxies.clone(),
             _headers: PhantomData,
         }
     }
@@ -175,6 +213,7 @@ impl<H, S> Layer<S> for GetForwardedHeadersLayer<H> {
 /// See [`GetForwardedHeadersLayer`] for more information.
 pub struct GetForwardedHeadersService<S, T = Forwarded> {
     inner: S,
+    trusted_proxies: TrustedProxies,
     _headers: PhantomData<fn() -> T>,
 }
 
@@ -182,6 +221,7 @@ impl<S: fmt::Debug, T> fmt::Debug for GetForwardedHeadersService<S, T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("GetForwardedHeadersService")
             .field("inner", &self.inner)
+            .field("trusted_proxies", &self.trusted_proxies)
             .field("_headers", &format_args!("{}", std::any::type_name::<T>()))
             .finish()
     }
@@ -191,6 +231,7 @@ impl<S: Clone, T> Clone for GetForwardedHeadersService<S, T> {
     fn clone(&self) -> Self {
         GetForwardedHeadersService {
             inner: self.inner.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
             _headers: PhantomData,
         }
     }
@@ -201,9 +242,26 @@ impl<S, T> GetForwardedHeadersService<S, T> {
     pub const fn new(inner: S) -> Self {
         Self {
             inner,
+            trusted_proxies: TrustedProxies::all(),
             _headers: PhantomData,
         }
     }
+
+    /// Only honor the forwarded information reported by the given [`TrustedProxies`].
+    ///
+    /// See the [`GetForwardedHeadersLayer`] documentation for more details.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Only honor the forwarded information reported by the given [`TrustedProxies`].
+    ///
+    /// See the [`GetForwardedHeadersLayer`] documentation for more details.
+    pub fn set_trusted_proxies(&mut self, trusted_proxies: TrustedProxies) -> &mut Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
 }
 
 impl<S> GetForwardedHeadersService<S> {
@@ -262,7 +320,7 @@ macro_rules! get_forwarded_service_for_tuple {
             fn serve(
                 &self,
                 mut ctx: Context<State>,
-                req: Request<Body>,
+                mut req: Request<Body>,
             ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
                 let mut forwarded_elements: Vec<ForwardedElement> = Vec::with_capacity(1);
 
@@ -281,21 +339,14 @@ macro_rules! get_forwarded_service_for_tuple {
                         for other in iter {
                             forwarded_elements.push(other);
                         }
+                        apply_x_forwarded_port::<$ty>(req.headers(), &mut forwarded_elements);
                     }
                 )*
 
-                if !forwarded_elements.is_empty() {
-                    match ctx.get_mut::<Forwarded>() {
-                        Some(ref mut f) => {
-                            f.extend(forwarded_elements);
-                        }
-                        None => {
-                            let mut it = forwarded_elements.into_iter();
-                            let mut forwarded = Forwarded::new(it.next().unwrap());
-                            forwarded.extend(it);
-                            ctx.insert(forwarded);
-                        }
-                    }
+                if !insert_forwarded(&mut ctx, &self.trusted_proxies, forwarded_elements) {
+                    $(
+                        remove_forward_header::<$ty>(req.headers_mut());
+                    )*
                 }
 
                 self.inner.serve(ctx, req)
@@ -317,32 +368,107 @@ where
     fn serve(
         &self,
         mut ctx: Context<State>,
-        req: Request<Body>,
+        mut req: Request<Body>,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
         let mut forwarded_elements: Vec<ForwardedElement> = Vec::with_capacity(1);
 
         if let Some(header) = req.headers().typed_get::<H>() {
             forwarded_elements.extend(header);
+            apply_x_forwarded_port::<H>(req.headers(), &mut forwarded_elements);
         }
 
-        if !forwarded_elements.is_empty() {
-            match ctx.get_mut::<Forwarded>() {
-                Some(ref mut f) => {
-                    f.extend(forwarded_elements);
-                }
-                None => {
-                    let mut it = forwarded_elements.into_iter();
-                    let mut forwarded = Forwarded::new(it.next().unwrap());
-                    forwarded.extend(it);
-                    ctx.insert(forwarded);
-                }
-            }
+        if !insert_forwarded(&mut ctx, &self.trusted_proxies, forwarded_elements) {
+            remove_forward_header::<H>(req.headers_mut());
         }
 
         self.inner.serve(ctx, req)
     }
 }
 
+/// Apply the port of the legacy `X-Forwarded-Port` header to the
+/// authority reported by the `X-Forwarded-Host` header `H`,
+/// in case that authority has no port of its own.
+fn apply_x_forwarded_port<H: ForwardHeader>(
+    headers: &HeaderMap,
+    forwarded_elements: &mut [ForwardedElement],
+) {
+    if *H::name() != X_FORWARDED_HOST {
+        return;
+    }
+    // the x-forwarded-host is merged into the first (client) element
+    let Some(element) = forwarded_elements.first_mut() else {
+        return;
+    };
+    let Some((host, None)) = element
+        .ref_forwarded_host()
+        .cloned()
+        .map(ForwardedAuthority::into_parts)
+    else {
+        return;
+    };
+    let Some(port) = headers
+        .get(&X_FORWARDED_PORT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|s| s.split(',').next())
+        .and_then(|s| s.trim().parse::<u16>().ok())
+        .filter(|port| *port != 0)
+    else {
+        return;
+    };
+    element.set_forwarded_host(ForwardedAuthority::new(host, Some(port)));
+}
+
+/// Remove the forward header `H` from the headers of a request of an untrusted peer,
+/// including the `X-Forwarded-Port` header which is read along with the `X-Forwarded-Host` header.
+fn remove_forward_header<H: ForwardHeader>(headers: &mut HeaderMap) {
+    headers.remove(H::name());
+    if *H::name() == X_FORWARDED_HOST {
+        headers.remove(&X_FORWARDED_PORT);
+    }
+}
+
+/// Insert the trusted forwarded elements, ordered from client to the closest proxy,
+/// into the [`Forwarded`] context, returning `false` in case the peer
+/// of the connection is not trusted to report forwarded information,
+/// in which case the peer is recorded as the client instead.
+fn insert_forwarded<State>(
+    ctx: &mut Context<State>,
+    trusted_proxies: &TrustedProxies,
+    mut forwarded_elements: Vec<ForwardedElement>,
+) -> bool {
+    let peer_addr = ctx.get::<SocketInfo>().map(|info| *info.peer_addr());
+    let trusted =
+        match trusted_proxies.client_index(peer_addr.map(|addr| addr.ip()), &forwarded_elements) {
+            Some(index) => {
+                forwarded_elements.drain(..index);
+                true
+            }
+            None => {
+                forwarded_elements.clear();
+                if let Some(peer_addr) = peer_addr.filter(|_| !ctx.contains::<Forwarded>()) {
+                    forwarded_elements.push(ForwardedElement::forwarded_for(peer_addr));
+                }
+                false
+            }
+        };
+
+    if !forwarded_elements.is_empty() {
+        match ctx.get_mut::<Forwarded>() {
+            Some(ref mut f) => {
+                f.extend(forwarded_elements);
+            }
+            None => {
+                let mut it = forwarded_elements.into_iter();
+                let mut forwarded = Forwarded::new(it.next().unwrap());
+                forwarded.extend(it);
+                ctx.insert(forwarded);
+            }
+        }
+    }
+
+    trusted
+}
+
 all_the_tuples_no_last_special_case!(get_forwarded_service_for_tuple);
 
 #[cfg(test)]
@@ -354,6 +480,7 @@ mod tests {
     };
     use rama_core::{error::OpaqueError, service::service_fn, Layer};
     use rama_net::forwarded::{ForwardedProtocol, ForwardedVersion};
+    use rama_net::stream::dep::ipnet::IpNet;
     use std::{convert::Infallible, net::IpAddr};
 
     fn assert_is_service<T: Service<(), Request<()>>>(_: T) {}
@@ -422,6 +549,52 @@ mod tests {
         service.serve(Context::default(), req).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_get_forwarded_header_ws_upgrade() {
+        use rama_net::http::RequestContext;
+
+        let service = GetForwardedHeadersLayer::forwarded().layer(service_fn(
+            |ctx: Context<()>, req: Request<()>| async move {
+                let request_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+                let expected = req.headers()["x-expected"].to_str().unwrap();
+                assert_eq!(
+                    format!("{} {}", request_ctx.protocol, request_ctx.authority),
+                    expected
+                );
+                Ok::<_, Infallible>(())
+            },
+        ));
+
+        for (uri, forwarded, expected) in [
+            (
+                "/chat",
+                Some("for=12.23.34.45;host=example.com;proto=wss"),
+                "wss example.com:443",
+            ),
+            (
+                "/chat",
+                Some("for=12.23.34.45;host=example.com;proto=ws"),
+                "ws example.com:80",
+            ),
+            ("ws://example.com/chat", None, "ws example.com:80"),
+            ("wss://example.com/chat", None, "wss example.com:443"),
+            ("wss://example.com:8443/chat", None, "wss example.com:8443"),
+        ] {
+            let mut builder = Request::builder()
+                .uri(uri)
+                .header("Connection", "upgrade")
+                .header("Upgrade", "websocket")
+                .header("x-expected", expected);
+            if let Some(forwarded) = forwarded {
+                builder = builder.header("Forwarded", forwarded);
+            }
+            service
+                .serve(Context::default(), builder.body(()).unwrap())
+                .await
+                .unwrap();
+        }
+    }
+
     #[tokio::test]
     async fn test_get_forwarded_header_via() {
         let service =
@@ -463,4 +636,269 @@ mod tests {
 
         service.serve(Context::default(), req).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_get_forwarded_header_x_forwarded_request_context() {
+        use rama_net::http::RequestContext;
+
+        let service = GetForwardedHeadersLayer::<(XForwardedHost, XForwardedProto)>::new().layer(
+            service_fn(|ctx: Context<()>, req: Request<()>| async move {
+                let request_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+                let expected = req.headers()["x-expected"].to_str().unwrap();
+                assert_eq!(
+                    format!("{} {}", request_ctx.protocol, request_ctx.authority),
+                    expected
+                );
+                Ok::<_, Infallible>(())
+            }),
+        );
+
+        for (uri, headers, expected) in [
+            // host only
+            (
+                "/",
+                vec![("x-forwarded-host", "example.com")],
+                "http example.com:80",
+            ),
+            // host with port
+            (
+                "/",
+                vec![("x-forwarded-host", "example.com:8080")],
+                "http example.com:8080",
+            ),
+            // host, proto and port
+            (
+                "/",
+                vec![
+                    ("x-forwarded-host", "example.com"),
+                    ("x-forwarded-proto", "https"),
+                    ("x-forwarded-port", "8443"),
+                ],
+                "https example.com:8443",
+            ),
+            // proto defines the default port
+            (
+                "/",
+                vec![
+                    ("x-forwarded-host", "example.com"),
+                    ("x-forwarded-proto", "https"),
+                ],
+                "https example.com:443",
+            ),
+            // left-most value of comma-separated lists
+            (
+                "/",
+                vec![
+                    ("x-forwarded-host", "example.com, proxy.internal"),
+                    ("x-forwarded-proto", "https,http"),
+                    ("x-forwarded-port", "8443, 80"),
+                ],
+                "https example.com:8443",
+            ),
+            // port 0 is ignored
+            (
+                "/",
+                vec![
+                    ("x-forwarded-host", "example.com"),
+                    ("x-forwarded-port", "0"),
+                ],
+                "http example.com:80",
+            ),
+            // the port of the x-forwarded-host has priority over the x-forwarded-port
+            (
+                "/",
+                vec![
+                    ("x-forwarded-host", "example.com:8080"),
+                    ("x-forwarded-port", "8443"),
+                ],
+                "http example.com:8080",
+            ),
+            // x-forwarded-host has priority over the host header
+            (
+                "/",
+                vec![
+                    ("host", "proxy.internal"),
+                    ("x-forwarded-host", "example.com"),
+                ],
+                "http example.com:80",
+            ),
+            // the x-forwarded-port only applies to the x-forwarded-host
+            (
+                "/",
+                vec![
+                    ("host", "example.com"),
+                    ("x-forwarded-proto", "https"),
+                    ("x-forwarded-port", "8443"),
+                ],
+                "https example.com:443",
+            ),
+            (
+                "http://example.com/",
+                vec![("x-forwarded-port", "8443")],
+                "http example.com:80",
+            ),
+            (
+                "http://example.com:8080/",
+                vec![
+                    ("x-forwarded-host", "other.com"),
+                    ("x-forwarded-port", "8443"),
+                ],
+                "http example.com:8080",
+            ),
+        ] {
+            let mut builder = Request::builder().uri(uri).header("x-expected", expected);
+            for (name, value) in headers {
+                builder = builder.header(name, value);
+            }
+            service
+                .serve(Context::default(), builder.body(()).unwrap())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_forwarded_header_x_forwarded_untrusted() {
+        use rama_net::http::RequestContext;
+
+        let service = GetForwardedHeadersLayer::<(XForwardedHost, XForwardedProto)>::new()
+            .with_trusted_proxies(TrustedProxies::nets(["10.0.0.0/8"
+                .parse::<IpNet>()
+                .unwrap()]))
+            .layer(service_fn(
+                |ctx: Context<()>, req: Request<()>| async move {
+                    assert!(!req.headers().contains_key("x-forwarded-host"));
+                    assert!(!req.headers().contains_key("x-forwarded-proto"));
+                    assert!(!req.headers().contains_key("x-forwarded-port"));
+                    let request_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+                    assert_eq!(request_ctx.protocol, rama_net::Protocol::HTTP);
+                    assert_eq!(request_ctx.authority.to_string(), "example.com:80");
+                    Ok::<_, Infallible>(())
+                },
+            ));
+
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, ([203, 0, 113, 7], 4000).into()));
+        let req = Request::builder()
+            .header("host", "example.com")
+            .header("x-forwarded-host", "evil.com")
+            .header("x-forwarded-proto", "https")
+            .header("x-forwarded-port", "8443")
+            .body(())
+            .unwrap();
+        service.serve(ctx, req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_forwarded_header_trusted_proxies() {
+        let service = GetForwardedHeadersLayer::x_forwarded_for()
+            .with_trusted_proxies(TrustedProxies::nets([
+                "10.0.0.0/8".parse::<IpNet>().unwrap(),
+                "fd00::/8".parse::<IpNet>().unwrap(),
+            ]))
+            .layer(service_fn(
+                |ctx: Context<()>, req: Request<()>| async move {
+                    let forwarded = ctx.get::<Forwarded>().unwrap();
+                    let expected = req.headers()["x-expected"].to_str().unwrap();
+                    assert_eq!(forwarded.client_ip().unwrap().to_string(), expected);
+                    Ok::<_, Infallible>(
+                        req.headers()
+                            .get("X-Forwarded-For")
+                            .map(|value| value.to_str().unwrap().to_owned()),
+                    )
+                },
+            ));
+
+        for (peer_addr, x_forwarded_for, expected_client, expected_header) in [
+            // spoofed header from an untrusted peer
+            ("203.0.113.7:4000", "1.2.3.4, 10.0.0.2", "203.0.113.7", None),
+            // legitimate chain of two trusted proxies, with a spoofed element prepended by the client
+            (
+                "10.0.0.1:4000",
+                "6.6.6.6, 1.2.3.4, 10.0.0.2",
+                "1.2.3.4",
+                Some("6.6.6.6, 1.2.3.4, 10.0.0.2"),
+            ),
+            (
+                "[fd00::1]:4000",
+                "2001:db8::1, fd00::2",
+                "2001:db8::1",
+                Some("2001:db8::1, fd00::2"),
+            ),
+        ] {
+            let mut ctx = Context::default();
+            ctx.insert(SocketInfo::new(None, peer_addr.parse().unwrap()));
+            let req = Request::builder()
+                .header("X-Forwarded-For", x_forwarded_for)
+                .header("x-expected", expected_client)
+                .body(())
+                .unwrap();
+
+            let header = service.serve(ctx, req).await.unwrap();
+            assert_eq!(header.as_deref(), expected_header);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_forwarded_header_trusted_hops() {
+        let service = GetForwardedHeadersLayer::forwarded()
+            .with_trusted_proxies(TrustedProxies::hops(2))
+            .layer(service_fn(|ctx: Context<()>, _| async move {
+                let forwarded = ctx.get::<Forwarded>().unwrap();
+                assert_eq!(forwarded.client_ip(), Some(IpAddr::from([1, 2, 3, 4])));
+                assert_eq!(forwarded.iter().count(), 4);
+                Ok::<_, Infallible>(())
+            }));
+
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, ([10, 0, 0, 1], 4000).into()));
+        let req = Request::builder()
+            .header(
+                "Forwarded",
+                "for=6.6.6.6, for=1.2.3.4;proto=https, for=unknown, for=_hidden, for=10.0.0.2",
+            )
+            .body(())
+            .unwrap();
+
+        service.serve(ctx, req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_forwarded_header_forwarded_in_process() {
+        use crate::{Body, BodyExtractExt};
+        use rama_http_backend::test_utils::InProcessConnection;
+        use rama_net::{http::RequestContext, stream::SocketInfo};
+
+        let service = GetForwardedHeadersLayer::forwarded().layer(service_fn(
+            |ctx: Context<()>, req: Request| async move {
+                let forwarded = ctx.get::<Forwarded>().unwrap();
+                assert_eq!(forwarded.client_ip(), Some(IpAddr::from([12, 23, 34, 45])));
+                assert_eq!(forwarded.client_proto(), Some(ForwardedProtocol::HTTP));
+
+                let peer_addr = *ctx.get::<SocketInfo>().unwrap().peer_addr();
+                let request_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+                Ok::<_, Infallible>(Response::new(Body::from(format!(
+                    "{peer_addr} {}",
+                    request_ctx.client_ip.unwrap()
+                ))))
+            },
+        ));
+
+        let conn = InProcessConnection::http1(Context::default(), service)
+            .await
+            .unwrap();
+
+        let req = Request::builder()
+            .uri("http://example.com/")
+            .header("Forwarded", "for=\"12.23.34.45:5000\";proto=http")
+            .body(Body::empty())
+            .unwrap();
+
+        let resp = conn.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.try_into_string().await.unwrap(),
+            "127.0.0.1:54321 12.23.34.45"
+        );
+    }
 }
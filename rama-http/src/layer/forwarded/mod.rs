@@ -1,11 +1,24 @@
 //! Middleware to support the reading and writing of Forwarded headers.
 //!
-//! See the [`GetForwardedHeadersLayer`] and [`SetForwardedHeadersLayer`] documentation for more details.
+//! See the [`GetForwardedHeadersLayer`], [`SetForwardedHeadersLayer`], [`ClientIpLayer`]
+//! and [`SetViaLayer`] documentation for more details.
 
 mod get_forwarded;
 #[doc(inline)]
 pub use get_forwarded::{GetForwardedHeadersLayer, GetForwardedHeadersService};
 
+mod trusted;
+#[doc(inline)]
+pub use trusted::TrustedProxies;
+
 mod set_forwarded;
 #[doc(inline)]
 pub use set_forwarded::{SetForwardedHeadersLayer, SetForwardedHeadersService};
+
+mod set_via;
+#[doc(inline)]
+pub use set_via::{SetViaLayer, SetViaService};
+
+mod client_ip;
+#[doc(inline)]
+pub use client_ip::{ClientIpLayer, ClientIpService, ClientIpSource};
@@ -5,7 +5,7 @@ use crate::Request;
 use rama_core::error::BoxError;
 use rama_core::{Context, Layer, Service};
 use rama_net::address::Domain;
-use rama_net::forwarded::{Forwarded, ForwardedElement, NodeId};
+use rama_net::forwarded::{Forwarded, ForwardedElement, NodeId, NodeObfuscation};
 use rama_net::http::RequestContext;
 use rama_net::stream::SocketInfo;
 use rama_utils::macros::all_the_tuples_no_last_special_case;
@@ -28,8 +28,18 @@ use std::marker::PhantomData;
 /// - [`SetForwardedHeadersLayer::x_forwarded_host`]: the canonical [`X-Forwarded-Host`][`XForwardedHost`] header (non-standard);
 /// - [`SetForwardedHeadersLayer::x_forwarded_proto`]: the canonical [`X-Forwarded-Proto`][`XForwardedProto`] header (non-standard).
 ///
+/// Use [`SetForwardedHeadersLayer::forwarded_and_legacy`] to also set the legacy
+/// `X-Forwarded-For`, `X-Forwarded-Host` and `X-Forwarded-Proto` headers, for upstreams
+/// which do not support the standard [`Forwarded`] header.
+///
 /// The "by" property is set to `rama` by default. Use [`SetForwardedHeadersLayer::forward_by`] to overwrite this,
-/// typically with the actual [`IPv4`]/[`IPv6`] address of your proxy.
+/// typically with the actual [`IPv4`]/[`IPv6`] address of your proxy or an obfuscated identifier (e.g. `_proxy`),
+/// or [`SetForwardedHeadersLayer::without_forward_by`] to omit it.
+/// The "for" property is set to the peer address, which can be obfuscated
+/// using [`SetForwardedHeadersLayer::forward_for_obfuscation`].
+///
+/// The element of this proxy is appended to the forwarded information found in the [`Context`],
+/// or otherwise to the header as received, if any.
 ///
 /// [`IPv4`]: std::net::Ipv4Addr
 /// [`IPv6`]: std::net::Ipv6Addr
@@ -84,7 +94,8 @@ use std::marker::PhantomData;
 /// # }
 /// ```
 pub struct SetForwardedHeadersLayer<T = Forwarded> {
-    by_node: NodeId,
+    by_node: Option<NodeId>,
+    for_obfuscation: NodeObfuscation,
     _headers: PhantomData<fn() -> T>,
 }
 
@@ -92,6 +103,7 @@ impl<T: fmt::Debug> fmt::Debug for SetForwardedHeadersLayer<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("SetForwardedHeadersLayer")
             .field("by_node", &self.by_node)
+            .field("for_obfuscation", &self.for_obfuscation)
             .field(
                 "_headers",
                 &format_args!("{}", std::any::type_name::<fn() -> T>()),
@@ -104,6 +116,7 @@ impl<T: Clone> Clone for SetForwardedHeadersLayer<T> {
     fn clone(&self) -> Self {
         Self {
             by_node: self.by_node.clone(),
+            for_obfuscation: self.for_obfuscation.clone(),
             _headers: PhantomData,
         }
     }
@@ -114,7 +127,7 @@ impl<T> SetForwardedHeadersLayer<T> {
     ///
     /// Default of `None` will be set to `rama` otherwise.
     pub fn forward_by(mut self, node_id: impl Into<NodeId>) -> Self {
-        self.by_node = node_id.into();
+        self.by_node = Some(node_id.into());
         self
     }
 
@@ -122,7 +135,35 @@ impl<T> SetForwardedHeadersLayer<T> {
     ///
     /// Default of `None` will be set to `rama` otherwise.
     pub fn set_forward_by(&mut self, node_id: impl Into<NodeId>) -> &mut Self {
-        self.by_node = node_id.into();
+        self.by_node = Some(node_id.into());
+        self
+    }
+
+    /// Omit the "by" property, not identifying this proxy at all.
+    pub fn without_forward_by(mut self) -> Self {
+        self.by_node = None;
+        self
+    }
+
+    /// Omit the "by" property, not identifying this proxy at all.
+    pub fn unset_forward_by(&mut self) -> &mut Self {
+        self.by_node = None;
+        self
+    }
+
+    /// Set the [`NodeObfuscation`] used to expose the peer address as the "for" property.
+    ///
+    /// By default the peer address is revealed.
+    pub fn forward_for_obfuscation(mut self, obfuscation: NodeObfuscation) -> Self {
+        self.for_obfuscation = obfuscation;
+        self
+    }
+
+    /// Set the [`NodeObfuscation`] used to expose the peer address as the "for" property.
+    ///
+    /// By default the peer address is revealed.
+    pub fn set_forward_for_obfuscation(&mut self, obfuscation: NodeObfuscation) -> &mut Self {
+        self.for_obfuscation = obfuscation;
         self
     }
 }
@@ -131,7 +172,8 @@ impl<T> SetForwardedHeadersLayer<T> {
     /// Create a new `SetForwardedHeadersLayer` for the specified headers `T`.
     pub fn new() -> Self {
         Self {
-            by_node: Domain::from_static("rama").into(),
+            by_node: Some(Domain::from_static("rama").into()),
+            for_obfuscation: NodeObfuscation::Reveal,
             _headers: PhantomData,
         }
     }
@@ -151,6 +193,19 @@ impl SetForwardedHeadersLayer {
     }
 }
 
+impl SetForwardedHeadersLayer<(Forwarded, XForwardedFor, XForwardedHost, XForwardedProto)> {
+    #[inline]
+    /// Create a new `SetForwardedHeadersLayer` for the standard [`Forwarded`] header,
+    /// as well as the legacy [`X-Forwarded-For`], [`X-Forwarded-Host`] and [`X-Forwarded-Proto`] headers.
+    ///
+    /// [`X-Forwarded-For`]: XForwardedFor
+    /// [`X-Forwarded-Host`]: XForwardedHost
+    /// [`X-Forwarded-Proto`]: XForwardedProto
+    pub fn forwarded_and_legacy() -> Self {
+        Self::new()
+    }
+}
+
 impl SetForwardedHeadersLayer<Via> {
     #[inline]
     /// Create a new `SetForwardedHeadersLayer` for the canonical [`Via`] header.
@@ -190,6 +245,7 @@ impl<H, S> Layer<S> for SetForwardedHeadersLayer<H> {
         Self::Service {
             inner,
             by_node: self.by_node.clone(),
+            for_obfuscation: self.for_obfuscation.clone(),
             _headers: PhantomData,
         }
     }
@@ -201,7 +257,8 @@ impl<H, S> Layer<S> for SetForwardedHeadersLayer<H> {
 /// See [`SetForwardedHeadersLayer`] for more information.
 pub struct SetForwardedHeadersService<S, T = Forwarded> {
     inner: S,
-    by_node: NodeId,
+    by_node: Option<NodeId>,
+    for_obfuscation: NodeObfuscation,
     _headers: PhantomData<fn() -> T>,
 }
 
@@ -210,6 +267,7 @@ impl<S: fmt::Debug, T> fmt::Debug for SetForwardedHeadersService<S, T> {
         f.debug_struct("SetForwardedHeadersService")
             .field("inner", &self.inner)
             .field("by_node", &self.by_node)
+            .field("for_obfuscation", &self.for_obfuscation)
             .field(
                 "_headers",
                 &format_args!("{}", std::any::type_name::<fn() -> T>()),
@@ -223,6 +281,7 @@ impl<S: Clone, T> Clone for SetForwardedHeadersService<S, T> {
         SetForwardedHeadersService {
             inner: self.inner.clone(),
             by_node: self.by_node.clone(),
+            for_obfuscation: self.for_obfuscation.clone(),
             _headers: PhantomData,
         }
     }
@@ -233,7 +292,7 @@ impl<S, T> SetForwardedHeadersService<S, T> {
     ///
     /// Default of `None` will be set to `rama` otherwise.
     pub fn forward_by(mut self, node_id: impl Into<NodeId>) -> Self {
-        self.by_node = node_id.into();
+        self.by_node = Some(node_id.into());
         self
     }
 
@@ -241,9 +300,94 @@ impl<S, T> SetForwardedHeadersService<S, T> {
     ///
     /// Default of `None` will be set to `rama` otherwise.
     pub fn set_forward_by(&mut self, node_id: impl Into<NodeId>) -> &mut Self {
-        self.by_node = node_id.into();
+        self.by_node = Some(node_id.into());
+        self
+    }
+
+    /// Omit the "by" property, not identifying this proxy at all.
+    pub fn without_forward_by(mut self) -> Self {
+        self.by_node = None;
+        self
+    }
+
+    /// Omit the "by" property, not identifying this proxy at all.
+    pub fn unset_forward_by(&mut self) -> &mut Self {
+        self.by_node = None;
+        self
+    }
+
+    /// Set the [`NodeObfuscation`] used to expose the peer address as the "for" property.
+    ///
+    /// By default the peer address is revealed.
+    pub fn forward_for_obfuscation(mut self, obfuscation: NodeObfuscation) -> Self {
+        self.for_obfuscation = obfuscation;
+        self
+    }
+
+    /// Set the [`NodeObfuscation`] used to expose the peer address as the "for" property.
+    ///
+    /// By default the peer address is revealed.
+    pub fn set_forward_for_obfuscation(&mut self, obfuscation: NodeObfuscation) -> &mut Self {
+        self.for_obfuscation = obfuscation;
         self
     }
+
+    /// Create the [`ForwardedElement`] of this proxy for the given request.
+    fn forwarded_element<State, Body>(
+        &self,
+        ctx: &mut Context<State>,
+        req: &Request<Body>,
+    ) -> Result<ForwardedElement, BoxError>
+    where
+        State: Clone + Send + Sync + 'static,
+    {
+        let peer_addr = ctx.get::<SocketInfo>().map(|socket| *socket.peer_addr());
+
+        let request_ctx: &mut RequestContext =
+            ctx.get_or_try_insert_with_ctx(|ctx| (ctx, req).try_into())?;
+
+        let mut forwarded_element = ForwardedElement::forwarded_host(request_ctx.authority.clone());
+
+        if let Some(by_node) = &self.by_node {
+            forwarded_element.set_forwarded_by(by_node.clone());
+        }
+
+        if let Some(peer_addr) = peer_addr {
+            forwarded_element.set_forwarded_for(self.for_obfuscation.node_id(peer_addr));
+        }
+
+        if let Ok(forwarded_proto) = (&request_ctx.protocol).try_into() {
+            forwarded_element.set_forwarded_proto(forwarded_proto);
+        }
+
+        Ok(forwarded_element)
+    }
+}
+
+/// Set the header `H` to the given [`Forwarded`] information, with the given element appended,
+/// or in case there is none, append the element to the header as received, if any.
+fn set_forwarded_header<H: ForwardHeader, Body>(
+    req: &mut Request<Body>,
+    forwarded: Option<&Forwarded>,
+    forwarded_element: &ForwardedElement,
+) {
+    let header = match forwarded {
+        Some(forwarded) => {
+            H::try_from_forwarded(forwarded.iter().chain(std::iter::once(forwarded_element)))
+        }
+        None => {
+            let received: Vec<_> = req
+                .headers()
+                .typed_get::<H>()
+                .into_iter()
+                .flatten()
+                .collect();
+            H::try_from_forwarded(received.iter().chain(std::iter::once(forwarded_element)))
+        }
+    };
+    if let Some(header) = header {
+        req.headers_mut().typed_insert(header);
+    }
 }
 
 impl<S, T> SetForwardedHeadersService<S, T> {
@@ -251,7 +395,8 @@ impl<S, T> SetForwardedHeadersService<S, T> {
     pub fn new(inner: S) -> Self {
         Self {
             inner,
-            by_node: Domain::from_static("rama").into(),
+            by_node: Some(Domain::from_static("rama").into()),
+            for_obfuscation: NodeObfuscation::Reveal,
             _headers: PhantomData,
         }
     }
@@ -265,6 +410,19 @@ impl<S> SetForwardedHeadersService<S> {
     }
 }
 
+impl<S> SetForwardedHeadersService<S, (Forwarded, XForwardedFor, XForwardedHost, XForwardedProto)> {
+    #[inline]
+    /// Create a new `SetForwardedHeadersService` for the standard [`Forwarded`] header,
+    /// as well as the legacy [`X-Forwarded-For`], [`X-Forwarded-Host`] and [`X-Forwarded-Proto`] headers.
+    ///
+    /// [`X-Forwarded-For`]: XForwardedFor
+    /// [`X-Forwarded-Host`]: XForwardedHost
+    /// [`X-Forwarded-Proto`]: XForwardedProto
+    pub fn forwarded_and_legacy(inner: S) -> Self {
+        Self::new(inner)
+    }
+}
+
 impl<S> SetForwardedHeadersService<S, Via> {
     #[inline]
     /// Create a new `SetForwardedHeadersService` for the canonical [`Via`] header.
@@ -312,35 +470,8 @@ where
         mut ctx: Context<State>,
         mut req: Request<Body>,
     ) -> Result<Self::Response, Self::Error> {
-        let forwarded: Option<Forwarded> = ctx.get().cloned();
-
-        let mut forwarded_element = ForwardedElement::forwarded_by(self.by_node.clone());
-
-        if let Some(peer_addr) = ctx.get::<SocketInfo>().map(|socket| *socket.peer_addr()) {
-            forwarded_element.set_forwarded_for(peer_addr);
-        }
-        let request_ctx: &mut RequestContext =
-            ctx.get_or_try_insert_with_ctx(|ctx| (ctx, &req).try_into())?;
-
-        forwarded_element.set_forwarded_host(request_ctx.authority.clone());
-
-        if let Ok(forwarded_proto) = (&request_ctx.protocol).try_into() {
-            forwarded_element.set_forwarded_proto(forwarded_proto);
-        }
-
-        let forwarded = match forwarded {
-            None => Some(Forwarded::new(forwarded_element)),
-            Some(mut forwarded) => {
-                forwarded.append(forwarded_element);
-                Some(forwarded)
-            }
-        };
-
-        if let Some(forwarded) = forwarded {
-            if let Some(header) = H::try_from_forwarded(forwarded.iter()) {
-                req.headers_mut().typed_insert(header);
-            }
-        }
+        let forwarded_element = self.forwarded_element(&mut ctx, &req)?;
+        set_forwarded_header::<H, _>(&mut req, ctx.get(), &forwarded_element);
 
         self.inner.serve(ctx, req).await.map_err(Into::into)
     }
@@ -364,38 +495,10 @@ macro_rules! set_forwarded_service_for_tuple {
                 mut ctx: Context<State>,
                 mut req: Request<Body>,
             ) -> Result<Self::Response, Self::Error> {
-                let forwarded: Option<Forwarded> = ctx.get().cloned();
-
-                let mut forwarded_element = ForwardedElement::forwarded_by(self.by_node.clone());
-
-                if let Some(peer_addr) = ctx.get::<SocketInfo>().map(|socket| *socket.peer_addr()) {
-                    forwarded_element.set_forwarded_for(peer_addr);
-                }
-
-                let request_ctx: &mut RequestContext =
-                    ctx.get_or_try_insert_with_ctx(|ctx| (ctx, &req).try_into())?;
-
-                forwarded_element.set_forwarded_host(request_ctx.authority.clone());
-
-                if let Ok(forwarded_proto) = (&request_ctx.protocol).try_into() {
-                    forwarded_element.set_forwarded_proto(forwarded_proto);
-                }
-
-                let forwarded = match forwarded {
-                    None => Some(Forwarded::new(forwarded_element)),
-                    Some(mut forwarded) => {
-                        forwarded.append(forwarded_element);
-                        Some(forwarded)
-                    }
-                };
-
-                if let Some(forwarded) = forwarded {
-                    $(
-                        if let Some(header) = $ty::try_from_forwarded(forwarded.iter()) {
-                            req.headers_mut().typed_insert(header);
-                        }
-                    )*
-                }
+                let forwarded_element = self.forwarded_element(&mut ctx, &req)?;
+                $(
+                    set_forwarded_header::<$ty, _>(&mut req, ctx.get(), &forwarded_element);
+                )*
 
                 self.inner.serve(ctx, req).await.map_err(Into::into)
             }
@@ -559,4 +662,128 @@ mod tests {
         ctx.insert(SocketInfo::new(None, "127.0.0.1:62345".parse().unwrap()));
         service.serve(ctx, req).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_set_forwarded_service_appends_to_received_header() {
+        async fn svc(request: Request<()>) -> Result<(), Infallible> {
+            assert_eq!(
+                request.headers().get("Forwarded").unwrap(),
+                "for=12.23.34.45,for=\"[2001:db8::1]:62345\";host=\"www.example.com:443\";proto=https",
+            );
+            Ok(())
+        }
+
+        let service = SetForwardedHeadersService::forwarded(service_fn(svc)).without_forward_by();
+        let req = Request::builder()
+            .uri("https://www.example.com")
+            .header("Forwarded", "for=12.23.34.45")
+            .body(())
+            .unwrap();
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(
+            None,
+            "[2001:db8::1]:62345".parse().unwrap(),
+        ));
+        service.serve(ctx, req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_set_forwarded_round_trip() {
+        use crate::layer::forwarded::GetForwardedHeadersLayer;
+        use rama_net::forwarded::ForwardedProtocol;
+
+        // what one proxy emits, the next one parses
+        let upstream = GetForwardedHeadersLayer::forwarded().layer(service_fn(
+            |ctx: Context<()>, req: Request<()>| async move {
+                let forwarded = ctx.get::<Forwarded>().unwrap();
+                let expected = req.headers()["x-expected"].to_str().unwrap();
+                let last = forwarded.iter().last().unwrap();
+                let by = last
+                    .ref_forwarded_by()
+                    .map(|node| node.to_string())
+                    .unwrap_or_default();
+                assert_eq!(
+                    format!(
+                        "{} {} {by} {}",
+                        forwarded.iter().count(),
+                        last.ref_forwarded_for().unwrap(),
+                        last.ref_forwarded_host().unwrap(),
+                    ),
+                    expected,
+                );
+                assert_eq!(last.ref_forwarded_proto(), Some(ForwardedProtocol::HTTPS));
+                Ok::<_, Infallible>(())
+            },
+        ));
+
+        for (layer, peer_addr, received, expected) in [
+            (
+                SetForwardedHeadersLayer::forwarded().forward_by(IpAddr::from([10, 0, 0, 1])),
+                "[2001:db8::1]:62345",
+                None,
+                "1 [2001:db8::1]:62345 10.0.0.1 www.example.com:443",
+            ),
+            (
+                SetForwardedHeadersLayer::forwarded()
+                    .forward_by(NodeId::try_from_str("_proxy").unwrap()),
+                "12.23.34.45:62345",
+                Some("for=unknown;by=_edge"),
+                "2 12.23.34.45:62345 _proxy www.example.com:443",
+            ),
+            (
+                SetForwardedHeadersLayer::forwarded()
+                    .without_forward_by()
+                    .forward_for_obfuscation(NodeObfuscation::Replace(
+                        NodeId::try_from_str("_client").unwrap(),
+                    )),
+                "12.23.34.45:62345",
+                Some("for=\"[2001:db8::2]\""),
+                "2 _client  www.example.com:443",
+            ),
+        ] {
+            let service = layer.layer(upstream.clone());
+            let mut builder = Request::builder()
+                .uri("https://www.example.com")
+                .header("x-expected", expected);
+            if let Some(received) = received {
+                builder = builder.header("Forwarded", received);
+            }
+            let mut ctx = Context::default();
+            ctx.insert(SocketInfo::new(None, peer_addr.parse().unwrap()));
+            service.serve(ctx, builder.body(()).unwrap()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_forwarded_and_legacy_round_trip() {
+        use crate::layer::forwarded::GetForwardedHeadersLayer;
+
+        let service = SetForwardedHeadersLayer::forwarded_and_legacy().layer(
+            GetForwardedHeadersLayer::<(XForwardedFor, XForwardedHost, XForwardedProto)>::new()
+                .layer(service_fn(
+                    |ctx: Context<()>, req: Request<()>| async move {
+                        assert!(req.headers().contains_key("Forwarded"));
+                        let forwarded = ctx.get::<Forwarded>().unwrap();
+                        assert_eq!(forwarded.client_ip(), Some(IpAddr::from([12, 23, 34, 45])));
+                        assert_eq!(
+                            forwarded.client_host().unwrap().to_string(),
+                            "www.example.com:443"
+                        );
+                        assert_eq!(
+                            forwarded.client_proto().map(|proto| proto.to_string()),
+                            Some("https".to_owned())
+                        );
+                        Ok::<_, Infallible>(())
+                    },
+                )),
+        );
+
+        let req = Request::builder()
+            .uri("https://www.example.com")
+            .body(())
+            .unwrap();
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, "12.23.34.45:62345".parse().unwrap()));
+        service.serve(ctx, req).await.unwrap();
+    }
 }
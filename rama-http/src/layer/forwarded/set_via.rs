@@ -0,0 +1,372 @@
+use crate::headers::{Header, HeaderMapExt, Via, ViaElement};
+use crate::{HeaderMap, HeaderValue, Request, Response, Version};
+use rama_core::{Context, Layer, Service};
+use rama_net::address::Domain;
+use rama_net::forwarded::{ForwardedVersion, NodeId};
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Layer to append a [`Via`] element for this proxy, as required by
+/// [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-7.6.3),
+/// to both the requests it forwards and the responses it relays.
+///
+/// The element consists of the protocol version of the received message
+/// and the pseudonym of this proxy, e.g. `1.1 my-proxy`. The pseudonym
+/// defaults to the hostname of this machine, falling back to `rama` if unknown,
+/// and can be set using [`SetViaLayer::with_pseudonym`].
+///
+/// The [`Via`] header of a request, as received, is inserted in the [`Context`].
+///
+/// Enable [`SetViaLayer::with_skip_duplicate`] to not append an element in case
+/// the last element was already added by a proxy with the same pseudonym,
+/// e.g. to avoid growing the header when a message loops through this proxy.
+///
+/// ## Example
+///
+/// ```rust
+/// use rama_core::{service::service_fn, Context, Layer, Service};
+/// use rama_http::{layer::forwarded::SetViaLayer, Body, Request, Response};
+/// use rama_net::address::Domain;
+/// use std::convert::Infallible;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// async fn svc(_ctx: Context<()>, req: Request) -> Result<Response, Infallible> {
+///     let via: Vec<_> = req.headers().get_all("via").iter().collect();
+///     assert_eq!(via, ["1.0 fred", "1.1 my-proxy"]);
+///     Ok(Response::new(Body::empty()))
+/// }
+///
+/// let service = SetViaLayer::new()
+///     .with_pseudonym(Domain::from_static("my-proxy"))
+///     .layer(service_fn(svc));
+///
+/// let req = Request::builder()
+///     .header("via", "1.0 fred")
+///     .body(Body::empty())
+///     .unwrap();
+/// let res = service.serve(Context::default(), req).await.unwrap();
+/// assert_eq!(res.headers()["via"], "1.1 my-proxy");
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SetViaLayer {
+    pseudonym: NodeId,
+    skip_duplicate: bool,
+}
+
+impl SetViaLayer {
+    /// Create a new [`SetViaLayer`], using the hostname as pseudonym.
+    pub fn new() -> Self {
+        Self {
+            pseudonym: default_pseudonym().clone(),
+            skip_duplicate: false,
+        }
+    }
+
+    /// Set the pseudonym (or host) identifying this proxy.
+    ///
+    /// Defaults to the hostname of this machine, or `rama` if unknown.
+    pub fn with_pseudonym(mut self, pseudonym: impl Into<NodeId>) -> Self {
+        self.pseudonym = pseudonym.into();
+        self
+    }
+
+    /// Set the pseudonym (or host) identifying this proxy.
+    ///
+    /// Defaults to the hostname of this machine, or `rama` if unknown.
+    pub fn set_pseudonym(&mut self, pseudonym: impl Into<NodeId>) -> &mut Self {
+        self.pseudonym = pseudonym.into();
+        self
+    }
+
+    /// Skip appending an element in case the pseudonym of this proxy
+    /// is already the last element, disabled by default.
+    pub fn with_skip_duplicate(mut self, skip: bool) -> Self {
+        self.skip_duplicate = skip;
+        self
+    }
+
+    /// Skip appending an element in case the pseudonym of this proxy
+    /// is already the last element, disabled by default.
+    pub fn set_skip_duplicate(&mut self, skip: bool) -> &mut Self {
+        self.skip_duplicate = skip;
+        self
+    }
+}
+
+impl Default for SetViaLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for SetViaLayer {
+    type Service = SetViaService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetViaService {
+            inner,
+            pseudonym: self.pseudonym.clone(),
+            skip_duplicate: self.skip_duplicate,
+        }
+    }
+}
+
+/// Middleware [`Service`] to append a [`Via`] element for this proxy
+/// to both the requests it forwards and the responses it relays.
+///
+/// See [`SetViaLayer`] for more information.
+pub struct SetViaService<S> {
+    inner: S,
+    pseudonym: NodeId,
+    skip_duplicate: bool,
+}
+
+impl<S: fmt::Debug> fmt::Debug for SetViaService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetViaService")
+            .field("inner", &self.inner)
+            .field("pseudonym", &self.pseudonym)
+            .field("skip_duplicate", &self.skip_duplicate)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for SetViaService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            pseudonym: self.pseudonym.clone(),
+            skip_duplicate: self.skip_duplicate,
+        }
+    }
+}
+
+impl<S> SetViaService<S> {
+    /// Create a new [`SetViaService`], using the hostname as pseudonym.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            pseudonym: default_pseudonym().clone(),
+            skip_duplicate: false,
+        }
+    }
+
+    /// Set the pseudonym (or host) identifying this proxy.
+    ///
+    /// Defaults to the hostname of this machine, or `rama` if unknown.
+    pub fn with_pseudonym(mut self, pseudonym: impl Into<NodeId>) -> Self {
+        self.pseudonym = pseudonym.into();
+        self
+    }
+
+    /// Set the pseudonym (or host) identifying this proxy.
+    ///
+    /// Defaults to the hostname of this machine, or `rama` if unknown.
+    pub fn set_pseudonym(&mut self, pseudonym: impl Into<NodeId>) -> &mut Self {
+        self.pseudonym = pseudonym.into();
+        self
+    }
+
+    /// Skip appending an element in case the pseudonym of this proxy
+    /// is already the last element, disabled by default.
+    pub fn with_skip_duplicate(mut self, skip: bool) -> Self {
+        self.skip_duplicate = skip;
+        self
+    }
+
+    /// Skip appending an element in case the pseudonym of this proxy
+    /// is already the last element, disabled by default.
+    pub fn set_skip_duplicate(&mut self, skip: bool) -> &mut Self {
+        self.skip_duplicate = skip;
+        self
+    }
+
+    define_inner_service_accessors!();
+
+    /// Append the element of this proxy to the [`Via`] header(s) of a message
+    /// received with the given version, keeping the received values as-is.
+    fn append_via(&self, headers: &mut HeaderMap, version: Version, received: Option<&Via>) {
+        let Ok(version) = ForwardedVersion::try_from(version) else {
+            tracing::trace!(?version, "skip via element for unsupported http version");
+            return;
+        };
+        if self.skip_duplicate
+            && received
+                .and_then(Via::last)
+                .is_some_and(|last| last.received_by() == &self.pseudonym)
+        {
+            return;
+        }
+
+        let element = ViaElement::new(version, self.pseudonym.clone()).to_string();
+        match HeaderValue::try_from(element) {
+            Ok(value) => {
+                headers.append(Via::name(), value);
+            }
+            Err(err) => tracing::debug!(%err, "failed to encode via element as header value"),
+        }
+    }
+}
+
+impl<S, State, ReqBody, ResBody> Service<State, Request<ReqBody>> for SetViaService<S>
+where
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let received = req.headers().typed_get::<Via>();
+        let version = req.version();
+        self.append_via(req.headers_mut(), version, received.as_ref());
+        if let Some(received) = received {
+            ctx.insert(received);
+        }
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        let received = res.headers().typed_get::<Via>();
+        let version = res.version();
+        self.append_via(res.headers_mut(), version, received.as_ref());
+
+        Ok(res)
+    }
+}
+
+/// The hostname of this machine as [`NodeId`], or `rama` if unknown.
+fn default_pseudonym() -> &'static NodeId {
+    static PSEUDONYM: OnceLock<NodeId> = OnceLock::new();
+    PSEUDONYM.get_or_init(|| {
+        std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|hostname| hostname.trim().to_owned())
+            .filter(|hostname| !hostname.is_empty())
+            .map(|hostname| NodeId::from_str_lossy(&hostname))
+            .unwrap_or_else(|| Domain::from_static("rama").into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{header::VIA, Body};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn via(headers: &HeaderMap) -> Vec<&str> {
+        headers
+            .get_all(VIA)
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect()
+    }
+
+    async fn serve(
+        layer: SetViaLayer,
+        version: Version,
+        received: Option<&'static str>,
+        expected_req_via: &'static [&'static str],
+    ) -> Response {
+        let mut req = Request::builder().version(version);
+        if let Some(received) = received {
+            req = req.header(VIA, received);
+        }
+        let req = req.body(Body::empty()).unwrap();
+
+        layer
+            .with_pseudonym(Domain::from_static("my-proxy"))
+            .layer(service_fn(
+                move |ctx: Context<()>, req: Request| async move {
+                    assert_eq!(via(req.headers()), expected_req_via);
+                    // the via header as received is available as typed extension
+                    assert_eq!(
+                        ctx.get::<Via>(),
+                        received
+                            .map(|value| {
+                                Via::decode(&mut std::iter::once(&HeaderValue::from_static(value)))
+                                    .unwrap()
+                            })
+                            .as_ref()
+                    );
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .header(VIA, "1.1 upstream (Upstream, v1)")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                },
+            ))
+            .serve(Context::default(), req)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_set_via_on_request_and_response() {
+        let res = serve(
+            SetViaLayer::new(),
+            Version::HTTP_11,
+            None,
+            &["1.1 my-proxy"],
+        )
+        .await;
+        assert_eq!(
+            via(res.headers()),
+            ["1.1 upstream (Upstream, v1)", "1.1 my-proxy"]
+        );
+
+        let res = serve(
+            SetViaLayer::new(),
+            Version::HTTP_2,
+            Some("1.0 fred, 1.1 p.example.net"),
+            &["1.0 fred, 1.1 p.example.net", "2 my-proxy"],
+        )
+        .await;
+        assert_eq!(
+            via(res.headers()),
+            ["1.1 upstream (Upstream, v1)", "1.1 my-proxy"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_via_skip_duplicate() {
+        serve(
+            SetViaLayer::new(),
+            Version::HTTP_11,
+            Some("1.0 fred, 1.1 my-proxy"),
+            &["1.0 fred, 1.1 my-proxy", "1.1 my-proxy"],
+        )
+        .await;
+
+        serve(
+            SetViaLayer::new().with_skip_duplicate(true),
+            Version::HTTP_11,
+            Some("1.0 fred, 1.1 my-proxy"),
+            &["1.0 fred, 1.1 my-proxy"],
+        )
+        .await;
+
+        let res = serve(
+            SetViaLayer::new().with_skip_duplicate(true),
+            Version::HTTP_11,
+            Some("1.1 my-proxy, 1.0 fred"),
+            &["1.1 my-proxy, 1.0 fred", "1.1 my-proxy"],
+        )
+        .await;
+        assert_eq!(
+            via(res.headers()),
+            ["1.1 upstream (Upstream, v1)", "1.1 my-proxy"]
+        );
+    }
+}
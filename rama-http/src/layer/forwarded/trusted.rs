@@ -0,0 +1,197 @@
+use rama_net::forwarded::{ForwardedElement, NodeId};
+use rama_net::stream::dep::ipnet::IpNet;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
+/// Configures which proxies are trusted to report forwarded information,
+/// used by the [`GetForwardedHeadersLayer`] to protect against spoofed headers.
+///
+/// The forwarded chain is walked from the proxy closest to this server
+/// towards the client, until the first node which is not a trusted proxy
+/// is found, which is considered the client. Elements reported before it
+/// could have been spoofed by that client, and are therefore dropped.
+///
+/// Nodes identified as `unknown` or by an obfuscated identifier
+/// (e.g. `_hidden`), as defined in [RFC 7239], can not be verified
+/// and are skipped when walking the chain.
+///
+/// [`GetForwardedHeadersLayer`]: super::GetForwardedHeadersLayer
+/// [RFC 7239]: https://datatracker.ietf.org/doc/html/rfc7239#section-6
+pub struct TrustedProxies(Kind);
+
+#[derive(Debug, Clone, Default)]
+enum Kind {
+    #[default]
+    All,
+    Nets(Arc<[IpNet]>),
+    Hops(usize),
+}
+
+impl TrustedProxies {
+    /// Trust all forwarded information, regardless of the peer which reported it.
+    ///
+    /// This is the default, and only safe to use in case this server
+    /// is not reachable other than via trusted proxies.
+    pub const fn all() -> Self {
+        Self(Kind::All)
+    }
+
+    /// Only trust proxies with an IP address within one of the given (IPv4 or IPv6) networks.
+    ///
+    /// Forwarded information is ignored altogether in case the
+    /// peer of the connection is not within one of these networks.
+    pub fn nets(nets: impl IntoIterator<Item = impl Into<IpNet>>) -> Self {
+        Self(Kind::Nets(nets.into_iter().map(Into::into).collect()))
+    }
+
+    /// Trust the given amount of proxies in front of this server,
+    /// the first one being the peer of the connection.
+    ///
+    /// Forwarded information is ignored altogether in case zero hops are trusted.
+    pub const fn hops(n: usize) -> Self {
+        Self(Kind::Hops(n))
+    }
+
+    /// Returns `true` if all forwarded information is trusted.
+    pub fn is_all(&self) -> bool {
+        matches!(self.0, Kind::All)
+    }
+
+    /// Returns the index of the client element within the given forwarded elements,
+    /// ordered from client to the closest proxy, or `None` in case the
+    /// peer of the connection is not trusted to report forwarded information.
+    pub(super) fn client_index(
+        &self,
+        peer_ip: Option<IpAddr>,
+        elements: &[ForwardedElement],
+    ) -> Option<usize> {
+        self.client_ip_index(
+            peer_ip,
+            elements
+                .iter()
+                .map(|element| element.ref_forwarded_for().and_then(NodeId::ip)),
+        )
+    }
+
+    /// Returns the index of the client within the given IP addresses, ordered from
+    /// client to the closest proxy, with `None` for nodes of which the IP is not known,
+    /// or `None` in case the peer of the connection is not trusted to report forwarded information.
+    pub(super) fn client_ip_index(
+        &self,
+        peer_ip: Option<IpAddr>,
+        ips: impl DoubleEndedIterator<Item = Option<IpAddr>> + ExactSizeIterator,
+    ) -> Option<usize> {
+        let mut trusted_hops = match &self.0 {
+            Kind::All => return Some(0),
+            Kind::Nets(nets) => {
+                if !contains(nets, peer_ip?) {
+                    return None;
+                }
+                0
+            }
+            // the peer of the connection is the first trusted hop
+            Kind::Hops(n) => n.checked_sub(1)?,
+        };
+
+        for (index, ip) in ips.enumerate().rev() {
+            let Some(ip) = ip else {
+                // unknown or obfuscated node
+                continue;
+            };
+            let trusted = match &self.0 {
+                Kind::All => true,
+                Kind::Nets(nets) => contains(nets, ip),
+                Kind::Hops(_) => match trusted_hops.checked_sub(1) {
+                    Some(remaining) => {
+                        trusted_hops = remaining;
+                        true
+                    }
+                    None => false,
+                },
+            };
+            if !trusted {
+                return Some(index);
+            }
+        }
+
+        Some(0)
+    }
+}
+
+fn contains(nets: &[IpNet], ip: IpAddr) -> bool {
+    let ip = IpNet::from(ip);
+    nets.iter().any(|net| net.contains(&ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements(nodes: &[&str]) -> Vec<ForwardedElement> {
+        nodes
+            .iter()
+            .map(|node| ForwardedElement::forwarded_for(NodeId::from_str_lossy(node)))
+            .collect()
+    }
+
+    #[test]
+    fn test_trusted_proxies_client_index() {
+        let nets = TrustedProxies::nets([
+            "10.0.0.0/8".parse::<IpNet>().unwrap(),
+            "fd00::/8".parse::<IpNet>().unwrap(),
+        ]);
+        let hops = TrustedProxies::hops(2);
+
+        for (trusted, peer_ip, nodes, expected) in [
+            (&TrustedProxies::all(), None, &["1.2.3.4"][..], Some(0)),
+            (&nets, None, &["1.2.3.4"], None),
+            (&nets, Some("1.1.1.1"), &["1.2.3.4"], None),
+            (&nets, Some("10.0.0.1"), &["1.2.3.4"], Some(0)),
+            (
+                &nets,
+                Some("10.0.0.1"),
+                &["6.6.6.6", "1.2.3.4", "10.0.0.2"],
+                Some(1),
+            ),
+            (
+                &nets,
+                Some("fd00::1"),
+                &["6.6.6.6", "2001:db8::1", "fd00::2"],
+                Some(1),
+            ),
+            (
+                &nets,
+                Some("10.0.0.1"),
+                &["1.2.3.4", "unknown", "_hidden"],
+                Some(0),
+            ),
+            (&nets, Some("10.0.0.1"), &["_hidden", "10.0.0.2"], Some(0)),
+            (
+                &hops,
+                Some("1.1.1.1"),
+                &["6.6.6.6", "1.2.3.4", "5.6.7.8"],
+                Some(1),
+            ),
+            (
+                &hops,
+                None,
+                &["6.6.6.6", "1.2.3.4", "_hidden", "5.6.7.8"],
+                Some(1),
+            ),
+            (
+                &TrustedProxies::hops(0),
+                Some("1.1.1.1"),
+                &["1.2.3.4"],
+                None,
+            ),
+        ] {
+            let peer_ip = peer_ip.map(|ip: &str| ip.parse().unwrap());
+            assert_eq!(
+                trusted.client_index(peer_ip, &elements(nodes)),
+                expected,
+                "{trusted:?} {peer_ip:?} {nodes:?}"
+            );
+        }
+    }
+}
@@ -18,12 +18,17 @@
 
 pub mod auth;
 pub mod body_limit;
+pub mod body_timeout;
+pub mod byte_range;
 pub mod catch_panic;
 pub mod classify;
+pub mod coalesce_body;
 pub mod collect_body;
 pub mod cors;
+pub mod deadline;
 pub mod dns;
 pub mod error_handling;
+pub mod exemption;
 pub mod follow_redirect;
 pub mod forwarded;
 pub mod header_config;
@@ -31,12 +36,17 @@ pub mod header_option_value;
 pub mod map_request_body;
 pub mod map_response_body;
 pub mod normalize_path;
+pub mod phase_timings;
 pub mod propagate_headers;
+pub mod provenance;
 pub mod proxy_auth;
 pub mod remove_header;
+pub mod request_framing;
 pub mod request_id;
 pub mod required_header;
 pub mod retry;
+pub mod rewrite_location;
+pub mod security_headers;
 pub mod sensitive_headers;
 pub mod set_header;
 pub mod set_status;
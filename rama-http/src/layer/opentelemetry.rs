@@ -255,7 +255,9 @@ impl<S, F> RequestMetricsService<S, F> {
                 HTTP_REQUEST_HOST,
                 authority.host().to_string(),
             ));
-            attributes.push(KeyValue::new(SERVER_PORT, authority.port() as i64));
+            if let Some(port) = authority.port() {
+                attributes.push(KeyValue::new(SERVER_PORT, port as i64));
+            }
         }
 
         // Request Info
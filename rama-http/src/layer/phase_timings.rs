@@ -0,0 +1,338 @@
+//! Middleware to record the [`PhaseTimings`] of requests.
+//!
+//! The [`PhaseTimingsLayer`] inserts [`PhaseTimings`] in the [`Context`],
+//! such that services further down the stack (e.g. the `HttpClient` of `rama-http-backend`)
+//! can record the upstream phases in them. Once the response is fully written downstream,
+//! which is when the server drops the response body, the [`PhaseMark::DownstreamWriteEnd`]
+//! is recorded.
+//!
+//! The [`PhaseTimings`] are also inserted in the response extensions, which is where
+//! the [`DefaultOnResponse`] of the [`TraceLayer`] picks them up to include in its event.
+//! Optionally the phases known by the time the response head is returned are also
+//! emitted as a [`Server-Timing`] response header, which is disabled by default.
+//!
+//! # Example
+//!
+//! ```
+//! use std::convert::Infallible;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use rama_core::error::BoxError;
+//! use rama_http::layer::phase_timings::PhaseTimingsLayer;
+//! use rama_http::{Body, PhaseMark, PhaseTimings, Request, Response};
+//!
+//! async fn handle(ctx: Context<()>, _req: Request) -> Result<Response, Infallible> {
+//!     let timings = ctx.get::<PhaseTimings>().unwrap();
+//!     timings.record(PhaseMark::UpstreamConnectStart);
+//!     // ... connect to upstream, serve request
+//!     # timings.record(PhaseMark::UpstreamConnected);
+//!     # timings.record(PhaseMark::UpstreamFirstByte);
+//!     # Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = PhaseTimingsLayer::new()
+//!     .server_timing_header(true)
+//!     .layer(service_fn(handle));
+//!
+//! let response = service.serve(Context::default(), Request::new(Body::empty())).await?;
+//! assert!(response.extensions().get::<PhaseTimings>().is_some());
+//! assert!(response.headers().contains_key("server-timing"));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Server-Timing`]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Server-Timing
+//! [`DefaultOnResponse`]: crate::layer::trace::DefaultOnResponse
+//! [`TraceLayer`]: crate::layer::trace::TraceLayer
+
+use crate::{HeaderValue, PhaseMark, PhaseTimings, PhaseTimingsBody, Request, Response};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, fmt::Write, time::Duration};
+
+const SERVER_TIMING: crate::HeaderName = crate::HeaderName::from_static("server-timing");
+
+/// Layer that applies the [`PhaseTimingsService`] middleware,
+/// which records the [`PhaseTimings`] of requests.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct PhaseTimingsLayer {
+    server_timing_header: bool,
+}
+
+impl PhaseTimingsLayer {
+    /// Create a new [`PhaseTimingsLayer`].
+    pub const fn new() -> Self {
+        Self {
+            server_timing_header: false,
+        }
+    }
+
+    /// Emit the phases known by the time the response head is returned
+    /// as a `Server-Timing` response header.
+    ///
+    /// Disabled by default.
+    pub fn server_timing_header(mut self, enabled: bool) -> Self {
+        self.server_timing_header = enabled;
+        self
+    }
+
+    /// Emit the phases known by the time the response head is returned
+    /// as a `Server-Timing` response header.
+    ///
+    /// Disabled by default.
+    pub fn set_server_timing_header(&mut self, enabled: bool) -> &mut Self {
+        self.server_timing_header = enabled;
+        self
+    }
+}
+
+impl<S> Layer<S> for PhaseTimingsLayer {
+    type Service = PhaseTimingsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PhaseTimingsService {
+            inner,
+            server_timing_header: self.server_timing_header,
+        }
+    }
+}
+
+/// Middleware which records the [`PhaseTimings`] of requests.
+///
+/// See the [module docs](self) for more details.
+pub struct PhaseTimingsService<S> {
+    inner: S,
+    server_timing_header: bool,
+}
+
+impl<S> PhaseTimingsService<S> {
+    /// Create a new [`PhaseTimingsService`].
+    pub const fn new(inner: S) -> Self {
+        Self {
+            inner,
+            server_timing_header: false,
+        }
+    }
+
+    /// Emit the phases known by the time the response head is returned
+    /// as a `Server-Timing` response header.
+    ///
+    /// Disabled by default.
+    pub fn server_timing_header(mut self, enabled: bool) -> Self {
+        self.server_timing_header = enabled;
+        self
+    }
+
+    /// Emit the phases known by the time the response head is returned
+    /// as a `Server-Timing` response header.
+    ///
+    /// Disabled by default.
+    pub fn set_server_timing_header(&mut self, enabled: bool) -> &mut Self {
+        self.server_timing_header = enabled;
+        self
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for PhaseTimingsService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhaseTimingsService")
+            .field("inner", &self.inner)
+            .field("server_timing_header", &self.server_timing_header)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for PhaseTimingsService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            server_timing_header: self.server_timing_header,
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for PhaseTimingsService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<PhaseTimingsBody<ResBody>>;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let timings = ctx.get_or_insert_with(PhaseTimings::new).clone();
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        if self.server_timing_header {
+            if let Some(value) = server_timing_header_value(&timings) {
+                res.headers_mut().append(SERVER_TIMING, value);
+            }
+        }
+        res.extensions_mut().insert(timings.clone());
+
+        Ok(res.map(|body| PhaseTimingsBody::on_drop(body, timings, PhaseMark::DownstreamWriteEnd)))
+    }
+}
+
+fn server_timing_header_value(timings: &PhaseTimings) -> Option<HeaderValue> {
+    let mut value = String::new();
+    for (name, duration) in [
+        ("middleware", timings.middleware()),
+        ("upstream-connect", timings.upstream_connect()),
+        ("upstream-ttfb", timings.upstream_first_byte()),
+    ] {
+        if let Some(duration) = duration {
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+            let _ = write!(value, "{name};dur={}", duration_as_millis(duration));
+        }
+    }
+    if value.is_empty() {
+        return None;
+    }
+    HeaderValue::try_from(value).ok()
+}
+
+fn duration_as_millis(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1_000_000.).round() / 1_000.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dep::http_body_util::BodyExt, Body};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    async fn upstream(ctx: Context<()>, _req: Request) -> Result<Response, Infallible> {
+        let timings = ctx.get::<PhaseTimings>().unwrap().clone();
+
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        timings.record(PhaseMark::UpstreamConnectStart);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        timings.record(PhaseMark::UpstreamConnected);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        timings.record(PhaseMark::UpstreamFirstByte);
+
+        Ok(Response::new(Body::new(PhaseTimingsBody::new(
+            Body::from("hello"),
+            timings,
+            PhaseMark::UpstreamBodyEnd,
+        ))))
+    }
+
+    #[tokio::test]
+    async fn test_phase_timings_sum_to_total() {
+        let service = PhaseTimingsLayer::new().layer(service_fn(upstream));
+
+        let start = std::time::Instant::now();
+        let res = service
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert!(!res.headers().contains_key(SERVER_TIMING));
+
+        let timings = res.extensions().get::<PhaseTimings>().unwrap().clone();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let elapsed = start.elapsed();
+        assert_eq!(body, "hello");
+
+        let phases = [
+            timings.middleware(),
+            timings.upstream_connect(),
+            timings.upstream_first_byte(),
+            timings.upstream_body(),
+            timings.downstream_write(),
+        ];
+        assert!(phases.iter().all(Option::is_some), "{timings:?}");
+        assert!(timings.middleware().unwrap() >= Duration::from_millis(5));
+        assert!(timings.upstream_connect().unwrap() >= Duration::from_millis(5));
+        assert!(timings.upstream_first_byte().unwrap() >= Duration::from_millis(5));
+
+        let sum: Duration = phases.into_iter().flatten().sum();
+        let total = timings.total();
+        assert!(total <= elapsed);
+        assert!(
+            total - sum < Duration::from_millis(1),
+            "{sum:?} vs {total:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_phase_timings_slow_consumer() {
+        let service = PhaseTimingsLayer::new().layer(service_fn(upstream));
+
+        let res = service
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+
+        let timings = res.extensions().get::<PhaseTimings>().unwrap().clone();
+        let mut body = res.into_body();
+        while let Some(frame) = body.frame().await {
+            frame.unwrap();
+        }
+        assert!(timings.get(PhaseMark::UpstreamBodyEnd).is_some());
+        assert!(timings.get(PhaseMark::DownstreamWriteEnd).is_none());
+
+        // the client is still receiving the body which was read entirely from upstream
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(body);
+
+        let downstream_write = timings.downstream_write().unwrap();
+        assert!(
+            downstream_write >= Duration::from_millis(20),
+            "{downstream_write:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_phase_timings_server_timing_header() {
+        let service = PhaseTimingsLayer::new()
+            .server_timing_header(true)
+            .layer(service_fn(upstream));
+
+        let res = service
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+
+        let value = res.headers().get(SERVER_TIMING).unwrap().to_str().unwrap();
+        let names: Vec<_> = value
+            .split(", ")
+            .map(|metric| metric.split(';').next().unwrap())
+            .collect();
+        assert_eq!(names, ["middleware", "upstream-connect", "upstream-ttfb"]);
+    }
+
+    #[tokio::test]
+    async fn test_phase_timings_reuse_existing() {
+        let service = PhaseTimingsLayer::new().layer(service_fn(upstream));
+
+        let timings = PhaseTimings::new();
+        let mut ctx = Context::default();
+        ctx.insert(timings.clone());
+
+        let res = service
+            .serve(ctx, Request::new(Body::empty()))
+            .await
+            .unwrap();
+        res.into_body().collect().await.unwrap();
+
+        assert!(timings.get(PhaseMark::DownstreamWriteEnd).is_some());
+    }
+}
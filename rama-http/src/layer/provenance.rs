@@ -0,0 +1,244 @@
+//! Middleware to expose which layer short-circuited a request.
+//!
+//! Rama layers which reject requests (e.g. authorization, CORS preflight
+//! and request validation) record a [`ResponseProvenance`] in the extensions
+//! of the response they produce, identifying the layer and its reason.
+//! Recording is enabled by default for debug builds only,
+//! use [`ResponseProvenance::set_enabled`] to toggle it at runtime,
+//! e.g. to leave it on in staging.
+//!
+//! The [`ResponseProvenance`] is included in the event of the [`DefaultOnResponse`].
+//! The [`ProvenanceHeaderLayer`] can emit it as the `x-rama-denied-by` response header.
+//! As this exposes the internals of the stack, only use it for internal listeners.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::{service::service_fn, Context, Layer, Service};
+//! use rama_http::layer::{
+//!     provenance::ProvenanceHeaderLayer, validate_request::ValidateRequestHeaderLayer,
+//! };
+//! use rama_http::{Body, Request, Response, ResponseProvenance, StatusCode};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! ResponseProvenance::set_enabled(true);
+//!
+//! let service = (
+//!     ProvenanceHeaderLayer::new(),
+//!     ValidateRequestHeaderLayer::bearer("secret"),
+//! )
+//!     .layer(service_fn(|_| async { Ok::<_, Infallible>(Response::new(Body::empty())) }));
+//!
+//! let res = service
+//!     .serve(Context::default(), Request::new(Body::empty()))
+//!     .await
+//!     .unwrap();
+//! assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+//! assert_eq!(
+//!     res.headers()["x-rama-denied-by"],
+//!     "require_authorization (bearer: unauthorized)"
+//! );
+//! # }
+//! ```
+//!
+//! [`DefaultOnResponse`]: crate::layer::trace::DefaultOnResponse
+
+use crate::{HeaderName, HeaderValue, Request, Response, ResponseProvenance};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+
+/// The `x-rama-denied-by` header, emitted by the [`ProvenanceHeader`] service.
+pub const X_RAMA_DENIED_BY: HeaderName = HeaderName::from_static("x-rama-denied-by");
+
+/// Layer that applies the [`ProvenanceHeader`] middleware,
+/// which emits the [`ResponseProvenance`] as the `x-rama-denied-by` response header.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ProvenanceHeaderLayer;
+
+impl ProvenanceHeaderLayer {
+    /// Create a new [`ProvenanceHeaderLayer`].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for ProvenanceHeaderLayer {
+    type Service = ProvenanceHeader<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ProvenanceHeader::new(inner)
+    }
+}
+
+/// Middleware which emits the [`ResponseProvenance`] of a response,
+/// if recorded, as the `x-rama-denied-by` response header.
+///
+/// See the [module docs](self) for more details.
+pub struct ProvenanceHeader<S> {
+    inner: S,
+}
+
+impl<S> ProvenanceHeader<S> {
+    /// Create a new [`ProvenanceHeader`] service.
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for ProvenanceHeader<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProvenanceHeader")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for ProvenanceHeader<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S, State, ReqBody, ResBody> Service<State, Request<ReqBody>> for ProvenanceHeader<S>
+where
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let mut res = self.inner.serve(ctx, req).await?;
+        if let Some(provenance) = res.extensions().get::<ResponseProvenance>() {
+            // fall back to the layer name only, in case the reason is not a valid header value
+            let value = HeaderValue::try_from(provenance.to_string())
+                .unwrap_or_else(|_| HeaderValue::from_static(provenance.layer()));
+            res.headers_mut().insert(X_RAMA_DENIED_BY, value);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        header::{ACCEPT, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, PROXY_AUTHORIZATION},
+        layer::{
+            cors::CorsLayer, proxy_auth::ProxyAuthLayer,
+            validate_request::ValidateRequestHeaderLayer,
+        },
+        Body, Method, StatusCode,
+    };
+    use rama_core::service::service_fn;
+    use rama_net::user::Basic;
+    use std::convert::Infallible;
+
+    async fn ok(_: Request) -> Result<Response, Infallible> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    async fn denied_by<S>(service: S, req: Request) -> (StatusCode, Option<String>)
+    where
+        S: Service<(), Request, Response = Response, Error = Infallible>,
+    {
+        let res = ProvenanceHeaderLayer::new()
+            .layer(service)
+            .serve(Context::default(), req)
+            .await
+            .unwrap();
+        let provenance = res.extensions().get::<ResponseProvenance>().cloned();
+        let header = res
+            .headers()
+            .get(X_RAMA_DENIED_BY)
+            .map(|value| value.to_str().unwrap().to_owned());
+        assert_eq!(provenance.map(|p| p.to_string()), header);
+        (res.status(), header)
+    }
+
+    #[tokio::test]
+    async fn test_provenance_attributes_short_circuiting_layer() {
+        ResponseProvenance::set_enabled(true);
+
+        // basic authorization
+        let (status, header) = denied_by(
+            ValidateRequestHeaderLayer::basic("john", "secret").layer(service_fn(ok)),
+            Request::new(Body::empty()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            header.as_deref(),
+            Some("require_authorization (basic: unauthorized)")
+        );
+
+        // proxy authentication
+        let (status, header) = denied_by(
+            ProxyAuthLayer::<_, Basic>::new(Basic::new("john", "secret")).layer(service_fn(ok)),
+            Request::builder()
+                .header(PROXY_AUTHORIZATION, "Basic am9objp3cm9uZw==")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PROXY_AUTHENTICATION_REQUIRED);
+        assert_eq!(header.as_deref(), Some("proxy_auth (invalid credentials)"));
+
+        // cors preflight
+        let (status, header) = denied_by(
+            CorsLayer::permissive().layer(service_fn(ok)),
+            Request::builder()
+                .method(Method::OPTIONS)
+                .header(ORIGIN, "https://example.com")
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(header.as_deref(), Some("cors (preflight request)"));
+
+        // request validation
+        let (status, header) = denied_by(
+            ValidateRequestHeaderLayer::accept("application/json").layer(service_fn(ok)),
+            Request::builder()
+                .header(ACCEPT, "text/html")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(
+            header.as_deref(),
+            Some("validate_request (invalid request)")
+        );
+
+        // requests served by the inner service have no provenance
+        let (status, header) = denied_by(
+            ValidateRequestHeaderLayer::accept("application/json").layer(service_fn(ok)),
+            Request::builder()
+                .header(ACCEPT, "application/json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(header, None);
+    }
+}
@@ -4,7 +4,7 @@
 
 use crate::header::PROXY_AUTHENTICATE;
 use crate::headers::{authorization::Credentials, HeaderMapExt, ProxyAuthorization};
-use crate::{Request, Response, StatusCode};
+use crate::{Request, Response, ResponseProvenance, StatusCode};
 use rama_core::{Context, Layer, Service};
 use rama_net::user::{auth::Authority, UserId};
 use rama_utils::macros::define_inner_service_accessors;
@@ -190,21 +190,23 @@ where
                 ctx.extend(ext);
                 self.inner.serve(ctx, req).await
             } else {
-                Ok(Response::builder()
-                    .status(StatusCode::PROXY_AUTHENTICATION_REQUIRED)
-                    .header(PROXY_AUTHENTICATE, C::SCHEME)
-                    .body(Default::default())
-                    .unwrap())
+                Ok(proxy_auth_required::<C, _>("invalid credentials"))
             }
         } else if self.allow_anonymous {
             ctx.insert(UserId::Anonymous);
             self.inner.serve(ctx, req).await
         } else {
-            Ok(Response::builder()
-                .status(StatusCode::PROXY_AUTHENTICATION_REQUIRED)
-                .header(PROXY_AUTHENTICATE, C::SCHEME)
-                .body(Default::default())
-                .unwrap())
+            Ok(proxy_auth_required::<C, _>("missing credentials"))
         }
     }
 }
+
+fn proxy_auth_required<C: Credentials, B: Default>(reason: &'static str) -> Response<B> {
+    let mut res = Response::builder()
+        .status(StatusCode::PROXY_AUTHENTICATION_REQUIRED)
+        .header(PROXY_AUTHENTICATE, C::SCHEME)
+        .body(Default::default())
+        .unwrap();
+    ResponseProvenance::record(&mut res, "proxy_auth", reason);
+    res
+}
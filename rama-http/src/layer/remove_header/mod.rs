@@ -39,7 +39,6 @@ fn remove_hop_by_hop_request_headers(headers: &mut HeaderMap) {
         &header::PROXY_CONNECTION,
         &header::PROXY_AUTHORIZATION,
         &header::TE,
-        &header::TRAILER,
         &header::TRANSFER_ENCODING,
         &header::UPGRADE,
         &header::X_FORWARDED_FOR,
@@ -71,6 +71,9 @@ impl RemoveRequestHeaderLayer {
     ///
     /// Removes all hop-by-hop request headers as specified in [RFC 2616](https://datatracker.ietf.org/doc/html/rfc2616#section-13.5.1).
     /// This does not support other hop-by-hop headers defined in [section-14.10](https://datatracker.ietf.org/doc/html/rfc2616#section-14.10).
+    ///
+    /// The `Trailer` header is preserved, as it declares the trailer fields
+    /// of the request body, which are forwarded end-to-end.
     pub fn hop_by_hop() -> Self {
         Self {
             mode: RemoveRequestHeaderMode::Hop,
@@ -225,12 +228,17 @@ mod test {
                     req.headers().get("foo").map(|v| v.to_str().unwrap()),
                     Some("bar")
                 );
+                assert_eq!(
+                    req.headers().get("trailer").map(|v| v.to_str().unwrap()),
+                    Some("x-checksum")
+                );
                 Ok::<_, Infallible>(Response::new(Body::empty()))
             },
         ));
         let req = Request::builder()
             .header("connection", "close")
             .header("foo", "bar")
+            .header("trailer", "x-checksum")
             .body(Body::empty())
             .unwrap();
         let _ = svc.serve(Context::default(), req).await.unwrap();
@@ -0,0 +1,239 @@
+//! Middleware that normalizes the (http/1) framing headers of incoming requests.
+//!
+//! Proxies forwarding requests whose framing can be interpreted in more than one way
+//! (e.g. both `Content-Length` and `Transfer-Encoding`, duplicate framing headers
+//! or obs-folded header values) expose their upstreams to request smuggling.
+//! This layer normalizes the framing of such requests using
+//! [`normalize_request_framing`], or rejects them with a `400 Bad Request`
+//! in case they are ambiguous. Requests of other versions than http/1 are left untouched.
+//!
+//! The [`FramingMode`] of this layer is inserted in the [`Context`], such that
+//! the http client normalizes outgoing requests using the same mode,
+//! instead of its default [`FramingMode::Strict`].
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::{service::service_fn, Context, Layer, Service};
+//! use rama_http::layer::request_framing::RequestFramingLayer;
+//! use rama_http::{Body, Request, Response, StatusCode};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = RequestFramingLayer::new()
+//!     .layer(service_fn(|req: Request| async move {
+//!         // only a single framing header reaches the inner service
+//!         assert!(!req.headers().contains_key("content-length"));
+//!         Ok::<_, Infallible>(Response::new(Body::empty()))
+//!     }));
+//!
+//! // CL.TE request smuggling attempt: the content-length is dropped
+//! let req = Request::builder()
+//!     .header("content-length", "13")
+//!     .header("transfer-encoding", "chunked")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let res = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(res.status(), StatusCode::OK);
+//!
+//! // obfuscated transfer encoding: the request is rejected
+//! let req = Request::builder()
+//!     .header("transfer-encoding", "xchunked")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let res = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+//! # }
+//! ```
+//!
+//! [`Context`]: rama_core::Context
+
+use crate::proto::h1::{normalize_request_framing, FramingMode};
+use crate::{Request, Response, ResponseProvenance, StatusCode};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+
+/// Layer that applies [`RequestFraming`] which normalizes the framing headers of requests.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct RequestFramingLayer {
+    mode: FramingMode,
+}
+
+impl RequestFramingLayer {
+    /// Create a new [`RequestFramingLayer`], using [`FramingMode::Strict`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new [`RequestFramingLayer`], using [`FramingMode::Lenient`].
+    pub fn lenient() -> Self {
+        Self {
+            mode: FramingMode::Lenient,
+        }
+    }
+
+    /// Set the [`FramingMode`] used to normalize the requests.
+    pub fn with_mode(mut self, mode: FramingMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the [`FramingMode`] used to normalize the requests.
+    pub fn set_mode(&mut self, mode: FramingMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+}
+
+impl<S> Layer<S> for RequestFramingLayer {
+    type Service = RequestFraming<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestFraming {
+            inner,
+            mode: self.mode,
+        }
+    }
+}
+
+/// Middleware that normalizes the framing headers of requests.
+///
+/// See the [module docs](self) for more details.
+pub struct RequestFraming<S> {
+    inner: S,
+    mode: FramingMode,
+}
+
+impl<S: fmt::Debug> fmt::Debug for RequestFraming<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestFraming")
+            .field("inner", &self.inner)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RequestFraming<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            mode: self.mode,
+        }
+    }
+}
+
+impl<S> RequestFraming<S> {
+    /// Create a new [`RequestFraming`], using [`FramingMode::Strict`].
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            mode: FramingMode::Strict,
+        }
+    }
+
+    /// Create a new [`RequestFraming`], using [`FramingMode::Lenient`].
+    pub fn lenient(inner: S) -> Self {
+        Self {
+            inner,
+            mode: FramingMode::Lenient,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S, State, ReqBody, ResBody> Service<State, Request<ReqBody>> for RequestFraming<S>
+where
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        if normalize_request_framing(&mut req, self.mode).is_err() {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::BAD_REQUEST;
+            ResponseProvenance::record(&mut res, "request_framing", "ambiguous framing");
+            return Ok(res);
+        }
+        ctx.insert(self.mode);
+        self.inner.serve(ctx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{header::TRANSFER_ENCODING, Body, BodyExtractExt, Version};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    async fn serve(
+        layer: RequestFramingLayer,
+        headers: &[(&'static str, &'static str)],
+    ) -> Response<Body> {
+        let service = layer.layer(service_fn(|req: Request| async move {
+            let te: Vec<_> = req.headers().get_all(TRANSFER_ENCODING).iter().collect();
+            Ok::<_, Infallible>(Response::new(Body::from(format!("{te:?}"))))
+        }));
+        let mut builder = Request::builder().version(Version::HTTP_11);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        service
+            .serve(Context::default(), builder.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request_framing_te_cl() {
+        let headers = &[("transfer-encoding", "chunked"), ("content-length", "3")];
+        for layer in [RequestFramingLayer::new(), RequestFramingLayer::lenient()] {
+            let res = serve(layer, headers).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_framing_mode_in_context() {
+        for mode in [FramingMode::Strict, FramingMode::Lenient] {
+            let service = RequestFramingLayer::new().with_mode(mode).layer(service_fn(
+                move |ctx: Context<()>, _req: Request| async move {
+                    assert_eq!(ctx.get::<FramingMode>(), Some(&mode));
+                    Ok::<_, Infallible>(Response::new(Body::empty()))
+                },
+            ));
+            let req = Request::builder().body(Body::empty()).unwrap();
+            let res = service.serve(Context::default(), req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_framing_modes() {
+        let headers = &[
+            ("transfer-encoding", "gzip"),
+            ("transfer-encoding", "chunked"),
+        ];
+
+        let res = serve(RequestFramingLayer::new(), headers).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = serve(RequestFramingLayer::lenient(), headers).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.try_into_string().await.unwrap();
+        assert_eq!(body, r#"["gzip, chunked"]"#);
+    }
+}
@@ -0,0 +1,79 @@
+use super::{Policy, PolicyResult, RetryAttempt, RetryBody};
+use crate::Request;
+use rama_core::Context;
+use std::fmt;
+
+/// A retry [`Policy`] that retries only if both of its `Policy`s retry.
+///
+/// See [`PolicyExt::and`][super::PolicyExt::and] for more details.
+pub struct And<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> And<A, B> {
+    pub(crate) const fn new(a: A, b: B) -> Self {
+        And { a, b }
+    }
+}
+
+impl<A: fmt::Debug, B: fmt::Debug> fmt::Debug for And<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("And")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .finish()
+    }
+}
+
+impl<A: Clone, B: Clone> Clone for And<A, B> {
+    fn clone(&self) -> Self {
+        And {
+            a: self.a.clone(),
+            b: self.b.clone(),
+        }
+    }
+}
+
+impl<A, B, State, Response, Error> Policy<State, Response, Error> for And<A, B>
+where
+    A: Policy<State, Response, Error>,
+    B: Policy<State, Response, Error>,
+    State: Clone + Send + Sync + 'static,
+    Response: Clone + Send + 'static,
+    Error: Clone + Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        req: Request<RetryBody>,
+        result: Result<Response, Error>,
+        attempt: usize,
+    ) -> PolicyResult<State, Response, Error> {
+        let (b_ctx, b_req) = (ctx.clone(), req.clone());
+        let (ctx, req) = match self.a.retry(ctx, req, result.clone(), attempt).await {
+            PolicyResult::Abort(result) => return PolicyResult::Abort(result),
+            PolicyResult::Retry { ctx, req } => (ctx, req),
+        };
+        match self.b.retry(b_ctx, b_req, result, attempt).await {
+            PolicyResult::Abort(result) => PolicyResult::Abort(result),
+            // left-biased: retry the input as (possibly) mutated by the first policy
+            PolicyResult::Retry { .. } => PolicyResult::Retry { ctx, req },
+        }
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        let input = self.a.clone_input(ctx, req)?;
+        self.b.clone_input(ctx, req)?;
+        Some(input)
+    }
+
+    fn on_retry(&self, attempt: &RetryAttempt) {
+        self.a.on_retry(attempt);
+        self.b.on_retry(attempt);
+    }
+}
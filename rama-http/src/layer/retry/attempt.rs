@@ -0,0 +1,47 @@
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// An [`Extensions`] value describing the attempt being served
+/// through the [`Retry`] layer.
+///
+/// The [`Retry`] layer inserts (or updates) it in the [`Context`]
+/// prior to each call of the inner service, such that downstream layers
+/// can tell the initial attempt and its retries apart. The [`Context`] of the
+/// final call thus contains the final attempt. Use the [`RetryOutcome`] to
+/// inspect the number of attempts made from outside the [`Retry`] layer.
+///
+/// [`Extensions`]: rama_core::context::Extensions
+/// [`Context`]: rama_core::Context
+/// [`Retry`]: super::Retry
+/// [`RetryOutcome`]: super::RetryOutcome
+pub struct RetryAttempt {
+    attempt: u32,
+    last_error: Option<String>,
+}
+
+impl RetryAttempt {
+    pub(super) fn retry(&self, last_error: Option<String>) -> Self {
+        Self {
+            attempt: self.attempt.saturating_add(1),
+            last_error,
+        }
+    }
+
+    /// Get the index of the attempt, starting at `0` for the initial attempt,
+    /// such that it equals the number of retries already made.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Returns `true` if this attempt is a retry,
+    /// meaning it is not the initial attempt.
+    pub fn is_retry(&self) -> bool {
+        self.attempt > 0
+    }
+
+    /// Get the error of the previous attempt, if any.
+    ///
+    /// This is `None` for the initial attempt, as well as for retries
+    /// of which the previous attempt resulted in a (retryable) response.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+}
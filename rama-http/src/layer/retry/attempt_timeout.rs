@@ -0,0 +1,81 @@
+use rama_core::layer::MakeLayerError;
+use std::{fmt, time::Duration};
+
+/// Timeout bounding each attempt made by the [`Retry`] service.
+///
+/// Created using [`Retry::with_attempt_timeout`] or one of its variants.
+///
+/// [`Retry`]: super::Retry
+/// [`Retry::with_attempt_timeout`]: super::Retry::with_attempt_timeout
+pub struct AttemptTimeout<F> {
+    timeout: Duration,
+    into_error: F,
+}
+
+impl<F> AttemptTimeout<F> {
+    pub(super) const fn new(timeout: Duration, into_error: F) -> Self {
+        Self {
+            timeout,
+            into_error,
+        }
+    }
+
+    /// Get the timeout bounding each attempt.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for AttemptTimeout<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttemptTimeout")
+            .field("timeout", &self.timeout)
+            .field("into_error", &self.into_error)
+            .finish()
+    }
+}
+
+impl<F: Clone> Clone for AttemptTimeout<F> {
+    fn clone(&self) -> Self {
+        Self {
+            timeout: self.timeout,
+            into_error: self.into_error.clone(),
+        }
+    }
+}
+
+/// Bounds each attempt made by the [`Retry`] service,
+/// failing an attempt which takes too long with an error of type `E`.
+///
+/// Implemented for `()`, in which case attempts are not bounded,
+/// and for [`AttemptTimeout`].
+///
+/// [`Retry`]: super::Retry
+pub trait MakeAttemptTimeout<E>: sealed::Sealed + Send + Sync + 'static {
+    /// Get the timeout bounding each attempt, together with
+    /// the function creating the error of an attempt which timed out,
+    /// or `None` in case attempts are not bounded.
+    fn attempt_timeout(&self) -> Option<(Duration, impl FnOnce() -> E + Send)>;
+}
+
+impl<E> MakeAttemptTimeout<E> for () {
+    fn attempt_timeout(&self) -> Option<(Duration, impl FnOnce() -> E + Send)> {
+        None::<(Duration, fn() -> E)>
+    }
+}
+
+impl<F, E> MakeAttemptTimeout<E> for AttemptTimeout<F>
+where
+    F: MakeLayerError<Error: Into<E>>,
+{
+    fn attempt_timeout(&self) -> Option<(Duration, impl FnOnce() -> E + Send)> {
+        Some((self.timeout, || self.into_error.make_layer_error().into()))
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for () {}
+    impl<F> Sealed for super::AttemptTimeout<F> {}
+}
@@ -0,0 +1,335 @@
+//! Exponential backoff retry [`Policy`].
+//!
+//! See [`ExponentialBackoffPolicy`] for more details.
+//!
+//! [`Policy`]: super::Policy
+
+use super::{managed::DoNotRetry, Policy, PolicyResult, RetryBody, RetryOutcome};
+use crate::Request;
+use parking_lot::Mutex;
+use rama_core::Context;
+use rama_utils::{
+    backoff::{exponential_delay, jitter_delay},
+    rng::HasherRng,
+};
+use std::{fmt, time::Duration};
+
+/// A retry [`Policy`] which sleeps `base * 2^retry` between attempts,
+/// capped at a maximum delay, with optional jitter.
+///
+/// The delays are computed just like the ones of the [`ExponentialBackoff`]
+/// found in `rama_utils`, but from the retry number rather than from shared state.
+///
+/// Whether or not a result is retried is decided by the classifier,
+/// a closure which returns `true` for results that should be retried.
+/// Requests are retried at most `max_retries` times.
+///
+/// The retry number is the attempt passed by the [`Retry`] layer,
+/// such that no state is shared between concurrent requests. Just like the [`ManagedPolicy`],
+/// requests with [`DoNotRetry`] in their [`Context`] are never retried.
+///
+/// This policy is a self-contained alternative to a [`ManagedPolicy`]
+/// combined with a [`Backoff`], of which the state is shared between all requests.
+///
+/// # Example
+///
+/// ```
+/// use rama_http::layer::retry::{ExponentialBackoffPolicy, RetryLayer};
+/// use rama_http::Response;
+/// use std::time::Duration;
+///
+/// let policy = ExponentialBackoffPolicy::new(|result: &Result<Response, std::io::Error>| {
+///     result
+///         .as_ref()
+///         .map(|res| res.status().is_server_error())
+///         .unwrap_or(true)
+/// })
+/// .with_base(Duration::from_millis(50))
+/// .with_max(Duration::from_secs(2))
+/// .with_jitter(0.2)
+/// .with_max_retries(5);
+///
+/// let _layer = RetryLayer::new(policy);
+/// ```
+///
+/// [`Retry`]: super::Retry
+/// [`ManagedPolicy`]: super::ManagedPolicy
+/// [`Backoff`]: rama_utils::backoff::Backoff
+/// [`ExponentialBackoff`]: rama_utils::backoff::ExponentialBackoff
+pub struct ExponentialBackoffPolicy<F> {
+    base: Duration,
+    max: Duration,
+    jitter: f64,
+    max_retries: usize,
+    classify: F,
+    rng: Mutex<HasherRng>,
+}
+
+impl<F> fmt::Debug for ExponentialBackoffPolicy<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExponentialBackoffPolicy")
+            .field("base", &self.base)
+            .field("max", &self.max)
+            .field("jitter", &self.jitter)
+            .field("max_retries", &self.max_retries)
+            .field("classify", &std::any::type_name::<F>())
+            .finish()
+    }
+}
+
+impl<F: Clone> Clone for ExponentialBackoffPolicy<F> {
+    fn clone(&self) -> Self {
+        Self {
+            base: self.base,
+            max: self.max,
+            jitter: self.jitter,
+            max_retries: self.max_retries,
+            classify: self.classify.clone(),
+            rng: Mutex::new(HasherRng::default()),
+        }
+    }
+}
+
+impl<F> ExponentialBackoffPolicy<F> {
+    /// Create a new [`ExponentialBackoffPolicy`] using the given classifier.
+    ///
+    /// By default the base delay is 100ms, capped at 10s, without jitter,
+    /// and requests are retried at most 3 times.
+    pub fn new(classify: F) -> Self {
+        Self {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            jitter: 0.0,
+            max_retries: 3,
+            classify,
+            rng: Mutex::new(HasherRng::default()),
+        }
+    }
+
+    /// Set the delay prior to the first retry,
+    /// doubled for every subsequent retry.
+    pub fn with_base(mut self, base: Duration) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Set the delay prior to the first retry,
+    /// doubled for every subsequent retry.
+    pub fn set_base(&mut self, base: Duration) -> &mut Self {
+        self.base = base;
+        self
+    }
+
+    /// Set the maximum delay between two attempts.
+    pub fn with_max(mut self, max: Duration) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// Set the maximum delay between two attempts.
+    pub fn set_max(&mut self, max: Duration) -> &mut Self {
+        self.max = max;
+        self
+    }
+
+    /// Set the jitter, the fraction (between `0.0` and `1.0`) of each delay
+    /// which is at most randomly added to it (without exceeding the maximum delay),
+    /// to avoid retry spikes.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the jitter, the fraction (between `0.0` and `1.0`) of each delay
+    /// which is at most randomly added to it (without exceeding the maximum delay),
+    /// to avoid retry spikes.
+    pub fn set_jitter(&mut self, jitter: f64) -> &mut Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the maximum number of times a request is retried.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the maximum number of times a request is retried.
+    pub fn set_max_retries(&mut self, max_retries: usize) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Compute the delay prior to the given retry (starting at `0`), without jitter.
+    pub fn delay(&self, retry: usize) -> Duration {
+        exponential_delay(self.base, self.max, retry)
+    }
+
+    fn jittered_delay(&self, retry: usize) -> Duration {
+        let delay = self.delay(retry);
+        delay + jitter_delay(delay, self.max, self.jitter, &mut *self.rng.lock())
+    }
+}
+
+impl<F, State, Response, Error> Policy<State, Response, Error> for ExponentialBackoffPolicy<F>
+where
+    F: Fn(&Result<Response, Error>) -> bool + Send + Sync + 'static,
+    State: Clone + Send + Sync + 'static,
+    Response: Send + 'static,
+    Error: Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        req: Request<RetryBody>,
+        result: Result<Response, Error>,
+        retry: usize,
+    ) -> PolicyResult<State, Response, Error> {
+        if ctx.get::<DoNotRetry>().is_some() || !(self.classify)(&result) {
+            return PolicyResult::Abort(result);
+        }
+
+        if retry >= self.max_retries {
+            tracing::debug!(retry, "exponential backoff: max retries reached: abort");
+            return PolicyResult::Abort(result);
+        }
+
+        let delay = self.jittered_delay(retry);
+        tracing::trace!(retry, ?delay, "exponential backoff: sleep prior to retry");
+        tokio::time::sleep(delay).await;
+        PolicyResult::Retry { ctx, req }
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        // the attempt about to be made is followed by retry number `attempts`,
+        // no clone is required in case that retry exceeds the maximum
+        let attempts = ctx
+            .get::<RetryOutcome>()
+            .map(RetryOutcome::attempts)
+            .unwrap_or_default();
+        if ctx.get::<DoNotRetry>().is_some() || attempts >= self.max_retries {
+            None
+        } else {
+            Some((ctx.clone(), req.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::retry::RetryLayer;
+    use crate::{IntoResponse, Response, StatusCode};
+    use parking_lot::Mutex;
+    use rama_core::{Layer, Service};
+    use std::{convert::Infallible, sync::Arc};
+    use tokio::time::Instant;
+
+    fn retry_server_errors(result: &Result<Response, Infallible>) -> bool {
+        matches!(result, Ok(res) if res.status().is_server_error())
+    }
+
+    #[test]
+    fn test_exponential_backoff_delay() {
+        let policy = ExponentialBackoffPolicy::new(retry_server_errors)
+            .with_base(Duration::from_millis(100))
+            .with_max(Duration::from_secs(1));
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        assert_eq!(policy.delay(4), Duration::from_secs(1));
+        assert_eq!(policy.delay(usize::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_exponential_backoff_jittered_delay() {
+        let max = Duration::from_secs(1);
+        let policy = ExponentialBackoffPolicy::new(retry_server_errors)
+            .with_base(Duration::from_millis(100))
+            .with_max(max)
+            .with_jitter(0.5);
+        for retry in 0..6 {
+            let delay = policy.delay(retry);
+            let jittered = policy.jittered_delay(retry);
+            assert!(
+                delay <= jittered && jittered <= delay.mul_f64(1.5).min(max),
+                "retry {retry}: {jittered:?} not within [{delay:?}, {max:?}]"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exponential_backoff_delays_grow() {
+        tokio::time::pause();
+
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let svc = RetryLayer::new(
+            ExponentialBackoffPolicy::new(retry_server_errors)
+                .with_base(Duration::from_millis(100))
+                .with_max(Duration::from_millis(300))
+                .with_max_retries(4),
+        )
+        .layer(rama_core::service::service_fn({
+            let attempts = attempts.clone();
+            move |_req: Request<RetryBody>| {
+                let attempts = attempts.clone();
+                async move {
+                    attempts.lock().push(Instant::now());
+                    Ok::<_, Infallible>(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+                }
+            }
+        }));
+
+        let mut ctx = Context::default();
+        let outcome = RetryOutcome::new();
+        ctx.insert(outcome.clone());
+
+        let req = Request::builder().body(RetryBody::empty()).unwrap();
+        let res = svc.serve(ctx, req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(outcome.attempts(), 5);
+
+        // the paused clock rounds each sleep up to the next millisecond
+        let delays: Vec<_> = attempts
+            .lock()
+            .windows(2)
+            .map(|w| w[1].duration_since(w[0]).as_millis())
+            .collect();
+        assert_eq!(delays.len(), 4);
+        for (delay, expected) in delays.into_iter().zip([100, 200, 300, 300]) {
+            assert!(
+                (expected..=expected + 1).contains(&delay),
+                "delay: {delay}ms, expected: {expected}ms"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exponential_backoff_abort() {
+        tokio::time::pause();
+
+        let policy = ExponentialBackoffPolicy::new(retry_server_errors).with_jitter(0.5);
+        let req = Request::builder().body(RetryBody::empty()).unwrap();
+
+        // results rejected by the classifier are not retried
+        let result = policy
+            .retry(
+                Context::default(),
+                req.clone(),
+                Ok(StatusCode::OK.into_response()),
+                0,
+            )
+            .await;
+        assert!(matches!(result, PolicyResult::Abort(Ok(_))));
+
+        // requests which are not to be retried are not cloned either
+        let mut ctx = Context::default();
+        ctx.insert(DoNotRetry);
+        assert!(policy.clone_input(&ctx, &req).is_none());
+        assert!(policy.clone_input(&Context::default(), &req).is_some());
+    }
+}
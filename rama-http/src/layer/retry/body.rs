@@ -1,61 +1,141 @@
+use crate::Body;
 use bytes::Bytes;
+use rama_core::error::{BoxError, OpaqueError};
+use std::{fmt, pin::Pin, task::Poll};
 
-#[derive(Debug, Clone)]
 /// A body that can be clone and used for requests that have to be rertried.
+///
+/// A [`RetryBody`] is usually buffered, either by the [`Retry`] service
+/// or the [`BufferRequestBodyService`] in front of it. The latter can also
+/// pass bodies exceeding its limit through as they are, in which case the body
+/// is streamed and thus not replayable, as reported by [`RetryBody::is_buffered`].
+/// Such a request is never retried by the [`Retry`] service.
+/// Polling a clone of a streamed body results in an error.
+///
+/// [`Retry`]: super::Retry
+/// [`BufferRequestBodyService`]: super::BufferRequestBodyService
 pub struct RetryBody {
-    bytes: Option<Bytes>,
+    kind: RetryBodyKind,
+}
+
+enum RetryBodyKind {
+    Buffered(Option<Bytes>),
+    Streaming(Body),
+    Consumed,
 }
 
 impl RetryBody {
     pub(crate) fn new(bytes: Bytes) -> Self {
-        RetryBody { bytes: Some(bytes) }
+        RetryBody {
+            kind: RetryBodyKind::Buffered(Some(bytes)),
+        }
+    }
+
+    pub(crate) fn streaming(body: Body) -> Self {
+        RetryBody {
+            kind: RetryBodyKind::Streaming(body),
+        }
     }
 
     #[cfg(test)]
     pub(crate) fn empty() -> Self {
-        RetryBody { bytes: None }
+        RetryBody {
+            kind: RetryBodyKind::Buffered(None),
+        }
+    }
+
+    /// Returns `true` if the body is buffered in memory,
+    /// such that it can be replayed by cloning it.
+    pub fn is_buffered(&self) -> bool {
+        matches!(self.kind, RetryBodyKind::Buffered(_))
     }
 
     /// Turn this body into bytes.
+    ///
+    /// Returns `None` in case the body is empty or not buffered.
     pub fn into_bytes(self) -> Option<Bytes> {
-        self.bytes
+        match self.kind {
+            RetryBodyKind::Buffered(bytes) => bytes,
+            RetryBodyKind::Streaming(_) | RetryBodyKind::Consumed => None,
+        }
+    }
+}
+
+impl fmt::Debug for RetryBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            RetryBodyKind::Buffered(bytes) => {
+                f.debug_struct("RetryBody").field("bytes", bytes).finish()
+            }
+            RetryBodyKind::Streaming(_) => f.debug_struct("RetryBody").finish_non_exhaustive(),
+            RetryBodyKind::Consumed => f
+                .debug_struct("RetryBody")
+                .field("consumed", &true)
+                .finish(),
+        }
+    }
+}
+
+impl Clone for RetryBody {
+    fn clone(&self) -> Self {
+        RetryBody {
+            kind: match &self.kind {
+                RetryBodyKind::Buffered(bytes) => RetryBodyKind::Buffered(bytes.clone()),
+                // a stream can only be consumed once
+                RetryBodyKind::Streaming(_) | RetryBodyKind::Consumed => RetryBodyKind::Consumed,
+            },
+        }
     }
 }
 
 impl crate::dep::http_body::Body for RetryBody {
     type Data = Bytes;
-    type Error = rama_core::error::BoxError;
+    type Error = BoxError;
 
     fn poll_frame(
-        mut self: std::pin::Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
-        std::task::Poll::Ready(
-            self.bytes
-                .take()
-                .map(|bytes| Ok(http_body::Frame::data(bytes))),
-        )
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        match &mut self.kind {
+            RetryBodyKind::Buffered(bytes) => {
+                Poll::Ready(bytes.take().map(|bytes| Ok(http_body::Frame::data(bytes))))
+            }
+            RetryBodyKind::Streaming(body) => Pin::new(body).poll_frame(cx).map_err(Into::into),
+            RetryBodyKind::Consumed => Poll::Ready(Some(Err(OpaqueError::from_display(
+                "streamed retry body cannot be replayed",
+            )
+            .into()))),
+        }
     }
 
     fn is_end_stream(&self) -> bool {
-        self.bytes.is_none()
+        match &self.kind {
+            RetryBodyKind::Buffered(bytes) => bytes.is_none(),
+            RetryBodyKind::Streaming(body) => body.is_end_stream(),
+            RetryBodyKind::Consumed => false,
+        }
     }
 
     fn size_hint(&self) -> http_body::SizeHint {
-        http_body::SizeHint::with_exact(
-            self.bytes
-                .as_ref()
-                .map(|b| b.len() as u64)
-                .unwrap_or_default(),
-        )
+        match &self.kind {
+            RetryBodyKind::Buffered(bytes) => http_body::SizeHint::with_exact(
+                bytes.as_ref().map(|b| b.len() as u64).unwrap_or_default(),
+            ),
+            RetryBodyKind::Streaming(body) => body.size_hint(),
+            RetryBodyKind::Consumed => http_body::SizeHint::default(),
+        }
     }
 }
 
 impl From<RetryBody> for crate::Body {
     fn from(body: RetryBody) -> Self {
-        match body.bytes {
-            Some(bytes) => bytes.into(),
-            None => crate::Body::empty(),
+        match body.kind {
+            RetryBodyKind::Buffered(Some(bytes)) => bytes.into(),
+            RetryBodyKind::Buffered(None) => crate::Body::empty(),
+            RetryBodyKind::Streaming(body) => body,
+            RetryBodyKind::Consumed => crate::Body::new(RetryBody {
+                kind: RetryBodyKind::Consumed,
+            }),
         }
     }
 }
@@ -71,4 +151,15 @@ mod tests {
         let s = body.try_into_string().await.unwrap();
         assert_eq!(s, "hello");
     }
+
+    #[tokio::test]
+    async fn consume_streamed_retry_body() {
+        let body = RetryBody::streaming(Body::from("hello"));
+        assert!(!body.is_buffered());
+
+        let clone = body.clone();
+        assert!(!clone.is_buffered());
+        assert_eq!(body.try_into_string().await.unwrap(), "hello");
+        clone.try_into_string().await.unwrap_err();
+    }
 }
@@ -0,0 +1,235 @@
+//! Buffer request bodies such that the requests can be retried.
+//!
+//! See [`BufferRequestBodyLayer`] for more details.
+
+use super::RetryBody;
+use crate::dep::{http_body::Body as _, http_body_util::BodyExt};
+use crate::{Body, Request};
+use bytes::{Bytes, BytesMut};
+use futures_lite::StreamExt;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+
+/// Layer which buffers the request body in memory (up to a limit),
+/// turning it into a replayable [`RetryBody`], such that the request
+/// can be cloned and retried by the [`Retry`] service wrapped by it.
+///
+/// Bodies of which the (announced) size exceeds the limit are passed through
+/// as a streamed [`RetryBody`] instead, the part already read included.
+/// Such requests are not retried, instead of failing them.
+///
+/// Without this layer the [`Retry`] service buffers the full request body itself.
+///
+/// # Example
+///
+/// ```
+/// use rama_core::Layer;
+/// use rama_http::layer::retry::{BufferRequestBodyLayer, HttpRetryPolicy, RetryLayer};
+///
+/// let _layer = (
+///     // retry requests with a body of up to 64KiB
+///     BufferRequestBodyLayer::new(64 * 1024),
+///     RetryLayer::new(HttpRetryPolicy::new()),
+/// );
+/// ```
+///
+/// [`Retry`]: super::Retry
+#[derive(Debug, Clone)]
+pub struct BufferRequestBodyLayer {
+    max_bytes: usize,
+}
+
+impl BufferRequestBodyLayer {
+    /// Create a new [`BufferRequestBodyLayer`],
+    /// buffering request bodies of up to `max_bytes` bytes.
+    pub const fn new(max_bytes: usize) -> Self {
+        Self { max_bytes }
+    }
+}
+
+impl<S> Layer<S> for BufferRequestBodyLayer {
+    type Service = BufferRequestBodyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BufferRequestBodyService::new(inner, self.max_bytes)
+    }
+}
+
+/// Service which buffers the request body in memory (up to a limit),
+/// turning it into a replayable [`RetryBody`].
+///
+/// See [`BufferRequestBodyLayer`] for more details.
+pub struct BufferRequestBodyService<S> {
+    inner: S,
+    max_bytes: usize,
+}
+
+impl<S> BufferRequestBodyService<S> {
+    /// Create a new [`BufferRequestBodyService`],
+    /// buffering request bodies of up to `max_bytes` bytes.
+    pub const fn new(inner: S, max_bytes: usize) -> Self {
+        Self { inner, max_bytes }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for BufferRequestBodyService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferRequestBodyService")
+            .field("inner", &self.inner)
+            .field("max_bytes", &self.max_bytes)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for BufferRequestBodyService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+impl<S, State, ReqBody> Service<State, Request<ReqBody>> for BufferRequestBodyService<S>
+where
+    S: Service<State, Request<RetryBody>, Error: Into<BoxError>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let (parts, body) = req.into_parts();
+        let body = buffer_body(Body::new(body), self.max_bytes).await?;
+        self.inner
+            .serve(ctx, Request::from_parts(parts, body))
+            .await
+            .map_err(Into::into)
+    }
+}
+
+/// Buffer the body in memory, or stream it in case it exceeds `max_bytes`.
+async fn buffer_body(mut body: Body, max_bytes: usize) -> Result<RetryBody, BoxError> {
+    if body.size_hint().lower() > max_bytes as u64 {
+        tracing::trace!(max_bytes, "request body exceeds buffer limit: stream it");
+        return Ok(RetryBody::streaming(body));
+    }
+
+    let mut buffer = BytesMut::new();
+    while let Some(frame) = body.frame().await {
+        // trailers are dropped, just like the retry service does when buffering
+        let Ok(data) = frame?.into_data() else {
+            continue;
+        };
+        if buffer.len() + data.len() > max_bytes {
+            tracing::trace!(max_bytes, "request body exceeds buffer limit: stream it");
+            buffer.extend_from_slice(&data);
+            let read = futures_lite::stream::once(Ok(buffer.freeze()));
+            return Ok(RetryBody::streaming(Body::from_stream(
+                read.chain(body.into_data_stream()),
+            )));
+        }
+        buffer.extend_from_slice(&data);
+    }
+    Ok(RetryBody::new(buffer.freeze()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::retry::{HttpRetryPolicy, RetryLayer, RetryableRequest};
+    use crate::{BodyExtractExt, IntoResponse, Method, Response, StatusCode};
+    use rama_core::service::service_fn;
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    fn chunked_body(chunks: &'static [&'static str]) -> Body {
+        Body::from_stream(futures_lite::stream::iter(
+            chunks.iter().map(|chunk| Ok::<_, Infallible>(*chunk)),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_buffer_body() {
+        let body = buffer_body(chunked_body(&["hello", " ", "world"]), 11)
+            .await
+            .unwrap();
+        assert!(body.is_buffered());
+        assert_eq!(body.into_bytes().unwrap(), "hello world");
+
+        // exceeding the limit while reading
+        let body = buffer_body(chunked_body(&["hello", " ", "world"]), 10)
+            .await
+            .unwrap();
+        assert!(!body.is_buffered());
+        assert_eq!(body.try_into_string().await.unwrap(), "hello world");
+
+        // exceeding the limit according to the size hint
+        let body = buffer_body(Body::from("hello world"), 4).await.unwrap();
+        assert!(!body.is_buffered());
+        assert_eq!(body.try_into_string().await.unwrap(), "hello world");
+    }
+
+    async fn serve_flaky_post(max_bytes: usize) -> (Response, Vec<String>) {
+        let bodies = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        let svc = (
+            BufferRequestBodyLayer::new(max_bytes),
+            RetryLayer::new(HttpRetryPolicy::new().with_fallback(std::time::Duration::ZERO)),
+        )
+            .layer(service_fn({
+                let bodies = bodies.clone();
+                move |req: Request<RetryBody>| {
+                    let bodies = bodies.clone();
+                    let attempts = attempts.clone();
+                    async move {
+                        let body = req.try_into_string().await.unwrap();
+                        bodies.lock().push(body);
+                        let res = match attempts.fetch_add(1, Ordering::AcqRel) {
+                            0 => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                            _ => StatusCode::OK.into_response(),
+                        };
+                        Ok::<_, BoxError>(res)
+                    }
+                }
+            }));
+
+        let mut ctx = Context::default();
+        ctx.insert(RetryableRequest);
+        let req = Request::builder()
+            .method(Method::POST)
+            .body(chunked_body(&["hello", " ", "world"]))
+            .unwrap();
+        let res = svc.serve(ctx, req).await.unwrap();
+        let bodies = std::mem::take(&mut *bodies.lock());
+        (res, bodies)
+    }
+
+    #[tokio::test]
+    async fn test_buffered_post_is_retried() {
+        let (res, bodies) = serve_flaky_post(1024).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(bodies, ["hello world", "hello world"]);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_post_is_not_retried() {
+        let (res, bodies) = serve_flaky_post(5).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(bodies, ["hello world"]);
+    }
+}
@@ -0,0 +1,363 @@
+//! Error classifying retry [`Policy`].
+//!
+//! See [`ClassifyRetry`] for more details.
+//!
+//! [`Policy`]: super::Policy
+
+use super::{managed::DoNotRetry, Policy, PolicyResult, RetryBody};
+use crate::Request;
+use rama_core::{
+    error::{BoxError, OpaqueError},
+    Context,
+};
+use std::{error::Error, fmt, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The decision made by a [`Classify`] implementation for a failed attempt.
+pub enum RetryDecision {
+    /// The request should be retried.
+    Retry,
+    /// The error should be returned to the caller.
+    Abort,
+}
+
+/// Classifies the errors of a service as retryable or not.
+///
+/// Implemented for closures `Fn(&E) -> RetryDecision`
+/// and for [`IoErrorKinds`], the latter for [`io::Error`], [`OpaqueError`] and [`BoxError`].
+pub trait Classify<E>: Send + Sync + 'static {
+    /// Decide whether the request which failed with the given error should be retried.
+    fn classify(&self, error: &E) -> RetryDecision;
+}
+
+impl<F, E> Classify<E> for F
+where
+    F: Fn(&E) -> RetryDecision + Send + Sync + 'static,
+{
+    fn classify(&self, error: &E) -> RetryDecision {
+        self(error)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [`Classify`] implementation which retries errors caused by
+/// an [`io::Error`] of one of the configured [`io::ErrorKind`]s.
+///
+/// The full [source chain] of the error is inspected.
+/// Use [`IoErrorKinds::matches`] within your own classifier
+/// for other error types.
+///
+/// [source chain]: std::error::Error::source
+pub struct IoErrorKinds(Vec<io::ErrorKind>);
+
+impl IoErrorKinds {
+    /// Create a new [`IoErrorKinds`] retrying the given kinds of [`io::Error`].
+    pub fn new(kinds: impl IntoIterator<Item = io::ErrorKind>) -> Self {
+        Self(kinds.into_iter().collect())
+    }
+
+    /// Returns `true` if the given error is caused by
+    /// an [`io::Error`] of one of the configured kinds.
+    pub fn matches(&self, error: &(dyn Error + 'static)) -> bool {
+        let mut source = Some(error);
+        while let Some(err) = source {
+            if let Some(io_err) = err.downcast_ref::<io::Error>() {
+                if self.0.contains(&io_err.kind()) {
+                    return true;
+                }
+                // io errors do not expose the error they wrap as their source
+                if let Some(inner) = io_err.get_ref() {
+                    source = Some(inner);
+                    continue;
+                }
+            }
+            source = err.source();
+        }
+        false
+    }
+
+    fn decide(&self, error: &(dyn Error + 'static)) -> RetryDecision {
+        if self.matches(error) {
+            RetryDecision::Retry
+        } else {
+            RetryDecision::Abort
+        }
+    }
+}
+
+impl Classify<io::Error> for IoErrorKinds {
+    fn classify(&self, error: &io::Error) -> RetryDecision {
+        self.decide(error)
+    }
+}
+
+impl Classify<OpaqueError> for IoErrorKinds {
+    fn classify(&self, error: &OpaqueError) -> RetryDecision {
+        self.decide(error)
+    }
+}
+
+impl Classify<BoxError> for IoErrorKinds {
+    fn classify(&self, error: &BoxError) -> RetryDecision {
+        self.decide(error.as_ref())
+    }
+}
+
+/// A retry [`Policy`] which classifies the errors of the inner service
+/// using a [`Classify`] implementation, such as a closure `Fn(&E) -> RetryDecision`.
+///
+/// This allows retry logic to be expressed against the concrete error type
+/// of the inner service, instead of comparing its string representation.
+///
+/// Responses are never retried.
+/// Requests are retried at most `max_retries` times (3 by default).
+/// Just like the [`ManagedPolicy`], requests with [`DoNotRetry`]
+/// in their [`Context`] are never retried.
+///
+/// # Example
+///
+/// ```
+/// use rama_http::layer::retry::{ClassifyRetry, RetryDecision, RetryLayer};
+/// use std::io;
+///
+/// // retry on the common transient io errors
+/// let _layer = RetryLayer::new(ClassifyRetry::io_error_kinds([
+///     io::ErrorKind::ConnectionReset,
+///     io::ErrorKind::TimedOut,
+/// ]));
+///
+/// // or classify the errors of the inner service yourself
+/// let _layer = RetryLayer::new(
+///     ClassifyRetry::new(|err: &io::Error| {
+///         if err.kind() == io::ErrorKind::ConnectionRefused {
+///             RetryDecision::Retry
+///         } else {
+///             RetryDecision::Abort
+///         }
+///     })
+///     .with_max_retries(5),
+/// );
+/// ```
+///
+/// [`ManagedPolicy`]: super::ManagedPolicy
+pub struct ClassifyRetry<C> {
+    classify: C,
+    max_retries: usize,
+}
+
+impl<C: fmt::Debug> fmt::Debug for ClassifyRetry<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClassifyRetry")
+            .field("classify", &self.classify)
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+impl<C: Clone> Clone for ClassifyRetry<C> {
+    fn clone(&self) -> Self {
+        Self {
+            classify: self.classify.clone(),
+            max_retries: self.max_retries,
+        }
+    }
+}
+
+impl<C> ClassifyRetry<C> {
+    /// Create a new [`ClassifyRetry`] policy using the given [`Classify`] implementation.
+    pub const fn new(classify: C) -> Self {
+        Self {
+            classify,
+            max_retries: 3,
+        }
+    }
+
+    /// Set the maximum amount of retries made for a single request.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the maximum amount of retries made for a single request.
+    pub fn set_max_retries(&mut self, max_retries: usize) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+}
+
+impl ClassifyRetry<IoErrorKinds> {
+    /// Create a new [`ClassifyRetry`] policy retrying errors
+    /// caused by an [`io::Error`] of one of the given kinds.
+    ///
+    /// See [`IoErrorKinds`] for more information.
+    pub fn io_error_kinds(kinds: impl IntoIterator<Item = io::ErrorKind>) -> Self {
+        Self::new(IoErrorKinds::new(kinds))
+    }
+}
+
+impl<C, State, Response, Error> Policy<State, Response, Error> for ClassifyRetry<C>
+where
+    C: Classify<Error>,
+    State: Clone + Send + Sync + 'static,
+    Response: Send + 'static,
+    Error: Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        req: Request<RetryBody>,
+        result: Result<Response, Error>,
+        attempt: usize,
+    ) -> PolicyResult<State, Response, Error> {
+        match &result {
+            Err(err)
+                if attempt < self.max_retries
+                    && self.classify.classify(err) == RetryDecision::Retry =>
+            {
+                PolicyResult::Retry { ctx, req }
+            }
+            _ => PolicyResult::Abort(result),
+        }
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        if ctx.contains::<DoNotRetry>() {
+            None
+        } else {
+            Some((ctx.clone(), req.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::retry::RetryLayer;
+    use crate::{IntoResponse, Response};
+    use rama_core::{Layer, Service};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[derive(Debug)]
+    enum BackendError {
+        Transient,
+        Permanent,
+    }
+
+    impl fmt::Display for BackendError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{self:?} backend error")
+        }
+    }
+
+    impl Error for BackendError {}
+
+    fn classify_backend_error(err: &BackendError) -> RetryDecision {
+        match err {
+            BackendError::Transient => RetryDecision::Retry,
+            BackendError::Permanent => RetryDecision::Abort,
+        }
+    }
+
+    struct Svc<E> {
+        counter: Arc<AtomicUsize>,
+        errors: fn(usize) -> Option<E>,
+    }
+
+    impl<E: Send + Sync + 'static> Service<(), Request<RetryBody>> for Svc<E> {
+        type Response = Response;
+        type Error = E;
+
+        async fn serve(
+            &self,
+            _ctx: Context<()>,
+            _req: Request<RetryBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            let attempt = self.counter.fetch_add(1, Ordering::AcqRel);
+            match (self.errors)(attempt) {
+                Some(err) => Err(err),
+                None => Ok("ok".into_response()),
+            }
+        }
+    }
+
+    fn request() -> Request {
+        Request::builder().body("hello".into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_classify_retry_transient_error() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let svc = RetryLayer::new(ClassifyRetry::new(classify_backend_error)).layer(Svc {
+            counter: counter.clone(),
+            errors: |attempt| (attempt < 2).then_some(BackendError::Transient),
+        });
+
+        svc.serve(Context::default(), request()).await.unwrap();
+        assert_eq!(counter.load(Ordering::Acquire), 3);
+    }
+
+    #[tokio::test]
+    async fn test_classify_retry_permanent_error() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let svc = RetryLayer::new(ClassifyRetry::new(classify_backend_error)).layer(Svc {
+            counter: counter.clone(),
+            errors: |_| Some(BackendError::Permanent),
+        });
+
+        svc.serve(Context::default(), request()).await.unwrap_err();
+        assert_eq!(counter.load(Ordering::Acquire), 1);
+    }
+
+    #[tokio::test]
+    async fn test_classify_retry_max_retries() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let svc = RetryLayer::new(ClassifyRetry::new(classify_backend_error).with_max_retries(1))
+            .layer(Svc {
+                counter: counter.clone(),
+                errors: |_| Some(BackendError::Transient),
+            });
+
+        svc.serve(Context::default(), request()).await.unwrap_err();
+        assert_eq!(counter.load(Ordering::Acquire), 2);
+    }
+
+    #[tokio::test]
+    async fn test_classify_retry_io_error_kinds() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let svc = RetryLayer::new(ClassifyRetry::io_error_kinds([
+            io::ErrorKind::ConnectionReset,
+        ]))
+        .layer(Svc::<BoxError> {
+            counter: counter.clone(),
+            errors: |attempt| match attempt {
+                0 => Some(io::Error::from(io::ErrorKind::ConnectionReset).into()),
+                1 => Some(
+                    OpaqueError::from_std(io::Error::from(io::ErrorKind::ConnectionRefused)).into(),
+                ),
+                _ => None,
+            },
+        });
+
+        // the connection reset is retried, the (wrapped) connection refused is not
+        svc.serve(Context::default(), request()).await.unwrap_err();
+        assert_eq!(counter.load(Ordering::Acquire), 2);
+    }
+
+    #[test]
+    fn test_io_error_kinds_source_chain() {
+        let kinds = IoErrorKinds::new([io::ErrorKind::TimedOut]);
+        let err = OpaqueError::from_std(io::Error::from(io::ErrorKind::TimedOut));
+        assert_eq!(kinds.classify(&err), RetryDecision::Retry);
+        let err = io::Error::other(io::Error::from(io::ErrorKind::TimedOut));
+        assert_eq!(kinds.classify(&err), RetryDecision::Retry);
+        let err = io::Error::from(io::ErrorKind::NotFound);
+        assert_eq!(kinds.classify(&err), RetryDecision::Abort);
+    }
+}
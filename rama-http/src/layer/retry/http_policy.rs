@@ -0,0 +1,414 @@
+//! Http aware retry [`Policy`].
+//!
+//! See [`HttpRetryPolicy`] for more details.
+//!
+//! [`Policy`]: super::Policy
+
+use super::{
+    classify::{Classify, IoErrorKinds, RetryDecision},
+    managed::DoNotRetry,
+    retry_after::parse_retry_after,
+    Policy, PolicyResult, RetryBody, RetryOutcome,
+};
+use crate::{Request, Response, StatusCode};
+use rama_core::Context;
+use rama_utils::backoff::exponential_delay;
+use std::{
+    fmt, io,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Debug, Clone, Default)]
+/// An [`Extensions`] value that can be added to the [`Context`]
+/// of a [`Request`] to signal that it is safe to retry the request,
+/// even though its method is not idempotent (e.g. `POST`).
+///
+/// This requires the [`HttpRetryPolicy`] to be used.
+///
+/// [`Extensions`]: rama_core::context::Extensions
+#[non_exhaustive]
+pub struct RetryableRequest;
+
+/// A retry [`Policy`] for http clients, which classifies both errors and responses.
+///
+/// By default it retries:
+///
+/// - errors caused by a connection failure (refused, reset, aborted, timed out, ...);
+/// - `429 Too Many Requests`, `502 Bad Gateway`, `503 Service Unavailable`
+///   and `504 Gateway Timeout` responses, configurable using [`HttpRetryPolicy::with_statuses`].
+///
+/// Prior to retrying a response it sleeps for the delay found in its `Retry-After` header,
+/// which can either contain a number of seconds or an http date. In case the header is absent
+/// or cannot be parsed, as well as for errors, it falls back to an exponential backoff
+/// (`fallback * 2^retry`). The delay is capped at a configurable maximum (60s by default).
+///
+/// Only requests with an idempotent method (`GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS` and `TRACE`)
+/// are retried, unless [`RetryableRequest`] is found in their [`Context`].
+/// Just like the [`ManagedPolicy`], requests with [`DoNotRetry`]
+/// in their [`Context`] are never retried.
+/// Requests are retried at most `max_retries` times (3 by default).
+///
+/// The request body is buffered by the [`Retry`] service, such that the request
+/// can be cloned as part of [`Policy::clone_input`]. A request for which no clone
+/// is made (e.g. because it is not idempotent) is never retried, as its body is consumed
+/// by the first attempt.
+///
+/// # Example
+///
+/// ```
+/// use rama_http::layer::retry::{HttpRetryPolicy, RetryLayer};
+/// use rama_http::StatusCode;
+/// use std::time::Duration;
+///
+/// let _layer = RetryLayer::new(
+///     HttpRetryPolicy::new()
+///         .with_statuses([StatusCode::SERVICE_UNAVAILABLE, StatusCode::GATEWAY_TIMEOUT])
+///         .with_max_delay(Duration::from_secs(10))
+///         .with_max_retries(5),
+/// );
+/// ```
+///
+/// [`ManagedPolicy`]: super::ManagedPolicy
+/// [`Retry`]: super::Retry
+pub struct HttpRetryPolicy<C = IoErrorKinds> {
+    classify: C,
+    statuses: Vec<StatusCode>,
+    max_delay: Duration,
+    fallback: Duration,
+    max_retries: usize,
+}
+
+impl<C: fmt::Debug> fmt::Debug for HttpRetryPolicy<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpRetryPolicy")
+            .field("classify", &self.classify)
+            .field("statuses", &self.statuses)
+            .field("max_delay", &self.max_delay)
+            .field("fallback", &self.fallback)
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+impl<C: Clone> Clone for HttpRetryPolicy<C> {
+    fn clone(&self) -> Self {
+        Self {
+            classify: self.classify.clone(),
+            statuses: self.statuses.clone(),
+            max_delay: self.max_delay,
+            fallback: self.fallback,
+            max_retries: self.max_retries,
+        }
+    }
+}
+
+impl Default for HttpRetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpRetryPolicy {
+    /// Create a new [`HttpRetryPolicy`] using the default configuration.
+    pub fn new() -> Self {
+        Self {
+            classify: IoErrorKinds::new([
+                io::ErrorKind::ConnectionRefused,
+                io::ErrorKind::ConnectionReset,
+                io::ErrorKind::ConnectionAborted,
+                io::ErrorKind::NotConnected,
+                io::ErrorKind::BrokenPipe,
+                io::ErrorKind::TimedOut,
+                io::ErrorKind::UnexpectedEof,
+            ]),
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            max_delay: Duration::from_secs(60),
+            fallback: Duration::from_secs(1),
+            max_retries: 3,
+        }
+    }
+}
+
+impl<C> HttpRetryPolicy<C> {
+    /// Classify the errors of the inner service using the given [`Classify`] implementation,
+    /// instead of retrying connection failures.
+    pub fn with_classify<T>(self, classify: T) -> HttpRetryPolicy<T> {
+        HttpRetryPolicy {
+            classify,
+            statuses: self.statuses,
+            max_delay: self.max_delay,
+            fallback: self.fallback,
+            max_retries: self.max_retries,
+        }
+    }
+
+    /// Set the status codes of the responses to be retried,
+    /// replacing the default ones.
+    pub fn with_statuses(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Set the status codes of the responses to be retried,
+    /// replacing the default ones.
+    pub fn set_statuses(&mut self, statuses: impl IntoIterator<Item = StatusCode>) -> &mut Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Set the maximum delay honored, applied to the fallback backoff as well.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the maximum delay honored, applied to the fallback backoff as well.
+    pub fn set_max_delay(&mut self, max_delay: Duration) -> &mut Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the delay prior to the first retry in case no valid `Retry-After` header is found,
+    /// doubled for every subsequent retry.
+    pub fn with_fallback(mut self, fallback: Duration) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Set the delay prior to the first retry in case no valid `Retry-After` header is found,
+    /// doubled for every subsequent retry.
+    pub fn set_fallback(&mut self, fallback: Duration) -> &mut Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Set the maximum number of times a request is retried.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the maximum number of times a request is retried.
+    pub fn set_max_retries(&mut self, max_retries: usize) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Compute the delay prior to the given retry (starting at `0`) of the given response,
+    /// `None` in case the response is not to be retried.
+    pub fn delay<Body>(&self, res: &Response<Body>, retry: usize) -> Option<Duration> {
+        if !self.statuses.contains(&res.status()) {
+            return None;
+        }
+
+        let delay = parse_retry_after(res, SystemTime::now())
+            .unwrap_or_else(|| exponential_delay(self.fallback, self.max_delay, retry));
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// Returns `true` if the request is allowed to be retried,
+/// based on its method and the [`RetryableRequest`] marker.
+fn is_retryable_request<State>(ctx: &Context<State>, req: &Request<RetryBody>) -> bool {
+    req.method().is_idempotent() || ctx.contains::<RetryableRequest>()
+}
+
+impl<C, State, Body, Error> Policy<State, Response<Body>, Error> for HttpRetryPolicy<C>
+where
+    C: Classify<Error>,
+    State: Clone + Send + Sync + 'static,
+    Body: Send + 'static,
+    Error: Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        req: Request<RetryBody>,
+        result: Result<Response<Body>, Error>,
+        attempt: usize,
+    ) -> PolicyResult<State, Response<Body>, Error> {
+        if attempt >= self.max_retries
+            || ctx.contains::<DoNotRetry>()
+            || !is_retryable_request(&ctx, &req)
+        {
+            return PolicyResult::Abort(result);
+        }
+
+        let delay = match &result {
+            Ok(res) => self.delay(res, attempt),
+            Err(err) => (self.classify.classify(err) == RetryDecision::Retry)
+                .then(|| exponential_delay(self.fallback, self.max_delay, attempt)),
+        };
+        let Some(delay) = delay else {
+            return PolicyResult::Abort(result);
+        };
+
+        tracing::trace!(attempt, ?delay, "http retry policy: sleep prior to retry");
+        tokio::time::sleep(delay).await;
+        PolicyResult::Retry { ctx, req }
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        let attempts = ctx
+            .get::<RetryOutcome>()
+            .map(RetryOutcome::attempts)
+            .unwrap_or_default();
+        if attempts >= self.max_retries
+            || ctx.contains::<DoNotRetry>()
+            || !is_retryable_request(ctx, req)
+        {
+            None
+        } else {
+            Some((ctx.clone(), req.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{header::RETRY_AFTER, layer::retry::RetryLayer, HeaderValue, IntoResponse, Method};
+    use rama_core::{error::BoxError, service::service_fn, Layer, Service};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn response(status: StatusCode, retry_after: Option<&str>) -> Response {
+        let mut res = status.into_response();
+        if let Some(value) = retry_after {
+            res.headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        }
+        res
+    }
+
+    #[test]
+    fn test_http_retry_policy_delay() {
+        let policy = HttpRetryPolicy::new()
+            .with_max_delay(Duration::from_secs(10))
+            .with_fallback(Duration::from_secs(1));
+
+        for (status, retry_after, retry, expected) in [
+            (StatusCode::TOO_MANY_REQUESTS, Some("3"), 0, Some(3)),
+            (StatusCode::BAD_GATEWAY, None, 1, Some(2)),
+            (StatusCode::GATEWAY_TIMEOUT, Some("3600"), 0, Some(10)),
+            (StatusCode::SERVICE_UNAVAILABLE, Some("soon"), 2, Some(4)),
+            (StatusCode::INTERNAL_SERVER_ERROR, Some("3"), 0, None),
+            (StatusCode::OK, None, 0, None),
+        ] {
+            assert_eq!(
+                policy.delay(&response(status, retry_after), retry),
+                expected.map(Duration::from_secs),
+                "status: {status}, retry-after: {retry_after:?}, retry: {retry}",
+            );
+        }
+
+        let policy = policy.with_statuses([StatusCode::INTERNAL_SERVER_ERROR]);
+        assert_eq!(
+            policy.delay(&response(StatusCode::INTERNAL_SERVER_ERROR, Some("3")), 0),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            policy.delay(&response(StatusCode::SERVICE_UNAVAILABLE, Some("3")), 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_http_retry_policy_delay_http_date() {
+        let policy = HttpRetryPolicy::new();
+
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(30));
+        let delay = policy
+            .delay(&response(StatusCode::SERVICE_UNAVAILABLE, Some(&date)), 0)
+            .unwrap();
+        // http dates have a precision of a second
+        assert!(
+            delay > Duration::from_secs(28) && delay <= Duration::from_secs(30),
+            "{delay:?}"
+        );
+
+        // dates in the past result in an immediate retry
+        let date = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(30));
+        assert_eq!(
+            policy.delay(&response(StatusCode::SERVICE_UNAVAILABLE, Some(&date)), 0),
+            Some(Duration::ZERO)
+        );
+    }
+
+    async fn serve_attempts(
+        method: Method,
+        retryable: bool,
+        results: fn(usize) -> Result<Response, BoxError>,
+    ) -> usize {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let svc = RetryLayer::new(HttpRetryPolicy::new()).layer(service_fn({
+            let attempts = attempts.clone();
+            move |_req: Request<RetryBody>| {
+                let attempts = attempts.clone();
+                async move { results(attempts.fetch_add(1, Ordering::AcqRel)) }
+            }
+        }));
+
+        let mut ctx = Context::default();
+        if retryable {
+            ctx.insert(RetryableRequest);
+        }
+        let req = Request::builder()
+            .method(method)
+            .body(RetryBody::new("hello".into()))
+            .unwrap();
+        let _ = svc.serve(ctx, req).await;
+        attempts.load(Ordering::Acquire)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_http_retry_policy_retries_statuses_and_connection_errors() {
+        let attempts = serve_attempts(Method::GET, false, |attempt| match attempt {
+            0 => Ok(response(StatusCode::SERVICE_UNAVAILABLE, Some("1"))),
+            1 => Err(io::Error::from(io::ErrorKind::ConnectionReset).into()),
+            _ => Ok(response(StatusCode::OK, None)),
+        })
+        .await;
+        assert_eq!(attempts, 3);
+
+        // other errors and statuses are not retried
+        let attempts = serve_attempts(Method::GET, false, |_| Err("oops".into())).await;
+        assert_eq!(attempts, 1);
+        let attempts = serve_attempts(Method::GET, false, |_| {
+            Ok(response(StatusCode::INTERNAL_SERVER_ERROR, None))
+        })
+        .await;
+        assert_eq!(attempts, 1);
+
+        // retries are bound by max_retries
+        let attempts = serve_attempts(Method::PUT, false, |_| {
+            Ok(response(StatusCode::BAD_GATEWAY, None))
+        })
+        .await;
+        assert_eq!(attempts, 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_http_retry_policy_idempotency_guard() {
+        let results = |attempt| match attempt {
+            0 => Ok(response(StatusCode::SERVICE_UNAVAILABLE, None)),
+            _ => Ok(response(StatusCode::OK, None)),
+        };
+
+        assert_eq!(serve_attempts(Method::POST, false, results).await, 1);
+        assert_eq!(serve_attempts(Method::PATCH, false, results).await, 1);
+        assert_eq!(serve_attempts(Method::POST, true, results).await, 2);
+        assert_eq!(serve_attempts(Method::DELETE, false, results).await, 2);
+    }
+}
@@ -1,24 +1,32 @@
-use super::Retry;
+use super::{AttemptTimeout, Retry};
+use rama_core::layer::timeout::Elapsed;
+use rama_core::layer::{LayerErrorFn, LayerErrorStatic};
 use rama_core::Layer;
-use std::fmt;
+use std::{fmt, time::Duration};
 
 /// Retry requests based on a policy
-pub struct RetryLayer<P> {
+pub struct RetryLayer<P, T = ()> {
     policy: P,
+    attempt_timeout: T,
+    timeout: Option<Duration>,
 }
 
-impl<P: fmt::Debug> fmt::Debug for RetryLayer<P> {
+impl<P: fmt::Debug, T: fmt::Debug> fmt::Debug for RetryLayer<P, T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("RetryLayer")
             .field("policy", &self.policy)
+            .field("attempt_timeout", &self.attempt_timeout)
+            .field("timeout", &self.timeout)
             .finish()
     }
 }
 
-impl<P: Clone> Clone for RetryLayer<P> {
+impl<P: Clone, T: Clone> Clone for RetryLayer<P, T> {
     fn clone(&self) -> Self {
         Self {
             policy: self.policy.clone(),
+            attempt_timeout: self.attempt_timeout.clone(),
+            timeout: self.timeout,
         }
     }
 }
@@ -26,18 +34,95 @@ impl<P: Clone> Clone for RetryLayer<P> {
 impl<P> RetryLayer<P> {
     /// Creates a new [`RetryLayer`] from a retry policy.
     pub const fn new(policy: P) -> Self {
-        RetryLayer { policy }
+        RetryLayer {
+            policy,
+            attempt_timeout: (),
+            timeout: None,
+        }
     }
 }
 
-impl<P, S> Layer<S> for RetryLayer<P>
+impl<P, T> RetryLayer<P, T> {
+    /// Bound each attempt by the given timeout.
+    ///
+    /// See [`Retry::with_attempt_timeout`] for more information.
+    pub fn with_attempt_timeout(
+        self,
+        timeout: Duration,
+    ) -> RetryLayer<P, AttemptTimeout<LayerErrorStatic<Elapsed>>> {
+        self.with_attempt_timeout_error(timeout, Elapsed::new(timeout))
+    }
+
+    /// Bound each attempt by the given timeout,
+    /// failing an attempt which does not complete in time with a clone of the given error.
+    ///
+    /// See [`Retry::with_attempt_timeout`] for more information.
+    pub fn with_attempt_timeout_error<E>(
+        self,
+        timeout: Duration,
+        error: E,
+    ) -> RetryLayer<P, AttemptTimeout<LayerErrorStatic<E>>>
+    where
+        E: Clone + Send + Sync + 'static,
+    {
+        RetryLayer {
+            policy: self.policy,
+            attempt_timeout: AttemptTimeout::new(timeout, LayerErrorStatic::new(error)),
+            timeout: self.timeout,
+        }
+    }
+
+    /// Bound each attempt by the given timeout,
+    /// failing an attempt which does not complete in time with the error created by the given function.
+    ///
+    /// See [`Retry::with_attempt_timeout`] for more information.
+    pub fn with_attempt_timeout_error_fn<F, E>(
+        self,
+        timeout: Duration,
+        error_fn: F,
+    ) -> RetryLayer<P, AttemptTimeout<LayerErrorFn<F>>>
+    where
+        F: FnOnce() -> E + Clone + Send + Sync + 'static,
+        E: Send + 'static,
+    {
+        RetryLayer {
+            policy: self.policy,
+            attempt_timeout: AttemptTimeout::new(timeout, LayerErrorFn::new(error_fn)),
+            timeout: self.timeout,
+        }
+    }
+
+    /// Bound all attempts together, including the backoff in between, by the given timeout.
+    ///
+    /// See [`Retry::with_timeout`] for more information.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Bound all attempts together, including the backoff in between, by the given timeout.
+    ///
+    /// See [`Retry::with_timeout`] for more information.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl<P, T, S> Layer<S> for RetryLayer<P, T>
 where
     P: Clone,
+    T: Clone,
 {
-    type Service = Retry<P, S>;
+    type Service = Retry<P, S, T>;
 
     fn layer(&self, service: S) -> Self::Service {
         let policy = self.policy.clone();
-        Retry::new(policy, service)
+        Retry {
+            policy,
+            inner: service,
+            attempt_timeout: self.attempt_timeout.clone(),
+            timeout: self.timeout,
+        }
     }
 }
@@ -0,0 +1,78 @@
+use super::{Policy, PolicyResult, RetryAttempt, RetryBody, RetryOutcome};
+use crate::Request;
+use rama_core::Context;
+use std::fmt;
+
+/// A retry [`Policy`] that caps the number of retries made by its inner `Policy`.
+///
+/// See [`PolicyExt::limit`][super::PolicyExt::limit] for more details.
+pub struct Limited<P> {
+    inner: P,
+    max_retries: usize,
+}
+
+impl<P> Limited<P> {
+    pub(crate) const fn new(inner: P, max_retries: usize) -> Self {
+        Limited { inner, max_retries }
+    }
+}
+
+impl<P: fmt::Debug> fmt::Debug for Limited<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Limited")
+            .field("inner", &self.inner)
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+impl<P: Clone> Clone for Limited<P> {
+    fn clone(&self) -> Self {
+        Limited {
+            inner: self.inner.clone(),
+            max_retries: self.max_retries,
+        }
+    }
+}
+
+impl<P, State, Response, Error> Policy<State, Response, Error> for Limited<P>
+where
+    P: Policy<State, Response, Error>,
+    State: Clone + Send + Sync + 'static,
+    Response: Send + 'static,
+    Error: Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        req: Request<RetryBody>,
+        result: Result<Response, Error>,
+        attempt: usize,
+    ) -> PolicyResult<State, Response, Error> {
+        if attempt >= self.max_retries {
+            return PolicyResult::Abort(result);
+        }
+        self.inner.retry(ctx, req, result, attempt).await
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        // no need to clone the input for an attempt which cannot be retried
+        let attempts = ctx
+            .get::<RetryOutcome>()
+            .map(RetryOutcome::attempts)
+            .unwrap_or_default();
+        if attempts >= self.max_retries {
+            None
+        } else {
+            self.inner.clone_input(ctx, req)
+        }
+    }
+
+    fn on_retry(&self, attempt: &RetryAttempt) {
+        self.inner.on_retry(attempt)
+    }
+}
@@ -46,6 +46,7 @@ where
         ctx: Context<State>,
         req: Request<RetryBody>,
         result: Result<Response, Error>,
+        _attempt: usize,
     ) -> PolicyResult<State, Response, Error> {
         if ctx.get::<DoNotRetry>().is_some() {
             // Custom extension to signal that the request should not be retried.
@@ -332,7 +333,7 @@ mod tests {
         result: Result<Response, ()>,
         policy: &impl Policy<(), Response, ()>,
     ) {
-        match policy.retry(ctx, req, result).await {
+        match policy.retry(ctx, req, result, 0).await {
             PolicyResult::Retry { .. } => (),
             PolicyResult::Abort(_) => panic!("expected retry"),
         };
@@ -344,7 +345,7 @@ mod tests {
         result: Result<Response, ()>,
         policy: &impl Policy<(), Response, ()>,
     ) {
-        match policy.retry(ctx, req, result).await {
+        match policy.retry(ctx, req, result, 0).await {
             PolicyResult::Retry { .. } => panic!("expected abort"),
             PolicyResult::Abort(_) => (),
         };
@@ -0,0 +1,70 @@
+use super::{Policy, PolicyResult, RetryAttempt, RetryBody};
+use crate::Request;
+use rama_core::Context;
+use std::fmt;
+
+/// A retry [`Policy`] that maps the result with which its inner `Policy` aborts.
+///
+/// See [`PolicyExt::map_result`][super::PolicyExt::map_result] for more details.
+pub struct MapResult<P, F> {
+    inner: P,
+    f: F,
+}
+
+impl<P, F> MapResult<P, F> {
+    pub(crate) const fn new(inner: P, f: F) -> Self {
+        MapResult { inner, f }
+    }
+}
+
+impl<P: fmt::Debug, F> fmt::Debug for MapResult<P, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapResult")
+            .field("inner", &self.inner)
+            .field("f", &std::any::type_name::<F>())
+            .finish()
+    }
+}
+
+impl<P: Clone, F: Clone> Clone for MapResult<P, F> {
+    fn clone(&self) -> Self {
+        MapResult {
+            inner: self.inner.clone(),
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<P, F, State, Response, Error> Policy<State, Response, Error> for MapResult<P, F>
+where
+    P: Policy<State, Response, Error>,
+    F: Fn(Result<Response, Error>) -> Result<Response, Error> + Send + Sync + 'static,
+    State: Clone + Send + Sync + 'static,
+    Response: Send + 'static,
+    Error: Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        req: Request<RetryBody>,
+        result: Result<Response, Error>,
+        attempt: usize,
+    ) -> PolicyResult<State, Response, Error> {
+        match self.inner.retry(ctx, req, result, attempt).await {
+            PolicyResult::Abort(result) => PolicyResult::Abort((self.f)(result)),
+            retry @ PolicyResult::Retry { .. } => retry,
+        }
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        self.inner.clone_input(ctx, req)
+    }
+
+    fn on_retry(&self, attempt: &RetryAttempt) {
+        self.inner.on_retry(attempt)
+    }
+}
@@ -4,55 +4,116 @@ use crate::dep::http_body::Body as HttpBody;
 use crate::dep::http_body_util::BodyExt;
 use crate::Request;
 use rama_core::error::BoxError;
+use rama_core::layer::timeout::{Deadline, Elapsed};
+use rama_core::layer::{LayerErrorFn, LayerErrorStatic};
 use rama_core::{Context, Service};
+use rama_utils::any::try_downcast;
 use rama_utils::macros::define_inner_service_accessors;
+use std::time::Duration;
 
 mod layer;
 mod policy;
 
+mod and;
+mod limited;
+mod map_result;
+mod or;
+
 mod body;
 #[doc(inline)]
 pub use body::RetryBody;
 
+mod buffer;
+#[doc(inline)]
+pub use buffer::{BufferRequestBodyLayer, BufferRequestBodyService};
+
 pub mod managed;
 pub use managed::ManagedPolicy;
 
+mod backoff;
+#[doc(inline)]
+pub use backoff::ExponentialBackoffPolicy;
+
+mod retry_after;
+#[doc(inline)]
+pub use retry_after::RetryAfterPolicy;
+
+mod http_policy;
+#[doc(inline)]
+pub use http_policy::{HttpRetryPolicy, RetryableRequest};
+
+mod outcome;
+#[doc(inline)]
+pub use outcome::RetryOutcome;
+
+mod attempt;
+#[doc(inline)]
+pub use attempt::RetryAttempt;
+
+mod attempt_timeout;
+#[doc(inline)]
+pub use attempt_timeout::{AttemptTimeout, MakeAttemptTimeout};
+
+pub mod classify;
+#[doc(inline)]
+pub use classify::{ClassifyRetry, RetryDecision};
+
 #[cfg(test)]
 mod tests;
 
+pub use self::and::And;
 pub use self::layer::RetryLayer;
-pub use self::policy::{Policy, PolicyResult};
+pub use self::limited::Limited;
+pub use self::map_result::MapResult;
+pub use self::or::Or;
+pub use self::policy::{Policy, PolicyExt, PolicyResult};
 
 /// Configure retrying requests of "failed" responses.
 ///
 /// A [`Policy`] classifies what is a "failed" response.
-pub struct Retry<P, S> {
+///
+/// The number of attempts and whether or not the request ultimately
+/// succeeded is recorded in the [`RetryOutcome`] found in the [`Context`].
+/// The [`Context`] passed to the inner service contains the [`RetryAttempt`] being made.
+///
+/// Each attempt can be bounded by its own timeout using [`Retry::with_attempt_timeout`],
+/// independently from the timeout bounding all attempts together,
+/// configured using [`Retry::with_timeout`].
+pub struct Retry<P, S, T = ()> {
     policy: P,
     inner: S,
+    attempt_timeout: T,
+    timeout: Option<Duration>,
 }
 
-impl<P, S> std::fmt::Debug for Retry<P, S>
+impl<P, S, T> std::fmt::Debug for Retry<P, S, T>
 where
     P: std::fmt::Debug,
     S: std::fmt::Debug,
+    T: std::fmt::Debug,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Retry")
             .field("policy", &self.policy)
             .field("inner", &self.inner)
+            .field("attempt_timeout", &self.attempt_timeout)
+            .field("timeout", &self.timeout)
             .finish()
     }
 }
 
-impl<P, S> Clone for Retry<P, S>
+impl<P, S, T> Clone for Retry<P, S, T>
 where
     P: Clone,
     S: Clone,
+    T: Clone,
 {
     fn clone(&self) -> Self {
         Retry {
             policy: self.policy.clone(),
             inner: self.inner.clone(),
+            attempt_timeout: self.attempt_timeout.clone(),
+            timeout: self.timeout,
         }
     }
 }
@@ -65,9 +126,90 @@ impl<P, S> Retry<P, S> {
         Retry {
             policy,
             inner: service,
+            attempt_timeout: (),
+            timeout: None,
+        }
+    }
+}
+
+impl<P, S, T> Retry<P, S, T> {
+    /// Bound each attempt by the given timeout.
+    ///
+    /// An attempt which does not complete in time is aborted, failing with an
+    /// [`Elapsed`] error which is passed to the [`Policy`] as any other error,
+    /// such that it can be retried. Use [`Self::with_attempt_timeout_error`]
+    /// or [`Self::with_attempt_timeout_error_fn`] in case the error type
+    /// of the inner service cannot be created from an [`Elapsed`] error.
+    ///
+    /// A [`Deadline`] expiring after this timeout, or earlier in case
+    /// the overall deadline expires first, is inserted in the [`Context`] of each attempt,
+    /// such that deadline aware services can bound their own work accordingly.
+    pub fn with_attempt_timeout(
+        self,
+        timeout: Duration,
+    ) -> Retry<P, S, AttemptTimeout<LayerErrorStatic<Elapsed>>> {
+        self.with_attempt_timeout_error(timeout, Elapsed::new(timeout))
+    }
+
+    /// Bound each attempt by the given timeout,
+    /// failing an attempt which does not complete in time with a clone of the given error.
+    ///
+    /// See [`Self::with_attempt_timeout`] for more information.
+    pub fn with_attempt_timeout_error<E>(
+        self,
+        timeout: Duration,
+        error: E,
+    ) -> Retry<P, S, AttemptTimeout<LayerErrorStatic<E>>>
+    where
+        E: Clone + Send + Sync + 'static,
+    {
+        Retry {
+            policy: self.policy,
+            inner: self.inner,
+            attempt_timeout: AttemptTimeout::new(timeout, LayerErrorStatic::new(error)),
+            timeout: self.timeout,
+        }
+    }
+
+    /// Bound each attempt by the given timeout,
+    /// failing an attempt which does not complete in time with the error created by the given function.
+    ///
+    /// See [`Self::with_attempt_timeout`] for more information.
+    pub fn with_attempt_timeout_error_fn<F, E>(
+        self,
+        timeout: Duration,
+        error_fn: F,
+    ) -> Retry<P, S, AttemptTimeout<LayerErrorFn<F>>>
+    where
+        F: FnOnce() -> E + Clone + Send + Sync + 'static,
+        E: Send + 'static,
+    {
+        Retry {
+            policy: self.policy,
+            inner: self.inner,
+            attempt_timeout: AttemptTimeout::new(timeout, LayerErrorFn::new(error_fn)),
+            timeout: self.timeout,
         }
     }
 
+    /// Bound all attempts together, including the backoff in between, by the given timeout.
+    ///
+    /// Once elapsed, the pending attempt is aborted and no more retries are made,
+    /// failing the request with a [`RetryError`] for which [`RetryError::is_timeout`]
+    /// returns `true`. The [`Deadline`] of the request, if any, is shortened accordingly.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Bound all attempts together, including the backoff in between, by the given timeout.
+    ///
+    /// See [`Self::with_timeout`] for more information.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
     define_inner_service_accessors!();
 }
 
@@ -78,10 +220,19 @@ pub struct RetryError {
     inner: Option<BoxError>,
 }
 
+impl RetryError {
+    /// Returns `true` if the request failed because
+    /// the overall timeout of the [`Retry`] service elapsed.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self.kind, RetryErrorKind::Timeout)
+    }
+}
+
 #[derive(Debug)]
 enum RetryErrorKind {
     BodyConsume,
     Service,
+    Timeout,
 }
 
 impl std::fmt::Display for RetryError {
@@ -98,6 +249,7 @@ impl std::fmt::Display for RetryErrorKind {
         match self {
             RetryErrorKind::BodyConsume => write!(f, "failed to consume body"),
             RetryErrorKind::Service => write!(f, "service error"),
+            RetryErrorKind::Timeout => write!(f, "retry timeout elapsed"),
         }
     }
 }
@@ -108,10 +260,11 @@ impl std::error::Error for RetryError {
     }
 }
 
-impl<P, S, State, Body> Service<State, Request<Body>> for Retry<P, S>
+impl<P, S, T, State, Body> Service<State, Request<Body>> for Retry<P, S, T>
 where
     P: Policy<State, S::Response, S::Error>,
-    S: Service<State, Request<RetryBody>, Error: Into<BoxError>>,
+    S: Service<State, Request<RetryBody>, Error: Into<BoxError> + std::fmt::Display>,
+    T: MakeAttemptTimeout<S::Error>,
     State: Clone + Send + Sync + 'static,
     Body: HttpBody<Data: Send + 'static, Error: Into<BoxError>> + Send + 'static,
 {
@@ -124,39 +277,119 @@ where
         request: Request<Body>,
     ) -> Result<Self::Response, Self::Error> {
         let mut ctx = ctx;
+        let outcome = ctx.get_or_insert_default::<RetryOutcome>().clone();
+
+        let Some(timeout) = self.timeout else {
+            return self.serve_attempts(ctx, request, &outcome).await;
+        };
+
+        let deadline = Deadline::after(timeout);
+        if ctx
+            .get::<Deadline>()
+            .is_none_or(|current| deadline < *current)
+        {
+            ctx.insert(deadline);
+        }
+
+        match tokio::time::timeout(timeout, self.serve_attempts(ctx, request, &outcome)).await {
+            Ok(result) => result,
+            Err(_) => {
+                outcome.record_result(false);
+                Err(RetryError {
+                    kind: RetryErrorKind::Timeout,
+                    inner: None,
+                })
+            }
+        }
+    }
+}
 
-        // consume body so we can clone the request if desired
+impl<P, S, T> Retry<P, S, T> {
+    async fn serve_attempts<State, Body>(
+        &self,
+        mut ctx: Context<State>,
+        request: Request<Body>,
+        outcome: &RetryOutcome,
+    ) -> Result<S::Response, RetryError>
+    where
+        P: Policy<State, S::Response, S::Error>,
+        S: Service<State, Request<RetryBody>, Error: Into<BoxError> + std::fmt::Display>,
+        T: MakeAttemptTimeout<S::Error>,
+        State: Clone + Send + Sync + 'static,
+        Body: HttpBody<Data: Send + 'static, Error: Into<BoxError>> + Send + 'static,
+    {
+        // consume body so we can clone the request if desired,
+        // unless it is already a retry body (e.g. buffered up to a limit)
         let (parts, body) = request.into_parts();
-        let body = body.collect().await.map_err(|e| RetryError {
-            kind: RetryErrorKind::BodyConsume,
-            inner: Some(e.into()),
-        })?;
-        let body = RetryBody::new(body.to_bytes());
+        let body = match try_downcast::<RetryBody, _>(body) {
+            Ok(body) => body,
+            Err(body) => {
+                let body = body.collect().await.map_err(|e| RetryError {
+                    kind: RetryErrorKind::BodyConsume,
+                    inner: Some(e.into()),
+                })?;
+                RetryBody::new(body.to_bytes())
+            }
+        };
         let mut request = Request::from_parts(parts, body);
 
-        let mut cloned = self.policy.clone_input(&ctx, &request);
+        // deadline of the request as a whole, not to be confused
+        // with the attempt deadlines inserted in the (cloned) contexts
+        let deadline = ctx.get::<Deadline>().copied();
+
+        // a streamed body cannot be replayed, and thus never retried
+        let mut cloned = if request.body().is_buffered() {
+            self.policy.clone_input(&ctx, &request)
+        } else {
+            None
+        };
 
+        let mut attempt = RetryAttempt::default();
         loop {
-            let resp = self.inner.serve(ctx, request).await;
+            outcome.record_attempt();
+            ctx.insert(attempt.clone());
+            let resp = match self.attempt_timeout.attempt_timeout() {
+                Some((timeout, into_error)) => {
+                    let attempt_deadline = Deadline::after(timeout);
+                    let attempt_deadline =
+                        deadline.map_or(attempt_deadline, |d| d.min(attempt_deadline));
+                    ctx.insert(attempt_deadline);
+                    tokio::time::timeout(
+                        attempt_deadline.remaining(),
+                        self.inner.serve(ctx, request),
+                    )
+                    .await
+                    .unwrap_or_else(|_| Err(into_error()))
+                }
+                None => self.inner.serve(ctx, request).await,
+            };
             match cloned.take() {
                 Some((cloned_ctx, cloned_req)) => {
-                    let (cloned_ctx, cloned_req) =
-                        match self.policy.retry(cloned_ctx, cloned_req, resp).await {
-                            PolicyResult::Abort(result) => {
-                                return result.map_err(|e| RetryError {
-                                    kind: RetryErrorKind::Service,
-                                    inner: Some(e.into()),
-                                })
-                            }
-                            PolicyResult::Retry { ctx, req } => (ctx, req),
-                        };
+                    let last_error = resp.as_ref().err().map(ToString::to_string);
+                    let (cloned_ctx, cloned_req) = match self
+                        .policy
+                        .retry(cloned_ctx, cloned_req, resp, attempt.attempt() as usize)
+                        .await
+                    {
+                        PolicyResult::Abort(result) => {
+                            outcome.record_result(result.is_ok());
+                            return result.map_err(|e| RetryError {
+                                kind: RetryErrorKind::Service,
+                                inner: Some(e.into()),
+                            })
+                        }
+                        PolicyResult::Retry { ctx, req } => (ctx, req),
+                    };
 
                     cloned = self.policy.clone_input(&cloned_ctx, &cloned_req);
                     ctx = cloned_ctx;
                     request = cloned_req;
+                    attempt = attempt.retry(last_error);
+                    self.policy.on_retry(&attempt);
                 }
                 // no clone was made, so no possibility to retry
                 None => {
+                    outcome.record_result(resp.is_ok());
                     return resp.map_err(|e| RetryError {
                         kind: RetryErrorKind::Service,
                         inner: Some(e.into()),
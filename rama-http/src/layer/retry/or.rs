@@ -0,0 +1,75 @@
+use super::{Policy, PolicyResult, RetryAttempt, RetryBody};
+use crate::Request;
+use rama_core::Context;
+use std::fmt;
+
+/// A retry [`Policy`] that retries if either of its `Policy`s retries.
+///
+/// See [`PolicyExt::or`][super::PolicyExt::or] for more details.
+pub struct Or<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> Or<A, B> {
+    pub(crate) const fn new(a: A, b: B) -> Self {
+        Or { a, b }
+    }
+}
+
+impl<A: fmt::Debug, B: fmt::Debug> fmt::Debug for Or<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Or")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .finish()
+    }
+}
+
+impl<A: Clone, B: Clone> Clone for Or<A, B> {
+    fn clone(&self) -> Self {
+        Or {
+            a: self.a.clone(),
+            b: self.b.clone(),
+        }
+    }
+}
+
+impl<A, B, State, Response, Error> Policy<State, Response, Error> for Or<A, B>
+where
+    A: Policy<State, Response, Error>,
+    B: Policy<State, Response, Error>,
+    State: Clone + Send + Sync + 'static,
+    Response: Send + 'static,
+    Error: Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        req: Request<RetryBody>,
+        result: Result<Response, Error>,
+        attempt: usize,
+    ) -> PolicyResult<State, Response, Error> {
+        let (b_ctx, b_req) = (ctx.clone(), req.clone());
+        match self.a.retry(ctx, req, result, attempt).await {
+            // left-biased: the second policy is not consulted
+            retry @ PolicyResult::Retry { .. } => retry,
+            PolicyResult::Abort(result) => self.b.retry(b_ctx, b_req, result, attempt).await,
+        }
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        self.a
+            .clone_input(ctx, req)
+            .or_else(|| self.b.clone_input(ctx, req))
+    }
+
+    fn on_retry(&self, attempt: &RetryAttempt) {
+        self.a.on_retry(attempt);
+        self.b.on_retry(attempt);
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+#[derive(Debug, Clone, Default)]
+/// An [`Extensions`] value recording the outcome of a request
+/// served through the [`Retry`] layer.
+///
+/// The [`Retry`] layer uses the [`RetryOutcome`] found in the [`Context`],
+/// inserting a new one if none is present. Insert one yourself prior to serving
+/// and keep a clone of it around, in order to inspect the outcome once the request
+/// has been served (e.g. for SLO tracking).
+///
+/// [`Extensions`]: rama_core::context::Extensions
+/// [`Context`]: rama_core::Context
+/// [`Retry`]: super::Retry
+pub struct RetryOutcome {
+    inner: Arc<RetryOutcomeInner>,
+}
+
+#[derive(Debug, Default)]
+struct RetryOutcomeInner {
+    attempts: AtomicUsize,
+    succeeded: AtomicBool,
+}
+
+impl RetryOutcome {
+    /// Create a new [`RetryOutcome`], with no attempts recorded yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of attempts made (so far),
+    /// which equals the number of times the inner service was served.
+    pub fn attempts(&self) -> usize {
+        self.inner.attempts.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if the request ultimately succeeded,
+    /// meaning the final attempt resulted in an `Ok` result.
+    pub fn succeeded(&self) -> bool {
+        self.inner.succeeded.load(Ordering::Acquire)
+    }
+
+    pub(super) fn record_attempt(&self) {
+        self.inner.attempts.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(super) fn record_result(&self, succeeded: bool) {
+        self.inner.succeeded.store(succeeded, Ordering::Release);
+    }
+}
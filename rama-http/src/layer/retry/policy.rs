@@ -1,4 +1,4 @@
-use super::RetryBody;
+use super::{And, Limited, MapResult, Or, RetryAttempt, RetryBody};
 use crate::Request;
 use rama_core::Context;
 use std::future::Future;
@@ -11,10 +11,8 @@ use std::future::Future;
 /// use rama_core::Context;
 /// use rama_http::Request;
 /// use rama_http::layer::retry::{Policy, PolicyResult, RetryBody};
-/// use std::sync::Arc;
-/// use parking_lot::Mutex;
 ///
-/// struct Attempts(Arc<Mutex<usize>>);
+/// struct Attempts(usize);
 ///
 /// impl<S, R, E> Policy<S, R, E> for Attempts
 ///     where
@@ -22,7 +20,7 @@ use std::future::Future;
 ///         R: Send + 'static,
 ///         E: Send + Sync + 'static,
 /// {
-///     async fn retry(&self, ctx: Context<S>, req: Request<RetryBody>, result: Result<R, E>) -> PolicyResult<S, R, E> {
+///     async fn retry(&self, ctx: Context<S>, req: Request<RetryBody>, result: Result<R, E>, attempt: usize) -> PolicyResult<S, R, E> {
 ///         match result {
 ///             Ok(_) => {
 ///                 // Treat all `Response`s as success,
@@ -32,10 +30,8 @@ use std::future::Future;
 ///             Err(_) => {
 ///                 // Treat all errors as failures...
 ///                 // But we limit the number of attempts...
-///                 let mut attempts = self.0.lock();
-///                 if *attempts > 0 {
+///                 if attempt < self.0 {
 ///                     // Try again!
-///                     *attempts -= 1;
 ///                     PolicyResult::Retry { ctx, req }
 ///                 } else {
 ///                     // Used all our attempts, no retry...
@@ -82,6 +78,13 @@ pub trait Policy<S, R, E>: Send + Sync + 'static {
     /// information about the number of retries required or to record that a
     /// failure failed after exhausting all retries.
     ///
+    /// ## Attempts
+    ///
+    /// The `attempt` is the index of the attempt which produced the `result`,
+    /// starting at `0` for the first try, such that it equals the number of retries
+    /// already made. This allows policies to limit or scale their retries
+    /// without having to keep track of the attempts themselves.
+    ///
     /// [`Service::Response`]: rama_core::Service::Response
     /// [`Service::Error`]: rama_core::Service::Error
     fn retry(
@@ -89,6 +92,7 @@ pub trait Policy<S, R, E>: Send + Sync + 'static {
         ctx: Context<S>,
         req: Request<RetryBody>,
         result: Result<R, E>,
+        attempt: usize,
     ) -> impl Future<Output = PolicyResult<S, R, E>> + Send + '_;
 
     /// Tries to clone a request before being passed to the inner service.
@@ -100,6 +104,18 @@ pub trait Policy<S, R, E>: Send + Sync + 'static {
         ctx: &Context<S>,
         req: &Request<RetryBody>,
     ) -> Option<(Context<S>, Request<RetryBody>)>;
+
+    /// Called right before a request is retried, after the policy decided to retry it.
+    ///
+    /// The given [`RetryAttempt`] is the one about to be made, containing
+    /// the error of the previous attempt, if any. It is the same [`RetryAttempt`]
+    /// as the one inserted in the [`Context`] of that attempt.
+    ///
+    /// This hook allows to log or emit metrics for each retry,
+    /// without having to wrap the policy. Does nothing by default.
+    fn on_retry(&self, attempt: &RetryAttempt) {
+        let _ = attempt;
+    }
 }
 
 impl<P, S, R, E> Policy<S, R, E> for &'static P
@@ -111,8 +127,9 @@ where
         ctx: Context<S>,
         req: Request<RetryBody>,
         result: Result<R, E>,
+        attempt: usize,
     ) -> impl Future<Output = PolicyResult<S, R, E>> + Send + '_ {
-        (**self).retry(ctx, req, result)
+        (**self).retry(ctx, req, result, attempt)
     }
 
     fn clone_input(
@@ -122,6 +139,10 @@ where
     ) -> Option<(Context<S>, Request<RetryBody>)> {
         (**self).clone_input(ctx, req)
     }
+
+    fn on_retry(&self, attempt: &RetryAttempt) {
+        (**self).on_retry(attempt)
+    }
 }
 
 impl<P, S, R, E> Policy<S, R, E> for std::sync::Arc<P>
@@ -133,8 +154,9 @@ where
         ctx: Context<S>,
         req: Request<RetryBody>,
         result: Result<R, E>,
+        attempt: usize,
     ) -> impl Future<Output = PolicyResult<S, R, E>> + Send + '_ {
-        (**self).retry(ctx, req, result)
+        (**self).retry(ctx, req, result, attempt)
     }
 
     fn clone_input(
@@ -144,8 +166,82 @@ where
     ) -> Option<(Context<S>, Request<RetryBody>)> {
         (**self).clone_input(ctx, req)
     }
+
+    fn on_retry(&self, attempt: &RetryAttempt) {
+        (**self).on_retry(attempt)
+    }
+}
+
+/// An extension trait for [`Policy`] that provides additional adapters.
+///
+/// # Example
+///
+/// ```
+/// use rama_http::layer::retry::{ClassifyRetry, PolicyExt, RetryLayer};
+/// use std::io;
+///
+/// // retry connection resets, at most twice
+/// let _layer = RetryLayer::new(
+///     ClassifyRetry::io_error_kinds([io::ErrorKind::ConnectionReset]).limit(2),
+/// );
+/// ```
+pub trait PolicyExt {
+    /// Create a new [`Policy`] that retries only if both `self` and `other` retry.
+    ///
+    /// Both policies are consulted for the same result, in order, such that both can
+    /// update their state or sleep prior to the retry. The second policy is not consulted
+    /// in case the first one aborts. As both policies inspect the same result,
+    /// the response and error types have to be [`Clone`].
+    ///
+    /// The combinator is left-biased: the [`Context`] and [`Request`] retried are the
+    /// ones (possibly) mutated by `self`, while the second policy receives and mutates
+    /// its own copy of the input. In case either policy aborts, its result is returned.
+    /// The input is only cloned in case both policies can clone it.
+    /// Both policies are notified by [`Policy::on_retry`].
+    fn and<P>(self, other: P) -> And<Self, P>
+    where
+        Self: Sized,
+    {
+        And::new(self, other)
+    }
+
+    /// Create a new [`Policy`] that retries if either `self` or `other` retries.
+    ///
+    /// The combinator is left-biased: `other` is only consulted in case `self` aborts,
+    /// receiving the result it aborted with together with the original [`Context`] and [`Request`].
+    /// The input is cloned using `self`, falling back to `other` if `self` cannot clone it.
+    /// Both policies are notified by [`Policy::on_retry`], regardless of which one retried.
+    fn or<P>(self, other: P) -> Or<Self, P>
+    where
+        Self: Sized,
+    {
+        Or::new(self, other)
+    }
+
+    /// Create a new [`Policy`] that retries at most `max_retries` times,
+    /// consulting `self` for the retries within that limit.
+    fn limit(self, max_retries: usize) -> Limited<Self>
+    where
+        Self: Sized,
+    {
+        Limited::new(self, max_retries)
+    }
+
+    /// Create a new [`Policy`] that maps the result returned when `self` aborts.
+    ///
+    /// This allows to translate the final result, e.g. into an error
+    /// once the retries are exhausted. Note that in case the input could not be cloned,
+    /// the policy is not consulted at all and the result is thus not mapped.
+    fn map_result<F>(self, f: F) -> MapResult<Self, F>
+    where
+        Self: Sized,
+    {
+        MapResult::new(self, f)
+    }
 }
 
+impl<T: ?Sized> PolicyExt for T {}
+
 /// The full result of a limit policy.
 pub enum PolicyResult<S, R, E> {
     /// The result should not be retried,
@@ -193,10 +289,11 @@ macro_rules! impl_retry_policy_either {
                 ctx: Context<State>,
                 req: http::Request<RetryBody>,
                 result: Result<Response, Error>,
+                attempt: usize,
             ) -> PolicyResult<State, Response, Error> {
                 match self {
                     $(
-                        rama_core::combinators::$id::$param(policy) => policy.retry(ctx, req, result).await,
+                        rama_core::combinators::$id::$param(policy) => policy.retry(ctx, req, result, attempt).await,
                     )+
                 }
             }
@@ -212,6 +309,14 @@ macro_rules! impl_retry_policy_either {
                     )+
                 }
             }
+
+            fn on_retry(&self, attempt: &RetryAttempt) {
+                match self {
+                    $(
+                        rama_core::combinators::$id::$param(policy) => policy.on_retry(attempt),
+                    )+
+                }
+            }
         }
     };
 }
@@ -0,0 +1,276 @@
+//! `Retry-After` aware retry [`Policy`].
+//!
+//! See [`RetryAfterPolicy`] for more details.
+//!
+//! [`Policy`]: super::Policy
+
+use super::{managed::DoNotRetry, Policy, PolicyResult, RetryBody, RetryOutcome};
+use crate::{header::RETRY_AFTER, Request, Response, StatusCode};
+use rama_core::Context;
+use rama_utils::backoff::exponential_delay;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone)]
+/// A retry [`Policy`] which retries `429 Too Many Requests` and
+/// `503 Service Unavailable` responses, honoring their `Retry-After` header.
+///
+/// The `Retry-After` header can either contain a number of seconds or an http date.
+/// The honored delay is capped at a configurable maximum (60s by default).
+/// In case the header is absent or cannot be parsed, it falls back to
+/// an exponential backoff (`fallback * 2^retry`, also capped at the maximum).
+///
+/// Other responses and errors are not retried.
+/// Requests are retried at most `max_retries` times (3 by default).
+/// Just like the [`ManagedPolicy`], requests with [`DoNotRetry`]
+/// in their [`Context`] are never retried.
+///
+/// # Example
+///
+/// ```
+/// use rama_http::layer::retry::{RetryAfterPolicy, RetryLayer};
+/// use std::time::Duration;
+///
+/// let _layer = RetryLayer::new(
+///     RetryAfterPolicy::new()
+///         .with_max_delay(Duration::from_secs(30))
+///         .with_fallback(Duration::from_millis(500)),
+/// );
+/// ```
+///
+/// [`ManagedPolicy`]: super::ManagedPolicy
+pub struct RetryAfterPolicy {
+    max_delay: Duration,
+    fallback: Duration,
+    max_retries: usize,
+}
+
+impl Default for RetryAfterPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryAfterPolicy {
+    /// Create a new [`RetryAfterPolicy`].
+    pub const fn new() -> Self {
+        Self {
+            max_delay: Duration::from_secs(60),
+            fallback: Duration::from_secs(1),
+            max_retries: 3,
+        }
+    }
+
+    /// Set the maximum delay honored, applied to the fallback backoff as well.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the maximum delay honored, applied to the fallback backoff as well.
+    pub fn set_max_delay(&mut self, max_delay: Duration) -> &mut Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the delay prior to the first retry in case no valid `Retry-After` header is found,
+    /// doubled for every subsequent retry.
+    pub fn with_fallback(mut self, fallback: Duration) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Set the delay prior to the first retry in case no valid `Retry-After` header is found,
+    /// doubled for every subsequent retry.
+    pub fn set_fallback(&mut self, fallback: Duration) -> &mut Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Set the maximum number of times a request is retried.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the maximum number of times a request is retried.
+    pub fn set_max_retries(&mut self, max_retries: usize) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Compute the delay prior to the given retry (starting at `0`) of the given response,
+    /// `None` in case the response is not to be retried.
+    pub fn delay<Body>(&self, res: &Response<Body>, retry: usize) -> Option<Duration> {
+        if !matches!(
+            res.status(),
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        ) {
+            return None;
+        }
+
+        let delay = parse_retry_after(res, SystemTime::now())
+            .unwrap_or_else(|| exponential_delay(self.fallback, self.max_delay, retry));
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// Parse the `Retry-After` header of the response as a delay relative to `now`.
+pub(super) fn parse_retry_after<Body>(res: &Response<Body>, now: SystemTime) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    match httpdate::parse_http_date(value) {
+        Ok(date) => Some(date.duration_since(now).unwrap_or_default()),
+        Err(err) => {
+            tracing::debug!(
+                error = %err,
+                "retry after policy: invalid Retry-After header: use fallback"
+            );
+            None
+        }
+    }
+}
+
+impl<State, Body, Error> Policy<State, Response<Body>, Error> for RetryAfterPolicy
+where
+    State: Clone + Send + Sync + 'static,
+    Body: Send + 'static,
+    Error: Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        req: Request<RetryBody>,
+        result: Result<Response<Body>, Error>,
+        retry: usize,
+    ) -> PolicyResult<State, Response<Body>, Error> {
+        if ctx.get::<DoNotRetry>().is_some() {
+            return PolicyResult::Abort(result);
+        }
+
+        let delay = match &result {
+            Ok(res) if retry < self.max_retries => self.delay(res, retry),
+            _ => None,
+        };
+        let Some(delay) = delay else {
+            return PolicyResult::Abort(result);
+        };
+
+        tracing::trace!(retry, ?delay, "retry after policy: sleep prior to retry");
+        tokio::time::sleep(delay).await;
+        PolicyResult::Retry { ctx, req }
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        let attempts = ctx
+            .get::<RetryOutcome>()
+            .map(RetryOutcome::attempts)
+            .unwrap_or_default();
+        if ctx.get::<DoNotRetry>().is_some() || attempts >= self.max_retries {
+            None
+        } else {
+            Some((ctx.clone(), req.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layer::retry::RetryLayer, HeaderValue, IntoResponse};
+    use rama_core::{service::service_fn, Layer, Service};
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tokio::time::Instant;
+
+    fn response(status: StatusCode, retry_after: Option<&str>) -> Response {
+        let mut res = status.into_response();
+        if let Some(value) = retry_after {
+            res.headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        }
+        res
+    }
+
+    #[test]
+    fn test_retry_after_delay() {
+        let policy = RetryAfterPolicy::new()
+            .with_max_delay(Duration::from_secs(10))
+            .with_fallback(Duration::from_secs(1));
+
+        for (status, retry_after, retry, expected) in [
+            (StatusCode::TOO_MANY_REQUESTS, Some("3"), 0, Some(3)),
+            (StatusCode::SERVICE_UNAVAILABLE, Some(" 5 "), 2, Some(5)),
+            // capped at the max delay
+            (StatusCode::TOO_MANY_REQUESTS, Some("3600"), 0, Some(10)),
+            // fallback backoff
+            (StatusCode::TOO_MANY_REQUESTS, None, 0, Some(1)),
+            (StatusCode::TOO_MANY_REQUESTS, Some("soon"), 2, Some(4)),
+            (StatusCode::SERVICE_UNAVAILABLE, None, 5, Some(10)),
+            // other statuses are not retried
+            (StatusCode::OK, Some("3"), 0, None),
+            (StatusCode::INTERNAL_SERVER_ERROR, Some("3"), 0, None),
+        ] {
+            assert_eq!(
+                policy.delay(&response(status, retry_after), retry),
+                expected.map(Duration::from_secs),
+                "status: {status}, retry-after: {retry_after:?}, retry: {retry}",
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after_http_date() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+
+        let res = response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            Some("Wed, 21 Oct 2015 07:28:30 GMT"),
+        );
+        assert_eq!(parse_retry_after(&res, now), Some(Duration::from_secs(30)));
+
+        // dates in the past result in an immediate retry
+        let res = response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            Some("Wed, 21 Oct 2015 07:27:00 GMT"),
+        );
+        assert_eq!(parse_retry_after(&res, now), Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_policy() {
+        tokio::time::pause();
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let svc = RetryLayer::new(RetryAfterPolicy::new()).layer(service_fn({
+            let attempts = attempts.clone();
+            move |_req: Request<RetryBody>| {
+                let attempts = attempts.clone();
+                async move {
+                    let res = match attempts.fetch_add(1, Ordering::AcqRel) {
+                        0 => response(StatusCode::TOO_MANY_REQUESTS, Some("7")),
+                        _ => response(StatusCode::OK, None),
+                    };
+                    Ok::<_, Infallible>(res)
+                }
+            }
+        }));
+
+        let start = Instant::now();
+        let req = Request::builder().body(RetryBody::empty()).unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(attempts.load(Ordering::Acquire), 2);
+        assert!(start.elapsed() >= Duration::from_secs(7));
+    }
+}
@@ -1,12 +1,14 @@
 use super::*;
 use crate::{response::IntoResponse, BodyExtractExt};
 use crate::{Request, Response};
-use parking_lot::Mutex;
 use rama_core::error::{error, OpaqueError};
 use rama_core::{Layer, Service};
-use std::sync::{
-    atomic::{AtomicBool, AtomicUsize, Ordering},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 #[tokio::test]
@@ -23,14 +25,20 @@ async fn retry_errors() {
 
         async fn serve(
             &self,
-            _ctx: Context<State>,
+            ctx: Context<State>,
             req: Request<RetryBody>,
         ) -> Result<Self::Response, Self::Error> {
             assert_eq!(req.try_into_string().await.unwrap(), "hello");
+            let attempt = ctx.get::<RetryAttempt>().unwrap();
             if self.errored.swap(true, Ordering::AcqRel) {
+                assert_eq!(attempt.attempt(), 1);
+                assert!(attempt.is_retry());
+                assert_eq!(attempt.last_error(), Some("retry me"));
                 self.response_counter.fetch_add(1, Ordering::AcqRel);
                 Ok("world".into_response())
             } else {
+                assert_eq!(attempt, &RetryAttempt::default());
+                assert!(!attempt.is_retry());
                 self.error_counter.fetch_add(1, Ordering::AcqRel);
                 Err(error!("retry me"))
             }
@@ -67,18 +75,22 @@ async fn retry_limit() {
 
         async fn serve(
             &self,
-            _ctx: Context<State>,
+            ctx: Context<State>,
             req: Request<RetryBody>,
         ) -> Result<Self::Response, Self::Error> {
             assert_eq!(req.try_into_string().await.unwrap(), "hello");
-            self.error_counter.fetch_add(1, Ordering::AcqRel);
+            let attempt = self.error_counter.fetch_add(1, Ordering::AcqRel) % 3;
+            assert_eq!(
+                ctx.get::<RetryAttempt>().unwrap().attempt() as usize,
+                attempt
+            );
             Err(error!("error forever"))
         }
     }
 
     let error_counter = Arc::new(AtomicUsize::new(0));
 
-    let svc = RetryLayer::new(Limit(Arc::new(Mutex::new(2)))).layer(Svc {
+    let svc = RetryLayer::new(Limit(2)).layer(Svc {
         error_counter: error_counter.clone(),
     });
 
@@ -88,6 +100,65 @@ async fn retry_limit() {
         .unwrap_err();
     assert_eq!(err.to_string(), "service error: error forever");
     assert_eq!(error_counter.load(Ordering::Acquire), 3);
+
+    // the limit applies per request, as the policy itself is stateless
+    svc.serve(Context::default(), request("hello"))
+        .await
+        .unwrap_err();
+    assert_eq!(error_counter.load(Ordering::Acquire), 6);
+}
+
+#[tokio::test]
+async fn retry_on_retry_hook() {
+    #[derive(Clone, Default)]
+    struct RecordRetries(Arc<parking_lot::Mutex<Vec<RetryAttempt>>>);
+
+    impl Policy<State, Response, Error> for RecordRetries {
+        async fn retry(
+            &self,
+            ctx: Context<State>,
+            req: Request<RetryBody>,
+            result: Result<Response, Error>,
+            attempt: usize,
+        ) -> PolicyResult<State, Response, Error> {
+            RetryErrors.retry(ctx, req, result, attempt).await
+        }
+
+        fn clone_input(
+            &self,
+            ctx: &Context<State>,
+            req: &Request<RetryBody>,
+        ) -> Option<(Context<State>, Request<RetryBody>)> {
+            Some((ctx.clone(), req.clone()))
+        }
+
+        fn on_retry(&self, attempt: &RetryAttempt) {
+            self.0.lock().push(attempt.clone());
+        }
+    }
+
+    let policy = RecordRetries::default();
+    let svc = RetryLayer::new(policy.clone().limit(2)).layer(rama_core::service::service_fn(
+        |ctx: Context<State>, _req: Request<RetryBody>| async move {
+            let attempt = ctx.get::<RetryAttempt>().unwrap().attempt();
+            Err::<Response, _>(OpaqueError::from_display(format!("attempt {attempt}")))
+        },
+    ));
+
+    let outcome = RetryOutcome::new();
+    let mut ctx = Context::default();
+    ctx.insert(outcome.clone());
+
+    let err = svc.serve(ctx, request("hello")).await.unwrap_err();
+    assert_eq!(err.to_string(), "service error: attempt 2");
+    assert_eq!(outcome.attempts(), 3);
+
+    let retries = std::mem::take(&mut *policy.0.lock());
+    let retries: Vec<_> = retries
+        .iter()
+        .map(|attempt| (attempt.attempt(), attempt.last_error()))
+        .collect();
+    assert_eq!(retries, [(1, Some("attempt 0")), (2, Some("attempt 1"))]);
 }
 
 #[tokio::test]
@@ -207,10 +278,7 @@ async fn retry_mutating_policy() {
 
     let response_counter = Arc::new(AtomicUsize::new(0));
 
-    let svc = RetryLayer::new(MutatingPolicy {
-        remaining: Arc::new(Mutex::new(2)),
-    })
-    .layer(Svc {
+    let svc = RetryLayer::new(MutatingPolicy { max_retries: 2 }).layer(Svc {
         responded: AtomicBool::new(false),
         response_counter: response_counter.clone(),
     });
@@ -223,6 +291,312 @@ async fn retry_mutating_policy() {
     assert_eq!(response_counter.load(Ordering::Acquire), 3);
 }
 
+#[tokio::test]
+async fn retry_outcome_records_attempts() {
+    struct Svc {
+        serve_counter: Arc<AtomicUsize>,
+    }
+
+    impl Service<State, Request<RetryBody>> for Svc {
+        type Response = Response;
+        type Error = OpaqueError;
+
+        async fn serve(
+            &self,
+            ctx: Context<State>,
+            _req: Request<RetryBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            let attempt = self.serve_counter.fetch_add(1, Ordering::AcqRel) + 1;
+            assert_eq!(ctx.get::<RetryOutcome>().unwrap().attempts(), attempt);
+            if attempt < 3 {
+                Err(error!("retry me"))
+            } else {
+                Ok("world".into_response())
+            }
+        }
+    }
+
+    let serve_counter = Arc::new(AtomicUsize::new(0));
+
+    let svc = RetryLayer::new(RetryErrors).layer(Svc {
+        serve_counter: serve_counter.clone(),
+    });
+
+    let outcome = RetryOutcome::new();
+    let mut ctx = Context::default();
+    ctx.insert(outcome.clone());
+
+    let resp = svc.serve(ctx, request("hello")).await.unwrap();
+    assert_eq!(resp.try_into_string().await.unwrap(), "world");
+    assert_eq!(serve_counter.load(Ordering::Acquire), 3);
+    assert_eq!(outcome.attempts(), 3);
+    assert!(outcome.succeeded());
+}
+
+#[tokio::test]
+async fn retry_outcome_records_failure() {
+    struct Svc {
+        serve_counter: Arc<AtomicUsize>,
+    }
+
+    impl Service<State, Request<RetryBody>> for Svc {
+        type Response = Response;
+        type Error = OpaqueError;
+
+        async fn serve(
+            &self,
+            _ctx: Context<State>,
+            _req: Request<RetryBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            self.serve_counter.fetch_add(1, Ordering::AcqRel);
+            Err(error!("error forever"))
+        }
+    }
+
+    let serve_counter = Arc::new(AtomicUsize::new(0));
+
+    let svc = RetryLayer::new(Limit(2)).layer(Svc {
+        serve_counter: serve_counter.clone(),
+    });
+
+    let outcome = RetryOutcome::new();
+    let mut ctx = Context::default();
+    ctx.insert(outcome.clone());
+
+    svc.serve(ctx, request("hello")).await.unwrap_err();
+    assert_eq!(serve_counter.load(Ordering::Acquire), 3);
+    assert_eq!(outcome.attempts(), 3);
+    assert!(!outcome.succeeded());
+}
+
+struct HangingSvc {
+    serve_counter: Arc<AtomicUsize>,
+    hanging_attempts: usize,
+}
+
+impl Service<State, Request<RetryBody>> for HangingSvc {
+    type Response = Response;
+    type Error = OpaqueError;
+
+    async fn serve(
+        &self,
+        _ctx: Context<State>,
+        _req: Request<RetryBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let attempt = self.serve_counter.fetch_add(1, Ordering::AcqRel) + 1;
+        if attempt <= self.hanging_attempts {
+            std::future::pending::<()>().await;
+        }
+        Ok("world".into_response())
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_attempt_timeout() {
+    let serve_counter = Arc::new(AtomicUsize::new(0));
+
+    let svc = RetryLayer::new(RetryErrors)
+        .with_attempt_timeout_error_fn(Duration::from_secs(1), || error!("attempt timeout"))
+        .with_timeout(Duration::from_secs(60))
+        .layer(HangingSvc {
+            serve_counter: serve_counter.clone(),
+            hanging_attempts: 1,
+        });
+
+    let outcome = RetryOutcome::new();
+    let mut ctx = Context::default();
+    ctx.insert(outcome.clone());
+
+    let start = tokio::time::Instant::now();
+    let resp = svc.serve(ctx, request("hello")).await.unwrap();
+    assert_eq!(resp.try_into_string().await.unwrap(), "world");
+    assert_eq!(serve_counter.load(Ordering::Acquire), 2);
+    assert_eq!(outcome.attempts(), 2);
+    assert!(outcome.succeeded());
+
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_secs(1), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_attempt_timeout_never_responding() {
+    let serve_counter = Arc::new(AtomicUsize::new(0));
+
+    let svc = RetryLayer::new(Limit(2))
+        .with_attempt_timeout(Duration::from_secs(1))
+        .layer(rama_core::service::service_fn({
+            let serve_counter = serve_counter.clone();
+            move |_req: Request<RetryBody>| {
+                serve_counter.fetch_add(1, Ordering::AcqRel);
+                std::future::pending::<Result<Response, rama_core::error::BoxError>>()
+            }
+        }));
+
+    let outcome = RetryOutcome::new();
+    let mut ctx = Context::default();
+    ctx.insert(outcome.clone());
+
+    let start = tokio::time::Instant::now();
+    let err = svc.serve(ctx, request("hello")).await.unwrap_err();
+    assert!(!err.is_timeout(), "{err}");
+    assert!(err.to_string().contains("timeout elapsed"), "{err}");
+    assert_eq!(serve_counter.load(Ordering::Acquire), 3);
+    assert_eq!(outcome.attempts(), 3);
+    assert!(!outcome.succeeded());
+    assert_eq!(start.elapsed(), Duration::from_secs(3));
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_overall_timeout() {
+    let serve_counter = Arc::new(AtomicUsize::new(0));
+
+    let svc = RetryLayer::new(RetryErrors)
+        .with_attempt_timeout_error_fn(Duration::from_secs(2), || error!("attempt timeout"))
+        .with_timeout(Duration::from_secs(5))
+        .layer(HangingSvc {
+            serve_counter: serve_counter.clone(),
+            hanging_attempts: usize::MAX,
+        });
+
+    let outcome = RetryOutcome::new();
+    let mut ctx = Context::default();
+    ctx.insert(outcome.clone());
+
+    let start = tokio::time::Instant::now();
+    let err = svc.serve(ctx, request("hello")).await.unwrap_err();
+    assert!(err.is_timeout(), "{err}");
+    assert_eq!(serve_counter.load(Ordering::Acquire), 3);
+    assert_eq!(outcome.attempts(), 3);
+    assert!(!outcome.succeeded());
+    assert_eq!(start.elapsed(), Duration::from_secs(5));
+}
+
+type ScriptedResult = Result<&'static str, InnerError>;
+
+/// Serve a request using the given policy, the inner service returning
+/// the scripted result for each attempt, returning the result and the bodies received.
+async fn serve_scripted<P>(
+    policy: P,
+    results: fn(usize) -> ScriptedResult,
+) -> (Result<&'static str, RetryError>, Vec<String>)
+where
+    P: Policy<State, &'static str, InnerError> + Clone,
+{
+    let bodies = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let svc = RetryLayer::new(policy).layer(rama_core::service::service_fn({
+        let bodies = bodies.clone();
+        move |req: Request<RetryBody>| {
+            let bodies = bodies.clone();
+            async move {
+                let body = req.try_into_string().await.unwrap();
+                let mut bodies = bodies.lock();
+                bodies.push(body);
+                results(bodies.len() - 1)
+            }
+        }
+    }));
+
+    let result = svc.serve(Context::default(), request("hello")).await;
+    let bodies = std::mem::take(&mut *bodies.lock());
+    (result, bodies)
+}
+
+#[tokio::test]
+async fn retry_and_combinator() {
+    fn results(attempt: usize) -> ScriptedResult {
+        match attempt {
+            0 | 1 => Err("retry me"),
+            2 => Err("reject"),
+            _ => Ok("world"),
+        }
+    }
+
+    // both policies retry the first two errors, the second one rejects the third
+    let (result, bodies) = serve_scripted(Limit(5).and(UnlessErr("reject")), results).await;
+    assert_eq!(result.unwrap_err().to_string(), "service error: reject");
+    assert_eq!(bodies.len(), 3);
+
+    // the first policy limits the retries
+    let (result, bodies) = serve_scripted(Limit(1).and(UnlessErr("reject")), results).await;
+    assert_eq!(result.unwrap_err().to_string(), "service error: retry me");
+    assert_eq!(bodies.len(), 2);
+
+    // and so can the second one
+    let (result, bodies) = serve_scripted(UnlessErr("reject").and(Limit(1)), results).await;
+    assert_eq!(result.unwrap_err().to_string(), "service error: retry me");
+    assert_eq!(bodies.len(), 2);
+}
+
+#[tokio::test]
+async fn retry_and_or_combinators_are_left_biased() {
+    fn results(attempt: usize) -> ScriptedResult {
+        match attempt {
+            0 => Err("retry me"),
+            _ => Ok("world"),
+        }
+    }
+
+    // the input mutated by the first policy is retried
+    let (result, bodies) = serve_scripted(SetBody("a").and(SetBody("b")), results).await;
+    assert_eq!(result.unwrap(), "world");
+    assert_eq!(bodies, ["hello", "a"]);
+
+    // the second policy is not consulted in case the first one retries
+    let (result, bodies) = serve_scripted(SetBody("a").or(SetBody("b")), results).await;
+    assert_eq!(result.unwrap(), "world");
+    assert_eq!(bodies, ["hello", "a"]);
+
+    // ... but only in case it aborts
+    let (result, bodies) = serve_scripted(Limit(0).or(SetBody("b")), results).await;
+    assert_eq!(result.unwrap(), "world");
+    assert_eq!(bodies, ["hello", "b"]);
+}
+
+#[tokio::test]
+async fn retry_limit_combinator() {
+    let (result, bodies) = serve_scripted(UnlessErr("reject").limit(2), |_| Err("retry me")).await;
+    assert_eq!(result.unwrap_err().to_string(), "service error: retry me");
+    assert_eq!(bodies.len(), 3);
+
+    let (result, bodies) = serve_scripted(UnlessErr("reject").limit(0), |_| Err("retry me")).await;
+    assert_eq!(result.unwrap_err().to_string(), "service error: retry me");
+    assert_eq!(bodies.len(), 1);
+
+    // the inner policy can still abort earlier
+    let (result, bodies) = serve_scripted(UnlessErr("reject").limit(5), |attempt| {
+        if attempt == 0 {
+            Err("retry me")
+        } else {
+            Err("reject")
+        }
+    })
+    .await;
+    assert_eq!(result.unwrap_err().to_string(), "service error: reject");
+    assert_eq!(bodies.len(), 2);
+}
+
+#[tokio::test]
+async fn retry_map_result_combinator() {
+    let policy = Limit(2).map_result(|result: ScriptedResult| result.map_err(|_| "out of retries"));
+
+    let (result, bodies) = serve_scripted(policy.clone(), |_| Err("retry me")).await;
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "service error: out of retries"
+    );
+    assert_eq!(bodies.len(), 3);
+
+    // successful results are mapped as well
+    let (result, bodies) = serve_scripted(
+        policy.map_result(|result: ScriptedResult| result.map(|_| "mapped")),
+        |_| Ok("world"),
+    )
+    .await;
+    assert_eq!(result.unwrap(), "mapped");
+    assert_eq!(bodies.len(), 1);
+}
+
 type State = ();
 type InnerError = &'static str;
 type Error = rama_core::error::OpaqueError;
@@ -244,6 +618,7 @@ impl Policy<State, Response, Error> for RetryErrors {
         ctx: Context<State>,
         req: Request<RetryBody>,
         result: Result<Response, Error>,
+        _attempt: usize,
     ) -> PolicyResult<State, Response, Error> {
         if result.is_err() {
             PolicyResult::Retry { ctx, req }
@@ -262,18 +637,21 @@ impl Policy<State, Response, Error> for RetryErrors {
 }
 
 #[derive(Clone)]
-struct Limit(Arc<Mutex<usize>>);
+struct Limit(usize);
 
-impl Policy<State, Response, Error> for Limit {
+impl<R, E> Policy<State, R, E> for Limit
+where
+    R: Send + 'static,
+    E: Send + Sync + 'static,
+{
     async fn retry(
         &self,
         ctx: Context<State>,
         req: Request<RetryBody>,
-        result: Result<Response, Error>,
-    ) -> PolicyResult<State, Response, Error> {
-        let mut attempts = self.0.lock();
-        if result.is_err() && *attempts > 0 {
-            *attempts -= 1;
+        result: Result<R, E>,
+        attempt: usize,
+    ) -> PolicyResult<State, R, E> {
+        if result.is_err() && attempt < self.0 {
             PolicyResult::Retry { ctx, req }
         } else {
             PolicyResult::Abort(result)
@@ -292,13 +670,18 @@ impl Policy<State, Response, Error> for Limit {
 #[derive(Clone)]
 struct UnlessErr(InnerError);
 
-impl Policy<State, Response, Error> for UnlessErr {
+impl<R, E> Policy<State, R, E> for UnlessErr
+where
+    R: Send + 'static,
+    E: std::fmt::Display + Send + Sync + 'static,
+{
     async fn retry(
         &self,
         ctx: Context<State>,
         req: Request<RetryBody>,
-        result: Result<Response, Error>,
-    ) -> PolicyResult<State, Response, Error> {
+        result: Result<R, E>,
+        _attempt: usize,
+    ) -> PolicyResult<State, R, E> {
         if result
             .as_ref()
             .err()
@@ -320,6 +703,41 @@ impl Policy<State, Response, Error> for UnlessErr {
     }
 }
 
+/// Test policy that retries errors, replacing the request body with the given one.
+#[derive(Clone)]
+struct SetBody(&'static str);
+
+impl<R, E> Policy<State, R, E> for SetBody
+where
+    R: Send + 'static,
+    E: Send + Sync + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<State>,
+        _req: Request<RetryBody>,
+        result: Result<R, E>,
+        _attempt: usize,
+    ) -> PolicyResult<State, R, E> {
+        if result.is_err() {
+            PolicyResult::Retry {
+                ctx,
+                req: request(self.0),
+            }
+        } else {
+            PolicyResult::Abort(result)
+        }
+    }
+
+    fn clone_input(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<State>, Request<RetryBody>)> {
+        Some((ctx.clone(), req.clone()))
+    }
+}
+
 #[derive(Clone)]
 struct CannotClone;
 
@@ -329,6 +747,7 @@ impl Policy<State, Response, Error> for CannotClone {
         _: Context<State>,
         _: Request<RetryBody>,
         _: Result<Response, Error>,
+        _: usize,
     ) -> PolicyResult<State, Response, Error> {
         unreachable!("retry cannot be called since request isn't cloned");
     }
@@ -346,7 +765,7 @@ impl Policy<State, Response, Error> for CannotClone {
 /// when retries are exhausted.
 #[derive(Clone)]
 struct MutatingPolicy {
-    remaining: Arc<Mutex<usize>>,
+    max_retries: usize,
 }
 
 impl Policy<State, Response, Error> for MutatingPolicy
@@ -358,12 +777,11 @@ where
         ctx: Context<State>,
         _req: Request<RetryBody>,
         _result: Result<Response, Error>,
+        attempt: usize,
     ) -> PolicyResult<State, Response, Error> {
-        let mut remaining = self.remaining.lock();
-        if *remaining == 0 {
+        if attempt >= self.max_retries {
             PolicyResult::Abort(Err(error!("out of retries")))
         } else {
-            *remaining -= 1;
             PolicyResult::Retry {
                 ctx,
                 req: request("retrying"),
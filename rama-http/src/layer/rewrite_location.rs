@@ -0,0 +1,265 @@
+//! Middleware to rewrite `Location` and `Content-Location` response headers,
+//! as is required when reverse-proxying to an internal (upstream) host.
+//!
+//! Absolute header values pointing to a mapped upstream authority have their
+//! scheme and authority replaced by the mapped public ones, while path and
+//! query are preserved. Relative values and unmapped authorities are left as-is.
+//!
+//! # Example
+//!
+//! ```
+//! use std::convert::Infallible;
+//! use rama_core::error::BoxError;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use rama_http::dep::http::uri::{Authority, Scheme};
+//! use rama_http::layer::rewrite_location::RewriteLocationLayer;
+//! use rama_http::{header::LOCATION, Body, Request, Response, StatusCode};
+//!
+//! async fn backend(_req: Request) -> Result<Response, Infallible> {
+//!     Ok(Response::builder()
+//!         .status(StatusCode::FOUND)
+//!         .header(LOCATION, "http://internal:8080/x")
+//!         .body(Body::empty())
+//!         .unwrap())
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let svc = RewriteLocationLayer::new()
+//!     .with_mapping(
+//!         Authority::from_static("internal:8080"),
+//!         Scheme::HTTPS,
+//!         Authority::from_static("public.example.com"),
+//!     )
+//!     .layer(service_fn(backend));
+//!
+//! let response = svc.serve(Context::default(), Request::new(Body::empty())).await?;
+//! assert_eq!(response.headers()[LOCATION], "https://public.example.com/x");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dep::http::uri::{Authority, Scheme};
+use crate::header::{CONTENT_LOCATION, LOCATION};
+use crate::{HeaderValue, Request, Response, Uri};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, sync::Arc};
+
+#[derive(Debug, Clone)]
+struct LocationMapping {
+    upstream: Authority,
+    public_scheme: Scheme,
+    public_authority: Authority,
+}
+
+/// Layer that applies [`RewriteLocation`] which rewrites the
+/// `Location` and `Content-Location` headers of responses.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct RewriteLocationLayer {
+    mappings: Arc<Vec<LocationMapping>>,
+}
+
+impl RewriteLocationLayer {
+    /// Create a new [`RewriteLocationLayer`], without any mappings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a mapping from the upstream authority to the public scheme and authority,
+    /// used for absolute header values pointing to that upstream authority.
+    ///
+    /// In case multiple mappings are defined for the same upstream authority,
+    /// the first one added is used.
+    pub fn with_mapping(
+        mut self,
+        upstream: Authority,
+        public_scheme: Scheme,
+        public_authority: Authority,
+    ) -> Self {
+        self.set_mapping(upstream, public_scheme, public_authority);
+        self
+    }
+
+    /// Add a mapping from the upstream authority to the public scheme and authority,
+    /// used for absolute header values pointing to that upstream authority.
+    ///
+    /// In case multiple mappings are defined for the same upstream authority,
+    /// the first one added is used.
+    pub fn set_mapping(
+        &mut self,
+        upstream: Authority,
+        public_scheme: Scheme,
+        public_authority: Authority,
+    ) -> &mut Self {
+        Arc::make_mut(&mut self.mappings).push(LocationMapping {
+            upstream,
+            public_scheme,
+            public_authority,
+        });
+        self
+    }
+}
+
+impl<S> Layer<S> for RewriteLocationLayer {
+    type Service = RewriteLocation<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RewriteLocation {
+            inner,
+            mappings: self.mappings.clone(),
+        }
+    }
+}
+
+/// Middleware to rewrite `Location` and `Content-Location` response headers.
+///
+/// See the [module docs](self) for more details.
+pub struct RewriteLocation<S> {
+    inner: S,
+    mappings: Arc<Vec<LocationMapping>>,
+}
+
+impl<S> RewriteLocation<S> {
+    /// Create a new [`RewriteLocation`], without any mappings.
+    ///
+    /// Use a [`RewriteLocationLayer`] in order to define the mappings.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            mappings: Default::default(),
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    fn rewrite(&self, value: &HeaderValue) -> Option<HeaderValue> {
+        let uri: Uri = value.to_str().ok()?.parse().ok()?;
+        let authority = uri.authority()?;
+        let mapping = self
+            .mappings
+            .iter()
+            .find(|mapping| &mapping.upstream == authority)?;
+
+        let mut parts = uri.into_parts();
+        parts.scheme = Some(mapping.public_scheme.clone());
+        parts.authority = Some(mapping.public_authority.clone());
+        let uri = Uri::from_parts(parts).ok()?;
+        HeaderValue::try_from(uri.to_string()).ok()
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for RewriteLocation<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RewriteLocation")
+            .field("inner", &self.inner)
+            .field("mappings", &self.mappings)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RewriteLocation<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            mappings: self.mappings.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for RewriteLocation<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let mut response = self.inner.serve(ctx, req).await?;
+        for header in [LOCATION, CONTENT_LOCATION] {
+            if let Some(value) = response
+                .headers()
+                .get(&header)
+                .and_then(|v| self.rewrite(v))
+            {
+                tracing::trace!(
+                    ?header,
+                    ?value,
+                    "rewrite location header for public authority"
+                );
+                response.headers_mut().insert(header, value);
+            }
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, StatusCode};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    async fn rewrite(location: &'static str) -> Response {
+        RewriteLocationLayer::new()
+            .with_mapping(
+                Authority::from_static("internal:8080"),
+                Scheme::HTTPS,
+                Authority::from_static("public.example.com"),
+            )
+            .layer(service_fn(move |_req: Request| async move {
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(StatusCode::FOUND)
+                        .header(LOCATION, location)
+                        .header(CONTENT_LOCATION, location)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+            }))
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_location() {
+        let response = rewrite("http://internal:8080/x").await;
+        assert_eq!(response.headers()[LOCATION], "https://public.example.com/x");
+        assert_eq!(
+            response.headers()[CONTENT_LOCATION],
+            "https://public.example.com/x"
+        );
+
+        let response = rewrite("http://INTERNAL:8080/x/y?a=b").await;
+        assert_eq!(
+            response.headers()[LOCATION],
+            "https://public.example.com/x/y?a=b"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_location_untouched() {
+        for location in [
+            "/x",
+            "http://internal/x",
+            "http://internal:8081/x",
+            "https://example.com/x",
+        ] {
+            let response = rewrite(location).await;
+            assert_eq!(response.headers()[LOCATION], location);
+            assert_eq!(response.headers()[CONTENT_LOCATION], location);
+        }
+    }
+}
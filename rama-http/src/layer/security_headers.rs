@@ -0,0 +1,726 @@
+//! Add a preset of modern security headers to responses.
+//!
+//! [`SecurityHeadersLayer`] adds the headers configured in [`SecurityHeaders`]
+//! to all responses, which by default are:
+//!
+//! - `X-Content-Type-Options: nosniff`
+//! - `X-Frame-Options: DENY`
+//! - `Referrer-Policy: strict-origin-when-cross-origin`
+//! - `Permissions-Policy: camera=(), microphone=(), geolocation=()`
+//!
+//! Cross-origin isolation (`Cross-Origin-Opener-Policy` and `Cross-Origin-Embedder-Policy`)
+//! can be enabled, as well as a `Content-Security-Policy`, configured using
+//! the typed [`ContentSecurityPolicy`] builder. In case the policy makes use of nonces,
+//! a fresh [`CspNonce`] is generated for each request, which is inserted
+//! in the [`Context`] (e.g. for template engines to use) and in the response extensions.
+//!
+//! Headers already set by the inner service are left untouched,
+//! unless [`SecurityHeaders::with_override`] is enabled.
+//!
+//! The [`SecurityHeaders`] can be overwritten per route by inserting
+//! it in the [`Context`] (e.g. by a layer wrapping the route), or by inserting
+//! it in the extensions of the response.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::{service::service_fn, Context, Layer, Service};
+//! use rama_http::layer::security_headers::{
+//!     ContentSecurityPolicy, CspDirective, CspNonce, SecurityHeaders, SecurityHeadersLayer,
+//! };
+//! use rama_http::{Body, Request, Response};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let csp = ContentSecurityPolicy::new()
+//!     .with_directive(CspDirective::DefaultSrc, ["'self'"])
+//!     .with_nonce(CspDirective::ScriptSrc);
+//!
+//! let svc = SecurityHeadersLayer::new(SecurityHeaders::new().with_content_security_policy(csp))
+//!     .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+//!         let nonce = ctx.get::<CspNonce>().unwrap();
+//!         let html = format!(r#"<script nonce="{}">alert(1)</script>"#, nonce.as_str());
+//!         Ok::<_, Infallible>(Response::new(Body::from(html)))
+//!     }));
+//!
+//! let res = svc.serve(Context::default(), Request::new(Body::empty())).await.unwrap();
+//! let nonce = res.extensions().get::<CspNonce>().unwrap();
+//! assert_eq!(
+//!     res.headers()["content-security-policy"],
+//!     format!("default-src 'self'; script-src 'nonce-{}'", nonce.as_str()),
+//! );
+//! assert_eq!(res.headers()["x-content-type-options"], "nosniff");
+//! # }
+//! ```
+
+use crate::{
+    header::{
+        CONTENT_SECURITY_POLICY, CROSS_ORIGIN_EMBEDDER_POLICY, CROSS_ORIGIN_OPENER_POLICY,
+        PERMISSIONS_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    },
+    HeaderMap, HeaderName, HeaderValue, Request, Response,
+};
+use nanoid::nanoid;
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{borrow::Cow, collections::BTreeMap, fmt, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A directive of a [`ContentSecurityPolicy`].
+pub enum CspDirective {
+    /// `default-src`
+    DefaultSrc,
+    /// `script-src`
+    ScriptSrc,
+    /// `style-src`
+    StyleSrc,
+    /// `img-src`
+    ImgSrc,
+    /// `connect-src`
+    ConnectSrc,
+    /// `font-src`
+    FontSrc,
+    /// `object-src`
+    ObjectSrc,
+    /// `media-src`
+    MediaSrc,
+    /// `frame-src`
+    FrameSrc,
+    /// `worker-src`
+    WorkerSrc,
+    /// `manifest-src`
+    ManifestSrc,
+    /// `base-uri`
+    BaseUri,
+    /// `form-action`
+    FormAction,
+    /// `frame-ancestors`
+    FrameAncestors,
+    /// `report-uri`
+    ReportUri,
+    /// `report-to`
+    ReportTo,
+    /// `upgrade-insecure-requests`, a directive without sources.
+    UpgradeInsecureRequests,
+}
+
+impl CspDirective {
+    /// Get the name of the directive as used in the policy.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DefaultSrc => "default-src",
+            Self::ScriptSrc => "script-src",
+            Self::StyleSrc => "style-src",
+            Self::ImgSrc => "img-src",
+            Self::ConnectSrc => "connect-src",
+            Self::FontSrc => "font-src",
+            Self::ObjectSrc => "object-src",
+            Self::MediaSrc => "media-src",
+            Self::FrameSrc => "frame-src",
+            Self::WorkerSrc => "worker-src",
+            Self::ManifestSrc => "manifest-src",
+            Self::BaseUri => "base-uri",
+            Self::FormAction => "form-action",
+            Self::FrameAncestors => "frame-ancestors",
+            Self::ReportUri => "report-uri",
+            Self::ReportTo => "report-to",
+            Self::UpgradeInsecureRequests => "upgrade-insecure-requests",
+        }
+    }
+}
+
+impl fmt::Display for CspDirective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct CspSources {
+    sources: Vec<Cow<'static, str>>,
+    nonce: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// A typed `Content-Security-Policy`, used by [`SecurityHeaders`].
+///
+/// Directives are rendered in the order of [`CspDirective`].
+pub struct ContentSecurityPolicy {
+    directives: BTreeMap<CspDirective, CspSources>,
+    report_only: bool,
+}
+
+impl ContentSecurityPolicy {
+    /// Create a new empty [`ContentSecurityPolicy`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the sources of the given directive, replacing any previous sources.
+    pub fn with_directive<I, T>(mut self, directive: CspDirective, sources: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Cow<'static, str>>,
+    {
+        self.set_directive(directive, sources);
+        self
+    }
+
+    /// Set the sources of the given directive, replacing any previous sources.
+    pub fn set_directive<I, T>(&mut self, directive: CspDirective, sources: I) -> &mut Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Cow<'static, str>>,
+    {
+        self.directives.entry(directive).or_default().sources =
+            sources.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Add the per-response [`CspNonce`] as a source of the given directive.
+    pub fn with_nonce(mut self, directive: CspDirective) -> Self {
+        self.set_nonce(directive);
+        self
+    }
+
+    /// Add the per-response [`CspNonce`] as a source of the given directive.
+    pub fn set_nonce(&mut self, directive: CspDirective) -> &mut Self {
+        self.directives.entry(directive).or_default().nonce = true;
+        self
+    }
+
+    /// Only report violations, using the `Content-Security-Policy-Report-Only` header.
+    pub fn with_report_only(mut self, report_only: bool) -> Self {
+        self.report_only = report_only;
+        self
+    }
+
+    /// Only report violations, using the `Content-Security-Policy-Report-Only` header.
+    pub fn set_report_only(&mut self, report_only: bool) -> &mut Self {
+        self.report_only = report_only;
+        self
+    }
+
+    /// Returns `true` if any of the directives makes use of a [`CspNonce`].
+    pub fn uses_nonce(&self) -> bool {
+        self.directives.values().any(|sources| sources.nonce)
+    }
+
+    /// Get the name of the header used for this policy.
+    pub fn header_name(&self) -> HeaderName {
+        if self.report_only {
+            crate::header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            CONTENT_SECURITY_POLICY
+        }
+    }
+
+    /// Render the policy as a header value, using the given nonce if any.
+    pub fn to_header_value(&self, nonce: Option<&CspNonce>) -> Option<HeaderValue> {
+        let mut policy = String::new();
+        for (directive, sources) in self.directives.iter() {
+            if !policy.is_empty() {
+                policy.push_str("; ");
+            }
+            policy.push_str(directive.as_str());
+            for source in sources.sources.iter() {
+                policy.push(' ');
+                policy.push_str(source);
+            }
+            if let Some(nonce) = nonce.filter(|_| sources.nonce) {
+                policy.push_str(" 'nonce-");
+                policy.push_str(nonce.as_str());
+                policy.push('\'');
+            }
+        }
+        HeaderValue::try_from(policy)
+            .inspect_err(|err| {
+                tracing::debug!(error = %err, "security headers: invalid content security policy");
+            })
+            .ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A nonce generated for each response by the [`SecurityHeaders`] service,
+/// in case its [`ContentSecurityPolicy`] makes use of nonces.
+///
+/// Available in the [`Context`] of the inner service (e.g. for template engines)
+/// and in the extensions of the response.
+pub struct CspNonce(Arc<str>);
+
+impl CspNonce {
+    /// Generate a new random [`CspNonce`].
+    pub fn new() -> Self {
+        Self(nanoid!(22).into())
+    }
+
+    /// Get the nonce as a string, as used in the `nonce` attribute of elements.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for CspNonce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for CspNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The value of the `X-Frame-Options` header.
+pub enum FrameOptions {
+    /// `DENY`: the page cannot be displayed in a frame.
+    Deny,
+    /// `SAMEORIGIN`: the page can only be displayed in a frame on the same origin.
+    SameOrigin,
+}
+
+impl FrameOptions {
+    fn header_value(self) -> HeaderValue {
+        match self {
+            Self::Deny => HeaderValue::from_static("DENY"),
+            Self::SameOrigin => HeaderValue::from_static("SAMEORIGIN"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The security headers added to responses by [`SecurityHeadersLayer`].
+///
+/// Can be inserted in the [`Context`] or response extensions
+/// to overwrite the headers of the layer for a specific route.
+pub struct SecurityHeaders {
+    nosniff: bool,
+    frame_options: Option<FrameOptions>,
+    referrer_policy: Option<HeaderValue>,
+    permissions_policy: Option<HeaderValue>,
+    cross_origin_opener_policy: Option<HeaderValue>,
+    cross_origin_embedder_policy: Option<HeaderValue>,
+    content_security_policy: Option<Arc<ContentSecurityPolicy>>,
+    override_existing: bool,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecurityHeaders {
+    /// Create a new [`SecurityHeaders`] with the default headers,
+    /// see the [module docs](self) for more information.
+    pub fn new() -> Self {
+        Self {
+            nosniff: true,
+            frame_options: Some(FrameOptions::Deny),
+            referrer_policy: Some(HeaderValue::from_static("strict-origin-when-cross-origin")),
+            permissions_policy: Some(HeaderValue::from_static(
+                "camera=(), microphone=(), geolocation=()",
+            )),
+            cross_origin_opener_policy: None,
+            cross_origin_embedder_policy: None,
+            content_security_policy: None,
+            override_existing: false,
+        }
+    }
+
+    /// Create a new [`SecurityHeaders`] without any headers enabled.
+    pub fn empty() -> Self {
+        Self {
+            nosniff: false,
+            frame_options: None,
+            referrer_policy: None,
+            permissions_policy: None,
+            cross_origin_opener_policy: None,
+            cross_origin_embedder_policy: None,
+            content_security_policy: None,
+            override_existing: false,
+        }
+    }
+
+    /// Enable or disable `X-Content-Type-Options: nosniff`.
+    pub fn with_nosniff(mut self, nosniff: bool) -> Self {
+        self.nosniff = nosniff;
+        self
+    }
+
+    /// Enable or disable `X-Content-Type-Options: nosniff`.
+    pub fn set_nosniff(&mut self, nosniff: bool) -> &mut Self {
+        self.nosniff = nosniff;
+        self
+    }
+
+    /// Set the `X-Frame-Options` header, `None` to disable it.
+    ///
+    /// Use the `frame-ancestors` [`CspDirective`] for more fine-grained control.
+    pub fn with_frame_options(mut self, frame_options: Option<FrameOptions>) -> Self {
+        self.frame_options = frame_options;
+        self
+    }
+
+    /// Set the `X-Frame-Options` header, `None` to disable it.
+    ///
+    /// Use the `frame-ancestors` [`CspDirective`] for more fine-grained control.
+    pub fn set_frame_options(&mut self, frame_options: Option<FrameOptions>) -> &mut Self {
+        self.frame_options = frame_options;
+        self
+    }
+
+    /// Set the `Referrer-Policy` header, `None` to disable it.
+    pub fn with_referrer_policy(mut self, policy: Option<HeaderValue>) -> Self {
+        self.referrer_policy = policy;
+        self
+    }
+
+    /// Set the `Referrer-Policy` header, `None` to disable it.
+    pub fn set_referrer_policy(&mut self, policy: Option<HeaderValue>) -> &mut Self {
+        self.referrer_policy = policy;
+        self
+    }
+
+    /// Set the `Permissions-Policy` header, `None` to disable it.
+    pub fn with_permissions_policy(mut self, policy: Option<HeaderValue>) -> Self {
+        self.permissions_policy = policy;
+        self
+    }
+
+    /// Set the `Permissions-Policy` header, `None` to disable it.
+    pub fn set_permissions_policy(&mut self, policy: Option<HeaderValue>) -> &mut Self {
+        self.permissions_policy = policy;
+        self
+    }
+
+    /// Enable or disable `Cross-Origin-Opener-Policy: same-origin`.
+    pub fn with_cross_origin_opener_policy(mut self, enabled: bool) -> Self {
+        self.set_cross_origin_opener_policy(enabled);
+        self
+    }
+
+    /// Enable or disable `Cross-Origin-Opener-Policy: same-origin`.
+    pub fn set_cross_origin_opener_policy(&mut self, enabled: bool) -> &mut Self {
+        self.cross_origin_opener_policy = enabled.then(|| HeaderValue::from_static("same-origin"));
+        self
+    }
+
+    /// Enable or disable `Cross-Origin-Embedder-Policy: require-corp`.
+    pub fn with_cross_origin_embedder_policy(mut self, enabled: bool) -> Self {
+        self.set_cross_origin_embedder_policy(enabled);
+        self
+    }
+
+    /// Enable or disable `Cross-Origin-Embedder-Policy: require-corp`.
+    pub fn set_cross_origin_embedder_policy(&mut self, enabled: bool) -> &mut Self {
+        self.cross_origin_embedder_policy =
+            enabled.then(|| HeaderValue::from_static("require-corp"));
+        self
+    }
+
+    /// Set the [`ContentSecurityPolicy`].
+    pub fn with_content_security_policy(mut self, policy: ContentSecurityPolicy) -> Self {
+        self.content_security_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Set the [`ContentSecurityPolicy`].
+    pub fn set_content_security_policy(&mut self, policy: ContentSecurityPolicy) -> &mut Self {
+        self.content_security_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Override headers already set by the inner service,
+    /// instead of leaving them untouched (the default).
+    pub fn with_override(mut self, override_existing: bool) -> Self {
+        self.override_existing = override_existing;
+        self
+    }
+
+    /// Override headers already set by the inner service,
+    /// instead of leaving them untouched (the default).
+    pub fn set_override(&mut self, override_existing: bool) -> &mut Self {
+        self.override_existing = override_existing;
+        self
+    }
+
+    fn uses_nonce(&self) -> bool {
+        self.content_security_policy
+            .as_ref()
+            .is_some_and(|csp| csp.uses_nonce())
+    }
+
+    fn apply(&self, headers: &mut HeaderMap, nonce: Option<&CspNonce>) {
+        let mut insert = |name: HeaderName, value: HeaderValue| {
+            if self.override_existing || !headers.contains_key(&name) {
+                headers.insert(name, value);
+            }
+        };
+
+        if self.nosniff {
+            insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        }
+        if let Some(frame_options) = self.frame_options {
+            insert(X_FRAME_OPTIONS, frame_options.header_value());
+        }
+        if let Some(value) = &self.referrer_policy {
+            insert(REFERRER_POLICY, value.clone());
+        }
+        if let Some(value) = &self.permissions_policy {
+            insert(PERMISSIONS_POLICY.clone(), value.clone());
+        }
+        if let Some(value) = &self.cross_origin_opener_policy {
+            insert(CROSS_ORIGIN_OPENER_POLICY.clone(), value.clone());
+        }
+        if let Some(value) = &self.cross_origin_embedder_policy {
+            insert(CROSS_ORIGIN_EMBEDDER_POLICY.clone(), value.clone());
+        }
+        if let Some(csp) = &self.content_security_policy {
+            if let Some(value) = csp.to_header_value(nonce) {
+                insert(csp.header_name(), value);
+            }
+        }
+    }
+}
+
+/// Layer that applies [`SecurityHeadersService`] which adds security headers to responses.
+///
+/// See the [module docs](self) for more information.
+#[derive(Debug, Clone, Default)]
+pub struct SecurityHeadersLayer {
+    headers: SecurityHeaders,
+}
+
+impl SecurityHeadersLayer {
+    /// Create a new [`SecurityHeadersLayer`] adding the given [`SecurityHeaders`].
+    pub const fn new(headers: SecurityHeaders) -> Self {
+        Self { headers }
+    }
+}
+
+impl<S> Layer<S> for SecurityHeadersLayer {
+    type Service = SecurityHeadersService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SecurityHeadersService {
+            inner,
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+/// Middleware which adds security headers to responses.
+///
+/// See the [module docs](self) for more information.
+pub struct SecurityHeadersService<S> {
+    inner: S,
+    headers: SecurityHeaders,
+}
+
+impl<S> SecurityHeadersService<S> {
+    /// Create a new [`SecurityHeadersService`] adding the given [`SecurityHeaders`].
+    pub const fn new(inner: S, headers: SecurityHeaders) -> Self {
+        Self { inner, headers }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for SecurityHeadersService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecurityHeadersService")
+            .field("inner", &self.inner)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for SecurityHeadersService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for SecurityHeadersService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let ctx_headers = ctx.get::<SecurityHeaders>().cloned();
+
+        // a nonce is generated in case any of the possible policies make use of it,
+        // as the response can still overwrite the policy
+        let nonce = (self.headers.uses_nonce()
+            || ctx_headers
+                .as_ref()
+                .is_some_and(SecurityHeaders::uses_nonce))
+        .then(CspNonce::new);
+        if let Some(nonce) = nonce.clone() {
+            ctx.insert(nonce);
+        }
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        let headers = res
+            .extensions_mut()
+            .remove::<SecurityHeaders>()
+            .or(ctx_headers)
+            .unwrap_or_else(|| self.headers.clone());
+        let nonce = nonce.or_else(|| headers.uses_nonce().then(CspNonce::new));
+        headers.apply(res.headers_mut(), nonce.as_ref());
+        if let Some(nonce) = nonce {
+            res.extensions_mut().insert(nonce);
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{header::CONTENT_SECURITY_POLICY_REPORT_ONLY, Body};
+    use rama_core::service::service_fn;
+    use std::{collections::HashSet, convert::Infallible};
+
+    async fn ok(_req: Request) -> Result<Response, Infallible> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    #[tokio::test]
+    async fn test_security_headers_defaults() {
+        let svc = SecurityHeadersLayer::default().layer(service_fn(ok));
+        let res = svc
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+
+        let headers = res.headers();
+        assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[X_FRAME_OPTIONS], "DENY");
+        assert_eq!(headers[REFERRER_POLICY], "strict-origin-when-cross-origin");
+        assert!(headers.contains_key(&PERMISSIONS_POLICY));
+        assert!(!headers.contains_key(&CROSS_ORIGIN_OPENER_POLICY));
+        assert!(!headers.contains_key(&CROSS_ORIGIN_EMBEDDER_POLICY));
+        assert!(!headers.contains_key(CONTENT_SECURITY_POLICY));
+        assert!(res.extensions().get::<CspNonce>().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_security_headers_nonce_unique_per_response() {
+        let csp = ContentSecurityPolicy::new()
+            .with_directive(CspDirective::DefaultSrc, ["'self'"])
+            .with_nonce(CspDirective::ScriptSrc)
+            .with_nonce(CspDirective::StyleSrc);
+        let svc =
+            SecurityHeadersLayer::new(SecurityHeaders::new().with_content_security_policy(csp))
+                .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+                    let nonce = ctx.get::<CspNonce>().unwrap().clone();
+                    Ok::<_, Infallible>(Response::new(Body::from(nonce.to_string())))
+                }));
+
+        let mut nonces = HashSet::new();
+        for _ in 0..16 {
+            let res = svc
+                .serve(Context::default(), Request::new(Body::empty()))
+                .await
+                .unwrap();
+            let nonce = res.extensions().get::<CspNonce>().unwrap().clone();
+            assert_eq!(
+                res.headers()[CONTENT_SECURITY_POLICY],
+                format!(
+                    "default-src 'self'; script-src 'nonce-{nonce}'; style-src 'nonce-{nonce}'"
+                )
+            );
+            let body = crate::dep::http_body_util::BodyExt::collect(res.into_body())
+                .await
+                .unwrap()
+                .to_bytes();
+            assert_eq!(body, nonce.as_str());
+            assert!(nonces.insert(nonce), "nonce reused");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_security_headers_override_precedence() {
+        let handler = service_fn(|_req: Request| async move {
+            let mut res = Response::new(Body::empty());
+            res.headers_mut()
+                .insert(X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"));
+            Ok::<_, Infallible>(res)
+        });
+
+        // headers set by the handler win by default
+        let svc = SecurityHeadersLayer::default().layer(handler.clone());
+        let res = svc
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.headers()[X_FRAME_OPTIONS], "SAMEORIGIN");
+        assert_eq!(res.headers()[X_CONTENT_TYPE_OPTIONS], "nosniff");
+
+        // unless override mode is selected
+        let svc =
+            SecurityHeadersLayer::new(SecurityHeaders::new().with_override(true)).layer(handler);
+        let res = svc
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.headers()[X_FRAME_OPTIONS], "DENY");
+    }
+
+    #[tokio::test]
+    async fn test_security_headers_per_route_override() {
+        let svc = SecurityHeadersLayer::default().layer(service_fn(|req: Request| async move {
+            let mut res = Response::new(Body::empty());
+            if req.uri().path() == "/embed" {
+                res.extensions_mut().insert(
+                    SecurityHeaders::new()
+                        .with_frame_options(None)
+                        .with_content_security_policy(
+                            ContentSecurityPolicy::new()
+                                .with_directive(CspDirective::FrameAncestors, ["*"])
+                                .with_report_only(true),
+                        ),
+                );
+            }
+            Ok::<_, Infallible>(res)
+        }));
+
+        let req = Request::builder()
+            .uri("/embed")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        assert!(!res.headers().contains_key(X_FRAME_OPTIONS));
+        assert_eq!(
+            res.headers()[CONTENT_SECURITY_POLICY_REPORT_ONLY],
+            "frame-ancestors *"
+        );
+
+        // the context can overwrite the headers as well
+        let mut ctx = Context::default();
+        ctx.insert(SecurityHeaders::empty().with_cross_origin_opener_policy(true));
+        let res = svc.serve(ctx, Request::new(Body::empty())).await.unwrap();
+        assert_eq!(res.headers()[&CROSS_ORIGIN_OPENER_POLICY], "same-origin");
+        assert!(!res.headers().contains_key(X_CONTENT_TYPE_OPTIONS));
+    }
+}
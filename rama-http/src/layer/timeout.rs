@@ -1,7 +1,12 @@
 //! Middleware that applies a timeout to requests.
 //!
 //! If the request does not complete within the specified timeout it will be aborted and a `408
-//! Request Timeout` response will be sent.
+//! Request Timeout` response will be sent. The timeout is shortened in case a [`Deadline`]
+//! found in the [`Context`] expires earlier, see the [`deadline`] module for more information.
+//!
+//! [`Deadline`]: rama_core::layer::timeout::Deadline
+//! [`Context`]: rama_core::Context
+//! [`deadline`]: crate::layer::deadline
 //!
 //! # Differences from `rama_core::service::layer::Timeout`
 //!
@@ -42,6 +47,7 @@
 //! [`Infallible`]: std::convert::Infallible
 
 use crate::{Request, Response, StatusCode};
+use rama_core::layer::timeout::Deadline;
 use rama_core::{Context, Layer, Service};
 use rama_utils::macros::define_inner_service_accessors;
 use std::fmt;
@@ -125,9 +131,13 @@ where
         ctx: Context<State>,
         req: Request<ReqBody>,
     ) -> Result<Self::Response, Self::Error> {
+        let timeout = match ctx.get::<Deadline>() {
+            Some(deadline) => deadline.remaining().min(self.timeout),
+            None => self.timeout,
+        };
         tokio::select! {
             res = self.inner.serve(ctx, req) => res,
-            _ = tokio::time::sleep(self.timeout) => {
+            _ = tokio::time::sleep(timeout) => {
                 let mut res = Response::new(ResBody::default());
                 *res.status_mut() = StatusCode::REQUEST_TIMEOUT;
                 Ok(res)
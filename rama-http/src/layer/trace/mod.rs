@@ -344,6 +344,13 @@
 //! - [`TraceLayer::new_for_grpc`] classifies based on the gRPC protocol and supports streaming
 //!   responses.
 //!
+//! # Sampling
+//!
+//! [`LogSamplingLayer`] can be placed inside the [`TraceLayer`] to only log a sample
+//! of the responses, using an error-rate aware [`LogSampler`]. The [`SamplingDecision`]
+//! is stored in the response extensions, such that [`DefaultOnResponse`]
+//! and other layers agree on which requests are logged.
+//!
 //! [tracing]: https://crates.io/crates/tracing
 //! [`Service`]: rama_core::Service
 //! [`Service::serve`]: rama_core::Service::serve
@@ -368,6 +375,7 @@ pub use self::{
     on_failure::{DefaultOnFailure, OnFailure},
     on_request::{DefaultOnRequest, OnRequest},
     on_response::{DefaultOnResponse, OnResponse},
+    sampling::{LogSampler, LogSampling, LogSamplingLayer, SamplingDecision},
     service::Trace,
 };
 
@@ -435,6 +443,7 @@ mod on_eos;
 mod on_failure;
 mod on_request;
 mod on_response;
+mod sampling;
 mod service;
 
 const DEFAULT_MESSAGE_LEVEL: Level = Level::DEBUG;
@@ -1,5 +1,5 @@
-use super::{Latency, DEFAULT_MESSAGE_LEVEL};
-use crate::Response;
+use super::{Latency, SamplingDecision, DEFAULT_MESSAGE_LEVEL};
+use crate::{PhaseTimings, Response, ResponseProvenance};
 use rama_utils::latency::LatencyUnit;
 use std::time::Duration;
 use tracing::Level;
@@ -42,7 +42,14 @@ where
 
 /// The default [`OnResponse`] implementation used by [`Trace`].
 ///
+/// The [`PhaseTimings`] found in the response extensions, if any,
+/// are included in the event, as recorded by the time the response head is produced.
+///
+/// No event is emitted for responses of which the [`SamplingDecision`]
+/// (e.g. as made by [`LogSampling`]) is to drop them.
+///
 /// [`Trace`]: super::Trace
+/// [`LogSampling`]: super::LogSampling
 #[derive(Clone, Debug)]
 pub struct DefaultOnResponse {
     level: Level,
@@ -137,6 +144,14 @@ impl DefaultOnResponse {
 
 impl<B> OnResponse<B> for DefaultOnResponse {
     fn on_response(self, response: &Response<B>, latency: Duration, _: &Span) {
+        if response
+            .extensions()
+            .get::<SamplingDecision>()
+            .is_some_and(|decision| !decision.is_sampled())
+        {
+            return;
+        }
+
         let latency = Latency {
             unit: self.latency_unit,
             duration: latency,
@@ -144,12 +159,22 @@ impl<B> OnResponse<B> for DefaultOnResponse {
         let response_headers = self
             .include_headers
             .then(|| tracing::field::debug(response.headers()));
+        let phase_timings = response
+            .extensions()
+            .get::<PhaseTimings>()
+            .map(tracing::field::debug);
+        let provenance = response
+            .extensions()
+            .get::<ResponseProvenance>()
+            .map(tracing::field::display);
 
         event_dynamic_lvl!(
             self.level,
             %latency,
             status = status(response),
             response_headers,
+            phase_timings,
+            provenance,
             "finished processing request"
         );
     }
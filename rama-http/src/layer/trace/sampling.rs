@@ -0,0 +1,479 @@
+use crate::{Request, Response};
+use parking_lot::Mutex;
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Minimum amount of requests within the current window,
+/// prior to the error ratio being compared against its threshold.
+const MIN_WINDOW_REQUESTS: u64 = 10;
+
+/// Length of the window used for the error budget and ratio.
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The sampling decision made by a [`LogSampler`] for a single request.
+///
+/// Inserted by [`LogSampling`] into the [`Response`] extensions,
+/// such that all logging layers (e.g. [`DefaultOnResponse`]) agree
+/// on whether or not a given request is sampled.
+///
+/// [`DefaultOnResponse`]: super::DefaultOnResponse
+pub enum SamplingDecision {
+    /// The request is sampled and is to be logged.
+    Sampled,
+    /// The request is not sampled and is not to be logged.
+    Dropped,
+}
+
+impl SamplingDecision {
+    /// Returns `true` if the request is sampled.
+    pub fn is_sampled(self) -> bool {
+        matches!(self, Self::Sampled)
+    }
+}
+
+/// Error-rate aware log sampler, used by [`LogSampling`].
+///
+/// - errors are always sampled, up to a budget per second;
+/// - successes are sampled at a configurable base rate;
+/// - once the error ratio (within the current second) crosses a threshold,
+///   successes are sampled at an elevated rate instead, decaying back
+///   to the base rate once the error ratio dropped below the threshold again.
+///
+/// Sampling is deterministic: a rate of `0.25` samples exactly one in four
+/// successes. Time is measured using the [`tokio`] clock, such that it can be
+/// controlled in tests.
+///
+/// The sampler is cheap to clone, clones share their state.
+#[derive(Clone)]
+pub struct LogSampler {
+    config: LogSamplerConfig,
+    state: Arc<Mutex<LogSamplerState>>,
+    sampled: Arc<AtomicU64>,
+    dropped: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Clone)]
+struct LogSamplerConfig {
+    error_budget: u64,
+    base_rate: f64,
+    elevated_rate: f64,
+    error_ratio_threshold: f64,
+    decay: Duration,
+}
+
+#[derive(Debug)]
+struct LogSamplerState {
+    window_start: Option<Instant>,
+    window_requests: u64,
+    window_errors: u64,
+    window_errors_sampled: u64,
+    elevated_at: Option<Instant>,
+    credit: f64,
+}
+
+impl fmt::Debug for LogSampler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogSampler")
+            .field("config", &self.config)
+            .field("state", &self.state)
+            .field("sampled", &self.sampled)
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+impl Default for LogSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogSampler {
+    /// Create a new [`LogSampler`], which by default samples up to 100 errors
+    /// per second and 10% of the successes, raised to all successes for 30 seconds
+    /// once at least 5% of the requests within a second are errors.
+    pub fn new() -> Self {
+        Self {
+            config: LogSamplerConfig {
+                error_budget: 100,
+                base_rate: 0.1,
+                elevated_rate: 1.0,
+                error_ratio_threshold: 0.05,
+                decay: Duration::from_secs(30),
+            },
+            state: Arc::new(Mutex::new(LogSamplerState {
+                window_start: None,
+                window_requests: 0,
+                window_errors: 0,
+                window_errors_sampled: 0,
+                elevated_at: None,
+                credit: 0.0,
+            })),
+            sampled: Arc::new(AtomicU64::new(0)),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Set the maximum amount of errors sampled per second.
+    pub fn with_error_budget(mut self, budget: u64) -> Self {
+        self.config.error_budget = budget;
+        self
+    }
+
+    /// Set the maximum amount of errors sampled per second.
+    pub fn set_error_budget(&mut self, budget: u64) -> &mut Self {
+        self.config.error_budget = budget;
+        self
+    }
+
+    /// Set the rate (between `0.0` and `1.0`) at which successes are sampled.
+    pub fn with_base_rate(mut self, rate: f64) -> Self {
+        self.config.base_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the rate (between `0.0` and `1.0`) at which successes are sampled.
+    pub fn set_base_rate(&mut self, rate: f64) -> &mut Self {
+        self.config.base_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the rate (between `0.0` and `1.0`) at which successes are sampled
+    /// while the error ratio is above its threshold.
+    pub fn with_elevated_rate(mut self, rate: f64) -> Self {
+        self.config.elevated_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the rate (between `0.0` and `1.0`) at which successes are sampled
+    /// while the error ratio is above its threshold.
+    pub fn set_elevated_rate(&mut self, rate: f64) -> &mut Self {
+        self.config.elevated_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the error ratio (between `0.0` and `1.0`) above which
+    /// the elevated sampling rate is used.
+    pub fn with_error_ratio_threshold(mut self, threshold: f64) -> Self {
+        self.config.error_ratio_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the error ratio (between `0.0` and `1.0`) above which
+    /// the elevated sampling rate is used.
+    pub fn set_error_ratio_threshold(&mut self, threshold: f64) -> &mut Self {
+        self.config.error_ratio_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the duration over which the sampling rate decays back from
+    /// the elevated rate to the base rate, once the error ratio dropped
+    /// below its threshold.
+    pub fn with_decay(mut self, decay: Duration) -> Self {
+        self.config.decay = decay;
+        self
+    }
+
+    /// Set the duration over which the sampling rate decays back from
+    /// the elevated rate to the base rate, once the error ratio dropped
+    /// below its threshold.
+    pub fn set_decay(&mut self, decay: Duration) -> &mut Self {
+        self.config.decay = decay;
+        self
+    }
+
+    /// Amount of requests sampled so far.
+    pub fn sampled(&self) -> u64 {
+        self.sampled.load(Ordering::Relaxed)
+    }
+
+    /// Amount of requests dropped by sampling so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Make the [`SamplingDecision`] for a request, given whether or not it resulted in an error.
+    pub fn sample(&self, is_error: bool) -> SamplingDecision {
+        let now = Instant::now();
+        let decision = self.state.lock().sample(&self.config, is_error, now);
+        match decision {
+            SamplingDecision::Sampled => self.sampled.fetch_add(1, Ordering::Relaxed),
+            SamplingDecision::Dropped => self.dropped.fetch_add(1, Ordering::Relaxed),
+        };
+        decision
+    }
+}
+
+impl LogSamplerState {
+    fn sample(
+        &mut self,
+        config: &LogSamplerConfig,
+        is_error: bool,
+        now: Instant,
+    ) -> SamplingDecision {
+        if self
+            .window_start
+            .is_none_or(|start| now.duration_since(start) >= WINDOW)
+        {
+            self.window_start = Some(now);
+            self.window_requests = 0;
+            self.window_errors = 0;
+            self.window_errors_sampled = 0;
+        }
+
+        self.window_requests += 1;
+        if is_error {
+            self.window_errors += 1;
+        }
+        if self.window_requests >= MIN_WINDOW_REQUESTS
+            && (self.window_errors as f64 / self.window_requests as f64)
+                >= config.error_ratio_threshold
+        {
+            if self.elevated_at.is_none() {
+                tracing::debug!(
+                    errors = self.window_errors,
+                    requests = self.window_requests,
+                    "log sampler: error ratio crossed threshold: elevate sampling rate"
+                );
+            }
+            self.elevated_at = Some(now);
+        }
+
+        if is_error {
+            return if self.window_errors_sampled < config.error_budget {
+                self.window_errors_sampled += 1;
+                SamplingDecision::Sampled
+            } else {
+                SamplingDecision::Dropped
+            };
+        }
+
+        self.credit += self.rate(config, now);
+        if self.credit >= 1.0 {
+            self.credit -= 1.0;
+            SamplingDecision::Sampled
+        } else {
+            SamplingDecision::Dropped
+        }
+    }
+
+    fn rate(&mut self, config: &LogSamplerConfig, now: Instant) -> f64 {
+        let Some(elevated_at) = self.elevated_at else {
+            return config.base_rate;
+        };
+        let elapsed = now.duration_since(elevated_at);
+        if elapsed >= config.decay {
+            tracing::debug!("log sampler: decayed back to base sampling rate");
+            self.elevated_at = None;
+            return config.base_rate;
+        }
+        let progress = elapsed.as_secs_f64() / config.decay.as_secs_f64();
+        (config.elevated_rate - config.base_rate).mul_add(-progress, config.elevated_rate)
+    }
+}
+
+/// Layer that applies [`LogSampling`] which makes a [`SamplingDecision`] for each response.
+#[derive(Debug, Clone, Default)]
+pub struct LogSamplingLayer {
+    sampler: LogSampler,
+}
+
+impl LogSamplingLayer {
+    /// Create a new [`LogSamplingLayer`] using the given [`LogSampler`].
+    pub const fn new(sampler: LogSampler) -> Self {
+        Self { sampler }
+    }
+}
+
+impl<S> Layer<S> for LogSamplingLayer {
+    type Service = LogSampling<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LogSampling {
+            inner,
+            sampler: self.sampler.clone(),
+        }
+    }
+}
+
+/// Middleware which makes a [`SamplingDecision`] for each response using a [`LogSampler`],
+/// inserting the decision into the [`Response`] extensions.
+///
+/// Server error responses (`5xx`) are considered errors. Errors returned
+/// by the inner service are counted as errors as well, but as no response
+/// exists in that case, the decision cannot be propagated.
+///
+/// Place this layer inside the [`TraceLayer`] (and other logging layers),
+/// such that these can find the decision in the response extensions.
+///
+/// [`TraceLayer`]: super::TraceLayer
+pub struct LogSampling<S> {
+    inner: S,
+    sampler: LogSampler,
+}
+
+impl<S> LogSampling<S> {
+    /// Create a new [`LogSampling`] using the given [`LogSampler`].
+    pub const fn new(inner: S, sampler: LogSampler) -> Self {
+        Self { inner, sampler }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Get a reference to the [`LogSampler`] used.
+    pub fn sampler(&self) -> &LogSampler {
+        &self.sampler
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for LogSampling<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogSampling")
+            .field("inner", &self.inner)
+            .field("sampler", &self.sampler)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for LogSampling<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            sampler: self.sampler.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for LogSampling<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        match self.inner.serve(ctx, req).await {
+            Ok(mut res) => {
+                let decision = self.sampler.sample(res.status().is_server_error());
+                res.extensions_mut().insert(decision);
+                Ok(res)
+            }
+            Err(err) => {
+                self.sampler.sample(true);
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, StatusCode};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn sample_n(sampler: &LogSampler, is_error: bool, n: usize) -> usize {
+        (0..n)
+            .filter(|_| sampler.sample(is_error).is_sampled())
+            .count()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_log_sampler_base_rate() {
+        let sampler = LogSampler::new().with_base_rate(0.25);
+        assert_eq!(sample_n(&sampler, false, 8), 2);
+        assert_eq!(sampler.sampled(), 2);
+        assert_eq!(sampler.dropped(), 6);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_log_sampler_error_budget() {
+        let sampler = LogSampler::new()
+            .with_error_budget(3)
+            .with_base_rate(0.0)
+            .with_elevated_rate(0.0);
+        assert_eq!(sample_n(&sampler, true, 5), 3);
+        assert_eq!(sampler.dropped(), 2);
+
+        // budget is per second
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(sample_n(&sampler, true, 5), 3);
+        assert_eq!(sampler.dropped(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_log_sampler_elevated_rate_and_decay() {
+        let sampler = LogSampler::new()
+            .with_base_rate(0.0)
+            .with_elevated_rate(1.0)
+            .with_error_ratio_threshold(0.5)
+            .with_decay(Duration::from_secs(10));
+
+        assert_eq!(sample_n(&sampler, false, 10), 0);
+
+        // error ratio crosses the threshold: all successes sampled
+        tokio::time::advance(Duration::from_secs(1)).await;
+        sample_n(&sampler, true, 10);
+        assert_eq!(sample_n(&sampler, false, 4), 4);
+
+        // half-way the decay: half of the successes sampled
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(sample_n(&sampler, false, 4), 2);
+
+        // decayed back to the base rate
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(sample_n(&sampler, false, 4), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_log_sampling_decision_extension() {
+        let svc = LogSamplingLayer::new(LogSampler::new().with_base_rate(0.0)).layer(service_fn(
+            |req: Request| async move {
+                let status = if req.uri().path() == "/error" {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    StatusCode::OK
+                };
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(status)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+            },
+        ));
+
+        for (path, expected) in [
+            ("/ok", SamplingDecision::Dropped),
+            ("/error", SamplingDecision::Sampled),
+        ] {
+            let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            let res = svc.serve(Context::default(), req).await.unwrap();
+            assert_eq!(
+                res.extensions().get::<SamplingDecision>(),
+                Some(&expected),
+                "path: {path}"
+            );
+        }
+        assert_eq!(svc.sampler().sampled(), 1);
+        assert_eq!(svc.sampler().dropped(), 1);
+    }
+}
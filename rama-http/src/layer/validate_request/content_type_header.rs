@@ -0,0 +1,113 @@
+use super::ValidateRequest;
+use crate::{dep::mime::Mime, header, Request, Response, StatusCode};
+use rama_core::Context;
+use std::{fmt, marker::PhantomData, sync::Arc};
+
+/// Type that performs validation of the Content-Type header,
+/// against an allowlist of media types.
+pub struct ContentTypeHeader<ResBody = crate::Body> {
+    allowed: Arc<[Mime]>,
+    _ty: PhantomData<fn() -> ResBody>,
+}
+
+impl<ResBody> ContentTypeHeader<ResBody> {
+    /// Create a new `ContentTypeHeader`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the `allowed` values is not in the form: `type/subtype`,
+    /// such as `application/json`, or `type/*`, such as `text/*`.
+    pub(super) fn new(allowed: &[&str]) -> Self
+    where
+        ResBody: Default,
+    {
+        Self {
+            allowed: allowed
+                .iter()
+                .map(|value| {
+                    value
+                        .parse::<Mime>()
+                        .expect("value is not a valid media type")
+                })
+                .collect(),
+            _ty: PhantomData,
+        }
+    }
+
+    fn is_allowed(&self, mime: &Mime) -> bool {
+        // parameters such as `charset` are ignored, only the essence is matched
+        self.allowed.iter().any(|allowed| {
+            allowed
+                .type_()
+                .as_str()
+                .eq_ignore_ascii_case(mime.type_().as_str())
+                && (allowed.subtype() == mime::STAR
+                    || allowed
+                        .subtype()
+                        .as_str()
+                        .eq_ignore_ascii_case(mime.subtype().as_str()))
+        })
+    }
+}
+
+impl<ResBody> Clone for ContentTypeHeader<ResBody> {
+    fn clone(&self) -> Self {
+        Self {
+            allowed: self.allowed.clone(),
+            _ty: PhantomData,
+        }
+    }
+}
+
+impl<ResBody> fmt::Debug for ContentTypeHeader<ResBody> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContentTypeHeader")
+            .field("allowed", &self.allowed)
+            .finish()
+    }
+}
+
+/// Returns `true` if the request declares to have no body,
+/// in which case no `Content-Type` is expected either.
+fn has_no_body<B>(req: &Request<B>) -> bool {
+    !req.headers().contains_key(header::TRANSFER_ENCODING)
+        && req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .is_none_or(|value| value.as_bytes() == b"0")
+}
+
+impl<S, B, ResBody> ValidateRequest<S, B> for ContentTypeHeader<ResBody>
+where
+    S: Clone + Send + Sync + 'static,
+    B: Send + Sync + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type ResponseBody = ResBody;
+
+    async fn validate(
+        &self,
+        ctx: Context<S>,
+        req: Request<B>,
+    ) -> Result<(Context<S>, Request<B>), Response<Self::ResponseBody>> {
+        let allowed = match req.headers().get(header::CONTENT_TYPE) {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse::<Mime>().ok())
+                .map(|mime| self.is_allowed(&mime))
+                .unwrap_or_default(),
+            None => has_no_body(&req),
+        };
+        if allowed {
+            return Ok((ctx, req));
+        }
+        tracing::debug!(
+            content_type = ?req.headers().get(header::CONTENT_TYPE),
+            "request rejected: unsupported media type",
+        );
+        let mut res = Response::new(ResBody::default());
+        *res.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        Err(res)
+    }
+}
@@ -138,6 +138,7 @@
 //! ```
 
 mod accept_header;
+mod content_type_header;
 mod validate;
 mod validate_fn;
 mod validate_request_header;
@@ -145,6 +146,8 @@ mod validate_request_header;
 #[doc(inline)]
 pub use accept_header::AcceptHeader;
 #[doc(inline)]
+pub use content_type_header::ContentTypeHeader;
+#[doc(inline)]
 pub use validate::ValidateRequest;
 #[doc(inline)]
 pub use validate_fn::{BoxValidateRequestFn, ValidateRequestFn};
@@ -1,5 +1,5 @@
-use super::{AcceptHeader, BoxValidateRequestFn, ValidateRequest};
-use crate::{Request, Response};
+use super::{AcceptHeader, BoxValidateRequestFn, ContentTypeHeader, ValidateRequest};
+use crate::{Request, Response, ResponseProvenance};
 use rama_core::{Context, Layer, Service};
 use rama_utils::macros::define_inner_service_accessors;
 use std::fmt;
@@ -58,6 +58,36 @@ impl<ResBody> ValidateRequestHeaderLayer<AcceptHeader<ResBody>> {
     }
 }
 
+impl<ResBody> ValidateRequestHeaderLayer<ContentTypeHeader<ResBody>> {
+    /// Validate requests have a `Content-Type` header matching one of the allowed media types,
+    /// responding with `415 Unsupported Media Type` otherwise.
+    ///
+    /// Allowed media types are to be `type/subtype` or `type/*`.
+    /// Parameters (e.g. `charset`) of the request's `Content-Type` are ignored.
+    /// Requests without `Content-Type` are only allowed in case they declare to have no body.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the `allowed` values is not in the form: `type/subtype` or `type/*`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rama_http::layer::validate_request::{ContentTypeHeader, ValidateRequestHeaderLayer};
+    ///
+    /// let layer = ValidateRequestHeaderLayer::<ContentTypeHeader>::content_type(&[
+    ///     "application/json",
+    ///     "text/*",
+    /// ]);
+    /// ```
+    pub fn content_type(allowed: &[&str]) -> Self
+    where
+        ResBody: Default,
+    {
+        Self::custom(ContentTypeHeader::new(allowed))
+    }
+}
+
 impl<T> ValidateRequestHeaderLayer<T> {
     /// Validate requests using a custom validator.
     pub fn custom(validate: T) -> Self {
@@ -140,6 +170,22 @@ impl<S, ResBody> ValidateRequestHeader<S, AcceptHeader<ResBody>> {
     }
 }
 
+impl<S, ResBody> ValidateRequestHeader<S, ContentTypeHeader<ResBody>> {
+    /// Validate requests have a `Content-Type` header matching one of the allowed media types.
+    ///
+    /// See [`ValidateRequestHeaderLayer::content_type`] for more details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the `allowed` values is not in the form: `type/subtype` or `type/*`.
+    pub fn content_type(inner: S, allowed: &[&str]) -> Self
+    where
+        ResBody: Default,
+    {
+        Self::custom(inner, ContentTypeHeader::new(allowed))
+    }
+}
+
 impl<S, T> ValidateRequestHeader<S, T> {
     /// Validate requests using a custom validator.
     pub fn custom(inner: S, validate: T) -> Self {
@@ -175,7 +221,10 @@ where
     ) -> Result<Self::Response, Self::Error> {
         match self.validate.validate(ctx, req).await {
             Ok((ctx, req)) => self.inner.serve(ctx, req).await,
-            Err(res) => Ok(res),
+            Err(mut res) => {
+                ResponseProvenance::record(&mut res, "validate_request", "invalid request");
+                Ok(res)
+            }
         }
     }
 }
@@ -338,6 +387,79 @@ mod tests {
         assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
     }
 
+    #[tokio::test]
+    async fn allowed_content_type_with_charset() {
+        let service = ValidateRequestHeaderLayer::content_type(&["application/json", "text/*"])
+            .layer(service_fn(echo));
+
+        for value in [
+            "application/json",
+            "application/json; charset=utf-8",
+            "Application/JSON;charset=\"UTF-8\"",
+            "text/plain; charset=iso-8859-1",
+        ] {
+            let request = Request::post("/")
+                .header(header::CONTENT_TYPE, value)
+                .body(Body::from("{}"))
+                .unwrap();
+
+            let res = service.serve(Context::default(), request).await.unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK, "content-type: {value}");
+        }
+    }
+
+    #[tokio::test]
+    async fn disallowed_content_type() {
+        let service =
+            ValidateRequestHeaderLayer::content_type(&["application/json"]).layer(service_fn(echo));
+
+        for value in [
+            "application/xml",
+            "application/json-seq",
+            "text/json",
+            "invalid",
+        ] {
+            let request = Request::post("/")
+                .header(header::CONTENT_TYPE, value)
+                .body(Body::from("{}"))
+                .unwrap();
+
+            let res = service.serve(Context::default(), request).await.unwrap();
+
+            assert_eq!(
+                res.status(),
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "content-type: {value}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_content_type() {
+        let service =
+            ValidateRequestHeaderLayer::content_type(&["application/json"]).layer(service_fn(echo));
+
+        let request = Request::post("/")
+            .header(header::CONTENT_LENGTH, "2")
+            .body(Body::from("{}"))
+            .unwrap();
+        let res = service.serve(Context::default(), request).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let request = Request::post("/")
+            .header(header::TRANSFER_ENCODING, "chunked")
+            .body(Body::from("{}"))
+            .unwrap();
+        let res = service.serve(Context::default(), request).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // requests without a body do not need a content type
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let res = service.serve(Context::default(), request).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
     async fn echo<B>(req: Request<B>) -> Result<Response<B>, BoxError> {
         Ok(Response::new(req.into_body()))
     }
@@ -22,7 +22,8 @@ pub use ::rama_http_types::{
     header, proto,
     response::{self, IntoResponse, Response},
     Body, BodyDataStream, BodyExtractExt, BodyLimit, HeaderMap, HeaderName, HeaderValue, Method,
-    Request, Scheme, StatusCode, Uri, Version,
+    PhaseMark, PhaseTimings, PhaseTimingsBody, Request, ResponseProvenance, Scheme, StatusCode,
+    Uri, Version,
 };
 
 pub mod headers;
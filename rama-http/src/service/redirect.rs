@@ -1,12 +1,25 @@
-//! Service that redirects all requests.
+//! Services that redirect requests.
+//!
+//! - [`Redirect`] redirects all requests to a single location;
+//! - [`RedirectService`] redirects requests based on an ordered list of [`RedirectRule`]s,
+//!   e.g. to migrate an old domain or path, falling through to its inner service otherwise.
 
+use crate::matcher::{PathMatcher, UriParams};
 use crate::Request;
-use crate::{header, HeaderValue, Response, StatusCode, Uri};
-use rama_core::{Context, Service};
+use crate::{header, HeaderValue, IntoResponse, Response, StatusCode, Uri};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use rama_core::{
+    error::{ErrorContext, OpaqueError},
+    Context, Layer, Service,
+};
+use rama_net::http::RequestContext;
+use rama_utils::macros::define_inner_service_accessors;
+use serde::{Deserialize, Serialize};
 use std::{
     convert::{Infallible, TryFrom},
     fmt,
     marker::PhantomData,
+    sync::Arc,
 };
 
 /// Service that redirects all requests.
@@ -95,3 +108,528 @@ impl<ResBody> Clone for Redirect<ResBody> {
         }
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A rule used by the [`RedirectService`] to redirect matching requests.
+///
+/// The rule matches on the path of the request, using the [`PathMatcher`] syntax
+/// (e.g. `/old/:id/*`), and optionally on the host of the request.
+/// The target is a template in which `{name}` is replaced by the captured
+/// path parameter of that name and `{*}` by the captured glob (including its leading `/`).
+///
+/// Rules are [`Deserialize`], such that redirect tables can be loaded from configuration:
+///
+/// ```yaml
+/// - host: old-domain.com
+///   path: /*
+///   target: https://new-domain.com{*}
+///   status: 308
+/// ```
+pub struct RedirectRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    path: String,
+    target: String,
+    #[serde(default = "default_redirect_status")]
+    status: u16,
+    #[serde(default = "default_preserve_query")]
+    preserve_query: bool,
+}
+
+const fn default_redirect_status() -> u16 {
+    StatusCode::PERMANENT_REDIRECT.as_u16()
+}
+
+const fn default_preserve_query() -> bool {
+    true
+}
+
+impl RedirectRule {
+    /// Create a new [`RedirectRule`] which redirects requests matching the
+    /// given path pattern to the given target template,
+    /// using a [`308 Permanent Redirect`][mdn] status code.
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/308
+    pub fn new(path: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            host: None,
+            path: path.into(),
+            target: target.into(),
+            status: default_redirect_status(),
+            preserve_query: default_preserve_query(),
+        }
+    }
+
+    /// Only match requests for the given host (case-insensitive).
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Only match requests for the given host (case-insensitive).
+    pub fn set_host(&mut self, host: impl Into<String>) -> &mut Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Redirect using the given (3xx) status code.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status.as_u16();
+        self
+    }
+
+    /// Redirect using the given (3xx) status code.
+    pub fn set_status(&mut self, status: StatusCode) -> &mut Self {
+        self.status = status.as_u16();
+        self
+    }
+
+    /// Define whether or not the query of the original request is appended
+    /// to the target, which is the default.
+    pub fn with_preserve_query(mut self, preserve: bool) -> Self {
+        self.preserve_query = preserve;
+        self
+    }
+
+    /// Define whether or not the query of the original request is appended
+    /// to the target, which is the default.
+    pub fn set_preserve_query(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_query = preserve;
+        self
+    }
+}
+
+/// Characters percent-encoded when substituting a path parameter in a target.
+const TARGET_PARAM_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+#[derive(Debug, Clone)]
+enum TargetFragment {
+    Literal(String),
+    Param(String),
+    Glob,
+}
+
+#[derive(Debug)]
+struct CompiledRedirectRule {
+    host: Option<String>,
+    path: PathMatcher,
+    target: Vec<TargetFragment>,
+    status: StatusCode,
+    preserve_query: bool,
+}
+
+impl CompiledRedirectRule {
+    fn try_new(rule: RedirectRule) -> Result<Self, OpaqueError> {
+        let status = StatusCode::from_u16(rule.status).context("redirect rule status")?;
+        if !status.is_redirection() {
+            return Err(OpaqueError::from_display(format!(
+                "redirect rule for path '{}': not a redirection status code: {status}",
+                rule.path
+            )));
+        }
+
+        let path_pattern = rule.path.trim().trim_matches('/');
+        let has_glob = path_pattern.ends_with('*');
+        let target = parse_target(&rule.target)?;
+        for fragment in &target {
+            let defined = match fragment {
+                TargetFragment::Literal(_) => true,
+                TargetFragment::Param(name) => path_pattern
+                    .split('/')
+                    .any(|segment| segment.strip_prefix(':') == Some(name)),
+                TargetFragment::Glob => has_glob,
+            };
+            if !defined {
+                return Err(OpaqueError::from_display(format!(
+                    "redirect rule for path '{}': target '{}' references an undefined capture",
+                    rule.path, rule.target
+                )));
+            }
+        }
+
+        let compiled = Self {
+            host: rule.host,
+            path: PathMatcher::new(&rule.path),
+            target,
+            status,
+            preserve_query: rule.preserve_query,
+        };
+        if compiled.loops() {
+            return Err(OpaqueError::from_display(format!(
+                "redirect rule for path '{}': target '{}' matches its own rule (redirect loop)",
+                rule.path, rule.target
+            )));
+        }
+        Ok(compiled)
+    }
+
+    /// Detect whether the target (with placeholder captures)
+    /// would be matched by this rule itself again.
+    fn loops(&self) -> bool {
+        let sample: String = self
+            .target
+            .iter()
+            .map(|fragment| match fragment {
+                TargetFragment::Literal(literal) => literal.as_str(),
+                TargetFragment::Param(_) => "x",
+                TargetFragment::Glob => "",
+            })
+            .collect();
+        let Ok(uri) = sample.parse::<Uri>() else {
+            return false;
+        };
+        let same_host = match (uri.host(), self.host.as_deref()) {
+            (None, _) => true,
+            (Some(target_host), Some(host)) => target_host.eq_ignore_ascii_case(host),
+            // unknown which hosts are served by the fallthrough service
+            (Some(_), None) => false,
+        };
+        same_host && self.path.matches_path(uri.path()).is_some()
+    }
+
+    fn location(&self, params: &UriParams, query: Option<&str>) -> String {
+        let mut location = String::new();
+        for fragment in &self.target {
+            match fragment {
+                TargetFragment::Literal(literal) => location.push_str(literal),
+                TargetFragment::Param(name) => location.extend(utf8_percent_encode(
+                    params.get(name).unwrap_or_default(),
+                    TARGET_PARAM_ENCODE_SET,
+                )),
+                TargetFragment::Glob => location.push_str(params.glob().unwrap_or_default()),
+            }
+        }
+        if let Some(query) = query.filter(|q| self.preserve_query && !q.is_empty()) {
+            location.push(if location.contains('?') { '&' } else { '?' });
+            location.push_str(query);
+        }
+        location
+    }
+}
+
+fn parse_target(target: &str) -> Result<Vec<TargetFragment>, OpaqueError> {
+    let mut fragments = Vec::new();
+    let mut rest = target;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            fragments.push(TargetFragment::Literal(rest[..start].to_owned()));
+        }
+        let end = rest[start..].find('}').ok_or_else(|| {
+            OpaqueError::from_display(format!("redirect target '{target}': unclosed capture"))
+        })?;
+        let name = rest[start + 1..start + end].trim();
+        fragments.push(match name {
+            "*" => TargetFragment::Glob,
+            "" => {
+                return Err(OpaqueError::from_display(format!(
+                    "redirect target '{target}': empty capture name"
+                )))
+            }
+            name => TargetFragment::Param(name.to_lowercase()),
+        });
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        fragments.push(TargetFragment::Literal(rest.to_owned()));
+    }
+    Ok(fragments)
+}
+
+/// Layer that applies [`RedirectService`], redirecting requests
+/// based on an ordered list of [`RedirectRule`]s.
+#[derive(Debug, Clone)]
+pub struct RedirectLayer {
+    rules: Arc<Vec<CompiledRedirectRule>>,
+}
+
+impl RedirectLayer {
+    /// Create a new [`RedirectLayer`] for the given (ordered) rules.
+    ///
+    /// Fails in case a rule is invalid, such as a non-redirection status code,
+    /// a target referencing a capture not defined by the path,
+    /// or a target which would be redirected by the rule itself again (loop).
+    pub fn try_new(rules: impl IntoIterator<Item = RedirectRule>) -> Result<Self, OpaqueError> {
+        let rules = rules
+            .into_iter()
+            .map(CompiledRedirectRule::try_new)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            rules: Arc::new(rules),
+        })
+    }
+}
+
+impl<S> Layer<S> for RedirectLayer {
+    type Service = RedirectService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RedirectService {
+            inner,
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+/// Service which redirects requests based on an ordered list of [`RedirectRule`]s,
+/// where the first matching rule is used.
+///
+/// Requests not matched by any rule fall through to the inner service,
+/// use `StatusCode::NOT_FOUND.into_endpoint_service()` as inner service
+/// in case you wish to respond with a `404 Not Found` to those instead.
+///
+/// # Example
+///
+/// ```
+/// use rama_core::{Context, Layer, Service};
+/// use rama_http::service::redirect::{RedirectLayer, RedirectRule};
+/// use rama_http::service::web::IntoEndpointService;
+/// use rama_http::{header::LOCATION, Body, Request, StatusCode};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let svc = RedirectLayer::try_new([
+///     RedirectRule::new("/*", "https://new-domain.com{*}").with_host("old-domain.com"),
+/// ])?
+/// .layer(StatusCode::NOT_FOUND.into_endpoint_service());
+///
+/// let req = Request::builder()
+///     .uri("http://old-domain.com/foo/bar?q=1")
+///     .body(Body::empty())?;
+/// let resp = svc.serve(Context::default(), req).await?;
+/// assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+/// assert_eq!(resp.headers()[LOCATION], "https://new-domain.com/foo/bar?q=1");
+/// # Ok(())
+/// # }
+/// ```
+pub struct RedirectService<S> {
+    inner: S,
+    rules: Arc<Vec<CompiledRedirectRule>>,
+}
+
+impl<S> RedirectService<S> {
+    /// Create a new [`RedirectService`] for the given (ordered) rules,
+    /// falling through to the given inner service.
+    ///
+    /// See [`RedirectLayer::try_new`] for more information.
+    pub fn try_new(
+        rules: impl IntoIterator<Item = RedirectRule>,
+        inner: S,
+    ) -> Result<Self, OpaqueError> {
+        Ok(RedirectLayer::try_new(rules)?.layer(inner))
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for RedirectService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedirectService")
+            .field("inner", &self.inner)
+            .field("rules", &self.rules)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RedirectService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+impl<State, S, Body> Service<State, Request<Body>> for RedirectService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<Body>, Response = Response>,
+    Body: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<Body>,
+    ) -> Result<Self::Response, Self::Error> {
+        let mut host = None;
+        for rule in self.rules.iter() {
+            if let Some(rule_host) = rule.host.as_deref() {
+                let host = host.get_or_insert_with(|| request_host(&ctx, &req));
+                if !host
+                    .as_deref()
+                    .is_some_and(|host| host.eq_ignore_ascii_case(rule_host))
+                {
+                    continue;
+                }
+            }
+            let Some(params) = rule.path.matches_path(req.uri().path()) else {
+                continue;
+            };
+
+            let location = rule.location(&params, req.uri().query());
+            return Ok(match HeaderValue::try_from(location) {
+                Ok(location) => {
+                    tracing::trace!(?location, status = %rule.status, "redirect request");
+                    let mut res = Response::default();
+                    *res.status_mut() = rule.status;
+                    res.headers_mut().insert(header::LOCATION, location);
+                    res
+                }
+                Err(err) => {
+                    tracing::error!(error = %err, "redirect service: invalid location");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            });
+        }
+        self.inner.serve(ctx, req).await
+    }
+}
+
+fn request_host<State, Body>(ctx: &Context<State>, req: &Request<Body>) -> Option<String> {
+    match ctx.get::<RequestContext>() {
+        Some(req_ctx) => Some(req_ctx.authority.host().to_string()),
+        None => RequestContext::try_from((ctx, req))
+            .map(|req_ctx| req_ctx.authority.host().to_string())
+            .ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::web::IntoEndpointService;
+    use crate::Body;
+
+    fn redirect_service(
+        rules: impl IntoIterator<Item = RedirectRule>,
+    ) -> impl Service<(), Request, Response = Response, Error = Infallible> {
+        RedirectService::try_new(rules, StatusCode::NOT_FOUND.into_endpoint_service()).unwrap()
+    }
+
+    async fn redirect(
+        svc: &impl Service<(), Request, Response = Response, Error = Infallible>,
+        uri: &str,
+    ) -> (StatusCode, Option<String>) {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .map(|v| v.to_str().unwrap().to_owned());
+        (resp.status(), location)
+    }
+
+    #[tokio::test]
+    async fn test_redirect_service_captures() {
+        let svc = redirect_service([
+            RedirectRule::new("/*", "https://new-domain.com{*}").with_host("old-domain.com"),
+            RedirectRule::new("/users/:id/posts/:post", "/u/{id}/p/{POST}")
+                .with_status(StatusCode::MOVED_PERMANENTLY),
+            RedirectRule::new("/docs/*", "/documentation{*}").with_status(StatusCode::FOUND),
+        ]);
+
+        for (uri, expected_status, expected_location) in [
+            (
+                "http://OLD-domain.com/foo/bar",
+                StatusCode::PERMANENT_REDIRECT,
+                Some("https://new-domain.com/foo/bar"),
+            ),
+            (
+                "http://example.com/users/glen%20dc/posts/42",
+                StatusCode::MOVED_PERMANENTLY,
+                Some("/u/glen%20dc/p/42"),
+            ),
+            (
+                "http://example.com/docs/guide/intro",
+                StatusCode::FOUND,
+                Some("/documentation/guide/intro"),
+            ),
+            ("http://example.com/users/1", StatusCode::NOT_FOUND, None),
+        ] {
+            let (status, location) = redirect(&svc, uri).await;
+            assert_eq!(status, expected_status, "uri: {uri}");
+            assert_eq!(location.as_deref(), expected_location, "uri: {uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redirect_service_query() {
+        let svc = redirect_service([
+            RedirectRule::new("/old", "/new"),
+            RedirectRule::new("/search", "/find?v=2"),
+            RedirectRule::new("/drop", "/dropped").with_preserve_query(false),
+        ]);
+
+        for (uri, expected_location) in [
+            ("http://example.com/old?a=1&b=2", "/new?a=1&b=2"),
+            ("http://example.com/old", "/new"),
+            ("http://example.com/search?q=rama", "/find?v=2&q=rama"),
+            ("http://example.com/drop?q=rama", "/dropped"),
+        ] {
+            let (_, location) = redirect(&svc, uri).await;
+            assert_eq!(location.as_deref(), Some(expected_location), "uri: {uri}");
+        }
+    }
+
+    #[test]
+    fn test_redirect_rule_validation() {
+        for rule in [
+            // loops
+            RedirectRule::new("/*", "/new{*}"),
+            RedirectRule::new("/a/:id", "/a/{id}"),
+            RedirectRule::new("/*", "http://example.com/new{*}").with_host("example.com"),
+            // undefined captures
+            RedirectRule::new("/a", "/b/{id}"),
+            RedirectRule::new("/a", "/b{*}"),
+            RedirectRule::new("/a/:id", "/b/{id"),
+            // not a redirection
+            RedirectRule::new("/a", "/b").with_status(StatusCode::OK),
+        ] {
+            assert!(RedirectLayer::try_new([rule.clone()]).is_err(), "{rule:?}");
+        }
+
+        for rule in [
+            // other host, unknown whether it is served by this service
+            RedirectRule::new("/*", "https://new-domain.com{*}"),
+            RedirectRule::new("/*", "https://new-domain.com{*}").with_host("old-domain.com"),
+            RedirectRule::new("/a/:id", "/b/{id}"),
+            RedirectRule::new("/a/*", "/b{*}"),
+        ] {
+            assert!(RedirectLayer::try_new([rule.clone()]).is_ok(), "{rule:?}");
+        }
+    }
+
+    #[test]
+    fn test_redirect_rules_deserialize() {
+        let rules: Vec<RedirectRule> = serde_json::from_str(
+            r#"[
+                {"host": "old-domain.com", "path": "/*", "target": "https://new-domain.com{*}"},
+                {"path": "/a", "target": "/b", "status": 302, "preserve_query": false}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules,
+            [
+                RedirectRule::new("/*", "https://new-domain.com{*}").with_host("old-domain.com"),
+                RedirectRule::new("/a", "/b")
+                    .with_status(StatusCode::FOUND)
+                    .with_preserve_query(false),
+            ]
+        );
+        assert!(RedirectLayer::try_new(rules).is_ok());
+    }
+}
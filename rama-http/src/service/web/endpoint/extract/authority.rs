@@ -30,13 +30,18 @@ where
         ctx: &Context<S>,
         parts: &Parts,
     ) -> Result<Self, Self::Rejection> {
-        Ok(Authority(match ctx.get::<RequestContext>() {
+        let authority = match ctx.get::<RequestContext>() {
             Some(ctx) => ctx.authority.clone(),
-            None => RequestContext::try_from((ctx, parts))
-                .map_err(|_| MissingAuthority)?
-                .authority
-                .clone(),
-        }))
+            None => {
+                RequestContext::try_from((ctx, parts))
+                    .map_err(|_| MissingAuthority)?
+                    .authority
+            }
+        };
+        // the port is unknown for a custom protocol without a default port
+        Ok(Authority(
+            authority.try_into().map_err(|_| MissingAuthority)?,
+        ))
     }
 }
 
@@ -0,0 +1,232 @@
+use super::BytesRejection;
+use crate::dep::http_body_util::BodyExt;
+use crate::service::web::extract::FromRequest;
+use crate::utils::macros::{composite_http_rejection, define_http_rejection};
+use crate::{HeaderMap, HeaderName, Request};
+use base64::Engine as _;
+use rama_utils::macros::impl_deref;
+use sha2::{Digest as _, Sha256};
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+static DIGEST: HeaderName = HeaderName::from_static("digest");
+static CONTENT_MD5: HeaderName = HeaderName::from_static("content-md5");
+
+/// Extractor to get the request body, collected as [`Bytes`],
+/// of which the checksum is verified against the checksum found in the request headers.
+///
+/// The checksum is read from the `Digest` header (`sha-256` or `md5`),
+/// or from the `Content-MD5` header, where `sha-256` is preferred if both are available.
+/// The checksum is computed while streaming the body, and the request is rejected
+/// with a `400 Bad Request` if it does not match, or if no supported checksum was found.
+///
+/// [`Bytes`]: https://docs.rs/bytes/latest/bytes/struct.Bytes.html
+#[derive(Debug, Clone)]
+pub struct VerifiedBytes(pub bytes::Bytes);
+
+impl_deref!(VerifiedBytes: bytes::Bytes);
+
+define_http_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "Request is missing a supported checksum: expected a `Digest` (sha-256 or md5) or `Content-MD5` header"]
+    /// Rejection type used if the [`VerifiedBytes`] extractor
+    /// cannot find a supported checksum in the request headers.
+    pub struct MissingChecksum;
+}
+
+define_http_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "Request checksum header is invalid"]
+    /// Rejection type used if the [`VerifiedBytes`] extractor
+    /// cannot decode the checksum found in the request headers.
+    pub struct InvalidChecksum(Error);
+}
+
+define_http_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "Request body does not match its checksum"]
+    /// Rejection type used if the checksum of the request body
+    /// does not match the checksum found in the request headers.
+    pub struct ChecksumMismatch;
+}
+
+composite_http_rejection! {
+    /// Rejection used for [`VerifiedBytes`]
+    ///
+    /// Contains one variant for each way the [`VerifiedBytes`] extractor
+    /// can fail.
+    pub enum VerifiedBytesRejection {
+        MissingChecksum,
+        InvalidChecksum,
+        ChecksumMismatch,
+        BytesRejection,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChecksumAlgorithm {
+    Sha256,
+    Md5,
+}
+
+impl ChecksumAlgorithm {
+    fn digest_len(self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Md5 => 16,
+        }
+    }
+}
+
+/// Find the preferred checksum in the headers, returning the encoded value.
+fn find_checksum(headers: &HeaderMap) -> Option<(ChecksumAlgorithm, &str)> {
+    let mut md5 = None;
+    for value in headers.get_all(&DIGEST) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for digest in value.split(',') {
+            let Some((algorithm, checksum)) = digest.trim().split_once('=') else {
+                continue;
+            };
+            if algorithm.eq_ignore_ascii_case("sha-256") {
+                return Some((ChecksumAlgorithm::Sha256, checksum.trim()));
+            }
+            if algorithm.eq_ignore_ascii_case("md5") {
+                md5 = Some(checksum.trim());
+            }
+        }
+    }
+    md5.or_else(|| headers.get(&CONTENT_MD5)?.to_str().ok().map(str::trim))
+        .map(|checksum| (ChecksumAlgorithm::Md5, checksum))
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Md5(md5::Context),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Md5 => Self::Md5(md5::Context::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Md5(hasher) => hasher.consume(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Md5(hasher) => hasher.compute().0.to_vec(),
+        }
+    }
+}
+
+impl FromRequest for VerifiedBytes {
+    type Rejection = VerifiedBytesRejection;
+
+    async fn from_request(req: Request) -> Result<Self, Self::Rejection> {
+        let (algorithm, expected) = find_checksum(req.headers()).ok_or(MissingChecksum)?;
+        let expected = BASE64.decode(expected).map_err(InvalidChecksum::from_err)?;
+        if expected.len() != algorithm.digest_len() {
+            return Err(InvalidChecksum::from_display(format!(
+                "unexpected checksum length: {}",
+                expected.len()
+            ))
+            .into());
+        }
+
+        let mut hasher = Hasher::new(algorithm);
+        let mut buf = bytes::BytesMut::new();
+        let mut body = req.into_body();
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(BytesRejection::from_err)?;
+            if let Some(data) = frame.data_ref() {
+                hasher.update(data);
+                buf.extend_from_slice(data);
+            }
+        }
+
+        if hasher.finalize() != expected {
+            return Err(ChecksumMismatch.into());
+        }
+        Ok(Self(buf.freeze()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::web::WebService;
+    use crate::{Method, StatusCode};
+    use rama_core::{Context, Service};
+    use std::convert::Infallible;
+
+    fn service() -> impl Service<(), Request, Response = crate::Response, Error = Infallible> {
+        WebService::default().post("/", |VerifiedBytes(body): VerifiedBytes| async move {
+            assert_eq!(body, "test");
+        })
+    }
+
+    async fn status(header: &'static str, value: &'static str) -> StatusCode {
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(header, value)
+            .body("test".into())
+            .unwrap();
+        service()
+            .serve(Context::default(), req)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_verified_bytes_match() {
+        // base64 encoded digests of "test"
+        for (header, value) in [
+            ("digest", "sha-256=n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg="),
+            ("digest", "SHA-256=n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg="),
+            ("digest", "md5=CY9rzUYh03PK3k6DJie09g=="),
+            (
+                "digest",
+                "unixsum=30637, md5=CY9rzUYh03PK3k6DJie09g==, sha-256=n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg=",
+            ),
+            ("content-md5", "CY9rzUYh03PK3k6DJie09g=="),
+        ] {
+            assert_eq!(status(header, value).await, StatusCode::OK, "{header}: {value}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verified_bytes_mismatch() {
+        for (header, value) in [
+            // digests of "tesT"
+            ("digest", "sha-256=5jdjoJbxRsVY7f4+mVrn4qzQeiSEZgYC8FB9bEuJFTU="),
+            ("content-md5", "MmGFzxp1GvLNN3Qn1jr7fg=="),
+            // sha-256 is preferred over md5
+            (
+                "digest",
+                "md5=CY9rzUYh03PK3k6DJie09g==, sha-256=5jdjoJbxRsVY7f4+mVrn4qzQeiSEZgYC8FB9bEuJFTU=",
+            ),
+            // invalid or missing checksums
+            ("digest", "sha-256=not-base64!"),
+            ("digest", "sha-256=CY9rzUYh03PK3k6DJie09g=="),
+            ("digest", "unixsum=30637"),
+            ("x-checksum", "CY9rzUYh03PK3k6DJie09g=="),
+        ] {
+            assert_eq!(
+                status(header, value).await,
+                StatusCode::BAD_REQUEST,
+                "{header}: {value}"
+            );
+        }
+    }
+}
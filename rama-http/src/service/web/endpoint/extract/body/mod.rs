@@ -23,6 +23,14 @@ mod form;
 #[doc(inline)]
 pub use form::*;
 
+mod trailers;
+#[doc(inline)]
+pub use trailers::*;
+
+mod checksum;
+#[doc(inline)]
+pub use checksum::*;
+
 /// Extractor to get the response body.
 #[derive(Debug)]
 pub struct Body(pub http::Body);
@@ -0,0 +1,81 @@
+use crate::service::web::extract::FromRequest;
+use crate::utils::macros::define_http_rejection;
+use crate::{HeaderMap, Request};
+use rama_core::error::OpaqueError;
+use rama_utils::macros::impl_deref;
+
+/// Extractor to get the trailers of the request body,
+/// awaiting the end of the body.
+///
+/// The data of the request body is consumed and discarded.
+/// Use the [`Body`] extractor in case you need both the data and the trailers.
+///
+/// The [`HeaderMap`] is empty in case the request body had no trailers.
+///
+/// [`Body`]: super::Body
+#[derive(Debug, Clone)]
+pub struct Trailers(pub HeaderMap);
+
+impl_deref!(Trailers: HeaderMap);
+
+define_http_rejection! {
+    #[status = BAD_REQUEST]
+    #[body = "Request Body failed to be read until its trailers"]
+    /// Rejection type used when the [`Trailers`] extractor fails to read the request body.
+    pub struct TrailersRejection(Error);
+}
+
+impl FromRequest for Trailers {
+    type Rejection = TrailersRejection;
+
+    async fn from_request(req: Request) -> Result<Self, Self::Rejection> {
+        req.into_body()
+            .trailers()
+            .await
+            .map_err(|err| TrailersRejection::from_err(OpaqueError::from_boxed(err)))
+            .map(|trailers| Self(trailers.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dep::http_body::Frame;
+    use crate::dep::http_body_util::StreamBody;
+    use crate::service::web::WebService;
+    use crate::{Body, HeaderValue, Method, StatusCode};
+    use rama_core::{Context, Service};
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn test_trailers() {
+        let service = WebService::default().post("/", |Trailers(trailers): Trailers| async move {
+            assert_eq!(trailers.get("x-checksum").unwrap(), "abc");
+        });
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+        let body = Body::new(StreamBody::new(futures_lite::stream::iter([
+            Ok::<_, Infallible>(Frame::data(bytes::Bytes::from_static(b"test"))),
+            Ok(Frame::trailers(trailers)),
+        ])));
+
+        let req = Request::builder().method(Method::POST).body(body).unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_trailers_missing() {
+        let service = WebService::default().post("/", |Trailers(trailers): Trailers| async move {
+            assert!(trailers.is_empty());
+        });
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .body("test".into())
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
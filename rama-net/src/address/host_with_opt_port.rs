@@ -0,0 +1,141 @@
+use super::{Authority, Host};
+use rama_core::error::OpaqueError;
+use std::{fmt, net::IpAddr};
+
+/// A [`Host`] with an optional port.
+///
+/// Unlike an [`Authority`] the port is not required,
+/// e.g. for a custom protocol without a known default port,
+/// for which the port was not explicitly specified either.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HostWithOptPort {
+    host: Host,
+    port: Option<u16>,
+}
+
+impl HostWithOptPort {
+    /// Creates a new [`HostWithOptPort`].
+    pub const fn new(host: Host, port: Option<u16>) -> Self {
+        Self { host, port }
+    }
+
+    /// Gets the [`Host`] reference.
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// Consumes the [`HostWithOptPort`] and returns the [`Host`].
+    pub fn into_host(self) -> Host {
+        self.host
+    }
+
+    /// Gets the port, if known.
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// Consume self into its parts: `(host, port)`
+    pub fn into_parts(self) -> (Host, Option<u16>) {
+        (self.host, self.port)
+    }
+}
+
+impl From<Host> for HostWithOptPort {
+    fn from(host: Host) -> Self {
+        Self { host, port: None }
+    }
+}
+
+impl From<(Host, u16)> for HostWithOptPort {
+    fn from((host, port): (Host, u16)) -> Self {
+        Self {
+            host,
+            port: Some(port),
+        }
+    }
+}
+
+impl From<Authority> for HostWithOptPort {
+    fn from(authority: Authority) -> Self {
+        let (host, port) = authority.into_parts();
+        (host, port).into()
+    }
+}
+
+impl From<HostWithOptPort> for Host {
+    fn from(value: HostWithOptPort) -> Self {
+        value.host
+    }
+}
+
+impl TryFrom<HostWithOptPort> for Authority {
+    type Error = OpaqueError;
+
+    fn try_from(value: HostWithOptPort) -> Result<Self, Self::Error> {
+        match value.port {
+            Some(port) => Ok((value.host, port).into()),
+            None => Err(OpaqueError::from_display(format!(
+                "missing port for host '{}'",
+                value.host
+            ))),
+        }
+    }
+}
+
+impl PartialEq<Authority> for HostWithOptPort {
+    fn eq(&self, other: &Authority) -> bool {
+        self.port == Some(other.port()) && &self.host == other.host()
+    }
+}
+
+impl fmt::Display for HostWithOptPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.host, self.port) {
+            (Host::Address(IpAddr::V6(ip)), Some(port)) => write!(f, "[{ip}]:{port}"),
+            (host, Some(port)) => write!(f, "{host}:{port}"),
+            (host, None) => host.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_with_opt_port_display() {
+        for (value, expected) in [
+            (
+                HostWithOptPort::new(Host::try_from("example.com").unwrap(), Some(8080)),
+                "example.com:8080",
+            ),
+            (
+                HostWithOptPort::new(Host::try_from("example.com").unwrap(), None),
+                "example.com",
+            ),
+            (
+                HostWithOptPort::new(Host::try_from("::1").unwrap(), Some(443)),
+                "[::1]:443",
+            ),
+            (
+                HostWithOptPort::new(Host::try_from("127.0.0.1").unwrap(), None),
+                "127.0.0.1",
+            ),
+        ] {
+            assert_eq!(value.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_host_with_opt_port_into_authority() {
+        let host = Host::try_from("example.com").unwrap();
+
+        let authority: Authority = HostWithOptPort::new(host.clone(), Some(80))
+            .try_into()
+            .unwrap();
+        assert_eq!(authority, Authority::new(host.clone(), 80));
+        assert_eq!(HostWithOptPort::from(authority.clone()), authority);
+
+        assert!(Authority::try_from(HostWithOptPort::from(host)).is_err());
+    }
+}
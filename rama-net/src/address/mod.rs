@@ -19,6 +19,10 @@ mod authority;
 #[doc(inline)]
 pub use authority::Authority;
 
+mod host_with_opt_port;
+#[doc(inline)]
+pub use host_with_opt_port::HostWithOptPort;
+
 mod socket_address;
 #[doc(inline)]
 pub use socket_address::SocketAddress;
@@ -30,6 +34,10 @@ mod parse_utils;
 #[doc(inline)]
 pub use proxy::ProxyAddress;
 
+mod no_proxy;
+#[doc(inline)]
+pub use no_proxy::NoProxy;
+
 mod domain_address;
 #[doc(inline)]
 pub use domain_address::DomainAddress;
@@ -0,0 +1,370 @@
+use super::{parse_utils, Authority, Domain, Host};
+use rama_core::error::{ErrorContext, OpaqueError};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+#[derive(Debug, Clone, Default)]
+/// A matcher of destinations which are to be connected to directly,
+/// bypassing any configured proxy, following the `NO_PROXY` conventions.
+///
+/// It is created from a list of patterns, each of which is one of:
+///
+/// - `*`: matches every destination;
+/// - a domain, e.g. `example.com`: matches that domain and all its subdomains;
+/// - a domain with a leading dot, e.g. `.example.com`: matches only the subdomains of that domain;
+/// - an IP address, e.g. `127.0.0.1` or `::1`: matches exactly that IP address;
+/// - a CIDR range, e.g. `10.0.0.0/8` or `fd00::/8`: matches all IP addresses within that range.
+///
+/// Domains and IP addresses can be followed by a port (e.g. `example.com:8080`,
+/// `[::1]:8080`), in which case they only match destinations using that port.
+///
+/// Parse it from a comma-separated list of patterns (e.g. the value of a `NO_PROXY`
+/// environment variable) using [`FromStr`] or from a list of patterns using [`NoProxy::from_patterns`].
+pub struct NoProxy {
+    match_all: bool,
+    rules: Vec<NoProxyRule>,
+}
+
+#[derive(Debug, Clone)]
+enum NoProxyRule {
+    Domain {
+        domain: Domain,
+        subdomains_only: bool,
+        port: Option<u16>,
+    },
+    Ip {
+        ip: IpAddr,
+        port: Option<u16>,
+    },
+    Cidr {
+        network: IpAddr,
+        prefix: u8,
+    },
+}
+
+impl NoProxy {
+    /// Create a new [`NoProxy`] which does not match any destination.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new [`NoProxy`] from the given list of patterns.
+    ///
+    /// Empty patterns are ignored, while invalid patterns return an error.
+    pub fn from_patterns<I>(patterns: I) -> Result<Self, OpaqueError>
+    where
+        I: IntoIterator<Item: AsRef<str>>,
+    {
+        let mut no_proxy = Self::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref().trim();
+            if pattern.is_empty() {
+                continue;
+            }
+            if pattern == "*" {
+                no_proxy.match_all = true;
+                continue;
+            }
+            let rule = NoProxyRule::parse(pattern)
+                .with_context(|| format!("parse no proxy pattern '{pattern}'"))?;
+            no_proxy.rules.push(rule);
+        }
+        Ok(no_proxy)
+    }
+
+    /// Returns `true` if this [`NoProxy`] does not match any destination.
+    pub fn is_empty(&self) -> bool {
+        !self.match_all && self.rules.is_empty()
+    }
+
+    /// Returns `true` if the given [`Authority`] is to be connected to directly,
+    /// bypassing the proxy.
+    pub fn matches(&self, authority: &Authority) -> bool {
+        self.match_all || self.rules.iter().any(|rule| rule.matches(authority))
+    }
+}
+
+impl NoProxyRule {
+    fn parse(pattern: &str) -> Result<Self, OpaqueError> {
+        if let Some((network, prefix)) = pattern.split_once('/') {
+            let network = parse_utils::try_to_parse_str_to_ip(network)
+                .context("parse CIDR network as IP address")?;
+            let prefix: u8 = prefix.parse().context("parse CIDR prefix length")?;
+            let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+            if prefix > max_prefix {
+                return Err(OpaqueError::from_display(format!(
+                    "CIDR prefix length {prefix} exceeds {max_prefix}"
+                )));
+            }
+            return Ok(Self::Cidr { network, prefix });
+        }
+
+        if let Some(ip) = parse_utils::try_to_parse_str_to_ip(pattern) {
+            return Ok(Self::Ip { ip, port: None });
+        }
+
+        let (host, port) = match parse_utils::split_port_from_str(pattern) {
+            Ok((host, port)) => (host, Some(port)),
+            Err(_) => (pattern, None),
+        };
+
+        if let Some(ip) = parse_utils::try_to_parse_str_to_ip(host) {
+            return Ok(Self::Ip { ip, port });
+        }
+
+        let (host, subdomains_only) = match host.strip_prefix('.') {
+            Some(host) => (host, true),
+            None => (host, false),
+        };
+        let domain = Domain::try_from(host.to_owned()).context("parse domain")?;
+        Ok(Self::Domain {
+            domain,
+            subdomains_only,
+            port,
+        })
+    }
+
+    fn matches(&self, authority: &Authority) -> bool {
+        match (self, authority.host()) {
+            (
+                Self::Domain {
+                    domain,
+                    subdomains_only,
+                    port,
+                },
+                Host::Name(target),
+            ) => {
+                port.is_none_or(|port| port == authority.port())
+                    && target.is_sub_of(domain)
+                    && !(*subdomains_only && target.is_parent_of(domain))
+            }
+            (Self::Ip { ip, port }, Host::Address(target)) => {
+                port.is_none_or(|port| port == authority.port()) && ip == target
+            }
+            (Self::Cidr { network, prefix }, Host::Address(target)) => {
+                cidr_contains(*network, *prefix, *target)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn cidr_contains(network: IpAddr, prefix: u8, ip: IpAddr) -> bool {
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or_default();
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - prefix as u32)
+                .unwrap_or_default();
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+impl FromStr for NoProxy {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_patterns(s.split(','))
+    }
+}
+
+impl TryFrom<&str> for NoProxy {
+    type Error = OpaqueError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for NoProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        if self.match_all {
+            f.write_str("*")?;
+            sep = ",";
+        }
+        for rule in &self.rules {
+            f.write_str(sep)?;
+            sep = ",";
+            match rule {
+                NoProxyRule::Domain {
+                    domain,
+                    subdomains_only,
+                    port,
+                } => {
+                    if *subdomains_only {
+                        f.write_str(".")?;
+                    }
+                    write!(f, "{domain}")?;
+                    if let Some(port) = port {
+                        write!(f, ":{port}")?;
+                    }
+                }
+                NoProxyRule::Ip { ip, port } => match (ip, port) {
+                    (IpAddr::V6(ip), Some(port)) => write!(f, "[{ip}]:{port}")?,
+                    (ip, Some(port)) => write!(f, "{ip}:{port}")?,
+                    (ip, None) => write!(f, "{ip}")?,
+                },
+                NoProxyRule::Cidr { network, prefix } => write!(f, "{network}/{prefix}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matches(no_proxy: &NoProxy, authorities: &[&str], expected: bool) {
+        for authority in authorities {
+            assert_eq!(
+                no_proxy.matches(&authority.parse().unwrap()),
+                expected,
+                "no_proxy = '{no_proxy}'; authority = '{authority}'",
+            );
+        }
+    }
+
+    #[test]
+    fn test_no_proxy_domain() {
+        let no_proxy: NoProxy = "example.com".parse().unwrap();
+        assert_matches(
+            &no_proxy,
+            &["example.com:80", "EXAMPLE.com:443", "www.example.com:80"],
+            true,
+        );
+        assert_matches(
+            &no_proxy,
+            &["example.org:80", "notexample.com:80", "127.0.0.1:80"],
+            false,
+        );
+    }
+
+    #[test]
+    fn test_no_proxy_domain_suffix() {
+        let no_proxy: NoProxy = ".example.com".parse().unwrap();
+        assert_matches(
+            &no_proxy,
+            &["www.example.com:80", "a.b.example.com:443"],
+            true,
+        );
+        assert_matches(
+            &no_proxy,
+            &["example.com:80", "notexample.com:80", "example.org:80"],
+            false,
+        );
+    }
+
+    #[test]
+    fn test_no_proxy_port() {
+        let no_proxy: NoProxy = "example.com:8080, 10.0.0.1:443, [::1]:8443"
+            .parse()
+            .unwrap();
+        assert_matches(
+            &no_proxy,
+            &[
+                "example.com:8080",
+                "api.example.com:8080",
+                "10.0.0.1:443",
+                "[::1]:8443",
+            ],
+            true,
+        );
+        assert_matches(
+            &no_proxy,
+            &["example.com:80", "10.0.0.1:80", "[::1]:80"],
+            false,
+        );
+    }
+
+    #[test]
+    fn test_no_proxy_ip() {
+        let no_proxy: NoProxy = "127.0.0.1,::1".parse().unwrap();
+        assert_matches(&no_proxy, &["127.0.0.1:80", "[::1]:443"], true);
+        assert_matches(
+            &no_proxy,
+            &["127.0.0.2:80", "[::2]:80", "localhost:80"],
+            false,
+        );
+    }
+
+    #[test]
+    fn test_no_proxy_cidr() {
+        let no_proxy: NoProxy = "10.0.0.0/8, 192.168.1.0/24, fd00::/8, 0.0.0.0/32"
+            .parse()
+            .unwrap();
+        assert_matches(
+            &no_proxy,
+            &[
+                "10.0.0.1:80",
+                "10.255.255.255:443",
+                "192.168.1.42:80",
+                "[fd12:3456::1]:80",
+                "0.0.0.0:80",
+            ],
+            true,
+        );
+        assert_matches(
+            &no_proxy,
+            &[
+                "11.0.0.1:80",
+                "192.168.2.1:80",
+                "[fe80::1]:80",
+                "example.com:80",
+            ],
+            false,
+        );
+
+        let no_proxy: NoProxy = "0.0.0.0/0".parse().unwrap();
+        assert_matches(&no_proxy, &["1.2.3.4:80", "255.255.255.255:80"], true);
+        assert_matches(&no_proxy, &["[::1]:80", "example.com:80"], false);
+    }
+
+    #[test]
+    fn test_no_proxy_wildcard() {
+        let no_proxy: NoProxy = "*".parse().unwrap();
+        assert_matches(
+            &no_proxy,
+            &["example.com:80", "127.0.0.1:443", "[::1]:8080"],
+            true,
+        );
+    }
+
+    #[test]
+    fn test_no_proxy_empty() {
+        for s in ["", " , ,"] {
+            let no_proxy: NoProxy = s.parse().unwrap();
+            assert!(no_proxy.is_empty());
+            assert_matches(&no_proxy, &["example.com:80", "127.0.0.1:80"], false);
+        }
+    }
+
+    #[test]
+    fn test_no_proxy_invalid() {
+        for s in [
+            "10.0.0.0/33",
+            "::/129",
+            "foo/8",
+            "10.0.0.0/x",
+            "exa mple.com",
+        ] {
+            assert!(s.parse::<NoProxy>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_no_proxy_display() {
+        let no_proxy: NoProxy = " *, .example.com ,example.org:8080,::1,[::1]:80,10.0.0.0/8"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            no_proxy.to_string(),
+            "*,.example.com,example.org:8080,::1,[::1]:80,10.0.0.0/8"
+        );
+    }
+}
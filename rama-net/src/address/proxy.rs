@@ -37,15 +37,7 @@ impl TryFrom<&str> for ProxyAddress {
 
                 let authority: Authority = slice[i + 1..]
                     .try_into()
-                    .or_else(|_| {
-                        Host::try_from(&slice[i + 1..]).map(|h| {
-                            (
-                                h,
-                                protocol.as_ref().unwrap_or(&Protocol::HTTP).default_port(),
-                            )
-                                .into()
-                        })
-                    })
+                    .or_else(|_| authority_with_default_port(&slice[i + 1..], protocol.as_ref()))
                     .context("parse proxy authority from address")?;
 
                 return Ok(ProxyAddress {
@@ -58,15 +50,7 @@ impl TryFrom<&str> for ProxyAddress {
 
         let authority: Authority = slice
             .try_into()
-            .or_else(|_| {
-                Host::try_from(slice).map(|h| {
-                    (
-                        h,
-                        protocol.as_ref().unwrap_or(&Protocol::HTTP).default_port(),
-                    )
-                        .into()
-                })
-            })
+            .or_else(|_| authority_with_default_port(slice, protocol.as_ref()))
             .context("parse proxy authority from address")?;
         Ok(ProxyAddress {
             protocol,
@@ -76,6 +60,22 @@ impl TryFrom<&str> for ProxyAddress {
     }
 }
 
+/// Parse the given bytes as a [`Host`], using the default port of the given
+/// [`Protocol`] (http if none is defined) to turn it into an [`Authority`].
+fn authority_with_default_port(
+    slice: &[u8],
+    protocol: Option<&Protocol>,
+) -> Result<Authority, OpaqueError> {
+    let host = Host::try_from(slice)?;
+    let protocol = protocol.unwrap_or(&Protocol::HTTP);
+    let port = protocol.default_port().ok_or_else(|| {
+        OpaqueError::from_display(format!(
+            "no default port known for proxy protocol '{protocol}': specify a port"
+        ))
+    })?;
+    Ok((host, port).into())
+}
+
 impl TryFrom<String> for ProxyAddress {
     type Error = OpaqueError;
 
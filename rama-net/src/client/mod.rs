@@ -3,3 +3,7 @@
 mod conn;
 #[doc(inline)]
 pub use conn::{ConnectorService, EstablishedClientConnection};
+
+mod reconnect;
+#[doc(inline)]
+pub use reconnect::{ReconnectConnector, ReconnectConnectorLayer};
@@ -0,0 +1,188 @@
+use super::{ConnectorService, EstablishedClientConnection};
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::{backoff::Backoff, macros::define_inner_service_accessors};
+use std::fmt;
+
+/// A [`Layer`] which wraps a connector in a [`ReconnectConnector`],
+/// retrying failed connection attempts using a [`Backoff`] strategy.
+pub struct ReconnectConnectorLayer<B> {
+    backoff: B,
+}
+
+impl<B: fmt::Debug> fmt::Debug for ReconnectConnectorLayer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectConnectorLayer")
+            .field("backoff", &self.backoff)
+            .finish()
+    }
+}
+
+impl<B: Clone> Clone for ReconnectConnectorLayer<B> {
+    fn clone(&self) -> Self {
+        Self {
+            backoff: self.backoff.clone(),
+        }
+    }
+}
+
+impl<B> ReconnectConnectorLayer<B> {
+    /// Create a new [`ReconnectConnectorLayer`] using the given [`Backoff`] strategy,
+    /// e.g. the same [`ExponentialBackoff`] as used by the retry layer of `rama-http`.
+    ///
+    /// [`ExponentialBackoff`]: rama_utils::backoff::ExponentialBackoff
+    pub const fn new(backoff: B) -> Self {
+        Self { backoff }
+    }
+}
+
+impl<S, B: Clone> Layer<S> for ReconnectConnectorLayer<B> {
+    type Service = ReconnectConnector<S, B>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ReconnectConnector::new(inner, self.backoff.clone())
+    }
+}
+
+/// A connector which retries failed connection attempts of the inner connector,
+/// waiting in between attempts as instructed by its [`Backoff`] strategy.
+///
+/// Each connection establishment uses its own clone of the [`Backoff`],
+/// such that concurrent connections do not influence each other's schedule.
+/// The error of the last attempt is returned once the [`Backoff`] gives up.
+pub struct ReconnectConnector<S, B> {
+    inner: S,
+    backoff: B,
+}
+
+impl<S: fmt::Debug, B: fmt::Debug> fmt::Debug for ReconnectConnector<S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectConnector")
+            .field("inner", &self.inner)
+            .field("backoff", &self.backoff)
+            .finish()
+    }
+}
+
+impl<S: Clone, B: Clone> Clone for ReconnectConnector<S, B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            backoff: self.backoff.clone(),
+        }
+    }
+}
+
+impl<S, B> ReconnectConnector<S, B> {
+    /// Create a new [`ReconnectConnector`] wrapping the given connector,
+    /// using the given [`Backoff`] strategy in between failed attempts.
+    pub const fn new(inner: S, backoff: B) -> Self {
+        Self { inner, backoff }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S, B, State, Request> Service<State, Request> for ReconnectConnector<S, B>
+where
+    S: ConnectorService<State, Request, Connection: Send>,
+    B: Backoff + Clone,
+    State: Clone + Send + Sync + 'static,
+    Request: Clone + Send + 'static,
+{
+    type Response = EstablishedClientConnection<S::Connection, State, Request>;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let backoff = self.backoff.clone();
+        loop {
+            let err = match self.inner.connect(ctx.clone(), req.clone()).await {
+                Ok(established) => return Ok(established),
+                Err(err) => err.into(),
+            };
+            tracing::debug!(error = %err, "connection attempt failed");
+            if !backoff.next_backoff().await {
+                return Err(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::service::service_fn;
+    use rama_utils::{backoff::ExponentialBackoff, rng::HasherRng};
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use tokio::time::Instant;
+
+    fn failing_connector(
+        failures: usize,
+        attempts: Arc<Mutex<Vec<Instant>>>,
+    ) -> impl ConnectorService<(), (), Connection = (), Error = BoxError> {
+        service_fn(move |ctx: Context<()>, req: ()| {
+            let attempts = attempts.clone();
+            async move {
+                let mut attempts = attempts.lock().unwrap();
+                attempts.push(Instant::now());
+                if attempts.len() <= failures {
+                    return Err(BoxError::from("connection refused"));
+                }
+                Ok(EstablishedClientConnection {
+                    ctx,
+                    req,
+                    conn: (),
+                    addr: ([127, 0, 0, 1], 8080).into(),
+                })
+            }
+        })
+    }
+
+    fn backoff(max: Duration) -> ExponentialBackoff<fn() -> HasherRng> {
+        ExponentialBackoff::new(
+            Duration::from_millis(100),
+            max,
+            0.5,
+            HasherRng::default as fn() -> HasherRng,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_follows_backoff_schedule() {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let connector = ReconnectConnectorLayer::new(backoff(Duration::from_secs(10)))
+            .layer(failing_connector(3, attempts.clone()));
+
+        connector.connect(Context::default(), ()).await.unwrap();
+
+        let attempts = attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 4);
+        for (iteration, window) in attempts.windows(2).enumerate() {
+            let delay = window[1] - window[0];
+            let base = Duration::from_millis(100) * 2_u32.pow(iteration as u32);
+            assert!(delay > base, "{delay:?} > {base:?}");
+            assert!(delay <= base.mul_f64(1.5), "{delay:?} <= {base:?} * 1.5");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_gives_up() {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        // the backoff gives up once its base reaches the maximum: 100ms, 200ms, 400ms
+        let connector = ReconnectConnector::new(
+            failing_connector(usize::MAX, attempts.clone()),
+            backoff(Duration::from_millis(400)),
+        );
+
+        let err = connector.connect(Context::default(), ()).await.unwrap_err();
+        assert_eq!(err.to_string(), "connection refused");
+        assert_eq!(attempts.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,42 @@
+use std::{fmt, net::IpAddr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The effective IP address of the client, as resolved by a single middleware
+/// from the forwarded information and the peer address of the connection.
+///
+/// This extension (which can be stored and modified via the [`Context`])
+/// gives middleware such as access control and rate limiting
+/// one canonical type to read the client address from.
+///
+/// [`Context`]: rama_core::Context
+pub struct ClientIp(IpAddr);
+
+impl ClientIp {
+    /// Create a new [`ClientIp`] for the given [`IpAddr`].
+    pub const fn new(ip: IpAddr) -> Self {
+        Self(ip)
+    }
+
+    /// Returns the [`IpAddr`] of the client.
+    pub const fn ip(&self) -> IpAddr {
+        self.0
+    }
+}
+
+impl From<IpAddr> for ClientIp {
+    fn from(ip: IpAddr) -> Self {
+        Self(ip)
+    }
+}
+
+impl From<ClientIp> for IpAddr {
+    fn from(client_ip: ClientIp) -> Self {
+        client_ip.0
+    }
+}
+
+impl fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -1,10 +1,14 @@
 use super::{ForwardedProtocol, ForwardedVersion, NodeId};
-use crate::address::{Authority, Host};
+use crate::address::{Authority, Host, HostWithOptPort};
 use rama_core::error::{ErrorContext, OpaqueError};
 use std::fmt;
 use std::net::SocketAddr;
 use std::{collections::HashMap, net::IpAddr};
 
+#[cfg(feature = "http")]
+use super::NodeObfuscation;
+#[cfg(feature = "http")]
+use crate::{http::RequestContext, stream::SocketInfo};
 #[cfg(feature = "http")]
 use rama_http_types::HeaderValue;
 
@@ -92,6 +96,13 @@ impl From<Authority> for ForwardedAuthority {
     }
 }
 
+impl From<HostWithOptPort> for ForwardedAuthority {
+    fn from(value: HostWithOptPort) -> Self {
+        let (host, port) = value.into_parts();
+        Self { host, port }
+    }
+}
+
 impl ForwardedElement {
     /// Merge the properties of another [`ForwardedElement`] into this one.
     pub fn merge(&mut self, other: ForwardedElement) -> &mut Self {
@@ -120,6 +131,50 @@ impl ForwardedElement {
         self
     }
 
+    #[cfg(feature = "http")]
+    /// Create a new [`ForwardedElement`] for a proxy adding itself to the forwarded chain,
+    /// using the [`SocketInfo`] of the incoming connection and the [`RequestContext`]
+    /// of the request being forwarded.
+    ///
+    /// The "for" parameter is set to the peer address, the "by" parameter
+    /// to the local address (if known), the "host" parameter to the request authority
+    /// and the "proto" parameter to the request protocol (if it is http or https).
+    ///
+    /// Use [`ForwardedElement::from_connection_with_obfuscation`]
+    /// in case you do not wish to reveal the (full) socket addresses.
+    pub fn from_connection(socket_info: &SocketInfo, request_ctx: &RequestContext) -> Self {
+        Self::from_connection_with_obfuscation(
+            socket_info,
+            request_ctx,
+            &NodeObfuscation::Reveal,
+            &NodeObfuscation::Reveal,
+        )
+    }
+
+    #[cfg(feature = "http")]
+    /// Same as [`ForwardedElement::from_connection`], but exposing the peer ("for")
+    /// and local ("by") addresses according to the given [`NodeObfuscation`] modes.
+    pub fn from_connection_with_obfuscation(
+        socket_info: &SocketInfo,
+        request_ctx: &RequestContext,
+        for_node: &NodeObfuscation,
+        by_node: &NodeObfuscation,
+    ) -> Self {
+        let by_node = match (by_node, socket_info.local_addr()) {
+            (NodeObfuscation::Replace(node_id), _) => Some(node_id.clone()),
+            (by_node, Some(addr)) => Some(by_node.node_id(*addr)),
+            (_, None) => None,
+        };
+        Self {
+            by_node,
+            for_node: Some(for_node.node_id(*socket_info.peer_addr())),
+            authority: Some(request_ctx.authority.clone().into()),
+            proto: (&request_ctx.protocol).try_into().ok(),
+            proto_version: None,
+            extensions: None,
+        }
+    }
+
     /// Return the host if one is defined.
     pub fn authority(&self) -> Option<(Host, Option<u16>)> {
         self.authority
@@ -271,25 +326,13 @@ impl fmt::Display for ForwardedElement {
 
         if let Some(ref by_node) = self.by_node {
             write!(f, "by=")?;
-            let quoted =
-                by_node.has_any_port() || by_node.ip().map(|ip| ip.is_ipv6()).unwrap_or_default();
-            if quoted {
-                write!(f, r##""{by_node}""##)?;
-            } else {
-                by_node.fmt(f)?;
-            }
+            fmt_node(f, by_node)?;
             separator = ";";
         }
 
         if let Some(ref for_node) = self.for_node {
             write!(f, "{separator}for=")?;
-            let quoted =
-                for_node.has_any_port() || for_node.ip().map(|ip| ip.is_ipv6()).unwrap_or_default();
-            if quoted {
-                write!(f, r##""{for_node}""##)?;
-            } else {
-                for_node.fmt(f)?;
-            }
+            fmt_node(f, for_node)?;
             separator = ";";
         }
 
@@ -314,6 +357,16 @@ impl fmt::Display for ForwardedElement {
     }
 }
 
+fn fmt_node(f: &mut fmt::Formatter<'_>, node: &NodeId) -> fmt::Result {
+    match node.ip() {
+        // ipv6 addresses are always enclosed in brackets within the forwarded header
+        Some(IpAddr::V6(ip)) if !node.has_any_port() => write!(f, r##""[{ip}]""##),
+        Some(IpAddr::V6(_)) => write!(f, r##""{node}""##),
+        _ if node.has_any_port() => write!(f, r##""{node}""##),
+        _ => fmt::Display::fmt(node, f),
+    }
+}
+
 impl std::str::FromStr for ForwardedElement {
     type Err = OpaqueError;
 
@@ -488,4 +541,79 @@ mod tests {
             assert_eq!(element, expected, "input: {}", s);
         }
     }
+
+    #[cfg(feature = "http")]
+    fn https_connection() -> (SocketInfo, RequestContext) {
+        (
+            SocketInfo::new(
+                Some("[2001:db8::1]:443".parse().unwrap()),
+                "[2001:db8:cafe::17]:4711".parse().unwrap(),
+            ),
+            RequestContext::builder()
+                .protocol(crate::Protocol::HTTPS)
+                .authority(Authority::try_from("example.com:443").unwrap())
+                .peer_addr("[2001:db8:cafe::17]:4711".parse().unwrap())
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_forwarded_element_from_connection() {
+        let (socket_info, request_ctx) = https_connection();
+        let element = ForwardedElement::from_connection(&socket_info, &request_ctx);
+
+        assert_eq!(
+            element.ref_forwarded_for(),
+            Some(&NodeId::try_from("[2001:db8:cafe::17]:4711").unwrap())
+        );
+        assert_eq!(
+            element.ref_forwarded_by(),
+            Some(&NodeId::try_from("[2001:db8::1]:443").unwrap())
+        );
+        assert_eq!(
+            element.ref_forwarded_proto(),
+            Some(ForwardedProtocol::HTTPS)
+        );
+        assert_eq!(
+            element.to_string(),
+            r##"by="[2001:db8::1]:443";for="[2001:db8:cafe::17]:4711";host="example.com:443";proto=https"##
+        );
+        assert_eq!(
+            ForwardedElement::try_from(element.to_string()).unwrap(),
+            element
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_forwarded_element_from_connection_with_obfuscation() {
+        let (socket_info, request_ctx) = https_connection();
+
+        for (for_node, by_node, expected) in [
+            (
+                NodeObfuscation::HidePort,
+                NodeObfuscation::Replace(NodeId::try_from("_proxy").unwrap()),
+                r##"by=_proxy;for="[2001:db8:cafe::17]";host="example.com:443";proto=https"##,
+            ),
+            (
+                NodeObfuscation::Unknown,
+                NodeObfuscation::HidePort,
+                r##"by="[2001:db8::1]";for=unknown;host="example.com:443";proto=https"##,
+            ),
+        ] {
+            let element = ForwardedElement::from_connection_with_obfuscation(
+                &socket_info,
+                &request_ctx,
+                &for_node,
+                &by_node,
+            );
+            assert_eq!(element.to_string(), expected);
+            assert_eq!(
+                ForwardedElement::try_from(element.to_string()).unwrap(),
+                element
+            );
+        }
+    }
 }
@@ -19,7 +19,7 @@ use obfuscated::{ObfNode, ObfPort};
 
 mod node;
 #[doc(inline)]
-pub use node::NodeId;
+pub use node::{NodeId, NodeObfuscation};
 
 mod element;
 #[doc(inline)]
@@ -33,6 +33,10 @@ mod version;
 #[doc(inline)]
 pub use version::ForwardedVersion;
 
+mod client_ip;
+#[doc(inline)]
+pub use client_ip::ClientIp;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// Forwarding information stored as a chain.
 ///
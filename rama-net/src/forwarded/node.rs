@@ -129,6 +129,41 @@ impl NodeId {
     }
 }
 
+/// How a socket address is exposed as [`NodeId`] in a [`ForwardedElement`]
+/// created from the connection information.
+///
+/// See <https://datatracker.ietf.org/doc/html/rfc7239#section-6.3>
+/// for more information about obfuscated identifiers.
+///
+/// [`ForwardedElement`]: super::ForwardedElement
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum NodeObfuscation {
+    #[default]
+    /// Expose both the ip address and port.
+    Reveal,
+    /// Expose only the ip address, hiding the port.
+    HidePort,
+    /// Hide the socket address entirely, using the `unknown` identifier.
+    Unknown,
+    /// Replace the socket address by the given (e.g. obfuscated) [`NodeId`].
+    Replace(NodeId),
+}
+
+impl NodeObfuscation {
+    /// Create the [`NodeId`] for the given [`SocketAddr`] according to this mode.
+    pub fn node_id(&self, addr: SocketAddr) -> NodeId {
+        match self {
+            Self::Reveal => addr.into(),
+            Self::HidePort => addr.ip().into(),
+            Self::Unknown => NodeId {
+                name: NodeName::Unknown,
+                port: None,
+            },
+            Self::Replace(node_id) => node_id.clone(),
+        }
+    }
+}
+
 impl NodePort {
     /// Converts a string slice to a [`NodePort`], converting invalid characters to underscore.
     fn from_str_lossy(s: &str) -> Self {
@@ -20,10 +20,16 @@ enum ProtocolKind {
     Http,
     /// The `https` protocol.
     Https,
+    /// The `ws` protocol.
+    Ws,
+    /// The `wss` protocol.
+    Wss,
 }
 
 const HTTP_STR: &str = "http";
 const HTTPS_STR: &str = "https";
+const WS_STR: &str = "ws";
+const WSS_STR: &str = "wss";
 
 impl ForwardedProtocol {
     /// `HTTP` protocol.
@@ -32,27 +38,39 @@ impl ForwardedProtocol {
     /// `HTTPS` protocol.
     pub const HTTPS: ForwardedProtocol = ForwardedProtocol(ProtocolKind::Https);
 
+    /// `WS` protocol.
+    pub const WS: ForwardedProtocol = ForwardedProtocol(ProtocolKind::Ws);
+
+    /// `WSS` protocol.
+    pub const WSS: ForwardedProtocol = ForwardedProtocol(ProtocolKind::Wss);
+
     /// Returns `true` if this protocol is http(s).
     pub fn is_http(&self) -> bool {
         match &self.0 {
             ProtocolKind::Http | ProtocolKind::Https => true,
+            ProtocolKind::Ws | ProtocolKind::Wss => false,
+        }
+    }
+
+    /// Returns `true` if this protocol is ws(s).
+    pub fn is_ws(&self) -> bool {
+        match &self.0 {
+            ProtocolKind::Ws | ProtocolKind::Wss => true,
+            ProtocolKind::Http | ProtocolKind::Https => false,
         }
     }
 
     /// Returns `true` if this protocol is "secure" by itself.
     pub fn is_secure(&self) -> bool {
         match self.0 {
-            ProtocolKind::Https => true,
-            ProtocolKind::Http => false,
+            ProtocolKind::Https | ProtocolKind::Wss => true,
+            ProtocolKind::Http | ProtocolKind::Ws => false,
         }
     }
 
     /// Returns the scheme str for this protocol.
     pub fn as_scheme(&self) -> &str {
-        match &self.0 {
-            ProtocolKind::Https => HTTPS_STR,
-            ProtocolKind::Http => HTTP_STR,
-        }
+        self.as_str()
     }
 
     #[inline]
@@ -66,6 +84,8 @@ impl ForwardedProtocol {
         match &self.0 {
             ProtocolKind::Https => HTTPS_STR,
             ProtocolKind::Http => HTTP_STR,
+            ProtocolKind::Wss => WSS_STR,
+            ProtocolKind::Ws => WS_STR,
         }
     }
 }
@@ -75,6 +95,8 @@ impl From<ForwardedProtocol> for Protocol {
         match p.0 {
             ProtocolKind::Https => Protocol::HTTPS,
             ProtocolKind::Http => Protocol::HTTP,
+            ProtocolKind::Wss => Protocol::WSS,
+            ProtocolKind::Ws => Protocol::WS,
         }
     }
 }
@@ -88,15 +110,7 @@ impl TryFrom<Protocol> for ForwardedProtocol {
     type Error = UnknownProtocol;
 
     fn try_from(p: Protocol) -> Result<Self, Self::Error> {
-        if p.is_http() {
-            if p.is_secure() {
-                Ok(ForwardedProtocol(ProtocolKind::Https))
-            } else {
-                Ok(ForwardedProtocol(ProtocolKind::Http))
-            }
-        } else {
-            Err(UnknownProtocol)
-        }
+        (&p).try_into()
     }
 }
 
@@ -104,14 +118,12 @@ impl TryFrom<&Protocol> for ForwardedProtocol {
     type Error = UnknownProtocol;
 
     fn try_from(p: &Protocol) -> Result<Self, Self::Error> {
-        if p.is_http() {
-            if p.is_secure() {
-                Ok(ForwardedProtocol(ProtocolKind::Https))
-            } else {
-                Ok(ForwardedProtocol(ProtocolKind::Http))
-            }
-        } else {
-            Err(UnknownProtocol)
+        match (p.is_http(), p.is_ws(), p.is_secure()) {
+            (true, _, true) => Ok(ForwardedProtocol(ProtocolKind::Https)),
+            (true, _, false) => Ok(ForwardedProtocol(ProtocolKind::Http)),
+            (_, true, true) => Ok(ForwardedProtocol(ProtocolKind::Wss)),
+            (_, true, false) => Ok(ForwardedProtocol(ProtocolKind::Ws)),
+            _ => Err(UnknownProtocol),
         }
     }
 }
@@ -129,6 +141,10 @@ impl TryFrom<&str> for ForwardedProtocol {
             Ok(ForwardedProtocol(ProtocolKind::Http))
         } else if eq_ignore_ascii_case!(s, HTTPS_STR) {
             Ok(ForwardedProtocol(ProtocolKind::Https))
+        } else if eq_ignore_ascii_case!(s, WS_STR) {
+            Ok(ForwardedProtocol(ProtocolKind::Ws))
+        } else if eq_ignore_ascii_case!(s, WSS_STR) {
+            Ok(ForwardedProtocol(ProtocolKind::Wss))
         } else {
             Err(InvalidProtocolStr)
         }
@@ -164,6 +180,8 @@ impl PartialEq<str> for ForwardedProtocol {
         match &self.0 {
             ProtocolKind::Https => other.eq_ignore_ascii_case(HTTPS_STR),
             ProtocolKind::Http => other.eq_ignore_ascii_case(HTTP_STR) || other.is_empty(),
+            ProtocolKind::Wss => other.eq_ignore_ascii_case(WSS_STR),
+            ProtocolKind::Ws => other.eq_ignore_ascii_case(WS_STR),
         }
     }
 }
@@ -212,11 +230,31 @@ mod tests {
     fn test_protocol_from_str() {
         assert_eq!("http".parse(), Ok(ForwardedProtocol::HTTP));
         assert_eq!("https".parse(), Ok(ForwardedProtocol::HTTPS));
+        assert_eq!("ws".parse(), Ok(ForwardedProtocol::WS));
+        assert_eq!("WSS".parse(), Ok(ForwardedProtocol::WSS));
+        assert!("ftp".parse::<ForwardedProtocol>().is_err());
     }
 
     #[test]
     fn test_protocol_secure() {
         assert!(!ForwardedProtocol::HTTP.is_secure());
         assert!(ForwardedProtocol::HTTPS.is_secure());
+        assert!(!ForwardedProtocol::WS.is_secure());
+        assert!(ForwardedProtocol::WSS.is_secure());
+    }
+
+    #[test]
+    fn test_protocol_ws_roundtrip() {
+        assert_eq!(Protocol::from(ForwardedProtocol::WSS), Protocol::WSS);
+        assert_eq!(Protocol::from(ForwardedProtocol::WS), Protocol::WS);
+        assert_eq!(
+            ForwardedProtocol::try_from(Protocol::WSS),
+            Ok(ForwardedProtocol::WSS)
+        );
+        assert_eq!(
+            ForwardedProtocol::try_from(&Protocol::HTTP),
+            Ok(ForwardedProtocol::HTTP)
+        );
+        assert!(ForwardedProtocol::try_from(Protocol::SOCKS5).is_err());
     }
 }
@@ -92,6 +92,22 @@ impl TryFrom<&[u8]> for ForwardedVersion {
     }
 }
 
+#[cfg(feature = "http")]
+impl TryFrom<Version> for ForwardedVersion {
+    type Error = InvalidForwardedVersion;
+
+    fn try_from(version: Version) -> Result<Self, Self::Error> {
+        Ok(ForwardedVersion(match version {
+            Version::HTTP_09 => VersionKind::Http09,
+            Version::HTTP_10 => VersionKind::Http10,
+            Version::HTTP_11 => VersionKind::Http11,
+            Version::HTTP_2 => VersionKind::H2,
+            Version::HTTP_3 => VersionKind::H3,
+            _ => return Err(InvalidForwardedVersion),
+        }))
+    }
+}
+
 impl std::fmt::Display for ForwardedVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.0 {
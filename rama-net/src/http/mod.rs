@@ -6,4 +6,6 @@
 
 mod request_context;
 #[doc(inline)]
-pub use request_context::RequestContext;
+pub use request_context::{
+    AuthorityError, RequestContext, RequestContextBuilder, RequestContextExt,
+};
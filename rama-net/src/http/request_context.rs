@@ -1,13 +1,19 @@
 use crate::forwarded::Forwarded;
+use crate::stream::SocketInfo;
 use crate::transport::{TransportContext, TransportProtocol, TryRefIntoTransportContext};
 use crate::{
-    address::{Authority, Host},
+    address::{Authority, Host, HostWithOptPort},
     Protocol,
 };
 use rama_core::error::OpaqueError;
 use rama_core::Context;
-use rama_http_types::Method;
+use rama_http_types::header::HOST;
 use rama_http_types::{dep::http::request::Parts, Request, Uri, Version};
+use rama_http_types::{HeaderMap, HeaderName, Method};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
 use tracing::{trace, warn};
 
 #[cfg(feature = "tls")]
@@ -37,6 +43,32 @@ fn try_get_host_from_secure_transport(_: &SecureTransport) -> Option<Host> {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// The context of the [`Request`].
+///
+/// Deriving it from a [`Request`] (or its [`Parts`]) and [`Context`] is not free,
+/// as it involves parsing the uri and headers (e.g. `Host`). Prefer to get it
+/// using [`Context::get_or_try_insert_with_ctx`] (or [`RequestContextExt`]), which derives it only once:
+/// the derived value is inserted in the [`Context`], and is never re-derived
+/// for as long as it is present in that [`Context`]:
+///
+/// ```
+/// use rama_core::Context;
+/// use rama_http_types::{Body, Request};
+/// use rama_net::http::RequestContext;
+///
+/// let req = Request::builder()
+///     .uri("http://example.com/foo")
+///     .body(Body::empty())
+///     .unwrap();
+///
+/// let mut ctx = Context::default();
+/// let req_ctx: &mut RequestContext = ctx
+///     .get_or_try_insert_with_ctx(|ctx| (ctx, &req).try_into())
+///     .unwrap();
+/// assert_eq!(req_ctx.authority.to_string(), "example.com:80");
+///
+/// // any later lookup returns the inserted value, without deriving it again
+/// assert!(ctx.contains::<RequestContext>());
+/// ```
 pub struct RequestContext {
     /// The HTTP Version.
     pub http_version: Version,
@@ -47,62 +79,119 @@ pub struct RequestContext {
     /// In http/1.1 this is typically defined by the `Host` header,
     /// whereas for h2 and h3 this is found in the pseudo `:authority` header.
     ///
+    /// In case no host is found in the uri, it is derived from the [`Forwarded`]
+    /// info found in the [`Context`], prior to using the `Host` header.
+    /// Legacy forward headers (e.g. `X-Forwarded-Host`) are only taken into account
+    /// once turned into such [`Forwarded`] info, e.g. by the `GetForwardedHeadersLayer`.
+    ///
+    /// The port is the one explicitly specified, or else the default port of
+    /// the [`Protocol`]. It is `None` for custom protocols without a default port,
+    /// in case no port was specified explicitly either.
+    ///
     /// This can be also manually set in case there is support for
-    /// forward headers (e.g. `Forwarded`, or `X-Forwarded-Host`)
-    /// or forward protocols (e.g. `HaProxy`).
-    pub authority: Authority,
+    /// forward protocols (e.g. `HaProxy`).
+    pub authority: HostWithOptPort,
+    /// Set in case a header used to derive the [`RequestContext::authority`]
+    /// (`Host`) was present but malformed.
+    ///
+    /// Such a header is treated as absent for the derivation,
+    /// but strict servers can use this to reject the request instead.
+    /// In case no authority can be derived at all, this error is returned
+    /// (as an [`OpaqueError`] which can be downcasted) instead.
+    pub authority_error: Option<AuthorityError>,
+    /// The address of the transport-level peer which originated the [`Request`],
+    /// `None` in case no [`SocketInfo`] was found in the [`Context`].
+    ///
+    /// Unlike the [`RequestContext::authority`] this is never derived from
+    /// forward headers or protocols, such that the "real" origin can be
+    /// compared with the origin "claimed" by the forwarding information.
+    pub peer_addr: Option<SocketAddr>,
+    /// The IP address of the client which originated the [`Request`].
+    ///
+    /// This is the client (first) `for=` IP address of the [`Forwarded`] info found
+    /// in the [`Context`], such that it reflects the real client behind (trusted) proxies,
+    /// falling back to the IP address of the [`RequestContext::peer_addr`].
+    ///
+    /// `None` in case neither is available, e.g. for a request received over a unix socket
+    /// without forwarded info, or in case the forwarded client IP is obfuscated and
+    /// there is no peer address either.
+    pub client_ip: Option<IpAddr>,
 }
 
 impl<Body, State> TryFrom<(&Context<State>, &Request<Body>)> for RequestContext {
     type Error = OpaqueError;
 
     fn try_from((ctx, req): (&Context<State>, &Request<Body>)) -> Result<Self, Self::Error> {
-        let uri = req.uri();
+        Self::derive(ctx, req.uri(), req.method(), req.version(), req.headers())
+    }
+}
+
+impl<State> TryFrom<(&Context<State>, &Parts)> for RequestContext {
+    type Error = OpaqueError;
+
+    fn try_from((ctx, parts): (&Context<State>, &Parts)) -> Result<Self, Self::Error> {
+        Self::derive(
+            ctx,
+            &parts.uri,
+            &parts.method,
+            parts.version,
+            &parts.headers,
+        )
+    }
+}
 
-        let protocol = protocol_from_uri_or_context(ctx, uri, req.method());
+impl RequestContext {
+    fn derive<State>(
+        ctx: &Context<State>,
+        uri: &Uri,
+        method: &Method,
+        version: Version,
+        headers: &HeaderMap,
+    ) -> Result<Self, OpaqueError> {
+        let protocol = protocol_from_uri_or_context(ctx, uri, method);
         tracing::trace!(
             uri = %uri, "request context: detected protocol: {protocol} (scheme: {:?})",
             uri.scheme()
         );
 
-        let default_port = uri.port_u16().unwrap_or_else(|| protocol.default_port());
-        tracing::trace!(uri = %uri, "request context: detected default port: {default_port}");
+        // custom protocols have no known default port,
+        // in which case the port remains unknown unless defined explicitly
+        let default_port = uri.port_u16().or_else(|| protocol.default_port());
+        tracing::trace!(uri = %uri, "request context: detected default port: {default_port:?}");
+
+        // headers which are present but malformed are recorded,
+        // even if the authority can be derived from another source
+        let (host_header_authority, authority_error) =
+            match host_header_authority(headers, default_port) {
+                Some(Ok(authority)) => (Some(authority), None),
+                Some(Err(err)) => (None, Some(err)),
+                None => (None, None),
+            };
 
         let authority = match ctx.get().and_then(try_get_host_from_secure_transport) {
             Some(h) => {
                 tracing::trace!(uri = %uri, host = %h, "request context: detected host from SNI");
-                (h, default_port).into()
+                HostWithOptPort::new(h, default_port)
             },
             None => uri
                 .host()
                 .and_then(|h| Host::try_from(h).ok().map(|h| {
                     tracing::trace!(uri = %uri, host = %h, "request context: detected host from (abs) uri");
-                    (h, default_port).into()
+                    HostWithOptPort::new(h, default_port)
                 }))
                 .or_else(|| {
                     ctx.get::<Forwarded>().and_then(|f| {
                         f.client_host().map(|fauth| {
                             let (host, port) = fauth.clone().into_parts();
-                            let port = port.unwrap_or(default_port);
                             tracing::trace!(uri = %uri, host = %host, "request context: detected host from forwarded info");
-                            (host, port).into()
+                            HostWithOptPort::new(host, port.or(default_port))
                         })
                     })
                 })
-                .or_else(|| {
-                    req.headers()
-                        .get(rama_http_types::header::HOST)
-                        .and_then(|host| {
-                            host.try_into() // try to consume as Authority, otherwise as Host
-                                .or_else(|_| Host::try_from(host).map(|h| {
-                                    tracing::trace!(uri = %uri, host = %h, "request context: detected host from host header");
-                                    (h, default_port).into()
-                                }))
-                                .ok()
-                        })
-                })
-                .ok_or_else(|| {
-                    OpaqueError::from_display("RequestContext: no authourity found in http::Request")
+                .or(host_header_authority)
+                .ok_or_else(|| match authority_error.clone() {
+                    Some(err) => OpaqueError::from_std(err),
+                    None => OpaqueError::from_display("RequestContext: no authourity found in http::Request"),
                 })?
         };
 
@@ -119,99 +208,279 @@ impl<Body, State> TryFrom<(&Context<State>, &Request<Body>)> for RequestContext
                     crate::forwarded::ForwardedVersion::HTTP_3 => Version::HTTP_3,
                 })
             })
-            .unwrap_or_else(|| req.version());
+            .unwrap_or(version);
         tracing::trace!(uri = %uri, "request context: maybe detected http version: {http_version:?}");
 
+        let peer_addr = ctx.get::<SocketInfo>().map(|info| *info.peer_addr());
+        let client_ip = ctx
+            .get::<Forwarded>()
+            .and_then(|forwarded| forwarded.client_ip())
+            .or_else(|| peer_addr.map(|addr| addr.ip()));
+
         Ok(RequestContext {
             http_version,
             protocol,
             authority,
+            authority_error,
+            peer_addr,
+            client_ip,
         })
     }
 }
 
-impl<State> TryFrom<(&Context<State>, &Parts)> for RequestContext {
-    type Error = OpaqueError;
+impl RequestContext {
+    /// Create a new [`RequestContextBuilder`],
+    /// to create a [`RequestContext`] with explicit values.
+    pub fn builder() -> RequestContextBuilder {
+        RequestContextBuilder::default()
+    }
 
-    fn try_from((ctx, parts): (&Context<State>, &Parts)) -> Result<Self, Self::Error> {
-        let uri = &parts.uri;
+    /// Override the [`Authority`], regardless of where it was derived from.
+    ///
+    /// This also clears the [`RequestContext::authority_error`],
+    /// as the overwritten authority no longer depends on any header.
+    ///
+    /// Insert the adjusted [`RequestContext`] in the [`Context`] to have it
+    /// used instead of deriving it, e.g. by [`RequestContextExt::get_or_try_insert_request_context`].
+    pub fn with_authority(mut self, authority: Authority) -> Self {
+        self.set_authority(authority);
+        self
+    }
 
-        let protocol = protocol_from_uri_or_context(ctx, uri, &parts.method);
-        tracing::trace!(
-            uri = %uri, "request context: detected protocol: {protocol} (scheme: {:?})",
-            uri.scheme()
-        );
+    /// Override the [`Authority`], regardless of where it was derived from.
+    ///
+    /// This also clears the [`RequestContext::authority_error`],
+    /// as the overwritten authority no longer depends on any header.
+    ///
+    /// Insert the adjusted [`RequestContext`] in the [`Context`] to have it
+    /// used instead of deriving it, e.g. by [`RequestContextExt::get_or_try_insert_request_context`].
+    pub fn set_authority(&mut self, authority: Authority) -> &mut Self {
+        self.authority = authority.into();
+        self.authority_error = None;
+        self
+    }
 
-        let default_port = uri.port_u16().unwrap_or_else(|| protocol.default_port());
-        tracing::trace!(uri = %uri, "request context: detected default port: {default_port}");
+    /// Override the [`Protocol`], regardless of where it was derived from.
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
 
-        let authority = match ctx.get().and_then(try_get_host_from_secure_transport) {
-            Some(h) => {
-                tracing::trace!(uri = %uri, host = %h, "request context: detected host from SNI");
-                (h, default_port).into()
-            }
-            None => {
-                uri
-                    .host()
-                    .and_then(|h| Host::try_from(h).ok().map(|h| {
-                        tracing::trace!(uri = %uri, host = %h, "request context: detected host from (abs) uri");
-                        (h, default_port).into()
-                    }))
-                    .or_else(|| {
-                        ctx.get::<Forwarded>().and_then(|f| {
-                            f.client_host().map(|fauth| {
-                                let (host, port) = fauth.clone().into_parts();
-                                let port = port.unwrap_or(default_port);
-                                tracing::trace!(uri = %uri, host = %host, "request context: detected host from forwarded info");
-                                (host, port).into()
-                            })
-                        })
-                    })
-                    .or_else(|| {
-                        parts
-                            .headers
-                            .get(rama_http_types::header::HOST)
-                            .and_then(|host| {
-                                host.try_into() // try to consume as Authority, otherwise as Host
-                                    .or_else(|_| Host::try_from(host).map(|h| {
-                                        tracing::trace!(uri = %uri, host = %h, "request context: detected host from host header");
-                                        (h, default_port).into()
-                                    }))
-                                    .ok()
-                            })
-                    })
-                    .ok_or_else(|| {
-                        OpaqueError::from_display(
-                            "RequestContext: no authourity found in http::request::Parts",
-                        )
-                    })?
-            }
-        };
+    /// Override the [`Protocol`], regardless of where it was derived from.
+    pub fn set_protocol(&mut self, protocol: Protocol) -> &mut Self {
+        self.protocol = protocol;
+        self
+    }
 
-        tracing::trace!(uri = %uri, "request context: detected authority: {authority}");
+    /// Override the HTTP [`Version`], regardless of where it was derived from.
+    pub fn with_http_version(mut self, http_version: Version) -> Self {
+        self.http_version = http_version;
+        self
+    }
 
-        let http_version = ctx
-            .get::<Forwarded>()
-            .and_then(|f| {
-                f.client_version().map(|v| match v {
-                    crate::forwarded::ForwardedVersion::HTTP_09 => Version::HTTP_09,
-                    crate::forwarded::ForwardedVersion::HTTP_10 => Version::HTTP_10,
-                    crate::forwarded::ForwardedVersion::HTTP_11 => Version::HTTP_11,
-                    crate::forwarded::ForwardedVersion::HTTP_2 => Version::HTTP_2,
-                    crate::forwarded::ForwardedVersion::HTTP_3 => Version::HTTP_3,
-                })
-            })
-            .unwrap_or(parts.version);
-        tracing::trace!(uri = %uri, "request context: maybe detected http version: {http_version:?}");
+    /// Override the HTTP [`Version`], regardless of where it was derived from.
+    pub fn set_http_version(&mut self, http_version: Version) -> &mut Self {
+        self.http_version = http_version;
+        self
+    }
+
+    /// Get the [`RequestContext::authority`], failing with the [`AuthorityError`]
+    /// in case a header used to derive it was present but malformed.
+    ///
+    /// This is the strict alternative to using the [`RequestContext::authority`]
+    /// directly, for servers which want to reject such requests.
+    pub fn authority_or_err(&self) -> Result<&HostWithOptPort, AuthorityError> {
+        match &self.authority_error {
+            Some(err) => Err(err.clone()),
+            None => Ok(&self.authority),
+        }
+    }
+}
+
+/// Extension trait for [`Context`], providing non-generic access to
+/// the memoized [`RequestContext`] of the current request.
+///
+/// Both methods look up the [`RequestContext`] only once,
+/// which makes them the preferred way to access it on hot paths.
+///
+/// ```
+/// use rama_core::Context;
+/// use rama_http_types::{Body, Request};
+/// use rama_net::http::{RequestContext, RequestContextExt};
+///
+/// let req = Request::builder()
+///     .uri("http://example.com/foo")
+///     .body(Body::empty())
+///     .unwrap();
+///
+/// let mut ctx = Context::default();
+/// assert!(ctx.request_context().is_none());
+///
+/// let req_ctx: &mut RequestContext = ctx.get_or_try_insert_request_context(&req).unwrap();
+/// assert_eq!(req_ctx.authority.to_string(), "example.com:80");
+///
+/// assert!(ctx.request_context().is_some());
+/// ```
+pub trait RequestContextExt: private::Sealed {
+    /// Get a shared reference to the [`RequestContext`],
+    /// in case it was already derived (or inserted) for this [`Context`].
+    fn request_context(&self) -> Option<&RequestContext>;
+
+    /// Get an exclusive reference to the [`RequestContext`]
+    /// of this [`Context`], deriving it from the given [`Request`]
+    /// and inserting it in case it is not present yet.
+    fn get_or_try_insert_request_context<Body>(
+        &mut self,
+        req: &Request<Body>,
+    ) -> Result<&mut RequestContext, OpaqueError>;
+}
+
+impl<State> RequestContextExt for Context<State> {
+    #[inline]
+    fn request_context(&self) -> Option<&RequestContext> {
+        self.get()
+    }
+
+    #[inline]
+    fn get_or_try_insert_request_context<Body>(
+        &mut self,
+        req: &Request<Body>,
+    ) -> Result<&mut RequestContext, OpaqueError> {
+        self.get_or_try_insert_with_ctx(|ctx| (ctx, req).try_into())
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+
+    impl<State> Sealed for rama_core::Context<State> {}
+}
+
+#[derive(Debug, Clone, Default)]
+/// Builder to create a [`RequestContext`] with explicit values,
+/// e.g. to mock the context of a request.
+///
+/// Created using [`RequestContext::builder`].
+pub struct RequestContextBuilder {
+    http_version: Option<Version>,
+    protocol: Option<Protocol>,
+    authority: Option<Authority>,
+    peer_addr: Option<SocketAddr>,
+    client_ip: Option<IpAddr>,
+}
+
+impl RequestContextBuilder {
+    /// Set the HTTP Version, [`Version::HTTP_11`] by default.
+    pub fn http_version(mut self, http_version: Version) -> Self {
+        self.http_version = Some(http_version);
+        self
+    }
+
+    /// Set the [`Protocol`], [`Protocol::HTTP`] by default.
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Set the [`Authority`], required to build the [`RequestContext`].
+    pub fn authority(mut self, authority: Authority) -> Self {
+        self.authority = Some(authority);
+        self
+    }
 
+    /// Set the address of the transport-level peer, `None` by default.
+    pub fn peer_addr(mut self, peer_addr: SocketAddr) -> Self {
+        self.peer_addr = Some(peer_addr);
+        self
+    }
+
+    /// Set the IP address of the client, defaulting to the IP address of the peer (if any).
+    pub fn client_ip(mut self, client_ip: IpAddr) -> Self {
+        self.client_ip = Some(client_ip);
+        self
+    }
+
+    /// Build the [`RequestContext`], using the defaults for all values not set,
+    /// failing in case no [`Authority`] was set.
+    pub fn build(self) -> Result<RequestContext, OpaqueError> {
         Ok(RequestContext {
-            http_version,
-            protocol,
-            authority,
+            http_version: self.http_version.unwrap_or(Version::HTTP_11),
+            protocol: self.protocol.unwrap_or(Protocol::HTTP),
+            authority: self
+                .authority
+                .ok_or_else(|| {
+                    OpaqueError::from_display("RequestContextBuilder: no authority defined")
+                })?
+                .into(),
+            authority_error: None,
+            peer_addr: self.peer_addr,
+            client_ip: self
+                .client_ip
+                .or_else(|| self.peer_addr.map(|addr| addr.ip())),
         })
     }
 }
 
+fn host_header_authority(
+    headers: &HeaderMap,
+    default_port: Option<u16>,
+) -> Option<Result<HostWithOptPort, AuthorityError>> {
+    let value = headers.get(HOST)?;
+    // try to consume as Authority, otherwise as Host
+    let authority = match Authority::try_from(value) {
+        Ok(authority) => authority.into(),
+        Err(_) => match Host::try_from(value) {
+            Ok(host) => HostWithOptPort::new(host, default_port),
+            Err(_) => {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                tracing::debug!("request context: malformed host header: {value}");
+                return Some(Err(AuthorityError::new(HOST, value)));
+            }
+        },
+    };
+    tracing::trace!(%authority, "request context: detected authority from host header");
+    Some(Ok(authority))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Error recorded when a header used to derive the [`RequestContext::authority`]
+/// was present, but could not be parsed as a [`Host`] or [`Authority`].
+pub struct AuthorityError {
+    header: HeaderName,
+    value: String,
+}
+
+impl AuthorityError {
+    fn new(header: HeaderName, value: impl Into<String>) -> Self {
+        Self {
+            header,
+            value: value.into(),
+        }
+    }
+
+    /// The name of the malformed header.
+    pub fn header_name(&self) -> &HeaderName {
+        &self.header
+    }
+
+    /// The (lossy utf-8) value of the malformed header.
+    pub fn header_value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for AuthorityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RequestContext: malformed authority in {} header: {:?}",
+            self.header, self.value
+        )
+    }
+}
+
+impl std::error::Error for AuthorityError {}
+
 #[allow(clippy::unnecessary_lazy_evaluations)]
 fn protocol_from_uri_or_context<State>(
     ctx: &Context<State>,
@@ -257,9 +526,12 @@ fn protocol_from_uri_or_context<State>(
         })
 }
 
-impl From<RequestContext> for TransportContext {
-    fn from(value: RequestContext) -> Self {
-        Self {
+impl TryFrom<RequestContext> for TransportContext {
+    type Error = OpaqueError;
+
+    fn try_from(value: RequestContext) -> Result<Self, Self::Error> {
+        let authority = transport_authority(&value.protocol, value.authority)?;
+        Ok(Self {
             protocol: if value.http_version == Version::HTTP_3 {
                 TransportProtocol::Udp
             } else {
@@ -267,14 +539,17 @@ impl From<RequestContext> for TransportContext {
             },
             app_protocol: Some(value.protocol),
             http_version: Some(value.http_version),
-            authority: value.authority,
-        }
+            authority,
+        })
     }
 }
 
-impl From<&RequestContext> for TransportContext {
-    fn from(value: &RequestContext) -> Self {
-        Self {
+impl TryFrom<&RequestContext> for TransportContext {
+    type Error = OpaqueError;
+
+    fn try_from(value: &RequestContext) -> Result<Self, Self::Error> {
+        let authority = transport_authority(&value.protocol, value.authority.clone())?;
+        Ok(Self {
             protocol: if value.http_version == Version::HTTP_3 {
                 TransportProtocol::Udp
             } else {
@@ -282,11 +557,24 @@ impl From<&RequestContext> for TransportContext {
             },
             app_protocol: Some(value.protocol.clone()),
             http_version: Some(value.http_version),
-            authority: value.authority.clone(),
-        }
+            authority,
+        })
     }
 }
 
+/// The transport layer requires a port, which is unknown for a
+/// custom [`Protocol`] without default port, in case it wasn't specified explicitly.
+fn transport_authority(
+    protocol: &Protocol,
+    authority: HostWithOptPort,
+) -> Result<Authority, OpaqueError> {
+    authority.try_into().map_err(|_| {
+        OpaqueError::from_display(format!(
+            "RequestContext: no port found for protocol '{protocol}' without a default port"
+        ))
+    })
+}
+
 impl<State, Body> TryRefIntoTransportContext<State> for rama_http_types::Request<Body> {
     type Error = OpaqueError;
 
@@ -331,6 +619,75 @@ mod tests {
         assert_eq!(req_ctx.http_version, Version::HTTP_11);
         assert_eq!(req_ctx.protocol, Protocol::HTTP);
         assert_eq!(req_ctx.authority.to_string(), "example.com:8080");
+        assert_eq!(req_ctx.peer_addr, None);
+    }
+
+    #[test]
+    fn test_request_context_peer_addr() {
+        let req = Request::builder()
+            .uri("http://example.com:8080")
+            .header(FORWARDED, "for=192.0.2.60;host=example.org")
+            .body(())
+            .unwrap();
+
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, "127.0.0.1:12345".parse().unwrap()));
+        ctx.insert(req.headers().typed_get::<Forwarded>().unwrap());
+
+        let req_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+        assert_eq!(req_ctx.peer_addr, Some("127.0.0.1:12345".parse().unwrap()));
+        assert_eq!(req_ctx.authority.to_string(), "example.com:8080");
+
+        // the client ip reflects the real client behind the proxy
+        assert_eq!(req_ctx.client_ip, Some("192.0.2.60".parse().unwrap()));
+
+        let (parts, _) = req.into_parts();
+        let req_ctx = RequestContext::try_from((&ctx, &parts)).unwrap();
+        assert_eq!(req_ctx.peer_addr, Some("127.0.0.1:12345".parse().unwrap()));
+        assert_eq!(req_ctx.client_ip, Some("192.0.2.60".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_request_context_client_ip() {
+        // direct connection: the peer is the client
+        let req = Request::builder()
+            .uri("http://example.com")
+            .body(())
+            .unwrap();
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, "[::1]:12345".parse().unwrap()));
+        let req_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+        assert_eq!(req_ctx.client_ip, Some("::1".parse().unwrap()));
+
+        // obfuscated forwarded client falls back to the peer
+        let req = Request::builder()
+            .uri("http://example.com")
+            .header(FORWARDED, "for=_hidden, for=10.0.0.1")
+            .body(())
+            .unwrap();
+        ctx.insert(req.headers().typed_get::<Forwarded>().unwrap());
+        let req_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+        assert_eq!(req_ctx.client_ip, Some("::1".parse().unwrap()));
+
+        // unix socket: no peer ip, only the forwarded client ip (if any)
+        let req = Request::builder()
+            .uri("http://example.com")
+            .body(())
+            .unwrap();
+        let req_ctx = RequestContext::try_from((&Context::<()>::default(), &req)).unwrap();
+        assert_eq!(req_ctx.peer_addr, None);
+        assert_eq!(req_ctx.client_ip, None);
+
+        let req = Request::builder()
+            .uri("http://example.com")
+            .header(FORWARDED, "for=\"[2001:db8::1]:4711\"")
+            .body(())
+            .unwrap();
+        let mut ctx = Context::<()>::default();
+        ctx.insert(req.headers().typed_get::<Forwarded>().unwrap());
+        let req_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+        assert_eq!(req_ctx.peer_addr, None);
+        assert_eq!(req_ctx.client_ip, Some("2001:db8::1".parse().unwrap()));
     }
 
     #[test]
@@ -356,53 +713,108 @@ mod tests {
 
     #[test]
     fn test_request_context_authority() {
-        let ctx = RequestContext {
-            http_version: Version::HTTP_11,
-            protocol: Protocol::HTTP,
-            authority: "example.com:8080".try_into().unwrap(),
-        };
+        let ctx = RequestContext::builder()
+            .authority("example.com:8080".try_into().unwrap())
+            .build()
+            .unwrap();
 
         assert_eq!(ctx.authority.to_string(), "example.com:8080");
     }
 
+    #[test]
+    fn test_request_context_builder() {
+        let ctx = RequestContext::builder()
+            .authority("example.com:443".parse().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(ctx.http_version, Version::HTTP_11);
+        assert_eq!(ctx.protocol, Protocol::HTTP);
+        assert_eq!(ctx.peer_addr, None);
+
+        let builder = RequestContext::builder()
+            .http_version(Version::HTTP_2)
+            .protocol(Protocol::HTTPS)
+            .peer_addr("127.0.0.1:12345".parse().unwrap());
+        assert!(builder.clone().build().is_err());
+
+        let ctx = builder
+            .authority("example.com:443".parse().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(ctx.http_version, Version::HTTP_2);
+        assert_eq!(ctx.protocol, Protocol::HTTPS);
+        assert_eq!(ctx.authority.to_string(), "example.com:443");
+        assert_eq!(ctx.peer_addr, Some("127.0.0.1:12345".parse().unwrap()));
+        assert_eq!(ctx.client_ip, Some("127.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_request_context_overrides() {
+        let req = Request::builder()
+            .uri("http://example.com/foo")
+            .header("host", "exa mple.com")
+            .body(())
+            .unwrap();
+
+        let mut ctx = Context::default();
+        let req_ctx = RequestContext::try_from((&ctx, &req)).unwrap();
+        assert_eq!(req_ctx.authority.to_string(), "example.com:80");
+        assert_eq!(req_ctx.authority_or_err().unwrap_err().header_name(), HOST);
+
+        let req_ctx = req_ctx
+            .with_authority("upstream.internal:8443".parse().unwrap())
+            .with_protocol(Protocol::HTTPS)
+            .with_http_version(Version::HTTP_2);
+        assert_eq!(
+            req_ctx.authority_or_err().unwrap().to_string(),
+            "upstream.internal:8443"
+        );
+        ctx.insert(req_ctx);
+
+        // the inserted (overwritten) context is used instead of deriving it
+        let req_ctx = ctx.get_or_try_insert_request_context(&req).unwrap();
+        assert_eq!(req_ctx.authority.to_string(), "upstream.internal:8443");
+        assert_eq!(req_ctx.protocol, Protocol::HTTPS);
+        assert_eq!(req_ctx.http_version, Version::HTTP_2);
+
+        req_ctx.set_protocol(Protocol::WSS);
+        assert_eq!(ctx.request_context().unwrap().protocol, Protocol::WSS);
+    }
+
     #[test]
     fn forwarded_parsing() {
         for (forwarded_str_vec, expected) in [
             // base
             (
                 vec!["host=192.0.2.60;proto=http;by=203.0.113.43"],
-                RequestContext {
-                    http_version: Version::HTTP_11,
-                    protocol: Protocol::HTTP,
-                    authority: "192.0.2.60:80".parse().unwrap(),
-                },
+                RequestContext::builder()
+                    .authority("192.0.2.60:80".parse().unwrap())
+                    .build()
+                    .unwrap(),
             ),
             // ipv6
             (
                 vec!["host=\"[2001:db8:cafe::17]:4711\""],
-                RequestContext {
-                    http_version: Version::HTTP_11,
-                    protocol: Protocol::HTTP,
-                    authority: "[2001:db8:cafe::17]:4711".parse().unwrap(),
-                },
+                RequestContext::builder()
+                    .authority("[2001:db8:cafe::17]:4711".parse().unwrap())
+                    .build()
+                    .unwrap(),
             ),
             // multiple values in one header
             (
                 vec!["host=192.0.2.60, host=127.0.0.1"],
-                RequestContext {
-                    http_version: Version::HTTP_11,
-                    protocol: Protocol::HTTP,
-                    authority: "192.0.2.60:80".parse().unwrap(),
-                },
+                RequestContext::builder()
+                    .authority("192.0.2.60:80".parse().unwrap())
+                    .build()
+                    .unwrap(),
             ),
             // multiple header values
             (
                 vec!["host=192.0.2.60", "host=127.0.0.1"],
-                RequestContext {
-                    http_version: Version::HTTP_11,
-                    protocol: Protocol::HTTP,
-                    authority: "192.0.2.60:80".parse().unwrap(),
-                },
+                RequestContext::builder()
+                    .authority("192.0.2.60:80".parse().unwrap())
+                    .build()
+                    .unwrap(),
             ),
         ] {
             let mut req_builder = Request::builder();
@@ -425,6 +837,91 @@ mod tests {
         }
     }
 
+    #[test]
+    fn x_forwarded_headers_ignored() {
+        // legacy forward headers are only used once turned into forwarded info
+        for (uri, headers, expected) in [
+            (
+                "/foo",
+                vec![
+                    ("host", "proxy.internal"),
+                    ("x-forwarded-host", "example.com"),
+                    ("x-forwarded-proto", "https"),
+                    ("x-forwarded-port", "8443"),
+                ],
+                RequestContext::builder()
+                    .authority("proxy.internal:80".parse().unwrap())
+                    .build()
+                    .unwrap(),
+            ),
+            // the x-forwarded-port does not rewrite the port of an absolute uri
+            (
+                "http://example.com/foo",
+                vec![("x-forwarded-port", "8443")],
+                RequestContext::builder()
+                    .authority("example.com:80".parse().unwrap())
+                    .build()
+                    .unwrap(),
+            ),
+            (
+                "https://example.com:9443/foo",
+                vec![
+                    ("x-forwarded-host", "other.com"),
+                    ("x-forwarded-port", "8443"),
+                ],
+                RequestContext::builder()
+                    .protocol(Protocol::HTTPS)
+                    .authority("example.com:9443".parse().unwrap())
+                    .build()
+                    .unwrap(),
+            ),
+        ] {
+            let mut req_builder = Request::builder().uri(uri);
+            for (name, value) in headers.clone() {
+                req_builder = req_builder.header(name, value);
+            }
+            let req = req_builder.body(()).unwrap();
+
+            let req_ctx = RequestContext::try_from((&Context::<()>::default(), &req)).unwrap();
+            assert_eq!(req_ctx, expected, "Failed for {uri} {:?}", headers);
+
+            let (parts, _) = req.into_parts();
+            let req_ctx = RequestContext::try_from((&Context::<()>::default(), &parts)).unwrap();
+            assert_eq!(req_ctx, expected, "Failed for {uri} {:?}", headers);
+        }
+    }
+
+    #[test]
+    fn malformed_authority_headers() {
+        // malformed host header is recorded, while the authority is taken from the uri
+        let req = Request::builder()
+            .uri("http://example.com/foo")
+            .header("host", "exa mple.com")
+            .body(())
+            .unwrap();
+        let req_ctx = RequestContext::try_from((&Context::<()>::default(), &req)).unwrap();
+        assert_eq!(req_ctx.authority.to_string(), "example.com:80");
+        let err = req_ctx.authority_error.unwrap();
+        assert_eq!(err.header_name(), HOST);
+        assert_eq!(err.header_value(), "exa mple.com");
+
+        // malformed host header without any other source is returned as error
+        let req = Request::builder()
+            .header("host", "exa mple.com")
+            .body(())
+            .unwrap();
+        let err = RequestContext::try_from((&Context::<()>::default(), &req)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<AuthorityError>().unwrap().header_name(),
+            HOST
+        );
+
+        // missing authority is not a malformed authority
+        let req = Request::builder().body(()).unwrap();
+        let err = RequestContext::try_from((&Context::<()>::default(), &req)).unwrap_err();
+        assert!(err.downcast_ref::<AuthorityError>().is_none());
+    }
+
     #[test]
     fn test_request_ctx_https_request_behind_haproxy_plain() {
         let req = Request::builder()
@@ -488,7 +985,6 @@ mod tests {
             ("https", Protocol::HTTPS),
             ("ws", Protocol::WSS),
             ("wss", Protocol::WSS),
-            ("ftp", Protocol::from_static("ftp")),
         ];
         for (scheme, expected_protocol) in test_cases {
             let req = Request::builder()
@@ -509,8 +1005,43 @@ mod tests {
             assert_eq!(req_ctx.protocol, expected_protocol);
             assert_eq!(
                 req_ctx.authority.to_string(),
-                format!("www.example.com:{}", expected_protocol.default_port())
+                format!(
+                    "www.example.com:{}",
+                    expected_protocol.default_port().unwrap()
+                )
             );
         }
     }
+
+    #[test]
+    fn test_request_ctx_custom_protocol_no_default_port() {
+        let req = Request::builder()
+            .uri("ftp://www.example.com")
+            .body(())
+            .unwrap();
+        let req_ctx = RequestContext::try_from((&Context::default(), &req)).unwrap();
+        assert_eq!(req_ctx.protocol, Protocol::from_static("ftp"));
+        // the host is kept, without guessing port 80 for an unknown protocol
+        assert_eq!(req_ctx.authority.to_string(), "www.example.com");
+        assert_eq!(req_ctx.authority.port(), None);
+        assert!(TransportContext::try_from(&req_ctx).is_err());
+
+        let req = Request::builder()
+            .uri("ftp://www.example.com:21")
+            .body(())
+            .unwrap();
+        let req_ctx = RequestContext::try_from((&Context::default(), &req)).unwrap();
+        assert_eq!(req_ctx.protocol, Protocol::from_static("ftp"));
+        assert_eq!(req_ctx.authority.to_string(), "www.example.com:21");
+        let transport_ctx = TransportContext::try_from(&req_ctx).unwrap();
+        assert_eq!(transport_ctx.authority.to_string(), "www.example.com:21");
+
+        let req = Request::builder()
+            .uri("ftp://www.example.com:2121")
+            .method(Method::CONNECT)
+            .body(())
+            .unwrap();
+        let req_ctx = RequestContext::try_from((&Context::default(), &req)).unwrap();
+        assert_eq!(req_ctx.authority.to_string(), "www.example.com:2121");
+    }
 }
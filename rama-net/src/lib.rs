@@ -40,5 +40,8 @@ pub mod tls;
 #[cfg(any(feature = "tls", feature = "http"))]
 pub mod fingerprint;
 
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
 #[cfg(any(windows, unix))]
 pub use ::socket2 as socket;
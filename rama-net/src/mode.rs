@@ -1,11 +1,22 @@
+use std::{fmt, str::FromStr};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
 /// Enum representing the IP modes that can be used by the DNS resolver.
+///
+/// It can be parsed from (and displayed as) `dual`, `ipv4-only`, `ipv6-only`,
+/// `dual-prefer-ipv4` or `dual-prefer-ipv6`, case-insensitive.
 pub enum DnsResolveIpMode {
+    /// Resolve both IPv4 and IPv6 addresses, preferring IPv6.
     #[default]
     Dual,
+    /// Resolve IPv4 addresses only.
     SingleIpV4,
+    /// Resolve IPv6 addresses only.
     SingleIpV6,
+    /// Resolve both IPv4 and IPv6 addresses, preferring IPv4.
     DualPreferIpV4,
+    /// Resolve both IPv4 and IPv6 addresses, preferring IPv6.
+    DualPreferIpV6,
 }
 
 impl DnsResolveIpMode {
@@ -16,6 +27,7 @@ impl DnsResolveIpMode {
             DnsResolveIpMode::Dual
                 | DnsResolveIpMode::SingleIpV4
                 | DnsResolveIpMode::DualPreferIpV4
+                | DnsResolveIpMode::DualPreferIpV6
         )
     }
 
@@ -26,14 +38,188 @@ impl DnsResolveIpMode {
             DnsResolveIpMode::Dual
                 | DnsResolveIpMode::SingleIpV6
                 | DnsResolveIpMode::DualPreferIpV4
+                | DnsResolveIpMode::DualPreferIpV6
         )
     }
 }
+
 ///Mode for establishing a connection
+///
+/// It can be parsed from (and displayed as) `dual`, `ipv4-only` or `ipv6-only`, case-insensitive.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
 pub enum ConnectIpMode {
+    /// Connect to both IPv4 and IPv6 addresses.
     #[default]
     Dual,
+    /// Connect to IPv4 addresses only.
     Ipv4,
+    /// Connect to IPv6 addresses only.
     Ipv6,
 }
+
+impl ConnectIpMode {
+    /// checks if IPv4 is supported in current mode
+    pub fn ipv4_supported(&self) -> bool {
+        matches!(self, ConnectIpMode::Dual | ConnectIpMode::Ipv4)
+    }
+
+    /// checks if IPv6 is supported in current mode
+    pub fn ipv6_supported(&self) -> bool {
+        matches!(self, ConnectIpMode::Dual | ConnectIpMode::Ipv6)
+    }
+
+    /// checks if the given IP address is supported in current mode
+    pub fn ip_supported(&self, ip: &std::net::IpAddr) -> bool {
+        match ip {
+            std::net::IpAddr::V4(_) => self.ipv4_supported(),
+            std::net::IpAddr::V6(_) => self.ipv6_supported(),
+        }
+    }
+}
+
+rama_utils::macros::error::static_str_error! {
+    #[doc = "invalid ip mode"]
+    pub struct ParseModeError;
+}
+
+impl DnsResolveIpMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DnsResolveIpMode::Dual => "dual",
+            DnsResolveIpMode::SingleIpV4 => "ipv4-only",
+            DnsResolveIpMode::SingleIpV6 => "ipv6-only",
+            DnsResolveIpMode::DualPreferIpV4 => "dual-prefer-ipv4",
+            DnsResolveIpMode::DualPreferIpV6 => "dual-prefer-ipv6",
+        }
+    }
+}
+
+impl fmt::Display for DnsResolveIpMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DnsResolveIpMode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            DnsResolveIpMode::Dual,
+            DnsResolveIpMode::SingleIpV4,
+            DnsResolveIpMode::SingleIpV6,
+            DnsResolveIpMode::DualPreferIpV4,
+            DnsResolveIpMode::DualPreferIpV6,
+        ]
+        .into_iter()
+        .find(|mode| mode.as_str().eq_ignore_ascii_case(s.trim()))
+        .ok_or(ParseModeError)
+    }
+}
+
+impl ConnectIpMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ConnectIpMode::Dual => "dual",
+            ConnectIpMode::Ipv4 => "ipv4-only",
+            ConnectIpMode::Ipv6 => "ipv6-only",
+        }
+    }
+}
+
+impl fmt::Display for ConnectIpMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ConnectIpMode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            ConnectIpMode::Dual,
+            ConnectIpMode::Ipv4,
+            ConnectIpMode::Ipv6,
+        ]
+        .into_iter()
+        .find(|mode| mode.as_str().eq_ignore_ascii_case(s.trim()))
+        .ok_or(ParseModeError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dns_resolve_ip_mode_supported() {
+        for (mode, ipv4, ipv6) in [
+            (DnsResolveIpMode::Dual, true, true),
+            (DnsResolveIpMode::SingleIpV4, true, false),
+            (DnsResolveIpMode::SingleIpV6, false, true),
+            (DnsResolveIpMode::DualPreferIpV4, true, true),
+            (DnsResolveIpMode::DualPreferIpV6, true, true),
+        ] {
+            assert_eq!(mode.ipv4_supported(), ipv4, "{mode:?}");
+            assert_eq!(mode.ipv6_supported(), ipv6, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_connect_ip_mode_supported() {
+        let ipv4: std::net::IpAddr = "127.0.0.1".parse().unwrap();
+        let ipv6: std::net::IpAddr = "::1".parse().unwrap();
+        for (mode, ipv4_supported, ipv6_supported) in [
+            (ConnectIpMode::Dual, true, true),
+            (ConnectIpMode::Ipv4, true, false),
+            (ConnectIpMode::Ipv6, false, true),
+        ] {
+            assert_eq!(mode.ipv4_supported(), ipv4_supported, "{mode:?}");
+            assert_eq!(mode.ipv6_supported(), ipv6_supported, "{mode:?}");
+            assert_eq!(mode.ip_supported(&ipv4), ipv4_supported, "{mode:?}");
+            assert_eq!(mode.ip_supported(&ipv6), ipv6_supported, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_dns_resolve_ip_mode_round_trip() {
+        for mode in [
+            DnsResolveIpMode::Dual,
+            DnsResolveIpMode::SingleIpV4,
+            DnsResolveIpMode::SingleIpV6,
+            DnsResolveIpMode::DualPreferIpV4,
+            DnsResolveIpMode::DualPreferIpV6,
+        ] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+            assert_eq!(mode.to_string().to_uppercase().parse(), Ok(mode));
+        }
+    }
+
+    #[test]
+    fn test_connect_ip_mode_round_trip() {
+        for mode in [
+            ConnectIpMode::Dual,
+            ConnectIpMode::Ipv4,
+            ConnectIpMode::Ipv6,
+        ] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+            assert_eq!(mode.to_string().to_uppercase().parse(), Ok(mode));
+        }
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(
+            "Dual-Prefer-IPv4".parse(),
+            Ok(DnsResolveIpMode::DualPreferIpV4)
+        );
+        assert_eq!(" ipv6-only ".parse(), Ok(ConnectIpMode::Ipv6));
+        for s in ["", "ipv4", "dual-prefer", "dual-prefer-ipv4"] {
+            assert!(s.parse::<ConnectIpMode>().is_err(), "{s:?}");
+        }
+        for s in ["", "ipv6", "dual prefer ipv4"] {
+            assert!(s.parse::<DnsResolveIpMode>().is_err(), "{s:?}");
+        }
+    }
+}
@@ -173,12 +173,13 @@ impl Protocol {
 
     /// Return a port that can be used as default in case no port is defined.
     ///
-    /// NOTE that this is not going to be valid for non-http ports.
-    pub fn default_port(&self) -> u16 {
+    /// Returns `None` for custom protocols, as their default port is unknown.
+    pub fn default_port(&self) -> Option<u16> {
         match &self.0 {
-            ProtocolKind::Https | ProtocolKind::Wss => 443,
-            ProtocolKind::Http | ProtocolKind::Ws => 80,
-            ProtocolKind::Socks5 | ProtocolKind::Socks5h | ProtocolKind::Custom(_) => 80, // \_(ツ)_/¯
+            ProtocolKind::Https | ProtocolKind::Wss => Some(443),
+            ProtocolKind::Http | ProtocolKind::Ws => Some(80),
+            ProtocolKind::Socks5 | ProtocolKind::Socks5h => Some(80), // \_(ツ)_/¯
+            ProtocolKind::Custom(_) => None,
         }
     }
 
@@ -453,6 +454,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_default_port() {
+        assert_eq!(Protocol::HTTP.default_port(), Some(80));
+        assert_eq!(Protocol::HTTPS.default_port(), Some(443));
+        assert_eq!(Protocol::WS.default_port(), Some(80));
+        assert_eq!(Protocol::WSS.default_port(), Some(443));
+        assert_eq!(Protocol::from_static("custom").default_port(), None);
+        assert_eq!("ftp".parse::<Protocol>().unwrap().default_port(), None);
+    }
+
     #[test]
     fn test_scheme_is_secure() {
         assert!(!Protocol::HTTP.is_secure());
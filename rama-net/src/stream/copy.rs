@@ -0,0 +1,254 @@
+use rama_core::graceful::ShutdownGuard;
+use std::{
+    io,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const COPY_BUF_SIZE: usize = 8 * 1024;
+
+const SIDE_NONE: u8 = 0;
+const SIDE_A: u8 = 1;
+const SIDE_B: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// One of the two sides of a [`copy_bidirectional_with_stats`] pump.
+pub enum CopySide {
+    /// The first stream (`a`).
+    A,
+    /// The second stream (`b`).
+    B,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Transfer statistics as returned by [`copy_bidirectional_with_stats`].
+pub struct CopyBidirectionalStats {
+    /// Amount of bytes copied from `a` to `b`.
+    pub a_to_b: u64,
+    /// Amount of bytes copied from `b` to `a`.
+    pub b_to_a: u64,
+    /// The side which reached its end (EOF) first, if any.
+    pub first_closed: Option<CopySide>,
+    /// Whether or not the pump was stopped by a graceful shutdown.
+    pub cancelled: bool,
+}
+
+/// Copy data in both directions between the two given streams,
+/// until both of them reached their end (EOF), returning the transfer statistics.
+///
+/// Half-closes are honored: once one side reaches its end, the write half
+/// of the other side is shut down, while data keeps flowing in the other direction.
+///
+/// In case a [`ShutdownGuard`] is given, the pump stops once a graceful shutdown
+/// is triggered. The write halves of both sides are shut down at that point,
+/// after which the remaining incoming data is drained (and discarded)
+/// for at most the given `drain_timeout`.
+pub async fn copy_bidirectional_with_stats<A, B>(
+    a: &mut A,
+    b: &mut B,
+    guard: Option<&ShutdownGuard>,
+    drain_timeout: Duration,
+) -> io::Result<CopyBidirectionalStats>
+where
+    A: AsyncRead + AsyncWrite + Unpin + ?Sized,
+    B: AsyncRead + AsyncWrite + Unpin + ?Sized,
+{
+    let (mut a_read, mut a_write) = tokio::io::split(a);
+    let (mut b_read, mut b_write) = tokio::io::split(b);
+
+    let a_to_b = AtomicU64::new(0);
+    let b_to_a = AtomicU64::new(0);
+    let first_closed = AtomicU8::new(SIDE_NONE);
+
+    let result = {
+        let copy = async {
+            tokio::try_join!(
+                pump(&mut a_read, &mut b_write, &a_to_b, SIDE_A, &first_closed),
+                pump(&mut b_read, &mut a_write, &b_to_a, SIDE_B, &first_closed),
+            )
+        };
+        match guard {
+            Some(guard) => tokio::select! {
+                result = copy => Some(result),
+                _ = guard.cancelled() => None,
+            },
+            None => Some(copy.await),
+        }
+    };
+
+    let cancelled = match result {
+        Some(result) => {
+            result?;
+            false
+        }
+        None => {
+            tracing::trace!(
+                "copy bidirectional: graceful shutdown: shut down write halves and drain"
+            );
+            let drain = async {
+                let _ = tokio::join!(a_write.shutdown(), b_write.shutdown());
+                let _ = tokio::join!(drain(&mut a_read), drain(&mut b_read));
+            };
+            if tokio::time::timeout(drain_timeout, drain).await.is_err() {
+                tracing::debug!("copy bidirectional: drain timeout elapsed");
+            }
+            true
+        }
+    };
+
+    Ok(CopyBidirectionalStats {
+        a_to_b: a_to_b.into_inner(),
+        b_to_a: b_to_a.into_inner(),
+        first_closed: match first_closed.into_inner() {
+            SIDE_A => Some(CopySide::A),
+            SIDE_B => Some(CopySide::B),
+            _ => None,
+        },
+        cancelled,
+    })
+}
+
+async fn pump<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    transferred: &AtomicU64,
+    side: u8,
+    first_closed: &AtomicU8,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; COPY_BUF_SIZE];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            let _ =
+                first_closed.compare_exchange(SIDE_NONE, side, Ordering::AcqRel, Ordering::Acquire);
+            return writer.shutdown().await;
+        }
+        writer.write_all(&buf[..n]).await?;
+        writer.flush().await?;
+        transferred.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+async fn drain<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<()> {
+    let mut buf = vec![0u8; COPY_BUF_SIZE];
+    while reader.read(&mut buf).await? > 0 {}
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::graceful::Shutdown;
+
+    #[tokio::test]
+    async fn test_copy_bidirectional_with_stats() {
+        let (mut a_peer, mut a) = tokio::io::duplex(64);
+        let (mut b, mut b_peer) = tokio::io::duplex(64);
+
+        let pump = tokio::spawn(async move {
+            copy_bidirectional_with_stats(&mut a, &mut b, None, Duration::from_secs(1)).await
+        });
+
+        a_peer.write_all(b"hello").await.unwrap();
+        let mut buf = [0u8; 5];
+        b_peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        // half-close a: b sees EOF, while b can still send data to a
+        a_peer.shutdown().await.unwrap();
+        let mut rest = Vec::new();
+        b_peer.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+
+        b_peer.write_all(b"world!").await.unwrap();
+        b_peer.shutdown().await.unwrap();
+        let mut buf = Vec::new();
+        a_peer.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"world!");
+
+        let stats = pump.await.unwrap().unwrap();
+        assert_eq!(
+            stats,
+            CopyBidirectionalStats {
+                a_to_b: 5,
+                b_to_a: 6,
+                first_closed: Some(CopySide::A),
+                cancelled: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_bidirectional_with_stats_graceful_shutdown() {
+        let (mut a_peer, mut a) = tokio::io::duplex(64);
+        let (mut b, mut b_peer) = tokio::io::duplex(64);
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        let guard = shutdown.guard();
+
+        let pump = tokio::spawn(async move {
+            copy_bidirectional_with_stats(&mut a, &mut b, Some(&guard), Duration::from_secs(1))
+                .await
+        });
+
+        b_peer.write_all(b"foo").await.unwrap();
+        let mut buf = [0u8; 3];
+        a_peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"foo");
+
+        tx.send(()).unwrap();
+
+        // both peers see their stream being closed
+        let mut buf = Vec::new();
+        a_peer.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        b_peer.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+
+        // after which the pump finishes once both peers close as well
+        drop(a_peer);
+        drop(b_peer);
+        let stats = pump.await.unwrap().unwrap();
+        assert_eq!(
+            stats,
+            CopyBidirectionalStats {
+                a_to_b: 0,
+                b_to_a: 3,
+                first_closed: None,
+                cancelled: true,
+            }
+        );
+        shutdown.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_copy_bidirectional_with_stats_drain_timeout() {
+        let (_a_peer, mut a) = tokio::io::duplex(64);
+        let (mut b, _b_peer) = tokio::io::duplex(64);
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+        let guard = shutdown.guard();
+
+        let pump = tokio::spawn(async move {
+            copy_bidirectional_with_stats(&mut a, &mut b, Some(&guard), Duration::from_secs(5))
+                .await
+        });
+        tx.send(()).unwrap();
+
+        // peers never close, so the pump stops once the drain timeout elapsed
+        let stats = pump.await.unwrap().unwrap();
+        assert!(stats.cancelled);
+        shutdown.shutdown().await;
+    }
+}
@@ -0,0 +1,325 @@
+//! Provides [`BytesRWLimiter`] which wraps a [`AsyncRead`] and/or [`AsyncWrite`]
+//! in order to enforce a quota on the number of bytes read and/or written.
+//!
+//! Use [`BytesRWLimiter::handle`] to get a [`BytesRWLimiterHandle`], a requirement
+//! to inspect or top up the remaining quota even though the [`BytesRWLimiter`]
+//! is consumed by a protocol consumer, which is for example the case when you wish
+//! to limit the bytes read and/or written for a Tcp stream that is owned by a Tls stream.
+//!
+//! [`AsyncRead`]: crate::stream::AsyncRead
+//! [`AsyncWrite`]: crate::stream::AsyncWrite
+
+use std::{
+    fmt, io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+rama_utils::macros::error::static_str_error! {
+    #[doc = "byte quota exceeded"]
+    pub struct QuotaExceeded;
+}
+
+pin_project! {
+    /// A wrapper around a [`AsyncRead`] and/or [`AsyncWrite`] that enforces
+    /// a quota on the number of bytes read and/or written.
+    ///
+    /// Once a quota is exhausted, the next read or write returns an [`io::Error`]
+    /// of kind [`io::ErrorKind::Other`] wrapping a [`QuotaExceeded`] error.
+    /// Reads and writes that would cross the quota are truncated to the remaining
+    /// quota rather than failing outright.
+    ///
+    /// Use [`BytesRWLimiter::handle`] to get a [`BytesRWLimiterHandle`] in order
+    /// to inspect or top up the remaining quota even though the [`BytesRWLimiter`]
+    /// is consumed by a protocol consumer.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub struct BytesRWLimiter<S> {
+        read: Arc<Quota>,
+        written: Arc<Quota>,
+        #[pin]
+        stream: S,
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for BytesRWLimiter<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BytesRWLimiter")
+            .field("read", &self.read)
+            .field("written", &self.written)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> BytesRWLimiter<S> {
+    /// Create a new [`BytesRWLimiter`] that wraps the
+    /// given [`AsyncRead`] and/or [`AsyncWrite`].
+    ///
+    /// A quota of `None` means that direction is unlimited.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn new(stream: S, read_quota: Option<usize>, write_quota: Option<usize>) -> Self {
+        Self {
+            read: Arc::new(Quota::new(read_quota)),
+            written: Arc::new(Quota::new(write_quota)),
+            stream,
+        }
+    }
+
+    /// Get the remaining read quota, `None` if unlimited.
+    pub fn read_remaining(&self) -> Option<usize> {
+        self.read.remaining()
+    }
+
+    /// Get the remaining write quota, `None` if unlimited.
+    pub fn write_remaining(&self) -> Option<usize> {
+        self.written.remaining()
+    }
+
+    /// Get a [`BytesRWLimiterHandle`] that can be used to inspect or top up
+    /// the remaining quota even though the limiter is consumed by a protocol
+    /// consumer in a later stage.
+    pub fn handle(&self) -> BytesRWLimiterHandle {
+        BytesRWLimiterHandle {
+            read: self.read.clone(),
+            written: self.written.clone(),
+        }
+    }
+
+    /// Get the inner [`AsyncRead`] and/or [`AsyncWrite`] stream.
+    /// Dropping the quota enforcement for this stream.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> AsyncRead for BytesRWLimiter<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.as_mut().project();
+        let remaining = match this.read.remaining() {
+            None => return this.stream.poll_read(cx, buf),
+            Some(0) => return Poll::Ready(Err(quota_exceeded())),
+            Some(remaining) => remaining,
+        };
+
+        let bytes_read = if remaining < buf.remaining() {
+            let mut limited_buf = ReadBuf::new(buf.initialize_unfilled_to(remaining));
+            futures_lite::ready!(this.stream.poll_read(cx, &mut limited_buf))?;
+            let bytes_read = limited_buf.filled().len();
+            buf.advance(bytes_read);
+            bytes_read
+        } else {
+            let size = buf.filled().len();
+            futures_lite::ready!(this.stream.poll_read(cx, buf))?;
+            buf.filled().len().saturating_sub(size)
+        };
+
+        this.read.consume(bytes_read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for BytesRWLimiter<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.as_mut().project();
+        let buf = match this.written.remaining() {
+            None => return this.stream.poll_write(cx, buf),
+            Some(0) => return Poll::Ready(Err(quota_exceeded())),
+            Some(remaining) => &buf[..buf.len().min(remaining)],
+        };
+
+        let bytes_written = futures_lite::ready!(this.stream.poll_write(cx, buf))?;
+        this.written.consume(bytes_written);
+        Poll::Ready(Ok(bytes_written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_shutdown(cx)
+    }
+}
+
+fn quota_exceeded() -> io::Error {
+    io::Error::other(QuotaExceeded::new())
+}
+
+/// A handle to a limiter that can be used to inspect or top up
+/// the remaining quota even though the limiter is consumed by a protocol
+/// consumer.
+#[derive(Debug, Clone)]
+pub struct BytesRWLimiterHandle {
+    read: Arc<Quota>,
+    written: Arc<Quota>,
+}
+
+impl BytesRWLimiterHandle {
+    /// Get the remaining read quota, `None` if unlimited.
+    pub fn read_remaining(&self) -> Option<usize> {
+        self.read.remaining()
+    }
+
+    /// Get the remaining write quota, `None` if unlimited.
+    pub fn write_remaining(&self) -> Option<usize> {
+        self.written.remaining()
+    }
+
+    /// Top up the remaining read quota with the given amount of bytes.
+    ///
+    /// This is a no-op in case reads are unlimited.
+    pub fn add_read_quota(&self, bytes: usize) {
+        self.read.add(bytes)
+    }
+
+    /// Top up the remaining write quota with the given amount of bytes.
+    ///
+    /// This is a no-op in case writes are unlimited.
+    pub fn add_write_quota(&self, bytes: usize) {
+        self.written.add(bytes)
+    }
+}
+
+/// Remaining quota, where [`Quota::UNLIMITED`] is used to indicate no quota is enforced.
+#[derive(Debug)]
+struct Quota(AtomicUsize);
+
+impl Quota {
+    const UNLIMITED: usize = usize::MAX;
+
+    fn new(quota: Option<usize>) -> Self {
+        Self(AtomicUsize::new(
+            quota
+                .map(|quota| quota.min(Self::UNLIMITED - 1))
+                .unwrap_or(Self::UNLIMITED),
+        ))
+    }
+
+    fn remaining(&self) -> Option<usize> {
+        match self.0.load(Ordering::Acquire) {
+            Self::UNLIMITED => None,
+            remaining => Some(remaining),
+        }
+    }
+
+    fn consume(&self, bytes: usize) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+                (remaining != Self::UNLIMITED).then(|| remaining.saturating_sub(bytes))
+            });
+    }
+
+    fn add(&self, bytes: usize) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+                (remaining != Self::UNLIMITED)
+                    .then(|| remaining.saturating_add(bytes).min(Self::UNLIMITED - 1))
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_test::io::Builder;
+
+    fn assert_quota_exceeded(err: io::Error) {
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(err.into_inner().unwrap().is::<QuotaExceeded>());
+    }
+
+    #[tokio::test]
+    async fn test_read_limiter() {
+        let stream = Builder::new().read(b"foo").read(b"barbaz").build();
+
+        let mut limiter = BytesRWLimiter::new(stream, Some(5), None);
+        let mut buf = [0u8; 16];
+
+        assert_eq!(limiter.read(&mut buf).await.unwrap(), 3);
+        assert_eq!(&buf[..3], b"foo");
+        assert_eq!(limiter.read_remaining(), Some(2));
+
+        // read is truncated to the remaining quota
+        assert_eq!(limiter.read(&mut buf).await.unwrap(), 2);
+        assert_eq!(&buf[..2], b"ba");
+        assert_eq!(limiter.read_remaining(), Some(0));
+
+        assert_quota_exceeded(limiter.read(&mut buf).await.unwrap_err());
+
+        // remaining data is still available on the inner stream
+        let mut stream = limiter.into_inner();
+        stream.read_exact(&mut buf[..4]).await.unwrap();
+        assert_eq!(&buf[..4], b"rbaz");
+    }
+
+    #[tokio::test]
+    async fn test_write_limiter() {
+        let stream = Builder::new().write(b"foo").write(b"ba").build();
+
+        let mut limiter = BytesRWLimiter::new(stream, None, Some(5));
+
+        assert_eq!(limiter.write(b"foo").await.unwrap(), 3);
+        assert_eq!(limiter.write_remaining(), Some(2));
+
+        // write is truncated to the remaining quota
+        assert_eq!(limiter.write(b"barbaz").await.unwrap(), 2);
+        assert_eq!(limiter.write_remaining(), Some(0));
+
+        assert_quota_exceeded(limiter.write(b"baz").await.unwrap_err());
+        assert_eq!(limiter.read_remaining(), None);
+    }
+
+    #[tokio::test]
+    async fn test_limiter_handle_top_up() {
+        let stream = Builder::new().write(b"foo").write(b"bar").build();
+
+        let limiter = BytesRWLimiter::new(stream, None, Some(3));
+        let handle = limiter.handle();
+
+        let mut limiter = limiter;
+        limiter.write_all(b"foo").await.unwrap();
+        assert_eq!(handle.write_remaining(), Some(0));
+        assert_quota_exceeded(limiter.write(b"bar").await.unwrap_err());
+
+        handle.add_write_quota(3);
+        assert_eq!(handle.write_remaining(), Some(3));
+        limiter.write_all(b"bar").await.unwrap();
+        assert_eq!(handle.write_remaining(), Some(0));
+
+        // unlimited direction cannot be topped up
+        handle.add_read_quota(3);
+        assert_eq!(handle.read_remaining(), None);
+    }
+}
@@ -5,10 +5,24 @@
 mod tracker;
 #[doc(inline)]
 pub use tracker::{
-    BytesRWTrackerHandle, IncomingBytesTrackerLayer, IncomingBytesTrackerService,
-    OutgoingBytesTrackerLayer, OutgoingBytesTrackerService,
+    BytesLimitExceeded, BytesRWCloseReason, BytesRWReport, BytesRWTracker, BytesRWTrackerHandle,
+    IncomingBytesTrackerLayer, IncomingBytesTrackerService, LatencyTracker, LatencyTrackerHandle,
+    LatencyTrackerLayer, LatencyTrackerService, OutgoingBytesTrackerLayer,
+    OutgoingBytesTrackerService, TunnelId, TunnelRegistry, TunnelStats, TunnelTotals,
 };
 
+mod limit;
+#[doc(inline)]
+pub use limit::{BytesRWLimiter, BytesRWLimiterHandle, QuotaExceeded};
+
+mod throttle;
+#[doc(inline)]
+pub use throttle::{ThrottledStream, ThrottledStreamHandle};
+
+mod timeout;
+#[doc(inline)]
+pub use timeout::IdleTimeoutStream;
+
 #[cfg(feature = "http")]
 pub mod http;
 
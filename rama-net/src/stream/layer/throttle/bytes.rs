@@ -0,0 +1,380 @@
+//! Provides [`ThrottledStream`] which wraps a [`AsyncRead`] and/or [`AsyncWrite`]
+//! in order to limit the rate (bytes per second) at which bytes are read and/or written.
+//!
+//! Use [`ThrottledStream::handle`] to get a [`ThrottledStreamHandle`], a requirement
+//! to change the rates even though the [`ThrottledStream`] is consumed by a protocol consumer.
+//! Combined with a [`BytesRWTrackerHandle`] this can for example be used to deprioritize
+//! a connection once it transferred a certain amount of bytes.
+//!
+//! [`AsyncRead`]: crate::stream::AsyncRead
+//! [`AsyncWrite`]: crate::stream::AsyncWrite
+//! [`BytesRWTrackerHandle`]: crate::stream::layer::BytesRWTrackerHandle
+
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use pin_project_lite::pin_project;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep},
+};
+
+pin_project! {
+    /// A wrapper around a [`AsyncRead`] and/or [`AsyncWrite`] that limits
+    /// the rate at which bytes are read and/or written using a token bucket per direction.
+    ///
+    /// Each bucket holds at most `burst` tokens (bytes) and is refilled
+    /// at the configured rate (bytes per second). Reads and writes are truncated to the
+    /// available tokens, and in case no tokens are available a timer is registered
+    /// to wake up the task once a token becomes available again.
+    ///
+    /// Use [`ThrottledStream::handle`] to get a [`ThrottledStreamHandle`] in order
+    /// to change the rates even though the [`ThrottledStream`] is consumed by a protocol consumer.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub struct ThrottledStream<S> {
+        read: Arc<Mutex<TokenBucket>>,
+        written: Arc<Mutex<TokenBucket>>,
+        read_sleep: Option<Pin<Box<Sleep>>>,
+        write_sleep: Option<Pin<Box<Sleep>>>,
+        #[pin]
+        stream: S,
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for ThrottledStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThrottledStream")
+            .field("read", &self.read)
+            .field("written", &self.written)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> ThrottledStream<S> {
+    /// Create a new [`ThrottledStream`] that wraps the
+    /// given [`AsyncRead`] and/or [`AsyncWrite`].
+    ///
+    /// The rates are expressed in bytes per second, where a rate of `None`
+    /// means that direction is not throttled. The `burst` is the maximum amount
+    /// of bytes that can be read or written at once after being idle.
+    ///
+    /// A rate or burst of `0` is treated as `1`.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn new(
+        stream: S,
+        read_rate: Option<usize>,
+        write_rate: Option<usize>,
+        burst: usize,
+    ) -> Self {
+        Self {
+            read: Arc::new(Mutex::new(TokenBucket::new(read_rate, burst))),
+            written: Arc::new(Mutex::new(TokenBucket::new(write_rate, burst))),
+            read_sleep: None,
+            write_sleep: None,
+            stream,
+        }
+    }
+
+    /// Get the read rate in bytes per second, `None` if not throttled.
+    pub fn read_rate(&self) -> Option<usize> {
+        self.read.lock().rate
+    }
+
+    /// Get the write rate in bytes per second, `None` if not throttled.
+    pub fn write_rate(&self) -> Option<usize> {
+        self.written.lock().rate
+    }
+
+    /// Get a [`ThrottledStreamHandle`] that can be used to change the rates
+    /// even though the stream is consumed by a protocol consumer in a later stage.
+    pub fn handle(&self) -> ThrottledStreamHandle {
+        ThrottledStreamHandle {
+            read: self.read.clone(),
+            written: self.written.clone(),
+        }
+    }
+
+    /// Get the inner [`AsyncRead`] and/or [`AsyncWrite`] stream.
+    /// Dropping the throttling for this stream.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> AsyncRead for ThrottledStream<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.as_mut().project();
+        let available = match futures_lite::ready!(poll_tokens(this.read, this.read_sleep, cx)) {
+            None => return this.stream.poll_read(cx, buf),
+            Some(available) => available,
+        };
+
+        let bytes_read = if available < buf.remaining() {
+            let mut limited_buf = ReadBuf::new(buf.initialize_unfilled_to(available));
+            futures_lite::ready!(this.stream.poll_read(cx, &mut limited_buf))?;
+            let bytes_read = limited_buf.filled().len();
+            buf.advance(bytes_read);
+            bytes_read
+        } else {
+            let size = buf.filled().len();
+            futures_lite::ready!(this.stream.poll_read(cx, buf))?;
+            buf.filled().len().saturating_sub(size)
+        };
+
+        this.read.lock().consume(bytes_read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for ThrottledStream<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.as_mut().project();
+        let buf = match futures_lite::ready!(poll_tokens(this.written, this.write_sleep, cx)) {
+            None => return this.stream.poll_write(cx, buf),
+            Some(available) => &buf[..buf.len().min(available)],
+        };
+
+        let bytes_written = futures_lite::ready!(this.stream.poll_write(cx, buf))?;
+        this.written.lock().consume(bytes_written);
+        Poll::Ready(Ok(bytes_written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_shutdown(cx)
+    }
+}
+
+/// Wait until at least one token is available in the bucket,
+/// returning the amount of available tokens, or `None` if not throttled.
+fn poll_tokens(
+    bucket: &Mutex<TokenBucket>,
+    sleep: &mut Option<Pin<Box<Sleep>>>,
+    cx: &mut Context<'_>,
+) -> Poll<Option<usize>> {
+    loop {
+        let now = Instant::now();
+        let wait = match bucket.lock().available(now) {
+            Tokens::Unlimited => return Poll::Ready(None),
+            Tokens::Available(available) => return Poll::Ready(Some(available)),
+            Tokens::Wait(wait) => wait,
+        };
+
+        let sleep = match sleep {
+            Some(sleep) => {
+                sleep.as_mut().reset(now + wait);
+                sleep
+            }
+            None => sleep.insert(Box::pin(tokio::time::sleep(wait))),
+        };
+        futures_lite::ready!(sleep.as_mut().poll(cx));
+    }
+}
+
+/// A handle to a throttled stream that can be used to change the rates
+/// even though the stream is consumed by a protocol consumer.
+///
+/// A rate change takes effect on the next read or write,
+/// a read or write already waiting for tokens finishes its wait first.
+#[derive(Debug, Clone)]
+pub struct ThrottledStreamHandle {
+    read: Arc<Mutex<TokenBucket>>,
+    written: Arc<Mutex<TokenBucket>>,
+}
+
+impl ThrottledStreamHandle {
+    /// Get the read rate in bytes per second, `None` if not throttled.
+    pub fn read_rate(&self) -> Option<usize> {
+        self.read.lock().rate
+    }
+
+    /// Get the write rate in bytes per second, `None` if not throttled.
+    pub fn write_rate(&self) -> Option<usize> {
+        self.written.lock().rate
+    }
+
+    /// Set the read rate in bytes per second, `None` to no longer throttle reads.
+    pub fn set_read_rate(&self, rate: Option<usize>) {
+        self.read.lock().set_rate(rate, Instant::now())
+    }
+
+    /// Set the write rate in bytes per second, `None` to no longer throttle writes.
+    pub fn set_write_rate(&self, rate: Option<usize>) {
+        self.written.lock().set_rate(rate, Instant::now())
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: Option<usize>,
+    burst: usize,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug)]
+enum Tokens {
+    Unlimited,
+    Available(usize),
+    Wait(Duration),
+}
+
+impl TokenBucket {
+    fn new(rate: Option<usize>, burst: usize) -> Self {
+        let burst = burst.max(1);
+        Self {
+            rate: rate.map(|rate| rate.max(1)),
+            burst,
+            tokens: burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn set_rate(&mut self, rate: Option<usize>, now: Instant) {
+        self.refill(now);
+        self.rate = rate.map(|rate| rate.max(1));
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(rate) = self.rate {
+            let elapsed = now.saturating_duration_since(self.last_refill);
+            self.tokens = elapsed
+                .as_secs_f64()
+                .mul_add(rate as f64, self.tokens)
+                .min(self.burst as f64);
+        }
+        self.last_refill = now;
+    }
+
+    fn available(&mut self, now: Instant) -> Tokens {
+        let Some(rate) = self.rate else {
+            return Tokens::Unlimited;
+        };
+        self.refill(now);
+        if self.tokens >= 1. {
+            Tokens::Available(self.tokens as usize)
+        } else {
+            Tokens::Wait(Duration::from_secs_f64((1. - self.tokens) / rate as f64))
+        }
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        if self.rate.is_some() {
+            self.tokens -= bytes as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn assert_elapsed(start: Instant, expected: Duration) {
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= expected && elapsed < expected + Duration::from_millis(50),
+            "{elapsed:?} ~ {expected:?}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_read() {
+        let (mut client, server) = tokio::io::duplex(64);
+        client.write_all(&[b'a'; 30]).await.unwrap();
+
+        let mut stream = ThrottledStream::new(server, Some(10), None, 10);
+        let mut buf = [0u8; 30];
+
+        let start = Instant::now();
+        // burst is available immediately, remaining 20 bytes take 2 seconds
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_elapsed(start, Duration::from_secs(2));
+        assert_eq!(buf, [b'a'; 30]);
+
+        // writes are not throttled
+        let start = Instant::now();
+        stream.write_all(&[b'b'; 30]).await.unwrap();
+        assert_elapsed(start, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_write() {
+        let (client, mut server) = tokio::io::duplex(64);
+
+        let mut stream = ThrottledStream::new(client, None, Some(20), 5);
+
+        let start = Instant::now();
+        // burst is available immediately, remaining 40 bytes take 2 seconds
+        stream.write_all(&[b'a'; 45]).await.unwrap();
+        assert_elapsed(start, Duration::from_secs(2));
+
+        let mut buf = [0u8; 45];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [b'a'; 45]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_handle_set_rate() {
+        let (client, mut server) = tokio::io::duplex(256);
+
+        let mut stream = ThrottledStream::new(client, Some(1), Some(10), 10);
+        let handle = stream.handle();
+        assert_eq!(handle.read_rate(), Some(1));
+        assert_eq!(handle.write_rate(), Some(10));
+
+        let start = Instant::now();
+        stream.write_all(&[b'a'; 20]).await.unwrap();
+        assert_elapsed(start, Duration::from_secs(1));
+
+        // deprioritize the connection
+        handle.set_write_rate(Some(5));
+        assert_eq!(stream.write_rate(), Some(5));
+        let start = Instant::now();
+        stream.write_all(&[b'a'; 10]).await.unwrap();
+        assert_elapsed(start, Duration::from_secs(2));
+
+        // lift the throttle
+        handle.set_write_rate(None);
+        let start = Instant::now();
+        stream.write_all(&[b'a'; 200]).await.unwrap();
+        assert_elapsed(start, Duration::ZERO);
+
+        let mut buf = vec![0u8; 230];
+        server.read_exact(&mut buf).await.unwrap();
+    }
+}
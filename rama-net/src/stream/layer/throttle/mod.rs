@@ -0,0 +1,3 @@
+mod bytes;
+#[doc(inline)]
+pub use bytes::{ThrottledStream, ThrottledStreamHandle};
@@ -0,0 +1,207 @@
+//! Provides [`IdleTimeoutStream`] which wraps a [`AsyncRead`] and/or [`AsyncWrite`]
+//! in order to fail pending reads once no bytes flowed for a configured duration.
+//!
+//! Use a [`BytesRWTrackerHandle`] in case you wish to inspect the last activity
+//! of a stream from elsewhere (e.g. to reap idle connections from a separate task).
+//!
+//! [`AsyncRead`]: crate::stream::AsyncRead
+//! [`AsyncWrite`]: crate::stream::AsyncWrite
+//! [`BytesRWTrackerHandle`]: crate::stream::layer::BytesRWTrackerHandle
+
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use pin_project_lite::pin_project;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep},
+};
+
+pin_project! {
+    /// A wrapper around a [`AsyncRead`] and/or [`AsyncWrite`] that fails pending reads
+    /// with an [`io::Error`] of kind [`io::ErrorKind::TimedOut`] once no bytes were read
+    /// or written for the configured idle timeout.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub struct IdleTimeoutStream<S> {
+        timeout: Duration,
+        last_activity: Instant,
+        sleep: Option<Pin<Box<Sleep>>>,
+        #[pin]
+        stream: S,
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for IdleTimeoutStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdleTimeoutStream")
+            .field("timeout", &self.timeout)
+            .field("last_activity", &self.last_activity)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> IdleTimeoutStream<S> {
+    /// Create a new [`IdleTimeoutStream`] that wraps the
+    /// given [`AsyncRead`] and/or [`AsyncWrite`],
+    /// failing pending reads once idle for the given duration.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn new(stream: S, timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_activity: Instant::now(),
+            sleep: None,
+            stream,
+        }
+    }
+
+    /// Get the idle timeout of this stream.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Get the [`Instant`] at which bytes were last read or written,
+    /// or at which the stream was created in case no bytes flowed yet.
+    pub fn last_activity(&self) -> Instant {
+        self.last_activity
+    }
+
+    /// Get the inner [`AsyncRead`] and/or [`AsyncWrite`] stream.
+    /// Dropping the idle timeout for this stream.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> AsyncRead for IdleTimeoutStream<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.as_mut().project();
+        let size = buf.filled().len();
+        match this.stream.poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                if buf.filled().len() > size {
+                    *this.last_activity = Instant::now();
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => {
+                let deadline = *this.last_activity + *this.timeout;
+                let sleep = match this.sleep {
+                    Some(sleep) => {
+                        if sleep.deadline() != deadline {
+                            sleep.as_mut().reset(deadline);
+                        }
+                        sleep
+                    }
+                    None => this
+                        .sleep
+                        .insert(Box::pin(tokio::time::sleep_until(deadline))),
+                };
+                futures_lite::ready!(sleep.as_mut().poll(cx));
+                Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "stream idle timeout elapsed",
+                )))
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for IdleTimeoutStream<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.as_mut().project();
+        let bytes_written = futures_lite::ready!(this.stream.poll_write(cx, buf))?;
+        if bytes_written > 0 {
+            *this.last_activity = Instant::now();
+        }
+        Poll::Ready(Ok(bytes_written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_read() {
+        let (_client, server) = tokio::io::duplex(64);
+
+        let mut stream = IdleTimeoutStream::new(server, Duration::from_secs(5));
+        let mut buf = [0u8; 3];
+
+        let start = Instant::now();
+        let err = stream.read_exact(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_reset_by_activity() {
+        let (mut client, server) = tokio::io::duplex(64);
+
+        let mut stream = IdleTimeoutStream::new(server, Duration::from_secs(5));
+        let start = Instant::now();
+
+        let writer = tokio::spawn(async move {
+            for _ in 0..3 {
+                tokio::time::sleep(Duration::from_secs(3)).await;
+                client.write_all(b"foo").await.unwrap();
+            }
+            client
+        });
+
+        let mut buf = [0u8; 3];
+        for _ in 0..3 {
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"foo");
+        }
+        assert_eq!(stream.last_activity(), start + Duration::from_secs(9));
+
+        // writes count as activity as well
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        stream.write_all(b"bar").await.unwrap();
+
+        let _client = writer.await.unwrap();
+        let err = stream.read_exact(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(start.elapsed(), Duration::from_secs(18));
+    }
+}
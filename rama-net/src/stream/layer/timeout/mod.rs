@@ -0,0 +1,3 @@
+mod idle;
+#[doc(inline)]
+pub use idle::IdleTimeoutStream;
@@ -6,6 +6,21 @@
 //! is consumed by a protocol consumer, which is for example the case when you wish
 //! to track the bytes read and/or written for a Tcp stream that is owned by a Tls stream.
 //!
+//! Use [`BytesRWTracker::with_window`] in case you also wish to know the
+//! throughput (bytes per second) over a sliding window of time.
+//!
+//! Use [`BytesRWTracker::with_read_limit`] and/or [`BytesRWTracker::with_write_limit`]
+//! in case you wish to cap the total number of bytes read and/or written.
+//!
+//! Use [`BytesRWTracker::with_histogram`] in case you also wish to know the
+//! distribution of the chunk sizes read and written, e.g. to tune buffer sizes.
+//!
+//! Use [`BytesRWTracker::with_activity`] in case you also wish to know when
+//! bytes were last read and written, e.g. to reap idle connections.
+//!
+//! Use [`BytesRWTracker::with_report`] in case you wish to receive a final
+//! [`BytesRWReport`] once the tracked stream is gone, without polling a handle.
+//!
 //! [`AsyncRead`]: crate::stream::AsyncRead
 //! [`AsyncWrite`]: crate::stream::AsyncWrite
 
@@ -13,15 +28,22 @@ use std::{
     fmt, io,
     pin::Pin,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
+use parking_lot::Mutex;
 use pin_project_lite::pin_project;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
+rama_utils::macros::error::static_str_error! {
+    #[doc = "bytes limit exceeded"]
+    pub struct BytesLimitExceeded;
+}
+
 pin_project! {
     /// A wrapper around a [`AsyncRead`] and/or [`AsyncWrite`] that tracks the number
     /// of bytes read and/or written.
@@ -33,8 +55,13 @@ pin_project! {
     /// [`AsyncRead`]: crate::stream::AsyncRead
     /// [`AsyncWrite`]: crate::stream::AsyncWrite
     pub struct BytesRWTracker<S> {
-        read: Arc<AtomicUsize>,
-        written: Arc<AtomicUsize>,
+        counters: Arc<Counters>,
+        rate: Option<Arc<RateWindow>>,
+        histogram: Option<Arc<ChunkHistogram>>,
+        activity: Option<Arc<Activity>>,
+        read_limit: Option<BytesLimit>,
+        write_limit: Option<BytesLimit>,
+        report: Option<ReportOnDrop>,
         #[pin]
         stream: S,
     }
@@ -43,8 +70,13 @@ pin_project! {
 impl<S: fmt::Debug> fmt::Debug for BytesRWTracker<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("BytesRWTracker")
-            .field("read", &self.read)
-            .field("written", &self.written)
+            .field("counters", &self.counters)
+            .field("rate", &self.rate)
+            .field("histogram", &self.histogram)
+            .field("activity", &self.activity)
+            .field("read_limit", &self.read_limit)
+            .field("write_limit", &self.write_limit)
+            .field("report", &self.report)
             .field("stream", &self.stream)
             .finish()
     }
@@ -58,20 +90,221 @@ impl<S> BytesRWTracker<S> {
     /// [`AsyncWrite`]: crate::stream::AsyncWrite
     pub fn new(stream: S) -> Self {
         Self {
-            read: Arc::new(AtomicUsize::new(0)),
-            written: Arc::new(AtomicUsize::new(0)),
+            counters: Arc::new(Counters::default()),
+            rate: None,
+            histogram: None,
+            activity: None,
+            read_limit: None,
+            write_limit: None,
+            report: None,
             stream,
         }
     }
 
+    /// Create a new [`BytesRWTracker`] that wraps the
+    /// given [`AsyncRead`] and/or [`AsyncWrite`],
+    /// and which fails reads once the given number of bytes was read.
+    ///
+    /// A read crossing the limit is truncated to it, after which the next read
+    /// returns an [`io::Error`] of kind [`io::ErrorKind::Other`] wrapping
+    /// a [`BytesLimitExceeded`] error. The limit applies to the total number
+    /// of bytes read by this tracker, it is not lifted by [`BytesRWTracker::reset`].
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn with_read_limit(stream: S, limit: usize) -> Self {
+        let mut tracker = Self::new(stream);
+        tracker.read_limit = Some(BytesLimit::new(limit));
+        tracker
+    }
+
+    /// Create a new [`BytesRWTracker`] that wraps the
+    /// given [`AsyncRead`] and/or [`AsyncWrite`],
+    /// and which fails writes once the given number of bytes was written.
+    ///
+    /// A write crossing the limit is truncated to it, after which the next write
+    /// returns an [`io::Error`] of kind [`io::ErrorKind::Other`] wrapping
+    /// a [`BytesLimitExceeded`] error. The limit applies to the total number
+    /// of bytes written by this tracker, it is not lifted by [`BytesRWTracker::reset`].
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn with_write_limit(stream: S, limit: usize) -> Self {
+        let mut tracker = Self::new(stream);
+        tracker.write_limit = Some(BytesLimit::new(limit));
+        tracker
+    }
+
+    /// Track the read and write throughput over a sliding window
+    /// of the given duration, replacing any previously configured window.
+    ///
+    /// See [`BytesRWTracker::read_rate`] and [`BytesRWTracker::write_rate`].
+    /// [`BytesRWTrackerHandle`]s obtained prior to calling this method
+    /// will not report the throughput of this window.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.rate = Some(Arc::new(RateWindow::new(window)));
+        self
+    }
+
+    /// Track the distribution of the chunk sizes read and written,
+    /// replacing any previously configured histogram.
+    ///
+    /// The given buckets are the (inclusive) upper bounds of the chunk sizes
+    /// counted by each bucket, an overflow bucket with [`usize::MAX`] as its bound
+    /// is added for chunks larger than the largest given bound.
+    /// Each `poll_read` and `poll_write` completion which read or wrote
+    /// at least one byte is counted in the first bucket that fits it.
+    ///
+    /// See [`BytesRWTracker::read_histogram`] and [`BytesRWTracker::written_histogram`].
+    /// [`BytesRWTrackerHandle`]s obtained prior to calling this method
+    /// will not report this histogram.
+    pub fn with_histogram(mut self, buckets: &[usize]) -> Self {
+        self.histogram = Some(Arc::new(ChunkHistogram::new(buckets)));
+        self
+    }
+
+    /// Track the instants at which bytes were last read and written.
+    ///
+    /// See [`BytesRWTracker::last_read_at`] and [`BytesRWTracker::last_write_at`].
+    /// [`BytesRWTrackerHandle`]s obtained prior to calling this method
+    /// will not report these instants.
+    pub fn with_activity(mut self) -> Self {
+        self.activity = Some(Arc::new(Activity::new()));
+        self
+    }
+
+    /// Call the given function with a [`BytesRWReport`] once this tracker is dropped,
+    /// replacing any previously configured report function.
+    ///
+    /// The function is called exactly once, also in case the tracker is dropped
+    /// because the task owning it was aborted, or when the inner stream is taken
+    /// using [`BytesRWTracker::into_inner`], which ends the tracking of that stream.
+    pub fn with_report(mut self, f: impl FnOnce(BytesRWReport) + Send + 'static) -> Self {
+        self.report = Some(ReportOnDrop {
+            f: Some(Box::new(f)),
+            counters: self.counters.clone(),
+            start: Instant::now(),
+            error: None,
+        });
+        self
+    }
+
     /// Get the number of bytes read (so far).
     pub fn read(&self) -> usize {
-        self.read.load(Ordering::Acquire)
+        self.counters.read.load(Ordering::Acquire)
     }
 
     /// Get the number of bytes written (so far).
     pub fn written(&self) -> usize {
-        self.written.load(Ordering::Acquire)
+        self.counters.written.load(Ordering::Acquire)
+    }
+
+    /// Get the number of flushes which completed successfully (so far).
+    pub fn flushes(&self) -> usize {
+        self.counters.flushes.load(Ordering::Acquire)
+    }
+
+    /// Get the number of shutdowns which completed successfully (so far).
+    pub fn shutdowns(&self) -> usize {
+        self.counters.shutdowns.load(Ordering::Acquire)
+    }
+
+    /// Get the size of the largest single write (so far)
+    /// accepted by the underlying stream.
+    pub fn max_write_chunk(&self) -> usize {
+        self.counters.max_write_chunk.load(Ordering::Acquire)
+    }
+
+    /// Get the [`Instant`] at which bytes were last read, `None` if nothing was read yet.
+    ///
+    /// Always `None` in case the activity is not tracked,
+    /// see [`BytesRWTracker::with_activity`].
+    pub fn last_read_at(&self) -> Option<Instant> {
+        self.activity
+            .as_ref()
+            .and_then(|activity| activity.last_read_at())
+    }
+
+    /// Get the [`Instant`] at which bytes were last written, `None` if nothing was written yet.
+    ///
+    /// Always `None` in case the activity is not tracked,
+    /// see [`BytesRWTracker::with_activity`].
+    pub fn last_write_at(&self) -> Option<Instant> {
+        self.activity
+            .as_ref()
+            .and_then(|activity| activity.last_write_at())
+    }
+
+    /// Returns `true` once a read reached the end of the stream (EOF),
+    /// meaning the peer closed its write side.
+    ///
+    /// A read which returns no bytes because the given buffer had no room left
+    /// is not considered to be an EOF.
+    pub fn is_read_closed(&self) -> bool {
+        self.counters.read_closed.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` once a shutdown of the write side completed successfully.
+    pub fn is_write_closed(&self) -> bool {
+        self.counters.write_closed.load(Ordering::Acquire)
+    }
+
+    /// Reset the number of bytes read and written to zero,
+    /// returning the previous `(read, written)` counts.
+    ///
+    /// Each counter is swapped with zero atomically, such that no bytes are
+    /// lost or counted twice. The reset is observed by all
+    /// [`BytesRWTrackerHandle`]s of this tracker.
+    pub fn reset(&self) -> (usize, usize) {
+        self.counters.reset()
+    }
+
+    /// Get the read throughput in bytes per second,
+    /// computed over the sliding rate window.
+    ///
+    /// The rate decays to `0.0` once no bytes were read for the duration of the window.
+    /// Always `0.0` in case no rate window was configured using
+    /// [`BytesRWTracker::with_window`].
+    pub fn read_rate(&self) -> f64 {
+        self.rate
+            .as_ref()
+            .map(|rate| rate.read_rate(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    /// Get the write throughput in bytes per second,
+    /// computed over the sliding rate window.
+    ///
+    /// The rate decays to `0.0` once no bytes were written for the duration of the window.
+    /// Always `0.0` in case no rate window was configured using
+    /// [`BytesRWTracker::with_window`].
+    pub fn write_rate(&self) -> f64 {
+        self.rate
+            .as_ref()
+            .map(|rate| rate.write_rate(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    /// Get the `(bucket bound, count)` pairs of the read chunk size histogram.
+    ///
+    /// Always empty in case no histogram was configured using
+    /// [`BytesRWTracker::with_histogram`].
+    pub fn read_histogram(&self) -> Vec<(usize, u64)> {
+        self.histogram
+            .as_ref()
+            .map(|histogram| histogram.snapshot(&histogram.read))
+            .unwrap_or_default()
+    }
+
+    /// Get the `(bucket bound, count)` pairs of the written chunk size histogram.
+    ///
+    /// Always empty in case no histogram was configured using
+    /// [`BytesRWTracker::with_histogram`].
+    pub fn written_histogram(&self) -> Vec<(usize, u64)> {
+        self.histogram
+            .as_ref()
+            .map(|histogram| histogram.snapshot(&histogram.written))
+            .unwrap_or_default()
     }
 
     /// Get a [`BytesRWTrackerHandle`] that can be used to get the number of bytes
@@ -79,8 +312,10 @@ impl<S> BytesRWTracker<S> {
     /// consumer in a later stage.
     pub fn handle(&self) -> BytesRWTrackerHandle {
         BytesRWTrackerHandle {
-            read: self.read.clone(),
-            written: self.written.clone(),
+            counters: self.counters.clone(),
+            rate: self.rate.clone(),
+            histogram: self.histogram.clone(),
+            activity: self.activity.clone(),
         }
     }
 
@@ -109,20 +344,60 @@ where
     ) -> Poll<io::Result<()>> {
         let this = self.as_mut().project();
         let size = buf.filled().len();
-        let res: Poll<Result<(), io::Error>> = this.stream.poll_read(cx, buf);
+        // a read into a full buffer returns nothing, without that being an EOF
+        let has_capacity = buf.remaining() > 0;
+        let remaining = this.read_limit.as_ref().map(BytesLimit::remaining);
+        let res: Poll<Result<(), io::Error>> = match remaining {
+            Some(0) => return Poll::Ready(Err(bytes_limit_exceeded())),
+            Some(remaining) if remaining < buf.remaining() => {
+                let mut limited_buf = ReadBuf::new(buf.initialize_unfilled_to(remaining));
+                let res = this.stream.poll_read(cx, &mut limited_buf);
+                let bytes_read = limited_buf.filled().len();
+                buf.advance(bytes_read);
+                res
+            }
+            _ => this.stream.poll_read(cx, buf),
+        };
+        if let (Poll::Ready(Err(err)), Some(report)) = (&res, this.report.as_mut()) {
+            report.error = Some(err.kind());
+        }
         if let Poll::Ready(Ok(_)) = res {
             let new_size = buf.filled().len();
             match new_size.cmp(&size) {
                 std::cmp::Ordering::Greater => {
                     let bytes_read = new_size - size;
-                    this.read.fetch_add(bytes_read, Ordering::AcqRel);
+                    this.counters.read.fetch_add(bytes_read, Ordering::AcqRel);
+                    if let Some(limit) = this.read_limit.as_mut() {
+                        limit.consume(bytes_read);
+                    }
+                    // only get the current time in case it is needed
+                    if this.activity.is_some() || this.rate.is_some() {
+                        let now = Instant::now();
+                        if let Some(activity) = this.activity.as_ref() {
+                            activity.record_read(now);
+                        }
+                        if let Some(rate) = this.rate.as_ref() {
+                            rate.record_read(now, bytes_read);
+                        }
+                    }
+                    if let Some(histogram) = this.histogram.as_ref() {
+                        histogram.record(&histogram.read, bytes_read);
+                    }
                 }
                 std::cmp::Ordering::Less => {
                     tracing::error!(
                         "BytesRWTracker: poll_read returned Ok(()) with filled buffer smaller then before");
                 }
+                std::cmp::Ordering::Equal if has_capacity => {
+                    tracing::trace!(
+                        "BytesRWTracker: poll_read returned Ok(()) with nothing read: EOF"
+                    );
+                    this.counters.read_closed.store(true, Ordering::Release);
+                }
                 std::cmp::Ordering::Equal => {
-                    tracing::trace!("BytesRWTracker: poll_read returned Ok(()) with nothing read");
+                    tracing::trace!(
+                        "BytesRWTracker: poll_read returned Ok(()) with nothing read: buffer full"
+                    );
                 }
             }
         }
@@ -140,19 +415,47 @@ where
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
         let this = self.as_mut().project();
+        let buf = match this.write_limit.as_ref().map(BytesLimit::remaining) {
+            Some(0) => return Poll::Ready(Err(bytes_limit_exceeded())),
+            Some(remaining) => &buf[..buf.len().min(remaining)],
+            None => buf,
+        };
         let res: Poll<Result<usize, io::Error>> = this.stream.poll_write(cx, buf);
+        if let (Poll::Ready(Err(err)), Some(report)) = (&res, this.report.as_mut()) {
+            report.error = Some(err.kind());
+        }
         if let Poll::Ready(Ok(bytes_written)) = res {
-            this.written.fetch_add(bytes_written, Ordering::AcqRel);
+            if let Some(limit) = this.write_limit.as_mut() {
+                limit.consume(bytes_written);
+            }
+            record_written(
+                this.counters,
+                this.activity,
+                this.rate,
+                this.histogram,
+                bytes_written,
+            );
         }
         res
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        self.project().stream.poll_flush(cx)
+        let this = self.project();
+        let res = this.stream.poll_flush(cx);
+        if let Poll::Ready(Ok(())) = res {
+            this.counters.flushes.fetch_add(1, Ordering::AcqRel);
+        }
+        res
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        self.project().stream.poll_shutdown(cx)
+        let this = self.project();
+        let res = this.stream.poll_shutdown(cx);
+        if let Poll::Ready(Ok(())) = res {
+            this.counters.shutdowns.fetch_add(1, Ordering::AcqRel);
+            this.counters.write_closed.store(true, Ordering::Release);
+        }
+        res
     }
 
     fn poll_write_vectored(
@@ -161,9 +464,33 @@ where
         bufs: &[io::IoSlice<'_>],
     ) -> Poll<Result<usize, io::Error>> {
         let this = self.as_mut().project();
-        let res: Poll<Result<usize, io::Error>> = this.stream.poll_write_vectored(cx, bufs);
+        let remaining = this.write_limit.as_ref().map(BytesLimit::remaining);
+        let res: Poll<Result<usize, io::Error>> = match remaining {
+            Some(0) => return Poll::Ready(Err(bytes_limit_exceeded())),
+            Some(remaining) if bufs.iter().map(|buf| buf.len()).sum::<usize>() > remaining => {
+                // only in case the limit is crossed are the slices truncated,
+                // which requires a (small) allocation for the truncated slices
+                this.stream
+                    .poll_write_vectored(cx, &truncate_io_slices(bufs, remaining))
+            }
+            _ => this.stream.poll_write_vectored(cx, bufs),
+        };
+        if let (Poll::Ready(Err(err)), Some(report)) = (&res, this.report.as_mut()) {
+            report.error = Some(err.kind());
+        }
         if let Poll::Ready(Ok(bytes_written)) = res {
-            this.written.fetch_add(bytes_written, Ordering::AcqRel);
+            // the inner stream might only have consumed part of the slices,
+            // only what it reports to have written is accounted for
+            if let Some(limit) = this.write_limit.as_mut() {
+                limit.consume(bytes_written);
+            }
+            record_written(
+                this.counters,
+                this.activity,
+                this.rate,
+                this.histogram,
+                bytes_written,
+            );
         }
         res
     }
@@ -178,19 +505,467 @@ where
 /// consumer.
 #[derive(Debug, Clone)]
 pub struct BytesRWTrackerHandle {
-    read: Arc<AtomicUsize>,
-    written: Arc<AtomicUsize>,
+    counters: Arc<Counters>,
+    rate: Option<Arc<RateWindow>>,
+    histogram: Option<Arc<ChunkHistogram>>,
+    activity: Option<Arc<Activity>>,
 }
 
 impl BytesRWTrackerHandle {
     /// Get the number of bytes read (so far).
     pub fn read(&self) -> usize {
-        self.read.load(Ordering::Acquire)
+        self.counters.read.load(Ordering::Acquire)
     }
 
     /// Get the number of bytes written (so far).
     pub fn written(&self) -> usize {
-        self.written.load(Ordering::Acquire)
+        self.counters.written.load(Ordering::Acquire)
+    }
+
+    /// Get the number of flushes which completed successfully (so far).
+    pub fn flushes(&self) -> usize {
+        self.counters.flushes.load(Ordering::Acquire)
+    }
+
+    /// Get the number of shutdowns which completed successfully (so far).
+    pub fn shutdowns(&self) -> usize {
+        self.counters.shutdowns.load(Ordering::Acquire)
+    }
+
+    /// Get the size of the largest single write (so far)
+    /// accepted by the underlying stream.
+    pub fn max_write_chunk(&self) -> usize {
+        self.counters.max_write_chunk.load(Ordering::Acquire)
+    }
+
+    /// Get the [`Instant`] at which bytes were last read, `None` if nothing was read yet.
+    ///
+    /// Always `None` in case the activity is not tracked,
+    /// see [`BytesRWTracker::with_activity`].
+    pub fn last_read_at(&self) -> Option<Instant> {
+        self.activity
+            .as_ref()
+            .and_then(|activity| activity.last_read_at())
+    }
+
+    /// Get the [`Instant`] at which bytes were last written, `None` if nothing was written yet.
+    ///
+    /// Always `None` in case the activity is not tracked,
+    /// see [`BytesRWTracker::with_activity`].
+    pub fn last_write_at(&self) -> Option<Instant> {
+        self.activity
+            .as_ref()
+            .and_then(|activity| activity.last_write_at())
+    }
+
+    /// Returns `true` once a read reached the end of the stream (EOF),
+    /// meaning the peer closed its write side.
+    ///
+    /// Together with [`Self::is_write_closed`] this allows to tell
+    /// a finished stream apart from one which is merely idle,
+    /// without having to wait for the owner of the stream.
+    pub fn is_read_closed(&self) -> bool {
+        self.counters.read_closed.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` once a shutdown of the write side completed successfully.
+    pub fn is_write_closed(&self) -> bool {
+        self.counters.write_closed.load(Ordering::Acquire)
+    }
+
+    /// Reset the number of bytes read and written to zero,
+    /// returning the previous `(read, written)` counts.
+    ///
+    /// Each counter is swapped with zero atomically, such that no bytes are
+    /// lost or counted twice. The reset is observed by the tracker and
+    /// all other handles of it, useful to measure per-request byte counts
+    /// on a long-lived connection.
+    pub fn reset(&self) -> (usize, usize) {
+        self.counters.reset()
+    }
+
+    /// Get the read throughput in bytes per second,
+    /// computed over the sliding rate window.
+    ///
+    /// The rate decays to `0.0` once no bytes were read for the duration of the window.
+    /// Always `0.0` in case the tracker has no rate window configured.
+    pub fn read_rate(&self) -> f64 {
+        self.rate
+            .as_ref()
+            .map(|rate| rate.read_rate(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    /// Get the write throughput in bytes per second,
+    /// computed over the sliding rate window.
+    ///
+    /// The rate decays to `0.0` once no bytes were written for the duration of the window.
+    /// Always `0.0` in case the tracker has no rate window configured.
+    pub fn write_rate(&self) -> f64 {
+        self.rate
+            .as_ref()
+            .map(|rate| rate.write_rate(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    /// Get the `(bucket bound, count)` pairs of the read chunk size histogram.
+    ///
+    /// Always empty in case the tracker has no histogram configured.
+    pub fn read_histogram(&self) -> Vec<(usize, u64)> {
+        self.histogram
+            .as_ref()
+            .map(|histogram| histogram.snapshot(&histogram.read))
+            .unwrap_or_default()
+    }
+
+    /// Get the `(bucket bound, count)` pairs of the written chunk size histogram.
+    ///
+    /// Always empty in case the tracker has no histogram configured.
+    pub fn written_histogram(&self) -> Vec<(usize, u64)> {
+        self.histogram
+            .as_ref()
+            .map(|histogram| histogram.snapshot(&histogram.written))
+            .unwrap_or_default()
+    }
+}
+
+fn record_written(
+    counters: &Counters,
+    activity: &Option<Arc<Activity>>,
+    rate: &Option<Arc<RateWindow>>,
+    histogram: &Option<Arc<ChunkHistogram>>,
+    bytes_written: usize,
+) {
+    if bytes_written == 0 {
+        return;
+    }
+    counters.written.fetch_add(bytes_written, Ordering::AcqRel);
+    let max_write_chunk = &counters.max_write_chunk;
+    let mut current = max_write_chunk.load(Ordering::Acquire);
+    while bytes_written > current {
+        match max_write_chunk.compare_exchange_weak(
+            current,
+            bytes_written,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => break,
+            Err(actual) => current = actual,
+        }
+    }
+    // only get the current time in case it is needed
+    if activity.is_some() || rate.is_some() {
+        let now = Instant::now();
+        if let Some(activity) = activity.as_ref() {
+            activity.record_written(now);
+        }
+        if let Some(rate) = rate.as_ref() {
+            rate.record_written(now, bytes_written);
+        }
+    }
+    if let Some(histogram) = histogram.as_ref() {
+        histogram.record(&histogram.written, bytes_written);
+    }
+}
+
+/// Truncate the given slices such that their total length does not exceed `limit`.
+fn truncate_io_slices<'a>(bufs: &'a [io::IoSlice<'a>], mut limit: usize) -> Vec<io::IoSlice<'a>> {
+    let mut truncated = Vec::with_capacity(bufs.len());
+    for buf in bufs {
+        if limit == 0 {
+            break;
+        }
+        let len = buf.len().min(limit);
+        truncated.push(io::IoSlice::new(&buf[..len]));
+        limit -= len;
+    }
+    truncated
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The reason a tracked stream was closed, as far as known by the [`BytesRWTracker`].
+pub enum BytesRWCloseReason {
+    /// A read or write of the stream failed with an error of this kind.
+    Error(io::ErrorKind),
+    /// The peer closed its write side (a read reached EOF).
+    Eof,
+    /// The write side was shut down.
+    Shutdown,
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+/// Final report of a [`BytesRWTracker`],
+/// passed to the function configured using [`BytesRWTracker::with_report`].
+pub struct BytesRWReport {
+    /// The number of bytes read, since the last reset.
+    pub read: usize,
+    /// The number of bytes written, since the last reset.
+    pub written: usize,
+    /// The time elapsed between configuring the report,
+    /// using [`BytesRWTracker::with_report`], and the report.
+    pub duration: Duration,
+    /// The reason the stream was closed, `None` if unknown,
+    /// e.g. because it was dropped without being closed.
+    ///
+    /// An error takes precedence over an EOF, which takes precedence over a shutdown.
+    pub close_reason: Option<BytesRWCloseReason>,
+}
+
+/// Calls the report function of a [`BytesRWTracker`] once dropped,
+/// which happens together with the tracker or when its inner stream is taken.
+struct ReportOnDrop {
+    f: Option<Box<dyn FnOnce(BytesRWReport) + Send + 'static>>,
+    counters: Arc<Counters>,
+    start: Instant,
+    error: Option<io::ErrorKind>,
+}
+
+impl fmt::Debug for ReportOnDrop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReportOnDrop")
+            .field("reported", &self.f.is_none())
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl Drop for ReportOnDrop {
+    fn drop(&mut self) {
+        let Some(f) = self.f.take() else {
+            return;
+        };
+        let close_reason = if let Some(kind) = self.error {
+            Some(BytesRWCloseReason::Error(kind))
+        } else if self.counters.read_closed.load(Ordering::Acquire) {
+            Some(BytesRWCloseReason::Eof)
+        } else if self.counters.write_closed.load(Ordering::Acquire) {
+            Some(BytesRWCloseReason::Shutdown)
+        } else {
+            None
+        };
+        f(BytesRWReport {
+            read: self.counters.read.load(Ordering::Acquire),
+            written: self.counters.written.load(Ordering::Acquire),
+            duration: self.start.elapsed(),
+            close_reason,
+        });
+    }
+}
+
+/// Limit on the total number of bytes read or written by a [`BytesRWTracker`],
+/// counted separately from the (resettable) [`Counters`] shared with the handles.
+#[derive(Debug)]
+struct BytesLimit {
+    max: usize,
+    used: usize,
+}
+
+impl BytesLimit {
+    fn new(max: usize) -> Self {
+        Self { max, used: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.max.saturating_sub(self.used)
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        self.used = self.used.saturating_add(bytes);
+    }
+}
+
+fn bytes_limit_exceeded() -> io::Error {
+    io::Error::other(BytesLimitExceeded::new())
+}
+
+/// Counters which are always tracked, shared by a [`BytesRWTracker`]
+/// and its [`BytesRWTrackerHandle`]s within a single allocation.
+#[derive(Debug, Default)]
+struct Counters {
+    read: AtomicUsize,
+    written: AtomicUsize,
+    flushes: AtomicUsize,
+    shutdowns: AtomicUsize,
+    max_write_chunk: AtomicUsize,
+    read_closed: AtomicBool,
+    write_closed: AtomicBool,
+}
+
+impl Counters {
+    fn reset(&self) -> (usize, usize) {
+        (
+            self.read.swap(0, Ordering::AcqRel),
+            self.written.swap(0, Ordering::AcqRel),
+        )
+    }
+}
+
+/// Instants at which bytes were last read and written,
+/// stored as nanoseconds since activity tracking was enabled (plus one, zero meaning never),
+/// such that they can be updated atomically.
+#[derive(Debug)]
+struct Activity {
+    start: Instant,
+    last_read: AtomicU64,
+    last_written: AtomicU64,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_read: AtomicU64::new(0),
+            last_written: AtomicU64::new(0),
+        }
+    }
+
+    fn record_read(&self, now: Instant) {
+        self.last_read.fetch_max(self.encode(now), Ordering::AcqRel);
+    }
+
+    fn record_written(&self, now: Instant) {
+        self.last_written
+            .fetch_max(self.encode(now), Ordering::AcqRel);
+    }
+
+    fn last_read_at(&self) -> Option<Instant> {
+        self.decode(self.last_read.load(Ordering::Acquire))
+    }
+
+    fn last_write_at(&self) -> Option<Instant> {
+        self.decode(self.last_written.load(Ordering::Acquire))
+    }
+
+    fn encode(&self, instant: Instant) -> u64 {
+        let nanos = instant.saturating_duration_since(self.start).as_nanos();
+        u64::try_from(nanos).unwrap_or(u64::MAX - 1) + 1
+    }
+
+    fn decode(&self, value: u64) -> Option<Instant> {
+        (value != 0).then(|| self.start + Duration::from_nanos(value - 1))
+    }
+}
+
+/// Amount of slots the rate window is divided in.
+const RATE_WINDOW_SLOTS: usize = 16;
+
+/// Sliding window used to compute the read and write throughput,
+/// divided in a fixed amount of time slots in order to keep
+/// the memory footprint small and constant.
+#[derive(Debug)]
+struct RateWindow {
+    window: Duration,
+    slot_nanos: u64,
+    start: Instant,
+    read: Mutex<RateSlots>,
+    written: Mutex<RateSlots>,
+}
+
+/// Ring buffer of `(slot start, bytes)` snapshots.
+#[derive(Debug)]
+struct RateSlots([(Instant, usize); RATE_WINDOW_SLOTS]);
+
+impl RateWindow {
+    fn new(window: Duration) -> Self {
+        let start = Instant::now();
+        let slot_nanos = (window.as_nanos() / RATE_WINDOW_SLOTS as u128).clamp(1, u64::MAX as u128);
+        Self {
+            window,
+            slot_nanos: slot_nanos as u64,
+            start,
+            read: Mutex::new(RateSlots([(start, 0); RATE_WINDOW_SLOTS])),
+            written: Mutex::new(RateSlots([(start, 0); RATE_WINDOW_SLOTS])),
+        }
+    }
+
+    fn record_read(&self, now: Instant, bytes: usize) {
+        self.record(&self.read, now, bytes)
+    }
+
+    fn record_written(&self, now: Instant, bytes: usize) {
+        self.record(&self.written, now, bytes)
+    }
+
+    fn read_rate(&self, now: Instant) -> f64 {
+        self.rate(&self.read, now)
+    }
+
+    fn write_rate(&self, now: Instant) -> f64 {
+        self.rate(&self.written, now)
+    }
+
+    fn record(&self, slots: &Mutex<RateSlots>, now: Instant, bytes: usize) {
+        let index =
+            (now.saturating_duration_since(self.start).as_nanos() / self.slot_nanos as u128) as u64;
+        let slot_start = self.start + Duration::from_nanos(index.saturating_mul(self.slot_nanos));
+
+        let mut slots = slots.lock();
+        let slot = &mut slots.0[(index % RATE_WINDOW_SLOTS as u64) as usize];
+        if slot.0 != slot_start {
+            // slot is being reused for a new period of time
+            *slot = (slot_start, 0);
+        }
+        slot.1 += bytes;
+    }
+
+    fn rate(&self, slots: &Mutex<RateSlots>, now: Instant) -> f64 {
+        let window_secs = self.window.as_secs_f64();
+        if window_secs == 0. {
+            return 0.;
+        }
+
+        let bytes: usize = slots
+            .lock()
+            .0
+            .iter()
+            .filter(|(slot_start, _)| now.saturating_duration_since(*slot_start) < self.window)
+            .map(|(_, bytes)| *bytes)
+            .sum();
+
+        bytes as f64 / window_secs
+    }
+}
+
+/// Histogram of the chunk sizes read and written,
+/// counted per bucket using atomics such that it can be updated without locking.
+#[derive(Debug)]
+struct ChunkHistogram {
+    bounds: Box<[usize]>,
+    read: Box<[AtomicU64]>,
+    written: Box<[AtomicU64]>,
+}
+
+impl ChunkHistogram {
+    fn new(buckets: &[usize]) -> Self {
+        let mut bounds = buckets.to_vec();
+        bounds.sort_unstable();
+        bounds.dedup();
+        if bounds.last() != Some(&usize::MAX) {
+            bounds.push(usize::MAX);
+        }
+        let counters = || bounds.iter().map(|_| AtomicU64::new(0)).collect();
+        Self {
+            read: counters(),
+            written: counters(),
+            bounds: bounds.into_boxed_slice(),
+        }
+    }
+
+    fn record(&self, counters: &[AtomicU64], bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        let index = self.bounds.partition_point(|bound| *bound < bytes);
+        counters[index].fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn snapshot(&self, counters: &[AtomicU64]) -> Vec<(usize, u64)> {
+        self.bounds
+            .iter()
+            .zip(counters)
+            .map(|(bound, count)| (*bound, count.load(Ordering::Acquire)))
+            .collect()
     }
 }
 
@@ -413,4 +1188,617 @@ mod tests {
         t1.unwrap();
         t2.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_rw_tracker_rate() {
+        let stream = Builder::new().read(b"foo").write(b"barbaz").build();
+
+        let mut tracker = BytesRWTracker::new(stream).with_window(Duration::from_secs(60));
+        let handle = tracker.handle();
+        let mut buf = [0u8; 3];
+
+        assert_eq!(handle.read_rate(), 0.);
+        assert_eq!(handle.write_rate(), 0.);
+        tracker.read_exact(&mut buf).await.unwrap();
+        tracker.write_all(b"barbaz").await.unwrap();
+        assert_eq!(handle.read_rate(), 3. / 60.);
+        assert_eq!(handle.write_rate(), 6. / 60.);
+        assert_eq!(tracker.read_rate(), handle.read_rate());
+        assert_eq!(tracker.write_rate(), handle.write_rate());
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_rate_disabled() {
+        let stream = Builder::new().read(b"foo").build();
+
+        let mut tracker = BytesRWTracker::new(stream);
+        let handle = tracker.handle();
+        let mut buf = [0u8; 3];
+
+        tracker.read_exact(&mut buf).await.unwrap();
+        assert_eq!(handle.read(), 3);
+        assert_eq!(handle.read_rate(), 0.);
+        assert_eq!(handle.write_rate(), 0.);
+    }
+
+    #[test]
+    fn test_rate_window_slides() {
+        let rate = RateWindow::new(Duration::from_secs(16));
+        let start = rate.start;
+
+        rate.record_read(start, 160);
+        rate.record_read(start + Duration::from_secs(8), 160);
+        rate.record_written(start + Duration::from_secs(8), 32);
+
+        assert_eq!(rate.read_rate(start + Duration::from_secs(8)), 20.);
+        assert_eq!(rate.write_rate(start + Duration::from_secs(8)), 2.);
+
+        // first sample has left the window
+        assert_eq!(rate.read_rate(start + Duration::from_secs(20)), 10.);
+        assert_eq!(rate.write_rate(start + Duration::from_secs(20)), 2.);
+
+        // no traffic within the window
+        assert_eq!(rate.read_rate(start + Duration::from_secs(30)), 0.);
+        assert_eq!(rate.write_rate(start + Duration::from_secs(30)), 0.);
+
+        // slots are reused once the window has passed
+        rate.record_read(start + Duration::from_secs(32), 16);
+        assert_eq!(rate.read_rate(start + Duration::from_secs(32)), 1.);
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_rate_idle_decay() {
+        let stream = Builder::new().read(b"foo").write(b"bar").build();
+
+        let mut tracker = BytesRWTracker::new(stream).with_window(Duration::from_millis(50));
+        let handle = tracker.handle();
+        let mut buf = [0u8; 3];
+
+        tracker.read_exact(&mut buf).await.unwrap();
+        tracker.write_all(b"bar").await.unwrap();
+        assert!(handle.read_rate() > 0.);
+        assert!(handle.write_rate() > 0.);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(handle.read_rate(), 0.);
+        assert_eq!(handle.write_rate(), 0.);
+        assert_eq!(handle.read(), 3);
+        assert_eq!(handle.written(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_rate_concurrent_handles() {
+        let mut builder = Builder::new();
+        for _ in 0..64 {
+            builder.read(b"foo").write(b"bar");
+        }
+        let stream = builder.build();
+
+        let tracker = BytesRWTracker::new(stream).with_window(Duration::from_secs(60));
+        let handle = tracker.handle();
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    let mut last = (0., 0.);
+                    while handle.read() < 192 || handle.written() < 192 {
+                        let rates = (handle.read_rate(), handle.write_rate());
+                        assert!(rates.0 >= last.0);
+                        assert!(rates.1 >= last.1);
+                        last = rates;
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+
+        let writer = tokio::spawn(async move {
+            let mut tracker = tracker;
+            let mut buf = [0u8; 3];
+            for _ in 0..64 {
+                tracker.read_exact(&mut buf).await.unwrap();
+                tracker.write_all(b"bar").await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        writer.await.unwrap();
+        for reader in readers {
+            reader.await.unwrap();
+        }
+
+        assert_eq!(handle.read_rate(), 192. / 60.);
+        assert_eq!(handle.write_rate(), 192. / 60.);
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_reset() {
+        let stream = Builder::new()
+            .read(b"foo")
+            .write(b"foo")
+            .read(b"bar")
+            .write(b"barbaz")
+            .build();
+
+        let mut tracker = BytesRWTracker::new(stream);
+        let handle = tracker.handle();
+        let other_handle = handle.clone();
+        let mut buf = [0u8; 3];
+
+        tracker.read_exact(&mut buf).await.unwrap();
+        tracker.write_all(b"foo").await.unwrap();
+        assert_eq!(handle.reset(), (3, 3));
+        assert_eq!(tracker.read(), 0);
+        assert_eq!(tracker.written(), 0);
+        assert_eq!(other_handle.read(), 0);
+        assert_eq!(other_handle.written(), 0);
+
+        tracker.read_exact(&mut buf).await.unwrap();
+        assert_eq!(other_handle.read(), 3);
+        assert_eq!(other_handle.written(), 0);
+        tracker.write_all(b"barbaz").await.unwrap();
+        assert_eq!(tracker.reset(), (3, 6));
+        assert_eq!(handle.read(), 0);
+        assert_eq!(handle.written(), 0);
+        assert_eq!(handle.reset(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_flushes_and_shutdowns() {
+        let stream = Builder::new().write(b"foo").build();
+
+        let mut tracker = BytesRWTracker::new(stream);
+        let handle = tracker.handle();
+
+        assert_eq!(handle.flushes(), 0);
+        assert_eq!(handle.shutdowns(), 0);
+
+        tracker.write_all(b"foo").await.unwrap();
+        tracker.flush().await.unwrap();
+        tracker.flush().await.unwrap();
+        assert_eq!(handle.flushes(), 2);
+        assert_eq!(handle.shutdowns(), 0);
+
+        tracker.shutdown().await.unwrap();
+        assert_eq!(tracker.flushes(), 2);
+        assert_eq!(tracker.shutdowns(), 1);
+        assert_eq!(handle.shutdowns(), 1);
+        assert_eq!(handle.written(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_max_write_chunk() {
+        let stream = Builder::new()
+            .write(b"foo")
+            .write(b"0123456789")
+            .write(b"bar_baz")
+            .build();
+
+        let mut tracker = BytesRWTracker::new(stream);
+        let handle = tracker.handle();
+        assert_eq!(handle.max_write_chunk(), 0);
+
+        tracker.write_all(b"foo").await.unwrap();
+        assert_eq!(handle.max_write_chunk(), 3);
+        tracker.write_all(b"0123456789").await.unwrap();
+        tracker.write_all(b"bar_baz").await.unwrap();
+
+        assert_eq!(tracker.max_write_chunk(), 10);
+        assert_eq!(handle.max_write_chunk(), 10);
+        assert_eq!(handle.written(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_histogram_buckets() {
+        let stream = Builder::new()
+            .read(b"f")
+            .read(b"foo")
+            .read(b"foob")
+            .read(b"foobarbaz")
+            .write(b"bar")
+            .write(b"barbazqux")
+            .build();
+
+        let mut tracker = BytesRWTracker::new(stream).with_histogram(&[4, 1, 4]);
+        let mut buf = [0u8; 16];
+
+        assert_eq!(
+            tracker.read_histogram(),
+            vec![(1, 0), (4, 0), (usize::MAX, 0)]
+        );
+        for expected in [1, 3, 4, 9] {
+            assert_eq!(
+                AsyncReadExt::read(&mut tracker, &mut buf).await.unwrap(),
+                expected
+            );
+        }
+        tracker.write_all(b"bar").await.unwrap();
+        tracker.write_all(b"barbazqux").await.unwrap();
+        // a 0-byte read from EOF is not counted
+        assert_eq!(AsyncReadExt::read(&mut tracker, &mut buf).await.unwrap(), 0);
+
+        assert_eq!(
+            tracker.read_histogram(),
+            vec![(1, 1), (4, 2), (usize::MAX, 1)]
+        );
+        assert_eq!(
+            tracker.written_histogram(),
+            vec![(1, 0), (4, 1), (usize::MAX, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_histogram_disabled() {
+        let stream = Builder::new().read(b"foo").build();
+
+        let mut tracker = BytesRWTracker::new(stream);
+        let handle = tracker.handle();
+        let mut buf = [0u8; 3];
+
+        tracker.read_exact(&mut buf).await.unwrap();
+        assert!(handle.read_histogram().is_empty());
+        assert!(handle.written_histogram().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rw_handle_tracker_histogram() {
+        let stream = Builder::new()
+            .read(b"foo")
+            .write(b"barbaz")
+            .read(b"bazbazbaz")
+            .build();
+
+        let tracker = BytesRWTracker::new(stream).with_histogram(&[4, 8]);
+        let handle = tracker.handle();
+
+        let task = tokio::spawn(async move {
+            let mut tracker = tracker;
+            let mut buf = [0u8; 16];
+            tracker.read_exact(&mut buf[..3]).await.unwrap();
+            tracker.write_all(b"barbaz").await.unwrap();
+            tracker.read_exact(&mut buf[..9]).await.unwrap();
+        });
+        task.await.unwrap();
+
+        assert_eq!(
+            handle.read_histogram(),
+            vec![(4, 1), (8, 0), (usize::MAX, 1)]
+        );
+        assert_eq!(
+            handle.written_histogram(),
+            vec![(4, 0), (8, 1), (usize::MAX, 0)]
+        );
+    }
+
+    fn assert_bytes_limit_exceeded(err: io::Error) {
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(err.into_inner().unwrap().is::<BytesLimitExceeded>());
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_read_limit() {
+        let stream = Builder::new().read(b"foo").read(b"barbaz").build();
+
+        let mut tracker = BytesRWTracker::with_read_limit(stream, 5);
+        let mut buf = [0u8; 16];
+
+        assert_eq!(AsyncReadExt::read(&mut tracker, &mut buf).await.unwrap(), 3);
+        assert_eq!(&buf[..3], b"foo");
+
+        // partial read up to the boundary is delivered
+        assert_eq!(AsyncReadExt::read(&mut tracker, &mut buf).await.unwrap(), 2);
+        assert_eq!(&buf[..2], b"ba");
+        assert_eq!(tracker.read(), 5);
+
+        assert_bytes_limit_exceeded(
+            AsyncReadExt::read(&mut tracker, &mut buf)
+                .await
+                .unwrap_err(),
+        );
+        assert_bytes_limit_exceeded(
+            AsyncReadExt::read(&mut tracker, &mut buf)
+                .await
+                .unwrap_err(),
+        );
+        assert_eq!(tracker.read(), 5);
+
+        // a reset does not lift the limit
+        assert_eq!(tracker.handle().reset(), (5, 0));
+        assert_bytes_limit_exceeded(
+            AsyncReadExt::read(&mut tracker, &mut buf)
+                .await
+                .unwrap_err(),
+        );
+        assert_eq!(tracker.read(), 0);
+
+        let mut stream = tracker.into_inner();
+        stream.read_exact(&mut buf[..4]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_write_limit() {
+        let stream = Builder::new().write(b"foo").write(b"ba").build();
+
+        let mut tracker = BytesRWTracker::with_write_limit(stream, 5);
+        let handle = tracker.handle();
+
+        assert_eq!(tracker.write(b"foo").await.unwrap(), 3);
+        assert_eq!(tracker.write(b"barbaz").await.unwrap(), 2);
+        assert_eq!(handle.written(), 5);
+
+        assert_bytes_limit_exceeded(tracker.write(b"baz").await.unwrap_err());
+        assert_bytes_limit_exceeded(tracker.write_all(b"baz").await.unwrap_err());
+        assert_eq!(handle.written(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_read_and_write_limit() {
+        let stream = Builder::new().read(b"foo").write(b"barbaz").build();
+
+        let mut tracker =
+            BytesRWTracker::with_read_limit(BytesRWTracker::with_write_limit(stream, 6), 3);
+        let mut buf = [0u8; 3];
+
+        tracker.read_exact(&mut buf).await.unwrap();
+        tracker.write_all(b"barbaz").await.unwrap();
+        assert_bytes_limit_exceeded(
+            AsyncReadExt::read(&mut tracker, &mut buf)
+                .await
+                .unwrap_err(),
+        );
+        assert_bytes_limit_exceeded(tracker.write(b"!").await.unwrap_err());
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_no_activity_by_default() {
+        let stream = Builder::new().read(b"foo").write(b"bar").build();
+
+        let mut tracker = BytesRWTracker::new(stream);
+        let handle = tracker.handle();
+        let mut buf = [0u8; 3];
+
+        tracker.read_exact(&mut buf).await.unwrap();
+        tracker.write_all(b"bar").await.unwrap();
+
+        assert_eq!(handle.read(), 3);
+        assert_eq!(handle.written(), 3);
+        assert_eq!(handle.last_read_at(), None);
+        assert_eq!(handle.last_write_at(), None);
+        assert_eq!(tracker.last_read_at(), None);
+        assert_eq!(tracker.last_write_at(), None);
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_last_activity() {
+        let stream = Builder::new().read(b"foo").write(b"bar").build();
+
+        let start = Instant::now();
+        let mut tracker = BytesRWTracker::new(stream).with_activity();
+        let handle = tracker.handle();
+        let mut buf = [0u8; 3];
+
+        assert_eq!(handle.last_read_at(), None);
+        assert_eq!(handle.last_write_at(), None);
+
+        tracker.read_exact(&mut buf).await.unwrap();
+        let last_read_at = handle.last_read_at().unwrap();
+        assert!(last_read_at >= start);
+        assert_eq!(handle.last_write_at(), None);
+
+        tracker.write_all(b"bar").await.unwrap();
+        let last_write_at = handle.last_write_at().unwrap();
+        assert!(last_write_at >= last_read_at);
+        assert!(last_write_at <= Instant::now());
+
+        // timestamps remain readable after the tracker is consumed
+        drop(tracker.into_inner());
+        assert_eq!(handle.last_read_at(), Some(last_read_at));
+        assert_eq!(handle.last_write_at(), Some(last_write_at));
+        assert_eq!(handle.read(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_read_and_write_closed() {
+        let stream = Builder::new().read(b"foo").write(b"bar").build();
+
+        let mut tracker = BytesRWTracker::new(stream);
+        let handle = tracker.handle();
+        assert!(!handle.is_read_closed());
+        assert!(!handle.is_write_closed());
+
+        // a read into a buffer without capacity is not an EOF
+        let n = AsyncReadExt::read(&mut tracker, &mut []).await.unwrap();
+        assert_eq!(n, 0);
+        assert!(!handle.is_read_closed());
+
+        let mut buf = [0u8; 3];
+        tracker.read_exact(&mut buf).await.unwrap();
+        assert!(!handle.is_read_closed());
+
+        tracker.write_all(b"bar").await.unwrap();
+        assert!(!handle.is_write_closed());
+
+        let n = AsyncReadExt::read(&mut tracker, &mut buf).await.unwrap();
+        assert_eq!(n, 0);
+        assert!(handle.is_read_closed());
+        assert!(tracker.is_read_closed());
+        assert!(!handle.is_write_closed());
+
+        tracker.shutdown().await.unwrap();
+        assert!(handle.is_write_closed());
+        assert!(tracker.is_write_closed());
+
+        // flags remain readable after the tracker is consumed
+        drop(tracker.into_inner());
+        assert!(handle.is_read_closed());
+        assert!(handle.is_write_closed());
+    }
+
+    /// Vectored writer which accepts at most `max` bytes per write.
+    #[derive(Debug, Default)]
+    struct PartialVectoredWriter {
+        max: usize,
+        written: Vec<u8>,
+    }
+
+    impl AsyncWrite for PartialVectoredWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<Result<usize, io::Error>> {
+            self.poll_write_vectored(cx, &[io::IoSlice::new(buf)])
+        }
+
+        fn poll_write_vectored(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            bufs: &[io::IoSlice<'_>],
+        ) -> Poll<Result<usize, io::Error>> {
+            let mut n = 0;
+            for buf in bufs {
+                let len = buf.len().min(self.max - n);
+                self.written.extend_from_slice(&buf[..len]);
+                n += len;
+            }
+            Poll::Ready(Ok(n))
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<(), io::Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_write_vectored_partial() {
+        let mut tracker = BytesRWTracker::new(PartialVectoredWriter {
+            max: 4,
+            ..Default::default()
+        });
+        let handle = tracker.handle();
+        assert!(tracker.is_write_vectored());
+
+        let bufs = [io::IoSlice::new(b"foo"), io::IoSlice::new(b"barbaz")];
+        assert_eq!(tracker.write_vectored(&bufs).await.unwrap(), 4);
+        assert_eq!(handle.written(), 4);
+        assert_eq!(handle.max_write_chunk(), 4);
+
+        let bufs = [io::IoSlice::new(b"az")];
+        assert_eq!(tracker.write_vectored(&bufs).await.unwrap(), 2);
+        assert_eq!(handle.written(), 6);
+        assert_eq!(tracker.into_inner().written, b"foobaz");
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_write_vectored_limit() {
+        let mut tracker = BytesRWTracker::with_write_limit(
+            PartialVectoredWriter {
+                max: usize::MAX,
+                ..Default::default()
+            },
+            5,
+        );
+        let handle = tracker.handle();
+
+        let bufs = [
+            io::IoSlice::new(b"foo"),
+            io::IoSlice::new(b"bar"),
+            io::IoSlice::new(b"baz"),
+        ];
+        assert_eq!(tracker.write_vectored(&bufs).await.unwrap(), 5);
+        assert_eq!(handle.written(), 5);
+        assert_bytes_limit_exceeded(tracker.write_vectored(&bufs).await.unwrap_err());
+        assert_eq!(tracker.into_inner().written, b"fooba");
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_report_on_drop() {
+        let stream = Builder::new().read(b"foo").write(b"bar").build();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tracker = BytesRWTracker::new(stream).with_report(move |report| {
+            tx.send(report).unwrap();
+        });
+
+        let mut buf = [0u8; 3];
+        tracker.read_exact(&mut buf).await.unwrap();
+        tracker.write_all(b"bar").await.unwrap();
+        assert!(AsyncReadExt::read(&mut tracker, &mut buf).await.unwrap() == 0);
+        assert!(rx.try_recv().is_err());
+
+        drop(tracker);
+        let report = rx.recv().await.unwrap();
+        assert_eq!(report.read, 3);
+        assert_eq!(report.written, 3);
+        assert_eq!(report.close_reason, Some(BytesRWCloseReason::Eof));
+        // reported exactly once
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_report_on_into_inner() {
+        let stream = Builder::new().write(b"foo").build();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tracker = BytesRWTracker::new(stream).with_report(move |report| {
+            tx.send(report).unwrap();
+        });
+
+        tracker.write_all(b"foo").await.unwrap();
+        tracker.shutdown().await.unwrap();
+        let stream = tracker.into_inner();
+
+        let report = rx.recv().await.unwrap();
+        assert_eq!(report.read, 0);
+        assert_eq!(report.written, 3);
+        assert_eq!(report.close_reason, Some(BytesRWCloseReason::Shutdown));
+        drop(stream);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rw_tracker_report_on_abort() {
+        let (client, mut server) = tokio::io::duplex(64);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tracker = BytesRWTracker::new(client).with_report(move |report| {
+            tx.send(report).unwrap();
+        });
+        let handle = tracker.handle();
+
+        server.write_all(b"foo").await.unwrap();
+        let task = tokio::spawn(async move {
+            let mut buf = [0u8; 3];
+            tracker.read_exact(&mut buf).await.unwrap();
+            // wait forever, until aborted
+            let _ = tracker.read_exact(&mut buf).await;
+        });
+
+        // wait for the first read to be tracked
+        while handle.read() < 3 {
+            tokio::task::yield_now().await;
+        }
+        assert!(rx.try_recv().is_err());
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+
+        let report = rx.recv().await.unwrap();
+        assert_eq!(report.read, 3);
+        assert_eq!(report.written, 0);
+        assert_eq!(report.close_reason, None);
+        assert!(rx.recv().await.is_none());
+    }
 }
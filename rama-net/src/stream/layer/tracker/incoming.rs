@@ -1,21 +1,27 @@
-use super::bytes::BytesRWTracker;
+use super::bytes::{BytesRWReport, BytesRWTracker};
 use crate::stream::Stream;
 use rama_core::{Context, Layer, Service};
 use rama_utils::macros::define_inner_service_accessors;
-use std::{fmt, future::Future};
+use std::{fmt, future::Future, sync::Arc};
+
+type ReportFn = Arc<dyn Fn(BytesRWReport) + Send + Sync + 'static>;
 
 /// A [`Service`] that wraps a [`Service`]'s input IO [`Stream`] with an atomic R/W tracker.
 ///
+/// See [`IncomingBytesTrackerLayer`] for more information.
+///
 /// [`Service`]: rama_core::Service
 /// [`Stream`]: crate::stream::Stream
 pub struct IncomingBytesTrackerService<S> {
     inner: S,
+    report: Option<ReportFn>,
 }
 
 impl<S: fmt::Debug> fmt::Debug for IncomingBytesTrackerService<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("IncomingBytesTrackerService")
             .field("inner", &self.inner)
+            .field("report", &self.report.is_some())
             .finish()
     }
 }
@@ -25,7 +31,10 @@ impl<S> IncomingBytesTrackerService<S> {
     ///
     /// See [`IncomingBytesTrackerService`] for more information.
     pub const fn new(inner: S) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            report: None,
+        }
     }
 
     define_inner_service_accessors!();
@@ -38,6 +47,7 @@ where
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            report: self.report.clone(),
         }
     }
 }
@@ -56,7 +66,10 @@ where
         mut ctx: Context<State>,
         stream: IO,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
-        let tracked_stream = BytesRWTracker::new(stream);
+        let mut tracked_stream = BytesRWTracker::new(stream);
+        if let Some(report) = self.report.clone() {
+            tracked_stream = tracked_stream.with_report(move |r| report(r));
+        }
         let handle = tracked_stream.handle();
         ctx.insert(handle);
         self.inner.serve(ctx, tracked_stream)
@@ -65,17 +78,56 @@ where
 
 /// A [`Layer`] that wraps a [`Service`]'s input IO [`Stream`] with an atomic R/W tracker.
 ///
+/// The [`BytesRWTrackerHandle`] of the tracker is inserted into the [`Context`],
+/// such that services further down the stack (e.g. http services served by an http server)
+/// can get the number of bytes read and written using `ctx.get::<BytesRWTrackerHandle>()`.
+///
+/// Add this layer in front of the TLS acceptor in case you wish to track
+/// the (encrypted) bytes of a TLS connection, as the handle has to be inserted
+/// before the stream is consumed by the TLS layer.
+///
+/// Use [`IncomingBytesTrackerLayer::with_report`] to receive a [`BytesRWReport`]
+/// for each connection once it is closed, without having to keep track of the handles.
+///
+/// [`BytesRWTrackerHandle`]: super::BytesRWTrackerHandle
 /// [`Layer`]: rama_core::Layer
 /// [`Service`]: rama_core::Service
 /// [`Stream`]: crate::stream::Stream
-#[derive(Debug, Clone)]
-#[non_exhaustive]
-pub struct IncomingBytesTrackerLayer;
+#[derive(Clone)]
+pub struct IncomingBytesTrackerLayer {
+    report: Option<ReportFn>,
+}
+
+impl fmt::Debug for IncomingBytesTrackerLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncomingBytesTrackerLayer")
+            .field("report", &self.report.is_some())
+            .finish()
+    }
+}
 
 impl IncomingBytesTrackerLayer {
     /// Create a new [`IncomingBytesTrackerLayer`].
     pub const fn new() -> Self {
-        Self
+        Self { report: None }
+    }
+
+    /// Call the given function with the [`BytesRWReport`] of each tracked connection,
+    /// once its tracker is dropped.
+    ///
+    /// See [`BytesRWTracker::with_report`] for more information.
+    pub fn with_report(mut self, f: impl Fn(BytesRWReport) + Send + Sync + 'static) -> Self {
+        self.report = Some(Arc::new(f));
+        self
+    }
+
+    /// Call the given function with the [`BytesRWReport`] of each tracked connection,
+    /// once its tracker is dropped.
+    ///
+    /// See [`BytesRWTracker::with_report`] for more information.
+    pub fn set_report(&mut self, f: impl Fn(BytesRWReport) + Send + Sync + 'static) -> &mut Self {
+        self.report = Some(Arc::new(f));
+        self
     }
 }
 
@@ -89,6 +141,61 @@ impl<S> Layer<S> for IncomingBytesTrackerLayer {
     type Service = IncomingBytesTrackerService<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        IncomingBytesTrackerService { inner }
+        IncomingBytesTrackerService {
+            inner,
+            report: self.report.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::layer::BytesRWTrackerHandle;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn test_incoming_bytes_tracker_handle_in_context() {
+        let stream = Builder::new().read(b"ping").write(b"pong").build();
+
+        let svc = IncomingBytesTrackerLayer::new().layer(service_fn(
+            |ctx: Context<()>, mut stream: BytesRWTracker<_>| async move {
+                let handle = ctx.get::<BytesRWTrackerHandle>().unwrap().clone();
+                let mut buf = [0u8; 4];
+                stream.read_exact(&mut buf).await.unwrap();
+                stream.write_all(b"pong").await.unwrap();
+                Ok::<_, Infallible>((handle.read(), handle.written()))
+            },
+        ));
+
+        let (read, written) = svc.serve(Context::default(), stream).await.unwrap();
+        assert_eq!(read, 4);
+        assert_eq!(written, 4);
+    }
+
+    #[tokio::test]
+    async fn test_incoming_bytes_tracker_report() {
+        let stream = Builder::new().read(b"ping").write(b"pong").build();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let svc = IncomingBytesTrackerLayer::new()
+            .with_report(move |report| tx.send(report).unwrap())
+            .layer(service_fn(
+                |_ctx: Context<()>, mut stream: BytesRWTracker<_>| async move {
+                    let mut buf = [0u8; 4];
+                    stream.read_exact(&mut buf).await.unwrap();
+                    stream.write_all(b"pong").await.unwrap();
+                    Ok::<_, Infallible>(())
+                },
+            ));
+
+        svc.serve(Context::default(), stream).await.unwrap();
+        let report = rx.try_recv().unwrap();
+        assert_eq!(report.read, 4);
+        assert_eq!(report.written, 4);
+        assert!(rx.try_recv().is_err());
     }
 }
@@ -0,0 +1,441 @@
+//! Provides [`LatencyTracker`] which wraps a [`AsyncRead`] and/or [`AsyncWrite`]
+//! in order to track when the first bytes were read and written.
+//!
+//! Use [`LatencyTracker::handle`] to get a [`LatencyTrackerHandle`], a requirement
+//! to get the timings even though the [`LatencyTracker`] is consumed by a protocol consumer,
+//! in the same way as is done for a [`BytesRWTrackerHandle`].
+//!
+//! [`AsyncRead`]: crate::stream::AsyncRead
+//! [`AsyncWrite`]: crate::stream::AsyncWrite
+//! [`BytesRWTrackerHandle`]: super::BytesRWTrackerHandle
+
+use pin_project_lite::pin_project;
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{self, Poll},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::stream::Stream;
+
+pin_project! {
+    /// A wrapper around a [`AsyncRead`] and/or [`AsyncWrite`] that tracks
+    /// the time of the first read and first write, measured since the
+    /// tracker was created (e.g. when the connection was accepted).
+    ///
+    /// Use [`LatencyTracker::handle`] to get a [`LatencyTrackerHandle`] in order
+    /// to get the timings even though the [`LatencyTracker`] is consumed by a protocol consumer.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub struct LatencyTracker<S> {
+        timings: Arc<Timings>,
+        #[pin]
+        stream: S,
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for LatencyTracker<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyTracker")
+            .field("timings", &self.timings)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> LatencyTracker<S> {
+    /// Create a new [`LatencyTracker`] that wraps the
+    /// given [`AsyncRead`] and/or [`AsyncWrite`],
+    /// using the current time as the time the stream was accepted.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn new(stream: S) -> Self {
+        Self {
+            timings: Arc::new(Timings::new()),
+            stream,
+        }
+    }
+
+    /// Get a [`LatencyTrackerHandle`] that can be used to get the timings
+    /// even though the tracker is consumed by a protocol consumer in a later stage.
+    pub fn handle(&self) -> LatencyTrackerHandle {
+        LatencyTrackerHandle {
+            timings: self.timings.clone(),
+        }
+    }
+
+    /// Get the inner [`AsyncRead`] and/or [`AsyncWrite`] stream.
+    /// Dropping the tracking info and capabilities for this stream.
+    ///
+    /// Any previously obtained [`LatencyTrackerHandle`] will no longer
+    /// be updated but will still report the timings recorded up to the point
+    /// where this method was called.
+    ///
+    /// [`AsyncRead`]: crate::stream::AsyncRead
+    /// [`AsyncWrite`]: crate::stream::AsyncWrite
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> AsyncRead for LatencyTracker<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let size = buf.filled().len();
+        let res = this.stream.poll_read(cx, buf);
+        if let Poll::Ready(Ok(_)) = res {
+            if buf.filled().len() > size {
+                this.timings.record_read(Instant::now());
+            }
+        }
+        res
+    }
+}
+
+impl<S> AsyncWrite for LatencyTracker<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.project();
+        let res = this.stream.poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            if n > 0 {
+                this.timings.record_written(Instant::now());
+            }
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.project();
+        let res = this.stream.poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(n)) = res {
+            if n > 0 {
+                this.timings.record_written(Instant::now());
+            }
+        }
+        res
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A handle to a [`LatencyTracker`] that can be used to get the timings
+/// of the first read and write, even though the tracker is consumed by a protocol consumer.
+///
+/// All timings are `None` until the event they refer to happened,
+/// and are never overwritten once recorded.
+pub struct LatencyTrackerHandle {
+    timings: Arc<Timings>,
+}
+
+impl LatencyTrackerHandle {
+    /// Get the [`Instant`] at which the tracked stream was accepted (created).
+    pub fn accepted_at(&self) -> Instant {
+        self.timings.start
+    }
+
+    /// Get the [`Instant`] at which bytes were first read, `None` if nothing was read yet.
+    pub fn first_read_at(&self) -> Option<Instant> {
+        self.timings
+            .decode(self.timings.first_read.load(Ordering::Acquire))
+    }
+
+    /// Get the [`Instant`] at which bytes were first written, `None` if nothing was written yet.
+    pub fn first_write_at(&self) -> Option<Instant> {
+        self.timings
+            .decode(self.timings.first_written.load(Ordering::Acquire))
+    }
+
+    /// Get the [`Instant`] at which bytes were first read after the first write,
+    /// `None` if no bytes were read since the first write (yet).
+    pub fn first_response_at(&self) -> Option<Instant> {
+        self.timings
+            .decode(self.timings.first_response.load(Ordering::Acquire))
+    }
+
+    /// Get the time to first byte: the time between accepting the stream
+    /// and the first byte being read from it, `None` if nothing was read yet.
+    pub fn ttfb(&self) -> Option<Duration> {
+        self.first_read_at()
+            .map(|at| at.saturating_duration_since(self.timings.start))
+    }
+
+    /// Get the time between the first write and the first byte read after it,
+    /// e.g. the latency of the first response for a client stream,
+    /// `None` if no bytes were read since the first write (yet).
+    pub fn response_latency(&self) -> Option<Duration> {
+        let first_write_at = self.first_write_at()?;
+        self.first_response_at()
+            .map(|at| at.saturating_duration_since(first_write_at))
+    }
+}
+
+/// Timings stored as nanoseconds since `start` (offset by one),
+/// where `0` means the event did not happen yet.
+#[derive(Debug)]
+struct Timings {
+    start: Instant,
+    first_read: AtomicU64,
+    first_written: AtomicU64,
+    first_response: AtomicU64,
+}
+
+impl Timings {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            first_read: AtomicU64::new(0),
+            first_written: AtomicU64::new(0),
+            first_response: AtomicU64::new(0),
+        }
+    }
+
+    fn record_read(&self, now: Instant) {
+        let value = self.encode(now);
+        set_once(&self.first_read, value);
+        if self.first_written.load(Ordering::Acquire) != 0 {
+            set_once(&self.first_response, value);
+        }
+    }
+
+    fn record_written(&self, now: Instant) {
+        set_once(&self.first_written, self.encode(now));
+    }
+
+    fn encode(&self, instant: Instant) -> u64 {
+        let nanos = instant.saturating_duration_since(self.start).as_nanos();
+        u64::try_from(nanos).unwrap_or(u64::MAX - 1) + 1
+    }
+
+    fn decode(&self, value: u64) -> Option<Instant> {
+        (value != 0).then(|| self.start + Duration::from_nanos(value - 1))
+    }
+}
+
+fn set_once(slot: &AtomicU64, value: u64) {
+    // only the first event is recorded, later ones leave the value untouched
+    let _ = slot.compare_exchange(0, value, Ordering::AcqRel, Ordering::Acquire);
+}
+
+/// A [`Service`] that wraps a [`Service`]'s input IO [`Stream`] with a [`LatencyTracker`].
+///
+/// See [`LatencyTrackerLayer`] for more information.
+///
+/// [`Service`]: rama_core::Service
+/// [`Stream`]: crate::stream::Stream
+pub struct LatencyTrackerService<S> {
+    inner: S,
+}
+
+impl<S: fmt::Debug> fmt::Debug for LatencyTrackerService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyTrackerService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<S> LatencyTrackerService<S> {
+    /// Create a new [`LatencyTrackerService`].
+    ///
+    /// See [`LatencyTrackerService`] for more information.
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S> Clone for LatencyTrackerService<S>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<State, S, IO> Service<State, IO> for LatencyTrackerService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, LatencyTracker<IO>>,
+    IO: Stream,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn serve(
+        &self,
+        mut ctx: Context<State>,
+        stream: IO,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
+        let tracked_stream = LatencyTracker::new(stream);
+        let handle = tracked_stream.handle();
+        ctx.insert(handle);
+        self.inner.serve(ctx, tracked_stream)
+    }
+}
+
+/// A [`Layer`] that wraps a [`Service`]'s input IO [`Stream`] with a [`LatencyTracker`].
+///
+/// The [`LatencyTrackerHandle`] of the tracker is inserted into the [`Context`],
+/// such that services further down the stack (e.g. http services served by an http server)
+/// can report the time to first byte using `ctx.get::<LatencyTrackerHandle>()`.
+///
+/// [`Layer`]: rama_core::Layer
+/// [`Service`]: rama_core::Service
+/// [`Stream`]: crate::stream::Stream
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct LatencyTrackerLayer;
+
+impl LatencyTrackerLayer {
+    /// Create a new [`LatencyTrackerLayer`].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for LatencyTrackerLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for LatencyTrackerLayer {
+    type Service = LatencyTrackerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LatencyTrackerService { inner }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn test_latency_tracker() {
+        let stream = Builder::new()
+            .write(b"ping")
+            .wait(Duration::from_millis(10))
+            .read(b"pong")
+            .read(b"pong")
+            .write(b"ping")
+            .build();
+        let mut tracker = LatencyTracker::new(stream);
+        let handle = tracker.handle();
+
+        assert!(handle.first_read_at().is_none());
+        assert!(handle.first_write_at().is_none());
+        assert!(handle.first_response_at().is_none());
+        assert!(handle.ttfb().is_none());
+        assert!(handle.response_latency().is_none());
+
+        tracker.write_all(b"ping").await.unwrap();
+        let first_write_at = handle.first_write_at().unwrap();
+        assert!(handle.first_read_at().is_none());
+        assert!(handle.response_latency().is_none());
+
+        let mut buf = [0u8; 4];
+        tracker.read_exact(&mut buf).await.unwrap();
+        let first_read_at = handle.first_read_at().unwrap();
+        assert_eq!(handle.first_response_at(), Some(first_read_at));
+        assert!(first_read_at >= first_write_at);
+        assert!(handle.response_latency().unwrap() >= Duration::from_millis(10));
+        assert_eq!(
+            handle.ttfb(),
+            Some(first_read_at.duration_since(handle.accepted_at()))
+        );
+
+        // repeated reads and writes do not overwrite the first timings
+        tracker.read_exact(&mut buf).await.unwrap();
+        tracker.write_all(b"ping").await.unwrap();
+        assert_eq!(handle.first_read_at(), Some(first_read_at));
+        assert_eq!(handle.first_write_at(), Some(first_write_at));
+        assert_eq!(handle.first_response_at(), Some(first_read_at));
+    }
+
+    #[tokio::test]
+    async fn test_latency_tracker_read_before_write() {
+        let stream = Builder::new().read(b"hello").write(b"world").build();
+        let mut tracker = LatencyTracker::new(stream);
+        let handle = tracker.handle();
+
+        let mut buf = [0u8; 5];
+        tracker.read_exact(&mut buf).await.unwrap();
+        tracker.write_all(b"world").await.unwrap();
+
+        assert!(handle.ttfb().is_some());
+        assert!(handle.first_write_at().is_some());
+        // no bytes were read since the first write
+        assert!(handle.first_response_at().is_none());
+        assert!(handle.response_latency().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_latency_tracker_handle_in_context() {
+        let stream = Builder::new().read(b"ping").build();
+
+        let svc = LatencyTrackerLayer::new().layer(service_fn(
+            |ctx: Context<()>, mut stream: LatencyTracker<_>| async move {
+                let handle = ctx.get::<LatencyTrackerHandle>().unwrap().clone();
+                let mut buf = [0u8; 4];
+                stream.read_exact(&mut buf).await.unwrap();
+                Ok::<_, Infallible>(handle.ttfb())
+            },
+        ));
+
+        let ttfb = svc.serve(Context::default(), stream).await.unwrap();
+        assert!(ttfb.is_some());
+    }
+}
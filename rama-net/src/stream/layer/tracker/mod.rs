@@ -1,6 +1,12 @@
 mod bytes;
 #[doc(inline)]
-pub use bytes::BytesRWTrackerHandle;
+pub use bytes::{
+    BytesLimitExceeded, BytesRWCloseReason, BytesRWReport, BytesRWTracker, BytesRWTrackerHandle,
+};
+
+mod tunnel;
+#[doc(inline)]
+pub use tunnel::{TunnelId, TunnelRegistry, TunnelStats, TunnelTotals};
 
 mod incoming;
 #[doc(inline)]
@@ -9,3 +15,9 @@ pub use incoming::{IncomingBytesTrackerLayer, IncomingBytesTrackerService};
 mod outgoing;
 #[doc(inline)]
 pub use outgoing::{OutgoingBytesTrackerLayer, OutgoingBytesTrackerService};
+
+mod latency;
+#[doc(inline)]
+pub use latency::{
+    LatencyTracker, LatencyTrackerHandle, LatencyTrackerLayer, LatencyTrackerService,
+};
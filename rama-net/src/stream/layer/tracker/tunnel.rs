@@ -0,0 +1,336 @@
+//! Provides [`TunnelRegistry`] which keeps track of the bytes flowing
+//! through the tunnels (e.g. upgraded or forwarded streams) which are currently open.
+//!
+//! Each tunnel is wrapped in a [`BytesRWTracker`] by [`TunnelRegistry::track`],
+//! and registered until the tracker is dropped, at which point a summary
+//! of the tunnel is logged (as an info event with the `rama::tunnel` target).
+//!
+//! With the `telemetry` feature enabled the rama services which establish tunnels
+//! track them in the [`TunnelRegistry`] found in the [`Context`], falling back to
+//! [`TunnelRegistry::global`], for which the aggregated metrics are recorded using OpenTelemetry.
+
+use super::{BytesRWTracker, BytesRWTrackerHandle};
+use crate::stream::SocketInfo;
+use parking_lot::Mutex;
+use rama_core::Context;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// The window over which the byte rates of tunnels are measured.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Identifier of a tunnel tracked by a [`TunnelRegistry`],
+/// unique within that registry.
+pub struct TunnelId(u64);
+
+impl TunnelId {
+    /// Returns the numeric value of this [`TunnelId`].
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for TunnelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Snapshot of a tunnel tracked by a [`TunnelRegistry`].
+///
+/// Read bytes flow from the peer of the tunnel (up),
+/// written bytes flow towards the peer (down).
+pub struct TunnelStats {
+    /// The identifier of the tunnel.
+    pub id: TunnelId,
+    /// The kind of tunnel, e.g. `upgrade` or `forward`.
+    pub kind: &'static str,
+    /// The peer address of the connection, if known.
+    pub peer_addr: Option<SocketAddr>,
+    /// The number of bytes read from the peer.
+    pub read: usize,
+    /// The number of bytes written to the peer.
+    pub written: usize,
+    /// The number of bytes read per second, over the last 10 seconds.
+    pub read_rate: f64,
+    /// The number of bytes written per second, over the last 10 seconds.
+    pub written_rate: f64,
+    /// The time elapsed since the tunnel was opened.
+    pub age: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+/// The aggregated stats of all tunnels tracked by a [`TunnelRegistry`].
+pub struct TunnelTotals {
+    /// The number of open tunnels.
+    pub tunnels: usize,
+    /// The number of bytes read from the peers of the open tunnels.
+    pub read: usize,
+    /// The number of bytes written to the peers of the open tunnels.
+    pub written: usize,
+    /// The number of bytes read per second, over the last 10 seconds.
+    pub read_rate: f64,
+    /// The number of bytes written per second, over the last 10 seconds.
+    pub written_rate: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Registry of the open tunnels, which keeps track of the bytes flowing through them.
+///
+/// Each tunnel is wrapped in a [`BytesRWTracker`] by [`TunnelRegistry::track`],
+/// and registered until the tracker is dropped, at which point a summary
+/// of the tunnel is logged (as an info event with the `rama::tunnel` target).
+///
+/// With the `telemetry` feature enabled the rama services which establish tunnels
+/// track them in the [`TunnelRegistry`] found in the [`Context`], falling back to
+/// [`TunnelRegistry::global`], for which the aggregated metrics are recorded using OpenTelemetry.
+pub struct TunnelRegistry(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: AtomicU64,
+    tunnels: Mutex<HashMap<TunnelId, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    kind: &'static str,
+    peer_addr: Option<SocketAddr>,
+    handle: BytesRWTrackerHandle,
+    opened_at: Instant,
+}
+
+impl TunnelRegistry {
+    /// Create a new, empty, [`TunnelRegistry`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the process-wide [`TunnelRegistry`],
+    /// used by rama services in case no [`TunnelRegistry`] is found in the [`Context`].
+    pub fn global() -> &'static Self {
+        static GLOBAL: OnceLock<TunnelRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(|| {
+            let registry = Self::new();
+            #[cfg(feature = "telemetry")]
+            metrics::register(&registry);
+            registry
+        })
+    }
+
+    /// Returns the [`TunnelRegistry`] found in the given [`Context`],
+    /// falling back to [`TunnelRegistry::global`].
+    pub fn from_ctx<State>(ctx: &Context<State>) -> &Self {
+        ctx.get::<Self>().unwrap_or_else(|| Self::global())
+    }
+
+    /// Track the given tunnel stream of the given kind (e.g. `upgrade` or `forward`),
+    /// until the returned [`BytesRWTracker`] is dropped.
+    ///
+    /// The peer address is taken from the [`SocketInfo`] found in the [`Context`], if any.
+    pub fn track<State, S>(
+        &self,
+        ctx: &Context<State>,
+        kind: &'static str,
+        stream: S,
+    ) -> BytesRWTracker<S> {
+        let id = TunnelId(self.0.next_id.fetch_add(1, Ordering::Relaxed));
+        let peer_addr = ctx.get::<SocketInfo>().map(|info| *info.peer_addr());
+
+        let registry = self.clone();
+        let tracker = BytesRWTracker::new(stream)
+            .with_window(RATE_WINDOW)
+            .with_report(move |report| {
+                registry.0.tunnels.lock().remove(&id);
+                tracing::info!(
+                    target: "rama::tunnel",
+                    tunnel_id = %id,
+                    tunnel_kind = kind,
+                    peer_addr = ?peer_addr,
+                    read = report.read,
+                    written = report.written,
+                    duration = ?report.duration,
+                    close_reason = ?report.close_reason,
+                    "tunnel closed",
+                );
+            });
+
+        self.0.tunnels.lock().insert(
+            id,
+            Entry {
+                kind,
+                peer_addr,
+                handle: tracker.handle(),
+                opened_at: Instant::now(),
+            },
+        );
+        tracker
+    }
+
+    /// Returns the number of open tunnels.
+    pub fn len(&self) -> usize {
+        self.0.tunnels.lock().len()
+    }
+
+    /// Returns `true` if no tunnels are open.
+    pub fn is_empty(&self) -> bool {
+        self.0.tunnels.lock().is_empty()
+    }
+
+    /// Returns a snapshot of all open tunnels, ordered by their [`TunnelId`].
+    pub fn tunnels(&self) -> Vec<TunnelStats> {
+        let mut tunnels: Vec<_> = self
+            .0
+            .tunnels
+            .lock()
+            .iter()
+            .map(|(id, entry)| TunnelStats {
+                id: *id,
+                kind: entry.kind,
+                peer_addr: entry.peer_addr,
+                read: entry.handle.read(),
+                written: entry.handle.written(),
+                read_rate: entry.handle.read_rate(),
+                written_rate: entry.handle.write_rate(),
+                age: entry.opened_at.elapsed(),
+            })
+            .collect();
+        tunnels.sort_unstable_by_key(|stats| stats.id);
+        tunnels
+    }
+
+    /// Returns the aggregated stats of all open tunnels,
+    /// optionally only those of the given kind.
+    pub fn totals(&self, kind: Option<&str>) -> TunnelTotals {
+        self.0
+            .tunnels
+            .lock()
+            .values()
+            .filter(|entry| kind.is_none_or(|kind| entry.kind == kind))
+            .fold(TunnelTotals::default(), |mut totals, entry| {
+                totals.tunnels += 1;
+                totals.read += entry.handle.read();
+                totals.written += entry.handle.written();
+                totals.read_rate += entry.handle.read_rate();
+                totals.written_rate += entry.handle.write_rate();
+                totals
+            })
+    }
+}
+
+#[cfg(feature = "telemetry")]
+mod metrics {
+    use super::TunnelRegistry;
+    use rama_core::telemetry::opentelemetry::{
+        global, semantic_conventions, InstrumentationScope, KeyValue,
+    };
+
+    const NETWORK_TUNNEL_ACTIVE: &str = "network.tunnel.active";
+    const NETWORK_TUNNEL_READ_RATE: &str = "network.tunnel.read_rate";
+    const NETWORK_TUNNEL_WRITTEN_RATE: &str = "network.tunnel.written_rate";
+
+    const TUNNEL_KINDS: [&str; 2] = ["upgrade", "forward"];
+
+    /// Register the aggregated metrics of the given registry,
+    /// observed per kind of tunnel.
+    pub(super) fn register(registry: &TunnelRegistry) {
+        let meter = global::meter_with_scope(
+            InstrumentationScope::builder(const_format::formatcp!(
+                "{}-network-tunnel",
+                rama_utils::info::NAME
+            ))
+            .with_version(rama_utils::info::VERSION)
+            .with_schema_url(semantic_conventions::SCHEMA_URL)
+            .build(),
+        );
+
+        let r = registry.clone();
+        meter
+            .u64_observable_gauge(NETWORK_TUNNEL_ACTIVE)
+            .with_description("Measures the number of open tunnels.")
+            .with_callback(move |observer| {
+                for kind in TUNNEL_KINDS {
+                    let totals = r.totals(Some(kind));
+                    observer.observe(totals.tunnels as u64, &[KeyValue::new("kind", kind)]);
+                }
+            })
+            .build();
+
+        let r = registry.clone();
+        meter
+            .f64_observable_gauge(NETWORK_TUNNEL_READ_RATE)
+            .with_description("Measures the bytes per second read from the peers of open tunnels.")
+            .with_unit("By/s")
+            .with_callback(move |observer| {
+                for kind in TUNNEL_KINDS {
+                    let totals = r.totals(Some(kind));
+                    observer.observe(totals.read_rate, &[KeyValue::new("kind", kind)]);
+                }
+            })
+            .build();
+
+        let r = registry.clone();
+        meter
+            .f64_observable_gauge(NETWORK_TUNNEL_WRITTEN_RATE)
+            .with_description("Measures the bytes per second written to the peers of open tunnels.")
+            .with_unit("By/s")
+            .with_callback(move |observer| {
+                for kind in TUNNEL_KINDS {
+                    let totals = r.totals(Some(kind));
+                    observer.observe(totals.written_rate, &[KeyValue::new("kind", kind)]);
+                }
+            })
+            .build();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_tunnel_registry_tracks_open_tunnels() {
+        let registry = TunnelRegistry::new();
+        let mut ctx = Context::<()>::default();
+        ctx.insert(SocketInfo::new(None, ([127, 0, 0, 1], 4000).into()));
+        assert!(TunnelRegistry::from_ctx(&ctx).is_empty());
+        ctx.insert(registry.clone());
+
+        let (stream, mut peer) = tokio::io::duplex(64);
+        let mut tunnel = TunnelRegistry::from_ctx(&ctx).track(&ctx, "forward", stream);
+        let (other, _other_peer) = tokio::io::duplex(64);
+        let _other = registry.track(&Context::<()>::default(), "upgrade", other);
+
+        peer.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        tunnel.read_exact(&mut buf).await.unwrap();
+        tunnel.write_all(b"hi").await.unwrap();
+
+        let tunnels = registry.tunnels();
+        assert_eq!(tunnels.len(), 2);
+        assert_eq!(tunnels[0].kind, "forward");
+        assert_eq!(tunnels[0].peer_addr, Some(([127, 0, 0, 1], 4000).into()));
+        assert_eq!((tunnels[0].read, tunnels[0].written), (5, 2));
+        assert_eq!(tunnels[1].kind, "upgrade");
+        assert!(tunnels[1].peer_addr.is_none());
+
+        let totals = registry.totals(Some("forward"));
+        assert_eq!((totals.tunnels, totals.read, totals.written), (1, 5, 2));
+        assert_eq!(registry.totals(None).tunnels, 2);
+
+        drop(tunnel);
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.tunnels()[0].kind, "upgrade");
+    }
+}
@@ -11,6 +11,24 @@ mod read;
 #[doc(inline)]
 pub use read::{ChainReader, HeapReader};
 
+mod peek;
+#[doc(inline)]
+pub use peek::PeekStream;
+
+mod rewind;
+#[doc(inline)]
+pub use rewind::RewindStream;
+
+mod copy;
+#[doc(inline)]
+pub use copy::{copy_bidirectional_with_stats, CopyBidirectionalStats, CopySide};
+
+mod tap;
+#[doc(inline)]
+pub use tap::{
+    read_tap_record, write_tap_records, TapDirection, TapHandle, TapRecord, TapSink, TapStream,
+};
+
 /// A stream is a type that implements `AsyncRead`, `AsyncWrite` and `Send`.
 /// This is specific to Rama and is directly linked to the supertraits of `Tokio`.
 pub trait Stream: AsyncRead + AsyncWrite + Send + 'static {}
@@ -0,0 +1,233 @@
+use bytes::{Buf, Bytes, BytesMut};
+use pin_project_lite::pin_project;
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+pin_project! {
+    /// A [`Stream`] which replays bytes peeked (read) from the inner stream
+    /// before reading from the inner stream itself, while writes go directly
+    /// to the inner stream.
+    ///
+    /// Useful to sniff the protocol used by a stream
+    /// without consuming the bytes needed by the service handling it,
+    /// see [`PeekStream::peek`].
+    ///
+    /// [`Stream`]: crate::stream::Stream
+    pub struct PeekStream<S> {
+        peek: BytesMut,
+        #[pin]
+        stream: S,
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for PeekStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeekStream")
+            .field("peek", &self.peek)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> PeekStream<S> {
+    /// Create a new [`PeekStream`] for the given stream, with nothing peeked yet.
+    pub fn new(stream: S) -> Self {
+        Self {
+            peek: BytesMut::new(),
+            stream,
+        }
+    }
+
+    /// Create a new [`PeekStream`] which replays the given (already) peeked bytes
+    /// prior to reading from the given stream.
+    pub fn with_peeked(peek: impl AsRef<[u8]>, stream: S) -> Self {
+        Self {
+            peek: BytesMut::from(peek.as_ref()),
+            stream,
+        }
+    }
+
+    /// Get the peeked bytes which were not yet replayed.
+    pub fn peeked(&self) -> &[u8] {
+        &self.peek
+    }
+
+    /// Get a reference to the inner stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Consume the [`PeekStream`] into the bytes not yet replayed and the inner stream.
+    pub fn into_parts(self) -> (Bytes, S) {
+        (self.peek.freeze(), self.stream)
+    }
+}
+
+impl<S> PeekStream<S>
+where
+    S: AsyncRead + Unpin,
+{
+    /// Peek up to `n` bytes of the stream, reading from the inner stream
+    /// until `n` bytes are buffered or the inner stream reached its end.
+    ///
+    /// Fewer than `n` bytes are returned in case the stream ended earlier.
+    /// The peeked bytes are replayed by the [`AsyncRead`] implementation of this stream,
+    /// and can be peeked again (e.g. with a larger `n`).
+    pub async fn peek(&mut self, n: usize) -> io::Result<&[u8]> {
+        while self.peek.len() < n {
+            self.peek.reserve(n - self.peek.len());
+            if self.stream.read_buf(&mut self.peek).await? == 0 {
+                break;
+            }
+        }
+        Ok(&self.peek[..n.min(self.peek.len())])
+    }
+}
+
+impl<S> AsyncRead for PeekStream<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        if this.peek.has_remaining() {
+            let n = this.peek.len().min(buf.remaining());
+            buf.put_slice(&this.peek[..n]);
+            this.peek.advance(n);
+            return Poll::Ready(Ok(()));
+        }
+        this.stream.poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for PeekStream<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.project().stream.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.project().stream.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn test_peek_stream() {
+        let stream = Builder::new().read(b"lo world").write(b"hi").build();
+
+        let mut stream = PeekStream::with_peeked(b"hel", stream);
+        assert_eq!(stream.peeked(), b"hel");
+
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"he");
+        assert_eq!(stream.peeked(), b"l");
+
+        let mut buf = [0u8; 9];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"llo world");
+        assert!(stream.peeked().is_empty());
+
+        stream.write_all(b"hi").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_peek_stream_peek() {
+        let stream = Builder::new()
+            .read(b"he")
+            .read(b"llo")
+            .read(b" world")
+            .write(b"hi")
+            .build();
+        let mut stream = PeekStream::new(stream);
+
+        assert_eq!(stream.peek(1).await.unwrap(), b"h");
+        assert_eq!(stream.peek(4).await.unwrap(), b"hell");
+        assert_eq!(stream.peek(2).await.unwrap(), b"he");
+        assert_eq!(stream.peeked(), b"hello");
+
+        let mut buf = [0u8; 11];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello world");
+
+        stream.write_all(b"hi").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_peek_stream_peek_eof() {
+        let stream = Builder::new().read(b"foo").build();
+        let mut stream = PeekStream::new(stream);
+
+        assert_eq!(stream.peek(8).await.unwrap(), b"foo");
+        assert_eq!(stream.peek(16).await.unwrap(), b"foo");
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"foo");
+    }
+
+    #[tokio::test]
+    async fn test_peek_stream_sniff_router() {
+        // a minimal sniff router, dispatching on the first byte of the stream
+        async fn sniff_router<S: AsyncRead + Unpin>(stream: S) -> (&'static str, Vec<u8>) {
+            let mut stream = PeekStream::new(stream);
+            let label = match stream.peek(1).await.unwrap() {
+                [0x16] => "tls",
+                [b] if b.is_ascii_alphabetic() => "plain",
+                _ => "unknown",
+            };
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await.unwrap();
+            (label, buf)
+        }
+
+        let (label, buf) = sniff_router(Builder::new().read(&[0x16, 0x03, 0x01]).build()).await;
+        assert_eq!(label, "tls");
+        assert_eq!(buf, [0x16, 0x03, 0x01]);
+
+        let (label, buf) = sniff_router(Builder::new().read(b"GET / HTTP/1.1\r\n").build()).await;
+        assert_eq!(label, "plain");
+        assert_eq!(buf, b"GET / HTTP/1.1\r\n");
+
+        let (label, buf) = sniff_router(Builder::new().build()).await;
+        assert_eq!(label, "unknown");
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+use bytes::{Buf, Bytes, BytesMut};
+use pin_project_lite::pin_project;
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+pin_project! {
+    /// A [`Stream`] which allows to push bytes back ("rewind") into the stream,
+    /// such that they are read again prior to reading from the inner stream,
+    /// while writes go directly to the inner stream.
+    ///
+    /// Useful for prefix readers (e.g. a HaProxy PROXY protocol header reader)
+    /// which might read more bytes than they consume, and wish to hand over
+    /// the stream (e.g. to an http server) without losing those bytes.
+    ///
+    /// [`Stream`]: crate::stream::Stream
+    pub struct RewindStream<S> {
+        // stack of rewound buffers, the last one is read first
+        rewound: Vec<Bytes>,
+        #[pin]
+        stream: S,
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for RewindStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RewindStream")
+            .field("rewound", &self.rewound)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S> RewindStream<S> {
+    /// Create a new [`RewindStream`] for the given stream, with nothing rewound yet.
+    pub const fn new(stream: S) -> Self {
+        Self {
+            rewound: Vec::new(),
+            stream,
+        }
+    }
+
+    /// Push the given bytes back into the stream, such that they are read
+    /// prior to any bytes that were rewound earlier or are still to be read
+    /// from the inner stream.
+    pub fn rewind(&mut self, buf: Bytes) {
+        if !buf.is_empty() {
+            self.rewound.push(buf);
+        }
+    }
+
+    /// Get the amount of rewound bytes which are not yet read again.
+    pub fn rewound_len(&self) -> usize {
+        self.rewound.iter().map(Bytes::len).sum()
+    }
+
+    /// Get a reference to the inner stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Get a mutable reference to the inner stream.
+    ///
+    /// Reading directly from the inner stream skips any rewound bytes.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consume the [`RewindStream`] into the rewound bytes not yet read and the inner stream.
+    pub fn into_parts(self) -> (Bytes, S) {
+        let Self {
+            mut rewound,
+            stream,
+        } = self;
+        let rewound = if rewound.len() <= 1 {
+            rewound.pop().unwrap_or_default()
+        } else {
+            let mut buf = BytesMut::with_capacity(rewound.iter().map(Bytes::len).sum());
+            for chunk in rewound.iter().rev() {
+                buf.extend_from_slice(chunk);
+            }
+            buf.freeze()
+        };
+        (rewound, stream)
+    }
+}
+
+impl<S> AsyncRead for RewindStream<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        if let Some(chunk) = this.rewound.last_mut() {
+            let n = chunk.len().min(buf.remaining());
+            buf.put_slice(&chunk[..n]);
+            chunk.advance(n);
+            if chunk.is_empty() {
+                this.rewound.pop();
+            }
+            return Poll::Ready(Ok(()));
+        }
+        this.stream.poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for RewindStream<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.project().stream.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        self.project().stream.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn test_rewind_stream() {
+        let stream = Builder::new().read(b"hello world").write(b"hi").build();
+        let mut stream = RewindStream::new(stream);
+
+        let mut buf = [0u8; 8];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello wo");
+
+        // read too much, put the tail back
+        stream.rewind(Bytes::copy_from_slice(&buf[5..]));
+        assert_eq!(stream.rewound_len(), 3);
+
+        let mut buf = [0u8; 6];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b" world");
+        assert_eq!(stream.rewound_len(), 0);
+
+        stream.write_all(b"hi").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rewind_stream_stacked() {
+        let stream = Builder::new().read(b"!").build();
+        let mut stream = RewindStream::new(stream);
+
+        stream.rewind(Bytes::from_static(b"world"));
+        stream.rewind(Bytes::new());
+        stream.rewind(Bytes::from_static(b"hello "));
+        assert_eq!(stream.rewound_len(), 11);
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world!");
+    }
+
+    #[tokio::test]
+    async fn test_rewind_stream_larger_than_read_buf() {
+        let stream = Builder::new().read(b"tail").build();
+        let mut stream = RewindStream::new(stream);
+
+        let rewound = Bytes::from(vec![b'x'; 1024]);
+        stream.rewind(rewound.clone());
+
+        let mut buf = [0u8; 100];
+        let mut read = Vec::new();
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            assert!(n <= buf.len());
+            read.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(&read[..1024], &rewound[..]);
+        assert_eq!(&read[1024..], b"tail");
+    }
+
+    #[tokio::test]
+    async fn test_rewind_stream_into_parts() {
+        let mut stream = RewindStream::new(Builder::new().build());
+        stream.rewind(Bytes::from_static(b"bar"));
+        stream.rewind(Bytes::from_static(b"foo"));
+
+        let mut buf = [0u8; 1];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"f");
+
+        let (rewound, _stream) = stream.into_parts();
+        assert_eq!(rewound, "oobar");
+    }
+}
@@ -0,0 +1,358 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use pin_project_lite::pin_project;
+use std::{
+    fmt, io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The direction of the bytes recorded by a [`TapStream`].
+pub enum TapDirection {
+    /// Bytes read from the tapped stream.
+    Read,
+    /// Bytes written to the tapped stream.
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A chunk of bytes recorded by a [`TapStream`].
+pub struct TapRecord {
+    /// The direction of the recorded bytes.
+    pub direction: TapDirection,
+    /// The recorded bytes.
+    pub data: Bytes,
+}
+
+/// A sink in which a [`TapStream`] records the bytes read and written.
+///
+/// Recording a chunk is never allowed to block (or wait for) the tapped stream,
+/// and is expected to drop the record instead in case the sink is not ready.
+pub trait TapSink: Send + 'static {
+    /// Try to record the given chunk, returning `false` if it was dropped.
+    fn try_record(&self, record: TapRecord) -> bool;
+}
+
+impl TapSink for mpsc::Sender<TapRecord> {
+    fn try_record(&self, record: TapRecord) -> bool {
+        self.try_send(record).is_ok()
+    }
+}
+
+/// Handle to the drop statistics of a [`TapStream`].
+#[derive(Debug, Clone, Default)]
+pub struct TapHandle {
+    dropped: Arc<AtomicU64>,
+}
+
+impl TapHandle {
+    /// Amount of chunks that were not recorded because the sink was not ready.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+pin_project! {
+    /// A [`Stream`] which mirrors all bytes read from and written to
+    /// the inner stream into a [`TapSink`], e.g. to record traffic for debugging.
+    ///
+    /// The data path of the inner stream is never altered. Chunks which
+    /// cannot be recorded immediately (e.g. because the sink is full) are dropped,
+    /// such that a slow sink never blocks the inner stream. The dropped
+    /// chunks are counted in the [`TapHandle`].
+    ///
+    /// Use [`write_tap_records`] to write the records received through an
+    /// [`mpsc`] channel to a writer, and [`read_tap_record`] to read them back.
+    ///
+    /// [`Stream`]: crate::stream::Stream
+    pub struct TapStream<S, W = mpsc::Sender<TapRecord>> {
+        #[pin]
+        stream: S,
+        sink: W,
+        handle: TapHandle,
+    }
+}
+
+impl<S: fmt::Debug, W> fmt::Debug for TapStream<S, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TapStream")
+            .field("stream", &self.stream)
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl<S, W: TapSink> TapStream<S, W> {
+    /// Create a new [`TapStream`], recording the bytes of the given stream into the sink.
+    pub fn new(stream: S, sink: W) -> Self {
+        Self {
+            stream,
+            sink,
+            handle: TapHandle::default(),
+        }
+    }
+}
+
+impl<S, W> TapStream<S, W> {
+    /// Get a [`TapHandle`] to the drop statistics of this stream.
+    pub fn handle(&self) -> TapHandle {
+        self.handle.clone()
+    }
+
+    /// Get a reference to the inner stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Get a mutable reference to the inner stream.
+    ///
+    /// Bytes read from or written to the inner stream directly are not recorded.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consume the [`TapStream`] into the inner stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+fn record<W: TapSink>(sink: &W, handle: &TapHandle, direction: TapDirection, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    let record = TapRecord {
+        direction,
+        data: Bytes::copy_from_slice(data),
+    };
+    if !sink.try_record(record) {
+        let dropped = handle.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        tracing::trace!(
+            ?direction,
+            dropped,
+            "tap stream: sink not ready: drop chunk"
+        );
+    }
+}
+
+impl<S, W> AsyncRead for TapStream<S, W>
+where
+    S: AsyncRead,
+    W: TapSink,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let filled = buf.filled().len();
+        let result = this.stream.poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            record(
+                this.sink,
+                this.handle,
+                TapDirection::Read,
+                &buf.filled()[filled..],
+            );
+        }
+        result
+    }
+}
+
+impl<S, W> AsyncWrite for TapStream<S, W>
+where
+    S: AsyncWrite,
+    W: TapSink,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.project();
+        let result = this.stream.poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            record(this.sink, this.handle, TapDirection::Write, &buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.project().stream.poll_shutdown(cx)
+    }
+}
+
+const TAP_RECORD_READ: u8 = 0;
+const TAP_RECORD_WRITE: u8 = 1;
+
+/// Write all [`TapRecord`]s received from the given channel to the writer,
+/// until all senders are dropped, returning the amount of records written.
+///
+/// Each record is written as a single direction byte (`0` for read, `1` for write),
+/// followed by the length of the data as a big-endian `u32` and the data itself.
+/// Use [`read_tap_record`] to read the records back.
+pub async fn write_tap_records<W>(
+    mut records: mpsc::Receiver<TapRecord>,
+    mut writer: W,
+) -> io::Result<u64>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut count = 0;
+    let mut buf = BytesMut::new();
+    while let Some(record) = records.recv().await {
+        let len = u32::try_from(record.data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "tap record too large"))?;
+        buf.clear();
+        buf.put_u8(match record.direction {
+            TapDirection::Read => TAP_RECORD_READ,
+            TapDirection::Write => TAP_RECORD_WRITE,
+        });
+        buf.put_u32(len);
+        writer.write_all(&buf).await?;
+        writer.write_all(&record.data).await?;
+        count += 1;
+    }
+    writer.flush().await?;
+    Ok(count)
+}
+
+/// Read the next [`TapRecord`] as written by [`write_tap_records`],
+/// returning `None` in case the reader reached its end.
+pub async fn read_tap_record<R>(reader: &mut R) -> io::Result<Option<TapRecord>>
+where
+    R: AsyncRead + Unpin,
+{
+    let direction = match reader.read_u8().await {
+        Ok(TAP_RECORD_READ) => TapDirection::Read,
+        Ok(TAP_RECORD_WRITE) => TapDirection::Write,
+        Ok(direction) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid tap record direction: {direction}"),
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let len = reader.read_u32().await?;
+    let mut data = BytesMut::zeroed(len as usize);
+    reader.read_exact(&mut data).await?;
+    Ok(Some(TapRecord {
+        direction,
+        data: data.freeze(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Buf;
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn test_tap_stream() {
+        let stream = Builder::new()
+            .read(b"hello")
+            .write(b"world")
+            .read(b"!")
+            .build();
+        let (tx, mut rx) = mpsc::channel(8);
+        let mut stream = TapStream::new(stream, tx);
+
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        stream.write_all(b"world").await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"!");
+        assert_eq!(stream.handle().dropped(), 0);
+        drop(stream);
+
+        let mut records = Vec::new();
+        while let Some(record) = rx.recv().await {
+            records.push((record.direction, record.data));
+        }
+        assert_eq!(
+            records,
+            [
+                (TapDirection::Read, Bytes::from_static(b"hello")),
+                (TapDirection::Write, Bytes::from_static(b"world")),
+                (TapDirection::Read, Bytes::from_static(b"!")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tap_stream_drops_when_sink_full() {
+        let stream = Builder::new().read(b"a").read(b"b").read(b"c").build();
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut stream = TapStream::new(stream, tx);
+        let handle = stream.handle();
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"abc");
+        assert_eq!(handle.dropped(), 2);
+
+        assert_eq!(rx.recv().await.unwrap().data, "a");
+    }
+
+    #[tokio::test]
+    async fn test_tap_records_round_trip() {
+        let records = [
+            TapRecord {
+                direction: TapDirection::Write,
+                data: Bytes::from_static(b"GET / HTTP/1.1\r\n\r\n"),
+            },
+            TapRecord {
+                direction: TapDirection::Read,
+                data: Bytes::from_static(b"HTTP/1.1 200 OK\r\n\r\n"),
+            },
+            TapRecord {
+                direction: TapDirection::Read,
+                data: Bytes::new(),
+            },
+        ];
+
+        let (tx, rx) = mpsc::channel(records.len());
+        for record in records.iter().cloned() {
+            tx.send(record).await.unwrap();
+        }
+        drop(tx);
+
+        let mut buf = Vec::new();
+        assert_eq!(write_tap_records(rx, &mut buf).await.unwrap(), 3);
+
+        let mut reader = &buf[..];
+        for expected in records {
+            let record = read_tap_record(&mut reader).await.unwrap().unwrap();
+            assert_eq!(record, expected);
+        }
+        assert!(read_tap_record(&mut reader).await.unwrap().is_none());
+        assert!(!reader.has_remaining());
+    }
+
+    #[tokio::test]
+    async fn test_read_tap_record_invalid() {
+        let mut reader = &[2u8, 0, 0, 0, 0][..];
+        let err = read_tap_record(&mut reader).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut reader = &[0u8, 0, 0, 0, 4, b'a'][..];
+        let err = read_tap_record(&mut reader).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
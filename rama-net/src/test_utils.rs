@@ -0,0 +1,368 @@
+//! Utilities to test network services and middleware.
+//!
+//! Available with the `test-utils` feature.
+
+use bytes::Bytes;
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An action in the script of a [`ScriptedStream`].
+pub enum ScriptAction {
+    /// Bytes returned by reads, possibly spread over multiple reads.
+    Read(Bytes),
+    /// Bytes expected to be written, possibly spread over multiple writes.
+    Write(Bytes),
+    /// Stall both reads and writes for the given duration.
+    Sleep(Duration),
+    /// Fail the next read or write with an error of the given kind.
+    Error(io::ErrorKind),
+    /// Return end-of-file from the next read.
+    Eof,
+}
+
+/// A stream which plays a script of [`ScriptAction`]s,
+/// used to test services and middleware which operate on streams.
+///
+/// Contrary to the `tokio_test` mock stream it also allows to script stalls,
+/// using [`tokio::time::sleep`], such that it integrates with [`tokio::time::pause`],
+/// as well as mid-stream errors and end-of-file.
+///
+/// A read (write) waits while the next action is a write (read),
+/// until the action was consumed by a write (read). Writing bytes other
+/// than the expected ones panics, as does writing when the script is exhausted.
+/// Reading when the script is exhausted returns end-of-file.
+///
+/// Dropping the stream panics in case not all actions were consumed,
+/// unless the thread is already panicking.
+///
+/// # Example
+///
+/// ```
+/// use rama_net::test_utils::ScriptedStream;
+/// use std::time::Duration;
+/// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+///
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// let mut stream = ScriptedStream::builder()
+///     .write(b"ping")
+///     .sleep(Duration::from_secs(5))
+///     .read(b"pong")
+///     .build();
+///
+/// stream.write_all(b"ping").await.unwrap();
+///
+/// let mut buf = [0u8; 4];
+/// let result = tokio::time::timeout(Duration::from_secs(1), stream.read_exact(&mut buf)).await;
+/// assert!(result.is_err());
+///
+/// stream.read_exact(&mut buf).await.unwrap();
+/// assert_eq!(&buf, b"pong");
+/// # }
+/// ```
+pub struct ScriptedStream {
+    actions: VecDeque<ScriptAction>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl fmt::Debug for ScriptedStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptedStream")
+            .field("actions", &self.actions)
+            .field("sleep", &self.sleep.as_ref().map(|sleep| sleep.deadline()))
+            .finish()
+    }
+}
+
+impl ScriptedStream {
+    /// Create a new [`ScriptedStream`] which plays the given actions.
+    pub fn new(actions: impl IntoIterator<Item = ScriptAction>) -> Self {
+        Self {
+            actions: actions.into_iter().collect(),
+            sleep: None,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    /// Create a [`ScriptedStreamBuilder`] to build a [`ScriptedStream`].
+    pub fn builder() -> ScriptedStreamBuilder {
+        ScriptedStreamBuilder::default()
+    }
+
+    /// Get the actions which were not consumed (yet).
+    pub fn remaining(&self) -> impl Iterator<Item = &ScriptAction> {
+        self.actions.iter()
+    }
+
+    /// Pop the front action, waking the other side which might be waiting on it.
+    fn advance(&mut self) {
+        self.actions.pop_front();
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn poll_sleep(&mut self, cx: &mut Context<'_>, duration: Duration) -> Poll<()> {
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(duration)));
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                self.sleep = None;
+                self.advance();
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl AsyncRead for ScriptedStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            match this.actions.front_mut() {
+                None => return Poll::Ready(Ok(())),
+                Some(ScriptAction::Sleep(duration)) => {
+                    let duration = *duration;
+                    this.read_waker = Some(cx.waker().clone());
+                    if this.poll_sleep(cx, duration).is_pending() {
+                        return Poll::Pending;
+                    }
+                }
+                Some(ScriptAction::Read(data)) => {
+                    let n = data.len().min(buf.remaining());
+                    buf.put_slice(&data.split_to(n));
+                    if data.is_empty() {
+                        this.advance();
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                Some(ScriptAction::Error(kind)) => {
+                    let err = io::Error::new(*kind, "scripted stream error");
+                    this.advance();
+                    return Poll::Ready(Err(err));
+                }
+                Some(ScriptAction::Eof) => {
+                    this.advance();
+                    return Poll::Ready(Ok(()));
+                }
+                Some(ScriptAction::Write(_)) => {
+                    this.read_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for ScriptedStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+        loop {
+            match this.actions.front_mut() {
+                None => panic!("scripted stream: unexpected write: script is exhausted: {buf:?}"),
+                Some(ScriptAction::Sleep(duration)) => {
+                    let duration = *duration;
+                    this.write_waker = Some(cx.waker().clone());
+                    if this.poll_sleep(cx, duration).is_pending() {
+                        return Poll::Pending;
+                    }
+                }
+                Some(ScriptAction::Write(expected)) => {
+                    let n = expected.len().min(buf.len());
+                    assert_eq!(
+                        &buf[..n],
+                        &expected[..n],
+                        "scripted stream: unexpected bytes written"
+                    );
+                    let _ = expected.split_to(n);
+                    if expected.is_empty() {
+                        this.advance();
+                    }
+                    return Poll::Ready(Ok(n));
+                }
+                Some(ScriptAction::Error(kind)) => {
+                    let err = io::Error::new(*kind, "scripted stream error");
+                    this.advance();
+                    return Poll::Ready(Err(err));
+                }
+                Some(ScriptAction::Read(_) | ScriptAction::Eof) => {
+                    this.write_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for ScriptedStream {
+    fn drop(&mut self) {
+        if !std::thread::panicking() && !self.actions.is_empty() {
+            panic!(
+                "scripted stream: dropped with unconsumed actions: {:?}",
+                self.actions
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Builder for a [`ScriptedStream`].
+pub struct ScriptedStreamBuilder {
+    actions: Vec<ScriptAction>,
+}
+
+impl ScriptedStreamBuilder {
+    /// Script bytes to be returned by reads.
+    pub fn read(mut self, data: impl AsRef<[u8]>) -> Self {
+        self.actions
+            .push(ScriptAction::Read(Bytes::copy_from_slice(data.as_ref())));
+        self
+    }
+
+    /// Script bytes expected to be written.
+    pub fn write(mut self, data: impl AsRef<[u8]>) -> Self {
+        self.actions
+            .push(ScriptAction::Write(Bytes::copy_from_slice(data.as_ref())));
+        self
+    }
+
+    /// Script a stall of both reads and writes for the given duration.
+    pub fn sleep(mut self, duration: Duration) -> Self {
+        self.actions.push(ScriptAction::Sleep(duration));
+        self
+    }
+
+    /// Script an error of the given kind for the next read or write.
+    pub fn error(mut self, kind: io::ErrorKind) -> Self {
+        self.actions.push(ScriptAction::Error(kind));
+        self
+    }
+
+    /// Script end-of-file for the next read.
+    pub fn eof(mut self) -> Self {
+        self.actions.push(ScriptAction::Eof);
+        self
+    }
+
+    /// Build the [`ScriptedStream`].
+    pub fn build(self) -> ScriptedStream {
+        ScriptedStream::new(self.actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::time::Instant;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scripted_stream() {
+        let mut stream = ScriptedStream::builder()
+            .read(b"hello")
+            .sleep(Duration::from_secs(3))
+            .write(b"world")
+            .eof()
+            .build();
+
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        let start = Instant::now();
+        stream.write_all(b"world").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+        assert_eq!(stream.remaining().count(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scripted_stream_error() {
+        let mut stream = ScriptedStream::builder()
+            .read(b"a")
+            .error(io::ErrorKind::ConnectionReset)
+            .build();
+
+        let mut buf = Vec::new();
+        let err = stream.read_to_end(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(buf, b"a");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scripted_stream_stall_timeout() {
+        let mut stream = ScriptedStream::builder()
+            .sleep(Duration::from_secs(10))
+            .read(b"late")
+            .build();
+
+        let mut buf = [0u8; 4];
+        let result =
+            tokio::time::timeout(Duration::from_secs(1), stream.read_exact(&mut buf)).await;
+        assert!(result.is_err());
+
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"late");
+    }
+
+    #[tokio::test]
+    async fn test_scripted_stream_concurrent_read_write() {
+        let stream = ScriptedStream::builder()
+            .write(b"ping")
+            .read(b"pong")
+            .build();
+        let (mut read_half, mut write_half) = tokio::io::split(stream);
+
+        let reader = tokio::spawn(async move {
+            let mut buf = [0u8; 4];
+            read_half.read_exact(&mut buf).await.unwrap();
+            (read_half, buf)
+        });
+        write_half.write_all(b"ping").await.unwrap();
+
+        let (read_half, buf) = reader.await.unwrap();
+        assert_eq!(&buf, b"pong");
+        drop(read_half.unsplit(write_half));
+    }
+
+    #[test]
+    #[should_panic(expected = "unconsumed actions")]
+    fn test_scripted_stream_unconsumed_panics_on_drop() {
+        drop(ScriptedStream::builder().read(b"unread").build());
+    }
+}
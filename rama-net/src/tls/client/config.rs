@@ -1,4 +1,5 @@
 use super::{merge_client_hello_lists, ClientHelloExtension};
+use crate::address::Host;
 use crate::tls::{CipherSuite, CompressionAlgorithm, DataEncoding, KeyLogIntent};
 
 #[derive(Debug, Clone, Default)]
@@ -26,6 +27,30 @@ pub struct ClientConfig {
 }
 
 impl ClientConfig {
+    /// Use the given [`Host`] as the server name (SNI) in the ClientHello,
+    /// instead of the host of the target the client connects to.
+    ///
+    /// This does not affect the request itself (e.g. the http `Host` header),
+    /// and is for example useful to test domain fronting. Note that the server
+    /// certificate is verified against the overwritten server name.
+    pub fn with_sni_override(mut self, host: Host) -> Self {
+        self.set_sni_override(host);
+        self
+    }
+
+    /// Use the given [`Host`] as the server name (SNI) in the ClientHello,
+    /// instead of the host of the target the client connects to.
+    ///
+    /// This does not affect the request itself (e.g. the http `Host` header),
+    /// and is for example useful to test domain fronting. Note that the server
+    /// certificate is verified against the overwritten server name.
+    pub fn set_sni_override(&mut self, host: Host) -> &mut Self {
+        let extensions = self.extensions.get_or_insert_with(Vec::new);
+        extensions.retain(|ext| !matches!(ext, ClientHelloExtension::ServerName(_)));
+        extensions.push(ClientHelloExtension::ServerName(Some(host)));
+        self
+    }
+
     /// Merge this [`ClientConfig`] with aother one.
     pub fn merge(&mut self, other: ClientConfig) {
         if let Some(cipher_suites) = other.cipher_suites {
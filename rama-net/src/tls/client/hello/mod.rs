@@ -3,6 +3,7 @@ use crate::tls::{
     enums::CompressionAlgorithm, ApplicationProtocol, CipherSuite, ECPointFormat, ExtensionId,
     ProtocolVersion, SignatureScheme, SupportedGroup,
 };
+use bytes::Bytes;
 
 #[cfg(feature = "rustls")]
 mod rustls;
@@ -24,6 +25,7 @@ pub struct ClientHello {
     pub(super) cipher_suites: Vec<CipherSuite>,
     pub(super) compression_algorithms: Vec<CompressionAlgorithm>,
     pub(super) extensions: Vec<ClientHelloExtension>,
+    pub(super) raw: Option<Bytes>,
 }
 
 impl ClientHello {
@@ -123,6 +125,21 @@ impl ClientHello {
         }
         None
     }
+
+    /// Return the raw ClientHello handshake message (without the handshake header)
+    /// from which this [`ClientHello`] was parsed.
+    ///
+    /// Useful to compute fingerprints other than the ones provided by rama
+    /// (e.g. [`Ja3`] and [`Ja4`]) or to archive the ClientHello as-is.
+    ///
+    /// Only available if the tls implementation exposes the raw message,
+    /// which is for example not the case for `rustls`.
+    ///
+    /// [`Ja3`]: crate::fingerprint::Ja3
+    /// [`Ja4`]: crate::fingerprint::Ja4
+    pub fn raw_bytes(&self) -> Option<&[u8]> {
+        self.raw.as_deref()
+    }
 }
 
 #[derive(Debug, Clone)]
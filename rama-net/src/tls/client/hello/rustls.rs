@@ -34,6 +34,7 @@ impl<'a> From<rustls::server::ClientHello<'a>> for super::ClientHello {
             cipher_suites,
             compression_algorithms: vec![],
             extensions,
+            raw: None,
         }
     }
 }
@@ -131,4 +131,29 @@ mod tests {
             ClientHelloExtension::ApplicationLayerProtocolNegotiation(_)
         ));
     }
+
+    #[test]
+    fn test_client_config_sni_override() {
+        let config = ClientConfig {
+            extensions: Some(vec![
+                ClientHelloExtension::ServerName(Some(Host::Name(Domain::from_static(
+                    "example.com",
+                )))),
+                ClientHelloExtension::SupportedVersions(vec![]),
+            ]),
+            ..Default::default()
+        }
+        .with_sni_override(Host::Name(Domain::from_static("front.example.com")));
+
+        let extensions = config.extensions.unwrap();
+        assert_eq!(extensions.len(), 2);
+        assert!(matches!(
+            extensions[0],
+            ClientHelloExtension::SupportedVersions(_)
+        ));
+        assert!(matches!(
+            &extensions[1],
+            ClientHelloExtension::ServerName(Some(host)) if host == &Host::Name(Domain::from_static("front.example.com"))
+        ));
+    }
 }
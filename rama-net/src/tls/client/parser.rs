@@ -8,6 +8,7 @@ use crate::address::Host;
 use crate::tls::{
     enums::CompressionAlgorithm, ApplicationProtocol, CipherSuite, ExtensionId, ProtocolVersion,
 };
+use bytes::Bytes;
 use nom::{
     bytes::streaming::take,
     combinator::{complete, cond, map, map_parser, opt, verify},
@@ -20,14 +21,17 @@ use rama_core::error::OpaqueError;
 use std::str;
 
 #[inline]
-pub(crate) fn parse_client_hello(i: &[u8]) -> Result<ClientHello, OpaqueError> {
-    match parse_client_hello_inner(i) {
+pub(crate) fn parse_client_hello(input: &[u8]) -> Result<ClientHello, OpaqueError> {
+    match parse_client_hello_inner(input) {
         Err(err) => Err(OpaqueError::from_display(format!(
             "parse client hello handshake message: {err:?}"
         ))),
         Ok((i, hello)) => {
             if i.is_empty() {
-                Ok(hello)
+                Ok(ClientHello {
+                    raw: Some(Bytes::copy_from_slice(input)),
+                    ..hello
+                })
             } else {
                 Err(OpaqueError::from_display(
                     "parse client hello handshake message: unexpected trailer content",
@@ -64,6 +68,7 @@ fn parse_client_hello_inner(i: &[u8]) -> IResult<&[u8], ClientHello> {
             cipher_suites,
             compression_algorithms,
             extensions,
+            raw: None,
         },
     ))
 }
@@ -329,6 +334,28 @@ mod tests {
         assert!(parse_client_hello(&[]).is_err());
     }
 
+    #[test]
+    fn test_parse_client_hello_raw_bytes() {
+        let input = [
+            0x03, 0x03, // legacy version
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+            0x1d, 0x1e, 0x1f, 0x20, // random
+            0x00, // session id
+            0x00, 0x04, 0x13, 0x01, 0x13, 0x02, // cipher suites
+            0x01, 0x00, // compression algorithms
+            0x00, 0x0a, 0x00, 0x0a, 0x00, 0x06, 0x00, 0x04, 0x00, 0x1d, 0x00,
+            0x17, // extensions: supported groups
+        ];
+        let client_hello = parse_client_hello(&input).unwrap();
+        assert_eq!(client_hello.raw_bytes(), Some(&input[..]));
+        assert_eq!(client_hello.cipher_suites().len(), 2);
+        assert_eq!(
+            client_hello.ext_supported_groups(),
+            Some(&[SupportedGroup::X25519, SupportedGroup::SECP256R1][..])
+        );
+    }
+
     #[test]
     fn test_parse_client_hello_pcap_dump_apple_itunes_bytes_success() {
         let client_hello = parse_client_hello(&[
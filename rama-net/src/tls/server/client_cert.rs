@@ -0,0 +1,125 @@
+use std::{fmt, net::IpAddr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A client certificate verified by a (tls) server,
+/// added to the service context by Tls implementations
+/// in case client auth is enabled and the client provided a certificate.
+///
+/// Use it to authorize the client in downstream services,
+/// e.g. by matching on its subject alternative names.
+pub struct VerifiedClientCertificate {
+    /// The subject distinguished name of the (leaf) certificate,
+    /// formatted as a comma-separated list of `key=value` pairs,
+    /// e.g. `CN=client.example.com,O=Example`.
+    pub subject: String,
+    /// The subject alternative names of the (leaf) certificate.
+    pub subject_alt_names: Vec<SubjectAltName>,
+    /// The SHA-256 fingerprint of the DER-encoded (leaf) certificate.
+    pub fingerprint_sha256: [u8; 32],
+}
+
+impl VerifiedClientCertificate {
+    /// Returns the (lowercase) hex-encoded SHA-256 fingerprint of the certificate.
+    pub fn fingerprint_sha256_hex(&self) -> String {
+        hex::encode(self.fingerprint_sha256)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A subject alternative name (SAN) of a [`VerifiedClientCertificate`].
+pub enum SubjectAltName {
+    /// A DNS name, e.g. `client.example.com`.
+    Dns(String),
+    /// An IP address.
+    Ip(IpAddr),
+    /// An email address (`rfc822Name`).
+    Email(String),
+    /// An URI, e.g. a SPIFFE ID such as `spiffe://example.org/service/foo`.
+    Uri(String),
+}
+
+impl fmt::Display for SubjectAltName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dns(name) => write!(f, "DNS:{name}"),
+            Self::Ip(ip) => write!(f, "IP:{ip}"),
+            Self::Email(email) => write!(f, "email:{email}"),
+            Self::Uri(uri) => write!(f, "URI:{uri}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Error returned by a (tls) server in case the
+/// handshake failed because of the verification of the client certificate.
+pub enum ClientVerifyError {
+    /// Client auth is required, but the client did not provide a certificate.
+    MissingCertificate,
+    /// The certificate provided by the client was rejected,
+    /// e.g. because it is not signed by one of the trusted CAs or expired.
+    Rejected {
+        /// Reason, as reported by the tls implementation, why the certificate was rejected.
+        reason: String,
+    },
+}
+
+impl fmt::Display for ClientVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingCertificate => {
+                f.write_str("tls client verification: client did not provide a certificate")
+            }
+            Self::Rejected { reason } => write!(
+                f,
+                "tls client verification: client certificate rejected: {reason}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ClientVerifyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verified_client_certificate_fingerprint_hex() {
+        let mut fingerprint_sha256 = [0u8; 32];
+        fingerprint_sha256[0] = 0xab;
+        fingerprint_sha256[31] = 0x01;
+        let cert = VerifiedClientCertificate {
+            subject: "CN=client.example.com".to_owned(),
+            subject_alt_names: vec![
+                SubjectAltName::Dns("client.example.com".to_owned()),
+                SubjectAltName::Uri("spiffe://example.org/service/foo".to_owned()),
+            ],
+            fingerprint_sha256,
+        };
+        let hex = cert.fingerprint_sha256_hex();
+        assert_eq!(hex.len(), 64);
+        assert!(hex.starts_with("ab00"));
+        assert!(hex.ends_with("0001"));
+    }
+
+    #[test]
+    fn test_subject_alt_name_display() {
+        for (san, expected) in [
+            (
+                SubjectAltName::Dns("example.com".to_owned()),
+                "DNS:example.com",
+            ),
+            (SubjectAltName::Ip([127, 0, 0, 1].into()), "IP:127.0.0.1"),
+            (
+                SubjectAltName::Email("john@example.com".to_owned()),
+                "email:john@example.com",
+            ),
+            (
+                SubjectAltName::Uri("spiffe://example.org/foo".to_owned()),
+                "URI:spiffe://example.org/foo",
+            ),
+        ] {
+            assert_eq!(san.to_string(), expected);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::SniCertResolver;
 use crate::{
     address::Host,
     tls::{client::ClientHello, ApplicationProtocol, DataEncoding, KeyLogIntent, ProtocolVersion},
@@ -58,6 +59,8 @@ pub enum ServerAuth {
     Single(ServerAuthData),
     /// Issuer which provides certs on the fly
     CertIssuer(ServerCertIssuerData),
+    /// Data selected based on the SNI hostname sent by the client
+    Sni(SniCertResolver),
 }
 
 impl Default for ServerAuth {
@@ -213,6 +216,15 @@ pub enum ClientVerifyMode {
     Auto,
     /// Explicitly disable client verification (if possible)
     Disable,
-    /// PEM-encoded certificate chain containing the acceptable client certificates
+    /// Require clients to provide a certificate,
+    /// verified against the given trusted (CA) certificates.
+    ///
+    /// Clients that do not provide a (valid) certificate fail the handshake.
     ClientAuth(DataEncoding),
+    /// Request clients to provide a certificate,
+    /// verified against the given trusted (CA) certificates.
+    ///
+    /// Clients that do not provide a certificate are still accepted,
+    /// while clients that provide an invalid certificate fail the handshake.
+    OptionalClientAuth(DataEncoding),
 }
@@ -6,3 +6,15 @@ pub use config::{
     CacheKind, ClientVerifyMode, DynamicCertIssuer, DynamicIssuer, SelfSignedData, ServerAuth,
     ServerAuthData, ServerCertIssuerData, ServerCertIssuerKind, ServerConfig,
 };
+
+mod sni;
+#[doc(inline)]
+pub use sni::{SelectedServerName, SniCertResolver};
+
+mod client_cert;
+#[doc(inline)]
+pub use client_cert::{ClientVerifyError, SubjectAltName, VerifiedClientCertificate};
+
+mod peek;
+#[doc(inline)]
+pub use peek::{TlsPeekRouter, TlsPeekStream};
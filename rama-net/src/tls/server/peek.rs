@@ -0,0 +1,170 @@
+use crate::stream::{PeekStream, Stream};
+use rama_core::{error::BoxError, Context, Service};
+use std::fmt;
+
+/// The [`Stream`] type passed by the [`TlsPeekRouter`] to its inner services,
+/// replaying the bytes that were peeked in order to detect TLS traffic.
+pub type TlsPeekStream<S> = PeekStream<S>;
+
+/// Amount of bytes peeked in order to detect a TLS record header:
+/// the content type followed by the (major, minor) protocol version.
+const TLS_PEEK_LEN: usize = 3;
+
+/// Content type of a TLS handshake record.
+const TLS_CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+
+/// A [`Service`] router which allows to serve both TLS and plaintext
+/// traffic on the same listener (port).
+///
+/// It peeks the first bytes of the incoming stream in order to detect a TLS
+/// handshake record, after which it dispatches the stream to either the TLS
+/// service or the fallback (plaintext) service. The peeked bytes are replayed
+/// to the service handling the stream, such that no bytes are lost.
+///
+/// Streams which are closed prior to sending enough bytes to
+/// detect a TLS record header are dispatched to the fallback service.
+pub struct TlsPeekRouter<T, F> {
+    tls_service: T,
+    fallback: F,
+}
+
+impl<T, F> TlsPeekRouter<T, F> {
+    /// Create a new [`TlsPeekRouter`], dispatching TLS traffic to the `tls_service`
+    /// and all other traffic to the `fallback` service.
+    pub const fn new(tls_service: T, fallback: F) -> Self {
+        Self {
+            tls_service,
+            fallback,
+        }
+    }
+}
+
+impl<T: fmt::Debug, F: fmt::Debug> fmt::Debug for TlsPeekRouter<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsPeekRouter")
+            .field("tls_service", &self.tls_service)
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+impl<T: Clone, F: Clone> Clone for TlsPeekRouter<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            tls_service: self.tls_service.clone(),
+            fallback: self.fallback.clone(),
+        }
+    }
+}
+
+impl<State, Stream, Response, T, F> Service<State, Stream> for TlsPeekRouter<T, F>
+where
+    State: Clone + Send + Sync + 'static,
+    Stream: self::Stream + Unpin,
+    Response: Send + 'static,
+    T: Service<State, TlsPeekStream<Stream>, Response = Response, Error: Into<BoxError>>,
+    F: Service<State, TlsPeekStream<Stream>, Response = Response, Error: Into<BoxError>>,
+{
+    type Response = Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        stream: Stream,
+    ) -> Result<Self::Response, Self::Error> {
+        let mut stream = PeekStream::new(stream);
+        let is_tls = is_tls_record_header(stream.peek(TLS_PEEK_LEN).await?);
+
+        if is_tls {
+            tracing::trace!("tls peek router: tls record detected: serve tls service");
+            self.tls_service
+                .serve(ctx, stream)
+                .await
+                .map_err(Into::into)
+        } else {
+            tracing::trace!("tls peek router: no tls record detected: serve fallback service");
+            self.fallback.serve(ctx, stream).await.map_err(Into::into)
+        }
+    }
+}
+
+fn is_tls_record_header(peek: &[u8]) -> bool {
+    // only handshake records can start a TLS connection,
+    // and all TLS versions (and SSLv3) use major version 3
+    matches!(peek, [TLS_CONTENT_TYPE_HANDSHAKE, 0x03, minor] if *minor <= 0x04)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+    use tokio::io::AsyncReadExt;
+    use tokio_test::io::{Builder, Mock};
+
+    const CLIENT_HELLO_PREFIX: &[u8] = &[
+        0x16, 0x03, 0x01, 0x00, 0xf8, 0x01, 0x00, 0x00, 0xf4, 0x03, 0x03,
+    ];
+
+    async fn read_all(
+        label: &'static str,
+        mut stream: TlsPeekStream<Mock>,
+    ) -> Result<(&'static str, Vec<u8>), Infallible> {
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        Ok((label, buf))
+    }
+
+    async fn route(stream: Mock) -> (&'static str, Vec<u8>) {
+        TlsPeekRouter::new(
+            service_fn(|stream| read_all("tls", stream)),
+            service_fn(|stream| read_all("plain", stream)),
+        )
+        .serve(Context::default(), stream)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tls_peek_router_client_hello() {
+        let stream = Builder::new()
+            .read(&CLIENT_HELLO_PREFIX[..2])
+            .read(&CLIENT_HELLO_PREFIX[2..])
+            .build();
+
+        let (label, bytes) = route(stream).await;
+        assert_eq!(label, "tls");
+        assert_eq!(bytes, CLIENT_HELLO_PREFIX);
+    }
+
+    #[tokio::test]
+    async fn test_tls_peek_router_plaintext_http() {
+        let request = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let (label, bytes) = route(Builder::new().read(request).build()).await;
+        assert_eq!(label, "plain");
+        assert_eq!(bytes, request);
+    }
+
+    #[tokio::test]
+    async fn test_tls_peek_router_short_stream() {
+        let (label, bytes) = route(Builder::new().read(&[0x16, 0x03]).build()).await;
+        assert_eq!(label, "plain");
+        assert_eq!(bytes, [0x16, 0x03]);
+    }
+
+    #[test]
+    fn test_is_tls_record_header() {
+        for (input, expected) in [
+            (&[0x16, 0x03, 0x00][..], true),
+            (&[0x16, 0x03, 0x03][..], true),
+            (&[0x16, 0x03, 0x05][..], false),
+            (&[0x17, 0x03, 0x03][..], false),
+            (&[0x16, 0x02, 0x00][..], false),
+            (b"GET"[..].as_ref(), false),
+            (&[][..], false),
+        ] {
+            assert_eq!(is_tls_record_header(input), expected, "input: {input:?}");
+        }
+    }
+}
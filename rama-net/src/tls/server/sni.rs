@@ -0,0 +1,147 @@
+use super::ServerAuthData;
+use crate::address::Domain;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+#[derive(Clone)]
+/// Resolves the [`ServerAuthData`] to present to a client
+/// based on the SNI hostname it sent as part of its client hello,
+/// used to serve multiple domains on a single listener.
+///
+/// Clients which send no hostname or one for which the resolver
+/// has no [`ServerAuthData`] are presented the fallback [`ServerAuthData`]
+/// if one is defined, and get their handshake aborted otherwise.
+///
+/// Used as [`ServerAuth::Sni`].
+///
+/// [`ServerAuth::Sni`]: super::ServerAuth::Sni
+pub struct SniCertResolver {
+    resolver: Arc<dyn Fn(&str) -> Option<ServerAuthData> + Send + Sync>,
+    fallback: Option<ServerAuthData>,
+}
+
+impl SniCertResolver {
+    /// Create a new [`SniCertResolver`] using the given resolver,
+    /// called with the SNI hostname sent by the client.
+    ///
+    /// Tls implementations can cache the certificate resolved for a hostname
+    /// for a while (e.g. `boring` does so for up to an hour), such that the
+    /// resolver is not necessarily called for every handshake.
+    pub fn new<F>(resolver: F) -> Self
+    where
+        F: Fn(&str) -> Option<ServerAuthData> + Send + Sync + 'static,
+    {
+        Self {
+            resolver: Arc::new(resolver),
+            fallback: None,
+        }
+    }
+
+    /// Create a new [`SniCertResolver`] resolving the [`ServerAuthData`]
+    /// for the SNI hostnames from the given (domain, data) pairs.
+    ///
+    /// Hostnames are matched exactly, ignoring case.
+    pub fn from_map<I>(certs: I) -> Self
+    where
+        I: IntoIterator<Item = (Domain, ServerAuthData)>,
+    {
+        let certs: HashMap<Domain, ServerAuthData> = certs.into_iter().collect();
+        Self::new(move |server_name| {
+            let domain = Domain::try_from(server_name.to_owned()).ok()?;
+            certs.get(&domain).cloned()
+        })
+    }
+
+    /// Set the [`ServerAuthData`] presented to clients
+    /// which send no or an unknown SNI hostname,
+    /// instead of aborting their handshake.
+    pub fn with_fallback(mut self, data: ServerAuthData) -> Self {
+        self.fallback = Some(data);
+        self
+    }
+
+    /// Set the [`ServerAuthData`] presented to clients
+    /// which send no or an unknown SNI hostname,
+    /// instead of aborting their handshake.
+    pub fn set_fallback(&mut self, data: ServerAuthData) -> &mut Self {
+        self.fallback = Some(data);
+        self
+    }
+
+    /// Resolve the [`ServerAuthData`] for the given SNI hostname,
+    /// without considering the fallback.
+    pub fn resolve(&self, server_name: &str) -> Option<ServerAuthData> {
+        (self.resolver)(server_name)
+    }
+
+    /// Returns the [`ServerAuthData`] presented to clients
+    /// which send no or an unknown SNI hostname, if any.
+    pub fn fallback(&self) -> Option<&ServerAuthData> {
+        self.fallback.as_ref()
+    }
+}
+
+impl fmt::Debug for SniCertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SniCertResolver")
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The SNI hostname for which a [`SniCertResolver`] resolved the server certificate,
+/// added to the service context by Tls implementations.
+///
+/// It is not added in case the fallback certificate was presented.
+pub struct SelectedServerName {
+    /// The SNI hostname as sent by the client.
+    pub server_name: Domain,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::DataEncoding;
+
+    fn auth_data(name: &str) -> ServerAuthData {
+        ServerAuthData {
+            private_key: DataEncoding::Der(format!("{name}-key").into_bytes()),
+            cert_chain: DataEncoding::Der(format!("{name}-cert").into_bytes()),
+            ocsp: None,
+        }
+    }
+
+    fn cert_chain(data: Option<ServerAuthData>) -> Option<String> {
+        data.map(|data| match data.cert_chain {
+            DataEncoding::Der(der) => String::from_utf8(der).unwrap(),
+            _ => unreachable!(),
+        })
+    }
+
+    #[test]
+    fn test_sni_cert_resolver_from_map() {
+        let resolver = SniCertResolver::from_map([
+            (Domain::from_static("a.example.com"), auth_data("a")),
+            (Domain::from_static("b.example.com"), auth_data("b")),
+        ]);
+        assert_eq!(
+            cert_chain(resolver.resolve("a.example.com")).as_deref(),
+            Some("a-cert")
+        );
+        assert_eq!(
+            cert_chain(resolver.resolve("B.Example.Com")).as_deref(),
+            Some("b-cert")
+        );
+        assert!(resolver.resolve("c.example.com").is_none());
+        assert!(resolver.resolve("example.com").is_none());
+        assert!(resolver.resolve("").is_none());
+        assert!(resolver.fallback().is_none());
+
+        let resolver = resolver.with_fallback(auth_data("default"));
+        assert!(resolver.resolve("c.example.com").is_none());
+        assert_eq!(
+            cert_chain(resolver.fallback().cloned()).as_deref(),
+            Some("default-cert")
+        );
+    }
+}
@@ -57,13 +57,13 @@ impl<State, Body> TryFrom<(&Context<State>, &Request<Body>)> for TransportContex
     fn try_from(
         (ctx, req): (&Context<State>, &Request<Body>),
     ) -> Result<TransportContext, Self::Error> {
-        Ok(match ctx.get::<RequestContext>() {
-            Some(req_ctx) => req_ctx.into(),
+        match ctx.get::<RequestContext>() {
+            Some(req_ctx) => req_ctx.try_into(),
             None => {
                 let req_ctx = RequestContext::try_from((ctx, req))?;
-                req_ctx.into()
+                req_ctx.try_into()
             }
-        })
+        }
     }
 }
 
@@ -73,12 +73,12 @@ impl<State> TryFrom<(&Context<State>, &HttpParts)> for TransportContext {
     fn try_from(
         (ctx, parts): (&Context<State>, &HttpParts),
     ) -> Result<TransportContext, Self::Error> {
-        Ok(match ctx.get::<RequestContext>() {
-            Some(req_ctx) => req_ctx.into(),
+        match ctx.get::<RequestContext>() {
+            Some(req_ctx) => req_ctx.try_into(),
             None => {
                 let req_ctx = RequestContext::try_from((ctx, parts))?;
-                req_ctx.into()
+                req_ctx.try_into()
             }
-        })
+        }
     }
 }
@@ -0,0 +1,209 @@
+use super::TcpStreamConnector;
+use rama_net::mode::ConnectIpMode;
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+use tokio::net::{TcpSocket, TcpStream};
+
+#[derive(Debug, Clone, Default)]
+/// A [`TcpStreamConnector`] which binds the sockets of outbound connections
+/// to a pinned source address and/or network device before connecting,
+/// used to pick the egress of multi-homed hosts.
+///
+/// A source address is pinned per IP family, and only used to connect to
+/// targets of that family. Targets of a family for which no source address
+/// is pinned are refused, as long as a source address is pinned for the other family,
+/// such that no connection leaves via an unintended address. Use
+/// [`LocalBindConnector::connect_ip_mode`] to only attempt addresses of the pinned families.
+///
+/// Use it as the connector of a `TcpConnector` (available with the `http` feature)
+/// or pass it directly to [`tcp_connect`].
+///
+/// ```
+/// use rama_tcp::client::LocalBindConnector;
+/// use std::net::Ipv4Addr;
+///
+/// let connector = LocalBindConnector::new().with_bind_source(Ipv4Addr::LOCALHOST.into());
+/// assert_eq!(connector.bind_source(false), Some(Ipv4Addr::LOCALHOST.into()));
+/// ```
+///
+/// [`tcp_connect`]: super::tcp_connect
+pub struct LocalBindConnector {
+    ipv4: Option<Ipv4Addr>,
+    ipv6: Option<Ipv6Addr>,
+    device: Option<Arc<str>>,
+}
+
+impl LocalBindConnector {
+    /// Create a new [`LocalBindConnector`], which binds to nothing until configured otherwise.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pin the source address used for outbound connections of its IP family,
+    /// replacing the source address previously pinned for that family, if any.
+    pub fn with_bind_source(mut self, ip: IpAddr) -> Self {
+        self.set_bind_source(ip);
+        self
+    }
+
+    /// Pin the source address used for outbound connections of its IP family,
+    /// replacing the source address previously pinned for that family, if any.
+    pub fn set_bind_source(&mut self, ip: IpAddr) -> &mut Self {
+        match ip {
+            IpAddr::V4(ip) => self.ipv4 = Some(ip),
+            IpAddr::V6(ip) => self.ipv6 = Some(ip),
+        }
+        self
+    }
+
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    /// Bind outbound connections to the network device (interface) with the given name,
+    /// e.g. `eth1`, using `SO_BINDTODEVICE`.
+    pub fn with_bind_device(mut self, name: impl AsRef<str>) -> Self {
+        self.set_bind_device(name);
+        self
+    }
+
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    /// Bind outbound connections to the network device (interface) with the given name,
+    /// e.g. `eth1`, using `SO_BINDTODEVICE`.
+    pub fn set_bind_device(&mut self, name: impl AsRef<str>) -> &mut Self {
+        self.device = Some(name.as_ref().into());
+        self
+    }
+
+    /// Returns the source address pinned for the given IP family, if any.
+    pub fn bind_source(&self, ipv6: bool) -> Option<IpAddr> {
+        if ipv6 {
+            self.ipv6.map(Into::into)
+        } else {
+            self.ipv4.map(Into::into)
+        }
+    }
+
+    /// Returns the name of the network device outbound connections are bound to, if any.
+    pub fn bind_device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+
+    /// Returns the [`ConnectIpMode`] matching the pinned source addresses,
+    /// which can be inserted in the [`Context`] to only attempt
+    /// the resolved addresses this connector can connect to.
+    ///
+    /// [`Context`]: rama_core::Context
+    pub fn connect_ip_mode(&self) -> ConnectIpMode {
+        match (self.ipv4, self.ipv6) {
+            (Some(_), None) => ConnectIpMode::Ipv4,
+            (None, Some(_)) => ConnectIpMode::Ipv6,
+            _ => ConnectIpMode::Dual,
+        }
+    }
+
+    async fn connect_socket(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+
+        match self.bind_source(addr.is_ipv6()) {
+            Some(ip) => socket.bind((ip, 0).into())?,
+            None if self.ipv4.is_some() || self.ipv6.is_some() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    format!("no source address pinned for the IP family of {addr}"),
+                ));
+            }
+            None => (),
+        }
+
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Some(device) = &self.device {
+            socket.bind_device(Some(device.as_bytes()))?;
+        }
+
+        socket.connect(addr).await
+    }
+}
+
+impl TcpStreamConnector for LocalBindConnector {
+    type Error = io::Error;
+
+    fn connect(
+        &self,
+        addr: SocketAddr,
+    ) -> impl Future<Output = Result<TcpStream, Self::Error>> + Send + '_ {
+        self.connect_socket(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_bind_connect_ip_mode() {
+        let connector = LocalBindConnector::new();
+        assert_eq!(connector.connect_ip_mode(), ConnectIpMode::Dual);
+
+        let connector = connector.with_bind_source(Ipv4Addr::LOCALHOST.into());
+        assert_eq!(connector.connect_ip_mode(), ConnectIpMode::Ipv4);
+        assert_eq!(
+            connector.bind_source(false),
+            Some(Ipv4Addr::LOCALHOST.into())
+        );
+        assert!(connector.bind_source(true).is_none());
+
+        let connector = connector.with_bind_source(Ipv6Addr::LOCALHOST.into());
+        assert_eq!(connector.connect_ip_mode(), ConnectIpMode::Dual);
+    }
+
+    #[tokio::test]
+    async fn test_local_bind_refuses_unpinned_family() {
+        let connector = LocalBindConnector::new().with_bind_source(Ipv4Addr::LOCALHOST.into());
+        let err = connector
+            .connect((Ipv6Addr::LOCALHOST, 1).into())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrNotAvailable);
+    }
+
+    // the entire 127.0.0.0/8 block is only routed to the loopback device on linux
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_local_bind_source_address() {
+        use crate::client::tcp_connect;
+        use rama_core::Context;
+        use rama_dns::InMemoryDns;
+        use rama_net::address::{Authority, Domain};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let source = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+        let mut dns = InMemoryDns::new();
+        dns.insert(
+            Domain::from_static("example.com"),
+            vec![IpAddr::V6(Ipv6Addr::LOCALHOST), addr.ip()],
+        );
+
+        let (stream, connected_addr) = tcp_connect(
+            &Context::<()>::default(),
+            Authority::new(Domain::from_static("example.com").into(), addr.port()),
+            false,
+            dns,
+            LocalBindConnector::new().with_bind_source(source),
+        )
+        .await
+        .unwrap();
+        assert_eq!(connected_addr, addr);
+        assert_eq!(stream.local_addr().unwrap().ip(), source);
+
+        let (_, peer_addr) = listener.accept().await.unwrap();
+        assert_eq!(peer_addr.ip(), source);
+    }
+}
@@ -1,28 +1,20 @@
 use rama_core::{
-    combinators::Either,
-    error::{BoxError, ErrorContext, OpaqueError},
+    error::{BoxError, OpaqueError},
+    layer::timeout::Deadline,
     Context,
 };
 use rama_dns::{DnsOverwrite, DnsResolver, HickoryDns};
 use rama_net::address::{Authority, Domain, Host};
 use rama_net::mode::{ConnectIpMode, DnsResolveIpMode};
 use std::{
+    fmt,
     future::Future,
     net::{IpAddr, SocketAddr},
     ops::Deref,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
+    sync::Arc,
     time::Duration,
 };
-use tokio::{
-    net::TcpStream,
-    sync::{
-        mpsc::{channel, Sender},
-        Semaphore,
-    },
-};
+use tokio::{net::TcpStream, task::JoinSet};
 
 /// Trait used internally by [`tcp_connect`] and the `TcpConnector`
 /// to actually establish the [`TcpStream`.]
@@ -101,6 +93,60 @@ macro_rules! impl_stream_connector_either {
 
 ::rama_core::combinators::impl_either!(impl_stream_connector_either);
 
+#[derive(Debug)]
+/// Error returned by [`tcp_connect`] in case no connection could be established
+/// to any of the (resolved) addresses of the target [`Authority`].
+///
+/// Every address that was attempted is listed together with the error
+/// that made that attempt fail, in the order in which the attempts failed.
+/// Retrieve it by downcasting the [`OpaqueError`] returned by [`tcp_connect`].
+pub struct ConnectError {
+    authority: Authority,
+    attempts: Vec<(SocketAddr, OpaqueError)>,
+    timed_out: bool,
+}
+
+impl ConnectError {
+    /// Get the target [`Authority`] to which no connection could be established.
+    pub fn authority(&self) -> &Authority {
+        &self.authority
+    }
+
+    /// Get the addresses which were attempted, each with the error of its attempt.
+    ///
+    /// This is empty in case no address could be resolved for the target,
+    /// or in case the connect [`Deadline`] expired prior to any attempt failing.
+    pub fn attempts(&self) -> &[(SocketAddr, OpaqueError)] {
+        &self.attempts
+    }
+
+    /// Returns `true` if the connect [`Deadline`] (found in the [`Context`])
+    /// expired before a connection could be established.
+    pub fn is_timeout(&self) -> bool {
+        self.timed_out
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.timed_out {
+            write!(f, "connect deadline expired for {}", self.authority)?;
+        } else {
+            write!(f, "failed to connect to {}", self.authority)?;
+        }
+        if self.attempts.is_empty() {
+            return f.write_str(": no address attempted");
+        }
+        for (index, (addr, err)) in self.attempts.iter().enumerate() {
+            let sep = if index == 0 { ": " } else { "; " };
+            write!(f, "{sep}{addr} ({err})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
 #[inline]
 /// Establish a [`TcpStream`] connection for the given [`Authority`],
 /// using the default settings and no custom state.
@@ -118,6 +164,21 @@ where
 }
 
 /// Establish a [`TcpStream`] connection for the given [`Authority`].
+///
+/// In case the [`Authority`] is a domain, all its resolved addresses are attempted
+/// until one of them connects, such that a single dead address does not fail
+/// the connect as a whole. The attempts interleave IPv4 and IPv6 addresses,
+/// starting with the family preferred by the [`DnsResolveIpMode`] found in the [`Context`],
+/// and are raced as described by Happy Eyeballs ([RFC 8305]), which can be configured
+/// by inserting a [`HappyEyeballsConfig`] in the [`Context`].
+/// The attempts are bound by the [`Deadline`] found in the [`Context`], if any.
+/// Only addresses of the IP families allowed by the [`ConnectIpMode`]
+/// found in the [`Context`] (dual-stack by default) are attempted.
+///
+/// The returned error contains a [`ConnectError`] in case no
+/// connection could be established to any of the attempted addresses.
+///
+/// [RFC 8305]: https://www.rfc-editor.org/rfc/rfc8305
 pub async fn tcp_connect<State, Dns, Connector>(
     ctx: &Context<State>,
     authority: Authority,
@@ -130,7 +191,7 @@ where
     Dns: DnsResolver<Error: Into<BoxError>> + Clone,
     Connector: TcpStreamConnector<Error: Into<BoxError> + Send + 'static> + Clone,
 {
-    let ip_mode = ctx.get().copied().unwrap_or_default();
+    let ip_mode: ConnectIpMode = ctx.get().copied().unwrap_or_default();
     let dns_mode = ctx.get().copied().unwrap_or_default();
 
     let (host, port) = authority.into_parts();
@@ -138,30 +199,36 @@ where
         Host::Name(domain) => domain,
         Host::Address(ip) => {
             //check if IP Version is allowed
-            match (ip, ip_mode) {
-                (IpAddr::V4(_), ConnectIpMode::Ipv6) => {
-                    return Err(OpaqueError::from_display("IPv4 address is not allowed"));
-                }
-                (IpAddr::V6(_), ConnectIpMode::Ipv4) => {
-                    return Err(OpaqueError::from_display("IPv6 address is not allowed"));
-                }
-                _ => (),
+            if !ip_mode.ip_supported(&ip) {
+                return Err(OpaqueError::from_display(match ip {
+                    IpAddr::V4(_) => "IPv4 address is not allowed",
+                    IpAddr::V6(_) => "IPv6 address is not allowed",
+                }));
             }
 
             // if the authority is already defined as an IP address, we can directly connect to it
             let addr = (ip, port).into();
-            let stream = connector
-                .connect(addr)
-                .await
-                .map_err(|err| OpaqueError::from_boxed(err.into()))
-                .context("establish tcp client connection")?;
-            return Ok((stream, addr));
+            let authority = Authority::new(Host::Address(ip), port);
+            let deadline = ctx.get::<Deadline>().copied();
+            return match with_deadline(deadline, connector.connect(addr)).await {
+                Some(Ok(stream)) => Ok((stream, addr)),
+                Some(Err(err)) => Err(OpaqueError::from_std(ConnectError {
+                    authority,
+                    attempts: vec![(addr, OpaqueError::from_boxed(err.into()))],
+                    timed_out: false,
+                })),
+                None => Err(OpaqueError::from_std(ConnectError {
+                    authority,
+                    attempts: Vec::new(),
+                    timed_out: true,
+                })),
+            };
         }
     };
 
     if allow_overwrites {
         if let Some(dns_overwrite) = ctx.get::<DnsOverwrite>() {
-            if let Ok(tuple) = tcp_connect_inner(
+            if let Ok(tuple) = happy_eyeballs_connect(
                 ctx,
                 domain.clone(),
                 port,
@@ -178,12 +245,76 @@ where
     }
 
     //... otherwise we'll try to establish a connection,
-    // with dual-stack parallel connections...
+    // racing the dual-stack addresses...
+
+    happy_eyeballs_connect(ctx, domain, port, dns_mode, dns, connector, ip_mode).await
+}
 
-    tcp_connect_inner(ctx, domain, port, dns_mode, dns, connector, ip_mode).await
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Configuration of the Happy Eyeballs ([RFC 8305]) connection racing
+/// used by [`tcp_connect`] to connect to the resolved addresses of a domain.
+///
+/// Insert it in the [`Context`] to overwrite the default configuration.
+///
+/// [RFC 8305]: https://www.rfc-editor.org/rfc/rfc8305
+pub struct HappyEyeballsConfig {
+    fallback_delay: Duration,
 }
 
-async fn tcp_connect_inner<State, Dns, Connector>(
+impl HappyEyeballsConfig {
+    /// The default delay prior to starting the next connection attempt,
+    /// as recommended by [RFC 8305](https://www.rfc-editor.org/rfc/rfc8305#section-8).
+    pub const DEFAULT_FALLBACK_DELAY: Duration = Duration::from_millis(250);
+
+    /// Create a new [`HappyEyeballsConfig`] using the default configuration.
+    pub const fn new() -> Self {
+        Self {
+            fallback_delay: Self::DEFAULT_FALLBACK_DELAY,
+        }
+    }
+
+    /// Set the delay given to a pending connection attempt before the
+    /// next address is attempted in parallel, 250ms by default.
+    ///
+    /// A failed attempt starts the next one immediately.
+    pub const fn with_fallback_delay(mut self, delay: Duration) -> Self {
+        self.fallback_delay = delay;
+        self
+    }
+
+    /// Set the delay given to a pending connection attempt before the
+    /// next address is attempted in parallel, 250ms by default.
+    ///
+    /// A failed attempt starts the next one immediately.
+    pub fn set_fallback_delay(&mut self, delay: Duration) -> &mut Self {
+        self.fallback_delay = delay;
+        self
+    }
+
+    /// Get the delay given to a pending connection attempt before the
+    /// next address is attempted in parallel.
+    pub const fn fallback_delay(&self) -> Duration {
+        self.fallback_delay
+    }
+}
+
+impl Default for HappyEyeballsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Connect to the resolved addresses of the given domain,
+/// racing them as described by Happy Eyeballs ([RFC 8305]).
+///
+/// The addresses are attempted in an interleaved order, starting with the family
+/// preferred by the [`DnsResolveIpMode`]. Each attempt is given a head start of the
+/// [`HappyEyeballsConfig::fallback_delay`] (or until it fails) before the next address
+/// is attempted in parallel. The first established stream is returned,
+/// aborting all attempts still pending.
+///
+/// [RFC 8305]: https://www.rfc-editor.org/rfc/rfc8305
+async fn happy_eyeballs_connect<State, Dns, Connector>(
     ctx: &Context<State>,
     domain: Domain,
     port: u16,
@@ -197,149 +328,379 @@ where
     Dns: DnsResolver<Error: Into<BoxError>> + Clone,
     Connector: TcpStreamConnector<Error: Into<BoxError> + Send + 'static> + Clone,
 {
-    let (tx, mut rx) = channel(1);
-    let connected = Arc::new(AtomicBool::new(false));
-    let sem = Arc::new(Semaphore::new(3));
-
-    if dns_mode.ipv4_supported() {
-        ctx.spawn(tcp_connect_inner_branch(
-            dns_mode,
-            dns.clone(),
-            connect_mode,
-            connector.clone(),
-            IpKind::Ipv4,
-            domain.clone(),
-            port,
-            tx.clone(),
-            connected.clone(),
-            sem.clone(),
-        ));
-    }
-
-    if dns_mode.ipv6_supported() {
-        ctx.spawn(tcp_connect_inner_branch(
-            dns_mode,
-            dns.clone(),
-            connect_mode,
-            connector.clone(),
-            IpKind::Ipv6,
-            domain.clone(),
-            port,
-            tx.clone(),
-            connected.clone(),
-            sem.clone(),
-        ));
-    }
-
-    if let Some((stream, addr)) = rx.recv().await {
-        connected.store(true, Ordering::Release);
-        return Ok((stream, addr));
-    }
+    let fallback_delay = ctx
+        .get::<HappyEyeballsConfig>()
+        .copied()
+        .unwrap_or_default()
+        .fallback_delay();
+    let deadline = ctx.get::<Deadline>().copied();
+
+    let mut failed_attempts = Vec::new();
+    let result = with_deadline(deadline, async {
+        // no need to resolve addresses of a family we are not allowed to connect to
+        let (ipv4, ipv6) = tokio::join!(
+            resolve(
+                &dns,
+                domain.clone(),
+                IpKind::Ipv4,
+                dns_mode.ipv4_supported() && connect_mode.ipv4_supported(),
+            ),
+            resolve(
+                &dns,
+                domain.clone(),
+                IpKind::Ipv6,
+                dns_mode.ipv6_supported() && connect_mode.ipv6_supported(),
+            ),
+        );
+        let addrs = match dns_mode {
+            DnsResolveIpMode::DualPreferIpV4 | DnsResolveIpMode::SingleIpV4 => {
+                interleave(ipv4, ipv6)
+            }
+            DnsResolveIpMode::Dual
+            | DnsResolveIpMode::DualPreferIpV6
+            | DnsResolveIpMode::SingleIpV6 => interleave(ipv6, ipv4),
+        };
 
-    Err(OpaqueError::from_display(format!(
-        "failed to connect to any resolved IP address for {domain} (port {port})"
-    )))
-}
+        race_attempts(
+            addrs
+                .into_iter()
+                .filter(|ip| connect_mode.ip_supported(ip))
+                .map(|ip| SocketAddr::new(ip, port)),
+            connector,
+            fallback_delay,
+            &mut failed_attempts,
+        )
+        .await
+    })
+    .await;
+
+    let timed_out = match result {
+        Some(Some(tuple)) => return Ok(tuple),
+        Some(None) => false,
+        None => true,
+    };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum IpKind {
-    Ipv4,
-    Ipv6,
+    Err(OpaqueError::from_std(ConnectError {
+        authority: Authority::new(Host::Name(domain), port),
+        attempts: failed_attempts,
+        timed_out,
+    }))
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn tcp_connect_inner_branch<Dns, Connector>(
-    dns_mode: DnsResolveIpMode,
-    dns: Dns,
-    connect_mode: ConnectIpMode,
+/// Attempt to connect to the given addresses, starting the next attempt
+/// once the previous one failed or did not succeed within the fallback delay.
+///
+/// Returns the first established stream, `None` in case all attempts failed.
+/// The attempts which failed are recorded in order of failure.
+async fn race_attempts<Connector>(
+    mut addrs: impl Iterator<Item = SocketAddr>,
     connector: Connector,
-    ip_kind: IpKind,
-    domain: Domain,
-    port: u16,
-    tx: Sender<(TcpStream, SocketAddr)>,
-    connected: Arc<AtomicBool>,
-    sem: Arc<Semaphore>,
-) where
-    Dns: DnsResolver<Error: Into<BoxError>> + Clone,
+    fallback_delay: Duration,
+    failed_attempts: &mut Vec<(SocketAddr, OpaqueError)>,
+) -> Option<(TcpStream, SocketAddr)>
+where
     Connector: TcpStreamConnector<Error: Into<BoxError> + Send + 'static> + Clone,
 {
-    let ip_it = match ip_kind {
-        IpKind::Ipv4 => match dns.ipv4_lookup(domain).await {
-            Ok(ips) => Either::A(ips.into_iter().map(IpAddr::V4)),
-            Err(err) => {
+    // pending attempts are aborted when this set is dropped,
+    // which is what cancels the losers of the race
+    let mut attempts = JoinSet::new();
+    let mut next = addrs.next();
+
+    loop {
+        if let Some(addr) = next.take() {
+            tracing::trace!("tcp connect attempt to {addr}");
+            let connector = connector.clone();
+            attempts.spawn(async move { (addr, connector.connect(addr).await) });
+        }
+
+        let result = tokio::select! {
+            result = attempts.join_next() => match result {
+                Some(result) => result,
+                // no attempts pending and no addresses left
+                None => return None,
+            },
+            _ = tokio::time::sleep(fallback_delay) => {
+                next = addrs.next();
+                continue;
+            }
+        };
+
+        match result {
+            Ok((addr, Ok(stream))) => {
+                tracing::trace!("tcp connection established to {addr}");
+                return Some((stream, addr));
+            }
+            Ok((addr, Err(err))) => {
                 let err = OpaqueError::from_boxed(err.into());
-                tracing::trace!(err = %err, "[{ip_kind:?}] failed to resolve domain to IPv4 addresses");
-                return;
+                tracing::trace!(err = %err, "tcp connector failed to connect to {addr}");
+                failed_attempts.push((addr, err));
             }
-        },
-        IpKind::Ipv6 => match dns.ipv6_lookup(domain).await {
-            Ok(ips) => Either::B(ips.into_iter().map(IpAddr::V6)),
             Err(err) => {
-                let err = OpaqueError::from_boxed(err.into());
-                tracing::trace!(err = ?err, "[{ip_kind:?}] failed to resolve domain to IPv6 addresses");
-                return;
+                tracing::debug!(err = %err, "tcp connect attempt failed to complete");
             }
-        },
-    };
+        }
+        // a failed attempt makes room for the next one right away
+        next = addrs.next();
+    }
+}
 
-    let (ipv4_delay_scalar, ipv6_delay_scalar) = match dns_mode {
-        DnsResolveIpMode::DualPreferIpV4 | DnsResolveIpMode::SingleIpV4 => (15 * 2, 21 * 2),
-        _ => (21 * 2, 15 * 2),
+/// Resolve the addresses of the given IP family for the domain,
+/// returning no addresses if it is not enabled or the lookup failed.
+async fn resolve<Dns>(dns: &Dns, domain: Domain, ip_kind: IpKind, enabled: bool) -> Vec<IpAddr>
+where
+    Dns: DnsResolver<Error: Into<BoxError>>,
+{
+    if !enabled {
+        return Vec::new();
+    }
+    let result = match ip_kind {
+        IpKind::Ipv4 => dns
+            .ipv4_lookup(domain)
+            .await
+            .map(|ips| ips.into_iter().map(IpAddr::V4).collect()),
+        IpKind::Ipv6 => dns
+            .ipv6_lookup(domain)
+            .await
+            .map(|ips| ips.into_iter().map(IpAddr::V6).collect()),
     };
-    for (index, ip) in ip_it.enumerate() {
-        let addr = (ip, port).into();
+    result.unwrap_or_else(|err| {
+        let err = OpaqueError::from_boxed(err.into());
+        tracing::trace!(err = %err, "[{ip_kind:?}] failed to resolve domain");
+        Vec::new()
+    })
+}
 
-        let sem = match (ip.is_ipv4(), connect_mode) {
-            (true, ConnectIpMode::Ipv6) => {
-                tracing::trace!("[{ip_kind:?}] #{index}: abort connect loop to {addr} (IPv4 address is not allowed)");
-                continue;
-            }
-            (false, ConnectIpMode::Ipv4) => {
-                tracing::trace!("[{ip_kind:?}] #{index}: abort connect loop to {addr} (IPv6 address is not allowed)");
-                continue;
-            }
-            _ => sem.clone(),
-        };
+/// Interleave the addresses of both families, starting with the preferred one.
+fn interleave(preferred: Vec<IpAddr>, other: Vec<IpAddr>) -> Vec<IpAddr> {
+    let mut addrs = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return addrs,
+            (a, b) => addrs.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Await the given future, returning `None` in case the optional [`Deadline`] expires first.
+async fn with_deadline<F: Future>(deadline: Option<Deadline>, fut: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout(deadline.remaining(), fut).await.ok(),
+        None => Some(fut.await),
+    }
+}
 
-        let tx = tx.clone();
-        let connected = connected.clone();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum IpKind {
+    Ipv4,
+    Ipv6,
+}
 
-        // back off retries exponentially
-        if index > 0 {
-            let delay = match ip_kind {
-                IpKind::Ipv4 => Duration::from_micros((ipv4_delay_scalar * index) as u64),
-                IpKind::Ipv6 => Duration::from_micros((ipv6_delay_scalar * index) as u64),
-            };
-            tokio::time::sleep(delay).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_dns::InMemoryDns;
+    use std::{io, net::Ipv4Addr};
+    use tokio::{net::TcpListener, sync::mpsc::channel};
+
+    const DEAD_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+
+    fn dns(ips: impl IntoIterator<Item = IpAddr>) -> InMemoryDns {
+        let mut dns = InMemoryDns::new();
+        dns.insert(
+            Domain::from_static("example.com"),
+            ips.into_iter().collect(),
+        );
+        dns
+    }
+
+    async fn refuse_dead_ip(addr: SocketAddr) -> Result<TcpStream, io::Error> {
+        if addr.ip() == DEAD_IP {
+            Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+        } else {
+            TcpStream::connect(addr).await
         }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connect_skips_dead_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_addr = listener.local_addr().unwrap();
+
+        let (_, addr) = tcp_connect(
+            &Context::<()>::default(),
+            Authority::new(Domain::from_static("example.com").into(), live_addr.port()),
+            false,
+            dns([DEAD_IP, live_addr.ip()]),
+            refuse_dead_ip,
+        )
+        .await
+        .unwrap();
+        assert_eq!(addr, live_addr);
+    }
 
-        if connected.load(Ordering::Acquire) {
-            tracing::trace!("[{ip_kind:?}] #{index}: abort connect loop to {addr} (connection already established)");
-            return;
+    #[tokio::test]
+    async fn test_tcp_connect_error_lists_all_attempts() {
+        let err = tcp_connect(
+            &Context::<()>::default(),
+            Authority::new(Domain::from_static("example.com").into(), 8080),
+            false,
+            dns([DEAD_IP, DEAD_IP]),
+            refuse_dead_ip,
+        )
+        .await
+        .unwrap_err();
+
+        let err = err.downcast_ref::<ConnectError>().unwrap();
+        assert!(!err.is_timeout());
+        assert_eq!(err.authority().to_string(), "example.com:8080");
+        assert_eq!(err.attempts().len(), 2);
+        for (addr, _) in err.attempts() {
+            assert_eq!(*addr, SocketAddr::new(DEAD_IP, 8080));
         }
+    }
 
-        let connector = connector.clone();
-        tokio::spawn(async move {
-            let _permit = sem.acquire().await.unwrap();
-            if connected.load(Ordering::Acquire) {
-                tracing::trace!("[{ip_kind:?}] #{index}: abort spawned attempt to {addr} (connection already established)");
-                return;
-            }
+    #[tokio::test]
+    async fn test_tcp_connect_respects_deadline() {
+        let mut ctx = Context::<()>::default();
+        ctx.insert(Deadline::after(Duration::from_millis(50)));
+
+        let err = tcp_connect(
+            &ctx,
+            Authority::new(Domain::from_static("example.com").into(), 8080),
+            false,
+            dns([DEAD_IP]),
+            |_addr: SocketAddr| std::future::pending::<Result<TcpStream, io::Error>>(),
+        )
+        .await
+        .unwrap_err();
+
+        let err = err.downcast_ref::<ConnectError>().unwrap();
+        assert!(err.is_timeout());
+        assert!(err.attempts().is_empty());
+    }
 
-            tracing::trace!("[{ip_kind:?}] #{index}: tcp connect attempt to {addr}");
+    #[tokio::test]
+    async fn test_tcp_connect_ip_address_error() {
+        let err = tcp_connect(
+            &Context::<()>::default(),
+            Authority::new(DEAD_IP.into(), 8080),
+            false,
+            dns([]),
+            refuse_dead_ip,
+        )
+        .await
+        .unwrap_err();
+
+        let err = err.downcast_ref::<ConnectError>().unwrap();
+        assert_eq!(err.attempts().len(), 1);
+        assert_eq!(err.attempts()[0].0, SocketAddr::new(DEAD_IP, 8080));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connect_ip_mode_filters_addresses() {
+        let ips: [IpAddr; 4] = [
+            "127.0.0.2".parse().unwrap(),
+            "fd00::2".parse().unwrap(),
+            "127.0.0.3".parse().unwrap(),
+            "fd00::3".parse().unwrap(),
+        ];
+
+        for (mode, expected) in [
+            (ConnectIpMode::Ipv4, [ips[0], ips[2]]),
+            (ConnectIpMode::Ipv6, [ips[1], ips[3]]),
+        ] {
+            let mut ctx = Context::<()>::default();
+            ctx.insert(mode);
+
+            let err = tcp_connect(
+                &ctx,
+                Authority::new(Domain::from_static("example.com").into(), 8080),
+                false,
+                dns(ips),
+                |_addr: SocketAddr| async {
+                    Err::<TcpStream, _>(io::Error::from(io::ErrorKind::ConnectionRefused))
+                },
+            )
+            .await
+            .unwrap_err();
+
+            let err = err.downcast_ref::<ConnectError>().unwrap();
+            let mut attempted: Vec<_> = err.attempts().iter().map(|(addr, _)| addr.ip()).collect();
+            attempted.sort();
+            assert_eq!(attempted, expected, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_interleave() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            interleave(vec![ip("::1"), ip("::2"), ip("::3")], vec![ip("127.0.0.1")]),
+            [ip("::1"), ip("127.0.0.1"), ip("::2"), ip("::3")]
+        );
+        assert_eq!(
+            interleave(vec![], vec![ip("127.0.0.1"), ip("127.0.0.2")]),
+            [ip("127.0.0.1"), ip("127.0.0.2")]
+        );
+        assert!(interleave(vec![], vec![]).is_empty());
+    }
 
-            match connector.connect(addr).await {
-                Ok(stream) => {
-                    tracing::trace!("[{ip_kind:?}] #{index}: tcp connection stablished to {addr}");
-                    if let Err(err) = tx.send((stream, addr)).await {
-                        tracing::trace!(err = %err, "[{ip_kind:?}] #{index}: failed to send resolved IP address");
+    #[tokio::test]
+    async fn test_tcp_connect_happy_eyeballs_races_black_holed_family() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_addr = listener.local_addr().unwrap();
+        let fallback_delay = Duration::from_millis(50);
+
+        for (dns_mode, black_holed, winner) in [
+            (
+                DnsResolveIpMode::Dual,
+                "fd00::1".parse().unwrap(),
+                "127.0.0.3".parse().unwrap(),
+            ),
+            (
+                DnsResolveIpMode::DualPreferIpV4,
+                "127.0.0.3".parse().unwrap(),
+                "fd00::1".parse().unwrap(),
+            ),
+        ] {
+            let mut ctx = Context::<()>::default();
+            ctx.insert(dns_mode);
+            ctx.insert(HappyEyeballsConfig::new().with_fallback_delay(fallback_delay));
+
+            // the black holed attempt holds a sender, dropped once it is cancelled
+            let (cancelled_tx, mut cancelled_rx) = channel::<()>(1);
+            let connector = move |addr: SocketAddr| {
+                let cancelled_tx = cancelled_tx.clone();
+                async move {
+                    if addr.ip() == black_holed {
+                        let _cancelled_tx = cancelled_tx;
+                        std::future::pending::<()>().await;
                     }
-                }
-                Err(err) => {
-                    let err = OpaqueError::from_boxed(err.into());
-                    tracing::trace!(err = %err, "[{ip_kind:?}] #{index}: tcp connector failed to connect");
+                    // mock the winning address by the live listener
+                    TcpStream::connect(live_addr).await
                 }
             };
-        });
+
+            let start = tokio::time::Instant::now();
+            let (_, addr) = tcp_connect(
+                &ctx,
+                Authority::new(Domain::from_static("example.com").into(), 8080),
+                false,
+                dns([black_holed, winner]),
+                connector,
+            )
+            .await
+            .unwrap();
+            assert_eq!(addr, SocketAddr::new(winner, 8080), "{dns_mode:?}");
+            // the preferred family was given a head start
+            assert!(start.elapsed() >= fallback_delay, "{dns_mode:?}");
+
+            // the pending attempt is aborted, dropping the last sender
+            tokio::time::timeout(Duration::from_secs(1), cancelled_rx.recv())
+                .await
+                .unwrap();
+        }
     }
 }
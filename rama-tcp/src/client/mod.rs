@@ -5,7 +5,13 @@ pub mod service;
 
 mod connect;
 #[doc(inline)]
-pub use connect::{default_tcp_connect, tcp_connect, TcpStreamConnector};
+pub use connect::{
+    default_tcp_connect, tcp_connect, ConnectError, HappyEyeballsConfig, TcpStreamConnector,
+};
+
+mod bind;
+#[doc(inline)]
+pub use bind::LocalBindConnector;
 
 #[cfg(feature = "http")]
 mod request;
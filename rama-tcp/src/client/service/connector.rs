@@ -1,5 +1,5 @@
 use rama_core::{
-    error::{BoxError, ErrorContext, ErrorExt, OpaqueError},
+    error::{BoxError, ErrorExt, OpaqueError},
     Context, Service,
 };
 use rama_dns::{DnsResolver, HickoryDns};
@@ -8,9 +8,13 @@ use rama_net::{
     client::EstablishedClientConnection,
     transport::{TransportProtocol, TryRefIntoTransportContext},
 };
+use std::io;
 use tokio::net::TcpStream;
 
-use crate::client::connect::TcpStreamConnector;
+use crate::{
+    client::{connect::TcpStreamConnector, ConnectError},
+    fd_budget::FdBudget,
+};
 
 use super::{CreatedTcpStreamConnector, TcpStreamConnectorCloneFactory, TcpStreamConnectorFactory};
 
@@ -20,9 +24,56 @@ use super::{CreatedTcpStreamConnector, TcpStreamConnectorCloneFactory, TcpStream
 pub struct TcpConnector<Dns = HickoryDns, ConnectorFactory = ()> {
     dns: Dns,
     connector_factory: ConnectorFactory,
+    fd_budget: Option<FdBudget>,
 }
 
-impl<Dns, Connector> TcpConnector<Dns, Connector> {}
+impl<Dns, Connector> TcpConnector<Dns, Connector> {
+    /// Track the dialed connections using the given [`FdBudget`],
+    /// shedding new dials with a [`FdPressureError`] once its soft limit is reached.
+    ///
+    /// Dials which fail because the process ran out of file descriptors
+    /// are reported as a [`FdPressureError`] as well.
+    ///
+    /// The [`FdGuard`] of a dialed connection is added to the [`Context`]
+    /// of the [`EstablishedClientConnection`].
+    ///
+    /// [`FdPressureError`]: crate::fd_budget::FdPressureError
+    /// [`FdGuard`]: crate::fd_budget::FdGuard
+    pub fn with_fd_budget(mut self, budget: FdBudget) -> Self {
+        self.fd_budget = Some(budget);
+        self
+    }
+
+    /// Track the dialed connections using the given [`FdBudget`],
+    /// shedding new dials with a [`FdPressureError`] once its soft limit is reached.
+    ///
+    /// Dials which fail because the process ran out of file descriptors
+    /// are reported as a [`FdPressureError`] as well.
+    ///
+    /// The [`FdGuard`] of a dialed connection is added to the [`Context`]
+    /// of the [`EstablishedClientConnection`].
+    ///
+    /// [`FdPressureError`]: crate::fd_budget::FdPressureError
+    /// [`FdGuard`]: crate::fd_budget::FdGuard
+    pub fn set_fd_budget(&mut self, budget: FdBudget) -> &mut Self {
+        self.fd_budget = Some(budget);
+        self
+    }
+
+    fn connect_err(&self, err: OpaqueError, context: &'static str) -> BoxError {
+        let pressure = self.fd_budget.as_ref().and_then(|budget| {
+            err.downcast_ref::<ConnectError>()?
+                .attempts()
+                .iter()
+                .filter_map(|(_, err)| err.downcast_ref::<io::Error>())
+                .find_map(|err| budget.report_error(err))
+        });
+        match pressure {
+            Some(pressure) => pressure.into(),
+            None => err.context(context).into(),
+        }
+    }
+}
 
 impl TcpConnector {
     /// Create a new [`TcpConnector`], which is used to establish a connection to a server.
@@ -33,6 +84,7 @@ impl TcpConnector {
         Self {
             dns: HickoryDns::default(),
             connector_factory: (),
+            fd_budget: None,
         }
     }
 }
@@ -46,6 +98,7 @@ impl<Dns, ConnectorFactory> TcpConnector<Dns, ConnectorFactory> {
         TcpConnector {
             dns,
             connector_factory: self.connector_factory,
+            fd_budget: self.fd_budget,
         }
     }
 }
@@ -60,6 +113,7 @@ where {
         TcpConnector {
             dns: self.dns,
             connector_factory: TcpStreamConnectorCloneFactory(connector),
+            fd_budget: self.fd_budget,
         }
     }
 
@@ -69,6 +123,7 @@ where {
         TcpConnector {
             dns: self.dns,
             connector_factory: factory,
+            fd_budget: self.fd_budget,
         }
     }
 }
@@ -106,6 +161,15 @@ where
             .await
             .map_err(Into::into)?;
 
+        let fd_guard = self
+            .fd_budget
+            .as_ref()
+            .map(FdBudget::try_reserve)
+            .transpose()?;
+        if let Some(fd_guard) = fd_guard {
+            ctx.insert(fd_guard);
+        }
+
         if let Some(proxy) = ctx.get::<ProxyAddress>() {
             let (conn, addr) = crate::client::tcp_connect(
                 &ctx,
@@ -115,7 +179,7 @@ where
                 connector,
             )
             .await
-            .context("tcp connector: conncept to proxy")?;
+            .map_err(|err| self.connect_err(err, "tcp connector: conncept to proxy"))?;
             return Ok(EstablishedClientConnection {
                 ctx,
                 req,
@@ -146,7 +210,7 @@ where
         let (conn, addr) =
             crate::client::tcp_connect(&ctx, authority, false, self.dns.clone(), connector)
                 .await
-                .context("tcp connector: connect to server")?;
+                .map_err(|err| self.connect_err(err, "tcp connector: connect to server"))?;
 
         Ok(EstablishedClientConnection {
             ctx,
@@ -156,3 +220,101 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::Request, fd_budget::FdGuard, fd_budget::FdPressureError};
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tokio::net::TcpListener;
+
+    fn request(addr: SocketAddr) -> Request {
+        Request::new(addr.into())
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connector_fd_budget_tracks_dialed_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let budget = FdBudget::new(8);
+        let connector = TcpConnector::new().with_fd_budget(budget.clone());
+
+        let EstablishedClientConnection { ctx, conn, .. } = connector
+            .serve(Context::default(), request(addr))
+            .await
+            .unwrap();
+        assert!(ctx.contains::<FdGuard>());
+        assert_eq!(budget.open_sockets(), 1);
+
+        drop(conn);
+        drop(ctx);
+        assert_eq!(budget.open_sockets(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connector_fd_budget_sheds_dials_over_soft_limit() {
+        let dials = Arc::new(AtomicUsize::new(0));
+        let connector_dials = dials.clone();
+
+        let budget = FdBudget::new(1);
+        let connector = TcpConnector::new()
+            .with_connector(move |addr: SocketAddr| {
+                connector_dials.fetch_add(1, Ordering::SeqCst);
+                TcpStream::connect(addr)
+            })
+            .with_fd_budget(budget.clone());
+
+        let accepted = budget.track();
+        let err = connector
+            .serve(Context::default(), request(([127, 0, 0, 1], 1).into()))
+            .await
+            .unwrap_err();
+        let err = FdPressureError::find(err.as_ref()).unwrap();
+        assert!(!err.is_exhausted());
+        assert_eq!(err.soft_limit(), 1);
+        assert_eq!(dials.load(Ordering::SeqCst), 0);
+
+        drop(accepted);
+        assert_eq!(budget.open_sockets(), 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_tcp_connector_fd_budget_reports_fd_exhaustion() {
+        // simulate the process running out of file descriptors
+        async fn emfile(_addr: SocketAddr) -> Result<TcpStream, io::Error> {
+            Err(io::Error::from_raw_os_error(24))
+        }
+
+        let budget = FdBudget::new(8);
+        let connector = TcpConnector::new()
+            .with_connector(emfile)
+            .with_fd_budget(budget.clone());
+
+        let err = connector
+            .serve(Context::default(), request(([127, 0, 0, 1], 1).into()))
+            .await
+            .unwrap_err();
+        assert!(FdPressureError::find(err.as_ref()).unwrap().is_exhausted());
+        assert_eq!(budget.open_sockets(), 0);
+
+        // other connect errors are not affected
+        async fn refused(_addr: SocketAddr) -> Result<TcpStream, io::Error> {
+            Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+        }
+        let err = TcpConnector::new()
+            .with_connector(refused)
+            .with_fd_budget(budget.clone())
+            .serve(Context::default(), request(([127, 0, 0, 1], 1).into()))
+            .await
+            .unwrap_err();
+        assert!(FdPressureError::find(err.as_ref()).is_none());
+    }
+}
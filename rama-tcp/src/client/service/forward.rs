@@ -7,10 +7,10 @@ use rama_core::{
 use rama_net::{
     address::Authority,
     client::{ConnectorService, EstablishedClientConnection},
-    stream::Stream,
+    stream::{copy_bidirectional_with_stats, Stream},
 };
 use rama_utils::macros::impl_deref;
-use std::{fmt, ops::DerefMut};
+use std::{fmt, ops::DerefMut, time::Duration};
 use tokio::sync::Mutex;
 
 /// [`Forwarder`] using [`Forwarder::ctx`] requires this struct
@@ -179,6 +179,10 @@ where
     }
 }
 
+/// Time given to drain both ends of a forwarded connection
+/// once a graceful shutdown is triggered.
+const GRACEFUL_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 pub struct ForwarderService<S>(Mutex<S>);
 
@@ -191,14 +195,24 @@ where
     type Response = ();
     type Error = OpaqueError;
 
-    async fn serve(
-        &self,
-        _ctx: Context<State>,
-        mut source: I,
-    ) -> Result<Self::Response, Self::Error> {
+    async fn serve(&self, ctx: Context<State>, source: I) -> Result<Self::Response, Self::Error> {
+        #[cfg(feature = "telemetry")]
+        let source =
+            rama_net::stream::layer::TunnelRegistry::from_ctx(&ctx).track(&ctx, "forward", source);
+        let mut source = source;
         let mut target = self.0.lock().await;
-        match tokio::io::copy_bidirectional(&mut source, target.deref_mut()).await {
-            Ok(_) => Ok(()),
+        match copy_bidirectional_with_stats(
+            &mut source,
+            target.deref_mut(),
+            ctx.guard(),
+            GRACEFUL_DRAIN_TIMEOUT,
+        )
+        .await
+        {
+            Ok(stats) => {
+                tracing::trace!(?stats, "tcp forwarder: connection closed");
+                Ok(())
+            }
             Err(err) => {
                 if is_connection_error(&err) {
                     Ok(())
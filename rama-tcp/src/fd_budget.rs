@@ -0,0 +1,359 @@
+//! File descriptor (fd) pressure handling.
+//!
+//! A process can only have a limited amount of file descriptors open,
+//! and each accepted or dialed socket consumes one. Once that limit is hit,
+//! accepting and dialing fail with `EMFILE` (or `ENFILE` for the system-wide limit).
+//!
+//! The [`FdBudget`] estimates how close rama is to that limit
+//! by tracking the sockets it owns, such that new upstream dials can be
+//! shed (see [`FdPressureError`]) before the limit is actually hit.
+//! Use the same [`FdBudget`] for the [`TcpListener`] and [`TcpConnector`]
+//! to have it track both the accepted and dialed sockets.
+//!
+//! [`TcpListener`]: crate::server::TcpListener
+//! [`TcpConnector`]: crate::client::service::TcpConnector
+
+use parking_lot::Mutex;
+use std::{
+    fmt, io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+
+#[derive(Clone)]
+/// A budget of file descriptors, tracking the sockets owned by rama
+/// using [`FdGuard`]s against a configurable soft limit.
+///
+/// Clones of an [`FdBudget`] share the same gauge of open sockets.
+pub struct FdBudget {
+    soft_limit: usize,
+    retry_after: Duration,
+    alarm_interval: Duration,
+    state: Arc<FdBudgetState>,
+}
+
+struct FdBudgetState {
+    open: AtomicUsize,
+    last_alarm: Mutex<Option<Instant>>,
+}
+
+impl FdBudget {
+    /// Create a new [`FdBudget`] with the given soft limit,
+    /// the amount of open sockets from which new upstream dials are shed.
+    ///
+    /// Pick a limit below the `RLIMIT_NOFILE` of the process,
+    /// leaving room for the other file descriptors it uses.
+    pub fn new(soft_limit: usize) -> Self {
+        Self {
+            soft_limit,
+            retry_after: Duration::from_secs(1),
+            alarm_interval: Duration::from_secs(10),
+            state: Arc::new(FdBudgetState {
+                open: AtomicUsize::new(0),
+                last_alarm: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Set the duration after which clients can retry a shed request,
+    /// as reported by the [`FdPressureError`]. Defaults to 1 second.
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Set the duration after which clients can retry a shed request,
+    /// as reported by the [`FdPressureError`]. Defaults to 1 second.
+    pub fn set_retry_after(&mut self, retry_after: Duration) -> &mut Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Set the minimum interval between two fd pressure alarms logged,
+    /// such that the logs are not flooded while under pressure. Defaults to 10 seconds.
+    pub fn with_alarm_interval(mut self, interval: Duration) -> Self {
+        self.alarm_interval = interval;
+        self
+    }
+
+    /// Set the minimum interval between two fd pressure alarms logged,
+    /// such that the logs are not flooded while under pressure. Defaults to 10 seconds.
+    pub fn set_alarm_interval(&mut self, interval: Duration) -> &mut Self {
+        self.alarm_interval = interval;
+        self
+    }
+
+    /// Returns the soft limit of this [`FdBudget`].
+    pub fn soft_limit(&self) -> usize {
+        self.soft_limit
+    }
+
+    /// Returns the amount of sockets currently tracked by this [`FdBudget`].
+    pub fn open_sockets(&self) -> usize {
+        self.state.open.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if the amount of open sockets reached the soft limit.
+    pub fn is_under_pressure(&self) -> bool {
+        self.open_sockets() >= self.soft_limit
+    }
+
+    /// Track an already opened socket (e.g. an accepted one)
+    /// for as long as the returned [`FdGuard`] is alive.
+    pub fn track(&self) -> FdGuard {
+        self.state.open.fetch_add(1, Ordering::AcqRel);
+        FdGuard {
+            _inner: Arc::new(FdGuardInner {
+                state: self.state.clone(),
+            }),
+        }
+    }
+
+    /// Reserve a socket that is about to be opened (e.g. to dial an upstream),
+    /// tracking it for as long as the returned [`FdGuard`] is alive.
+    ///
+    /// Returns a [`FdPressureError`] instead in case the soft limit is reached,
+    /// such that the dial can be shed.
+    pub fn try_reserve(&self) -> Result<FdGuard, FdPressureError> {
+        let reserved = self
+            .state
+            .open
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+                (open < self.soft_limit).then_some(open + 1)
+            });
+        match reserved {
+            Ok(_) => Ok(FdGuard {
+                _inner: Arc::new(FdGuardInner {
+                    state: self.state.clone(),
+                }),
+            }),
+            Err(open) => {
+                self.alarm(open, None);
+                Err(self.pressure_error(open, false))
+            }
+        }
+    }
+
+    /// Report an error returned while opening a socket.
+    ///
+    /// Returns a [`FdPressureError`] in case the error is caused
+    /// by the process (or system) running out of file descriptors,
+    /// and `None` otherwise.
+    pub fn report_error(&self, err: &io::Error) -> Option<FdPressureError> {
+        if !crate::utils::is_fd_exhaustion_error(err) {
+            return None;
+        }
+        let open = self.open_sockets();
+        self.alarm(open, Some(err));
+        Some(self.pressure_error(open, true))
+    }
+
+    fn pressure_error(&self, open: usize, exhausted: bool) -> FdPressureError {
+        FdPressureError {
+            open_sockets: open,
+            soft_limit: self.soft_limit,
+            retry_after: self.retry_after,
+            exhausted,
+        }
+    }
+
+    /// Log an fd pressure alarm, unless one was already logged within the alarm interval.
+    fn alarm(&self, open: usize, err: Option<&io::Error>) -> bool {
+        let now = Instant::now();
+        {
+            let mut last_alarm = self.state.last_alarm.lock();
+            if last_alarm.is_some_and(|last| now.duration_since(last) < self.alarm_interval) {
+                return false;
+            }
+            *last_alarm = Some(now);
+        }
+        match err {
+            Some(err) => tracing::error!(
+                error = err as &dyn std::error::Error,
+                open_sockets = open,
+                soft_limit = self.soft_limit,
+                "fd budget: out of file descriptors",
+            ),
+            None => tracing::warn!(
+                open_sockets = open,
+                soft_limit = self.soft_limit,
+                "fd budget: soft limit reached, shedding new upstream dials",
+            ),
+        }
+        true
+    }
+}
+
+impl fmt::Debug for FdBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FdBudget")
+            .field("soft_limit", &self.soft_limit)
+            .field("retry_after", &self.retry_after)
+            .field("alarm_interval", &self.alarm_interval)
+            .field("open_sockets", &self.open_sockets())
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+/// A socket tracked by an [`FdBudget`],
+/// released once the guard and all its clones are dropped.
+///
+/// The [`TcpConnector`] adds it to the [`Context`] of the established connection.
+///
+/// [`TcpConnector`]: crate::client::service::TcpConnector
+/// [`Context`]: rama_core::Context
+pub struct FdGuard {
+    _inner: Arc<FdGuardInner>,
+}
+
+struct FdGuardInner {
+    state: Arc<FdBudgetState>,
+}
+
+impl Drop for FdGuardInner {
+    fn drop(&mut self) {
+        self.state.open.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl fmt::Debug for FdGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FdGuard").finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Error returned when a socket could not be opened due to fd pressure,
+/// either because it was shed by the [`FdBudget`] or because
+/// the process ran out of file descriptors.
+///
+/// Http services can use it to respond with a `503 Service Unavailable`
+/// and a `Retry-After` header, instead of a generic `502 Bad Gateway`.
+pub struct FdPressureError {
+    open_sockets: usize,
+    soft_limit: usize,
+    retry_after: Duration,
+    exhausted: bool,
+}
+
+impl FdPressureError {
+    /// Returns the amount of sockets tracked at the time of the error.
+    pub fn open_sockets(&self) -> usize {
+        self.open_sockets
+    }
+
+    /// Returns the soft limit of the [`FdBudget`] that returned this error.
+    pub fn soft_limit(&self) -> usize {
+        self.soft_limit
+    }
+
+    /// Returns the duration after which the request can be retried.
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    /// Returns `true` if the process actually ran out of file descriptors,
+    /// and `false` if the socket was shed because the soft limit was reached.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// Find the first [`FdPressureError`] in the chain of the given error.
+    pub fn find<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a Self> {
+        let mut next = Some(err);
+        while let Some(err) = next {
+            if let Some(err) = err.downcast_ref::<Self>() {
+                return Some(err);
+            }
+            next = err.source();
+        }
+        None
+    }
+}
+
+impl fmt::Display for FdPressureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.exhausted {
+            write!(
+                f,
+                "fd pressure: out of file descriptors ({} sockets open)",
+                self.open_sockets
+            )
+        } else {
+            write!(
+                f,
+                "fd pressure: soft limit of {} sockets reached",
+                self.soft_limit
+            )
+        }
+    }
+}
+
+impl std::error::Error for FdPressureError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fd_budget_guards() {
+        let budget = FdBudget::new(2);
+        assert_eq!(budget.open_sockets(), 0);
+
+        let accepted = budget.track();
+        let dialed = budget.try_reserve().unwrap();
+        assert_eq!(budget.open_sockets(), 2);
+        assert!(budget.is_under_pressure());
+
+        let err = budget.try_reserve().unwrap_err();
+        assert!(!err.is_exhausted());
+        assert_eq!(err.open_sockets(), 2);
+        assert_eq!(err.retry_after(), Duration::from_secs(1));
+
+        // tracking is never refused, as the socket is already open
+        let accepted_over_limit = budget.clone().track();
+        assert_eq!(budget.open_sockets(), 3);
+
+        let dialed_clone = dialed.clone();
+        drop(dialed);
+        assert_eq!(budget.open_sockets(), 3);
+        drop(dialed_clone);
+        drop(accepted_over_limit);
+        assert_eq!(budget.open_sockets(), 1);
+        assert!(!budget.is_under_pressure());
+
+        let _dialed = budget.try_reserve().unwrap();
+        drop(accepted);
+        assert_eq!(budget.open_sockets(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fd_budget_report_error() {
+        let budget = FdBudget::new(10);
+        assert!(budget
+            .report_error(&io::Error::from(io::ErrorKind::ConnectionRefused))
+            .is_none());
+        let err = budget
+            .report_error(&io::Error::from_raw_os_error(24))
+            .unwrap();
+        assert!(err.is_exhausted());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fd_budget_alarm_once_per_interval() {
+        let budget = FdBudget::new(0).with_alarm_interval(Duration::from_secs(5));
+        assert!(budget.alarm(0, None));
+        assert!(!budget.alarm(0, None));
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert!(!budget.clone().alarm(0, None));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(budget.alarm(0, None));
+        assert!(!budget.alarm(0, None));
+    }
+}
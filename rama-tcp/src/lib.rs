@@ -18,5 +18,6 @@
 #![cfg_attr(not(test), warn(clippy::print_stdout, clippy::dbg_macro))]
 
 pub mod client;
+pub mod fd_budget;
 pub mod server;
 pub mod utils;
@@ -1,3 +1,5 @@
+use super::rate_limit::{AcceptRateLimit, AcceptRateLimiter};
+use crate::fd_budget::{FdBudget, FdGuard};
 use rama_core::error::BoxError;
 use rama_core::graceful::ShutdownGuard;
 use rama_core::rt::Executor;
@@ -14,6 +16,9 @@ use tokio::net::{TcpListener as TokioTcpListener, TcpStream};
 /// Builder for `TcpListener`.
 pub struct TcpListenerBuilder<S> {
     ttl: Option<u32>,
+    reuse_port: Option<usize>,
+    accept_rate_limit: Option<AcceptRateLimit>,
+    fd_budget: Option<FdBudget>,
     state: S,
 }
 
@@ -24,6 +29,9 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("TcpListenerBuilder")
             .field("ttl", &self.ttl)
+            .field("reuse_port", &self.reuse_port)
+            .field("accept_rate_limit", &self.accept_rate_limit)
+            .field("fd_budget", &self.fd_budget)
             .field("state", &self.state)
             .finish()
     }
@@ -34,6 +42,9 @@ impl TcpListenerBuilder<()> {
     pub fn new() -> Self {
         Self {
             ttl: None,
+            reuse_port: None,
+            accept_rate_limit: None,
+            fd_budget: None,
             state: (),
         }
     }
@@ -49,6 +60,9 @@ impl<S: Clone> Clone for TcpListenerBuilder<S> {
     fn clone(&self) -> Self {
         Self {
             ttl: self.ttl,
+            reuse_port: self.reuse_port,
+            accept_rate_limit: self.accept_rate_limit.clone(),
+            fd_budget: self.fd_budget.clone(),
             state: self.state.clone(),
         }
     }
@@ -72,6 +86,66 @@ impl<S> TcpListenerBuilder<S> {
         self.ttl = Some(ttl);
         self
     }
+
+    /// Bind the given number of listeners to the same address using `SO_REUSEPORT`,
+    /// each served by its own accept worker task, such that the kernel
+    /// load-balances the incoming connections across them.
+    ///
+    /// Only supported on unix platforms, binding fails on other platforms
+    /// in case more than one worker is requested.
+    pub fn reuse_port(mut self, workers: usize) -> Self {
+        self.reuse_port = Some(workers);
+        self
+    }
+
+    /// Bind the given number of listeners to the same address using `SO_REUSEPORT`,
+    /// each served by its own accept worker task, such that the kernel
+    /// load-balances the incoming connections across them.
+    ///
+    /// Only supported on unix platforms, binding fails on other platforms
+    /// in case more than one worker is requested.
+    pub fn set_reuse_port(&mut self, workers: usize) -> &mut Self {
+        self.reuse_port = Some(workers);
+        self
+    }
+
+    /// Limit the rate at which connections are accepted, see [`AcceptRateLimit`].
+    ///
+    /// The limit is shared by all accept workers of the listener,
+    /// in case it was bound using [`TcpListenerBuilder::reuse_port`].
+    pub fn accept_rate_limit(mut self, limit: AcceptRateLimit) -> Self {
+        self.accept_rate_limit = Some(limit);
+        self
+    }
+
+    /// Limit the rate at which connections are accepted, see [`AcceptRateLimit`].
+    ///
+    /// The limit is shared by all accept workers of the listener,
+    /// in case it was bound using [`TcpListenerBuilder::reuse_port`].
+    pub fn set_accept_rate_limit(&mut self, limit: AcceptRateLimit) -> &mut Self {
+        self.accept_rate_limit = Some(limit);
+        self
+    }
+
+    /// Track the accepted connections using the given [`FdBudget`],
+    /// for as long as they are being served.
+    ///
+    /// Accept errors caused by the process running out of file descriptors
+    /// are reported to the [`FdBudget`], which logs them at most once per alarm interval.
+    pub fn fd_budget(mut self, budget: FdBudget) -> Self {
+        self.fd_budget = Some(budget);
+        self
+    }
+
+    /// Track the accepted connections using the given [`FdBudget`],
+    /// for as long as they are being served.
+    ///
+    /// Accept errors caused by the process running out of file descriptors
+    /// are reported to the [`FdBudget`], which logs them at most once per alarm interval.
+    pub fn set_fd_budget(&mut self, budget: FdBudget) -> &mut Self {
+        self.fd_budget = Some(budget);
+        self
+    }
 }
 
 impl<S> TcpListenerBuilder<S>
@@ -80,7 +154,13 @@ where
 {
     /// Create a new `TcpListenerBuilder` with the given state.
     pub fn with_state(state: S) -> Self {
-        Self { ttl: None, state }
+        Self {
+            ttl: None,
+            reuse_port: None,
+            accept_rate_limit: None,
+            fd_budget: None,
+            state,
+        }
     }
 }
 
@@ -101,25 +181,84 @@ where
     ) -> Result<TcpListener<S>, BoxError> {
         let socket_addr = addr.try_into().map_err(Into::<BoxError>::into)?;
         let tokio_socket_addr: SocketAddr = socket_addr.into();
-        let inner = TokioTcpListener::bind(tokio_socket_addr)
-            .await
-            .map_err(Into::<BoxError>::into)?;
+
+        let (inner, shards) = match self.reuse_port {
+            Some(workers) if workers > 1 => bind_reuse_port(tokio_socket_addr, workers)?,
+            _ => (
+                TokioTcpListener::bind(tokio_socket_addr)
+                    .await
+                    .map_err(Into::<BoxError>::into)?,
+                Vec::new(),
+            ),
+        };
 
         if let Some(ttl) = self.ttl {
             inner.set_ttl(ttl)?;
+            for shard in shards.iter() {
+                shard.set_ttl(ttl)?;
+            }
         }
 
         Ok(TcpListener {
             inner,
+            shards,
+            accept_rate_limiter: self
+                .accept_rate_limit
+                .as_ref()
+                .map(|limit| Arc::new(AcceptRateLimiter::new(limit))),
+            fd_budget: self.fd_budget,
             state: self.state,
         })
     }
 }
 
+#[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+fn bind_reuse_port(
+    addr: SocketAddr,
+    workers: usize,
+) -> Result<(TokioTcpListener, Vec<TokioTcpListener>), BoxError> {
+    use tokio::net::TcpSocket;
+
+    fn bind_shard(addr: SocketAddr) -> io::Result<TokioTcpListener> {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        socket.set_reuseaddr(true)?;
+        socket.set_reuseport(true)?;
+        socket.bind(addr)?;
+        socket.listen(1024)
+    }
+
+    let inner = bind_shard(addr)?;
+    // in case port 0 was requested, all shards have to bind to the port assigned to the first
+    let addr = inner.local_addr()?;
+    let shards = (1..workers)
+        .map(|_| bind_shard(addr))
+        .collect::<Result<Vec<_>, _>>()?;
+    tracing::trace!(%addr, workers, "TCP listener: bound sharded listeners using SO_REUSEPORT");
+    Ok((inner, shards))
+}
+
+#[cfg(not(all(unix, not(target_os = "solaris"), not(target_os = "illumos"))))]
+fn bind_reuse_port(
+    _addr: SocketAddr,
+    _workers: usize,
+) -> Result<(TokioTcpListener, Vec<TokioTcpListener>), BoxError> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_REUSEPORT is not supported on this platform",
+    )
+    .into())
+}
+
 /// A TCP socket server, listening for incoming connections once served
 /// using one of the `serve` methods such as [`TcpListener::serve`].
 pub struct TcpListener<S> {
     inner: TokioTcpListener,
+    shards: Vec<TokioTcpListener>,
+    accept_rate_limiter: Option<Arc<AcceptRateLimiter>>,
+    fd_budget: Option<FdBudget>,
     state: S,
 }
 
@@ -130,6 +269,9 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("TcpListener")
             .field("inner", &self.inner)
+            .field("shards", &self.shards)
+            .field("accept_rate_limiter", &self.accept_rate_limiter)
+            .field("fd_budget", &self.fd_budget)
             .field("state", &self.state)
             .finish()
     }
@@ -183,6 +325,12 @@ impl<S> TcpListener<S> {
         self.inner.ttl()
     }
 
+    /// Returns the number of listeners (and thus accept workers)
+    /// bound to the local address, see [`TcpListenerBuilder::reuse_port`].
+    pub fn workers(&self) -> usize {
+        self.shards.len() + 1
+    }
+
     /// Gets a reference to the listener's state.
     pub fn state(&self) -> &S {
         &self.state
@@ -198,6 +346,9 @@ impl From<TokioTcpListener> for TcpListener<()> {
     fn from(value: TokioTcpListener) -> Self {
         Self {
             inner: value,
+            shards: Vec::new(),
+            accept_rate_limiter: None,
+            fd_budget: None,
             state: (),
         }
     }
@@ -221,6 +372,9 @@ impl TryFrom<std::net::TcpListener> for TcpListener<()> {
         value.set_nonblocking(true)?;
         Ok(Self {
             inner: TokioTcpListener::from_std(value)?,
+            shards: Vec::new(),
+            accept_rate_limiter: None,
+            fd_budget: None,
             state: (),
         })
     }
@@ -232,6 +386,9 @@ impl TcpListener<()> {
     pub fn with_state<S>(self, state: S) -> TcpListener<S> {
         TcpListener {
             inner: self.inner,
+            shards: self.shards,
+            accept_rate_limiter: self.accept_rate_limiter,
+            fd_budget: self.fd_budget,
             state,
         }
     }
@@ -245,6 +402,9 @@ where
     ///
     /// This method will block the current listener for each incoming connection,
     /// the underlying service can choose to spawn a task to handle the accepted stream.
+    ///
+    /// In case the listener was bound using [`TcpListenerBuilder::reuse_port`],
+    /// the connections of each additional listener are accepted in their own task.
     pub async fn serve<S>(self, service: S)
     where
         S: Service<State, TcpStream>,
@@ -252,25 +412,23 @@ where
         let ctx = Context::new(self.state, Executor::new());
         let service = Arc::new(service);
 
-        loop {
-            let (socket, peer_addr) = match self.inner.accept().await {
-                Ok(stream) => stream,
-                Err(err) => {
-                    handle_accept_err(err).await;
-                    continue;
-                }
-            };
-
-            let service = service.clone();
-            let mut ctx = ctx.clone();
-
-            tokio::spawn(async move {
-                let local_addr = socket.local_addr().ok();
-                ctx.insert(SocketInfo::new(local_addr, peer_addr));
-
-                let _ = service.serve(ctx, socket).await;
-            });
+        for shard in self.shards {
+            tokio::spawn(accept_loop(
+                shard,
+                self.accept_rate_limiter.clone(),
+                self.fd_budget.clone(),
+                ctx.clone(),
+                service.clone(),
+            ));
         }
+        accept_loop(
+            self.inner,
+            self.accept_rate_limiter,
+            self.fd_budget,
+            ctx,
+            service,
+        )
+        .await
     }
 
     /// Serve gracefully connections from this listener with the given service.
@@ -284,30 +442,105 @@ where
     {
         let ctx: Context<State> = Context::new(self.state, Executor::graceful(guard.clone()));
         let service = Arc::new(service);
-        let mut cancelled_fut = pin!(guard.cancelled());
 
-        loop {
-            tokio::select! {
-                _ = cancelled_fut.as_mut() => {
-                    tracing::trace!("signal received: initiate graceful shutdown");
-                    break;
-                }
-                result = self.inner.accept() => {
-                    match result {
-                        Ok((socket, peer_addr)) => {
-                            let service = service.clone();
-                            let mut ctx = ctx.clone();
-
-                            guard.spawn_task(async move {
-                                let local_addr = socket.local_addr().ok();
-                                ctx.insert(SocketInfo::new(local_addr, peer_addr));
-
-                                let _ = service.serve(ctx, socket).await;
-                            });
-                        }
-                        Err(err) => {
-                            handle_accept_err(err).await;
+        for shard in self.shards {
+            guard.spawn_task(accept_loop_graceful(
+                shard,
+                self.accept_rate_limiter.clone(),
+                self.fd_budget.clone(),
+                guard.clone(),
+                ctx.clone(),
+                service.clone(),
+            ));
+        }
+        accept_loop_graceful(
+            self.inner,
+            self.accept_rate_limiter,
+            self.fd_budget,
+            guard,
+            ctx,
+            service,
+        )
+        .await
+    }
+}
+
+async fn accept_loop<State, S>(
+    listener: TokioTcpListener,
+    limiter: Option<Arc<AcceptRateLimiter>>,
+    fd_budget: Option<FdBudget>,
+    ctx: Context<State>,
+    service: Arc<S>,
+) where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, TcpStream>,
+{
+    loop {
+        let (socket, peer_addr) = match accept(&listener, limiter.as_deref()).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                handle_accept_err(err, fd_budget.as_ref()).await;
+                continue;
+            }
+        };
+        if !admit(limiter.as_deref(), peer_addr) {
+            continue;
+        }
+
+        let service = service.clone();
+        let mut ctx = ctx.clone();
+        let fd_guard = fd_budget.as_ref().map(FdBudget::track);
+
+        tokio::spawn(async move {
+            let _fd_guard: Option<FdGuard> = fd_guard;
+            let local_addr = socket.local_addr().ok();
+            ctx.insert(SocketInfo::new(local_addr, peer_addr));
+
+            let _ = service.serve(ctx, socket).await;
+        });
+    }
+}
+
+async fn accept_loop_graceful<State, S>(
+    listener: TokioTcpListener,
+    limiter: Option<Arc<AcceptRateLimiter>>,
+    fd_budget: Option<FdBudget>,
+    guard: ShutdownGuard,
+    ctx: Context<State>,
+    service: Arc<S>,
+) where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, TcpStream>,
+{
+    let mut cancelled_fut = pin!(guard.cancelled());
+
+    loop {
+        tokio::select! {
+            _ = cancelled_fut.as_mut() => {
+                tracing::trace!("signal received: initiate graceful shutdown");
+                break;
+            }
+            result = accept(&listener, limiter.as_deref()) => {
+                match result {
+                    Ok((socket, peer_addr)) => {
+                        if !admit(limiter.as_deref(), peer_addr) {
+                            continue;
                         }
+
+                        let service = service.clone();
+                        let mut ctx = ctx.clone();
+                        let fd_guard = fd_budget.as_ref().map(FdBudget::track);
+
+                        guard.spawn_task(async move {
+                            let _fd_guard: Option<FdGuard> = fd_guard;
+                            let local_addr = socket.local_addr().ok();
+                            ctx.insert(SocketInfo::new(local_addr, peer_addr));
+
+                            let _ = service.serve(ctx, socket).await;
+                        });
+                    }
+                    Err(err) => {
+                        handle_accept_err(err, fd_budget.as_ref()).await;
                     }
                 }
             }
@@ -315,7 +548,25 @@ where
     }
 }
 
-async fn handle_accept_err(err: io::Error) {
+async fn accept(
+    listener: &TokioTcpListener,
+    limiter: Option<&AcceptRateLimiter>,
+) -> io::Result<(TcpStream, SocketAddr)> {
+    if let Some(limiter) = limiter {
+        limiter.ready().await;
+    }
+    listener.accept().await
+}
+
+fn admit(limiter: Option<&AcceptRateLimiter>, peer_addr: SocketAddr) -> bool {
+    let admitted = limiter.is_none_or(|limiter| limiter.admit());
+    if !admitted {
+        tracing::trace!(%peer_addr, "TCP accept rate limit exceeded: drop connection");
+    }
+    admitted
+}
+
+async fn handle_accept_err(err: io::Error, fd_budget: Option<&FdBudget>) {
     if crate::utils::is_connection_error(&err) {
         tracing::trace!(
             error = &err as &dyn std::error::Error,
@@ -333,7 +584,215 @@ async fn handle_accept_err(err: io::Error) {
         // > and then the listener will sleep for 1 second.
         //
         // hyper allowed customizing this but axum does not.
-        tracing::error!(error = &err as &dyn std::error::Error, "TCP accept error");
+        //
+        // In case an fd budget is used it logs the fd exhaustion instead,
+        // at most once per alarm interval, to not flood the logs.
+        if fd_budget
+            .and_then(|budget| budget.report_error(&err))
+            .is_none()
+        {
+            tracing::error!(error = &err as &dyn std::error::Error, "TCP accept error");
+        }
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
     }
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::server::AcceptRateLimitMode;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_reuse_port_shards_accept_connections() {
+        const WORKERS: usize = 4;
+        const CONNECTIONS: usize = 64;
+
+        let listener = TcpListener::build()
+            .reuse_port(WORKERS)
+            .bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        assert_eq!(listener.workers(), WORKERS);
+        let addr = listener.local_addr().unwrap();
+
+        let accepted: Arc<Vec<AtomicUsize>> =
+            Arc::new((0..WORKERS).map(|_| AtomicUsize::new(0)).collect());
+        for (index, shard) in std::iter::once(listener.inner)
+            .chain(listener.shards)
+            .enumerate()
+        {
+            assert_eq!(shard.local_addr().unwrap(), addr);
+            let accepted = accepted.clone();
+            tokio::spawn(async move {
+                while let Ok((_stream, _)) = shard.accept().await {
+                    accepted[index].fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+
+        let mut streams = Vec::with_capacity(CONNECTIONS);
+        for _ in 0..CONNECTIONS {
+            streams.push(TcpStream::connect(addr).await.unwrap());
+        }
+        while accepted
+            .iter()
+            .map(|count| count.load(Ordering::SeqCst))
+            .sum::<usize>()
+            < CONNECTIONS
+        {
+            tokio::task::yield_now().await;
+        }
+
+        let busy_shards = accepted
+            .iter()
+            .filter(|count| count.load(Ordering::SeqCst) > 0)
+            .count();
+        assert!(busy_shards > 1, "accepted: {accepted:?}");
+    }
+
+    #[tokio::test]
+    async fn test_reuse_port_serve() {
+        const CONNECTIONS: usize = 16;
+
+        let listener = TcpListener::build()
+            .reuse_port(2)
+            .bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(
+            listener.serve(service_fn(|mut stream: TcpStream| async move {
+                stream.write_all(b"hello").await.unwrap();
+                Ok::<_, Infallible>(())
+            })),
+        );
+
+        for _ in 0..CONNECTIONS {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"hello");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_accept_rate_limit_delay() {
+        const CONNECTIONS: usize = 5;
+
+        let listener = TcpListener::build()
+            .accept_rate_limit(AcceptRateLimit::new(2).with_burst(1))
+            .bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // all connections are pending in the backlog prior to serving
+        let mut streams = Vec::with_capacity(CONNECTIONS);
+        for _ in 0..CONNECTIONS {
+            streams.push(TcpStream::connect(addr).await.unwrap());
+        }
+
+        let accepted = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let start = Instant::now();
+        tokio::spawn(listener.serve(service_fn({
+            let accepted = accepted.clone();
+            move |_stream: TcpStream| {
+                let accepted = accepted.clone();
+                async move {
+                    accepted.lock().push(Instant::now());
+                    Ok::<_, Infallible>(())
+                }
+            }
+        })));
+
+        while accepted.lock().len() < CONNECTIONS {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let accepted = accepted.lock();
+        assert!(accepted[0] - start < Duration::from_millis(10));
+        for window in accepted.windows(2) {
+            assert!(
+                window[1] - window[0] >= Duration::from_millis(500),
+                "accepted: {accepted:?}"
+            );
+        }
+        assert!(accepted[CONNECTIONS - 1] - start >= Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_accept_rate_limit_drop() {
+        const CONNECTIONS: usize = 4;
+
+        let listener = TcpListener::build()
+            .accept_rate_limit(
+                AcceptRateLimit::new(1)
+                    .with_burst(2)
+                    .with_mode(AcceptRateLimitMode::Drop),
+            )
+            .bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut streams = Vec::with_capacity(CONNECTIONS);
+        for _ in 0..CONNECTIONS {
+            streams.push(TcpStream::connect(addr).await.unwrap());
+        }
+
+        tokio::spawn(
+            listener.serve(service_fn(|mut stream: TcpStream| async move {
+                stream.write_all(b"hello").await.unwrap();
+                Ok::<_, Infallible>(())
+            })),
+        );
+
+        let mut served = 0;
+        for mut stream in streams {
+            let mut buf = Vec::new();
+            let _ = stream.read_to_end(&mut buf).await;
+            if buf == b"hello" {
+                served += 1;
+            }
+        }
+        assert_eq!(served, 2);
+    }
+
+    #[tokio::test]
+    async fn test_fd_budget_tracks_accepted_connections() {
+        let budget = FdBudget::new(16);
+        let listener = TcpListener::build()
+            .fd_budget(budget.clone())
+            .bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(listener.serve(service_fn({
+            let budget = budget.clone();
+            move |mut stream: TcpStream| {
+                let budget = budget.clone();
+                async move {
+                    let open = budget.open_sockets().to_string();
+                    stream.write_all(open.as_bytes()).await.unwrap();
+                    Ok::<_, Infallible>(())
+                }
+            }
+        })));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"1");
+
+        while budget.open_sockets() > 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+}
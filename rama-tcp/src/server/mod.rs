@@ -41,3 +41,7 @@
 mod listener;
 #[doc(inline)]
 pub use listener::{TcpListener, TcpListenerBuilder};
+
+mod rate_limit;
+#[doc(inline)]
+pub use rate_limit::{AcceptRateLimit, AcceptRateLimitMode};
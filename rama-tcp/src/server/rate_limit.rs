@@ -0,0 +1,169 @@
+use parking_lot::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+/// What a [`TcpListener`] does with connections exceeding its [`AcceptRateLimit`].
+///
+/// [`TcpListener`]: super::TcpListener
+pub enum AcceptRateLimitMode {
+    #[default]
+    /// Delay accepting connections until the rate allows it,
+    /// leaving pending connections in the backlog of the listener.
+    Delay,
+    /// Accept connections as fast as possible,
+    /// but close those exceeding the rate immediately.
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+/// Limit on the rate (connections per second) at which a [`TcpListener`] accepts connections,
+/// enforced using a token bucket shared by all accept workers of that listener.
+///
+/// This is independent of, and applied prior to, any request-level limits.
+///
+/// [`TcpListener`]: super::TcpListener
+pub struct AcceptRateLimit {
+    rate: u32,
+    burst: u32,
+    mode: AcceptRateLimitMode,
+}
+
+impl AcceptRateLimit {
+    /// Create a new [`AcceptRateLimit`] for the given amount of connections per second,
+    /// with a burst equal to that rate and delaying the connections exceeding it.
+    ///
+    /// A rate of `0` is treated as `1`.
+    pub fn new(rate: u32) -> Self {
+        let rate = rate.max(1);
+        Self {
+            rate,
+            burst: rate,
+            mode: AcceptRateLimitMode::default(),
+        }
+    }
+
+    /// Set the maximum amount of connections accepted at once after being idle.
+    ///
+    /// A burst of `0` is treated as `1`.
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Set the maximum amount of connections accepted at once after being idle.
+    ///
+    /// A burst of `0` is treated as `1`.
+    pub fn set_burst(&mut self, burst: u32) -> &mut Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Set the [`AcceptRateLimitMode`], [`AcceptRateLimitMode::Delay`] by default.
+    pub fn with_mode(mut self, mode: AcceptRateLimitMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the [`AcceptRateLimitMode`], [`AcceptRateLimitMode::Delay`] by default.
+    pub fn set_mode(&mut self, mode: AcceptRateLimitMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Get the rate in connections per second.
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Get the maximum amount of connections accepted at once after being idle.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Get the [`AcceptRateLimitMode`].
+    pub fn mode(&self) -> AcceptRateLimitMode {
+        self.mode
+    }
+}
+
+#[derive(Debug)]
+/// The token bucket enforcing an [`AcceptRateLimit`].
+pub(super) struct AcceptRateLimiter {
+    mode: AcceptRateLimitMode,
+    bucket: Mutex<TokenBucket>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl AcceptRateLimiter {
+    pub(super) fn new(limit: &AcceptRateLimit) -> Self {
+        Self {
+            mode: limit.mode,
+            bucket: Mutex::new(TokenBucket {
+                rate: limit.rate as f64,
+                burst: limit.burst as f64,
+                tokens: limit.burst as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until the next connection can be accepted,
+    /// which is immediately unless in [`AcceptRateLimitMode::Delay`].
+    pub(super) async fn ready(&self) {
+        if self.mode != AcceptRateLimitMode::Delay {
+            return;
+        }
+        // the token is reserved prior to waiting, such that concurrent
+        // accept workers queue up behind each other instead of all waking up at once
+        let wait = self.bucket.lock().reserve(Instant::now());
+        if !wait.is_zero() {
+            tracing::trace!(?wait, "TCP accept rate limit exceeded: delay accept");
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Returns `true` if the accepted connection is to be served,
+    /// which is always the case unless in [`AcceptRateLimitMode::Drop`].
+    pub(super) fn admit(&self) -> bool {
+        self.mode != AcceptRateLimitMode::Drop || self.bucket.lock().try_take(Instant::now())
+    }
+}
+
+impl TokenBucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = elapsed
+            .as_secs_f64()
+            .mul_add(self.rate, self.tokens)
+            .min(self.burst);
+        self.last_refill = now;
+    }
+
+    fn reserve(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.;
+        if self.tokens >= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+}
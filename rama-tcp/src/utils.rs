@@ -16,3 +16,22 @@ pub fn is_connection_error(e: &io::Error) -> bool {
             | io::ErrorKind::Interrupted
     )
 }
+
+/// Check if the error is caused by the process (`EMFILE`)
+/// or system (`ENFILE`) running out of file descriptors.
+pub fn is_fd_exhaustion_error(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    const FD_EXHAUSTION_ERRORS: &[i32] = &[
+        23, // ENFILE
+        24, // EMFILE
+    ];
+    #[cfg(windows)]
+    const FD_EXHAUSTION_ERRORS: &[i32] = &[
+        10024, // WSAEMFILE
+    ];
+    #[cfg(not(any(unix, windows)))]
+    const FD_EXHAUSTION_ERRORS: &[i32] = &[];
+
+    e.raw_os_error()
+        .is_some_and(|code| FD_EXHAUSTION_ERRORS.contains(&code))
+}
@@ -1,4 +1,5 @@
 use super::TlsConnectorData;
+use crate::boring::key_update::{KeyUpdatePolicy, KeyUpdateStream};
 use crate::types::TlsTunnel;
 use pin_project_lite::pin_project;
 use private::{ConnectorKindAuto, ConnectorKindSecure, ConnectorKindTunnel};
@@ -267,7 +268,8 @@ where
         let host = transport_ctx.authority.host().clone();
 
         let connector_data = ctx.get().cloned();
-        let (stream, negotiated_params) = self.handshake(connector_data, host, conn).await?;
+        let (stream, negotiated_params, key_update_policy) =
+            self.handshake(connector_data, host, conn).await?;
 
         tracing::trace!(
             authority = %transport_ctx.authority,
@@ -275,12 +277,17 @@ where
         );
 
         ctx.insert(negotiated_params);
+        if let Some(key_update_policy) = key_update_policy {
+            ctx.insert(key_update_policy);
+        }
 
         Ok(EstablishedClientConnection {
             ctx,
             req,
             conn: AutoTlsStream {
-                inner: AutoTlsStreamData::Secure { inner: stream },
+                inner: AutoTlsStreamData::Secure {
+                    inner: KeyUpdateStream::new(stream, key_update_policy.unwrap_or_default()),
+                },
             },
             addr,
         })
@@ -325,8 +332,12 @@ where
         let host = transport_ctx.authority.host().clone();
 
         let connector_data = ctx.get().cloned();
-        let (conn, negotiated_params) = self.handshake(connector_data, host, conn).await?;
+        let (conn, negotiated_params, key_update_policy) =
+            self.handshake(connector_data, host, conn).await?;
         ctx.insert(negotiated_params);
+        if let Some(key_update_policy) = key_update_policy {
+            ctx.insert(key_update_policy);
+        }
 
         Ok(EstablishedClientConnection {
             ctx,
@@ -381,15 +392,21 @@ where
         };
 
         let connector_data = ctx.get().cloned();
-        let (stream, negotiated_params) = self.handshake(connector_data, host, conn).await?;
+        let (stream, negotiated_params, key_update_policy) =
+            self.handshake(connector_data, host, conn).await?;
         ctx.insert(negotiated_params);
+        if let Some(key_update_policy) = key_update_policy {
+            ctx.insert(key_update_policy);
+        }
 
         tracing::trace!("TlsConnector(tunnel): connection secured");
         Ok(EstablishedClientConnection {
             ctx,
             req,
             conn: AutoTlsStream {
-                inner: AutoTlsStreamData::Secure { inner: stream },
+                inner: AutoTlsStreamData::Secure {
+                    inner: KeyUpdateStream::new(stream, key_update_policy.unwrap_or_default()),
+                },
             },
             addr,
         })
@@ -402,7 +419,14 @@ impl<S, K> TlsConnector<S, K> {
         connector_data: Option<TlsConnectorData>,
         server_host: Host,
         stream: T,
-    ) -> Result<(SslStream<T>, NegotiatedTlsParameters), BoxError>
+    ) -> Result<
+        (
+            SslStream<T>,
+            NegotiatedTlsParameters,
+            Option<KeyUpdatePolicy>,
+        ),
+        BoxError,
+    >
     where
         T: Stream + Unpin,
     {
@@ -464,7 +488,9 @@ impl<S, K> TlsConnector<S, K> {
             }
         };
 
-        Ok((stream, params))
+        let key_update_policy = connector_data.and_then(|data| data.key_update_policy());
+
+        Ok((stream, params, key_update_policy))
     }
 }
 
@@ -489,7 +515,7 @@ pin_project! {
     /// A stream which can be either a secure or a plain stream.
     enum AutoTlsStreamData<S> {
         /// A secure stream.
-        Secure{ #[pin] inner: SslStream<S> },
+        Secure{ #[pin] inner: KeyUpdateStream<S> },
         /// A plain stream.
         Plain { #[pin] inner: S },
     }
@@ -599,6 +625,160 @@ mod tests {
         assert_send::<TlsConnectorLayer>();
     }
 
+    #[tokio::test]
+    async fn test_sni_override() {
+        use crate::boring::server::{TlsAcceptorData, TlsAcceptorLayer};
+        use parking_lot::Mutex;
+        use rama_core::service::service_fn;
+        use rama_http_types::{header::HOST, Body, Request};
+        use rama_net::address::Domain;
+        use rama_net::tls::client::{ClientConfig, ServerVerifyMode};
+        use rama_net::tls::server::{ServerAuth, ServerConfig};
+        use rama_net::tls::SecureTransport;
+        use std::{convert::Infallible, sync::Arc};
+        use tokio::io::DuplexStream;
+
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+
+        let acceptor_data =
+            TlsAcceptorData::try_from(ServerConfig::new(ServerAuth::default())).unwrap();
+        let server = TlsAcceptorLayer::new(acceptor_data)
+            .with_store_client_hello(true)
+            .layer(service_fn(
+                |ctx: Context<()>, _stream: SslStream<DuplexStream>| async move {
+                    Ok::<_, Infallible>(
+                        ctx.get::<SecureTransport>()
+                            .and_then(|t| t.client_hello())
+                            .and_then(|hello| hello.ext_server_name())
+                            .cloned(),
+                    )
+                },
+            ));
+        let server =
+            tokio::spawn(async move { server.serve(Context::default(), server_stream).await });
+
+        let connector_data = TlsConnectorData::try_from(
+            ClientConfig {
+                server_verify_mode: Some(ServerVerifyMode::Disable),
+                ..Default::default()
+            }
+            .with_sni_override(Host::Name(Domain::from_static("front.example.com"))),
+        )
+        .unwrap();
+        let client_stream = Arc::new(Mutex::new(Some(client_stream)));
+        let connector = TlsConnectorLayer::secure()
+            .with_connector_data(connector_data)
+            .layer(service_fn(move |ctx: Context<()>, req: Request| {
+                let conn = client_stream.lock().take().unwrap();
+                async move {
+                    Ok::<_, Infallible>(EstablishedClientConnection {
+                        ctx,
+                        req,
+                        conn,
+                        addr: ([127, 0, 0, 1], 443).into(),
+                    })
+                }
+            }));
+
+        let req = Request::builder()
+            .uri("https://real.example.com/")
+            .header(HOST, "real.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let EstablishedClientConnection { req, .. } =
+            connector.serve(Context::default(), req).await.unwrap();
+
+        // the request still targets the real host...
+        assert_eq!(req.uri().host(), Some("real.example.com"));
+        assert_eq!(req.headers()[HOST], "real.example.com");
+
+        // ... while the handshake used the overwritten server name
+        let server_name = server.await.unwrap().unwrap();
+        assert_eq!(
+            server_name,
+            Some(Host::Name(Domain::from_static("front.example.com")))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_key_update_policy() {
+        use crate::boring::server::{TlsAcceptorData, TlsAcceptorLayer};
+        use parking_lot::Mutex;
+        use rama_core::service::service_fn;
+        use rama_http_types::{Body, Request};
+        use rama_net::tls::client::{ClientConfig, ServerVerifyMode};
+        use rama_net::tls::server::{ServerAuth, ServerConfig};
+        use std::{convert::Infallible, sync::Arc};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+
+        // echo server
+        let acceptor_data =
+            TlsAcceptorData::try_from(ServerConfig::new(ServerAuth::default())).unwrap();
+        let server = TlsAcceptorLayer::new(acceptor_data).layer(service_fn(
+            |_ctx: Context<()>, mut stream: SslStream<DuplexStream>| async move {
+                let mut buf = [0; 512];
+                loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    stream.write_all(&buf[..n]).await.unwrap();
+                }
+                Ok::<_, Infallible>(())
+            },
+        ));
+        let server =
+            tokio::spawn(async move { server.serve(Context::default(), server_stream).await });
+
+        let connector_data = TlsConnectorData::try_from(ClientConfig {
+            server_verify_mode: Some(ServerVerifyMode::Disable),
+            ..Default::default()
+        })
+        .unwrap()
+        .with_key_update_max_bytes(1024);
+        let client_stream = Arc::new(Mutex::new(Some(client_stream)));
+        let connector = TlsConnectorLayer::auto()
+            .with_connector_data(connector_data)
+            .layer(service_fn(move |ctx: Context<()>, req: Request| {
+                let conn = client_stream.lock().take().unwrap();
+                async move {
+                    Ok::<_, Infallible>(EstablishedClientConnection {
+                        ctx,
+                        req,
+                        conn,
+                        addr: ([127, 0, 0, 1], 443).into(),
+                    })
+                }
+            }));
+
+        let req = Request::builder()
+            .uri("https://example.com/")
+            .body(Body::empty())
+            .unwrap();
+        let EstablishedClientConnection { ctx, mut conn, .. } =
+            connector.serve(Context::default(), req).await.unwrap();
+        assert_eq!(
+            ctx.get::<KeyUpdatePolicy>()
+                .and_then(|policy| policy.max_bytes()),
+            Some(1024)
+        );
+
+        // data keeps flowing in both directions across the key updates
+        let msg = [42; 512];
+        let mut buf = [0; 512];
+        for _ in 0..16 {
+            conn.write_all(&msg).await.unwrap();
+            conn.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, msg);
+        }
+        conn.shutdown().await.unwrap();
+        drop(conn);
+
+        server.await.unwrap().unwrap();
+    }
+
     #[test]
     fn assert_sync() {
         use rama_utils::test_helpers::assert_sync;
@@ -17,9 +17,10 @@ use rama_net::tls::{
 };
 use rama_net::tls::{openssl_cipher_list_str_from_cipher_list, ApplicationProtocol, KeyLogIntent};
 use rama_net::{address::Host, tls::client::ServerVerifyMode};
-use std::{fmt, sync::Arc};
+use std::{fmt, sync::Arc, time::Duration};
 use tracing::trace;
 
+use crate::boring::key_update::KeyUpdatePolicy;
 use crate::keylog::new_key_log_file_handle;
 
 #[derive(Debug, Clone)]
@@ -43,6 +44,7 @@ pub(super) struct ConnectConfigurationInput {
     pub(super) server_verify_mode: Option<ServerVerifyMode>,
     pub(super) client_auth: Option<ConnectorConfigClientAuth>,
     pub(super) store_server_certificate_chain: bool,
+    pub(super) key_update_policy: Option<KeyUpdatePolicy>,
 }
 
 #[derive(Debug, Clone)]
@@ -237,6 +239,10 @@ impl TlsConnectorData {
                 store_server_certificate_chain: other
                     .connect_config_input
                     .store_server_certificate_chain,
+                key_update_policy: other
+                    .connect_config_input
+                    .key_update_policy
+                    .or(self.connect_config_input.key_update_policy),
             }),
             server_name: other
                 .server_name
@@ -324,6 +330,71 @@ impl TlsConnectorData {
     pub fn server_name(&self) -> Option<&Host> {
         self.server_name.as_ref()
     }
+
+    /// Return the [`KeyUpdatePolicy`] of the established connections, if any.
+    pub fn key_update_policy(&self) -> Option<KeyUpdatePolicy> {
+        self.connect_config_input.key_update_policy
+    }
+
+    /// Update the keys of (TLS 1.3) connections once this many bytes
+    /// were read and written using them.
+    ///
+    /// Applied to the [`AutoTlsStream`] of the established connections,
+    /// and inserted in their [`Context`] to be used with a [`KeyUpdateStream`] otherwise.
+    ///
+    /// [`AutoTlsStream`]: super::AutoTlsStream
+    /// [`Context`]: rama_core::Context
+    /// [`KeyUpdateStream`]: crate::boring::key_update::KeyUpdateStream
+    pub fn with_key_update_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.set_key_update_max_bytes(max_bytes);
+        self
+    }
+
+    /// Update the keys of (TLS 1.3) connections once this many bytes
+    /// were read and written using them.
+    ///
+    /// Applied to the [`AutoTlsStream`] of the established connections,
+    /// and inserted in their [`Context`] to be used with a [`KeyUpdateStream`] otherwise.
+    ///
+    /// [`AutoTlsStream`]: super::AutoTlsStream
+    /// [`Context`]: rama_core::Context
+    /// [`KeyUpdateStream`]: crate::boring::key_update::KeyUpdateStream
+    pub fn set_key_update_max_bytes(&mut self, max_bytes: u64) -> &mut Self {
+        Arc::make_mut(&mut self.connect_config_input)
+            .key_update_policy
+            .get_or_insert_default()
+            .set_max_bytes(max_bytes);
+        self
+    }
+
+    /// Update the keys of (TLS 1.3) connections once they are in use for this long.
+    ///
+    /// Applied to the [`AutoTlsStream`] of the established connections,
+    /// and inserted in their [`Context`] to be used with a [`KeyUpdateStream`] otherwise.
+    ///
+    /// [`AutoTlsStream`]: super::AutoTlsStream
+    /// [`Context`]: rama_core::Context
+    /// [`KeyUpdateStream`]: crate::boring::key_update::KeyUpdateStream
+    pub fn with_key_update_max_age(mut self, max_age: Duration) -> Self {
+        self.set_key_update_max_age(max_age);
+        self
+    }
+
+    /// Update the keys of (TLS 1.3) connections once they are in use for this long.
+    ///
+    /// Applied to the [`AutoTlsStream`] of the established connections,
+    /// and inserted in their [`Context`] to be used with a [`KeyUpdateStream`] otherwise.
+    ///
+    /// [`AutoTlsStream`]: super::AutoTlsStream
+    /// [`Context`]: rama_core::Context
+    /// [`KeyUpdateStream`]: crate::boring::key_update::KeyUpdateStream
+    pub fn set_key_update_max_age(&mut self, max_age: Duration) -> &mut Self {
+        Arc::make_mut(&mut self.connect_config_input)
+            .key_update_policy
+            .get_or_insert_default()
+            .set_max_age(max_age);
+        self
+    }
 }
 
 impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
@@ -496,6 +567,7 @@ impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
                 server_verify_mode: value.server_verify_mode,
                 client_auth,
                 store_server_certificate_chain: value.store_server_certificate_chain,
+                key_update_policy: None,
             }),
             server_name,
         })
@@ -1,4 +1,21 @@
 //! TLS client support for Rama.
+//!
+//! # Renegotiation
+//!
+//! BoringSSL clients reject renegotiation requests of the server by default,
+//! failing the connection. The connectors in this module never change that default.
+//!
+//! The secure stream of an [`AutoTlsStream`] is a [`KeyUpdateStream`], which periodically updates
+//! the keys of long-lived TLS 1.3 connections and classifies rejected renegotiation attempts.
+//! Its thresholds are configured using [`TlsConnectorData::with_key_update_max_bytes`] and
+//! [`TlsConnectorData::with_key_update_max_age`]. The [`SslStream`] established by a secure
+//! [`TlsConnector`] can be wrapped in a [`KeyUpdateStream`] using the [`KeyUpdatePolicy`]
+//! inserted in the [`Context`] of the connection.
+//!
+//! [`KeyUpdateStream`]: super::key_update::KeyUpdateStream
+//! [`KeyUpdatePolicy`]: super::key_update::KeyUpdatePolicy
+//! [`SslStream`]: super::dep::tokio_boring::SslStream
+//! [`Context`]: rama_core::Context
 
 mod connector;
 #[doc(inline)]
@@ -0,0 +1,206 @@
+//! Direct boring-sys FFI and unsafe boring APIs used by the boring modules,
+//! for functionality which the boring crate does not expose using a safe API.
+//!
+//! All unsafe code of the boring modules is to be kept in this module.
+
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use super::server::session::{ticket_keys_index, SessionTicketKey, SessionTicketKeys};
+use crate::boring::dep::boring::{
+    rand::rand_bytes,
+    ssl::{SslContextBuilder, SslSession},
+};
+use crate::boring::dep::tokio_boring::SslStream;
+use boring_sys as ffi;
+use foreign_types::ForeignTypeRef;
+use moka::sync::Cache;
+use rama_core::error::OpaqueError;
+use std::{
+    ffi::{c_int, c_void},
+    ptr,
+};
+
+/// Length of the key name buffer passed to the ticket key callback.
+const TICKET_KEY_NAME_LEN: usize = 16;
+/// `EVP_MAX_IV_LENGTH`, the length of the iv buffer passed to the ticket key callback.
+const TICKET_IV_LEN: usize = 16;
+
+/// Queue a TLS 1.3 KeyUpdate message which requests the peer to update its keys as well,
+/// sent (and the own keys updated) with the next write on the stream.
+pub(super) fn request_key_update<S>(stream: &mut SslStream<S>) -> Result<(), OpaqueError> {
+    // SAFETY: the pointer is a valid connection owned by the stream,
+    // which is exclusively borrowed for the duration of the call
+    let result = unsafe {
+        ffi::SSL_key_update(
+            stream.ssl().as_ptr(),
+            ffi::SSL_KEY_UPDATE_REQUESTED as c_int,
+        )
+    };
+    if result != 1 {
+        return Err(OpaqueError::from_display(
+            "tls key update: queue key update",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+/// Push a `NO_RENEGOTIATION` error on the error queue of the current thread,
+/// as pushed by boringssl when rejecting a renegotiation attempt.
+pub(super) fn push_no_renegotiation_error() {
+    // SAFETY: only pushes an error on the thread local error queue, the file is a static C string
+    unsafe {
+        ffi::ERR_put_error(
+            ffi::ERR_LIB_SSL as c_int,
+            0,
+            ffi::SSL_R_NO_RENEGOTIATION as c_int,
+            c"ffi.rs".as_ptr(),
+            line!(),
+        );
+    }
+}
+
+#[cfg(test)]
+/// Set the session to be offered for resumption by a client connection.
+pub(super) fn set_session(
+    ssl: &mut crate::boring::dep::boring::ssl::SslRef,
+    session: &crate::boring::dep::boring::ssl::SslSessionRef,
+) -> Result<(), OpaqueError> {
+    // SAFETY: boring requires the session to be associated with the context of the connection,
+    // as ex data stored on the session is typed by that context. Only used in tests,
+    // where sessions are established by plain boring clients without any ex data,
+    // using the same ssl method as the connection they are offered by
+    unsafe { ssl.set_session(session) }.map_err(OpaqueError::from_std)
+}
+
+/// Install the callback which looks up sessions to resume by their ID in the given cache.
+pub(super) fn set_get_session_callback(
+    builder: &mut SslContextBuilder,
+    sessions: Cache<Vec<u8>, SslSession>,
+) {
+    // SAFETY: the cached sessions were all created by a boring ssl acceptor
+    // using the same session id context
+    unsafe {
+        builder.set_get_session_callback(move |_, id| Ok(sessions.get(id)));
+    }
+}
+
+/// Install the callback which encrypts and decrypts session tickets using
+/// the [`SessionTicketKeys`] stored in the ex data of the context.
+pub(super) fn set_ticket_key_callback(builder: &mut SslContextBuilder) -> Result<(), OpaqueError> {
+    // SAFETY: the pointer is a valid context owned by the builder,
+    // and the callback has the signature expected by boringssl
+    let result = unsafe {
+        ffi::SSL_CTX_set_tlsext_ticket_key_cb(builder.as_ptr(), Some(ticket_key_callback))
+    };
+    if result != 1 {
+        return Err(OpaqueError::from_display(
+            "session ticket keys: set ticket key callback",
+        ));
+    }
+    Ok(())
+}
+
+/// Callback used by BoringSSL to encrypt (`encrypt == 1`) or decrypt a session ticket.
+///
+/// Returns 1 on success, 2 if the ticket was decrypted using an older key
+/// (such that a new ticket is issued), 0 if no key was found to decrypt it
+/// (such that a full handshake is performed) and -1 on failure.
+///
+/// # Safety
+///
+/// Only to be called by boringssl, which guarantees that all pointers are valid,
+/// with `key_name` pointing to 16 bytes and `iv` to `EVP_MAX_IV_LENGTH` bytes.
+unsafe extern "C" fn ticket_key_callback(
+    ssl: *mut ffi::SSL,
+    key_name: *mut u8,
+    iv: *mut u8,
+    cipher_ctx: *mut ffi::EVP_CIPHER_CTX,
+    hmac_ctx: *mut ffi::HMAC_CTX,
+    encrypt: c_int,
+) -> c_int {
+    let Ok(index) = ticket_keys_index() else {
+        return -1;
+    };
+    // SAFETY: `ssl` is a valid connection, owned by boringssl for the duration of the callback,
+    // and the ex data at this index can only ever be set to `SessionTicketKeys`,
+    // which lives as long as the context does
+    let keys = unsafe {
+        (ffi::SSL_CTX_get_ex_data(ffi::SSL_get_SSL_CTX(ssl), index.as_raw())
+            as *const SessionTicketKeys)
+            .as_ref()
+    };
+    let Some(keys) = keys else {
+        return -1;
+    };
+    // SAFETY: boringssl passes a (writable) buffer of 16 bytes for the key name
+    let key_name = unsafe { std::slice::from_raw_parts_mut(key_name, TICKET_KEY_NAME_LEN) };
+    // SAFETY: boringssl passes a (writable) buffer of `EVP_MAX_IV_LENGTH` bytes for the iv
+    let iv = unsafe { std::slice::from_raw_parts_mut(iv, TICKET_IV_LEN) };
+
+    if encrypt == 1 {
+        let key = keys.encryption_key();
+        if rand_bytes(iv).is_err() {
+            return -1;
+        }
+        key_name.copy_from_slice(key.name());
+        // SAFETY: both contexts are valid and initialised by boringssl,
+        // and the key and iv have the lengths required by AES-256-CBC
+        let ok = unsafe {
+            ffi::EVP_EncryptInit_ex(
+                cipher_ctx,
+                ffi::EVP_aes_256_cbc(),
+                ptr::null_mut(),
+                key.aes_key().as_ptr(),
+                iv.as_ptr(),
+            ) == 1
+                && init_hmac(hmac_ctx, key)
+        };
+        return if ok { 1 } else { -1 };
+    }
+
+    let Some((position, key)) = keys
+        .iter()
+        .enumerate()
+        .find(|(_, key)| *key.name() == *key_name)
+    else {
+        return 0;
+    };
+    // SAFETY: both contexts are valid and initialised by boringssl,
+    // and the key and iv have the lengths required by AES-256-CBC
+    let ok = unsafe {
+        init_hmac(hmac_ctx, key)
+            && ffi::EVP_DecryptInit_ex(
+                cipher_ctx,
+                ffi::EVP_aes_256_cbc(),
+                ptr::null_mut(),
+                key.aes_key().as_ptr(),
+                iv.as_ptr(),
+            ) == 1
+    };
+    match (ok, position) {
+        (false, _) => -1,
+        (true, 0) => 1,
+        (true, _) => 2,
+    }
+}
+
+/// Initialise the HMAC context with the HMAC secret of the given key.
+///
+/// # Safety
+///
+/// `hmac_ctx` has to be a valid, initialised, HMAC context.
+unsafe fn init_hmac(hmac_ctx: *mut ffi::HMAC_CTX, key: &SessionTicketKey) -> bool {
+    let hmac_key = key.hmac_key();
+    // SAFETY: the context is valid as guaranteed by the caller,
+    // and the key pointer is valid for the given length
+    unsafe {
+        ffi::HMAC_Init_ex(
+            hmac_ctx,
+            hmac_key.as_ptr() as *const c_void,
+            hmac_key.len(),
+            ffi::EVP_sha256(),
+            ptr::null_mut(),
+        ) == 1
+    }
+}
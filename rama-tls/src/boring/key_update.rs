@@ -0,0 +1,539 @@
+//! TLS 1.3 key update support for long-lived boring connections.
+//!
+//! Connections which stay open for a long time (e.g. CONNECT tunnels terminated at rama)
+//! can be wrapped in a [`KeyUpdateStream`] to periodically update the traffic keys,
+//! once the bytes transferred or the age of the keys exceed the thresholds
+//! of its [`KeyUpdatePolicy`]. The thresholds are checked on each read and write.
+//! Once exceeded a KeyUpdate message is queued, which boring sends along with
+//! the next write, such that the peer updates its keys as well. Key updates are only done
+//! for TLS 1.3 connections, as older versions have no such mechanism.
+//!
+//! Use the [`KeyUpdateLayer`] to do this for the connections accepted
+//! by a [`TlsAcceptorService`], or [`KeyUpdateStream::new`] for client connections.
+//! The policy can also be configured using [`TlsAcceptorData::with_key_update_max_bytes`]
+//! and [`TlsConnectorData::with_key_update_max_bytes`] (or their `max_age` variants),
+//! in which case it is inserted in the [`Context`] of the established connection.
+//!
+//! # Renegotiation
+//!
+//! Legacy renegotiation is always rejected by boring, both as a server and a client.
+//! A [`KeyUpdateStream`] classifies the read errors caused by a rejected renegotiation
+//! attempt, such that these are logged (as a debug event with the `rama::tls` target)
+//! and, with the `telemetry` feature enabled, counted as `renegotiation_rejected` events
+//! in the `tls.connection.events` metric, next to the `key_update` events.
+//!
+//! [`TlsAcceptorService`]: super::server::TlsAcceptorService
+//! [`TlsAcceptorData::with_key_update_max_bytes`]: super::server::TlsAcceptorData::with_key_update_max_bytes
+//! [`TlsConnectorData::with_key_update_max_bytes`]: super::client::TlsConnectorData::with_key_update_max_bytes
+
+use crate::boring::dep::{
+    boring::ssl::{self, SslVersion},
+    tokio_boring::SslStream,
+};
+use rama_core::{Context, Layer, Service};
+use rama_net::stream::Stream;
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{self, Poll},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const EVENT_KEY_UPDATE: &str = "key_update";
+const EVENT_RENEGOTIATION_REJECTED: &str = "renegotiation_rejected";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// The thresholds at which a [`KeyUpdateStream`] updates the keys of its connection.
+///
+/// By default no thresholds are set, and as such keys are never updated.
+pub struct KeyUpdatePolicy {
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+}
+
+impl KeyUpdatePolicy {
+    /// Create a new [`KeyUpdatePolicy`] without any thresholds.
+    pub const fn new() -> Self {
+        Self {
+            max_bytes: None,
+            max_age: None,
+        }
+    }
+
+    /// Update the keys once this many bytes were read and written using them.
+    pub const fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Update the keys once this many bytes were read and written using them.
+    pub fn set_max_bytes(&mut self, max_bytes: u64) -> &mut Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Update the keys once they are in use for this long.
+    pub const fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Update the keys once they are in use for this long.
+    pub fn set_max_age(&mut self, max_age: Duration) -> &mut Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Get the amount of bytes after which the keys are updated, if any.
+    pub fn max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    /// Get the age after which the keys are updated, if any.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+}
+
+/// A boring [`SslStream`] which updates its keys according to a [`KeyUpdatePolicy`].
+///
+/// See the [module docs](self) for more details.
+pub struct KeyUpdateStream<S> {
+    inner: SslStream<S>,
+    policy: KeyUpdatePolicy,
+    bytes: u64,
+    keys_created_at: Instant,
+    key_updates: u64,
+}
+
+impl<S: fmt::Debug> fmt::Debug for KeyUpdateStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyUpdateStream")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .field("bytes", &self.bytes)
+            .field("keys_created_at", &self.keys_created_at)
+            .field("key_updates", &self.key_updates)
+            .finish()
+    }
+}
+
+impl<S> KeyUpdateStream<S> {
+    /// Create a new [`KeyUpdateStream`] for the given (established) [`SslStream`].
+    pub fn new(inner: SslStream<S>, policy: KeyUpdatePolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            bytes: 0,
+            keys_created_at: Instant::now(),
+            key_updates: 0,
+        }
+    }
+
+    /// Gets a reference to the inner [`SslStream`].
+    pub fn get_ref(&self) -> &SslStream<S> {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the inner [`SslStream`].
+    pub fn get_mut(&mut self) -> &mut SslStream<S> {
+        &mut self.inner
+    }
+
+    /// Consumes the [`KeyUpdateStream`], returning the inner [`SslStream`].
+    pub fn into_inner(self) -> SslStream<S> {
+        self.inner
+    }
+
+    /// Returns the number of key updates requested by this stream so far.
+    pub fn key_updates(&self) -> u64 {
+        self.key_updates
+    }
+
+    fn key_update_due(&self) -> bool {
+        self.policy.max_bytes.is_some_and(|max| self.bytes >= max)
+            || self
+                .policy
+                .max_age
+                .is_some_and(|max| self.keys_created_at.elapsed() >= max)
+    }
+
+    fn maybe_update_keys(&mut self) {
+        if !self.key_update_due() || self.inner.ssl().version2() != Some(SslVersion::TLS1_3) {
+            return;
+        }
+        match super::ffi::request_key_update(&mut self.inner) {
+            Ok(()) => {
+                tracing::trace!(
+                    target: "rama::tls",
+                    bytes = self.bytes,
+                    "tls key update requested"
+                );
+                self.key_updates += 1;
+                record_event(EVENT_KEY_UPDATE);
+            }
+            Err(err) => {
+                tracing::debug!(target: "rama::tls", %err, "tls key update failed");
+            }
+        }
+        // reset regardless of the outcome, to not retry on each read and write
+        self.bytes = 0;
+        self.keys_created_at = Instant::now();
+    }
+}
+
+impl<S> AsyncRead for KeyUpdateStream<S>
+where
+    S: Stream + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        match &result {
+            Poll::Ready(Ok(())) => {
+                this.bytes += (buf.filled().len() - filled) as u64;
+                // queued for the next write, requesting the peer to update its keys as well
+                this.maybe_update_keys();
+            }
+            Poll::Ready(Err(err)) if is_renegotiation_error(err) => {
+                tracing::debug!(target: "rama::tls", %err, "tls renegotiation attempt rejected");
+                record_event(EVENT_RENEGOTIATION_REJECTED);
+            }
+            _ => (),
+        }
+        result
+    }
+}
+
+impl<S> AsyncWrite for KeyUpdateStream<S>
+where
+    S: Stream + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.maybe_update_keys();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            this.bytes += n as u64;
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Returns `true` if the given (read) error is caused by boring rejecting a renegotiation attempt.
+fn is_renegotiation_error(err: &io::Error) -> bool {
+    err.get_ref()
+        .and_then(|err| err.downcast_ref::<ssl::Error>())
+        .and_then(|err| err.ssl_error())
+        .is_some_and(|stack| {
+            stack
+                .errors()
+                .iter()
+                .any(|err| err.reason().is_some_and(|r| r.contains("NO_RENEGOTIATION")))
+        })
+}
+
+#[cfg(feature = "telemetry")]
+fn record_event(event: &'static str) {
+    use rama_core::telemetry::opentelemetry::{
+        global, metrics::Counter, semantic_conventions, InstrumentationScope, KeyValue,
+    };
+    use std::sync::OnceLock;
+
+    static EVENTS: OnceLock<Counter<u64>> = OnceLock::new();
+    EVENTS
+        .get_or_init(|| {
+            global::meter_with_scope(
+                InstrumentationScope::builder(format!("{}-tls", rama_utils::info::NAME))
+                    .with_version(rama_utils::info::VERSION)
+                    .with_schema_url(semantic_conventions::SCHEMA_URL)
+                    .build(),
+            )
+            .u64_counter("tls.connection.events")
+            .with_description(
+                "Measures the key updates and rejected renegotiation attempts of tls connections.",
+            )
+            .build()
+        })
+        .add(1, &[KeyValue::new("event", event)]);
+}
+
+#[cfg(not(feature = "telemetry"))]
+fn record_event(_event: &'static str) {}
+
+#[derive(Debug, Clone)]
+/// A [`Layer`] which wraps the [`SslStream`] passed to the given service in a [`KeyUpdateStream`].
+pub struct KeyUpdateLayer {
+    policy: Option<KeyUpdatePolicy>,
+}
+
+impl KeyUpdateLayer {
+    /// Create a new [`KeyUpdateLayer`] using the given [`KeyUpdatePolicy`].
+    pub const fn new(policy: KeyUpdatePolicy) -> Self {
+        Self {
+            policy: Some(policy),
+        }
+    }
+
+    /// Create a new [`KeyUpdateLayer`] using the [`KeyUpdatePolicy`] found in the [`Context`],
+    /// as inserted by the [`TlsAcceptorService`] in case it is configured on its [`TlsAcceptorData`].
+    ///
+    /// The keys of connections without such policy are never updated.
+    ///
+    /// [`TlsAcceptorService`]: super::server::TlsAcceptorService
+    /// [`TlsAcceptorData`]: super::server::TlsAcceptorData
+    pub const fn from_context() -> Self {
+        Self { policy: None }
+    }
+}
+
+impl<S> Layer<S> for KeyUpdateLayer {
+    type Service = KeyUpdateService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        KeyUpdateService {
+            inner,
+            policy: self.policy,
+        }
+    }
+}
+
+/// A [`Service`] which wraps the [`SslStream`] it serves in a [`KeyUpdateStream`],
+/// before passing it to the inner service.
+pub struct KeyUpdateService<S> {
+    inner: S,
+    policy: Option<KeyUpdatePolicy>,
+}
+
+impl<S> KeyUpdateService<S> {
+    /// Create a new [`KeyUpdateService`] using the given [`KeyUpdatePolicy`].
+    pub const fn new(inner: S, policy: KeyUpdatePolicy) -> Self {
+        Self {
+            inner,
+            policy: Some(policy),
+        }
+    }
+
+    /// Create a new [`KeyUpdateService`] using the [`KeyUpdatePolicy`] found in the [`Context`].
+    ///
+    /// See [`KeyUpdateLayer::from_context`] for more details.
+    pub const fn from_context(inner: S) -> Self {
+        Self {
+            inner,
+            policy: None,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for KeyUpdateService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyUpdateService")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for KeyUpdateService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<T, S, IO> Service<T, SslStream<IO>> for KeyUpdateService<S>
+where
+    T: Clone + Send + Sync + 'static,
+    IO: Stream + Unpin,
+    S: Service<T, KeyUpdateStream<IO>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    fn serve(
+        &self,
+        ctx: Context<T>,
+        stream: SslStream<IO>,
+    ) -> impl std::future::Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
+        let policy = self
+            .policy
+            .or_else(|| ctx.get().copied())
+            .unwrap_or_default();
+        self.inner.serve(ctx, KeyUpdateStream::new(stream, policy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boring::dep::boring::{
+        error::ErrorStack,
+        ssl::{SslConnector, SslMethod, SslVerifyMode},
+    };
+    use crate::boring::server::{TlsAcceptorData, TlsAcceptorService};
+    use rama_net::tls::server::{ServerAuth, ServerConfig};
+    use std::convert::Infallible;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    #[test]
+    fn test_is_renegotiation_error() {
+        super::super::ffi::push_no_renegotiation_error();
+        let err = io::Error::other(ssl::Error::from(ErrorStack::get()));
+        assert!(is_renegotiation_error(&err));
+
+        let err = io::Error::other(ssl::Error::from(ErrorStack::get()));
+        assert!(!is_renegotiation_error(&err));
+        assert!(!is_renegotiation_error(&io::ErrorKind::BrokenPipe.into()));
+    }
+
+    #[tokio::test]
+    async fn test_key_update_stream() {
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+
+        // echo server which updates its keys every 1024 bytes
+        let data = TlsAcceptorData::try_from(ServerConfig::new(ServerAuth::default())).unwrap();
+        let server = TlsAcceptorService::new(
+            data,
+            KeyUpdateLayer::new(KeyUpdatePolicy::new().with_max_bytes(1024)).layer(
+                rama_core::service::service_fn(
+                    |_ctx: Context<()>, mut stream: KeyUpdateStream<DuplexStream>| async move {
+                        let mut buf = [0; 512];
+                        loop {
+                            let n = stream.read(&mut buf).await.unwrap();
+                            if n == 0 {
+                                break;
+                            }
+                            stream.write_all(&buf[..n]).await.unwrap();
+                        }
+                        Ok::<_, Infallible>(stream.key_updates())
+                    },
+                ),
+            ),
+            false,
+        );
+        let server =
+            tokio::spawn(async move { server.serve(Context::default(), server_stream).await });
+
+        let mut stream = connect_client(client_stream).await;
+
+        // data keeps flowing in both directions across the key updates
+        let msg = [42; 512];
+        let mut buf = [0; 512];
+        for _ in 0..16 {
+            stream.write_all(&msg).await.unwrap();
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, msg);
+        }
+        stream.shutdown().await.unwrap();
+        drop(stream);
+
+        let key_updates = server.await.unwrap().unwrap();
+        assert!(key_updates >= 7, "key updates: {key_updates}");
+    }
+
+    async fn connect_client(stream: DuplexStream) -> SslStream<DuplexStream> {
+        let mut connector = SslConnector::builder(SslMethod::tls_client()).unwrap();
+        connector.set_verify(SslVerifyMode::NONE);
+        let config = connector.build().configure().unwrap();
+        let stream = tokio_boring::connect(config, "localhost", stream)
+            .await
+            .unwrap();
+        assert_eq!(stream.ssl().version2(), Some(SslVersion::TLS1_3));
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_key_update_stream_read_only() {
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+
+        // server which only reads, using the policy configured on its acceptor data
+        let data = TlsAcceptorData::try_from(ServerConfig::new(ServerAuth::default()))
+            .unwrap()
+            .with_key_update_max_bytes(1024);
+        let server = TlsAcceptorService::new(
+            data,
+            KeyUpdateLayer::from_context().layer(rama_core::service::service_fn(
+                |_ctx: Context<()>, mut stream: KeyUpdateStream<DuplexStream>| async move {
+                    let mut buf = [0; 512];
+                    let mut read = 0;
+                    while read < 16 * 512 {
+                        read += stream.read(&mut buf).await.unwrap();
+                    }
+                    // requested while reading, without writing anything
+                    let key_updates = stream.key_updates();
+                    // the queued key update is sent along with this write
+                    stream.write_all(b"ok").await.unwrap();
+                    stream.shutdown().await.unwrap();
+                    Ok::<_, Infallible>(key_updates)
+                },
+            )),
+            false,
+        );
+        let server =
+            tokio::spawn(async move { server.serve(Context::default(), server_stream).await });
+
+        let mut stream = connect_client(client_stream).await;
+        let msg = [42; 512];
+        for _ in 0..16 {
+            stream.write_all(&msg).await.unwrap();
+        }
+
+        // the client keeps reading after the server updated its keys
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"ok");
+
+        let key_updates = server.await.unwrap().unwrap();
+        assert!(key_updates >= 7, "key updates: {key_updates}");
+    }
+
+    #[tokio::test]
+    async fn test_key_update_layer_without_policy() {
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+
+        let data = TlsAcceptorData::try_from(ServerConfig::new(ServerAuth::default())).unwrap();
+        let server = TlsAcceptorService::new(
+            data,
+            KeyUpdateLayer::from_context().layer(rama_core::service::service_fn(
+                |_ctx: Context<()>, mut stream: KeyUpdateStream<DuplexStream>| async move {
+                    let mut buf = Vec::new();
+                    stream.read_to_end(&mut buf).await.unwrap();
+                    Ok::<_, Infallible>(stream.key_updates())
+                },
+            )),
+            false,
+        );
+        let server =
+            tokio::spawn(async move { server.serve(Context::default(), server_stream).await });
+
+        let mut stream = connect_client(client_stream).await;
+        stream.write_all(&[42; 8 * 1024]).await.unwrap();
+        stream.shutdown().await.unwrap();
+        drop(stream);
+
+        assert_eq!(server.await.unwrap().unwrap(), 0);
+    }
+}
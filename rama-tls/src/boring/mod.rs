@@ -3,6 +3,10 @@
 pub mod client;
 pub mod server;
 
+pub mod key_update;
+
+mod ffi;
+
 pub mod dep {
     //! Dependencies for rama boring modules.
     //!
@@ -11,7 +11,9 @@ use crate::boring::dep::boring::{
     },
 };
 use boring::{
-    ssl::{ClientHello, NameType, SelectCertError, SslAcceptorBuilder, SslRef},
+    ssl::{
+        ClientHello, NameType, SelectCertError, SslAcceptorBuilder, SslRef, SslSessionCacheMode,
+    },
     x509::extension::{AuthorityKeyIdentifier, SubjectAlternativeName},
 };
 use moka::sync::Cache;
@@ -23,12 +25,15 @@ use rama_net::{
         client::ClientHello as RamaClientHello,
         server::{
             CacheKind, ClientVerifyMode, DynamicIssuer, SelfSignedData, ServerAuth, ServerAuthData,
-            ServerCertIssuerKind,
+            ServerCertIssuerKind, SniCertResolver,
         },
         ApplicationProtocol, DataEncoding, KeyLogIntent, ProtocolVersion,
     },
 };
 use std::{sync::Arc, time::Duration};
+
+use super::session::{SessionCache, SessionTicketKey, SessionTicketKeys};
+use crate::boring::key_update::KeyUpdatePolicy;
 use tokio_boring::{AsyncSelectCertError, BoxSelectCertFinish};
 
 #[derive(Debug, Clone)]
@@ -51,8 +56,147 @@ pub(super) struct TlsConfig {
     pub(super) protocol_versions: Option<Vec<ProtocolVersion>>,
     /// optionally define client certificates in case client auth is enabled
     pub(super) client_cert_chain: Option<Vec<X509>>,
+    /// fail the handshake if client auth is enabled and the client provides no certificate
+    pub(super) client_cert_required: bool,
     /// store client certificate chain if true and client provided this
     pub store_client_certificate_chain: bool,
+    /// issue session tickets to clients, enabled by default
+    pub(super) session_tickets: bool,
+    /// optionally define the keys used to encrypt and decrypt session tickets
+    pub(super) session_ticket_keys: Option<SessionTicketKeys>,
+    /// optionally define a session cache shared by all connections
+    pub(super) session_cache: Option<SessionCache>,
+    /// optionally define when the keys of (TLS 1.3) connections are to be updated
+    pub(super) key_update_policy: Option<KeyUpdatePolicy>,
+}
+
+impl TlsAcceptorData {
+    /// Enable or disable session tickets, which are enabled by default.
+    ///
+    /// Tickets can only be used to resume a session
+    /// when [`SessionTicketKey`]s are defined.
+    pub fn with_session_tickets(mut self, enabled: bool) -> Self {
+        self.set_session_tickets(enabled);
+        self
+    }
+
+    /// Enable or disable session tickets, which are enabled by default.
+    ///
+    /// Tickets can only be used to resume a session
+    /// when [`SessionTicketKey`]s are defined.
+    pub fn set_session_tickets(&mut self, enabled: bool) -> &mut Self {
+        Arc::make_mut(&mut self.config).session_tickets = enabled;
+        self
+    }
+
+    /// Define the [`SessionTicketKey`]s used to encrypt and decrypt session tickets.
+    ///
+    /// The first key is used to encrypt new tickets, while all keys can decrypt tickets.
+    /// Keep the previous keys around while rotating keys across a fleet,
+    /// such that the tickets issued by nodes which did not rotate yet still resume.
+    /// Tickets decrypted using any but the first key are renewed.
+    ///
+    /// Passing no keys disables session resumption using tickets.
+    pub fn with_session_ticket_keys(mut self, keys: Vec<SessionTicketKey>) -> Self {
+        self.set_session_ticket_keys(keys);
+        self
+    }
+
+    /// Define the [`SessionTicketKey`]s used to encrypt and decrypt session tickets.
+    ///
+    /// The first key is used to encrypt new tickets, while all keys can decrypt tickets.
+    /// Keep the previous keys around while rotating keys across a fleet,
+    /// such that the tickets issued by nodes which did not rotate yet still resume.
+    /// Tickets decrypted using any but the first key are renewed.
+    ///
+    /// Passing no keys disables session resumption using tickets.
+    pub fn set_session_ticket_keys(&mut self, keys: Vec<SessionTicketKey>) -> &mut Self {
+        Arc::make_mut(&mut self.config).session_ticket_keys = SessionTicketKeys::new(keys);
+        self
+    }
+
+    /// Define the session cache mode and the maximum amount of sessions
+    /// kept in a cache shared by all connections accepted using this data.
+    ///
+    /// Sessions are only cached in case the mode includes [`SslSessionCacheMode::SERVER`].
+    /// Note that TLS 1.3 sessions are resumed using tickets only.
+    pub fn with_session_cache(mut self, mode: SslSessionCacheMode, size: u64) -> Self {
+        self.set_session_cache(mode, size);
+        self
+    }
+
+    /// Define the session cache mode and the maximum amount of sessions
+    /// kept in a cache shared by all connections accepted using this data.
+    ///
+    /// Sessions are only cached in case the mode includes [`SslSessionCacheMode::SERVER`].
+    /// Note that TLS 1.3 sessions are resumed using tickets only.
+    pub fn set_session_cache(&mut self, mode: SslSessionCacheMode, size: u64) -> &mut Self {
+        Arc::make_mut(&mut self.config).session_cache = Some(SessionCache::new(mode, size));
+        self
+    }
+
+    /// Update the keys of (TLS 1.3) connections once this many bytes
+    /// were read and written using them.
+    ///
+    /// The resulting [`KeyUpdatePolicy`] is inserted in the [`Context`] of the accepted
+    /// connections, and applied by a [`KeyUpdateLayer::from_context`].
+    ///
+    /// [`Context`]: rama_core::Context
+    /// [`KeyUpdateLayer::from_context`]: crate::boring::key_update::KeyUpdateLayer::from_context
+    pub fn with_key_update_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.set_key_update_max_bytes(max_bytes);
+        self
+    }
+
+    /// Update the keys of (TLS 1.3) connections once this many bytes
+    /// were read and written using them.
+    ///
+    /// The resulting [`KeyUpdatePolicy`] is inserted in the [`Context`] of the accepted
+    /// connections, and applied by a [`KeyUpdateLayer::from_context`].
+    ///
+    /// [`Context`]: rama_core::Context
+    /// [`KeyUpdateLayer::from_context`]: crate::boring::key_update::KeyUpdateLayer::from_context
+    pub fn set_key_update_max_bytes(&mut self, max_bytes: u64) -> &mut Self {
+        Arc::make_mut(&mut self.config)
+            .key_update_policy
+            .get_or_insert_default()
+            .set_max_bytes(max_bytes);
+        self
+    }
+
+    /// Update the keys of (TLS 1.3) connections once they are in use for this long.
+    ///
+    /// The resulting [`KeyUpdatePolicy`] is inserted in the [`Context`] of the accepted
+    /// connections, and applied by a [`KeyUpdateLayer::from_context`].
+    ///
+    /// [`Context`]: rama_core::Context
+    /// [`KeyUpdateLayer::from_context`]: crate::boring::key_update::KeyUpdateLayer::from_context
+    pub fn with_key_update_max_age(mut self, max_age: Duration) -> Self {
+        self.set_key_update_max_age(max_age);
+        self
+    }
+
+    /// Update the keys of (TLS 1.3) connections once they are in use for this long.
+    ///
+    /// The resulting [`KeyUpdatePolicy`] is inserted in the [`Context`] of the accepted
+    /// connections, and applied by a [`KeyUpdateLayer::from_context`].
+    ///
+    /// [`Context`]: rama_core::Context
+    /// [`KeyUpdateLayer::from_context`]: crate::boring::key_update::KeyUpdateLayer::from_context
+    pub fn set_key_update_max_age(&mut self, max_age: Duration) -> &mut Self {
+        Arc::make_mut(&mut self.config)
+            .key_update_policy
+            .get_or_insert_default()
+            .set_max_age(max_age);
+        self
+    }
+
+    /// Get the [`KeyUpdatePolicy`] inserted in the [`Context`] of the accepted connections, if any.
+    ///
+    /// [`Context`]: rama_core::Context
+    pub fn key_update_policy(&self) -> Option<KeyUpdatePolicy> {
+        self.config.key_update_policy
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -76,6 +220,15 @@ enum TlsCertSourceKind {
         /// Cache for certs already issued
         cert_cache: Option<Cache<Host, IssuedCert>>,
     },
+    Sni {
+        resolver: SniCertResolver,
+        /// Certs resolved for (lowercase) SNI hostnames, such that
+        /// their data is not parsed again for every handshake
+        cert_cache: Cache<String, IssuedCert>,
+        /// Cert used for clients with no or an unknown SNI hostname,
+        /// aborting the handshake of such clients if not defined
+        fallback: Option<IssuedCert>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -85,11 +238,17 @@ struct IssuedCert {
 }
 
 impl TlsCertSource {
+    /// Returns true if the certs are selected based on the SNI hostname of the client.
+    pub(super) fn is_sni(&self) -> bool {
+        matches!(self.kind, TlsCertSourceKind::Sni { .. })
+    }
+
     pub(super) async fn issue_certs(
         self,
         mut builder: SslAcceptorBuilder,
         server_name: Option<Host>,
         maybe_client_hello: &Option<Arc<Mutex<Option<RamaClientHello>>>>,
+        maybe_selected_server_name: &Option<Arc<Mutex<Option<Domain>>>>,
     ) -> Result<SslAcceptorBuilder, OpaqueError> {
         match self.kind {
             TlsCertSourceKind::InMemory(issued_cert) => {
@@ -243,6 +402,84 @@ impl TlsCertSource {
                     }))
                 });
             }
+            TlsCertSourceKind::Sni {
+                resolver,
+                cert_cache,
+                fallback,
+            } => {
+                let cb_maybe_client_hello = maybe_client_hello.clone();
+                let cb_maybe_selected_server_name = maybe_selected_server_name.clone();
+                builder.set_select_certificate_callback(move |client_hello| {
+                    if let Some(cb_maybe_client_hello) = &cb_maybe_client_hello {
+                        let maybe_client_hello = match RamaClientHello::try_from(&client_hello) {
+                            Ok(ch) => Some(ch),
+                            Err(err) => {
+                                tracing::warn!(err = %err, "failed to extract boringssl client hello");
+                                None
+                            }
+                        };
+                        *cb_maybe_client_hello.lock() = maybe_client_hello;
+                    }
+
+                    let mut client_hello = client_hello;
+                    let ssl_ref = client_hello.ssl_mut();
+
+                    let sni = ssl_ref.servername(NameType::HOST_NAME).map(ToOwned::to_owned);
+                    let resolved = sni.as_deref().and_then(|sni| {
+                        let key = sni.to_ascii_lowercase();
+                        if let Some(issued_cert) = cert_cache.get(&key) {
+                            return Some(Ok((sni, issued_cert)));
+                        }
+                        let data = resolver.resolve(sni)?;
+                        Some(server_auth_data_to_private_key_and_ca_chain(&data).map(|issued_cert| {
+                            cert_cache.insert(key, issued_cert.clone());
+                            (sni, issued_cert)
+                        }))
+                    });
+
+                    let issued_cert = match resolved {
+                        Some(Err(err)) => {
+                            tracing::error!(error = %err, "boring: select certificate callback: server_auth_data to key and ca chain failed");
+                            return Err(SelectCertError::ERROR);
+                        }
+                        Some(Ok((sni, issued_cert))) => {
+                            tracing::trace!(%sni, "boring: select certificate callback: use resolved cert for sni");
+                            if let Some(cb_maybe_selected_server_name) = &cb_maybe_selected_server_name {
+                                match Domain::try_from(sni.to_owned()) {
+                                    Ok(domain) => *cb_maybe_selected_server_name.lock() = Some(domain),
+                                    Err(err) => tracing::warn!(error = %err, "boring: select certificate callback: invalid sni domain"),
+                                }
+                            }
+                            issued_cert
+                        }
+                        None => match &fallback {
+                            Some(fallback) => {
+                                tracing::trace!(?sni, "boring: select certificate callback: use fallback cert for sni");
+                                fallback.clone()
+                            }
+                            None => {
+                                tracing::debug!(?sni, "boring: select certificate callback: no cert for sni: abort handshake");
+                                return Err(SelectCertError::ERROR);
+                            }
+                        },
+                    };
+
+                    let host = to_host(ssl_ref, &server_name).map_err(|err| {
+                        tracing::error!(error = %err, "boring: failed getting host");
+                        SelectCertError::ERROR
+                    })?;
+                    add_issued_cert_to_ssl_ref(
+                        host,
+                        issued_cert,
+                        ssl_ref,
+                    ).map_err(|err| {
+                        tracing::error!(error = %err, "boring: select certificate callback: add certs to ssl ref");
+                        SelectCertError::ERROR
+                    })?;
+
+                    Ok(())
+                });
+            }
         }
 
         Ok(builder)
@@ -253,28 +490,14 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
     type Error = OpaqueError;
 
     fn try_from(value: rama_net::tls::server::ServerConfig) -> Result<Self, Self::Error> {
-        let client_cert_chain = match value.client_verify_mode {
+        let (client_cert_chain, client_cert_required) = match value.client_verify_mode {
             // no client auth
-            ClientVerifyMode::Auto | ClientVerifyMode::Disable => None,
+            ClientVerifyMode::Auto | ClientVerifyMode::Disable => (None, false),
             // client auth enabled
-            ClientVerifyMode::ClientAuth(DataEncoding::Der(bytes)) => Some(vec![X509::from_der(
-                &bytes[..],
-            )
-            .context("boring/TlsAcceptorData: parse x509 client cert from DER content")?]),
-            ClientVerifyMode::ClientAuth(DataEncoding::DerStack(bytes_list)) => Some(
-                bytes_list
-                    .into_iter()
-                    .map(|b| {
-                        X509::from_der(&b[..]).context(
-                            "boring/TlsAcceptorData: parse x509 client cert from DER content",
-                        )
-                    })
-                    .collect::<Result<Vec<_>, _>>()?,
-            ),
-            ClientVerifyMode::ClientAuth(DataEncoding::Pem(raw_data)) => Some(
-                X509::stack_from_pem(raw_data.as_bytes())
-                    .context("boring/TlsAcceptorData: parse x509 client cert from PEM content")?,
-            ),
+            ClientVerifyMode::ClientAuth(data) => (Some(client_certs_from_data(data)?), true),
+            ClientVerifyMode::OptionalClientAuth(data) => {
+                (Some(client_certs_from_data(data)?), false)
+            }
         };
 
         let cert_source_kind = match value.server_auth {
@@ -329,6 +552,22 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
                     }
                 }
             }
+
+            ServerAuth::Sni(resolver) => {
+                let fallback = resolver
+                    .fallback()
+                    .map(server_auth_data_to_private_key_and_ca_chain)
+                    .transpose()
+                    .context("boring/TlsAcceptorData: sni: fallback cert")?;
+                TlsCertSourceKind::Sni {
+                    resolver,
+                    cert_cache: Cache::builder()
+                        .time_to_live(Duration::from_secs(60 * 60))
+                        .max_capacity(1024)
+                        .build(),
+                    fallback,
+                }
+            }
         };
 
         // return the created server config, all good if you reach here
@@ -341,12 +580,33 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
                 keylog_intent: value.key_logger,
                 protocol_versions: value.protocol_versions.clone(),
                 client_cert_chain,
+                client_cert_required,
                 store_client_certificate_chain: value.store_client_certificate_chain,
+                session_tickets: true,
+                session_ticket_keys: None,
+                session_cache: None,
+                key_update_policy: None,
             }),
         })
     }
 }
 
+fn client_certs_from_data(data: DataEncoding) -> Result<Vec<X509>, OpaqueError> {
+    match data {
+        DataEncoding::Der(bytes) => Ok(vec![X509::from_der(&bytes[..])
+            .context("boring/TlsAcceptorData: parse x509 client cert from DER content")?]),
+        DataEncoding::DerStack(bytes_list) => bytes_list
+            .into_iter()
+            .map(|b| {
+                X509::from_der(&b[..])
+                    .context("boring/TlsAcceptorData: parse x509 client cert from DER content")
+            })
+            .collect(),
+        DataEncoding::Pem(raw_data) => X509::stack_from_pem(raw_data.as_bytes())
+            .context("boring/TlsAcceptorData: parse x509 client cert from PEM content"),
+    }
+}
+
 fn to_host(ssl_ref: &SslRef, server_name: &Option<Host>) -> Result<Host, OpaqueError> {
     let host = match (ssl_ref.servername(NameType::HOST_NAME), &server_name) {
         (Some(sni), _) => {
@@ -485,6 +745,30 @@ fn self_signed_server_auth(data: SelfSignedData) -> Result<IssuedCert, OpaqueErr
     })
 }
 
+#[cfg(test)]
+/// Generate self-signed [`ServerAuthData`], used by tests which require raw server auth data.
+pub(super) fn self_signed_server_auth_data(
+    data: SelfSignedData,
+) -> Result<ServerAuthData, OpaqueError> {
+    let issued_cert = self_signed_server_auth(data)?;
+    Ok(ServerAuthData {
+        private_key: DataEncoding::Der(
+            issued_cert
+                .key
+                .private_key_to_der()
+                .context("encode private key as DER")?,
+        ),
+        cert_chain: DataEncoding::DerStack(
+            issued_cert
+                .cert_chain
+                .iter()
+                .map(|cert| cert.to_der().context("encode cert as DER"))
+                .collect::<Result<_, _>>()?,
+        ),
+        ocsp: None,
+    })
+}
+
 #[inline]
 fn self_signed_server_ca(data: SelfSignedData) -> Result<(X509, PKey<Private>), OpaqueError> {
     self_signed_server_auth_gen_ca(&data)
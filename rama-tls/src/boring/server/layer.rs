@@ -19,12 +19,34 @@ impl TlsAcceptorLayer {
     }
 
     /// Set that the client hello should be stored
+    /// in the [`SecureTransport`] extension of the inner service's [`Context`].
+    ///
+    /// The stored [`ClientHello`] contains both the parsed fields
+    /// and the raw bytes, and can be used to compute fingerprints such as [`Ja3`] and [`Ja4`].
+    /// Disabled by default, as it adds some overhead to each handshake.
+    ///
+    /// [`SecureTransport`]: rama_net::tls::SecureTransport
+    /// [`Context`]: rama_core::Context
+    /// [`ClientHello`]: rama_net::tls::client::ClientHello
+    /// [`Ja3`]: rama_net::fingerprint::Ja3
+    /// [`Ja4`]: rama_net::fingerprint::Ja4
     pub const fn with_store_client_hello(mut self, store: bool) -> Self {
         self.store_client_hello = store;
         self
     }
 
     /// Set that the client hello should be stored
+    /// in the [`SecureTransport`] extension of the inner service's [`Context`].
+    ///
+    /// The stored [`ClientHello`] contains both the parsed fields
+    /// and the raw bytes, and can be used to compute fingerprints such as [`Ja3`] and [`Ja4`].
+    /// Disabled by default, as it adds some overhead to each handshake.
+    ///
+    /// [`SecureTransport`]: rama_net::tls::SecureTransport
+    /// [`Context`]: rama_core::Context
+    /// [`ClientHello`]: rama_net::tls::client::ClientHello
+    /// [`Ja3`]: rama_net::fingerprint::Ja3
+    /// [`Ja4`]: rama_net::fingerprint::Ja4
     pub fn set_store_client_hello(&mut self, store: bool) -> &mut Self {
         self.store_client_hello = store;
         self
@@ -9,11 +9,89 @@
 //! - [/examples/tls_boring_termination.rs](https://github.com/plabayo/rama/tree/main/examples/tls_boring_termination.rs):
 //!   Spawns a mini handmade http server, as well as a TLS termination proxy, forwarding the
 //!   plain text stream to the first.
+//!
+//! # Negotiated parameters
+//!
+//! Once the handshake completed, the [`TlsAcceptorService`] adds the
+//! [`NegotiatedTlsParameters`] to the [`Context`] of the inner service.
+//! Its `application_layer_protocol` holds the negotiated ALPN protocol
+//! (e.g. `h2` or `http/1.1`), which can be used to route the connection,
+//! and is `None` in case the client did not advertise ALPN
+//! or no protocol was agreed upon.
+//!
+//! [`NegotiatedTlsParameters`]: rama_net::tls::client::NegotiatedTlsParameters
+//!
+//! # Client authentication
+//!
+//! Client certificates are requested and verified when the [`ServerConfig`] used to create
+//! the [`TlsAcceptorData`] has its `client_verify_mode` set to [`ClientVerifyMode::ClientAuth`]
+//! (certificate required) or [`ClientVerifyMode::OptionalClientAuth`] (certificate optional).
+//! Only the certificates given to that mode are trusted to verify client certificates.
+//!
+//! Once verified, the client certificate is added to the [`Context`] as a
+//! [`VerifiedClientCertificate`], such that downstream services can authorize on it.
+//! A handshake failing because of client verification returns a [`ClientVerifyError`].
+//!
+//! [`ServerConfig`]: rama_net::tls::server::ServerConfig
+//! [`ClientVerifyMode::ClientAuth`]: rama_net::tls::server::ClientVerifyMode::ClientAuth
+//! [`ClientVerifyMode::OptionalClientAuth`]: rama_net::tls::server::ClientVerifyMode::OptionalClientAuth
+//! [`VerifiedClientCertificate`]: rama_net::tls::server::VerifiedClientCertificate
+//! [`ClientVerifyError`]: rama_net::tls::server::ClientVerifyError
+//! [`Context`]: rama_core::Context
+//!
+//! # Multiple domains
+//!
+//! Use [`ServerAuth::Sni`] to present a different certificate depending on
+//! the SNI hostname sent by the client, resolved using a [`SniCertResolver`].
+//! Clients sending no or an unknown hostname get the fallback certificate
+//! of the resolver, or have their handshake aborted if it has none.
+//! The hostname for which a certificate was resolved is added
+//! to the [`Context`] as a [`SelectedServerName`].
+//!
+//! [`ServerAuth::Sni`]: rama_net::tls::server::ServerAuth::Sni
+//! [`SniCertResolver`]: rama_net::tls::server::SniCertResolver
+//! [`SelectedServerName`]: rama_net::tls::server::SelectedServerName
+//!
+//! # Session resumption
+//!
+//! Clients can resume a previous session using a session ticket (TLS 1.2 and 1.3)
+//! or a session ID (TLS 1.2 only), skipping the full handshake.
+//! As each connection is accepted using its own boring ssl context, sessions can only
+//! be resumed once this is configured on the [`TlsAcceptorData`]:
+//!
+//! - [`TlsAcceptorData::with_session_ticket_keys`] sets the [`SessionTicketKey`]s used
+//!   to encrypt and decrypt the session tickets. The first key encrypts new tickets,
+//!   while all keys decrypt them, allowing keys to be rotated across a fleet;
+//! - [`TlsAcceptorData::with_session_cache`] enables a session cache shared by all
+//!   connections, used to resume sessions by their ID;
+//! - [`TlsAcceptorData::with_session_tickets`] disables session tickets altogether.
+//!
+//! # Renegotiation
+//!
+//! BoringSSL does not implement renegotiation as a server. A client attempting to
+//! renegotiate an established (TLS 1.2) connection receives a `no_renegotiation` alert,
+//! and the connection fails with an error, surfaced on the next read of the stream.
+//! There is no configuration to enable legacy renegotiation.
+//!
+//! # Key updates
+//!
+//! Use the [`KeyUpdateLayer`] to periodically update the keys of long-lived
+//! TLS 1.3 connections, which also classifies rejected renegotiation attempts
+//! as events in metrics. The thresholds can be configured on the [`TlsAcceptorData`]
+//! using [`TlsAcceptorData::with_key_update_max_bytes`] and
+//! [`TlsAcceptorData::with_key_update_max_age`], applied by [`KeyUpdateLayer::from_context`].
+//!
+//! [`KeyUpdateLayer`]: super::key_update::KeyUpdateLayer
+//! [`KeyUpdateLayer::from_context`]: super::key_update::KeyUpdateLayer::from_context
 
 mod acceptor_data;
 #[doc(inline)]
 pub use acceptor_data::TlsAcceptorData;
 
+pub(super) mod session;
+#[doc(inline)]
+pub use session::SessionTicketKey;
+
 mod service;
 #[doc(inline)]
 pub use service::TlsAcceptorService;
@@ -1,8 +1,12 @@
 use super::TlsAcceptorData;
 use crate::{
     boring::dep::{
-        boring::ssl::{AlpnError, SslAcceptor, SslMethod, SslRef},
-        tokio_boring::SslStream,
+        boring::{
+            hash::MessageDigest,
+            ssl::{AlpnError, SslAcceptor, SslMethod, SslOptions, SslRef, SslVerifyMode},
+            x509::{store::X509StoreBuilder, X509Ref, X509VerifyResult},
+        },
+        tokio_boring::{HandshakeError, SslStream},
     },
     keylog::new_key_log_file_handle,
     types::SecureTransport,
@@ -15,11 +19,17 @@ use rama_core::{
 use rama_net::{
     http::RequestContext,
     stream::Stream,
-    tls::{client::NegotiatedTlsParameters, ApplicationProtocol, DataEncoding},
+    tls::{
+        client::NegotiatedTlsParameters,
+        server::{
+            ClientVerifyError, SelectedServerName, SubjectAltName, VerifiedClientCertificate,
+        },
+        ApplicationProtocol, DataEncoding,
+    },
     transport::TransportContext,
 };
 use rama_utils::macros::define_inner_service_accessors;
-use std::{io::ErrorKind, sync::Arc};
+use std::{io::ErrorKind, net::IpAddr, sync::Arc};
 use tracing::{debug, trace};
 
 /// A [`Service`] which accepts TLS connections and delegates the underlying transport
@@ -107,10 +117,20 @@ where
             .store_client_hello
             .then_some(Arc::new(Mutex::new(None)));
 
+        let mut maybe_selected_server_name = tls_config
+            .cert_source
+            .is_sni()
+            .then(|| Arc::new(Mutex::new(None)));
+
         let mut acceptor_builder = tls_config
             .cert_source
             .clone()
-            .issue_certs(acceptor_builder, server_host.cloned(), &maybe_client_hello)
+            .issue_certs(
+                acceptor_builder,
+                server_host.cloned(),
+                &maybe_client_hello,
+                &maybe_selected_server_name,
+            )
             .await?;
 
         if let Some(min_ver) = tls_config.protocol_versions.iter().flatten().min() {
@@ -131,10 +151,28 @@ where
                 .context("build boring ssl acceptor: set max proto version")?;
         }
 
-        for ca_cert in tls_config.client_cert_chain.iter().flatten() {
+        if let Some(client_cert_chain) = tls_config.client_cert_chain.as_ref() {
+            // only the configured certificates are trusted to verify client certificates,
+            // as opposed to the default verify paths used for other purposes
+            let mut store_builder = X509StoreBuilder::new()
+                .context("build boring ssl acceptor: create client cert store")?;
+            for ca_cert in client_cert_chain {
+                acceptor_builder
+                    .add_client_ca(ca_cert)
+                    .context("build boring ssl acceptor: set ca client cert")?;
+                store_builder
+                    .add_cert(ca_cert.clone())
+                    .context("build boring ssl acceptor: add ca client cert to store")?;
+            }
             acceptor_builder
-                .add_client_ca(ca_cert)
-                .context("build boring ssl acceptor: set ca client cert")?;
+                .set_verify_cert_store(store_builder.build())
+                .context("build boring ssl acceptor: set client cert store")?;
+
+            let mut verify_mode = SslVerifyMode::PEER;
+            if tls_config.client_cert_required {
+                verify_mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+            }
+            acceptor_builder.set_verify(verify_mode);
         }
 
         if let Some(alpn_protocols) = tls_config.alpn_protocols.clone() {
@@ -172,6 +210,16 @@ where
             );
         }
 
+        if !tls_config.session_tickets {
+            acceptor_builder.set_options(SslOptions::NO_TICKET);
+        } else if let Some(session_ticket_keys) = tls_config.session_ticket_keys.as_ref() {
+            session_ticket_keys.install(&mut acceptor_builder)?;
+        }
+
+        if let Some(session_cache) = tls_config.session_cache.as_ref() {
+            session_cache.install(&mut acceptor_builder)?;
+        }
+
         if let Some(keylog_filename) = tls_config.keylog_intent.file_path() {
             let handle = new_key_log_file_handle(keylog_filename)?;
             acceptor_builder.set_keylog_callback(move |_, line| {
@@ -181,16 +229,25 @@ where
         }
 
         let acceptor = acceptor_builder.build();
+        let key_update_policy = tls_config.key_update_policy;
 
         let stream = tokio_boring::accept(&acceptor, stream)
             .await
-            .map_err(|err| match err.as_io_error() {
-                Some(err) => OpaqueError::from_display(err.to_string())
-                    .context("boring ssl acceptor: accept"),
-                None => OpaqueError::from_display(format!(
-                    "boring ssl acceptor: accept ({:?})",
-                    err.code()
-                )),
+            .map_err(|err| {
+                if tls_config.client_cert_chain.is_some() {
+                    if let Some(err) = client_verify_error(&err) {
+                        debug!(%err, "boring ssl acceptor: client verification failed");
+                        return OpaqueError::from_std(err).context("boring ssl acceptor: accept");
+                    }
+                }
+                match err.as_io_error() {
+                    Some(err) => OpaqueError::from_display(err.to_string())
+                        .context("boring ssl acceptor: accept"),
+                    None => OpaqueError::from_display(format!(
+                        "boring ssl acceptor: accept ({:?})",
+                        err.code()
+                    )),
+                }
             })?;
 
         match stream.ssl().session() {
@@ -229,6 +286,12 @@ where
                     None
                 };
 
+                if tls_config.client_cert_chain.is_some() {
+                    if let Some(certificate) = stream.ssl().peer_certificate() {
+                        ctx.insert(verified_client_certificate(&certificate)?);
+                    }
+                }
+
                 ctx.insert(NegotiatedTlsParameters {
                     protocol_version,
                     application_layer_protocol,
@@ -243,6 +306,13 @@ where
             }
         }
 
+        if let Some(server_name) = maybe_selected_server_name
+            .take()
+            .and_then(|maybe_selected_server_name| maybe_selected_server_name.lock().take())
+        {
+            ctx.insert(SelectedServerName { server_name });
+        }
+
         let secure_transport = maybe_client_hello
             .take()
             .and_then(|maybe_client_hello| maybe_client_hello.lock().take())
@@ -250,6 +320,10 @@ where
             .unwrap_or_default();
         ctx.insert(secure_transport);
 
+        if let Some(key_update_policy) = key_update_policy {
+            ctx.insert(key_update_policy);
+        }
+
         self.inner.serve(ctx, stream).await.map_err(|err| {
             OpaqueError::from_boxed(err.into())
                 .context("boring acceptor: service error")
@@ -257,3 +331,421 @@ where
         })
     }
 }
+
+/// Returns the [`ClientVerifyError`] that caused the handshake to fail, if any.
+fn client_verify_error<S>(err: &HandshakeError<S>) -> Option<ClientVerifyError> {
+    if let Some(ssl) = err.ssl() {
+        let verify_result = ssl.verify_result();
+        if verify_result != X509VerifyResult::OK {
+            return Some(ClientVerifyError::Rejected {
+                reason: verify_result.error_string().to_owned(),
+            });
+        }
+    }
+    err.as_ssl_error_stack()
+        .is_some_and(|stack| {
+            stack
+                .errors()
+                .iter()
+                .any(|err| err.reason() == Some("PEER_DID_NOT_RETURN_A_CERTIFICATE"))
+        })
+        .then_some(ClientVerifyError::MissingCertificate)
+}
+
+fn verified_client_certificate(
+    certificate: &X509Ref,
+) -> Result<VerifiedClientCertificate, OpaqueError> {
+    let subject = certificate
+        .subject_name()
+        .entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("UNKNOWN");
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+            format!("{key}={value}")
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let subject_alt_names = certificate
+        .subject_alt_names()
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| {
+                    if let Some(dns) = name.dnsname() {
+                        Some(SubjectAltName::Dns(dns.to_owned()))
+                    } else if let Some(ip) = name.ipaddress() {
+                        match ip.len() {
+                            4 => <[u8; 4]>::try_from(ip).ok().map(IpAddr::from),
+                            16 => <[u8; 16]>::try_from(ip).ok().map(IpAddr::from),
+                            _ => None,
+                        }
+                        .map(SubjectAltName::Ip)
+                    } else if let Some(email) = name.email() {
+                        Some(SubjectAltName::Email(email.to_owned()))
+                    } else {
+                        name.uri().map(|uri| SubjectAltName::Uri(uri.to_owned()))
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let digest = certificate
+        .digest(MessageDigest::sha256())
+        .context("boring ssl session: compute client certificate fingerprint")?;
+    let fingerprint_sha256 = <[u8; 32]>::try_from(&digest[..])
+        .context("boring ssl session: unexpected client certificate fingerprint length")?;
+
+    Ok(VerifiedClientCertificate {
+        subject,
+        subject_alt_names,
+        fingerprint_sha256,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::acceptor_data::self_signed_server_auth_data;
+    use super::super::SessionTicketKey;
+    use super::*;
+    use crate::boring::client::{TlsConnectorData, TlsConnectorLayer};
+    use crate::boring::dep::boring::{
+        nid::Nid,
+        ssl::{SslConnector, SslSession, SslSessionCacheMode, SslVersion},
+        x509::X509,
+    };
+    use rama_core::{service::service_fn, Layer};
+    use rama_http_types::{Body, Request};
+    use rama_net::address::{Domain, Host};
+    use rama_net::client::EstablishedClientConnection;
+    use rama_net::tls::client::{ClientConfig, ClientHelloExtension, ServerVerifyMode};
+    use rama_net::tls::server::{
+        SelfSignedData, ServerAuth, ServerAuthData, ServerConfig, SniCertResolver,
+    };
+    use std::convert::Infallible;
+    use tokio::io::DuplexStream;
+
+    /// Handshake a boring client advertising the given ALPN protocols with a boring server
+    /// supporting `h2` and `http/1.1`, returning the ALPN protocol recorded by the server.
+    async fn server_negotiated_alpn(
+        client_alpn: Option<Vec<ApplicationProtocol>>,
+    ) -> Option<ApplicationProtocol> {
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+
+        let mut server_config = ServerConfig::new(ServerAuth::default());
+        server_config.application_layer_protocol_negotiation = Some(vec![
+            ApplicationProtocol::HTTP_2,
+            ApplicationProtocol::HTTP_11,
+        ]);
+        let server = TlsAcceptorService::new(
+            TlsAcceptorData::try_from(server_config).unwrap(),
+            service_fn(
+                |ctx: Context<()>, _stream: SslStream<DuplexStream>| async move {
+                    Ok::<_, Infallible>(
+                        ctx.get::<NegotiatedTlsParameters>()
+                            .unwrap()
+                            .application_layer_protocol
+                            .clone(),
+                    )
+                },
+            ),
+            false,
+        );
+        let server =
+            tokio::spawn(async move { server.serve(Context::default(), server_stream).await });
+
+        let connector_data = TlsConnectorData::try_from(ClientConfig {
+            server_verify_mode: Some(ServerVerifyMode::Disable),
+            extensions: client_alpn.map(|alpn| {
+                vec![ClientHelloExtension::ApplicationLayerProtocolNegotiation(
+                    alpn,
+                )]
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        let client_stream = Arc::new(Mutex::new(Some(client_stream)));
+        let connector = TlsConnectorLayer::secure()
+            .with_connector_data(connector_data)
+            .layer(service_fn(move |ctx: Context<()>, req: Request| {
+                let conn = client_stream.lock().take().unwrap();
+                async move {
+                    Ok::<_, Infallible>(EstablishedClientConnection {
+                        ctx,
+                        req,
+                        conn,
+                        addr: ([127, 0, 0, 1], 443).into(),
+                    })
+                }
+            }));
+
+        let req = Request::builder()
+            .uri("https://example.com/")
+            .body(Body::empty())
+            .unwrap();
+        let EstablishedClientConnection { ctx, .. } =
+            connector.serve(Context::default(), req).await.unwrap();
+
+        let server_alpn = server.await.unwrap().unwrap();
+        // client and server agree on the negotiated protocol
+        assert_eq!(
+            ctx.get::<NegotiatedTlsParameters>()
+                .unwrap()
+                .application_layer_protocol,
+            server_alpn
+        );
+        server_alpn
+    }
+
+    #[tokio::test]
+    async fn test_negotiated_alpn_h2() {
+        let alpn = server_negotiated_alpn(Some(vec![
+            ApplicationProtocol::HTTP_2,
+            ApplicationProtocol::HTTP_11,
+        ]))
+        .await;
+        assert_eq!(alpn, Some(ApplicationProtocol::HTTP_2));
+    }
+
+    #[tokio::test]
+    async fn test_negotiated_alpn_http11() {
+        let alpn = server_negotiated_alpn(Some(vec![ApplicationProtocol::HTTP_11])).await;
+        assert_eq!(alpn, Some(ApplicationProtocol::HTTP_11));
+    }
+
+    #[tokio::test]
+    async fn test_negotiated_alpn_none() {
+        let alpn = server_negotiated_alpn(None).await;
+        assert_eq!(alpn, None);
+    }
+
+    /// Handshake a boring client using the given SNI hostname with a boring server
+    /// using the given [`SniCertResolver`], returning the common name of the certificate
+    /// presented to the client and the [`SelectedServerName`] recorded by the server.
+    async fn server_sni_handshake(
+        resolver: SniCertResolver,
+        server_name: &str,
+    ) -> Result<(String, Option<SelectedServerName>), BoxError> {
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+
+        let server = TlsAcceptorService::new(
+            TlsAcceptorData::try_from(ServerConfig::new(ServerAuth::Sni(resolver))).unwrap(),
+            service_fn(
+                |ctx: Context<()>, _stream: SslStream<DuplexStream>| async move {
+                    Ok::<_, Infallible>(ctx.get::<SelectedServerName>().cloned())
+                },
+            ),
+            false,
+        );
+        let server =
+            tokio::spawn(async move { server.serve(Context::default(), server_stream).await });
+
+        let connector_data = TlsConnectorData::try_from(ClientConfig {
+            server_verify_mode: Some(ServerVerifyMode::Disable),
+            store_server_certificate_chain: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let client_stream = Arc::new(Mutex::new(Some(client_stream)));
+        let connector = TlsConnectorLayer::secure()
+            .with_connector_data(connector_data)
+            .layer(service_fn(move |ctx: Context<()>, req: Request| {
+                let conn = client_stream.lock().take().unwrap();
+                async move {
+                    Ok::<_, Infallible>(EstablishedClientConnection {
+                        ctx,
+                        req,
+                        conn,
+                        addr: ([127, 0, 0, 1], 443).into(),
+                    })
+                }
+            }));
+
+        let req = Request::builder()
+            .uri(format!("https://{server_name}/"))
+            .body(Body::empty())
+            .unwrap();
+        let client_result = connector.serve(Context::default(), req).await;
+        let selected_server_name = server.await.unwrap()?;
+        let EstablishedClientConnection { ctx, .. } = client_result?;
+
+        let Some(DataEncoding::DerStack(cert_chain)) = ctx
+            .get::<NegotiatedTlsParameters>()
+            .unwrap()
+            .peer_certificate_chain
+            .clone()
+        else {
+            panic!("expected server certificate chain as DER stack");
+        };
+        let leaf = X509::from_der(&cert_chain[0]).unwrap();
+        let common_name = leaf
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .unwrap()
+            .data()
+            .as_utf8()
+            .unwrap()
+            .to_string();
+
+        Ok((common_name, selected_server_name))
+    }
+
+    fn sni_server_auth_data(common_name: &'static str) -> ServerAuthData {
+        self_signed_server_auth_data(SelfSignedData {
+            common_name: Some(Host::Name(Domain::from_static(common_name))),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sni_cert_selection() {
+        let resolver = SniCertResolver::from_map([
+            (
+                Domain::from_static("a.example.com"),
+                sni_server_auth_data("a.example.com"),
+            ),
+            (
+                Domain::from_static("b.example.com"),
+                sni_server_auth_data("b.example.com"),
+            ),
+        ]);
+
+        for server_name in ["a.example.com", "b.example.com"] {
+            let (common_name, selected_server_name) =
+                server_sni_handshake(resolver.clone(), server_name)
+                    .await
+                    .unwrap();
+            assert_eq!(common_name, server_name);
+            assert_eq!(
+                selected_server_name,
+                Some(SelectedServerName {
+                    server_name: Domain::from_static(server_name)
+                })
+            );
+        }
+
+        // unknown sni without a fallback aborts the handshake
+        assert!(server_sni_handshake(resolver.clone(), "c.example.com")
+            .await
+            .is_err());
+
+        // unknown sni with a fallback presents the fallback cert
+        let resolver = resolver.with_fallback(sni_server_auth_data("default.example.com"));
+        let (common_name, selected_server_name) = server_sni_handshake(resolver, "c.example.com")
+            .await
+            .unwrap();
+        assert_eq!(common_name, "default.example.com");
+        assert_eq!(selected_server_name, None);
+    }
+
+    /// Handshake a plain boring client (TLS 1.2) with a boring server using the given
+    /// [`TlsAcceptorData`], offering the given session for resumption,
+    /// returning the established session and whether it was resumed.
+    async fn session_resumption_handshake(
+        data: TlsAcceptorData,
+        session: Option<&SslSession>,
+    ) -> Result<(SslSession, bool), BoxError> {
+        let (client_stream, server_stream) = tokio::io::duplex(16 * 1024);
+
+        let server = TlsAcceptorService::new(
+            data,
+            service_fn(
+                |_ctx: Context<()>, _stream: SslStream<DuplexStream>| async move {
+                    Ok::<_, Infallible>(())
+                },
+            ),
+            false,
+        );
+        let server =
+            tokio::spawn(async move { server.serve(Context::default(), server_stream).await });
+
+        let mut connector = SslConnector::builder(SslMethod::tls_client())?;
+        connector.set_verify(SslVerifyMode::NONE);
+        connector.set_max_proto_version(Some(SslVersion::TLS1_2))?;
+        let mut config = connector.build().configure()?;
+        if let Some(session) = session {
+            crate::boring::ffi::set_session(&mut config, session)?;
+        }
+        let stream = tokio_boring::connect(config, "localhost", client_stream).await?;
+        server.await??;
+
+        let session = stream
+            .ssl()
+            .session()
+            .ok_or_else(|| OpaqueError::from_display("no session established"))?
+            .to_owned();
+        Ok((session, stream.ssl().session_reused()))
+    }
+
+    #[tokio::test]
+    async fn test_session_resumption() {
+        let old_key = SessionTicketKey::generate().unwrap();
+        let new_key = SessionTicketKey::generate().unwrap();
+        let data = TlsAcceptorData::try_from(ServerConfig::new(ServerAuth::default())).unwrap();
+
+        // without keys tickets cannot be resumed, as each connection has its own context
+        let (session, resumed) = session_resumption_handshake(data.clone(), None)
+            .await
+            .unwrap();
+        assert!(!resumed);
+        let (_, resumed) = session_resumption_handshake(data.clone(), Some(&session))
+            .await
+            .unwrap();
+        assert!(!resumed);
+
+        let old_data = data.clone().with_session_ticket_keys(vec![old_key.clone()]);
+        let (session, resumed) = session_resumption_handshake(old_data.clone(), None)
+            .await
+            .unwrap();
+        assert!(!resumed);
+        let (_, resumed) = session_resumption_handshake(old_data, Some(&session))
+            .await
+            .unwrap();
+        assert!(resumed);
+
+        // tickets issued using the previous key still resume after rotating keys...
+        let rotated_data = data
+            .clone()
+            .with_session_ticket_keys(vec![new_key.clone(), old_key]);
+        let (_, resumed) = session_resumption_handshake(rotated_data, Some(&session))
+            .await
+            .unwrap();
+        assert!(resumed);
+
+        // ... but not once the previous key is dropped
+        let new_data = data.clone().with_session_ticket_keys(vec![new_key]);
+        let (_, resumed) = session_resumption_handshake(new_data, Some(&session))
+            .await
+            .unwrap();
+        assert!(!resumed);
+
+        // sessions can be resumed by their ID using the session cache instead
+        let cache_data = data
+            .with_session_tickets(false)
+            .with_session_cache(SslSessionCacheMode::SERVER, 128);
+        let (session, resumed) = session_resumption_handshake(cache_data.clone(), None)
+            .await
+            .unwrap();
+        assert!(!resumed);
+        let (_, resumed) = session_resumption_handshake(cache_data, Some(&session))
+            .await
+            .unwrap();
+        assert!(resumed);
+    }
+
+    #[test]
+    fn test_session_ticket_key_from_bytes() {
+        let bytes: Vec<u8> = (0..SessionTicketKey::LEN as u8).collect();
+        let key = SessionTicketKey::try_from_bytes(&bytes).unwrap();
+        assert_eq!(key.name(), &bytes[..16]);
+        assert!(!format!("{key:?}").contains("32"));
+        assert!(SessionTicketKey::try_from_bytes(&bytes[1..]).is_err());
+    }
+}
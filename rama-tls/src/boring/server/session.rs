@@ -0,0 +1,164 @@
+use crate::boring::dep::boring::{
+    ex_data::Index,
+    rand::rand_bytes,
+    ssl::{SslContext, SslContextBuilder, SslSession, SslSessionCacheMode},
+};
+use moka::sync::Cache;
+use rama_core::error::{ErrorContext, OpaqueError};
+use std::{
+    fmt,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+const TICKET_KEY_NAME_LEN: usize = 16;
+const TICKET_KEY_SECRET_LEN: usize = 32;
+
+#[derive(Clone, PartialEq, Eq)]
+/// A key used to encrypt and decrypt TLS session tickets,
+/// which allow clients to resume a previous session without a full handshake.
+///
+/// Share the same keys across all nodes of a fleet,
+/// such that tickets issued by one node can be resumed on any other.
+pub struct SessionTicketKey {
+    name: [u8; TICKET_KEY_NAME_LEN],
+    hmac_key: [u8; TICKET_KEY_SECRET_LEN],
+    aes_key: [u8; TICKET_KEY_SECRET_LEN],
+}
+
+impl SessionTicketKey {
+    /// The length of the key material accepted by [`SessionTicketKey::try_from_bytes`].
+    pub const LEN: usize = TICKET_KEY_NAME_LEN + 2 * TICKET_KEY_SECRET_LEN;
+
+    /// Create a [`SessionTicketKey`] from its 80 bytes of key material:
+    /// the 16 byte name of the key, followed by
+    /// the 32 byte HMAC secret and the 32 byte AES secret.
+    ///
+    /// This is the same format as used by the `ssl_session_ticket_key` files of nginx.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, OpaqueError> {
+        if bytes.len() != Self::LEN {
+            return Err(OpaqueError::from_display(format!(
+                "session ticket key: expected {} bytes of key material, got {}",
+                Self::LEN,
+                bytes.len()
+            )));
+        }
+        let (name, secrets) = bytes.split_at(TICKET_KEY_NAME_LEN);
+        let (hmac_key, aes_key) = secrets.split_at(TICKET_KEY_SECRET_LEN);
+        Ok(Self {
+            name: name.try_into().unwrap(),
+            hmac_key: hmac_key.try_into().unwrap(),
+            aes_key: aes_key.try_into().unwrap(),
+        })
+    }
+
+    /// Generate a new random [`SessionTicketKey`].
+    pub fn generate() -> Result<Self, OpaqueError> {
+        let mut bytes = [0; Self::LEN];
+        rand_bytes(&mut bytes).context("session ticket key: generate random key material")?;
+        Self::try_from_bytes(&bytes)
+    }
+
+    /// The name of the key, included in the tickets encrypted with it.
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    pub(crate) fn hmac_key(&self) -> &[u8] {
+        &self.hmac_key
+    }
+
+    pub(crate) fn aes_key(&self) -> &[u8] {
+        &self.aes_key
+    }
+}
+
+impl fmt::Debug for SessionTicketKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never expose the secrets
+        f.debug_struct("SessionTicketKey")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The [`SessionTicketKey`]s of a [`TlsAcceptorData`], the first of which
+/// encrypts new tickets, while all of them can decrypt tickets.
+///
+/// [`TlsAcceptorData`]: super::TlsAcceptorData
+pub(crate) struct SessionTicketKeys(Arc<[SessionTicketKey]>);
+
+impl SessionTicketKeys {
+    pub(super) fn new(keys: Vec<SessionTicketKey>) -> Option<Self> {
+        (!keys.is_empty()).then(|| Self(keys.into()))
+    }
+
+    /// Use these keys for the session tickets of connections accepted with the given builder.
+    pub(super) fn install(&self, builder: &mut SslContextBuilder) -> Result<(), OpaqueError> {
+        builder.set_ex_data(ticket_keys_index()?, self.clone());
+        crate::boring::ffi::set_ticket_key_callback(builder)
+    }
+
+    /// The key used to encrypt new tickets.
+    pub(crate) fn encryption_key(&self) -> &SessionTicketKey {
+        &self.0[0]
+    }
+
+    /// All keys which can be used to decrypt tickets.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &SessionTicketKey> {
+        self.0.iter()
+    }
+}
+
+pub(crate) fn ticket_keys_index() -> Result<Index<SslContext, SessionTicketKeys>, OpaqueError> {
+    static INDEX: OnceLock<Index<SslContext, SessionTicketKeys>> = OnceLock::new();
+    if let Some(index) = INDEX.get() {
+        return Ok(*index);
+    }
+    let index = SslContext::new_ex_index().context("session ticket keys: create ex data index")?;
+    Ok(*INDEX.get_or_init(|| index))
+}
+
+#[derive(Debug, Clone)]
+/// Session cache shared by all connections accepted using the same [`TlsAcceptorData`],
+/// as each connection is accepted using its own boring ssl context.
+///
+/// [`TlsAcceptorData`]: super::TlsAcceptorData
+pub(super) struct SessionCache {
+    mode: SslSessionCacheMode,
+    sessions: Cache<Vec<u8>, SslSession>,
+}
+
+impl SessionCache {
+    pub(super) fn new(mode: SslSessionCacheMode, size: u64) -> Self {
+        Self {
+            mode,
+            sessions: Cache::builder()
+                // default session timeout of boringssl (for TLS 1.2)
+                .time_to_live(Duration::from_secs(60 * 60 * 2))
+                .max_capacity(size)
+                .build(),
+        }
+    }
+
+    /// Use this cache for the sessions of connections accepted with the given builder.
+    pub(super) fn install(&self, builder: &mut SslContextBuilder) -> Result<(), OpaqueError> {
+        builder.set_session_cache_mode(self.mode);
+        if !self.mode.contains(SslSessionCacheMode::SERVER) {
+            return Ok(());
+        }
+
+        builder
+            .set_session_id_context(b"rama")
+            .context("build boring ssl acceptor: set session id context")?;
+
+        let sessions = self.sessions.clone();
+        builder.set_new_session_callback(move |_, session| {
+            sessions.insert(session.id().to_vec(), session);
+        });
+
+        crate::boring::ffi::set_get_session_callback(builder, self.sessions.clone());
+        Ok(())
+    }
+}
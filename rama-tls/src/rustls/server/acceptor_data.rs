@@ -1,7 +1,11 @@
 use crate::rustls::dep::pemfile;
 use crate::rustls::dep::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use crate::rustls::dep::rcgen::{self, KeyPair};
-use crate::rustls::dep::rustls::{self, server::WebPkiClientVerifier, RootCertStore};
+use crate::rustls::dep::rustls::{
+    self,
+    server::{danger::ClientCertVerifier, WebPkiClientVerifier},
+    RootCertStore,
+};
 use crate::rustls::key_log::KeyLogFile;
 use rama_core::error::{ErrorContext, OpaqueError};
 use rama_net::address::{Domain, Host};
@@ -78,45 +82,11 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
         // builder with client auth configured
         let builder = match value.client_verify_mode {
             ClientVerifyMode::Auto | ClientVerifyMode::Disable => builder.with_no_client_auth(),
-            ClientVerifyMode::ClientAuth(DataEncoding::Der(bytes)) => {
-                let client_cert_der = CertificateDer::from(bytes);
-                let mut root_cert_storage = RootCertStore::empty();
-                root_cert_storage
-                    .add(client_cert_der)
-                    .context("rustls/TlsAcceptorData: der: add client cert to root cert storage")?;
-                let cert_verifier = WebPkiClientVerifier::builder(Arc::new(root_cert_storage))
-                    .build()
-                    .context("rustls/TlsAcceptorData: der: create webpki client verifier")?;
-                builder.with_client_cert_verifier(cert_verifier)
+            ClientVerifyMode::ClientAuth(data) => {
+                builder.with_client_cert_verifier(client_cert_verifier(data, false)?)
             }
-            ClientVerifyMode::ClientAuth(DataEncoding::DerStack(bytes_list)) => {
-                let mut root_cert_storage = RootCertStore::empty();
-                for bytes in bytes_list {
-                    let client_cert_der = CertificateDer::from(bytes);
-                    root_cert_storage.add(client_cert_der).context(
-                        "rustls/TlsAcceptorData: der: add client cert to root cert storage",
-                    )?
-                }
-                let cert_verifier = WebPkiClientVerifier::builder(Arc::new(root_cert_storage))
-                    .build()
-                    .context("rustls/TlsAcceptorData: der: create webpki client verifier")?;
-                builder.with_client_cert_verifier(cert_verifier)
-            }
-            ClientVerifyMode::ClientAuth(DataEncoding::Pem(raw_pem)) => {
-                let mut root_cert_storage = RootCertStore::empty();
-                let mut pem = BufReader::new(raw_pem.as_bytes());
-                for (index, cert) in pemfile::certs(&mut pem).enumerate() {
-                    let cert = cert.with_context(|| {
-                        format!("rustls/TlsAcceptorData: pem #{index}: parse tls client cert")
-                    })?;
-                    root_cert_storage
-                        .add(cert)
-                        .with_context(|| format!("rustls/TlsAcceptorData: pem #{index}: add client cert to root cert storage"))?;
-                }
-                let cert_verifier = WebPkiClientVerifier::builder(Arc::new(root_cert_storage))
-                    .build()
-                    .context("rustls/TlsAcceptorData: create webpki client verifier")?;
-                builder.with_client_cert_verifier(cert_verifier)
+            ClientVerifyMode::OptionalClientAuth(data) => {
+                builder.with_client_cert_verifier(client_cert_verifier(data, true)?)
             }
         };
 
@@ -189,6 +159,10 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
             ServerAuth::CertIssuer { .. } => {
                 return Err(OpaqueError::from_display("CertIssuer not supported for Rustls (open an PR with a patch to add support for it if you want this or use boring instead)"));
             }
+
+            ServerAuth::Sni(_) => {
+                return Err(OpaqueError::from_display("Sni server auth not supported for Rustls (open an PR with a patch to add support for it if you want this or use boring instead)"));
+            }
         };
 
         // set key logger if one is requested
@@ -213,6 +187,48 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
     }
 }
 
+fn client_cert_verifier(
+    data: DataEncoding,
+    allow_unauthenticated: bool,
+) -> Result<Arc<dyn ClientCertVerifier>, OpaqueError> {
+    let mut root_cert_storage = RootCertStore::empty();
+    match data {
+        DataEncoding::Der(bytes) => {
+            let client_cert_der = CertificateDer::from(bytes);
+            root_cert_storage
+                .add(client_cert_der)
+                .context("rustls/TlsAcceptorData: der: add client cert to root cert storage")?;
+        }
+        DataEncoding::DerStack(bytes_list) => {
+            for bytes in bytes_list {
+                let client_cert_der = CertificateDer::from(bytes);
+                root_cert_storage
+                    .add(client_cert_der)
+                    .context("rustls/TlsAcceptorData: der: add client cert to root cert storage")?
+            }
+        }
+        DataEncoding::Pem(raw_pem) => {
+            let mut pem = BufReader::new(raw_pem.as_bytes());
+            for (index, cert) in pemfile::certs(&mut pem).enumerate() {
+                let cert = cert.with_context(|| {
+                    format!("rustls/TlsAcceptorData: pem #{index}: parse tls client cert")
+                })?;
+                root_cert_storage
+                    .add(cert)
+                    .with_context(|| format!("rustls/TlsAcceptorData: pem #{index}: add client cert to root cert storage"))?;
+            }
+        }
+    }
+
+    let mut builder = WebPkiClientVerifier::builder(Arc::new(root_cert_storage));
+    if allow_unauthenticated {
+        builder = builder.allow_unauthenticated();
+    }
+    builder
+        .build()
+        .context("rustls/TlsAcceptorData: create webpki client verifier")
+}
+
 fn self_signed_server_auth(
     data: SelfSignedData,
 ) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), OpaqueError> {
@@ -0,0 +1,29 @@
+//! utilities for working with [`Any`] types
+//!
+//! [`Any`]: std::any::Any
+
+/// Downcast `k` to `T` in case `K` is of type `T`,
+/// returning `k` as is otherwise.
+pub fn try_downcast<T, K>(k: K) -> Result<T, K>
+where
+    T: 'static,
+    K: Send + 'static,
+{
+    let mut k = Some(k);
+    if let Some(k) = <dyn std::any::Any>::downcast_mut::<Option<T>>(&mut k) {
+        Ok(k.take().unwrap())
+    } else {
+        Err(k.unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_downcast() {
+        assert_eq!(try_downcast::<i32, _>(5_u32), Err(5_u32));
+        assert_eq!(try_downcast::<i32, _>(5_i32), Ok(5_i32));
+    }
+}
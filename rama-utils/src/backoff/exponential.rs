@@ -152,10 +152,7 @@ impl<F, R: Rng> ExponentialBackoff<F, R> {
             self.max > time::Duration::from_millis(0),
             "Maximum backoff must be non-zero"
         );
-        self.min
-            .checked_mul(2_u32.saturating_pow(self.state.lock().iterations))
-            .unwrap_or(self.max)
-            .min(self.max)
+        exponential_delay(self.min, self.max, self.state.lock().iterations as usize)
     }
 
     /// Returns a random, uniform duration on `[0, base*self.jitter]` no greater
@@ -164,46 +161,48 @@ impl<F, R: Rng> ExponentialBackoff<F, R> {
         if self.jitter <= 0.0 {
             None
         } else {
-            let jitter_factor = self.state.lock().rng.next_f64();
-            debug_assert!(
-                jitter_factor > 0.0,
-                "rng returns values between 0.0 and 1.0"
-            );
-            let rand_jitter = jitter_factor * self.jitter;
-            let secs = (base.as_secs() as f64) * rand_jitter;
-            let nanos = (base.subsec_nanos() as f64) * rand_jitter;
-            let remaining = self.max - base;
-            let result = time::Duration::new(secs as u64, nanos as u32);
-            if remaining.is_zero() || result.is_zero() {
-                None
-            } else {
-                Some(result.min(remaining))
-            }
+            let result = jitter_delay(base, self.max, self.jitter, &mut self.state.lock().rng);
+            (!result.is_zero()).then_some(result)
         }
     }
-}
 
-impl<F, R> Backoff for ExponentialBackoff<F, R>
-where
-    R: Rng,
-    F: Send + Sync + 'static,
-{
-    async fn next_backoff(&self) -> bool {
+    /// Compute the duration of the next backoff in the sequence,
+    /// advancing the sequence without sleeping.
+    ///
+    /// Returns `None` in case no backoff is possible anymore,
+    /// in which case the sequence is reset.
+    ///
+    /// This is the computation used by [`Backoff::next_backoff`],
+    /// exposed for users which wish to schedule the backoff themselves.
+    pub fn next_backoff_duration(&self) -> Option<time::Duration> {
         let base = self.base();
         let jitter = match self.jitter(base) {
             Some(jitter) => jitter,
             None => {
-                self.reset().await;
-                return false;
+                self.state.lock().iterations = 0;
+                return None;
             }
         };
 
-        let next = base + jitter;
-
         self.state.lock().iterations += 1;
 
-        tokio::time::sleep(next).await;
-        true
+        Some(base + jitter)
+    }
+}
+
+impl<F, R> Backoff for ExponentialBackoff<F, R>
+where
+    R: Rng,
+    F: Send + Sync + 'static,
+{
+    async fn next_backoff(&self) -> bool {
+        match self.next_backoff_duration() {
+            Some(next) => {
+                tokio::time::sleep(next).await;
+                true
+            }
+            None => false,
+        }
     }
 
     async fn reset(&self) {
@@ -224,6 +223,47 @@ impl Default for ExponentialBackoff<(), HasherRng> {
     }
 }
 
+/// Compute the exponential delay `base * 2^iteration`, capped at `max`.
+///
+/// This is the (jitter-free) delay computation used by [`ExponentialBackoff`],
+/// exposed for stateless users such as retry policies, which compute
+/// the delay from the attempt number rather than from shared state.
+pub fn exponential_delay(
+    base: time::Duration,
+    max: time::Duration,
+    iteration: usize,
+) -> time::Duration {
+    let iteration = u32::try_from(iteration).unwrap_or(u32::MAX);
+    base.checked_mul(2_u32.saturating_pow(iteration))
+        .unwrap_or(max)
+        .min(max)
+}
+
+/// Compute a random jitter on `[0, delay * jitter]`, such that
+/// the jittered delay (`delay` + the returned jitter) does not exceed `max`.
+///
+/// This is the jitter computation used by [`ExponentialBackoff`].
+pub fn jitter_delay<R: Rng>(
+    delay: time::Duration,
+    max: time::Duration,
+    jitter: f64,
+    rng: &mut R,
+) -> time::Duration {
+    if jitter <= 0.0 {
+        return time::Duration::ZERO;
+    }
+    let jitter_factor = rng.next_f64();
+    debug_assert!(
+        jitter_factor > 0.0,
+        "rng returns values between 0.0 and 1.0"
+    );
+    let rand_jitter = jitter_factor * jitter;
+    let secs = (delay.as_secs() as f64) * rand_jitter;
+    let nanos = (delay.subsec_nanos() as f64) * rand_jitter;
+    let result = time::Duration::new(secs as u64, nanos as u32);
+    result.min(max.saturating_sub(delay))
+}
+
 /// Backoff validation error.
 #[derive(Debug)]
 pub struct InvalidBackoff(&'static str);
@@ -273,6 +313,28 @@ mod tests {
         assert!(backoff.state.lock().iterations == 1);
     }
 
+    #[test]
+    fn backoff_duration_schedule() {
+        let min = Duration::from_millis(100);
+        let max = Duration::from_millis(800);
+        let jitter = 0.5;
+        let backoff = ExponentialBackoff::new(min, max, jitter, HasherRng::default).unwrap();
+
+        for iteration in 0..3 {
+            let base = min * 2_u32.pow(iteration);
+            let next = backoff.next_backoff_duration().unwrap();
+            assert!(next > base, "{next:?} > {base:?}");
+            assert!(
+                next <= base.mul_f64(1.0 + jitter),
+                "{next:?} <= {base:?} * 1.5"
+            );
+        }
+
+        // base has reached the maximum, so no more backoff is possible
+        assert!(backoff.next_backoff_duration().is_none());
+        assert_eq!(backoff.state.lock().iterations, 0);
+    }
+
     quickcheck! {
         fn backoff_base_first(min_ms: u64, max_ms: u64) -> TestResult {
             let min = time::Duration::from_millis(min_ms);
@@ -6,6 +6,8 @@
 //!
 //! [`ExponentialBackoff`] which implements the [`Backoff`] trait and provides
 //! a batteries included exponential backoff and jitter strategy.
+//! Its delay computation is also available as [`exponential_delay`] and [`jitter_delay`],
+//! for users which compute the delay from an attempt number rather than from shared state.
 //!
 //! [backoff]: https://en.wikipedia.org/wiki/Exponential_backoff
 
@@ -57,4 +59,4 @@ impl<T: Backoff> Backoff for std::sync::Arc<T> {
 
 mod exponential;
 #[doc(inline)]
-pub use exponential::ExponentialBackoff;
+pub use exponential::{exponential_delay, jitter_delay, ExponentialBackoff};
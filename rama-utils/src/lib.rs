@@ -25,6 +25,7 @@
 #[macro_use]
 pub mod macros;
 
+pub mod any;
 pub mod backoff;
 pub mod future;
 pub mod info;